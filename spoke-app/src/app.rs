@@ -1,34 +1,214 @@
-use std::{collections::HashSet, sync::mpsc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, mpsc},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use eframe::egui;
+use matrix_sdk::ruma::{UserId, events::room::member::MembershipState, presence::PresenceState};
+use spoke_core::{
+    matrix::{
+        ADMIN_LEVEL, AccountSettingsEventContent, AclChange, Block, ClientOptions, DeliveryState,
+        DeviceInfo, DirectoryListing, ImagePack, Knock, LeftRoom, MODERATOR_LEVEL, Member,
+        MessageRelation, MessageText, ModerationAction, NotificationMode, Pins, PolicyKind,
+        PolicyList, PollVotes, PowerLevelChange, PowerLevels, PublicRoom, Registration,
+        RoomEncryption, RoomPeek, RoomTemplate, ServerAcl, ServerCapabilities, ServerInfo,
+        SharedMedia, SpaceNode, StateEntry, VoiceMessage,
+    },
+    proxy::ProxyMode,
+    voice::{
+        audio::{INPUT_GAIN_RANGE_DB, InputMeter},
+        data::DataMessage,
+        devices::{AudioDevices, DeviceChoice, DeviceKind},
+        events::{
+            BitrateTier, MAX_PARTICIPANT_CAP, RingAction, VoiceConfigEventContent,
+            VoicePermissions, VoiceSummaryEventContent,
+        },
+        ice::RelayPolicy,
+        latency::LatencyReport,
+        levels::Level,
+        preflight::{PreflightReport, Probe, TurnServer},
+        recording, ring,
+        summary::CallTracker,
+    },
+};
 use tokio::sync::mpsc as tokio_mpsc;
 
-use crate::bridge::{spawn_matrix_task, AppCommand, AppEvent, InviteInfo, RoomInfo};
+use crate::bridge::{
+    AccountEvent, AccountId, AppCommand, AppEvent, EventSender, InviteInfo, LinkPreview, Login,
+    PollUpdate, Reaction, RoomInfo, RoomPreview, SenderProfile, TimelineItem, VoiceTuning,
+    spawn_matrix_task, spawn_server_probe,
+};
+use crate::composer::{self, Composer, Format, PillKind, Suggestion};
+use crate::keybinds::{Binding, KeybindInput, VoiceAction};
+use crate::logging::LogFilter;
+use crate::markup;
+use crate::message_actions::{self, MessageAction, Offer, Pick, Selection};
+use crate::notifier::Notifier;
+use crate::polls::{self, PollAction, PollDraft};
+use crate::search::{self, RoomSearch};
+use crate::settings::{InputMode, Settings, Theme};
+use crate::ui_state::{Dialog, Panel, UiState};
 
 pub struct SpokeApp {
-    event_rx: mpsc::Receiver<AppEvent>,
+    event_rx: mpsc::Receiver<AccountEvent>,
+    /// Handed to each bridge we start, tagged with its account.
+    event_tx: mpsc::Sender<AccountEvent>,
+    /// The account shown; events from any other are ignored.
+    account: AccountId,
     cmd_tx: tokio_mpsc::UnboundedSender<AppCommand>,
 
     status: String,
+    /// Our full MXID once connected.
+    own_user_id: String,
     rooms: Vec<RoomInfo>,
     pending_invites: Vec<InviteInfo>,
+    /// Rooms we left or were removed from, until forgotten.
+    left_rooms: Vec<LeftRoom>,
     selected_room: Option<usize>,
-    /// Per-room message log: room_id → [(sender, body)] in chronological order.
-    messages: std::collections::HashMap<String, Vec<(String, String)>>,
+    /// Per-room message log in chronological order, including local echoes.
+    messages: std::collections::HashMap<String, Vec<TimelineItem>>,
     fetched_rooms: HashSet<String>,
-    input: String,
+    /// Recently selected room IDs, most recent first.
+    recent_rooms: Vec<String>,
+    /// The selection whose neighbours were last preloaded.
+    preloaded_for: Option<String>,
+    /// Rooms whose message log starts from a snapshot (timeline cache or
+    /// fetched history). A later snapshot replaces the log outright.
+    snapshot_rooms: HashSet<String>,
+    composer: Composer,
+    /// Reactions per room, by the event reacted to.
+    reactions: HashMap<String, HashMap<String, Vec<Reaction>>>,
+    /// Message whose toolbar stays up while the pointer is on it or one of
+    /// its menus is open.
+    toolbar_for: Option<String>,
+    /// Message picked with Alt+Up/Down for keyboard actions.
+    focused_message: Option<String>,
+    /// Thread open in the Threads panel, by root event ID.
+    thread_view: Option<String>,
+    /// Messages picked for a bulk copy, forward or removal.
+    selection: Option<Selection>,
+    /// Poll votes per room, by the poll's start event ID.
+    polls: HashMap<String, HashMap<String, PollVotes>>,
+    /// The poll in the "Create poll" dialog.
+    poll_draft: Option<PollDraft>,
+    /// Link previews by URL; `None` when the server had nothing to show.
+    url_previews: HashMap<String, Option<LinkPreview>>,
+    /// URLs whose preview has been asked for, so each is fetched once.
+    url_previews_requested: HashSet<String>,
+    /// URLs whose preview failed, with the `ctx.input(|i| i.time)` after
+    /// which it's asked for again.
+    url_preview_retry: HashMap<String, f64>,
+    /// Sticker and emote packs per room, for the sticker picker.
+    image_packs: HashMap<String, Vec<ImagePack>>,
+    image_packs_requested: HashSet<String>,
+    /// Pinned messages per room, for the pins panel and the Pin action.
+    pins: HashMap<String, Pins>,
+    pins_requested: HashSet<String>,
+    /// Sticker and emote thumbnails by `mxc://` URI; present once requested.
+    pack_images: markup::Images,
+    /// Back-pagination token per room: `Some(None)` once the start is reached.
+    history_tokens: HashMap<String, Option<String>>,
+    /// Rooms with older history on the way (`None`) or that failed to load
+    /// (`Some(error)`, not retried until asked).
+    history_loading: HashMap<String, Option<String>>,
+    /// The timeline's content height and scroll offset last frame.
+    timeline_metrics: (f32, f32),
+    /// Set when older messages were prepended, to keep the same ones in view.
+    scroll_keep: Option<(f32, f32)>,
+    /// Scroll offset to apply to the timeline next frame.
+    scroll_to: Option<f32>,
+    /// A message to bring into view once the timeline draws it.
+    jump_to: Option<String>,
+    /// Ctrl+F search in the selected room.
+    search: Option<RoomSearch>,
+    /// Open dialogs and persisted per-room panel layout.
+    ui: UiState,
+    /// Space hierarchies by space room ID, as last fetched from `/hierarchy`.
+    spaces: HashMap<String, SpaceNode>,
+
+    /// Kick/ban/unban being confirmed in the moderation dialog.
+    moderation: Option<ModerationDraft>,
+    /// Report being written in the report dialog.
+    report: Option<ReportDraft>,
+    /// Pending knocks on rooms we moderate, by room ID.
+    knocks: HashMap<String, Vec<Knock>>,
+    /// Notifying messages since each room was last read.
+    unread: HashMap<String, Unread>,
+    notifier: Notifier,
+    /// Window title as last set; empty until the first frame sets it.
+    window_title: String,
+    /// Per-room notification modes, once fetched; `None` follows the default.
+    notification_modes: HashMap<String, Option<NotificationMode>>,
+    /// Power levels shown in the roles dialog, with the room they belong to.
+    power_levels: Option<(String, PowerLevels)>,
+    /// Draft values in the roles dialog: user to promote, events_default, voice.
+    power_level_draft: (String, i64, i64),
+    /// Voice permission thresholds in the roles dialog: room, current, draft.
+    voice_permissions: Option<(String, VoicePermissions, VoicePermissions)>,
+    /// Room shown in the federation dialog, with its ACL and policy lists
+    /// once loaded.
+    server_acl: Option<(String, Option<(ServerAcl, Vec<PolicyList>)>)>,
+    acl_draft: AclDraft,
+    /// Room shown in the developer-mode state viewer, with its state once
+    /// loaded.
+    room_state: Option<(String, Option<Vec<StateEntry>>)>,
+    custom_event: CustomEventDraft,
+    /// Space whose AFK channel dialog is open.
+    afk: Option<AfkDraft>,
+    spin_off: Option<SpinOffDraft>,
+    /// What the homeserver rate limited, and until when (ms since the epoch).
+    rate_limited: Option<(String, u64)>,
+    /// What each room is watching together; `None` once stopped.
+    shared_media: HashMap<String, Option<SharedMedia>>,
+    /// Link being typed into the "watch together" menu.
+    share_media_draft: String,
+    /// The sync settings' excluded event types as typed; applied when the field loses focus.
+    excluded_types_draft: Option<String>,
+    /// Room shown in the room settings dialog, with its directory listing
+    /// once loaded.
+    room_settings: Option<(String, Option<DirectoryListing>)>,
+    /// Encryption of the room in `room_settings`, once loaded.
+    room_encryption: Option<RoomEncryption>,
+    /// The user asked to turn encryption on and is being asked to confirm.
+    confirm_encryption: bool,
+    room_settings_tab: RoomSettingsTab,
+    /// The room settings dialog's voice tab, once the config arrives.
+    voice_config: Option<VoiceConfigDraft>,
 
     // Invite dialog state.
-    show_invite_dialog: bool,
     invite_input: String,
 
     // Create room dialog state.
-    show_create_room_dialog: bool,
     create_room_name: String,
+    create_room_template: RoomTemplate,
+    create_room_encrypted: bool,
+    create_room_topic: String,
+    create_room_alias: String,
+    /// User IDs to invite, separated by commas or spaces.
+    create_room_invite: String,
+    /// The space a voice channel is being added to, from the space list.
+    create_room_space: Option<String>,
+    /// `None` uses the server's default room version.
+    create_room_version: Option<String>,
+    /// What the homeserver supports; `None` until known, when nothing is gated.
+    capabilities: Option<ServerCapabilities>,
 
     // Join room dialog state.
-    show_join_dialog: bool,
     join_room_input: String,
+    /// The address last previewed, with the preview once it's in.
+    join_peek: Option<(String, Option<Result<RoomPeek, String>>)>,
+    /// User ID typed into the "New direct message" dialog.
+    dm_input: String,
+
+    // Explore (room directory) dialog state.
+    explore_query: String,
+    explore_server: String,
+    /// The query the current results belong to (stale pages are dropped).
+    explore_results_query: Option<String>,
+    explore_results: Vec<PublicRoom>,
+    explore_next_batch: Option<String>,
+    explore_loading: bool,
 
     // Login state.
     logged_in: bool,
@@ -37,108 +217,1104 @@ pub struct SpokeApp {
     login_password: String,
     login_error: Option<String>,
     login_connecting: bool,
-    pending_spawn: Option<(mpsc::Sender<AppEvent>, tokio_mpsc::UnboundedReceiver<AppCommand>)>,
+    /// Preflight result for the homeserver it was run against.
+    server_info: Option<(String, Result<ServerInfo, String>)>,
+    /// Homeserver whose preflight is in flight.
+    server_probe: Option<String>,
+    /// SSO page opened for the login in progress.
+    sso_url: Option<String>,
+    pending_spawn: Option<(EventSender, tokio_mpsc::UnboundedReceiver<AppCommand>)>,
+
+    // Settings.
+    settings: Settings,
+    /// `updated_ts` of the settings last taken from or pushed to account
+    /// data; `None` until the account's copy has been read.
+    settings_synced_ts: Option<u64>,
+    /// The account's roaming settings as last seen, for other machines'
+    /// sections.
+    account_settings: Option<AccountSettingsEventContent>,
+    keybind_input: KeybindInput,
+    /// Action waiting for its next input to be captured as a binding.
+    capturing_binding: Option<VoiceAction>,
+    /// A captured binding that clashes with another action: (action, binding, other).
+    binding_conflict: Option<(VoiceAction, Binding, VoiceAction)>,
+    /// File used by settings export/import.
+    settings_file: String,
+    /// Outcome of the last export/import, shown under the buttons.
+    settings_file_status: Option<Result<String, String>>,
+    log_filter: LogFilter,
+    /// Log filter being edited; applied with the Apply button.
+    log_filter_input: String,
+    log_filter_error: Option<String>,
 
     // Voice state.
     in_voice: bool,
     voice_muted: bool,
+    voice_deafened: bool,
+    /// Mute state to restore when undeafening.
+    muted_before_deafen: bool,
     voice_room_id: Option<String>,
     voice_participants: Vec<String>,
+    /// Who's in each room's call, by room ID, with when each membership
+    /// lapses (ms since the Unix epoch).
+    voice_members: HashMap<String, Vec<(String, u64)>>,
+    /// Participants whose speech ducks everyone else.
+    voice_priority: HashSet<String>,
+    /// Participants talking right now, us included.
+    voice_speaking: HashSet<String>,
+    /// How loud each participant is, us included; missing means silent.
+    voice_levels: HashMap<String, Level>,
+    /// Remote audio tracks being played.
+    voice_pipelines: usize,
+    /// Our mic level while we publish, and the meter's displayed state:
+    /// (decaying peak, time until which to show clipping).
+    input_meter: Option<Arc<InputMeter>>,
+    input_level: (f32, f64),
+    /// Audio devices to offer in settings; `None` until first asked for.
+    audio_devices: Option<AudioDevices>,
+    /// The mic's latest level while it's being tested in settings.
+    mic_test: Option<Level>,
+    /// The voice message being recorded, if any.
+    voice_recording: Option<VoiceRecording>,
+    /// `mxc://` URI of the voice message playing.
+    playing_voice: Option<String>,
+    /// The call we're in, for its summary when we leave.
+    call: Option<CallTracker>,
+    /// Summary card offered to the call's initiator: (room ID, summary).
+    call_summary: Option<(String, VoiceSummaryEventContent)>,
+    /// File the call's chat is exported to, and the outcome of the last try.
+    call_export_file: String,
+    call_export_status: Option<Result<String, String>>,
+
+    // Stage state (for the active voice room).
+    voice_stage: bool,
+    voice_can_publish: bool,
+    /// Further rights granted by the room's voice permissions.
+    voice_can_screen_share: bool,
+    voice_priority_speaker: bool,
+    voice_can_moderate: bool,
+    hand_raised: bool,
+    /// Listeners with a raised hand, in the order they raised it.
+    raised_hands: Vec<String>,
+
+    // In-call data signals.
+    /// Participants currently typing in the call's room.
+    call_typing: HashSet<String>,
+    /// Participants with a (non-stage) hand raised via data message.
+    call_hands: HashSet<String>,
+    /// Whether we've told the call we're typing.
+    sent_call_typing: bool,
+    /// Emoji reactions currently floating over the call view.
+    floating_reactions: Vec<FloatingReaction>,
+    /// "alice joined the call" notices, with the time they were shown.
+    call_toasts: Vec<(String, f64)>,
+    /// Our mic's device went away mid-call: the inputs offered instead.
+    mic_lost: Option<Vec<String>>,
+    /// Our speakers' device went away mid-call.
+    output_lost: bool,
+    /// A ring waiting for us to answer or decline.
+    incoming_call: Option<IncomingCall>,
+
+    /// Member lists by room ID, for the members panel.
+    members: HashMap<String, Vec<Member>>,
+    /// Rooms whose member list has been asked for.
+    members_requested: HashSet<String>,
+    /// Rooms showing only their stored members while the full list loads.
+    members_loading: HashSet<String>,
+
+    // Profiles.
+    /// Resolved sender profiles by user ID.
+    profiles: HashMap<String, SenderProfile>,
+    /// Users whose profile has been asked for, so each is fetched once.
+    profiles_requested: HashSet<String>,
+    /// Display name and avatar file being edited in the settings window.
+    display_name_input: String,
+    avatar_path_input: String,
+
+    // Sessions (our account's devices), listed in the settings window.
+    /// `None` until loaded.
+    devices: Option<Vec<DeviceInfo>>,
+    devices_requested: bool,
+    /// Other sessions ticked for signing out.
+    devices_selected: HashSet<String>,
+    device_name_input: String,
+    device_password_input: String,
+    logout_keep_crypto: bool,
+
+    /// Latest voice preflight results, shown in the diagnostics dialog.
+    voice_preflight: Option<PreflightReport>,
+    /// Last latency measurement, for the diagnostics dialog.
+    voice_latency: Option<Result<LatencyReport, String>>,
+    /// A latency measurement is waiting for its marker to come back.
+    voice_latency_pending: bool,
+    /// TURN servers from the last voice grant.
+    turn_servers: Vec<TurnServer>,
+    /// Per-server ICE test results; `Some(empty)` while a test runs.
+    ice_test: Option<Vec<(String, Probe)>>,
+    /// STUN URL being added in the settings window.
+    stun_input: String,
+
+    // Receipts and typing.
+    /// Others typing, per room.
+    typing_users: HashMap<String, Vec<String>>,
+    /// Latest public read receipt per user, per room.
+    read_markers: HashMap<String, HashMap<String, String>>,
+    /// Event we last sent a read receipt for, per room.
+    sent_receipts: HashMap<String, String>,
+    /// Room we've told we're typing in.
+    sent_typing: Option<String>,
+    /// `ctx.input(|i| i.time)` when we last told it.
+    sent_typing_at: f64,
+}
+
+/// Quick reactions offered in the call controls.
+const CALL_REACTIONS: [&str; 6] = ["👍", "😂", "🎉", "❤", "😮", "👏"];
+
+/// Recently visited rooms kept warm by preloading.
+const RECENT_ROOMS: usize = 4;
+
+/// How long a reaction takes to float off the top of the overlay, in seconds.
+const REACTION_LIFETIME: f64 = 2.5;
+
+/// How long a join/leave notice stays up, in seconds.
+const CALL_TOAST_LIFETIME: f64 = 4.0;
+
+/// Seconds between repeats of our typing notice while the composer keeps
+/// changing; the SDK's notices lapse after four.
+const TYPING_RESEND: f64 = 3.0;
+
+/// How long settings must go unchanged before they're pushed to account
+/// data, so dragging a slider doesn't send one request per frame.
+const SETTINGS_ROAM_DELAY: std::time::Duration = std::time::Duration::from_secs(2);
+
+struct ModerationDraft {
+    room_id: String,
+    user_id: String,
+    action: ModerationAction,
+    reason: String,
+}
+
+/// A message or room report being written in the report dialog.
+struct ReportDraft {
+    room_id: String,
+    /// `None` reports the whole room.
+    event_id: Option<String>,
+    reason: String,
+    /// 0 (mild) to 100 (most offensive); sent negated as the report score.
+    severity: i32,
+}
+
+/// Text fields of the federation dialog.
+struct AclDraft {
+    /// Server glob to deny.
+    deny: String,
+    /// Room ID of a policy list to follow.
+    follow: String,
+    /// Rule to add: list room ID, kind, entity and reason.
+    rule_list: String,
+    rule_kind: PolicyKind,
+    rule_entity: String,
+    rule_reason: String,
+}
+
+impl Default for AclDraft {
+    fn default() -> Self {
+        Self {
+            deny: String::new(),
+            follow: String::new(),
+            rule_list: String::new(),
+            rule_kind: PolicyKind::User,
+            rule_entity: String::new(),
+            rule_reason: String::new(),
+        }
+    }
+}
+
+/// An event being written in the developer-mode state viewer.
+#[derive(Default)]
+struct CustomEventDraft {
+    /// A state event (under `state_key`) rather than a message-like one.
+    state: bool,
+    event_type: String,
+    state_key: String,
+    /// JSON content.
+    content: String,
+    /// Why `content` can't be sent.
+    error: Option<String>,
+}
+
+/// A space's AFK channel setting, as edited in its dialog.
+struct AfkDraft {
+    space_id: String,
+    /// `false` until the current setting arrives.
+    loaded: bool,
+    can_edit: bool,
+    afk_room: Option<String>,
+    timeout_mins: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RoomSettingsTab {
+    General,
+    Voice,
+}
+
+/// A room's voice config as edited in the room settings dialog.
+struct VoiceConfigDraft {
+    room_id: String,
+    /// As last seen in the room.
+    current: VoiceConfigEventContent,
+    draft: VoiceConfigEventContent,
+    can_edit: bool,
+    /// User ID being typed into the ban list.
+    ban: String,
+}
+
+/// A discussion about to be spun off into a new room.
+struct SpinOffDraft {
+    room_id: String,
+    /// The message, or the root of the thread it's in.
+    event_id: String,
+    sender: String,
+    quote: String,
+    name: String,
+}
+
+/// A voice message being recorded in the composer.
+struct VoiceRecording {
+    room_id: String,
+    /// `ctx.input(|i| i.time)` when recording was asked for.
+    started: f64,
+    /// Mic level, once the bridge has started recording.
+    meter: Option<Arc<InputMeter>>,
+    /// Decaying peak shown in the level bar.
+    level: f32,
+}
+
+/// Someone ringing us to join a DM call.
+struct IncomingCall {
+    room_id: String,
+    call_id: String,
+    caller: String,
+    /// `ctx.input(|i| i.time)` when the ring arrived.
+    since: f64,
+}
+
+struct FloatingReaction {
+    emoji: String,
+    sender: String,
+    /// `ctx.input(|i| i.time)` when the reaction arrived.
+    started: f64,
+    /// Horizontal position as a fraction of the overlay width.
+    x: f32,
 }
 
 impl SpokeApp {
-    pub fn new(
-        cc: &eframe::CreationContext<'_>,
-        event_rx: mpsc::Receiver<AppEvent>,
-        cmd_tx: tokio_mpsc::UnboundedSender<AppCommand>,
-        mut pending_spawn: Option<(
-            mpsc::Sender<AppEvent>,
-            tokio_mpsc::UnboundedReceiver<AppCommand>,
-        )>,
-    ) -> Self {
+    pub fn new(cc: &eframe::CreationContext<'_>, log_filter: LogFilter) -> Self {
+        // Avatars arrive as encoded thumbnails.
+        egui_extras::install_image_loaders(&cc.egui_ctx);
+
         let hs_env = std::env::var("SPOKE_HS").ok();
         let user_env = std::env::var("SPOKE_USER").ok();
         let pass_env = std::env::var("SPOKE_PASS").ok();
 
-        let login_homeserver =
-            hs_env.clone().unwrap_or_else(|| "http://localhost:8448".into());
+        let login_homeserver = hs_env
+            .clone()
+            .unwrap_or_else(|| "http://localhost:8448".into());
         let login_username = user_env.clone().unwrap_or_default();
         let login_password = pass_env.clone().unwrap_or_default();
 
+        let (event_tx, event_rx) = mpsc::channel();
+        let mut app = Self::logged_out(
+            KeybindInput::new(&cc.egui_ctx),
+            log_filter,
+            event_tx,
+            event_rx,
+            AccountId(0),
+        );
+        spoke_core::proxy::set(app.settings.proxy.clone());
+        cc.egui_ctx
+            .set_theme(app.settings.appearance.theme.preference());
+
         // Auto-submit if all three env vars are set (dev convenience).
-        let mut login_connecting = false;
         if hs_env.is_some() && user_env.is_some() && pass_env.is_some() {
-            if let Some((event_tx, cmd_rx)) = pending_spawn.take() {
+            if let Some((event_tx, cmd_rx)) = app.pending_spawn.take() {
                 spawn_matrix_task(
                     event_tx,
                     cmd_rx,
                     cc.egui_ctx.clone(),
                     login_homeserver.clone(),
-                    login_username.clone(),
-                    login_password.clone(),
+                    Login::Password {
+                        username: login_username.clone(),
+                        password: login_password.clone(),
+                        register: true,
+                    },
+                    app.settings.sync.clone(),
+                    app.settings.privacy.invisible.clone(),
+                    client_options(&app.settings),
                 );
-                login_connecting = true;
+                app.login_connecting = true;
             }
         }
+        app.login_homeserver = login_homeserver;
+        app.login_username = login_username;
+        app.login_password = login_password;
+        app
+    }
 
+    /// The app as it is before anyone logs in: no rooms, nothing cached, the
+    /// login panel showing, and a bridge for `account` ready to spawn.
+    /// Settings and the panel layout come from disk.
+    fn logged_out(
+        keybind_input: KeybindInput,
+        log_filter: LogFilter,
+        event_tx: mpsc::Sender<AccountEvent>,
+        event_rx: mpsc::Receiver<AccountEvent>,
+        account: AccountId,
+    ) -> Self {
+        let (cmd_tx, cmd_rx) = tokio_mpsc::unbounded_channel();
+        let pending_spawn = Some((EventSender::new(account, event_tx.clone()), cmd_rx));
         Self {
             event_rx,
+            event_tx,
+            account,
             cmd_tx,
             status: String::new(),
+            own_user_id: String::new(),
             rooms: Vec::new(),
             pending_invites: Vec::new(),
+            left_rooms: Vec::new(),
             selected_room: None,
             messages: std::collections::HashMap::new(),
             fetched_rooms: HashSet::new(),
-            input: String::new(),
-            show_invite_dialog: false,
+            recent_rooms: Vec::new(),
+            preloaded_for: None,
+            snapshot_rooms: HashSet::new(),
+            composer: Composer::default(),
+            reactions: HashMap::new(),
+            toolbar_for: None,
+            focused_message: None,
+            thread_view: None,
+            selection: None,
+            polls: HashMap::new(),
+            poll_draft: None,
+            url_previews: HashMap::new(),
+            url_previews_requested: HashSet::new(),
+            url_preview_retry: HashMap::new(),
+            image_packs: HashMap::new(),
+            image_packs_requested: HashSet::new(),
+            pins: HashMap::new(),
+            pins_requested: HashSet::new(),
+            pack_images: HashMap::new(),
+            history_tokens: HashMap::new(),
+            history_loading: HashMap::new(),
+            timeline_metrics: (0.0, 0.0),
+            scroll_keep: None,
+            scroll_to: None,
+            jump_to: None,
+            search: None,
+            ui: UiState::load(),
+            spaces: HashMap::new(),
+            moderation: None,
+            report: None,
+            knocks: HashMap::new(),
+            unread: HashMap::new(),
+            notifier: Notifier::new(),
+            window_title: String::new(),
+            notification_modes: HashMap::new(),
+            power_levels: None,
+            power_level_draft: (String::new(), 0, 0),
+            voice_permissions: None,
+            server_acl: None,
+            acl_draft: AclDraft::default(),
+            room_state: None,
+            custom_event: CustomEventDraft::default(),
+            afk: None,
+            spin_off: None,
+            rate_limited: None,
+            shared_media: HashMap::new(),
+            share_media_draft: String::new(),
+            excluded_types_draft: None,
+            room_encryption: None,
+            confirm_encryption: false,
+            room_settings: None,
+            room_settings_tab: RoomSettingsTab::General,
+            voice_config: None,
             invite_input: String::new(),
-            show_create_room_dialog: false,
             create_room_name: String::new(),
-            show_join_dialog: false,
+            create_room_template: RoomTemplate::PrivateChat,
+            create_room_encrypted: RoomTemplate::PrivateChat.encrypted_by_default(),
+            create_room_topic: String::new(),
+            create_room_alias: String::new(),
+            create_room_invite: String::new(),
+            create_room_space: None,
+            create_room_version: None,
+            capabilities: None,
             join_room_input: String::new(),
+            join_peek: None,
+            dm_input: String::new(),
+            explore_query: String::new(),
+            explore_server: String::new(),
+            explore_results_query: None,
+            explore_results: Vec::new(),
+            explore_next_batch: None,
+            explore_loading: false,
             logged_in: false,
-            login_homeserver,
-            login_username,
-            login_password,
+            login_homeserver: String::new(),
+            login_username: String::new(),
+            login_password: String::new(),
             login_error: None,
-            login_connecting,
+            login_connecting: false,
+            server_info: None,
+            server_probe: None,
+            sso_url: None,
             pending_spawn,
+            settings: Settings::load(),
+            settings_synced_ts: None,
+            account_settings: None,
+            keybind_input,
+            capturing_binding: None,
+            binding_conflict: None,
+            settings_file: Settings::default_export_path().display().to_string(),
+            settings_file_status: None,
+            log_filter_input: log_filter.current(),
+            log_filter,
+            log_filter_error: None,
             in_voice: false,
             voice_muted: false,
+            voice_deafened: false,
+            muted_before_deafen: false,
             voice_room_id: None,
             voice_participants: Vec::new(),
+            voice_members: HashMap::new(),
+            voice_priority: HashSet::new(),
+            voice_speaking: HashSet::new(),
+            voice_levels: HashMap::new(),
+            voice_pipelines: 0,
+            input_meter: None,
+            voice_recording: None,
+            playing_voice: None,
+            input_level: (0.0, 0.0),
+            audio_devices: None,
+            mic_test: None,
+            call: None,
+            call_summary: None,
+            call_export_file: String::new(),
+            call_export_status: None,
+            voice_stage: false,
+            voice_can_publish: true,
+            voice_can_screen_share: false,
+            voice_priority_speaker: false,
+            voice_can_moderate: false,
+            hand_raised: false,
+            raised_hands: Vec::new(),
+            call_typing: HashSet::new(),
+            call_hands: HashSet::new(),
+            sent_call_typing: false,
+            floating_reactions: Vec::new(),
+            call_toasts: Vec::new(),
+            mic_lost: None,
+            output_lost: false,
+            incoming_call: None,
+            members: HashMap::new(),
+            members_requested: HashSet::new(),
+            members_loading: HashSet::new(),
+            profiles: HashMap::new(),
+            profiles_requested: HashSet::new(),
+            display_name_input: String::new(),
+            avatar_path_input: String::new(),
+            devices: None,
+            devices_requested: false,
+            devices_selected: HashSet::new(),
+            device_name_input: String::new(),
+            device_password_input: String::new(),
+            logout_keep_crypto: false,
+            voice_preflight: None,
+            voice_latency: None,
+            voice_latency_pending: false,
+            turn_servers: Vec::new(),
+            ice_test: None,
+            stun_input: String::new(),
+            typing_users: HashMap::new(),
+            read_markers: HashMap::new(),
+            sent_receipts: HashMap::new(),
+            sent_typing: None,
+            sent_typing_at: 0.0,
         }
     }
 }
 
 impl eframe::App for SpokeApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // A clicked desktop notification brings its room to the front.
+        if let Some(room_id) = self.notifier.take_click() {
+            if let Some(i) = self.rooms.iter().position(|r| r.id == room_id) {
+                self.selected_room = Some(i);
+            }
+            ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+        }
+
         // Drain events from the Matrix task.
-        while let Ok(event) = self.event_rx.try_recv() {
+        while let Ok(AccountEvent { account, event }) = self.event_rx.try_recv() {
+            if account != self.account {
+                continue;
+            }
             match event {
-                AppEvent::Connected { username } => {
+                AppEvent::SsoStarted { url } => {
+                    self.sso_url = Some(url);
+                }
+                AppEvent::ServerProbed { homeserver, result } => {
+                    if self.server_probe.as_deref() == Some(homeserver.as_str()) {
+                        self.server_probe = None;
+                    }
+                    self.server_info = Some((homeserver, result));
+                }
+                AppEvent::Connected { username, user_id } => {
+                    let _ = self.cmd_tx.send(AppCommand::ResolveProfiles {
+                        room_id: String::new(),
+                        user_ids: vec![user_id.clone()],
+                    });
+                    self.profiles_requested.insert(user_id.clone());
+                    self.own_user_id = user_id;
                     self.logged_in = true;
                     self.login_connecting = false;
                     self.login_password.clear();
                     self.status = format!("@{username}");
                 }
-                AppEvent::RoomsUpdated(rooms) => {
+                AppEvent::Capabilities(capabilities) => {
+                    self.capabilities = Some(capabilities);
+                }
+                AppEvent::RoomsUpdated(mut rooms) => {
+                    let selected_id = self
+                        .selected_room
+                        .and_then(|i| self.rooms.get(i))
+                        .map(|r| r.id.clone());
+                    // Keep previews we learned from history the bridge hasn't seen.
+                    for room in &mut rooms {
+                        let Some(old) = self.rooms.iter().find(|r| r.id == room.id) else {
+                            continue;
+                        };
+                        if let Some(known) = old.last_message.clone() {
+                            if room.last_message.as_ref().is_none_or(|m| m.ts < known.ts) {
+                                room.last_message = Some(known);
+                            }
+                        }
+                        room.last_activity_ts = room.last_activity_ts.max(old.last_activity_ts);
+                    }
                     if let Some(i) = self.selected_room {
                         if i >= rooms.len() {
-                            self.selected_room = if rooms.is_empty() { None } else { Some(rooms.len() - 1) };
+                            self.selected_room = if rooms.is_empty() {
+                                None
+                            } else {
+                                Some(rooms.len() - 1)
+                            };
                         }
                     }
                     self.rooms = rooms;
-                    if self.selected_room.is_none() && !self.rooms.is_empty() {
-                        self.selected_room = Some(0);
+                    self.sort_rooms();
+                    if let Some(i) =
+                        selected_id.and_then(|id| self.rooms.iter().position(|r| r.id == id))
+                    {
+                        self.selected_room = Some(i);
+                    }
+                    if self.selected_room.is_none() {
+                        self.selected_room = self.rooms.iter().position(|r| !r.is_space);
                     }
                 }
                 AppEvent::InvitesUpdated(invites) => {
                     self.pending_invites = invites;
                 }
-                AppEvent::Message { room_id, sender, body } => {
-                    self.messages.entry(room_id).or_default().push((sender, body));
+                AppEvent::LeftRoomsUpdated(rooms) => {
+                    self.left_rooms = rooms;
+                }
+                AppEvent::Message { room_id, item } => {
+                    if !item.membership {
+                        self.note_activity(&room_id, RoomPreview::from(&item));
+                    }
+                    let log = self.messages.entry(room_id).or_default();
+                    // Replace our local echo, or an event we already have.
+                    let existing = log.iter().position(|m| {
+                        (item.txn_id.is_some() && m.txn_id == item.txn_id)
+                            || (item.event_id.is_some() && m.event_id == item.event_id)
+                    });
+                    match existing {
+                        Some(i) => log[i] = item,
+                        None => log.push(item),
+                    }
+                }
+                AppEvent::Decrypted { room_id, item } => {
+                    if let Some(log) = self.messages.get_mut(&room_id) {
+                        if let Some(m) = log
+                            .iter_mut()
+                            .find(|m| m.event_id.is_some() && m.event_id == item.event_id)
+                        {
+                            *m = item;
+                        }
+                    }
+                }
+                AppEvent::MessageQueued { room_id, item } => {
+                    self.note_activity(&room_id, RoomPreview::from(&item));
+                    let log = self.messages.entry(room_id).or_default();
+                    if !log.iter().any(|m| m.txn_id == item.txn_id) {
+                        log.push(item);
+                    }
+                }
+                AppEvent::MessageEdited {
+                    room_id,
+                    event_id,
+                    sender,
+                    body,
+                    rich,
+                } => {
+                    let original = self.messages.get_mut(&room_id).and_then(|log| {
+                        log.iter_mut()
+                            .find(|m| m.event_id.as_deref() == Some(event_id.as_str()))
+                    });
+                    // Only the author may edit a message.
+                    if let Some(m) = original.filter(|m| m.sender == sender) {
+                        m.body = body;
+                        m.rich = rich;
+                        m.edited = true;
+                    }
+                }
+                AppEvent::Reactions { room_id, reactions } => {
+                    let room = self.reactions.entry(room_id).or_default();
+                    for reaction in reactions {
+                        let on = room.entry(reaction.target.clone()).or_default();
+                        if !on.iter().any(|r| r.event_id == reaction.event_id) {
+                            on.push(reaction);
+                        }
+                    }
+                }
+                AppEvent::Redacted { room_id, event_id } => {
+                    if let Some(room) = self.reactions.get_mut(&room_id) {
+                        room.remove(&event_id);
+                        for on in room.values_mut() {
+                            on.retain(|r| r.event_id != event_id);
+                        }
+                    }
+                    if let Some(log) = self.messages.get_mut(&room_id) {
+                        log.retain(|m| m.event_id.as_deref() != Some(event_id.as_str()));
+                    }
+                }
+                AppEvent::MessageDelivery {
+                    room_id,
+                    txn_id,
+                    state,
+                } => {
+                    let log = self.messages.entry(room_id).or_default();
+                    // Ignore updates for echoes the remote event already replaced.
+                    if let Some(m) = log.iter_mut().find(|m| {
+                        m.txn_id.as_deref() == Some(txn_id.as_str()) && m.delivery.is_some()
+                    }) {
+                        match state {
+                            DeliveryState::Sent { event_id } => {
+                                m.event_id = Some(event_id);
+                                m.delivery = None;
+                            }
+                            state => m.delivery = Some(state),
+                        }
+                    }
+                }
+                AppEvent::MembershipChanged {
+                    room_id,
+                    user_id,
+                    action,
+                    by,
+                    reason,
+                } => {
+                    let room = self
+                        .rooms
+                        .iter()
+                        .find(|r| r.id == room_id)
+                        .map_or(room_id.as_str(), |r| r.name.as_str());
+                    let what = match action {
+                        ModerationAction::Kick => "kicked from",
+                        ModerationAction::Ban => "banned from",
+                        ModerationAction::Unban => "unbanned in",
+                    };
+                    self.status = match reason {
+                        Some(reason) => format!("{user_id} was {what} {room} by {by}: {reason}"),
+                        None => format!("{user_id} was {what} {room} by {by}"),
+                    };
+                }
+                AppEvent::TypingUpdated { room_id, user_ids } => {
+                    self.typing_users.insert(room_id, user_ids);
+                }
+                AppEvent::ReceiptsUpdated { room_id, receipts } => {
+                    self.read_markers
+                        .entry(room_id)
+                        .or_default()
+                        .extend(receipts);
+                }
+                AppEvent::PowerLevelsLoaded { room_id, levels } => {
+                    self.power_level_draft.1 = levels.events_default;
+                    self.power_level_draft.2 = levels.voice;
+                    self.power_levels = Some((room_id, levels));
+                }
+                AppEvent::AfkPolicyLoaded {
+                    space_id,
+                    afk_room,
+                    timeout_mins,
+                    can_edit,
+                } => {
+                    if let Some(draft) = self.afk.as_mut().filter(|d| d.space_id == space_id) {
+                        *draft = AfkDraft {
+                            space_id,
+                            loaded: true,
+                            can_edit,
+                            afk_room,
+                            timeout_mins,
+                        };
+                    }
+                }
+                AppEvent::MovedToAfk { from, to } => {
+                    let name = |id: &str| {
+                        self.rooms
+                            .iter()
+                            .find(|r| r.id == id)
+                            .map_or(id.to_owned(), |r| r.name.clone())
+                    };
+                    self.status = format!(
+                        "Moved from {} to {} after a while without speaking",
+                        name(&from),
+                        name(&to)
+                    );
+                }
+                AppEvent::SharedMediaLoaded { room_id, media } => {
+                    self.shared_media.insert(room_id, media);
+                }
+                AppEvent::SharedMediaUpdated { room_id, update } => {
+                    update.apply(self.shared_media.entry(room_id).or_default());
+                }
+                AppEvent::ServerAclLoaded {
+                    room_id,
+                    acl,
+                    lists,
+                } => {
+                    if let Some((rid, loaded)) = &mut self.server_acl {
+                        if *rid == room_id {
+                            *loaded = Some((acl, lists));
+                        }
+                    }
+                }
+                AppEvent::VoicePermissionsLoaded {
+                    room_id,
+                    permissions,
+                } => {
+                    self.voice_permissions = Some((room_id, permissions, permissions));
+                }
+                AppEvent::KnocksUpdated { room_id, knocks } => {
+                    if knocks.is_empty() {
+                        self.knocks.remove(&room_id);
+                    } else {
+                        self.knocks.insert(room_id, knocks);
+                    }
+                }
+                AppEvent::VoiceConfigLoaded {
+                    room_id,
+                    config,
+                    can_edit,
+                } => match &mut self.voice_config {
+                    Some(voice) if voice.room_id == room_id => {
+                        // Someone else's change replaces an untouched form,
+                        // but never edits in progress.
+                        if voice.draft == voice.current {
+                            voice.draft = config.clone();
+                        }
+                        voice.current = config;
+                        voice.can_edit = can_edit;
+                    }
+                    _ if self
+                        .room_settings
+                        .as_ref()
+                        .is_some_and(|(open, _)| *open == room_id) =>
+                    {
+                        self.voice_config = Some(VoiceConfigDraft {
+                            room_id,
+                            current: config.clone(),
+                            draft: config,
+                            can_edit,
+                            ban: String::new(),
+                        });
+                    }
+                    _ => {}
+                },
+                AppEvent::DirectoryListingLoaded { room_id, listing } => {
+                    if let Some((open, shown)) = &mut self.room_settings {
+                        if *open == room_id {
+                            *shown = Some(listing);
+                        }
+                    }
+                }
+                AppEvent::RoomEncryptionLoaded {
+                    room_id,
+                    encryption,
+                } => {
+                    if self
+                        .room_settings
+                        .as_ref()
+                        .is_some_and(|(open, _)| *open == room_id)
+                    {
+                        self.room_encryption = Some(encryption);
+                    }
+                }
+                AppEvent::MembersLoaded {
+                    room_id,
+                    members,
+                    more,
+                } => {
+                    if more {
+                        self.members_loading.insert(room_id.clone());
+                    } else {
+                        self.members_loading.remove(&room_id);
+                    }
+                    self.members.insert(room_id, members);
+                }
+                AppEvent::ProfileResolved { user_id, profile } => {
+                    if user_id == self.own_user_id && self.display_name_input.is_empty() {
+                        self.display_name_input = profile.display_name.clone().unwrap_or_default();
+                    }
+                    self.profiles.insert(user_id, profile);
+                }
+                AppEvent::PollUpdates { room_id, updates } => {
+                    let room = self.polls.entry(room_id).or_default();
+                    for update in updates {
+                        match update {
+                            PollUpdate::Response {
+                                poll_id,
+                                sender,
+                                ts,
+                                answers,
+                            } => {
+                                room.entry(poll_id)
+                                    .or_default()
+                                    .add_response(&sender, ts, answers);
+                            }
+                            PollUpdate::End {
+                                poll_id,
+                                sender,
+                                ts,
+                            } => {
+                                room.entry(poll_id).or_default().add_end(&sender, ts);
+                            }
+                        }
+                    }
+                }
+                AppEvent::UrlPreviewLoaded { url, preview } => match preview {
+                    Ok(preview) => {
+                        self.url_preview_retry.remove(&url);
+                        self.url_previews.insert(url, preview);
+                    }
+                    Err(_) => {
+                        self.url_previews_requested.remove(&url);
+                        self.url_preview_retry
+                            .insert(url, ctx.input(|i| i.time) + URL_PREVIEW_RETRY);
+                    }
+                },
+                AppEvent::ImagePacksLoaded { room_id, packs } => {
+                    self.image_packs.insert(room_id, packs);
+                }
+                AppEvent::RoomStateLoaded { room_id, state } => {
+                    if let Some((shown, loaded)) = &mut self.room_state {
+                        if *shown == room_id {
+                            *loaded = Some(state);
+                        }
+                    }
+                }
+                AppEvent::PinsLoaded { room_id, pins } => {
+                    self.pins.insert(room_id, pins);
+                }
+                AppEvent::PinsChanged { room_id } => {
+                    // Only rooms we've shown pins for; the rest load on demand.
+                    if self.pins.contains_key(&room_id) {
+                        let _ = self.cmd_tx.send(AppCommand::FetchPins { room_id });
+                    }
+                }
+                AppEvent::PackImageLoaded { mxc, image } => {
+                    self.pack_images.insert(mxc, image);
+                }
+                AppEvent::LoggedOut => {
+                    // A new account for the next login; everything from the
+                    // old session goes, except where the user logs in.
+                    let event_tx = self.event_tx.clone();
+                    let event_rx = std::mem::replace(&mut self.event_rx, mpsc::channel().1);
+                    let next = self.next_account();
+                    let old = std::mem::replace(
+                        self,
+                        Self::logged_out(
+                            KeybindInput::new(ctx),
+                            self.log_filter.clone(),
+                            event_tx,
+                            event_rx,
+                            next,
+                        ),
+                    );
+                    self.settings = old.settings;
+                    self.ui = old.ui;
+                    self.ui.close(Dialog::Settings);
+                    self.login_homeserver = old.login_homeserver;
+                    self.login_username = old.login_username;
+                }
+                AppEvent::DevicesLoaded(devices) => {
+                    if self.device_name_input.is_empty() {
+                        if let Some(own) = devices.iter().find(|d| d.current) {
+                            self.device_name_input = own.display_name.clone().unwrap_or_default();
+                        }
+                    }
+                    self.devices_selected
+                        .retain(|id| devices.iter().any(|d| &d.device_id == id));
+                    self.devices = Some(devices);
+                }
+                AppEvent::Notification {
+                    room_id,
+                    sender,
+                    body,
+                    highlight,
+                    mentions_me,
+                    ..
+                } => {
+                    let is_direct = self.rooms.iter().any(|r| r.id == room_id && r.is_direct);
+                    if highlight || is_direct {
+                        self.notify_desktop(
+                            ctx,
+                            &room_id,
+                            format!("{}: {body}", self.display_name(&sender)),
+                        );
+                    }
+                    let unread = self.unread.entry(room_id).or_default();
+                    unread.notifications += 1;
+                    unread.highlight |= highlight;
+                    unread.mentions += u32::from(mentions_me);
+                    // Flash the taskbar entry for mentions that arrive while
+                    // we're in another window.
+                    let quiet =
+                        self.settings.notifications.do_not_disturb || ctx.input(|i| i.focused);
+                    if mentions_me && !quiet {
+                        ctx.send_viewport_cmd(egui::ViewportCommand::RequestUserAttention(
+                            egui::UserAttentionType::Critical,
+                        ));
+                    }
+                }
+                AppEvent::VoicePing { room_id, sender } => {
+                    let is_direct = self.rooms.iter().any(|r| r.id == room_id && r.is_direct);
+                    if is_direct && self.voice_room_id.as_deref() != Some(room_id.as_str()) {
+                        self.notify_desktop(
+                            ctx,
+                            &room_id,
+                            format!("📞 {} started a call", self.display_name(&sender)),
+                        );
+                    }
+                }
+                AppEvent::AccountSettings(account) => {
+                    // Ours win only if changed since the account's were.
+                    match &account {
+                        Some(account) if account.updated_ts >= self.settings.updated_ts => {
+                            if let Some(pinned) = self.settings.apply_account(account) {
+                                self.ui.pinned_rooms = pinned;
+                                self.ui.save();
+                            }
+                            ctx.set_theme(self.settings.appearance.theme.preference());
+                            self.settings_synced_ts = Some(account.updated_ts);
+                        }
+                        _ => self.settings_synced_ts = Some(0),
+                    }
+                    self.account_settings = account;
+                }
+                AppEvent::VoiceMembers { room_id, members } => {
+                    self.voice_members.insert(room_id, members);
+                }
+                AppEvent::IncomingCall {
+                    room_id,
+                    call_id,
+                    caller,
+                } => {
+                    // Already in that call: nothing to answer.
+                    if self.voice_room_id.as_deref() != Some(room_id.as_str()) {
+                        self.notify_desktop(
+                            ctx,
+                            &room_id,
+                            format!("📞 {} is calling you", self.display_name(&caller)),
+                        );
+                        let since = ctx.input(|i| i.time);
+                        self.incoming_call = Some(IncomingCall {
+                            room_id,
+                            call_id,
+                            caller,
+                            since,
+                        });
+                        self.ui.open(Dialog::IncomingCall);
+                    }
+                }
+                AppEvent::RingEnded {
+                    call_id,
+                    user,
+                    action,
+                } => {
+                    let now = ctx.input(|i| i.time);
+                    if self
+                        .incoming_call
+                        .as_ref()
+                        .is_some_and(|c| c.call_id == call_id)
+                    {
+                        if action == RingAction::Cancel {
+                            self.call_toasts.push((
+                                format!("Missed call from {}", self.display_name(&user)),
+                                now,
+                            ));
+                        }
+                        self.incoming_call = None;
+                        self.ui.close(Dialog::IncomingCall);
+                    } else if action == RingAction::Decline && user != self.own_user_id {
+                        self.call_toasts.push((
+                            format!("{} declined the call", self.display_name(&user)),
+                            now,
+                        ));
+                    }
+                }
+                AppEvent::NotificationModeLoaded { room_id, mode } => {
+                    self.notification_modes.insert(room_id, mode);
+                }
+                AppEvent::Reported { event_id, .. } => {
+                    let what = if event_id.is_some() {
+                        "Message"
+                    } else {
+                        "Room"
+                    };
+                    self.status = format!("{what} reported to your homeserver's admins");
+                }
+                AppEvent::RateLimited(limited) => {
+                    self.rate_limited = limited;
+                }
+                AppEvent::SpinOffProgress { step } => {
+                    self.status = step;
+                }
+                AppEvent::SpunOff {
+                    room_id,
+                    not_invited,
+                } => {
+                    if let Some(i) = self.rooms.iter().position(|r| r.id == room_id) {
+                        self.selected_room = Some(i);
+                    }
+                    self.status = if not_invited.is_empty() {
+                        "Discussion moved to its own room".to_owned()
+                    } else {
+                        format!(
+                            "Discussion moved to its own room; couldn't invite {}",
+                            not_invited.join(", ")
+                        )
+                    };
+                }
+                AppEvent::RoomPeeked { room, peek } => {
+                    if let Some((asked, result)) = &mut self.join_peek {
+                        if *asked == room {
+                            *result = Some(peek);
+                        }
+                    }
+                }
+                AppEvent::Knocked { room_id } => {
+                    self.status =
+                        format!("Asked to join {room_id}; you'll get an invite if accepted");
                 }
                 AppEvent::Joined { room_id } => {
                     if let Some(i) = self.rooms.iter().position(|r| r.id == room_id) {
@@ -147,57 +1323,352 @@ impl eframe::App for SpokeApp {
                 }
                 AppEvent::Error(e) => {
                     if !self.logged_in {
-                        // Recreate channels so the user can retry login.
-                        let (new_event_tx, new_event_rx) = std::sync::mpsc::channel();
-                        let (new_cmd_tx, new_cmd_rx) = tokio::sync::mpsc::unbounded_channel();
-                        self.event_rx = new_event_rx;
-                        self.cmd_tx = new_cmd_tx;
-                        self.pending_spawn = Some((new_event_tx, new_cmd_rx));
+                        // A new account and command channel so the user can
+                        // retry login; stragglers from this attempt are ignored.
+                        self.account = self.next_account();
+                        let (cmd_tx, cmd_rx) = tokio_mpsc::unbounded_channel();
+                        self.cmd_tx = cmd_tx;
+                        self.pending_spawn = Some((
+                            EventSender::new(self.account, self.event_tx.clone()),
+                            cmd_rx,
+                        ));
                         self.login_connecting = false;
                         self.login_error = Some(e);
                     } else {
                         self.status = format!("Error: {e}");
                     }
                 }
-                AppEvent::HistoryLoaded { room_id, messages } => {
-                    let slot = self.messages.entry(room_id).or_default();
-                    // Prepend history before any live messages already received.
-                    let live = std::mem::take(slot);
-                    *slot = messages;
-                    slot.extend(live);
+                AppEvent::CommandFailed { command, error } => {
+                    self.status = format!("Couldn't {command}: {error}");
+                }
+                AppEvent::CachedHistoryLoaded { room_id, messages } => {
+                    self.apply_snapshot(room_id, messages);
+                }
+                AppEvent::HistoryLoaded {
+                    room_id,
+                    messages,
+                    prev_batch,
+                } => {
+                    // Preloaded rooms don't need fetching again on selection.
+                    self.fetched_rooms.insert(room_id.clone());
+                    self.history_tokens.insert(room_id.clone(), prev_batch);
+                    self.apply_snapshot(room_id, messages);
+                }
+                AppEvent::MoreHistoryLoaded {
+                    room_id,
+                    messages,
+                    prev_batch,
+                } => {
+                    let log = self.messages.entry(room_id.clone()).or_default();
+                    let older: Vec<TimelineItem> = messages
+                        .into_iter()
+                        .filter(|m| {
+                            !log.iter()
+                                .any(|l| l.event_id.is_some() && l.event_id == m.event_id)
+                        })
+                        .collect();
+                    let prepended = !older.is_empty();
+                    log.splice(0..0, older);
+                    self.history_tokens
+                        .insert(room_id.clone(), prev_batch.clone());
+                    self.history_loading.remove(&room_id);
+                    let selected = self
+                        .selected_room
+                        .and_then(|i| self.rooms.get(i))
+                        .is_some_and(|r| r.id == room_id);
+                    if prepended && selected {
+                        self.scroll_keep = Some(self.timeline_metrics);
+                    }
+
+                    // Keep backfilling while a search is still looking.
+                    if let Some(search) = self.search.as_mut().filter(|s| s.room_id == room_id) {
+                        let matches = search.matches(log);
+                        if search.continue_backfill(&matches, prev_batch.is_some()) {
+                            if let Some(from) = prev_batch {
+                                let _ = self
+                                    .cmd_tx
+                                    .send(AppCommand::FetchMoreHistory { room_id, from });
+                            }
+                        }
+                    }
+                }
+                AppEvent::MoreHistoryFailed { room_id, error } => {
+                    self.history_loading.insert(room_id, Some(error));
                 }
                 // Voice events
-                AppEvent::VoiceJoined { room_id } => {
+                AppEvent::VoiceJoined {
+                    room_id,
+                    participants,
+                    stage,
+                    can_publish,
+                    can_screen_share,
+                    priority_speaker,
+                    can_moderate,
+                } => {
+                    // Joining closed any mic test.
+                    self.mic_test = None;
+                    // A re-grant (promotion) reconnects in the same room; keep
+                    // the roster and hand queue in that case, and the mute and
+                    // deafen state, which the bridge carries over. A new room's
+                    // session starts unmuted and undeafened.
+                    if self.voice_room_id.as_deref() != Some(room_id.as_str()) {
+                        self.call = Some(CallTracker::new(&self.own_user_id, &participants));
+                        self.voice_participants = participants;
+                        self.raised_hands.clear();
+                        self.voice_muted = false;
+                        self.voice_deafened = false;
+                    }
                     self.in_voice = true;
                     self.voice_room_id = Some(room_id);
-                    self.voice_participants.clear();
+                    self.voice_stage = stage;
+                    self.voice_can_publish = can_publish;
+                    self.voice_can_screen_share = can_screen_share;
+                    self.voice_priority_speaker = priority_speaker;
+                    self.voice_can_moderate = can_moderate;
+                    if can_publish {
+                        self.hand_raised = false;
+                        // Push-to-talk users start muted until they press the key.
+                        if self.push_to_talk() {
+                            self.set_voice_muted(true);
+                        }
+                    }
+                }
+                AppEvent::RecordingStarted { meter } => {
+                    if let Some(recording) = &mut self.voice_recording {
+                        recording.meter = Some(meter);
+                    }
+                }
+                AppEvent::RecordingStopped => {
+                    self.voice_recording = None;
+                }
+                AppEvent::VoicePlayback { url, playing } => {
+                    if playing {
+                        self.playing_voice = Some(url);
+                    } else if self.playing_voice.as_deref() == Some(url.as_str()) {
+                        self.playing_voice = None;
+                    }
                 }
                 AppEvent::VoiceLeft => {
+                    if let (Some(call), Some(room_id)) =
+                        (self.call.take(), self.voice_room_id.clone())
+                    {
+                        self.finish_call(call, room_id);
+                    }
                     self.in_voice = false;
                     self.voice_room_id = None;
                     self.voice_participants.clear();
+                    self.voice_priority.clear();
+                    self.voice_speaking.clear();
+                    self.voice_levels.clear();
+                    self.voice_pipelines = 0;
+                    self.voice_latency_pending = false;
+                    self.input_meter = None;
                     self.voice_muted = false;
+                    self.voice_deafened = false;
+                    self.voice_stage = false;
+                    self.voice_can_publish = true;
+                    self.voice_can_screen_share = false;
+                    self.voice_priority_speaker = false;
+                    self.voice_can_moderate = false;
+                    self.hand_raised = false;
+                    self.raised_hands.clear();
+                    self.call_typing.clear();
+                    self.call_hands.clear();
+                    self.sent_call_typing = false;
+                    self.floating_reactions.clear();
+                    self.call_toasts.clear();
+                    self.mic_lost = None;
+                    self.output_lost = false;
+                }
+                AppEvent::VoiceMicLost { devices } => {
+                    self.mic_lost = Some(devices);
+                }
+                AppEvent::AudioDevices(devices) => {
+                    self.audio_devices = Some(devices);
+                }
+                AppEvent::VoiceMicRestored { device } => {
+                    self.mic_lost = None;
+                    let now = ctx.input(|i| i.time);
+                    self.call_toasts
+                        .push((format!("Microphone back: {device}"), now));
+                }
+                AppEvent::VoiceOutputLost => {
+                    self.output_lost = true;
+                }
+                AppEvent::VoiceOutputRestored { device } => {
+                    self.output_lost = false;
+                    let now = ctx.input(|i| i.time);
+                    self.call_toasts
+                        .push((format!("Speakers back: {device}"), now));
+                }
+                AppEvent::VoiceDeviceSwitched { kind, device } => {
+                    let now = ctx.input(|i| i.time);
+                    let what = match kind {
+                        DeviceKind::Input => "Microphone",
+                        DeviceKind::Output => "Speakers",
+                    };
+                    self.call_toasts
+                        .push((format!("{what} switched to {device}"), now));
                 }
                 AppEvent::VoiceParticipantsUpdated(ps) => {
+                    if let Some(call) = &mut self.call {
+                        call.observe(&ps);
+                    }
+                    self.call_typing.retain(|p| ps.contains(p));
+                    self.call_hands.retain(|p| ps.contains(p));
+                    let own = &self.own_user_id;
+                    self.voice_speaking.retain(|p| p == own || ps.contains(p));
                     self.voice_participants = ps;
                 }
+                AppEvent::VoicePrioritySpeakers(identities) => {
+                    self.voice_priority = identities.into_iter().collect();
+                }
+                AppEvent::VoicePipelines(count) => {
+                    self.voice_pipelines = count;
+                }
+                AppEvent::VoiceLevels(levels) => {
+                    self.voice_levels = levels.into_iter().collect();
+                }
+                AppEvent::MicTestLevel(level) => {
+                    if self.mic_test.is_some() {
+                        self.mic_test = Some(level);
+                    }
+                }
+                AppEvent::VoiceSpeaking {
+                    participant,
+                    speaking,
+                } => {
+                    if speaking {
+                        self.voice_speaking.insert(participant);
+                    } else {
+                        self.voice_speaking.remove(&participant);
+                    }
+                }
+                AppEvent::VoiceInputMeter(meter) => {
+                    self.input_meter = meter;
+                }
+                AppEvent::VoiceParticipantJoined { identity } => {
+                    let now = ctx.input(|i| i.time);
+                    self.call_toasts
+                        .push((format!("{identity} joined the call"), now));
+                }
+                AppEvent::VoiceParticipantLeft { identity } => {
+                    let now = ctx.input(|i| i.time);
+                    self.call_toasts
+                        .push((format!("{identity} left the call"), now));
+                }
+                AppEvent::VoicePreflight { room_id: _, report } => {
+                    // Pop the dialog open only when there's something to say.
+                    if !report.guidance().is_empty() {
+                        self.ui.open(Dialog::VoiceDiagnostics);
+                    }
+                    self.voice_preflight = Some(report);
+                }
+                AppEvent::VoiceLatency(result) => {
+                    self.voice_latency_pending = false;
+                    self.voice_latency = Some(result);
+                }
+                AppEvent::TurnServers(servers) => {
+                    self.turn_servers = servers;
+                }
+                AppEvent::IceTestResults(results) => {
+                    self.ice_test = Some(results);
+                }
+                AppEvent::VoiceData { sender, message } => match message {
+                    DataMessage::Typing { typing } => {
+                        if typing {
+                            self.call_typing.insert(sender);
+                        } else {
+                            self.call_typing.remove(&sender);
+                        }
+                    }
+                    DataMessage::Hand { raised } => {
+                        if raised {
+                            self.call_hands.insert(sender);
+                        } else {
+                            self.call_hands.remove(&sender);
+                        }
+                    }
+                    DataMessage::Reaction { emoji } => {
+                        let started = ctx.input(|i| i.time);
+                        // Spread reactions across the width, stable per arrival.
+                        let x = 0.15 + 0.7 * ((started * 7.31).fract() as f32);
+                        self.floating_reactions.push(FloatingReaction {
+                            emoji,
+                            sender,
+                            started,
+                            x,
+                        });
+                    }
+                    // Answered by the voice session itself; never forwarded.
+                    DataMessage::LatencyProbe { .. } => {}
+                },
+                AppEvent::SpaceHierarchyLoaded { space_id, root } => {
+                    self.spaces.insert(space_id, root);
+                }
+                AppEvent::DirectoryResults {
+                    query,
+                    page,
+                    append,
+                } => {
+                    if self.explore_results_query.as_deref() == Some(query.as_str()) {
+                        if !append {
+                            self.explore_results.clear();
+                        }
+                        self.explore_results.extend(page.rooms);
+                        self.explore_next_batch = page.next_batch;
+                        self.explore_loading = false;
+                    }
+                }
+                AppEvent::StageUpdated {
+                    room_id,
+                    stage,
+                    speakers,
+                } => {
+                    if self.voice_room_id.as_deref() == Some(room_id.as_str()) {
+                        self.voice_stage = stage;
+                        // Promoted listeners no longer need their hand up.
+                        self.raised_hands.retain(|u| !speakers.contains(u));
+                    }
+                }
+                AppEvent::HandRaised {
+                    room_id,
+                    user_id,
+                    raised,
+                } => {
+                    if self.voice_room_id.as_deref() == Some(room_id.as_str()) {
+                        self.raised_hands.retain(|u| *u != user_id);
+                        if raised {
+                            self.raised_hands.push(user_id);
+                        }
+                    }
+                }
             }
         }
+        self.update_window_title(ctx);
 
         if !self.logged_in {
             self.show_login_panel(ctx);
             return;
         }
 
+        self.handle_keybinds(ctx);
+        self.handle_room_navigation(ctx);
+        self.handle_search_shortcut(ctx);
+        self.handle_message_shortcuts(ctx);
+
         // Trigger a history fetch the first time each room is selected.
         if let Some(room) = self.selected_room.and_then(|i| self.rooms.get(i)) {
             if self.fetched_rooms.insert(room.id.clone()) {
-                let _ = self.cmd_tx.send(AppCommand::FetchHistory { room_id: room.id.clone() });
+                let _ = self.cmd_tx.send(AppCommand::FetchHistory {
+                    room_id: room.id.clone(),
+                });
             }
         }
+        self.preload_adjacent_rooms();
 
         // ── Invite dialog ─────────────────────────────────────────────────────
-        if self.show_invite_dialog {
+        if self.ui.is_open(Dialog::Invite) {
             let mut open = true;
             egui::Window::new("Invite User")
                 .collapsible(false)
@@ -213,124 +1684,455 @@ impl eframe::App for SpokeApp {
 
                     ui.horizontal(|ui| {
                         let can_invite = !self.invite_input.is_empty();
-                        if ui.add_enabled(can_invite, egui::Button::new("Invite")).clicked() {
-                            if let Some(room) =
-                                self.selected_room.and_then(|i| self.rooms.get(i))
-                            {
+                        if ui
+                            .add_enabled(can_invite, egui::Button::new("Invite"))
+                            .clicked()
+                        {
+                            if let Some(room) = self.selected_room.and_then(|i| self.rooms.get(i)) {
                                 let _ = self.cmd_tx.send(AppCommand::InviteUser {
                                     room_id: room.id.clone(),
                                     mxid: std::mem::take(&mut self.invite_input),
                                 });
                             }
-                            self.show_invite_dialog = false;
+                            self.ui.close(Dialog::Invite);
                         }
                         if ui.button("Cancel").clicked() {
-                            self.show_invite_dialog = false;
+                            self.ui.close(Dialog::Invite);
                             self.invite_input.clear();
                         }
                     });
                 });
             if !open {
-                self.show_invite_dialog = false;
+                self.ui.close(Dialog::Invite);
                 self.invite_input.clear();
             }
         }
 
+        // ── Moderation dialog ─────────────────────────────────────────────────
+        if self.ui.is_open(Dialog::Moderation) {
+            self.show_moderation_dialog(ctx);
+        }
+        if self.ui.is_open(Dialog::Report) {
+            self.show_report_dialog(ctx);
+        }
+        if self.ui.is_open(Dialog::SpinOff) {
+            self.show_spin_off_dialog(ctx);
+        }
+        if self.ui.is_open(Dialog::PowerLevels) {
+            self.show_power_levels_dialog(ctx);
+        }
+        if self.ui.is_open(Dialog::ServerAcl) {
+            self.show_server_acl_dialog(ctx);
+        }
+        if self.ui.is_open(Dialog::RoomState) {
+            self.show_room_state_dialog(ctx);
+        }
+        if self.ui.is_open(Dialog::RoomSettings) {
+            self.show_room_settings_dialog(ctx);
+        }
+        if self.ui.is_open(Dialog::CallSummary) {
+            self.show_call_summary_dialog(ctx);
+        }
+        if self.ui.is_open(Dialog::IncomingCall) {
+            self.show_incoming_call_dialog(ctx);
+        }
+        if self.ui.is_open(Dialog::CreatePoll) {
+            self.show_poll_dialog(ctx);
+        }
+        if self.ui.is_open(Dialog::AfkChannel) {
+            self.show_afk_dialog(ctx);
+        }
+
         // ── Create Room dialog ────────────────────────────────────────────────
-        if self.show_create_room_dialog {
+        if self.ui.is_open(Dialog::CreateRoom) {
+            self.show_create_room_dialog(ctx);
+        }
+
+        // ── Join Room dialog ──────────────────────────────────────────────────
+        if self.ui.is_open(Dialog::JoinRoom) {
             let mut open = true;
-            egui::Window::new("Create Room")
+            egui::Window::new("Join Room")
                 .collapsible(false)
                 .resizable(false)
                 .open(&mut open)
                 .show(ctx, |ui| {
-                    ui.label("Room name");
+                    ui.label("Room address");
                     let resp = ui.add(
-                        egui::TextEdit::singleline(&mut self.create_room_name)
+                        egui::TextEdit::singleline(&mut self.join_room_input)
+                            .hint_text("#alias:server or !id:server")
                             .desired_width(240.0),
                     );
                     resp.request_focus();
+                    let address = self.join_room_input.trim().to_owned();
+                    match self
+                        .join_peek
+                        .as_ref()
+                        .filter(|(asked, _)| *asked == address)
+                    {
+                        Some((_, None)) => {
+                            ui.horizontal(|ui| {
+                                ui.spinner();
+                                ui.weak("Loading preview…");
+                            });
+                        }
+                        Some((_, Some(Ok(peek)))) => self.room_peek_ui(ui, peek),
+                        Some((_, Some(Err(e)))) => {
+                            ui.weak(format!("No preview: {e}"));
+                        }
+                        None => {}
+                    }
                     ui.horizontal(|ui| {
-                        let can_create = !self.create_room_name.is_empty();
-                        let enter = resp.has_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
-                        if ui.add_enabled(can_create, egui::Button::new("Create")).clicked() || (can_create && enter) {
-                            let _ = self.cmd_tx.send(AppCommand::CreateRoom {
-                                name: std::mem::take(&mut self.create_room_name),
+                        let can_join = !self.join_room_input.is_empty();
+                        let previewed = self
+                            .join_peek
+                            .as_ref()
+                            .is_some_and(|(asked, _)| *asked == address);
+                        if ui
+                            .add_enabled(can_join && !previewed, egui::Button::new("Preview"))
+                            .clicked()
+                        {
+                            let _ = self.cmd_tx.send(AppCommand::PeekRoom {
+                                room: address.clone(),
+                            });
+                            self.join_peek = Some((address.clone(), None));
+                        }
+                        let enter =
+                            resp.has_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                        if ui
+                            .add_enabled(can_join, egui::Button::new("Join"))
+                            .clicked()
+                            || (can_join && enter)
+                        {
+                            let _ = self.cmd_tx.send(AppCommand::JoinRoomByAlias {
+                                alias: std::mem::take(&mut self.join_room_input),
+                            });
+                            self.ui.close(Dialog::JoinRoom);
+                            self.join_peek = None;
+                        }
+                        let can_knock = self
+                            .capabilities
+                            .as_ref()
+                            .is_none_or(ServerCapabilities::knocking);
+                        let knock = ui.add_enabled(
+                            can_join && can_knock,
+                            egui::Button::new("Request to join"),
+                        );
+                        let knock = if can_knock {
+                            knock.on_hover_text("For rooms that only admit members on request")
+                        } else {
+                            knock.on_disabled_hover_text("This server doesn't support knocking")
+                        };
+                        if knock.clicked() {
+                            let _ = self.cmd_tx.send(AppCommand::KnockRoom {
+                                room: std::mem::take(&mut self.join_room_input),
+                                reason: None,
                             });
-                            self.show_create_room_dialog = false;
+                            self.ui.close(Dialog::JoinRoom);
+                            self.join_peek = None;
                         }
                         if ui.button("Cancel").clicked() {
-                            self.show_create_room_dialog = false;
-                            self.create_room_name.clear();
+                            self.ui.close(Dialog::JoinRoom);
+                            self.join_peek = None;
+                            self.join_room_input.clear();
                         }
                     });
                 });
             if !open {
-                self.show_create_room_dialog = false;
-                self.create_room_name.clear();
+                self.ui.close(Dialog::JoinRoom);
+                self.join_peek = None;
+                self.join_room_input.clear();
             }
         }
 
-        // ── Join Room dialog ──────────────────────────────────────────────────
-        if self.show_join_dialog {
+        // ── Direct message dialog ─────────────────────────────────────────────
+        if self.ui.is_open(Dialog::DirectMessage) {
             let mut open = true;
-            egui::Window::new("Join Room")
+            egui::Window::new("New Direct Message")
                 .collapsible(false)
                 .resizable(false)
                 .open(&mut open)
                 .show(ctx, |ui| {
-                    ui.label("Room address");
+                    ui.label("User");
                     let resp = ui.add(
-                        egui::TextEdit::singleline(&mut self.join_room_input)
-                            .hint_text("#alias:server or !id:server")
+                        egui::TextEdit::singleline(&mut self.dm_input)
+                            .hint_text("@user:server")
                             .desired_width(240.0),
                     );
                     resp.request_focus();
                     ui.horizontal(|ui| {
-                        let can_join = !self.join_room_input.is_empty();
-                        let enter = resp.has_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
-                        if ui.add_enabled(can_join, egui::Button::new("Join")).clicked() || (can_join && enter) {
-                            let _ = self.cmd_tx.send(AppCommand::JoinRoomByAlias {
-                                alias: std::mem::take(&mut self.join_room_input),
+                        let can_start = !self.dm_input.trim().is_empty();
+                        let enter =
+                            resp.has_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                        if ui
+                            .add_enabled(can_start, egui::Button::new("Message"))
+                            .clicked()
+                            || (can_start && enter)
+                        {
+                            let _ = self.cmd_tx.send(AppCommand::StartDirectMessage {
+                                mxid: std::mem::take(&mut self.dm_input),
                             });
-                            self.show_join_dialog = false;
+                            self.ui.close(Dialog::DirectMessage);
                         }
                         if ui.button("Cancel").clicked() {
-                            self.show_join_dialog = false;
-                            self.join_room_input.clear();
+                            self.ui.close(Dialog::DirectMessage);
+                            self.dm_input.clear();
                         }
                     });
                 });
             if !open {
-                self.show_join_dialog = false;
-                self.join_room_input.clear();
+                self.ui.close(Dialog::DirectMessage);
+                self.dm_input.clear();
+            }
+        }
+
+        // ── Explore dialog ────────────────────────────────────────────────────
+        if self.ui.is_open(Dialog::Explore) {
+            self.show_explore_dialog(ctx);
+        }
+
+        // ── Settings window ───────────────────────────────────────────────────
+        if self.ui.is_open(Dialog::Settings) {
+            self.show_settings_window(ctx);
+        } else if self.mic_test.take().is_some() {
+            let _ = self.cmd_tx.send(AppCommand::TestMic { on: false });
+        }
+
+        // ── Voice diagnostics dialog ──────────────────────────────────────────
+        if self.ui.is_open(Dialog::VoiceDiagnostics) {
+            self.show_voice_diagnostics(ctx);
+        }
+
+        // ── Mic lost warning ──────────────────────────────────────────────────
+        if let Some(devices) = self.mic_lost.as_ref().filter(|_| self.in_voice) {
+            let mut retry: Option<Option<String>> = None;
+            egui::TopBottomPanel::top("mic_lost").show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.colored_label(
+                        ui.visuals().warn_fg_color,
+                        "⚠ Microphone disconnected — nobody can hear you. Reconnecting…",
+                    );
+                    if ui.small_button("Retry now").clicked() {
+                        retry = Some(None);
+                    }
+                    ui.menu_button("Use another microphone", |ui| {
+                        if devices.is_empty() {
+                            ui.weak("No microphones found");
+                        }
+                        for device in devices {
+                            if ui.button(device).clicked() {
+                                retry = Some(Some(device.clone()));
+                                ui.close_menu();
+                            }
+                        }
+                    });
+                });
+            });
+            if let Some(device) = retry {
+                let _ = self.cmd_tx.send(AppCommand::RetryMic { device });
             }
         }
+        if self.output_lost && self.in_voice {
+            egui::TopBottomPanel::top("output_lost").show(ctx, |ui| {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    "⚠ Speakers disconnected — you can't hear the call. Reconnecting…",
+                );
+            });
+        }
 
         // ── Left sidebar ──────────────────────────────────────────────────────
-        egui::SidePanel::left("rooms")
+        let sidebar = egui::SidePanel::left("rooms")
             .resizable(true)
-            .default_width(200.0)
+            .default_width(self.ui.sidebar_width.unwrap_or(200.0))
             .show(ctx, |ui| {
                 ui.add_space(8.0);
-                ui.heading("Spoke");
+                ui.horizontal(|ui| {
+                    ui.heading("Spoke");
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        if ui.small_button("⚙").on_hover_text("Settings").clicked() {
+                            self.ui.open(Dialog::Settings);
+                        }
+                        self.account_menu_ui(ui);
+                    });
+                });
                 ui.small(&self.status);
+                if let Some((what, until)) = &self.rate_limited {
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_millis() as u64;
+                    let wait = until.saturating_sub(now).div_ceil(1000);
+                    let text = if wait > 0 {
+                        format!("⏳ Rate limited, retrying in {wait} s")
+                    } else {
+                        "⏳ Rate limited, retrying…".to_owned()
+                    };
+                    ui.small(egui::RichText::new(text).color(ui.visuals().warn_fg_color))
+                        .on_hover_text(format!(
+                            "The homeserver is slowing us down ({what}); nothing is lost."
+                        ));
+                    ctx.request_repaint_after_secs(1.0);
+                }
                 ui.separator();
 
                 ui.horizontal(|ui| {
                     if ui.small_button("+ New").clicked() {
-                        self.show_create_room_dialog = true;
+                        self.ui.open(Dialog::CreateRoom);
                     }
                     if ui.small_button("Join…").clicked() {
-                        self.show_join_dialog = true;
+                        self.ui.open(Dialog::JoinRoom);
+                    }
+                    if ui.small_button("Explore").clicked() {
+                        self.ui.open(Dialog::Explore);
+                    }
+                    if ui.small_button("+ DM").clicked() {
+                        self.ui.open(Dialog::DirectMessage);
                     }
                 });
 
+                // Spaces — one collapsible tree per joined space.
+                let selected_id = self
+                    .selected_room
+                    .and_then(|i| self.rooms.get(i))
+                    .map(|r| r.id.clone());
+                let mut in_space: HashSet<String> = HashSet::new();
+                let mut action: Option<SpaceAction> = None;
+                for space in self.rooms.iter().filter(|r| r.is_space) {
+                    ui.collapsing(&space.name, |ui| {
+                        match self.spaces.get(&space.id) {
+                            Some(root) => {
+                                collect_space_ids(root, &mut in_space);
+                                for child in &root.children {
+                                    space_tree_ui(ui, child, selected_id.as_deref(), &mut action);
+                                }
+                            }
+                            None => {
+                                ui.small("Loading…");
+                            }
+                        }
+                        ui.horizontal(|ui| {
+                            if ui.small_button("⟳ Refresh").clicked() {
+                                action = Some(SpaceAction::Refresh(space.id.clone()));
+                            }
+                            if ui
+                                .small_button("💤 AFK…")
+                                .on_hover_text("AFK voice channel")
+                                .clicked()
+                            {
+                                action = Some(SpaceAction::Afk(space.id.clone()));
+                            }
+                            if ui
+                                .small_button("🔊 +")
+                                .on_hover_text("New voice channel")
+                                .clicked()
+                            {
+                                action = Some(SpaceAction::NewVoiceChannel(space.id.clone()));
+                            }
+                        });
+                    });
+                    // Collapsed headers don't run the body; still hide their rooms.
+                    if let Some(root) = self.spaces.get(&space.id) {
+                        collect_space_ids(root, &mut in_space);
+                    }
+                }
+                match action {
+                    Some(SpaceAction::Select(id)) => {
+                        self.selected_room = self.rooms.iter().position(|r| r.id == id);
+                    }
+                    Some(SpaceAction::Join(room_id)) => {
+                        let _ = self.cmd_tx.send(AppCommand::JoinRoom { room_id });
+                    }
+                    Some(SpaceAction::Refresh(space_id)) => {
+                        let _ = self
+                            .cmd_tx
+                            .send(AppCommand::FetchSpaceHierarchy { space_id });
+                    }
+                    Some(SpaceAction::NewVoiceChannel(space_id)) => {
+                        self.reset_create_room(RoomTemplate::VoiceChannel);
+                        self.create_room_space = Some(space_id);
+                        self.ui.open(Dialog::CreateRoom);
+                    }
+                    Some(SpaceAction::Afk(space_id)) => {
+                        let _ = self.cmd_tx.send(AppCommand::FetchAfkPolicy {
+                            space_id: space_id.clone(),
+                        });
+                        self.afk = Some(AfkDraft {
+                            space_id,
+                            loaded: false,
+                            can_edit: false,
+                            afk_room: None,
+                            timeout_mins: 5,
+                        });
+                        self.ui.open(Dialog::AfkChannel);
+                    }
+                    None => {}
+                }
+
+                // Pin/unpin and report from a room's context menu, applied
+                // after the lists.
+                let mut pin_toggle: Option<String> = None;
+                let mut report: Option<String> = None;
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as u64;
+
+                // Direct messages, kept apart from group rooms.
+                if self.rooms.iter().any(|r| r.is_direct) {
+                    ui.separator();
+                    ui.small("Direct messages");
+                    for (i, room) in self.rooms.iter().enumerate().filter(|(_, r)| r.is_direct) {
+                        let pinned = self.ui.is_pinned(&room.id);
+                        let unread = self.unread.get(&room.id).copied();
+                        match room_entry_ui(
+                            ui,
+                            room,
+                            self.selected_room == Some(i),
+                            pinned,
+                            unread,
+                            now,
+                        ) {
+                            Some(RoomEntryAction::Select) => self.selected_room = Some(i),
+                            Some(RoomEntryAction::TogglePin) => pin_toggle = Some(room.id.clone()),
+                            Some(RoomEntryAction::Report) => report = Some(room.id.clone()),
+                            None => {}
+                        }
+                    }
+                }
+
+                // Rooms that don't belong to any space.
+                if self.rooms.iter().any(|r| r.is_space || r.is_direct) {
+                    ui.separator();
+                    ui.small("Rooms");
+                }
                 for (i, room) in self.rooms.iter().enumerate() {
-                    let selected = self.selected_room == Some(i);
-                    if ui.selectable_label(selected, &room.name).clicked() {
-                        self.selected_room = Some(i);
+                    if room.is_space || room.is_direct || in_space.contains(&room.id) {
+                        continue;
                     }
+                    let pinned = self.ui.is_pinned(&room.id);
+                    let unread = self.unread.get(&room.id).copied();
+                    match room_entry_ui(
+                        ui,
+                        room,
+                        self.selected_room == Some(i),
+                        pinned,
+                        unread,
+                        now,
+                    ) {
+                        Some(RoomEntryAction::Select) => self.selected_room = Some(i),
+                        Some(RoomEntryAction::TogglePin) => pin_toggle = Some(room.id.clone()),
+                        Some(RoomEntryAction::Report) => report = Some(room.id.clone()),
+                        None => {}
+                    }
+                }
+                if let Some(room_id) = pin_toggle {
+                    self.ui.toggle_pinned(&room_id);
+                    // Pinned rooms roam with the settings.
+                    self.settings.save();
+                    self.sort_rooms();
+                }
+                if let Some(room_id) = report {
+                    self.open_report(room_id, None);
                 }
 
                 if !self.pending_invites.is_empty() {
@@ -349,37 +2151,392 @@ impl eframe::App for SpokeApp {
                     }
                 }
 
+                // Left, kicked and banned rooms, until forgotten.
+                if !self.left_rooms.is_empty() {
+                    ui.separator();
+                    let mut action: Option<AppCommand> = None;
+                    ui.collapsing(format!("Left rooms ({})", self.left_rooms.len()), |ui| {
+                        for room in &self.left_rooms {
+                            ui.horizontal(|ui| {
+                                ui.label(egui::RichText::new(&room.name).weak());
+                                // No point offering to rejoin until someone unbans us.
+                                if !room.banned && ui.small_button("Rejoin").clicked() {
+                                    action = Some(AppCommand::JoinRoom {
+                                        room_id: room.room_id.clone(),
+                                    });
+                                }
+                                if ui
+                                    .small_button("Forget")
+                                    .on_hover_text("Remove from your account")
+                                    .clicked()
+                                {
+                                    action = Some(AppCommand::ForgetRoom {
+                                        room_id: room.room_id.clone(),
+                                    });
+                                }
+                            });
+                            let how = match (&room.by, room.banned) {
+                                (Some(by), true) => Some(format!("Banned by {by}")),
+                                (None, true) => Some("Banned".to_owned()),
+                                (Some(by), false) => Some(format!("Removed by {by}")),
+                                (None, false) => None,
+                            };
+                            if let Some(how) = how {
+                                let text = match &room.reason {
+                                    Some(reason) => format!("{how}: {reason}"),
+                                    None => how,
+                                };
+                                ui.small(
+                                    egui::RichText::new(text).color(ui.visuals().warn_fg_color),
+                                );
+                            }
+                        }
+                    });
+                    if let Some(cmd) = action {
+                        let _ = self.cmd_tx.send(cmd);
+                    }
+                }
+
                 // ── Voice participants (sidebar section) ─────────────────────
                 if self.in_voice && !self.voice_participants.is_empty() {
                     ui.separator();
                     ui.small("Voice");
-                    for p in &self.voice_participants {
-                        ui.label(p);
+                    let mut unresolved = Vec::new();
+                    let everyone =
+                        std::iter::once(&self.own_user_id).chain(&self.voice_participants);
+                    for p in everyone {
+                        let profile = self.profiles.get(p);
+                        if profile.is_none() && !self.profiles_requested.contains(p) {
+                            unresolved.push(p.clone());
+                        }
+                        let mut label = if *p == self.own_user_id {
+                            "You".to_owned()
+                        } else {
+                            p.clone()
+                        };
+                        if self.voice_priority.contains(p) {
+                            label.push_str(" ★");
+                        }
+                        if self.call_hands.contains(p) {
+                            label.push_str(" ✋");
+                        }
+                        if self.call_typing.contains(p) {
+                            label.push_str(" ✎");
+                        }
+                        ui.horizontal(|ui| {
+                            let avatar = ui.scope(|ui| avatar_ui(ui, p, profile)).response.rect;
+                            if self.voice_speaking.contains(p) {
+                                speaking_ring(ui, avatar);
+                            }
+                            ui.label(
+                                egui::RichText::new(label).color(sender_color(ui.visuals(), p)),
+                            );
+                            let loudness = self.voice_levels.get(p).map_or(0.0, Level::meter);
+                            if loudness > 0.0 {
+                                ui.add(
+                                    egui::ProgressBar::new(loudness)
+                                        .desired_width(32.0)
+                                        .desired_height(4.0),
+                                );
+                            }
+                        });
+                    }
+                    if let (false, Some(room_id)) =
+                        (unresolved.is_empty(), self.voice_room_id.clone())
+                    {
+                        self.profiles_requested.extend(unresolved.iter().cloned());
+                        let _ = self.cmd_tx.send(AppCommand::ResolveProfiles {
+                            room_id,
+                            user_ids: unresolved,
+                        });
+                    }
+                }
+
+                // ── Stage: raised hands ──────────────────────────────────────
+                if self.in_voice && self.voice_stage && !self.raised_hands.is_empty() {
+                    ui.separator();
+                    ui.small("Raised hands");
+                    let mut promote: Option<String> = None;
+                    for user in &self.raised_hands {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("✋ {user}"));
+                            if self.voice_can_moderate && ui.small_button("Promote").clicked() {
+                                promote = Some(user.clone());
+                            }
+                        });
+                    }
+                    if let (Some(user_id), Some(room_id)) = (promote, self.voice_room_id.clone()) {
+                        let _ = self.cmd_tx.send(AppCommand::SetStageSpeaker {
+                            room_id,
+                            user_id,
+                            speaker: true,
+                        });
                     }
                 }
             });
+        self.ui.set_sidebar_width(sidebar.response.rect.width());
 
         // ── Bottom input bar ──────────────────────────────────────────────────
         egui::TopBottomPanel::bottom("input").show(ctx, |ui| {
+            let composer_id = composer_id();
             ui.add_space(6.0);
+
+            if let Some(rid) = self
+                .selected_room
+                .and_then(|i| self.rooms.get(i))
+                .map(|r| r.id.as_str())
+            {
+                if !self.settings.privacy.typing(rid) {
+                    ui.weak("Typing notifications hidden (privacy settings)");
+                } else if let Some(users) = self.typing_users.get(rid).filter(|u| !u.is_empty()) {
+                    let who = match users.as_slice() {
+                        [one] => format!("{one} is typing…"),
+                        [a, b] => format!("{a} and {b} are typing…"),
+                        _ => "Several people are typing…".to_owned(),
+                    };
+                    ui.weak(who);
+                }
+            }
+
+            // What the next message replies to, continues, or replaces.
+            if let Some(relation) = &self.composer.relation {
+                let (icon, verb, event_id) = match relation {
+                    MessageRelation::Reply { event_id } => ("↩", "Replying to", event_id),
+                    MessageRelation::Thread { root } => ("🧵", "Replying in thread of", root),
+                    MessageRelation::Edit { event_id } => ("✏", "Editing", event_id),
+                };
+                let target = self
+                    .find_message(event_id)
+                    .map(|m| quote_line(self.display_name(&m.sender), &m.body));
+                let mut cancel = false;
+                ui.horizontal(|ui| {
+                    ui.weak(format!(
+                        "{icon} {verb} {}",
+                        target.as_deref().unwrap_or("a message")
+                    ));
+                    cancel = ui.small_button("✕").on_hover_text("Cancel (Esc)").clicked();
+                });
+                if cancel {
+                    self.cancel_relation();
+                }
+            }
+
+            // `@`/`#` autocomplete for the token under the cursor.
+            let trigger = composer::cursor(ctx, composer_id)
+                .and_then(|c| self.composer.trigger(c).map(|t| (c, t)));
+            // Mentions can reach beyond recent senders once the full member
+            // list is in; it's fetched the first time one is typed.
+            let mention_room = trigger
+                .as_ref()
+                .filter(|(_, (kind, _, _))| *kind == PillKind::User)
+                .and_then(|_| self.selected_room.and_then(|i| self.rooms.get(i)))
+                .map(|r| r.id.clone());
+            if let Some(room_id) = &mention_room {
+                self.request_members(room_id);
+            }
+            let suggestions = trigger
+                .as_ref()
+                .map(|(_, (kind, _, query))| self.composer_suggestions(*kind, query))
+                .unwrap_or_default();
+            let mut pick: Option<Suggestion> = None;
+            let members_loading = mention_room.is_some_and(|r| {
+                !self.members.contains_key(&r) || self.members_loading.contains(&r)
+            });
+            if !suggestions.is_empty() || members_loading {
+                if ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Tab)) {
+                    pick = suggestions.first().cloned();
+                }
+                ui.horizontal(|ui| {
+                    ui.weak("Tab ⇥");
+                    for s in &suggestions {
+                        if ui.small_button(&s.label).on_hover_text(&s.target).clicked() {
+                            pick = Some(s.clone());
+                        }
+                    }
+                    if members_loading {
+                        ui.spinner().on_hover_text("Loading members…");
+                    }
+                });
+            }
+
+            // Formatting buttons, and their shortcuts while the field has focus.
+            let mut format: Option<Format> = None;
+            ui.horizontal(|ui| {
+                for f in Format::ALL {
+                    if ui.small_button(f.icon()).on_hover_text(f.hint()).clicked() {
+                        format = Some(f);
+                    }
+                }
+                ui.separator();
+                let room_id = self
+                    .selected_room
+                    .and_then(|i| self.rooms.get(i))
+                    .map(|r| r.id.clone());
+                if let Some(room_id) = room_id {
+                    if ui.small_button("📊").on_hover_text("Create poll").clicked() {
+                        self.poll_draft = Some(PollDraft::new(room_id.clone()));
+                        self.ui.open(Dialog::CreatePoll);
+                    }
+                    ui.menu_button("🎴", |ui| self.sticker_picker_ui(ui, &room_id))
+                        .response
+                        .on_hover_text("Send a sticker");
+                    let record = ui.add_enabled(
+                        self.voice_recording.is_none(),
+                        egui::Button::new("🎤").small(),
+                    );
+                    if record.on_hover_text("Record a voice message").clicked() {
+                        self.voice_recording = Some(VoiceRecording {
+                            room_id,
+                            started: ctx.input(|i| i.time),
+                            meter: None,
+                            level: 0.0,
+                        });
+                        let _ = self.cmd_tx.send(AppCommand::StartRecording);
+                    }
+                }
+            });
+            if ui.memory(|m| m.has_focus(composer_id)) {
+                for f in Format::ALL {
+                    let Some(key) = f.shortcut() else { continue };
+                    if ui.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, key)) {
+                        format = Some(f);
+                    }
+                }
+            }
+            if let Some(format) = format {
+                let len = self.composer.text.chars().count();
+                let selection = composer::selection(ctx, composer_id).unwrap_or(len..len);
+                let selection = self.composer.apply_format(format, selection);
+                composer::set_selection(ctx, composer_id, selection);
+                ui.memory_mut(|m| m.request_focus(composer_id));
+            }
+
+            if let Some(recording) = &mut self.voice_recording {
+                let elapsed = ctx.input(|i| i.time) - recording.started;
+                let mut stop: Option<bool> = None;
+                ui.horizontal(|ui| {
+                    let secs = elapsed as u64;
+                    ui.colored_label(
+                        egui::Color32::RED,
+                        format!("● {}:{:02}", secs / 60, secs % 60),
+                    );
+                    match &recording.meter {
+                        Some(meter) => {
+                            recording.level = meter.take().0.max(recording.level * 0.9);
+                            ui.add(egui::ProgressBar::new(recording.level).desired_width(120.0));
+                        }
+                        None => {
+                            ui.spinner();
+                        }
+                    }
+                    if ui.button("Send").clicked() {
+                        stop = Some(true);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        stop = Some(false);
+                    }
+                });
+                if elapsed >= recording::MAX_RECORDING.as_secs_f64() {
+                    stop = Some(true);
+                }
+                if let Some(send) = stop {
+                    let send_to = send.then(|| recording.room_id.clone());
+                    let _ = self.cmd_tx.send(AppCommand::StopRecording { send_to });
+                    self.voice_recording = None;
+                }
+                ctx.request_repaint();
+            }
+
             ui.horizontal(|ui| {
-                let input_field = egui::TextEdit::singleline(&mut self.input)
-                    .hint_text("Message…")
-                    .desired_width(ui.available_width() - 60.0);
+                // Enter sends; Shift+Enter is left to the field as a newline
+                // for multi-line Markdown.
+                let enter = ui.memory(|m| m.has_focus(composer_id))
+                    && ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Enter));
+                let mut text = std::mem::take(&mut self.composer.text);
+                let response = {
+                    let model = &self.composer;
+                    let mut layouter = |ui: &egui::Ui, text: &str, wrap_width: f32| {
+                        let job = composer::layout(ui, model, text, wrap_width);
+                        ui.fonts(|f| f.layout_job(job))
+                    };
+                    let input_field = egui::TextEdit::multiline(&mut text)
+                        .id(composer_id)
+                        .hint_text("Message… (Markdown, Shift+Enter for a new line)")
+                        .desired_rows(1)
+                        .desired_width(ui.available_width() - 60.0)
+                        .layouter(&mut layouter);
+                    ui.add(input_field)
+                };
+                self.composer.text = text;
+                if let Some(cursor) = self.composer.sync() {
+                    composer::set_cursor(ctx, composer_id, cursor);
+                }
+                if let (Some(suggestion), Some((cursor, (_, start, _)))) = (pick, trigger) {
+                    let cursor = self.composer.insert_pill(start, cursor, &suggestion);
+                    composer::set_cursor(ctx, composer_id, cursor);
+                    response.request_focus();
+                }
 
-                let response = ui.add(input_field);
                 let send_btn = ui.button("Send");
-                let submitted = send_btn.clicked()
-                    || (response.lost_focus()
-                        && ui.input(|i| i.key_pressed(egui::Key::Enter)));
+                let submitted = send_btn.clicked() || enter;
 
-                if submitted && !self.input.is_empty() {
-                    if let Some(room) =
-                        self.selected_room.and_then(|i| self.rooms.get(i))
-                    {
+                // Tell the call we're typing when composing in the voice room.
+                let in_voice_room = self.in_voice
+                    && self
+                        .selected_room
+                        .and_then(|i| self.rooms.get(i))
+                        .map(|r| r.id.as_str())
+                        == self.voice_room_id.as_deref();
+                let typing = in_voice_room && !self.composer.is_empty() && !submitted;
+                if typing != self.sent_call_typing {
+                    self.sent_call_typing = typing;
+                    let _ = self.cmd_tx.send(AppCommand::SendVoiceData {
+                        message: DataMessage::Typing { typing },
+                    });
+                }
+
+                // Room typing notice, unless disabled for this room.
+                let room_id = self
+                    .selected_room
+                    .and_then(|i| self.rooms.get(i))
+                    .map(|r| r.id.clone());
+                let typing_in = room_id
+                    .filter(|rid| self.settings.privacy.typing(rid) && !self.appears_offline())
+                    .filter(|_| !self.composer.is_empty() && !submitted);
+                if typing_in != self.sent_typing {
+                    if let Some(room_id) = self.sent_typing.take() {
+                        let _ = self.cmd_tx.send(AppCommand::SetTyping {
+                            room_id,
+                            typing: false,
+                        });
+                    }
+                    if let Some(room_id) = typing_in.clone() {
+                        let _ = self.cmd_tx.send(AppCommand::SetTyping {
+                            room_id,
+                            typing: true,
+                        });
+                        self.sent_typing_at = ctx.input(|i| i.time);
+                    }
+                    self.sent_typing = typing_in;
+                } else if let Some(room_id) = typing_in.filter(|_| response.changed()) {
+                    // Still typing: repeat the notice before it lapses.
+                    let now = ctx.input(|i| i.time);
+                    if now - self.sent_typing_at >= TYPING_RESEND {
+                        let _ = self.cmd_tx.send(AppCommand::SetTyping {
+                            room_id,
+                            typing: true,
+                        });
+                        self.sent_typing_at = now;
+                    }
+                }
+
+                if submitted && !self.composer.is_empty() {
+                    if let Some(room) = self.selected_room.and_then(|i| self.rooms.get(i)) {
+                        let people = self.mentionable(&room.id);
+                        self.composer.pill_typed_mentions(&people);
                         let _ = self.cmd_tx.send(AppCommand::SendMessage {
                             room_id: room.id.clone(),
-                            body: std::mem::take(&mut self.input),
+                            text: self.composer.take_message(),
                         });
                         response.request_focus();
                     }
@@ -388,20 +2545,199 @@ impl eframe::App for SpokeApp {
             ui.add_space(6.0);
         });
 
+        // ── Right panels (per-room layout) ────────────────────────────────────
+        if let Some(room_id) = self
+            .selected_room
+            .and_then(|i| self.rooms.get(i))
+            .map(|r| r.id.clone())
+        {
+            // The message toolbar needs them to offer Pin or Unpin.
+            self.request_pins(&room_id);
+            for panel in Panel::ALL {
+                if !self.ui.panel_open(&room_id, panel) || !self.panel_available(panel) {
+                    continue;
+                }
+                // Keyed by room so each room keeps its own width.
+                let shown = egui::SidePanel::right(egui::Id::new(("panel", panel, &room_id)))
+                    .resizable(true)
+                    .default_width(self.ui.panel_width(&room_id, panel))
+                    .show(ctx, |ui| self.side_panel_ui(ui, &room_id, panel));
+                self.ui
+                    .set_panel_width(&room_id, panel, shown.response.rect.width());
+            }
+        }
+
         // ── Central: message history ──────────────────────────────────────────
-        egui::CentralPanel::default().show(ctx, |ui| {
+        let central = egui::CentralPanel::default().show(ctx, |ui| {
             let current = self.selected_room.and_then(|i| self.rooms.get(i));
             let room_name = current.map(|r| r.name.as_str()).unwrap_or("—");
             let room_id = current.map(|r| r.id.clone());
+            let encrypted = current.is_some_and(|r| r.encrypted);
+            let is_direct = current.is_some_and(|r| r.is_direct);
 
             // Voice controls in the header (right-to-left layout).
             ui.horizontal(|ui| {
                 ui.heading(room_name);
+                if encrypted {
+                    ui.label("🔒").on_hover_text("End-to-end encrypted");
+                }
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                    if self.selected_room.is_some() {
+                    if let Some(rid) = room_id.as_deref() {
+                        for panel in Panel::ALL
+                            .into_iter()
+                            .rev()
+                            .filter(|p| self.panel_available(*p))
+                        {
+                            let open = self.ui.panel_open(rid, panel);
+                            if ui
+                                .selectable_label(open, panel.icon())
+                                .on_hover_text(panel.title())
+                                .clicked()
+                            {
+                                self.ui.toggle_panel(rid, panel);
+                            }
+                        }
+                        let mode = self.notification_modes.get(rid).copied();
+                        let bell = match mode.flatten() {
+                            Some(NotificationMode::Mute) => "🔕",
+                            _ => "🔔",
+                        };
+                        let menu = ui.menu_button(bell, |ui| {
+                            let Some(mode) = mode else {
+                                ui.spinner();
+                                return;
+                            };
+                            let mut choice = mode;
+                            ui.radio_value(&mut choice, None, "Default");
+                            for option in NotificationMode::ALL {
+                                ui.radio_value(&mut choice, Some(option), option.label());
+                            }
+                            if choice != mode {
+                                self.notification_modes.remove(rid);
+                                let _ = self.cmd_tx.send(AppCommand::SetNotificationMode {
+                                    room_id: rid.to_owned(),
+                                    mode: choice,
+                                });
+                                ui.close_menu();
+                            }
+                        });
+                        if menu.response.on_hover_text("Notifications").clicked() && mode.is_none()
+                        {
+                            let _ = self.cmd_tx.send(AppCommand::FetchNotificationMode {
+                                room_id: rid.to_owned(),
+                            });
+                        }
+                        if let Some(knocks) = self.knocks.get(rid) {
+                            ui.menu_button(format!("🚪 {}", knocks.len()), |ui| {
+                                for knock in knocks {
+                                    ui.horizontal(|ui| {
+                                        let name =
+                                            knock.display_name.as_deref().unwrap_or(&knock.user_id);
+                                        let label = ui.label(name).on_hover_text(&knock.user_id);
+                                        if let Some(reason) = &knock.reason {
+                                            label.on_hover_text(reason);
+                                        }
+                                        if ui.small_button("Accept").clicked() {
+                                            let _ = self.cmd_tx.send(AppCommand::AcceptKnock {
+                                                room_id: rid.to_owned(),
+                                                user_id: knock.user_id.clone(),
+                                            });
+                                        }
+                                        if ui.small_button("Reject").clicked() {
+                                            let _ = self.cmd_tx.send(AppCommand::RejectKnock {
+                                                room_id: rid.to_owned(),
+                                                user_id: knock.user_id.clone(),
+                                                reason: None,
+                                            });
+                                        }
+                                    });
+                                }
+                            })
+                            .response
+                            .on_hover_text("Requests to join");
+                        }
+                        ui.menu_button("🛡", |ui| {
+                            if ui.button("Unban user…").clicked() {
+                                self.moderation = Some(ModerationDraft {
+                                    room_id: rid.to_owned(),
+                                    user_id: String::new(),
+                                    action: ModerationAction::Unban,
+                                    reason: String::new(),
+                                });
+                                self.ui.open(Dialog::Moderation);
+                                ui.close_menu();
+                            }
+                            if ui.button("Room settings…").clicked() {
+                                self.room_settings = Some((rid.to_owned(), None));
+                                self.room_settings_tab = RoomSettingsTab::General;
+                                self.voice_config = None;
+                                self.room_encryption = None;
+                                self.confirm_encryption = false;
+                                let _ = self.cmd_tx.send(AppCommand::FetchDirectoryListing {
+                                    room_id: rid.to_owned(),
+                                });
+                                let _ = self.cmd_tx.send(AppCommand::FetchRoomEncryption {
+                                    room_id: rid.to_owned(),
+                                });
+                                let _ = self.cmd_tx.send(AppCommand::FetchVoiceConfig {
+                                    room_id: rid.to_owned(),
+                                });
+                                self.ui.open(Dialog::RoomSettings);
+                                ui.close_menu();
+                            }
+                            if ui.button("Roles & permissions…").clicked() {
+                                self.power_levels = None;
+                                self.voice_permissions = None;
+                                let _ = self.cmd_tx.send(AppCommand::FetchPowerLevels {
+                                    room_id: rid.to_owned(),
+                                });
+                                self.ui.open(Dialog::PowerLevels);
+                                ui.close_menu();
+                            }
+                            if ui.button("Federation…").clicked() {
+                                self.server_acl = Some((rid.to_owned(), None));
+                                self.acl_draft = AclDraft::default();
+                                let _ = self.cmd_tx.send(AppCommand::FetchServerAcl {
+                                    room_id: rid.to_owned(),
+                                });
+                                self.ui.open(Dialog::ServerAcl);
+                                ui.close_menu();
+                            }
+                            if self.settings.developer.enabled && ui.button("Room state…").clicked()
+                            {
+                                self.room_state = Some((rid.to_owned(), None));
+                                self.custom_event = CustomEventDraft::default();
+                                let _ = self.cmd_tx.send(AppCommand::FetchRoomState {
+                                    room_id: rid.to_owned(),
+                                });
+                                self.ui.open(Dialog::RoomState);
+                                ui.close_menu();
+                            }
+                        })
+                        .response
+                        .on_hover_text("Moderation");
                         if ui.button("Invite…").clicked() {
-                            self.show_invite_dialog = true;
+                            self.ui.open(Dialog::Invite);
                         }
+                        ui.menu_button("📺", |ui| {
+                            ui.label("Share a link to watch together:");
+                            ui.add(
+                                egui::TextEdit::singleline(&mut self.share_media_draft)
+                                    .hint_text("https://…"),
+                            );
+                            let url = self.share_media_draft.trim();
+                            let valid = url.starts_with("https://") || url.starts_with("http://");
+                            if ui.add_enabled(valid, egui::Button::new("Share")).clicked() {
+                                let _ = self.cmd_tx.send(AppCommand::ShareMedia {
+                                    room_id: rid.to_owned(),
+                                    url: url.to_owned(),
+                                });
+                                self.share_media_draft.clear();
+                                ui.close_menu();
+                            }
+                        })
+                        .response
+                        .on_hover_text("Watch together");
                         if ui.button("Leave").clicked() {
                             if let Some(rid) = room_id.clone() {
                                 let _ = self.cmd_tx.send(AppCommand::LeaveRoom { room_id: rid });
@@ -410,51 +2746,4493 @@ impl eframe::App for SpokeApp {
                         }
 
                         // Voice buttons — shown when a room is selected.
-                        let currently_in_this_room = self.in_voice
-                            && self.voice_room_id.as_deref() == room_id.as_deref();
+                        let currently_in_this_room =
+                            self.in_voice && self.voice_room_id.as_deref() == room_id.as_deref();
 
                         if currently_in_this_room {
                             if ui.button("Leave Voice").clicked() {
                                 let _ = self.cmd_tx.send(AppCommand::LeaveVoice);
                             }
-                            let mute_label = if self.voice_muted { "Unmute" } else { "Mute" };
-                            if ui.button(mute_label).clicked() {
-                                self.voice_muted = !self.voice_muted;
-                                let _ = self.cmd_tx.send(AppCommand::MuteVoice {
-                                    muted: self.voice_muted,
+                            let deafen_label = if self.voice_deafened {
+                                "Undeafen"
+                            } else {
+                                "Deafen"
+                            };
+                            if ui.button(deafen_label).clicked() {
+                                self.set_voice_deafened(!self.voice_deafened);
+                            }
+                            if self.voice_can_publish {
+                                let mute_label = if self.voice_muted { "Unmute" } else { "Mute" };
+                                if ui.button(mute_label).clicked() {
+                                    self.set_voice_muted(!self.voice_muted);
+                                }
+                                if !self.voice_stage {
+                                    let raised = self.hand_raised;
+                                    if ui.selectable_label(raised, "✋").clicked() {
+                                        self.hand_raised = !raised;
+                                        let _ = self.cmd_tx.send(AppCommand::SendVoiceData {
+                                            message: DataMessage::Hand {
+                                                raised: self.hand_raised,
+                                            },
+                                        });
+                                    }
+                                }
+                            } else if self.voice_stage {
+                                // Stage listener — ask to speak instead of muting.
+                                let hand_label = if self.hand_raised {
+                                    "Lower hand"
+                                } else {
+                                    "✋ Raise hand"
+                                };
+                                if ui.button(hand_label).clicked() {
+                                    self.hand_raised = !self.hand_raised;
+                                    let _ = self.cmd_tx.send(AppCommand::RaiseHand {
+                                        raised: self.hand_raised,
+                                    });
+                                }
+                            } else {
+                                // Below the room's speak threshold.
+                                ui.weak("Listening only");
+                            }
+                            if self.voice_priority_speaker {
+                                ui.weak("★").on_hover_text("Priority speaker");
+                            }
+                            if self.voice_can_screen_share {
+                                ui.weak("🖵")
+                                    .on_hover_text("You may share your screen in this room");
+                            }
+                            ui.menu_button("😀", |ui| {
+                                ui.horizontal(|ui| {
+                                    for emoji in CALL_REACTIONS {
+                                        if ui.button(emoji).clicked() {
+                                            let _ = self.cmd_tx.send(AppCommand::SendVoiceData {
+                                                message: DataMessage::Reaction {
+                                                    emoji: emoji.to_owned(),
+                                                },
+                                            });
+                                        }
+                                    }
                                 });
+                            });
+                            if self.voice_can_moderate {
+                                let mut stage = self.voice_stage;
+                                if ui.checkbox(&mut stage, "Stage").changed() {
+                                    if let Some(rid) = room_id.clone() {
+                                        let _ = self.cmd_tx.send(AppCommand::SetStageMode {
+                                            room_id: rid,
+                                            enabled: stage,
+                                        });
+                                    }
+                                }
+                            }
+                            // Small "in voice" indicator; click for diagnostics.
+                            let indicator = ui.add(
+                                egui::Label::new(
+                                    egui::RichText::new("● Voice")
+                                        .small()
+                                        .color(egui::Color32::GREEN),
+                                )
+                                .sense(egui::Sense::click()),
+                            );
+                            if indicator.on_hover_text("Connection diagnostics").clicked() {
+                                self.ui.open(Dialog::VoiceDiagnostics);
                             }
-                            // Small "in voice" indicator
-                            ui.small(egui::RichText::new("● Voice").color(egui::Color32::GREEN));
                         } else if !self.in_voice {
                             if ui.button("Join Voice").clicked() {
                                 if let Some(rid) = room_id.clone() {
-                                    let _ = self.cmd_tx.send(AppCommand::JoinVoice { room_id: rid });
+                                    let _ = self.cmd_tx.send(AppCommand::JoinVoice {
+                                        room_id: rid,
+                                        ice: self.settings.ice.clone(),
+                                        tuning: self.voice_tuning(),
+                                        devices: self.device_choice(),
+                                    });
+                                }
+                            }
+                            if is_direct
+                                && ui
+                                    .button("📞 Call")
+                                    .on_hover_text("Join voice and ring them")
+                                    .clicked()
+                            {
+                                if let Some(rid) = room_id.clone() {
+                                    let _ = self.cmd_tx.send(AppCommand::JoinVoice {
+                                        room_id: rid.clone(),
+                                        ice: self.settings.ice.clone(),
+                                        tuning: self.voice_tuning(),
+                                        devices: self.device_choice(),
+                                    });
+                                    let _ = self.cmd_tx.send(AppCommand::Ring { room_id: rid });
                                 }
                             }
                         }
+                        // Who's already in this room's call, leaving out
+                        // memberships whose client stopped renewing them.
+                        if !currently_in_this_room {
+                            let now = SystemTime::now()
+                                .duration_since(UNIX_EPOCH)
+                                .unwrap_or_default()
+                                .as_millis() as u64;
+                            let members: Vec<String> = self
+                                .voice_members
+                                .get(rid)
+                                .into_iter()
+                                .flatten()
+                                .filter(|(_, expires_ts)| *expires_ts > now)
+                                .map(|(user_id, _)| self.display_name(user_id).to_owned())
+                                .collect();
+                            if !members.is_empty() {
+                                ui.label(format!("🔊 {}", members.len()))
+                                    .on_hover_text(members.join("\n"));
+                            }
+                        }
                     }
                 });
             });
             ui.separator();
 
-            egui::ScrollArea::vertical()
-                .stick_to_bottom(true)
-                .show(ui, |ui| {
-                    if let Some(msgs) = room_id.as_ref().and_then(|id| self.messages.get(id)) {
-                        for (sender, body) in msgs {
+            if self
+                .search
+                .as_ref()
+                .is_some_and(|s| Some(&s.room_id) == room_id.as_ref())
+            {
+                self.search_bar_ui(ui);
+                ui.separator();
+            }
+            if self
+                .selection
+                .as_ref()
+                .is_some_and(|s| Some(&s.room_id) != room_id.as_ref())
+            {
+                self.selection = None;
+            }
+            if let (true, Some(rid)) = (self.selection.is_some(), room_id.clone()) {
+                self.selection_bar_ui(ui, &rid);
+                ui.separator();
+            }
+            if let Some(rid) = room_id
+                .as_deref()
+                .filter(|rid| matches!(self.shared_media.get(*rid), Some(Some(_))))
+            {
+                self.shared_media_ui(ui, rid);
+                ui.separator();
+            }
+
+            let mut timeline = egui::ScrollArea::vertical().stick_to_bottom(true);
+            if let Some(offset) = self.scroll_to.take() {
+                timeline = timeline.vertical_scroll_offset(offset);
+            }
+            let timeline = timeline.show(ui, |ui| {
+                if let Some(rid) = room_id.as_deref() {
+                    self.history_start_ui(ui, rid);
+                }
+                let mut retry = None;
+                let mut discard = None;
+                let mut moderate: Option<(String, ModerationAction)> = None;
+                let mut promote: Option<(String, i64)> = None;
+                let mut direct: Option<String> = None;
+                let mut unresolved: HashSet<String> = HashSet::new();
+                let mut action: Option<(String, MessageAction)> = None;
+                let mut open_thread: Option<String> = None;
+                let mut toolbar_for: Option<String> = None;
+                let mut picked: Option<(String, Pick)> = None;
+                let mut poll_action: Option<(String, PollAction)> = None;
+                let mut voice_action: Option<AppCommand> = None;
+                let room_polls = room_id.as_ref().and_then(|id| self.polls.get(id));
+                let mut wanted_previews: HashSet<String> = HashSet::new();
+                let mut wanted_images: HashSet<String> = HashSet::new();
+                let show_previews = room_id.as_deref().is_some_and(|rid| {
+                    let encrypted = self.rooms.iter().any(|r| r.id == rid && r.encrypted);
+                    self.settings.privacy.url_previews(rid, encrypted)
+                });
+                let room_reactions = room_id.as_ref().and_then(|id| self.reactions.get(id));
+                let search = self
+                    .search
+                    .as_mut()
+                    .filter(|s| Some(&s.room_id) == room_id.as_ref());
+                if let Some(msgs) = room_id.as_ref().and_then(|id| self.messages.get(id)) {
+                    let current_match = search.as_ref().and_then(|s| s.current(&s.matches(msgs)));
+                    let mut scrolled = false;
+                    let mut jumped = false;
+                    let collapse_membership = self.settings.appearance.collapse_membership;
+                    for (i, m) in msgs.iter().enumerate() {
+                        let hit = search.as_ref().is_some_and(|s| s.is_match(m));
+                        if search
+                            .as_ref()
+                            .is_some_and(|s| s.filter && !s.query.is_empty())
+                            && !hit
+                        {
+                            continue;
+                        }
+                        // Thread replies live in the Threads panel.
+                        if m.thread_root.is_some() {
+                            continue;
+                        }
+                        if m.membership {
+                            if !collapse_membership {
+                                ui.weak(&m.body);
+                            } else if i == 0 || !msgs[i - 1].membership {
+                                let run: Vec<&str> = msgs[i..]
+                                    .iter()
+                                    .take_while(|m| m.membership)
+                                    .map(|m| m.body.as_str())
+                                    .collect();
+                                let line = match run.len() {
+                                    1..=3 => run.join(", "),
+                                    n => format!("{}, {} and {} more", run[0], run[1], n - 2),
+                                };
+                                let label = ui.weak(line);
+                                if run.len() > 3 {
+                                    label.on_hover_text(run.join("\n"));
+                                }
+                            }
+                            continue;
+                        }
+                        let profile = self.profiles.get(&m.sender);
+                        if profile.is_none() {
+                            unresolved.insert(m.sender.clone());
+                        }
+                        let row = ui.vertical(|ui| {
+                            let quoted = m.reply_to.as_deref().and_then(|id| {
+                                msgs.iter().find(|q| q.event_id.as_deref() == Some(id))
+                            });
+                            if let Some(quoted) = quoted {
+                                let name = self
+                                    .profiles
+                                    .get(&quoted.sender)
+                                    .and_then(|p| p.display_name.as_deref())
+                                    .unwrap_or(&quoted.sender);
+                                ui.weak(format!("↩ {}", quote_line(name, &quoted.body)));
+                            }
                             ui.horizontal(|ui| {
-                                ui.strong(sender);
-                                ui.label(body);
+                                avatar_ui(ui, &m.sender, profile);
+                                let name = profile
+                                    .and_then(|p| p.display_name.as_deref())
+                                    .unwrap_or(&m.sender);
+                                let sender = ui
+                                    .add(
+                                        egui::Label::new(
+                                            egui::RichText::new(name)
+                                                .strong()
+                                                .color(sender_color(ui.visuals(), &m.sender)),
+                                        )
+                                        .sense(egui::Sense::click()),
+                                    )
+                                    .on_hover_text(&m.sender);
+                                sender.context_menu(|ui| {
+                                    if ui.button("Message").clicked() {
+                                        direct = Some(m.sender.clone());
+                                        ui.close_menu();
+                                    }
+                                    ui.separator();
+                                    for action in [ModerationAction::Kick, ModerationAction::Ban] {
+                                        let label = if action == ModerationAction::Kick {
+                                            "Kick…"
+                                        } else {
+                                            "Ban…"
+                                        };
+                                        if ui.button(label).clicked() {
+                                            moderate = Some((m.sender.clone(), action));
+                                            ui.close_menu();
+                                        }
+                                    }
+                                    ui.separator();
+                                    for (label, level) in [
+                                        ("Make moderator", MODERATOR_LEVEL),
+                                        ("Make admin", ADMIN_LEVEL),
+                                    ] {
+                                        if ui.button(label).clicked() {
+                                            promote = Some((m.sender.clone(), level));
+                                            ui.close_menu();
+                                        }
+                                    }
+                                });
+                                match &m.delivery {
+                                    None if m.poll.is_some() => {
+                                        if let (Some(poll), Some(poll_id)) = (&m.poll, &m.event_id)
+                                        {
+                                            let none = PollVotes::default();
+                                            let votes = room_polls
+                                                .and_then(|p| p.get(poll_id))
+                                                .unwrap_or(&none);
+                                            let results =
+                                                poll.tally(&m.sender, votes, &self.own_user_id);
+                                            let can_end = m.sender == self.own_user_id;
+                                            if let Some(chosen) =
+                                                polls::show(ui, poll, &results, can_end)
+                                            {
+                                                poll_action = Some((poll_id.clone(), chosen));
+                                            }
+                                        }
+                                    }
+                                    None if m.voice.is_some() => {
+                                        if let Some(voice) = &m.voice {
+                                            let playing = self.playing_voice.as_deref()
+                                                == Some(voice.url.as_str());
+                                            if voice_message_ui(ui, voice, playing) {
+                                                voice_action = Some(if playing {
+                                                    AppCommand::StopVoiceMessage
+                                                } else {
+                                                    AppCommand::PlayVoiceMessage {
+                                                        url: voice.url.clone(),
+                                                    }
+                                                });
+                                            }
+                                        }
+                                    }
+                                    None if m.undecrypted => {
+                                        ui.label(egui::RichText::new(&m.body).weak().italics())
+                                            .on_hover_text(
+                                                "Spoke asked your other devices and key \
+                                                 backup for this message's key. It will \
+                                                 appear here once the key arrives.",
+                                            );
+                                    }
+                                    None if m.sticker.is_some() => {
+                                        if let Some(mxc) = &m.sticker {
+                                            sticker_ui(
+                                                ui,
+                                                mxc,
+                                                &m.body,
+                                                &self.pack_images,
+                                                &mut wanted_images,
+                                            );
+                                        }
+                                    }
+                                    None if hit => {
+                                        let query =
+                                            search.as_ref().map(|s| s.query.as_str()).unwrap_or("");
+                                        ui.label(search::highlight(
+                                            ui,
+                                            &m.body,
+                                            query,
+                                            current_match == Some(i),
+                                        ));
+                                    }
+                                    None if m.rich.is_some() => {
+                                        if let Some(rich) = &m.rich {
+                                            markup::show(
+                                                ui,
+                                                rich,
+                                                m.mentions_me,
+                                                &self.pack_images,
+                                                &mut wanted_images,
+                                            );
+                                        }
+                                    }
+                                    None if m.mentions_me => {
+                                        let fill =
+                                            ui.visuals().selection.bg_fill.gamma_multiply(0.5);
+                                        ui.label(
+                                            egui::RichText::new(&m.body).background_color(fill),
+                                        );
+                                    }
+                                    None => {
+                                        ui.label(&m.body);
+                                    }
+                                    Some(DeliveryState::Failed { error }) => {
+                                        ui.label(egui::RichText::new(&m.body).weak());
+                                        ui.colored_label(egui::Color32::RED, "Failed")
+                                            .on_hover_text(error);
+                                        if ui.small_button("Retry").clicked() {
+                                            retry = m.txn_id.clone();
+                                        }
+                                        if ui.small_button("Discard").clicked() {
+                                            discard = m.txn_id.clone();
+                                        }
+                                    }
+                                    Some(_) => {
+                                        ui.label(egui::RichText::new(&m.body).weak());
+                                        ui.spinner().on_hover_text("Sending…");
+                                    }
+                                }
+                                if m.edited {
+                                    ui.weak("(edited)");
+                                }
                             });
-                        }
+                            if let Some(url) =
+                                first_link(m).filter(|_| show_previews && m.delivery.is_none())
+                            {
+                                match self.url_previews.get(&url) {
+                                    Some(Some(preview)) => {
+                                        ui.horizontal(|ui| {
+                                            ui.add_space(
+                                                AVATAR_POINTS + ui.spacing().item_spacing.x,
+                                            );
+                                            link_preview_ui(ui, preview);
+                                        });
+                                    }
+                                    Some(None) => {}
+                                    None => {
+                                        wanted_previews.insert(url);
+                                    }
+                                }
+                            }
+                            let replies = m.event_id.as_deref().map_or(0, |id| {
+                                msgs.iter()
+                                    .filter(|r| r.thread_root.as_deref() == Some(id))
+                                    .count()
+                            });
+                            let on = m
+                                .event_id
+                                .as_deref()
+                                .and_then(|id| room_reactions.and_then(|r| r.get(id)));
+                            if replies > 0 || on.is_some_and(|on| !on.is_empty()) {
+                                ui.horizontal(|ui| {
+                                    ui.add_space(AVATAR_POINTS + ui.spacing().item_spacing.x);
+                                    let on = on.map(Vec::as_slice).unwrap_or(&[]);
+                                    for (key, senders, mine) in
+                                        group_reactions(on, &self.own_user_id)
+                                    {
+                                        let label = format!("{key} {}", senders.len());
+                                        let names: Vec<&str> = senders
+                                            .iter()
+                                            .map(|s| {
+                                                self.profiles
+                                                    .get(*s)
+                                                    .and_then(|p| p.display_name.as_deref())
+                                                    .unwrap_or(*s)
+                                            })
+                                            .collect();
+                                        let chip = ui
+                                            .selectable_label(mine.is_some(), label)
+                                            .on_hover_text(names.join(", "));
+                                        if chip.clicked() {
+                                            if let Some(id) = &m.event_id {
+                                                action = Some((
+                                                    id.clone(),
+                                                    MessageAction::React(key.to_owned()),
+                                                ));
+                                            }
+                                        }
+                                    }
+                                    if replies > 0 {
+                                        let label = if replies == 1 {
+                                            "🧵 1 reply".to_owned()
+                                        } else {
+                                            format!("🧵 {replies} replies")
+                                        };
+                                        if ui.small_button(label).clicked() {
+                                            open_thread = m.event_id.clone();
+                                        }
+                                    }
+                                });
+                            }
+                        });
+                        if let Some(event_id) = &m.event_id {
+                            let rect = row.response.rect;
+                            let selecting = self.selection.is_some();
+                            if let Some(pick) = message_actions::pick(ui, rect, selecting) {
+                                picked = Some((event_id.clone(), pick));
+                            }
+                            if self
+                                .selection
+                                .as_ref()
+                                .is_some_and(|s| s.events.contains(event_id))
+                            {
+                                let fill = ui.visuals().selection.bg_fill.gamma_multiply(0.3);
+                                ui.painter().rect_filled(rect.expand(2.0), 4.0, fill);
+                            }
+                            let hovered = ui
+                                .ctx()
+                                .pointer_hover_pos()
+                                .is_some_and(|p| rect.contains(p) && ui.clip_rect().contains(p));
+                            let shown = hovered
+                                || self.toolbar_for.as_ref() == Some(event_id)
+                                || self.focused_message.as_ref() == Some(event_id);
+                            if self.focused_message.as_ref() == Some(event_id) {
+                                let stroke = ui.visuals().selection.stroke;
+                                ui.painter().rect_stroke(
+                                    rect.expand(2.0),
+                                    4.0,
+                                    stroke,
+                                    egui::StrokeKind::Outside,
+                                );
+                            }
+                            if shown && !selecting {
+                                let offer = Offer {
+                                    editable: m.sender == self.own_user_id,
+                                    threadable: m.thread_root.is_none(),
+                                    pinned: room_id
+                                        .as_ref()
+                                        .and_then(|id| self.pins.get(id))
+                                        .filter(|p| p.can_pin)
+                                        .map(|p| {
+                                            p.messages.iter().any(|pin| &pin.event_id == event_id)
+                                        }),
+                                };
+                                let id = egui::Id::new(("message_toolbar", event_id));
+                                let (chosen, keep) =
+                                    message_actions::toolbar(ui.ctx(), id, rect, offer);
+                                if keep {
+                                    toolbar_for = Some(event_id.clone());
+                                }
+                                if let Some(chosen) = chosen {
+                                    action = Some((event_id.clone(), chosen));
+                                }
+                            }
+                        }
+                        if current_match == Some(i)
+                            && search.as_ref().is_some_and(|s| s.scroll_pending)
+                        {
+                            row.response.scroll_to_me(Some(egui::Align::Center));
+                            scrolled = true;
+                        }
+                        if m.event_id.is_some() && m.event_id == self.jump_to {
+                            row.response.scroll_to_me(Some(egui::Align::Center));
+                            jumped = true;
+                        }
+                    }
+                    if let Some(s) = search.filter(|_| scrolled) {
+                        s.scroll_pending = false;
+                    }
+                    if jumped {
+                        self.jump_to = None;
+                    }
+                    if let Some(rid) = room_id.as_deref() {
+                        if self.settings.privacy.read_receipts(rid) {
+                            let seen = self.seen_by(rid, msgs);
+                            if !seen.is_empty() {
+                                ui.weak(format!("Seen by {}", seen.join(", ")));
+                            }
+                        }
+                    }
+                }
+                if let Some(txn_id) = retry {
+                    let _ = self.cmd_tx.send(AppCommand::RetryMessage { txn_id });
+                }
+                self.toolbar_for = toolbar_for;
+                if let (Some((poll_id, chosen)), Some(room_id)) = (poll_action, room_id.clone()) {
+                    let cmd = match chosen {
+                        PollAction::Vote(answers) => AppCommand::VotePoll {
+                            room_id,
+                            poll_id,
+                            answers,
+                        },
+                        PollAction::End => AppCommand::EndPoll { room_id, poll_id },
+                    };
+                    let _ = self.cmd_tx.send(cmd);
+                }
+                if let (Some((event_id, pick)), Some(room_id)) = (picked, room_id.clone()) {
+                    self.pick_message(room_id, event_id, pick);
+                }
+                if let (Some((event_id, action)), Some(room_id)) = (action, room_id.as_deref()) {
+                    self.message_action(ctx, room_id, &event_id, action);
+                }
+                if let (Some(root), Some(room_id)) = (open_thread, room_id.as_deref()) {
+                    self.open_thread(room_id, root);
+                }
+                let now = ctx.input(|i| i.time);
+                for url in wanted_previews {
+                    if self.url_preview_retry.get(&url).is_some_and(|&at| at > now) {
+                        continue;
+                    }
+                    if self.url_previews_requested.insert(url.clone()) {
+                        let _ = self.cmd_tx.send(AppCommand::FetchUrlPreview { url });
+                    }
+                }
+                self.request_pack_images(wanted_images);
+                if let Some(cmd) = voice_action {
+                    let _ = self.cmd_tx.send(cmd);
+                }
+                unresolved.retain(|u| self.profiles_requested.insert(u.clone()));
+                if let (false, Some(room_id)) = (unresolved.is_empty(), room_id.clone()) {
+                    let _ = self.cmd_tx.send(AppCommand::ResolveProfiles {
+                        room_id,
+                        user_ids: unresolved.into_iter().collect(),
+                    });
+                }
+                if let (Some((user_id, action)), Some(room_id)) = (moderate, room_id.clone()) {
+                    self.moderation = Some(ModerationDraft {
+                        room_id,
+                        user_id,
+                        action,
+                        reason: String::new(),
+                    });
+                    self.ui.open(Dialog::Moderation);
+                }
+                if let Some(mxid) = direct {
+                    let _ = self.cmd_tx.send(AppCommand::StartDirectMessage { mxid });
+                }
+                if let (Some((user_id, level)), Some(room_id)) = (promote, room_id.clone()) {
+                    let change = PowerLevelChange::User { user_id, level };
+                    let _ = self
+                        .cmd_tx
+                        .send(AppCommand::SetPowerLevel { room_id, change });
+                }
+                if let (Some(txn_id), Some(log)) = (
+                    discard,
+                    room_id.as_ref().and_then(|id| self.messages.get_mut(id)),
+                ) {
+                    log.retain(|m| m.txn_id.as_deref() != Some(txn_id.as_str()));
+                    let _ = self.cmd_tx.send(AppCommand::DiscardMessage { txn_id });
+                }
+            });
+            if let Some(rid) = room_id.as_deref() {
+                self.scrollback(ctx, rid, timeline.content_size.y, timeline.state.offset.y);
+            }
+        });
+
+        self.paint_reactions(ctx, central.response.rect);
+        self.paint_call_toasts(ctx, central.response.rect);
+        self.send_read_receipt(ctx);
+
+        if let Some(retry) = self.ui.save_if_dirty() {
+            ctx.request_repaint_after(retry);
+        }
+        self.roam_settings(ctx);
+    }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.ui.save();
+    }
+}
+
+impl SpokeApp {
+    /// Push local settings changes to account data once they've settled.
+    /// Waits for the account's copy first, so stale settings never clobber
+    /// newer ones from another device.
+    fn roam_settings(&mut self, ctx: &egui::Context) {
+        let Some(synced) = self.settings_synced_ts else {
+            return;
+        };
+        if self.settings.updated_ts <= synced {
+            return;
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let settled = self.settings.updated_ts + SETTINGS_ROAM_DELAY.as_millis() as u64;
+        if now < settled {
+            ctx.request_repaint_after(std::time::Duration::from_millis(settled - now));
+            return;
+        }
+        let account = self
+            .settings
+            .to_account(&self.ui.pinned_rooms, self.account_settings.as_ref());
+        let _ = self
+            .cmd_tx
+            .send(AppCommand::SaveAccountSettings(account.clone()));
+        self.settings_synced_ts = Some(self.settings.updated_ts);
+        self.account_settings = Some(account);
+    }
+
+    fn set_voice_muted(&mut self, muted: bool) {
+        if self.voice_muted != muted {
+            self.voice_muted = muted;
+            let _ = self.cmd_tx.send(AppCommand::MuteVoice { muted });
+        }
+    }
+
+    /// Whether the mic only opens while the push-to-talk key is held.
+    fn push_to_talk(&self) -> bool {
+        self.settings.voice.input_mode == InputMode::PushToTalk
+            && self
+                .settings
+                .keybinds
+                .get(VoiceAction::PushToTalk)
+                .is_some()
+    }
+
+    /// Deafening also mutes the mic; undeafening restores the previous mute.
+    fn set_voice_deafened(&mut self, deafened: bool) {
+        if self.voice_deafened == deafened {
+            return;
+        }
+        self.voice_deafened = deafened;
+        let _ = self.cmd_tx.send(AppCommand::DeafenVoice { deafened });
+        if self.voice_can_publish {
+            if deafened {
+                self.muted_before_deafen = self.voice_muted;
+                self.set_voice_muted(true);
+            } else {
+                self.set_voice_muted(self.muted_before_deafen);
+            }
+        }
+    }
+
+    /// Autocomplete candidates for an `@user` or `#room` token.
+    fn composer_suggestions(&self, kind: PillKind, query: &str) -> Vec<Suggestion> {
+        const MAX: usize = 5;
+        match kind {
+            PillKind::User => {
+                let Some(room_id) = self
+                    .selected_room
+                    .and_then(|i| self.rooms.get(i))
+                    .map(|r| &r.id)
+                else {
+                    return Vec::new();
+                };
+                self.mentionable(room_id)
+                    .into_iter()
+                    .filter(|(user_id, name)| {
+                        user_id.to_lowercase().contains(query)
+                            || name.to_lowercase().contains(query)
+                    })
+                    .take(MAX)
+                    .map(|(target, name)| Suggestion {
+                        kind,
+                        target,
+                        label: format!("@{name}"),
+                    })
+                    .collect()
+            }
+            PillKind::Room => self
+                .rooms
+                .iter()
+                .filter(|r| !r.is_space)
+                .filter(|r| {
+                    r.name.to_lowercase().contains(query)
+                        || r.alias
+                            .as_ref()
+                            .is_some_and(|a| a.to_lowercase().contains(query))
+                })
+                .take(MAX)
+                .map(|r| Suggestion {
+                    kind,
+                    target: r.alias.clone().unwrap_or_else(|| r.id.clone()),
+                    label: r.alias.clone().unwrap_or_else(|| format!("#{}", r.name)),
+                })
+                .collect(),
+        }
+    }
+
+    /// Install a history snapshot for `room_id`. The first snapshot goes in
+    /// front of live messages already received; later ones replace the log.
+    /// Local echoes the snapshot doesn't contain are kept either way.
+    fn apply_snapshot(&mut self, room_id: String, messages: Vec<TimelineItem>) {
+        if let Some(last) = messages.iter().rev().find(|m| !m.membership) {
+            self.note_activity(&room_id, RoomPreview::from(last));
+        }
+        let replace = !self.snapshot_rooms.insert(room_id.clone());
+        let known: HashSet<String> = messages.iter().filter_map(|m| m.event_id.clone()).collect();
+        let old = self.messages.remove(&room_id).unwrap_or_default();
+        let kept = old.into_iter().filter(|m| {
+            (!replace || m.txn_id.is_some())
+                && m.event_id.as_ref().is_none_or(|id| !known.contains(id))
+        });
+        let mut log = messages;
+        log.extend(kept);
+        self.messages.insert(room_id, log);
+    }
+
+    /// Users whose public read receipt is on the newest message in `log`.
+    fn seen_by(&self, room_id: &str, log: &[TimelineItem]) -> Vec<String> {
+        let Some(last) = log.iter().rev().find_map(|m| m.event_id.as_deref()) else {
+            return Vec::new();
+        };
+        let Some(markers) = self.read_markers.get(room_id) else {
+            return Vec::new();
+        };
+        let mut users: Vec<String> = markers
+            .iter()
+            .filter(|(_, e)| e.as_str() == last)
+            .map(|(u, _)| u.clone())
+            .collect();
+        users.sort();
+        users
+    }
+
+    /// Mark the selected room read up to its newest event while the window
+    /// has focus. With read receipts off the receipt is private, so other
+    /// devices still see the room as read.
+    fn send_read_receipt(&mut self, ctx: &egui::Context) {
+        if !ctx.input(|i| i.focused) {
+            return;
+        }
+        let Some(room_id) = self
+            .selected_room
+            .and_then(|i| self.rooms.get(i))
+            .map(|r| r.id.clone())
+        else {
+            return;
+        };
+        self.unread.remove(&room_id);
+        let Some(event_id) = self
+            .messages
+            .get(&room_id)
+            .and_then(|log| log.iter().rev().find_map(|m| m.event_id.clone()))
+        else {
+            return;
+        };
+        if self.sent_receipts.get(&room_id) == Some(&event_id) {
+            return;
+        }
+        self.sent_receipts.insert(room_id.clone(), event_id.clone());
+        let private = !self.settings.privacy.read_receipts(&room_id) || self.appears_offline();
+        let _ = self.cmd_tx.send(AppCommand::SendReadReceipt {
+            room_id,
+            event_id,
+            private,
+        });
+    }
+
+    /// Ctrl+F opens (or refocuses) search in the selected room.
+    fn handle_search_shortcut(&mut self, ctx: &egui::Context) {
+        let room_id = self
+            .selected_room
+            .and_then(|i| self.rooms.get(i))
+            .map(|r| r.id.clone());
+        // Switching rooms ends the search.
+        if self
+            .search
+            .as_ref()
+            .is_some_and(|s| Some(&s.room_id) != room_id.as_ref())
+        {
+            self.search = None;
+        }
+        if !ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::F)) {
+            return;
+        }
+        if let Some(room_id) = room_id {
+            self.search
+                .get_or_insert_with(|| RoomSearch::new(room_id))
+                .focus_pending = true;
+        }
+    }
+
+    /// Query field, match navigation, and backfill controls.
+    fn search_bar_ui(&mut self, ui: &mut egui::Ui) {
+        let Some(search) = self.search.as_mut() else {
+            return;
+        };
+        let log = self
+            .messages
+            .get(&search.room_id)
+            .map(Vec::as_slice)
+            .unwrap_or(&[]);
+        let matches = search.matches(log);
+        let token = self.history_tokens.get(&search.room_id);
+        let mut close = false;
+        let mut backfill_from: Option<String> = None;
+
+        ui.horizontal(|ui| {
+            let field = ui.add(
+                egui::TextEdit::singleline(&mut search.query)
+                    .hint_text("Search this room…")
+                    .desired_width(220.0),
+            );
+            if std::mem::take(&mut search.focus_pending) {
+                field.request_focus();
+            }
+            if field.changed() {
+                search.reset();
+            }
+            if field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                if ui.input(|i| i.modifiers.shift) {
+                    search.newer();
+                } else {
+                    search.older(&matches);
+                }
+                field.request_focus();
+            }
+            // Escape drops focus from the field; treat that as closing.
+            if field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                close = true;
+            }
+
+            if !search.query.is_empty() {
+                ui.label(format!("{} / {}", search.position(&matches), matches.len()));
+            }
+            if ui
+                .small_button("▲")
+                .on_hover_text("Older match (Enter)")
+                .clicked()
+            {
+                search.older(&matches);
+            }
+            if ui
+                .small_button("▼")
+                .on_hover_text("Newer match (Shift+Enter)")
+                .clicked()
+            {
+                search.newer();
+            }
+            ui.checkbox(&mut search.filter, "Only matches");
+
+            if search.backfill_remaining > 0 {
+                ui.spinner();
+                ui.weak("Searching older messages…");
+            } else {
+                match token {
+                    Some(None) => {
+                        ui.weak("Start of room");
+                    }
+                    Some(Some(from)) if !search.query.is_empty() => {
+                        if ui.small_button("Search older messages").clicked() {
+                            search.start_backfill(&matches);
+                            backfill_from = Some(from.clone());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            if ui.small_button("✕").clicked() {
+                close = true;
+            }
+        });
+
+        if let Some(from) = backfill_from {
+            let room_id = search.room_id.clone();
+            let _ = self
+                .cmd_tx
+                .send(AppCommand::FetchMoreHistory { room_id, from });
+        }
+        if close {
+            self.search = None;
+        }
+    }
+
+    /// Alt+Up / Alt+Down step through the room list (spaces excluded).
+    fn handle_room_navigation(&mut self, ctx: &egui::Context) {
+        let step = ctx.input(|i| {
+            if !i.modifiers.alt {
+                0
+            } else if i.key_pressed(egui::Key::ArrowUp) {
+                -1
+            } else if i.key_pressed(egui::Key::ArrowDown) {
+                1
+            } else {
+                0
+            }
+        });
+        if step != 0 {
+            if let Some(i) = self.adjacent_room(step) {
+                self.selected_room = Some(i);
+            }
+        }
+    }
+
+    /// Order the sidebar: pinned rooms first in the order they were pinned,
+    /// then everything else by latest activity. Keeps the selection.
+    fn sort_rooms(&mut self) {
+        let selected_id = self
+            .selected_room
+            .and_then(|i| self.rooms.get(i))
+            .map(|r| r.id.clone());
+        let pinned = &self.ui.pinned_rooms;
+        self.rooms.sort_by_key(|r| {
+            let pin = pinned
+                .iter()
+                .position(|id| *id == r.id)
+                .unwrap_or(usize::MAX);
+            (pin, std::cmp::Reverse(r.activity_ts()))
+        });
+        if let Some(id) = selected_id {
+            self.selected_room = self.rooms.iter().position(|r| r.id == id);
+        }
+    }
+
+    /// A message arrived or was sent in `room_id`; bump the room if it's newer
+    /// than what the sidebar shows.
+    fn note_activity(&mut self, room_id: &str, preview: RoomPreview) {
+        let Some(room) = self.rooms.iter_mut().find(|r| r.id == room_id) else {
+            return;
+        };
+        if room
+            .last_message
+            .as_ref()
+            .is_some_and(|m| m.ts > preview.ts)
+        {
+            return;
+        }
+        let moved = room
+            .last_message
+            .as_ref()
+            .is_none_or(|m| m.ts != preview.ts);
+        room.last_message = Some(preview);
+        if moved {
+            self.sort_rooms();
+        }
+    }
+
+    /// Index of the next non-space room `step` (±1) away from the selection.
+    fn adjacent_room(&self, step: isize) -> Option<usize> {
+        let rooms: Vec<usize> = (0..self.rooms.len())
+            .filter(|&i| !self.rooms[i].is_space)
+            .collect();
+        let pos = self
+            .selected_room
+            .and_then(|sel| rooms.iter().position(|&i| i == sel));
+        let next = match pos {
+            Some(p) => p.checked_add_signed(step).filter(|&p| p < rooms.len())?,
+            None => 0,
+        };
+        rooms.get(next).copied()
+    }
+
+    /// When the selection changes, warm history for the rooms around it and
+    /// the ones visited recently.
+    fn preload_adjacent_rooms(&mut self) {
+        let Some(current) = self
+            .selected_room
+            .and_then(|i| self.rooms.get(i))
+            .map(|r| r.id.clone())
+        else {
+            return;
+        };
+        if self.preloaded_for.as_deref() == Some(current.as_str()) {
+            return;
+        }
+        self.recent_rooms.retain(|id| *id != current);
+        self.recent_rooms.insert(0, current.clone());
+        self.recent_rooms.truncate(RECENT_ROOMS);
+
+        let mut room_ids: Vec<String> = Vec::new();
+        let neighbours = [self.adjacent_room(1), self.adjacent_room(-1)];
+        let candidates = neighbours
+            .into_iter()
+            .flatten()
+            .map(|i| self.rooms[i].id.clone())
+            .chain(self.recent_rooms.iter().skip(1).cloned());
+        for id in candidates {
+            if !self.fetched_rooms.contains(&id) && !room_ids.contains(&id) {
+                room_ids.push(id);
+            }
+        }
+        self.preloaded_for = Some(current);
+        if !room_ids.is_empty() {
+            let _ = self.cmd_tx.send(AppCommand::PreloadHistory { room_ids });
+        }
+    }
+
+    /// Capture new bindings, or apply bound voice actions.
+    fn handle_keybinds(&mut self, ctx: &egui::Context) {
+        self.keybind_input.poll();
+        self.keybind_input
+            .set_global(ctx, self.settings.voice.global_keybinds);
+
+        if let Some(action) = self.capturing_binding {
+            if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+                self.capturing_binding = None;
+            } else if let Some(binding) = self.keybind_input.capture(ctx) {
+                self.capturing_binding = None;
+                match self.settings.keybinds.conflict(action, &binding) {
+                    Some(other) => self.binding_conflict = Some((action, binding, other)),
+                    None => {
+                        self.settings.keybinds.set(action, binding);
+                        self.settings.save();
+                    }
+                }
+            }
+            return;
+        }
+
+        let transitions = self.keybind_input.transitions(ctx, &self.settings.keybinds);
+        if !self.in_voice {
+            return;
+        }
+        for (action, down) in transitions {
+            match (action, down) {
+                (VoiceAction::PushToTalk, down)
+                    if self.push_to_talk() && self.voice_can_publish && !self.voice_deafened =>
+                {
+                    self.set_voice_muted(!down);
+                }
+                (VoiceAction::ToggleMute, true) if self.voice_can_publish => {
+                    self.set_voice_muted(!self.voice_muted);
+                }
+                (VoiceAction::ToggleDeafen, true) => {
+                    self.set_voice_deafened(!self.voice_deafened);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// An account ID no bridge has used yet.
+    fn next_account(&self) -> AccountId {
+        AccountId(self.account.0 + 1)
+    }
+
+    /// The "Sessions" section of the settings window: rename this device,
+    /// sign out others after confirming the account password.
+    fn sessions_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.heading("Sessions");
+            if ui.small_button("⟳").on_hover_text("Refresh").clicked() {
+                self.devices_requested = false;
+            }
+        });
+        if !self.devices_requested {
+            self.devices_requested = true;
+            let _ = self.cmd_tx.send(AppCommand::FetchDevices);
+        }
+        let Some(devices) = &self.devices else {
+            ui.label("Loading…");
+            return;
+        };
+
+        ui.horizontal(|ui| {
+            ui.label("This device");
+            ui.add(egui::TextEdit::singleline(&mut self.device_name_input).desired_width(180.0));
+            if ui.button("Rename").clicked() {
+                let _ = self.cmd_tx.send(AppCommand::RenameDevice {
+                    name: self.device_name_input.clone(),
+                });
+            }
+        });
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        egui::Grid::new("sessions")
+            .num_columns(3)
+            .spacing([12.0, 4.0])
+            .show(ui, |ui| {
+                for device in devices {
+                    if device.current {
+                        ui.label("");
+                    } else {
+                        let mut ticked = self.devices_selected.contains(&device.device_id);
+                        if ui.checkbox(&mut ticked, "").changed() {
+                            if ticked {
+                                self.devices_selected.insert(device.device_id.clone());
+                            } else {
+                                self.devices_selected.remove(&device.device_id);
+                            }
+                        }
+                    }
+                    let name = device.display_name.as_deref().unwrap_or(&device.device_id);
+                    let label = if device.current {
+                        format!("{name} (this device)")
+                    } else {
+                        name.to_owned()
+                    };
+                    ui.label(label).on_hover_text(&device.device_id);
+                    let seen = last_seen_label(device.last_seen_ts, now);
+                    match &device.last_seen_ip {
+                        Some(ip) => ui.small(format!("{seen} · {ip}")),
+                        None => ui.small(seen),
+                    };
+                    ui.end_row();
+                }
+            });
+
+        if !self.devices_selected.is_empty() {
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.device_password_input)
+                        .password(true)
+                        .hint_text("account password")
+                        .desired_width(160.0),
+                );
+                let label = format!("Sign out {}", self.devices_selected.len());
+                let ready = !self.device_password_input.is_empty();
+                if ui.add_enabled(ready, egui::Button::new(label)).clicked() {
+                    let _ = self.cmd_tx.send(AppCommand::DeleteDevices {
+                        device_ids: self.devices_selected.drain().collect(),
+                        password: std::mem::take(&mut self.device_password_input),
+                    });
+                }
+            });
+        }
+    }
+
+    fn show_settings_window(&mut self, ctx: &egui::Context) {
+        let mut open = true;
+        egui::Window::new("Settings")
+            .collapsible(false)
+            .default_width(380.0)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.heading("Profile");
+                ui.horizontal(|ui| {
+                    let own = self.profiles.get(&self.own_user_id);
+                    avatar_ui(ui, &self.own_user_id, own);
+                    ui.monospace(&self.own_user_id);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Display name");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.display_name_input)
+                            .desired_width(180.0),
+                    );
+                    if ui.button("Save").clicked() {
+                        let _ = self.cmd_tx.send(AppCommand::SetDisplayName {
+                            name: self.display_name_input.clone(),
+                        });
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Avatar");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.avatar_path_input)
+                            .hint_text("path to a PNG or JPEG")
+                            .desired_width(180.0),
+                    );
+                    let path = self.avatar_path_input.trim();
+                    if ui
+                        .add_enabled(!path.is_empty(), egui::Button::new("Upload"))
+                        .clicked()
+                    {
+                        let _ = self.cmd_tx.send(AppCommand::SetAvatar {
+                            path: Some(path.into()),
+                        });
+                    }
+                    if ui.button("Remove").clicked() {
+                        let _ = self.cmd_tx.send(AppCommand::SetAvatar { path: None });
+                    }
+                });
+
+                ui.add_space(12.0);
+                self.sessions_ui(ui);
+                ui.add_space(6.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Log out").clicked() {
+                        let _ = self.cmd_tx.send(AppCommand::Logout {
+                            keep_crypto: self.logout_keep_crypto,
+                        });
+                    }
+                    ui.checkbox(
+                        &mut self.logout_keep_crypto,
+                        "Keep this device's encryption keys",
+                    )
+                    .on_hover_text("Set the local stores aside instead of deleting them");
+                });
+
+                ui.add_space(12.0);
+                ui.heading("Voice keybinds");
+                ui.small(
+                    "Gamepad buttons work while Spoke is in the background; \
+                     keyboard and mouse bindings only while it's focused, unless below.",
+                );
+                if ui
+                    .checkbox(
+                        &mut self.settings.voice.global_keybinds,
+                        "Keyboard and mouse bindings work in the background",
+                    )
+                    .on_hover_text(
+                        "Watches keys across the desktop. Needs Accessibility permission on macOS; \
+                         not available on Wayland.",
+                    )
+                    .changed()
+                {
+                    self.settings.save();
+                }
+                ui.add_space(6.0);
+
+                egui::Grid::new("keybinds")
+                    .num_columns(3)
+                    .spacing([12.0, 6.0])
+                    .show(ui, |ui| {
+                        for action in VoiceAction::ALL {
+                            ui.label(action.label());
+                            if self.capturing_binding == Some(action) {
+                                ui.label(
+                                    egui::RichText::new("Press a key, mouse or gamepad button…")
+                                        .italics(),
+                                );
+                            } else {
+                                let current = self
+                                    .settings
+                                    .keybinds
+                                    .get(action)
+                                    .map(Binding::label)
+                                    .unwrap_or_else(|| "Unbound".into());
+                                ui.monospace(current);
+                            }
+                            ui.horizontal(|ui| {
+                                if self.capturing_binding == Some(action) {
+                                    if ui.small_button("Cancel").clicked() {
+                                        self.capturing_binding = None;
+                                    }
+                                } else if ui.small_button("Change").clicked() {
+                                    self.capturing_binding = Some(action);
+                                    self.binding_conflict = None;
+                                }
+                                if self.settings.keybinds.get(action).is_some()
+                                    && ui.small_button("Clear").clicked()
+                                {
+                                    self.settings.keybinds.clear(action);
+                                    self.settings.save();
+                                }
+                            });
+                            ui.end_row();
+                        }
+                    });
+
+                if let Some((action, binding, other)) = self.binding_conflict.clone() {
+                    ui.add_space(6.0);
+                    ui.colored_label(
+                        egui::Color32::YELLOW,
+                        format!(
+                            "⚠ {} is already bound to {}.",
+                            binding.label(),
+                            other.label()
+                        ),
+                    );
+                    ui.horizontal(|ui| {
+                        if ui.button(format!("Use for {}", action.label())).clicked() {
+                            self.settings.keybinds.set(action, binding);
+                            self.settings.save();
+                            self.binding_conflict = None;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            self.binding_conflict = None;
+                        }
+                    });
+                }
+
+                ui.add_space(12.0);
+                ui.heading("Appearance");
+                let before = self.settings.appearance.theme;
+                ui.horizontal(|ui| {
+                    ui.label("Theme");
+                    egui::ComboBox::from_id_salt("theme")
+                        .selected_text(self.settings.appearance.theme.label())
+                        .show_ui(ui, |ui| {
+                            for theme in Theme::ALL {
+                                ui.selectable_value(
+                                    &mut self.settings.appearance.theme,
+                                    theme,
+                                    theme.label(),
+                                );
+                            }
+                        });
+                });
+                if ui
+                    .checkbox(
+                        &mut self.settings.appearance.collapse_membership,
+                        "Collapse joins and leaves",
+                    )
+                    .on_hover_text("Show each run of membership changes as one line")
+                    .changed()
+                {
+                    self.settings.save();
+                }
+                if self.settings.appearance.theme != before {
+                    ctx.set_theme(self.settings.appearance.theme.preference());
+                    self.settings.save();
+                }
+                ui.small(
+                    "Settings follow you to your other devices; audio settings are kept per \
+                     computer.",
+                );
+
+                ui.add_space(12.0);
+                ui.heading("Notifications");
+                let before = self.settings.notifications.clone();
+                ui.checkbox(
+                    &mut self.settings.notifications.desktop,
+                    "Desktop notifications for mentions, direct messages and calls",
+                );
+                ui.add_enabled(
+                    self.settings.notifications.desktop,
+                    egui::Checkbox::new(
+                        &mut self.settings.notifications.do_not_disturb,
+                        "Do not disturb",
+                    ),
+                );
+                if self.settings.notifications != before {
+                    self.settings.save();
+                }
+
+                ui.add_space(12.0);
+                ui.heading("Privacy");
+                ui.small("Hiding your read receipts or typing also hides everyone else's.");
+                ui.add_space(6.0);
+                let mut changed = false;
+                changed |= ui
+                    .checkbox(
+                        &mut self.settings.privacy.read_receipts,
+                        "Send read receipts",
+                    )
+                    .changed();
+                changed |= ui
+                    .checkbox(
+                        &mut self.settings.privacy.typing,
+                        "Send typing notifications",
+                    )
+                    .changed();
+                changed |= ui
+                    .checkbox(
+                        &mut self.settings.privacy.url_previews,
+                        "Show link previews in unencrypted rooms",
+                    )
+                    .on_hover_text(
+                        "Previews are fetched by your homeserver, which sees the links. \
+                         Turn them on for an encrypted room below.",
+                    )
+                    .changed();
+
+                if let Some(room) = self.selected_room.and_then(|i| self.rooms.get(i)) {
+                    ui.add_space(6.0);
+                    ui.label(format!("In {}:", room.name));
+                    let overrides = self.settings.privacy.room_mut(&room.id);
+                    egui::Grid::new("room_privacy")
+                        .num_columns(2)
+                        .spacing([12.0, 6.0])
+                        .show(ui, |ui| {
+                            for (label, value) in [
+                                ("Read receipts", &mut overrides.read_receipts),
+                                ("Typing notifications", &mut overrides.typing),
+                                ("Link previews", &mut overrides.url_previews),
+                            ] {
+                                ui.label(label);
+                                egui::ComboBox::from_id_salt(label)
+                                    .selected_text(match value {
+                                        None => "Default",
+                                        Some(true) => "On",
+                                        Some(false) => "Off",
+                                    })
+                                    .show_ui(ui, |ui| {
+                                        changed |=
+                                            ui.selectable_value(value, None, "Default").changed();
+                                        changed |=
+                                            ui.selectable_value(value, Some(true), "On").changed();
+                                        changed |= ui
+                                            .selectable_value(value, Some(false), "Off")
+                                            .changed();
+                                    });
+                                ui.end_row();
+                            }
+                        });
+                }
+                self.settings.privacy.prune();
+                if changed {
+                    self.settings.save();
+                }
+
+                ui.add_space(12.0);
+                ui.heading("Encryption");
+                ui.add_space(6.0);
+                let before = self.settings.encryption.clone();
+                ui.checkbox(
+                    &mut self.settings.encryption.verified_devices_only,
+                    "Never send encrypted messages to unverified sessions",
+                );
+                ui.small("Applies from the next sign-in.");
+                if self.settings.encryption != before {
+                    self.settings.save();
+                }
+
+                ui.add_space(12.0);
+                egui::CollapsingHeader::new("Advanced: sync").show(ui, |ui| {
+                    ui.small("Leaving things out of sync saves bandwidth on large accounts.");
+                    let before = self.settings.sync.clone();
+                    let sync = &mut self.settings.sync;
+                    ui.checkbox(&mut sync.presence, "Presence (online status)");
+                    ui.checkbox(&mut sync.typing, "Typing notifications");
+                    ui.checkbox(&mut sync.receipts, "Read receipts");
+                    ui.horizontal(|ui| {
+                        ui.label("Messages per room in each sync");
+                        ui.add(egui::DragValue::new(&mut sync.timeline_limit).range(1..=100));
+                    });
+                    ui.label("Leave out these event types (comma-separated, * matches anything):");
+                    let excluded = self
+                        .excluded_types_draft
+                        .get_or_insert_with(|| sync.excluded_types.join(", "));
+                    if ui.text_edit_singleline(excluded).lost_focus() {
+                        sync.excluded_types = excluded
+                            .split(',')
+                            .map(str::trim)
+                            .filter(|t| !t.is_empty())
+                            .map(str::to_owned)
+                            .collect();
+                        self.excluded_types_draft = None;
+                    }
+                    if self.settings.sync != before {
+                        self.settings.save();
+                        let _ = self
+                            .cmd_tx
+                            .send(AppCommand::SetSyncFilter(self.settings.sync.clone()));
+                    }
+                });
+
+                ui.add_space(12.0);
+                ui.heading("Voice");
+                ui.add_space(6.0);
+                if self.audio_devices.is_none() {
+                    self.audio_devices = Some(AudioDevices::default());
+                    let _ = self.cmd_tx.send(AppCommand::ListAudioDevices);
+                }
+                let devices = self.audio_devices.clone().unwrap_or_default();
+                let mut devices_changed = false;
+                egui::Grid::new("audio_devices")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label("Microphone");
+                        devices_changed |= device_picker(
+                            ui,
+                            "input_device",
+                            &mut self.settings.voice.input_device,
+                            &devices.inputs,
+                            devices.default_input.as_deref(),
+                        );
+                        ui.end_row();
+                        ui.label("Speakers");
+                        devices_changed |= device_picker(
+                            ui,
+                            "output_device",
+                            &mut self.settings.voice.output_device,
+                            &devices.outputs,
+                            devices.default_output.as_deref(),
+                        );
+                        ui.end_row();
+                    });
+                if ui.small_button("Refresh devices").clicked() {
+                    let _ = self.cmd_tx.send(AppCommand::ListAudioDevices);
+                }
+                let mode_before = self.settings.voice.input_mode;
+                ui.horizontal(|ui| {
+                    ui.label("Microphone opens with");
+                    for mode in InputMode::ALL {
+                        ui.radio_value(&mut self.settings.voice.input_mode, mode, mode.label());
+                    }
+                });
+                if self.settings.voice.input_mode == InputMode::PushToTalk
+                    && self
+                        .settings
+                        .keybinds
+                        .get(VoiceAction::PushToTalk)
+                        .is_none()
+                {
+                    ui.weak("Bind a push-to-talk key under Voice keybinds to use it.");
+                }
+                if self.settings.voice.input_mode != mode_before {
+                    self.settings.save();
+                    // Switching mid-call: push to talk starts closed, voice
+                    // activity open.
+                    if self.in_voice && self.voice_can_publish && !self.voice_deafened {
+                        self.set_voice_muted(self.push_to_talk());
+                    }
+                }
+                if devices_changed {
+                    self.settings.save();
+                    let _ = self
+                        .cmd_tx
+                        .send(AppCommand::SetAudioDevices(self.device_choice()));
+                }
+                let gain =
+                    egui::Slider::new(&mut self.settings.voice.input_gain_db, INPUT_GAIN_RANGE_DB)
+                        .suffix(" dB")
+                        .text("Microphone gain");
+                let gain_changed = ui.add(gain).changed();
+                let auto_gain = ui
+                    .checkbox(
+                        &mut self.settings.voice.auto_gain,
+                        "Adjust microphone level automatically",
+                    )
+                    .on_hover_text(
+                        "Turns a quiet mic up and a loud one down; the gain above applies on top",
+                    );
+                if gain_changed || auto_gain.changed() {
+                    self.settings.save();
+                    let _ = self
+                        .cmd_tx
+                        .send(AppCommand::SetVoiceTuning(self.voice_tuning()));
+                }
+                if let Some(meter) = &self.input_meter {
+                    let now = ctx.input(|i| i.time);
+                    let (peak, clipped) = meter.take();
+                    let (level, clip_until) = &mut self.input_level;
+                    *level = peak.max(*level * 0.9);
+                    if clipped {
+                        *clip_until = now + 1.0;
+                    }
+                    let clipping = now < *clip_until;
+                    let bar = egui::ProgressBar::new(*level)
+                        .desired_width(240.0)
+                        .fill(if clipping {
+                            egui::Color32::RED
+                        } else {
+                            egui::Color32::GREEN
+                        })
+                        .text(if clipping {
+                            "Clipping — lower the gain"
+                        } else {
+                            "Input level"
+                        });
+                    ui.add(bar);
+                    ctx.request_repaint();
+                } else {
+                    ui.horizontal(|ui| {
+                        let testing = self.mic_test.is_some();
+                        if ui
+                            .button(if testing {
+                                "Stop test"
+                            } else {
+                                "Test microphone"
+                            })
+                            .clicked()
+                        {
+                            self.mic_test = (!testing).then(Level::default);
+                            let _ = self.cmd_tx.send(AppCommand::TestMic { on: !testing });
+                        }
+                        if let Some(level) = self.mic_test {
+                            let bar = egui::ProgressBar::new(level.meter())
+                                .desired_width(240.0)
+                                .fill(if level.peak >= 1.0 {
+                                    egui::Color32::RED
+                                } else {
+                                    egui::Color32::GREEN
+                                })
+                                .text(if level.peak >= 1.0 {
+                                    "Clipping — lower the gain"
+                                } else {
+                                    "Input level"
+                                });
+                            ui.add(bar);
+                        }
+                    });
+                }
+                if ui
+                    .button("Play test tone")
+                    .on_hover_text("A one-second tone on your speakers")
+                    .clicked()
+                {
+                    let _ = self.cmd_tx.send(AppCommand::PlayTestTone);
+                }
+                if ui
+                    .checkbox(
+                        &mut self.settings.voice.echo_cancellation,
+                        "Cancel echo from speakers",
+                    )
+                    .on_hover_text(
+                        "Keeps others from hearing themselves through your mic. Not \
+                         needed with headphones.",
+                    )
+                    .changed()
+                {
+                    self.settings.save();
+                    let _ = self
+                        .cmd_tx
+                        .send(AppCommand::SetVoiceTuning(self.voice_tuning()));
+                }
+                ui.add_space(6.0);
+                let duck =
+                    egui::Slider::new(&mut self.settings.voice.priority_duck_db, -40.0..=0.0)
+                        .suffix(" dB")
+                        .text("Others while a priority speaker talks");
+                let duck_changed = ui.add(duck).changed();
+                let normalize = ui
+                    .checkbox(
+                        &mut self.settings.voice.normalize_levels,
+                        "Even out loudness between participants",
+                    )
+                    .on_hover_text("Slowly turns quiet participants up and loud ones down");
+                if duck_changed || normalize.changed() {
+                    self.settings.save();
+                    let _ = self
+                        .cmd_tx
+                        .send(AppCommand::SetVoiceTuning(self.voice_tuning()));
+                }
+                if ui
+                    .checkbox(
+                        &mut self.settings.voice.post_call_summary,
+                        "Post a summary when the last person leaves a call",
+                    )
+                    .changed()
+                {
+                    self.settings.save();
+                }
+
+                ui.add_space(12.0);
+                ui.heading("Proxy");
+                ui.small(
+                    "Applies to new connections; sign out and back in to reconnect to your \
+                     homeserver.",
+                );
+                ui.add_space(6.0);
+                let before = self.settings.proxy.clone();
+                egui::ComboBox::from_id_salt("proxy_mode")
+                    .selected_text(self.settings.proxy.mode.label())
+                    .show_ui(ui, |ui| {
+                        for mode in ProxyMode::ALL {
+                            ui.selectable_value(&mut self.settings.proxy.mode, mode, mode.label());
+                        }
+                    });
+                if self.settings.proxy.mode == ProxyMode::Manual {
+                    egui::Grid::new("proxy")
+                        .num_columns(2)
+                        .spacing([12.0, 4.0])
+                        .show(ui, |ui| {
+                            ui.label("Proxy URL");
+                            ui.add(
+                                egui::TextEdit::singleline(&mut self.settings.proxy.url)
+                                    .hint_text("http://proxy:3128 or socks5h://host:1080"),
+                            );
+                            ui.end_row();
+                            ui.label("Bypass for");
+                            ui.add(
+                                egui::TextEdit::singleline(&mut self.settings.proxy.no_proxy)
+                                    .hint_text("localhost, .corp"),
+                            );
+                            ui.end_row();
+                        });
+                }
+                let valid = self.settings.proxy.validate();
+                if let Err(e) = &valid {
+                    ui.colored_label(ui.visuals().error_fg_color, e);
+                }
+                if self.settings.proxy != before && valid.is_ok() {
+                    self.settings.save();
+                    spoke_core::proxy::set(self.settings.proxy.clone());
+                }
+                if self.settings.proxy.mode != ProxyMode::Direct {
+                    ui.weak(
+                        "Voice signalling connects directly; voice itself can use TURN over \
+                         TCP or TLS.",
+                    );
+                }
+
+                ui.add_space(12.0);
+                ui.heading("Voice connectivity");
+                ui.small("Applies from the next time you join voice.");
+                ui.add_space(6.0);
+                let before = self.settings.ice.clone();
+                ui.horizontal(|ui| {
+                    ui.label("Relay (TURN):");
+                    egui::ComboBox::from_id_salt("relay_policy")
+                        .selected_text(self.settings.ice.relay.label())
+                        .show_ui(ui, |ui| {
+                            for policy in RelayPolicy::ALL {
+                                ui.selectable_value(
+                                    &mut self.settings.ice.relay,
+                                    policy,
+                                    policy.label(),
+                                );
+                            }
+                        });
+                });
+                if self.settings.ice.relay == RelayPolicy::Always && self.turn_servers.is_empty() {
+                    ui.colored_label(
+                        ui.visuals().warn_fg_color,
+                        "The voice server hasn't offered any TURN servers, so calls connect \
+                         directly.",
+                    );
+                }
+                ui.label("STUN servers:");
+                let mut remove = None;
+                for (i, url) in self.settings.ice.stun_servers.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.monospace(url);
+                        if ui.small_button("Remove").clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = remove {
+                    self.settings.ice.stun_servers.remove(i);
+                }
+                if self.settings.ice.stun_servers.is_empty() {
+                    ui.weak("None — the voice server's defaults are used.");
+                }
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.stun_input)
+                            .hint_text("stun:host:3478"),
+                    );
+                    let url = self.stun_input.trim();
+                    let valid = spoke_core::voice::ice::stun_address(url).is_some();
+                    if ui.add_enabled(valid, egui::Button::new("Add")).clicked() {
+                        self.settings.ice.stun_servers.push(url.to_owned());
+                        self.stun_input.clear();
+                    }
+                });
+                ui.label("TURN servers (from the voice server):");
+                if self.turn_servers.is_empty() {
+                    ui.weak("None known yet — join voice once to fetch them.");
+                }
+                for server in &self.turn_servers {
+                    ui.monospace(&server.urls);
+                }
+                if self.settings.ice != before {
+                    self.settings.save();
+                    self.ice_test = None;
+                }
+
+                let testing = self.ice_test.as_ref().is_some_and(Vec::is_empty);
+                let nothing =
+                    self.settings.ice.stun_servers.is_empty() && self.turn_servers.is_empty();
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(!testing && !nothing, egui::Button::new("Test servers"))
+                        .clicked()
+                    {
+                        self.ice_test = Some(Vec::new());
+                        let _ = self.cmd_tx.send(AppCommand::TestIceServers {
+                            ice: self.settings.ice.clone(),
+                            turn_servers: self.turn_servers.clone(),
+                        });
+                    }
+                    if testing {
+                        ui.spinner();
+                    }
+                });
+                if let Some(results) = self.ice_test.as_ref().filter(|r| !r.is_empty()) {
+                    egui::Grid::new("ice_test")
+                        .num_columns(2)
+                        .spacing([12.0, 4.0])
+                        .show(ui, |ui| {
+                            for (server, probe) in results {
+                                ui.monospace(server);
+                                probe_ui(ui, probe);
+                                ui.end_row();
+                            }
+                        });
+                }
+
+                ui.add_space(12.0);
+                ui.heading("Logging");
+                ui.small(
+                    "Change what gets logged, e.g. matrix_sdk=trace to capture a sync \
+                     problem. Lasts until restart.",
+                );
+                ui.add_space(6.0);
+                ui.horizontal(|ui| {
+                    let resp = ui.add(
+                        egui::TextEdit::singleline(&mut self.log_filter_input)
+                            .font(egui::TextStyle::Monospace)
+                            .desired_width(260.0),
+                    );
+                    let enter = resp.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    let changed = self.log_filter_input.trim() != self.log_filter.current();
+                    if ui
+                        .add_enabled(changed, egui::Button::new("Apply"))
+                        .clicked()
+                        || (changed && enter)
+                    {
+                        self.log_filter_error =
+                            self.log_filter.set(self.log_filter_input.trim()).err();
+                    }
+                    let modified = self.log_filter.current() != self.log_filter.initial();
+                    if ui
+                        .add_enabled(modified, egui::Button::new("Reset"))
+                        .clicked()
+                    {
+                        self.log_filter_error = self.log_filter.reset().err();
+                        self.log_filter_input = self.log_filter.current();
+                    }
+                });
+                if let Some(e) = &self.log_filter_error {
+                    ui.colored_label(egui::Color32::RED, e);
+                }
+
+                ui.add_space(12.0);
+                ui.heading("Developer");
+                if ui
+                    .checkbox(&mut self.settings.developer.enabled, "Developer mode")
+                    .on_hover_text(
+                        "Adds a room state viewer and a custom event sender to the \
+                         moderation menu",
+                    )
+                    .changed()
+                {
+                    self.settings.save();
+                }
+
+                ui.add_space(12.0);
+                ui.heading("Backup");
+                ui.small(
+                    "Export all Spoke settings to a file to set up another machine the same way.",
+                );
+                ui.add_space(6.0);
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.settings_file).desired_width(220.0),
+                    );
+                    let path = std::path::PathBuf::from(self.settings_file.trim());
+                    if ui.button("Export").clicked() {
+                        self.settings_file_status = Some(
+                            self.settings
+                                .export(&path)
+                                .map(|()| format!("Exported to {}", path.display())),
+                        );
+                    }
+                    if ui.button("Import").clicked() {
+                        self.settings_file_status = Some(Settings::import(&path).map(|settings| {
+                            // The export may come from another machine; keep ours.
+                            let machine_id = std::mem::take(&mut self.settings.machine_id);
+                            self.settings = Settings {
+                                machine_id,
+                                ..settings
+                            };
+                            self.settings.save();
+                            ctx.set_theme(self.settings.appearance.theme.preference());
+                            self.capturing_binding = None;
+                            self.binding_conflict = None;
+                            format!("Imported from {}", path.display())
+                        }));
+                    }
+                });
+                match &self.settings_file_status {
+                    Some(Ok(msg)) => {
+                        ui.weak(msg);
+                    }
+                    Some(Err(e)) => {
+                        ui.colored_label(egui::Color32::RED, e);
+                    }
+                    None => {}
+                }
+            });
+        if !open {
+            self.ui.close(Dialog::Settings);
+            self.capturing_binding = None;
+            self.binding_conflict = None;
+        }
+    }
+
+    fn show_voice_diagnostics(&mut self, ctx: &egui::Context) {
+        let mut open = true;
+        egui::Window::new("Voice diagnostics")
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let Some(report) = &self.voice_preflight else {
+                    ui.label("No connectivity check has run yet. Join a voice channel first.");
+                    return;
+                };
+                egui::Grid::new("preflight")
+                    .num_columns(2)
+                    .spacing([12.0, 6.0])
+                    .show(ui, |ui| {
+                        for (name, probe) in [
+                            ("Voice server", &report.signal),
+                            ("UDP", &report.udp),
+                            ("TURN relay (UDP)", &report.turn_udp),
+                            ("TURN relay (TCP)", &report.turn_tcp),
+                        ] {
+                            ui.label(name);
+                            probe_ui(ui, probe);
+                            ui.end_row();
+                        }
+                    });
+                let guidance = report.guidance();
+                if !guidance.is_empty() {
+                    ui.separator();
+                    for line in guidance {
+                        ui.label(format!("• {line}"));
+                    }
+                }
+                if self.in_voice {
+                    ui.separator();
+                    ui.label(format!(
+                        "Remote audio streams playing: {}",
+                        self.voice_pipelines
+                    ));
+                    ui.horizontal(|ui| {
+                        let can_measure = self.voice_can_publish
+                            && self.voice_pipelines > 0
+                            && !self.voice_latency_pending;
+                        let button = ui
+                            .add_enabled(can_measure, egui::Button::new("Measure latency"))
+                            .on_hover_text(
+                                "Bounces a short tone off someone in the call. \
+                                 They'll hear a beep.",
+                            );
+                        if button.clicked() {
+                            self.voice_latency_pending = true;
+                            let _ = self.cmd_tx.send(AppCommand::MeasureVoiceLatency);
+                        }
+                        if self.voice_latency_pending {
+                            ui.spinner();
+                        }
+                    });
+                    match &self.voice_latency {
+                        Some(Ok(report)) => latency_ui(ui, report),
+                        Some(Err(e)) => {
+                            ui.colored_label(egui::Color32::RED, format!("✖ {e}"));
+                        }
+                        None => {}
+                    }
+                }
+            });
+        if !open {
+            self.ui.close(Dialog::VoiceDiagnostics);
+        }
+    }
+
+    /// Confirm a kick, ban or unban, with an optional reason.
+    fn show_moderation_dialog(&mut self, ctx: &egui::Context) {
+        let Some(draft) = self.moderation.as_mut() else {
+            self.ui.close(Dialog::Moderation);
+            return;
+        };
+        let title = match draft.action {
+            ModerationAction::Kick => "Kick User",
+            ModerationAction::Ban => "Ban User",
+            ModerationAction::Unban => "Unban User",
+        };
+        let mut open = true;
+        let mut confirmed = false;
+        let mut cancelled = false;
+        egui::Window::new(title)
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.label("Matrix ID:");
+                ui.text_edit_singleline(&mut draft.user_id);
+                ui.label("Reason (optional):");
+                ui.text_edit_singleline(&mut draft.reason);
+                ui.horizontal(|ui| {
+                    let label = match draft.action {
+                        ModerationAction::Kick => "Kick",
+                        ModerationAction::Ban => "Ban",
+                        ModerationAction::Unban => "Unban",
+                    };
+                    if ui
+                        .add_enabled(!draft.user_id.is_empty(), egui::Button::new(label))
+                        .clicked()
+                    {
+                        confirmed = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        cancelled = true;
+                    }
+                });
+            });
+
+        if confirmed {
+            let room_id = draft.room_id.clone();
+            let user_id = draft.user_id.trim().to_owned();
+            let reason = Some(draft.reason.trim().to_owned()).filter(|r| !r.is_empty());
+            let cmd = match draft.action {
+                ModerationAction::Kick => AppCommand::KickUser {
+                    room_id,
+                    user_id,
+                    reason,
+                },
+                ModerationAction::Ban => AppCommand::BanUser {
+                    room_id,
+                    user_id,
+                    reason,
+                },
+                ModerationAction::Unban => AppCommand::UnbanUser {
+                    room_id,
+                    user_id,
+                    reason,
+                },
+            };
+            let _ = self.cmd_tx.send(cmd);
+        }
+        if confirmed || cancelled || !open {
+            self.moderation = None;
+            self.ui.close(Dialog::Moderation);
+        }
+    }
+
+    fn open_report(&mut self, room_id: String, event_id: Option<String>) {
+        self.report = Some(ReportDraft {
+            room_id,
+            event_id,
+            reason: String::new(),
+            severity: 50,
+        });
+        self.ui.open(Dialog::Report);
+    }
+
+    fn show_report_dialog(&mut self, ctx: &egui::Context) {
+        let Some(draft) = self.report.as_mut() else {
+            self.ui.close(Dialog::Report);
+            return;
+        };
+        let title = if draft.event_id.is_some() {
+            "Report Message"
+        } else {
+            "Report Room"
+        };
+        let mut open = true;
+        let mut confirmed = false;
+        let mut cancelled = false;
+        egui::Window::new(title)
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.label("Reports go to your homeserver's admins, not the room's moderators.");
+                ui.label("Reason:");
+                ui.text_edit_multiline(&mut draft.reason);
+                if draft.event_id.is_some() {
+                    ui.add(egui::Slider::new(&mut draft.severity, 0..=100).text("How offensive"));
+                }
+                ui.horizontal(|ui| {
+                    // Room reports need a reason.
+                    let ready = draft.event_id.is_some() || !draft.reason.trim().is_empty();
+                    if ui.add_enabled(ready, egui::Button::new("Report")).clicked() {
+                        confirmed = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        cancelled = true;
+                    }
+                });
+            });
+
+        if confirmed {
+            let room_id = draft.room_id.clone();
+            let reason = draft.reason.trim().to_owned();
+            let cmd = match draft.event_id.clone() {
+                Some(event_id) => AppCommand::ReportEvent {
+                    room_id,
+                    event_id,
+                    score: Some(-draft.severity),
+                    reason: Some(reason).filter(|r| !r.is_empty()),
+                },
+                None => AppCommand::ReportRoom { room_id, reason },
+            };
+            let _ = self.cmd_tx.send(cmd);
+        }
+        if confirmed || cancelled || !open {
+            self.report = None;
+            self.ui.close(Dialog::Report);
+        }
+    }
+
+    /// Show an OS notification for `room_id` unless the window has focus or
+    /// notifications are off.
+    fn notify_desktop(&self, ctx: &egui::Context, room_id: &str, body: String) {
+        let prefs = &self.settings.notifications;
+        if !prefs.desktop || prefs.do_not_disturb || ctx.input(|i| i.focused) {
+            return;
+        }
+        let summary = self
+            .rooms
+            .iter()
+            .find(|r| r.id == room_id)
+            .map_or(room_id, |r| &r.name)
+            .to_owned();
+        self.notifier.show(ctx, summary, body, room_id.to_owned());
+    }
+
+    /// Show the unread totals in the window title: `Spoke (5)`, or
+    /// `Spoke (@2 · 5)` when some of them mention us.
+    fn update_window_title(&mut self, ctx: &egui::Context) {
+        let notifications: u32 = self.unread.values().map(|u| u.notifications).sum();
+        let mentions: u32 = self.unread.values().map(|u| u.mentions).sum();
+        let title = match (notifications, mentions) {
+            (0, _) => "Spoke".to_owned(),
+            (n, 0) => format!("Spoke ({n})"),
+            (n, m) => format!("Spoke (@{m} · {n})"),
+        };
+        if title != self.window_title {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.clone()));
+            self.window_title = title;
+        }
+    }
+
+    /// Audio levels for voice sessions, from settings.
+    fn voice_tuning(&self) -> VoiceTuning {
+        let voice = &self.settings.voice;
+        VoiceTuning {
+            duck_db: voice.priority_duck_db,
+            input_gain_db: voice.input_gain_db,
+            auto_gain: voice.auto_gain,
+            normalize: voice.normalize_levels,
+            echo_cancellation: voice.echo_cancellation,
+        }
+    }
+
+    fn device_choice(&self) -> DeviceChoice {
+        let voice = &self.settings.voice;
+        DeviceChoice {
+            input: voice.input_device.clone(),
+            output: voice.output_device.clone(),
+        }
+    }
+
+    /// A message in the selected room's log.
+    fn find_message(&self, event_id: &str) -> Option<&TimelineItem> {
+        let room_id = self
+            .selected_room
+            .and_then(|i| self.rooms.get(i))
+            .map(|r| &r.id)?;
+        self.messages
+            .get(room_id)?
+            .iter()
+            .find(|m| m.event_id.as_deref() == Some(event_id))
+    }
+
+    /// Drop the composer's reply/thread/edit target. Abandoning an edit also
+    /// clears the text it loaded.
+    fn cancel_relation(&mut self) {
+        if let Some(MessageRelation::Edit { .. }) = self.composer.relation.take() {
+            self.composer.text.clear();
+        }
+    }
+
+    /// Point the composer at `relation` and give it focus.
+    fn compose_with(&mut self, ctx: &egui::Context, relation: MessageRelation) {
+        if let Some(MessageRelation::Edit { .. }) = &self.composer.relation {
+            self.composer.text.clear();
+        }
+        self.composer.relation = Some(relation);
+        ctx.memory_mut(|m| m.request_focus(composer_id()));
+    }
+
+    /// Whether the signed-in account is set to appear offline.
+    fn appears_offline(&self) -> bool {
+        self.settings.privacy.invisible(&self.own_user_id)
+    }
+
+    /// Our avatar, opening a menu to appear offline or edit the profile.
+    fn account_menu_ui(&mut self, ui: &mut egui::Ui) {
+        if !self.logged_in {
+            return;
+        }
+        let offline = self.appears_offline();
+        let icon = if offline { "○" } else { "●" };
+        let menu = ui.menu_button(icon, |ui| {
+            let name = self.display_name(&self.own_user_id).to_owned();
+            ui.strong(name);
+            ui.weak(&self.own_user_id);
+            ui.separator();
+            let mut appear_offline = offline;
+            if ui
+                .checkbox(&mut appear_offline, "Appear offline")
+                .on_hover_text(
+                    "Others see you as offline; typing and read receipts aren't sent. \
+                     You still see theirs.",
+                )
+                .changed()
+            {
+                self.settings
+                    .privacy
+                    .set_invisible(&self.own_user_id, appear_offline);
+                self.settings.save();
+                let _ = self.cmd_tx.send(AppCommand::SetInvisible(appear_offline));
+                if appear_offline {
+                    if let Some(room_id) = self.sent_typing.take() {
+                        let _ = self.cmd_tx.send(AppCommand::SetTyping {
+                            room_id,
+                            typing: false,
+                        });
+                    }
+                }
+            }
+            if ui.button("Profile and settings…").clicked() {
+                self.ui.open(Dialog::Settings);
+                ui.close_menu();
+            }
+        });
+        menu.response.on_hover_text(if offline {
+            "Appearing offline"
+        } else {
+            "Online"
+        });
+    }
+
+    /// What's above the oldest loaded message: the start of the room, older
+    /// history loading, or why it couldn't be.
+    fn history_start_ui(&mut self, ui: &mut egui::Ui, room_id: &str) {
+        match self.history_loading.get(room_id) {
+            Some(Some(error)) => {
+                let mut retry = false;
+                ui.horizontal(|ui| {
+                    ui.weak("Couldn't load older messages").on_hover_text(error);
+                    retry = ui.small_button("Retry").clicked();
+                });
+                if retry {
+                    self.history_loading.remove(room_id);
+                }
+            }
+            Some(None) => {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.weak("Loading older messages…");
+                });
+            }
+            None if matches!(self.history_tokens.get(room_id), Some(None)) => {
+                ui.weak("This is the start of the room.");
+            }
+            None => {}
+        }
+    }
+
+    /// Keep the same messages in view when older ones were prepended, and
+    /// fetch more history once the timeline is scrolled near its top.
+    fn scrollback(&mut self, ctx: &egui::Context, room_id: &str, content_height: f32, offset: f32) {
+        if let Some((height, before)) = self.scroll_keep.take() {
+            let grown = content_height - height;
+            if grown > 0.0 {
+                self.scroll_to = Some(before + grown);
+                ctx.request_repaint();
+            }
+        }
+        self.timeline_metrics = (content_height, offset);
+        if offset > SCROLLBACK_TRIGGER_POINTS || self.history_loading.contains_key(room_id) {
+            return;
+        }
+        let Some(Some(from)) = self.history_tokens.get(room_id).cloned() else {
+            return;
+        };
+        self.history_loading.insert(room_id.to_owned(), None);
+        let _ = self.cmd_tx.send(AppCommand::FetchMoreHistory {
+            room_id: room_id.to_owned(),
+            from,
+        });
+    }
+
+    /// Carry out a hover-toolbar or keyboard action on one message.
+    fn message_action(
+        &mut self,
+        ctx: &egui::Context,
+        room_id: &str,
+        event_id: &str,
+        action: MessageAction,
+    ) {
+        let Some(m) = self.find_message(event_id) else {
+            return;
+        };
+        let (sender, body) = (m.sender.clone(), m.body.clone());
+        match action {
+            MessageAction::React(key) => {
+                let on = self
+                    .reactions
+                    .get(room_id)
+                    .and_then(|r| r.get(event_id))
+                    .map(Vec::as_slice)
+                    .unwrap_or(&[]);
+                let mine = group_reactions(on, &self.own_user_id)
+                    .into_iter()
+                    .find(|(k, ..)| *k == key)
+                    .and_then(|(.., mine)| mine.map(str::to_owned));
+                let cmd = match mine {
+                    Some(reaction_id) => AppCommand::RemoveReaction {
+                        room_id: room_id.to_owned(),
+                        reaction_id,
+                    },
+                    None => AppCommand::SendReaction {
+                        room_id: room_id.to_owned(),
+                        event_id: event_id.to_owned(),
+                        key,
+                    },
+                };
+                let _ = self.cmd_tx.send(cmd);
+            }
+            MessageAction::Reply => self.compose_with(
+                ctx,
+                MessageRelation::Reply {
+                    event_id: event_id.to_owned(),
+                },
+            ),
+            MessageAction::Thread => {
+                self.open_thread(room_id, event_id.to_owned());
+                self.compose_with(
+                    ctx,
+                    MessageRelation::Thread {
+                        root: event_id.to_owned(),
+                    },
+                );
+            }
+            MessageAction::Edit if sender == self.own_user_id => {
+                self.compose_with(
+                    ctx,
+                    MessageRelation::Edit {
+                        event_id: event_id.to_owned(),
+                    },
+                );
+                self.composer.text = body;
+                composer::set_cursor(ctx, composer_id(), self.composer.text.chars().count());
+            }
+            MessageAction::Edit => {}
+            MessageAction::CopyText => ctx.copy_text(body),
+            MessageAction::CopyLink => {
+                ctx.copy_text(format!("https://matrix.to/#/{room_id}/{event_id}"))
+            }
+            MessageAction::MessageSender => {
+                let _ = self
+                    .cmd_tx
+                    .send(AppCommand::StartDirectMessage { mxid: sender });
+            }
+            MessageAction::Select => {
+                self.pick_message(room_id.to_owned(), event_id.to_owned(), Pick::Add)
+            }
+            MessageAction::Report => {
+                self.open_report(room_id.to_owned(), Some(event_id.to_owned()))
+            }
+            MessageAction::SpinOff => self.open_spin_off(room_id, event_id),
+            MessageAction::TogglePin => {
+                let pinned = self
+                    .pins
+                    .get(room_id)
+                    .is_some_and(|p| p.messages.iter().any(|pin| pin.event_id == event_id));
+                let _ = self.cmd_tx.send(AppCommand::SetPinned {
+                    room_id: room_id.to_owned(),
+                    event_id: event_id.to_owned(),
+                    pinned: !pinned,
+                });
+            }
+        }
+    }
+
+    /// Start spinning off the discussion around `event_id`: the whole thread
+    /// when it's a thread reply.
+    fn open_spin_off(&mut self, room_id: &str, event_id: &str) {
+        let Some(m) = self.find_message(event_id) else {
+            return;
+        };
+        let origin = m
+            .thread_root
+            .as_deref()
+            .and_then(|root| self.find_message(root))
+            .unwrap_or(m);
+        let Some(origin_id) = origin.event_id.clone() else {
+            return;
+        };
+        let mut name: String = origin
+            .body
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .take(SPIN_OFF_NAME_CHARS)
+            .collect();
+        if name.trim().is_empty() {
+            name = "Spin-off".to_owned();
+        }
+        self.spin_off = Some(SpinOffDraft {
+            room_id: room_id.to_owned(),
+            event_id: origin_id,
+            sender: origin.sender.clone(),
+            quote: origin.body.clone(),
+            name,
+        });
+        self.ui.open(Dialog::SpinOff);
+    }
+
+    fn show_spin_off_dialog(&mut self, ctx: &egui::Context) {
+        let Some(draft) = self.spin_off.as_mut() else {
+            self.ui.close(Dialog::SpinOff);
+            return;
+        };
+        let mut open = true;
+        let mut confirmed = false;
+        let mut cancelled = false;
+        egui::Window::new("Spin Off Discussion")
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.label(
+                    "Creates a new room quoting this message and invites everyone in its thread.",
+                );
+                ui.weak(quote_line(&draft.sender, &draft.quote));
+                ui.add_space(6.0);
+                ui.horizontal(|ui| {
+                    ui.label("Room name:");
+                    ui.text_edit_singleline(&mut draft.name);
+                });
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(
+                            !draft.name.trim().is_empty(),
+                            egui::Button::new("Create room"),
+                        )
+                        .clicked()
+                    {
+                        confirmed = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        cancelled = true;
+                    }
+                });
+            });
+
+        if confirmed {
+            let _ = self.cmd_tx.send(AppCommand::SpinOff {
+                room_id: draft.room_id.clone(),
+                event_id: draft.event_id.clone(),
+                sender: draft.sender.clone(),
+                quote: draft.quote.clone(),
+                name: draft.name.trim().to_owned(),
+            });
+        }
+        if confirmed || cancelled || !open {
+            self.spin_off = None;
+            self.ui.close(Dialog::SpinOff);
+        }
+    }
+
+    /// Ask for the thumbnails of stickers and emotes not yet requested.
+    fn request_pack_images(&mut self, mxcs: HashSet<String>) {
+        for mxc in mxcs {
+            if !self.pack_images.contains_key(&mxc) {
+                self.pack_images.insert(mxc.clone(), None);
+                let _ = self.cmd_tx.send(AppCommand::FetchPackImage { mxc });
+            }
+        }
+    }
+
+    /// The composer's sticker menu: each pack's stickers as a grid of
+    /// buttons. Packs load the first time the menu opens in a room.
+    fn sticker_picker_ui(&mut self, ui: &mut egui::Ui, room_id: &str) {
+        if self.image_packs_requested.insert(room_id.to_owned()) {
+            let _ = self.cmd_tx.send(AppCommand::FetchImagePacks {
+                room_id: room_id.to_owned(),
+            });
+        }
+        let Some(packs) = self.image_packs.get(room_id) else {
+            ui.spinner();
+            return;
+        };
+        let mut chosen = None;
+        let mut wanted = HashSet::new();
+        let stickers = packs.iter().filter(|p| p.images.iter().any(|i| i.sticker));
+        let mut any = false;
+        egui::ScrollArea::vertical()
+            .max_height(320.0)
+            .show(ui, |ui| {
+                for pack in stickers {
+                    any = true;
+                    ui.weak(&pack.name);
+                    ui.horizontal_wrapped(|ui| {
+                        ui.set_max_width(
+                            6.0 * (PICKER_STICKER_POINTS + ui.spacing().item_spacing.x),
+                        );
+                        for image in pack.images.iter().filter(|i| i.sticker) {
+                            let hint = image.body.as_deref().unwrap_or(&image.shortcode);
+                            let resp = match self.pack_images.get(&image.url) {
+                                Some(Some(bytes)) => ui.add(egui::ImageButton::new(
+                                    egui::Image::from_bytes(
+                                        format!("bytes://{}", image.url),
+                                        egui::load::Bytes::Shared(bytes.clone()),
+                                    )
+                                    .fit_to_exact_size(
+                                        egui::vec2(PICKER_STICKER_POINTS, PICKER_STICKER_POINTS),
+                                    ),
+                                )),
+                                loaded => {
+                                    if loaded.is_none() {
+                                        wanted.insert(image.url.clone());
+                                    }
+                                    ui.small_button(&image.shortcode)
+                                }
+                            };
+                            if resp.on_hover_text(hint).clicked() {
+                                chosen = Some(image.clone());
+                            }
+                        }
+                    });
+                }
+            });
+        if !any {
+            ui.weak("No stickers in this room.");
+        }
+        self.request_pack_images(wanted);
+        if let Some(image) = chosen {
+            let _ = self.cmd_tx.send(AppCommand::SendSticker {
+                room_id: room_id.to_owned(),
+                image,
+            });
+            ui.close_menu();
+        }
+    }
+
+    /// Add a message to the selection or toggle it, starting or ending
+    /// selection mode as needed.
+    fn pick_message(&mut self, room_id: String, event_id: String, pick: Pick) {
+        match (&mut self.selection, pick) {
+            (Some(selection), Pick::Toggle) => selection.toggle(&event_id),
+            (Some(selection), Pick::Add) => {
+                selection.events.insert(event_id);
+            }
+            (None, _) => {
+                // Whether "Remove" is offered depends on our power level.
+                if self
+                    .power_levels
+                    .as_ref()
+                    .is_none_or(|(rid, _)| *rid != room_id)
+                {
+                    let _ = self.cmd_tx.send(AppCommand::FetchPowerLevels {
+                        room_id: room_id.clone(),
+                    });
+                }
+                self.selection = Some(Selection::new(room_id, event_id));
+                self.toolbar_for = None;
+            }
+        }
+        if self.selection.as_ref().is_some_and(|s| s.events.is_empty()) {
+            self.selection = None;
+        }
+    }
+
+    /// The room's shared player: link, position and play/pause/seek/stop.
+    fn shared_media_ui(&mut self, ui: &mut egui::Ui, room_id: &str) {
+        let Some(Some(media)) = self.shared_media.get(room_id) else {
+            return;
+        };
+        let position = media.position_now();
+        if media.playing {
+            ui.ctx()
+                .request_repaint_after(std::time::Duration::from_millis(250));
+        }
+        let mut sync: Option<(bool, u64)> = None;
+        let mut stop = false;
+        ui.horizontal(|ui| {
+            ui.label("📺");
+            ui.hyperlink(&media.url);
+            ui.monospace(format_position(position));
+            if ui.small_button("⏪").on_hover_text("Back 10 s").clicked() {
+                sync = Some((media.playing, position.saturating_sub(SEEK_STEP_MS)));
+            }
+            let (label, hover) = if media.playing {
+                ("⏸", "Pause for everyone")
+            } else {
+                ("▶", "Play for everyone")
+            };
+            if ui.small_button(label).on_hover_text(hover).clicked() {
+                sync = Some((!media.playing, position));
+            }
+            if ui
+                .small_button("⏩")
+                .on_hover_text("Forward 10 s")
+                .clicked()
+            {
+                sync = Some((media.playing, position + SEEK_STEP_MS));
+            }
+            if ui
+                .small_button("⏹")
+                .on_hover_text("Stop watching together")
+                .clicked()
+            {
+                stop = true;
+            }
+            ui.weak(format!(
+                "by {}",
+                self.display_name(media.updated_by.as_str())
+            ));
+        });
+
+        let media = media.clone();
+        if let Some((playing, position_ms)) = sync {
+            let _ = self.cmd_tx.send(AppCommand::SyncMedia {
+                room_id: room_id.to_owned(),
+                media,
+                playing,
+                position_ms,
+            });
+        } else if stop {
+            let _ = self.cmd_tx.send(AppCommand::StopMedia {
+                room_id: room_id.to_owned(),
+                media,
+            });
+        }
+    }
+
+    /// Count and bulk actions for the selected messages, above the timeline.
+    fn selection_bar_ui(&mut self, ui: &mut egui::Ui, room_id: &str) {
+        let Some(selection) = &self.selection else {
+            return;
+        };
+        // Timeline order, for the transcript and forwarding.
+        let chosen: Vec<&TimelineItem> = self
+            .messages
+            .get(room_id)
+            .into_iter()
+            .flatten()
+            .filter(|m| {
+                m.event_id
+                    .as_ref()
+                    .is_some_and(|id| selection.events.contains(id))
+            })
+            .collect();
+        let transcript: Vec<String> = chosen
+            .iter()
+            .map(|m| transcript_entry(self.display_name(&m.sender), &m.body))
+            .collect();
+        let bodies: Vec<String> = chosen.iter().map(|m| m.body.clone()).collect();
+        let event_ids: Vec<String> = chosen.iter().filter_map(|m| m.event_id.clone()).collect();
+        let can_remove = self
+            .power_levels
+            .as_ref()
+            .is_some_and(|(rid, l)| rid == room_id && l.own >= l.redact);
+        let targets: Vec<(String, String)> = self
+            .rooms
+            .iter()
+            .filter(|r| !r.is_space && r.id != room_id)
+            .map(|r| (r.id.clone(), r.name.clone()))
+            .collect();
+
+        let mut copy = false;
+        let mut forward = None;
+        let mut remove = false;
+        let mut cancel = false;
+        ui.horizontal(|ui| {
+            let n = event_ids.len();
+            ui.strong(if n == 1 {
+                "1 message selected".to_owned()
+            } else {
+                format!("{n} messages selected")
+            });
+            if ui
+                .button("Copy")
+                .on_hover_text("Copy as a transcript")
+                .clicked()
+            {
+                copy = true;
+            }
+            ui.menu_button("Forward to…", |ui| {
+                egui::ScrollArea::vertical()
+                    .max_height(240.0)
+                    .show(ui, |ui| {
+                        for (id, name) in &targets {
+                            if ui.button(name).clicked() {
+                                forward = Some(id.clone());
+                                ui.close_menu();
+                            }
+                        }
+                    });
+            });
+            if can_remove {
+                ui.menu_button("Remove…", |ui| {
+                    ui.label(format!("Remove {n} message(s) for everyone?"));
+                    if ui.button("Remove").clicked() {
+                        remove = true;
+                        ui.close_menu();
+                    }
+                });
+            }
+            if ui.button("Cancel").on_hover_text("Esc").clicked() {
+                cancel = true;
+            }
+        });
+
+        if copy {
+            ui.ctx().copy_text(transcript.join("\n"));
+        }
+        if let Some(target) = &forward {
+            for body in bodies {
+                let text = MessageText {
+                    body,
+                    ..Default::default()
+                };
+                let _ = self.cmd_tx.send(AppCommand::SendMessage {
+                    room_id: target.clone(),
+                    text,
+                });
+            }
+        }
+        if remove {
+            let _ = self.cmd_tx.send(AppCommand::RedactMessages {
+                room_id: room_id.to_owned(),
+                event_ids,
+                reason: None,
+            });
+        }
+        if copy || forward.is_some() || remove || cancel {
+            self.selection = None;
+        }
+    }
+
+    /// Show the thread rooted at `root` in the Threads panel.
+    fn open_thread(&mut self, room_id: &str, root: String) {
+        if !self.ui.panel_open(room_id, Panel::Threads) {
+            self.ui.toggle_panel(room_id, Panel::Threads);
+        }
+        self.thread_view = Some(root);
+    }
+
+    /// Alt+Up/Down picks a message; Alt+R/T/E act on it; Esc lets go of it
+    /// and of any reply/edit in progress. Up in an empty composer edits our
+    /// last message.
+    fn handle_message_shortcuts(&mut self, ctx: &egui::Context) {
+        let Some(room_id) = self
+            .selected_room
+            .and_then(|i| self.rooms.get(i))
+            .map(|r| r.id.clone())
+        else {
+            return;
+        };
+        let ids: Vec<String> = self
+            .messages
+            .get(&room_id)
+            .into_iter()
+            .flatten()
+            .filter(|m| m.thread_root.is_none())
+            .filter_map(|m| m.event_id.clone())
+            .collect();
+        if self
+            .focused_message
+            .as_ref()
+            .is_some_and(|id| !ids.contains(id))
+        {
+            self.focused_message = None;
+        }
+
+        let step = ctx.input_mut(|i| {
+            if i.consume_key(egui::Modifiers::ALT, egui::Key::ArrowUp) {
+                Some(-1)
+            } else if i.consume_key(egui::Modifiers::ALT, egui::Key::ArrowDown) {
+                Some(1)
+            } else {
+                None
+            }
+        });
+        if let Some(step) = step {
+            let current = self
+                .focused_message
+                .as_ref()
+                .and_then(|id| ids.iter().position(|i| i == id));
+            let next = match (current, step) {
+                (None, _) => ids.len().checked_sub(1),
+                (Some(i), -1) => Some(i.saturating_sub(1)),
+                (Some(i), _) => (i + 1 < ids.len()).then_some(i + 1),
+            };
+            self.focused_message = next.map(|i| ids[i].clone());
+        }
+
+        if (self.focused_message.is_some()
+            || self.composer.relation.is_some()
+            || self.selection.is_some())
+            && ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Escape))
+        {
+            self.focused_message = None;
+            self.selection = None;
+            self.cancel_relation();
+        }
+
+        let composer_focused = ctx.memory(|m| m.has_focus(composer_id()));
+        if composer_focused
+            && self.composer.is_empty()
+            && ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp))
+        {
+            let own = &self.own_user_id;
+            let last = self
+                .messages
+                .get(&room_id)
+                .and_then(|log| {
+                    log.iter()
+                        .rev()
+                        .find(|m| &m.sender == own && m.thread_root.is_none())
+                })
+                .and_then(|m| m.event_id.clone());
+            if let Some(event_id) = last {
+                self.message_action(ctx, &room_id, &event_id, MessageAction::Edit);
+            }
+        }
+
+        let Some(event_id) = self.focused_message.clone() else {
+            return;
+        };
+        for action in [
+            MessageAction::Reply,
+            MessageAction::Thread,
+            MessageAction::Edit,
+        ] {
+            let Some(key) = action.key() else { continue };
+            if ctx.input_mut(|i| i.consume_key(egui::Modifiers::ALT, key)) {
+                self.message_action(ctx, &room_id, &event_id, action);
+                self.focused_message = None;
+                break;
+            }
+        }
+    }
+
+    /// `user_id`'s display name, if we know it.
+    fn display_name<'a>(&'a self, user_id: &'a str) -> &'a str {
+        self.profiles
+            .get(user_id)
+            .and_then(|p| p.display_name.as_deref())
+            .unwrap_or(user_id)
+    }
+
+    /// People who can be mentioned in `room_id` as `(user ID, display name)`:
+    /// recent senders first, then the rest of the loaded member list. Falls
+    /// back to the localpart for anyone without a display name.
+    fn mentionable(&self, room_id: &str) -> Vec<(String, String)> {
+        let senders = self
+            .messages
+            .get(room_id)
+            .into_iter()
+            .flatten()
+            .rev()
+            .map(|m| m.sender.as_str());
+        let members = self
+            .members
+            .get(room_id)
+            .into_iter()
+            .flatten()
+            .filter(|m| m.membership == MembershipState::Join)
+            .map(|m| m.user_id.as_str());
+        let mut out: Vec<(String, String)> = Vec::new();
+        for user_id in senders.chain(members) {
+            if out.iter().any(|(u, _)| u == user_id) {
+                continue;
+            }
+            let name = match self.display_name(user_id) {
+                name if name != user_id => name.to_owned(),
+                _ => self
+                    .members
+                    .get(room_id)
+                    .and_then(|ms| ms.iter().find(|m| m.user_id == user_id))
+                    .and_then(|m| m.display_name.clone())
+                    .unwrap_or_else(|| {
+                        user_id
+                            .trim_start_matches('@')
+                            .split(':')
+                            .next()
+                            .unwrap_or(user_id)
+                            .to_owned()
+                    }),
+            };
+            out.push((user_id.to_owned(), name));
+        }
+        out
+    }
+
+    /// Whether the server supports what `panel` shows.
+    fn panel_available(&self, panel: Panel) -> bool {
+        match panel {
+            Panel::Threads => self
+                .capabilities
+                .as_ref()
+                .is_none_or(ServerCapabilities::threads),
+            Panel::Members | Panel::Pinned => true,
+        }
+    }
+
+    /// Wrap up a call we just left: post its summary if we were the last one
+    /// out (and that's enabled), and show the initiator a summary card.
+    fn finish_call(&mut self, call: CallTracker, room_id: String) {
+        let summary = call.summary();
+        if self.settings.voice.post_call_summary && self.voice_participants.is_empty() {
+            let _ = self.cmd_tx.send(AppCommand::PostCallSummary {
+                room_id: room_id.clone(),
+                summary: summary.clone(),
+            });
+        }
+        if call.initiator() {
+            self.call_export_file = call_export_path(call.started_ts()).display().to_string();
+            self.call_export_status = None;
+            self.call_summary = Some((room_id, summary));
+            self.ui.open(Dialog::CallSummary);
+        }
+    }
+
+    fn show_poll_dialog(&mut self, ctx: &egui::Context) {
+        let Some(draft) = self.poll_draft.as_mut() else {
+            self.ui.close(Dialog::CreatePoll);
+            return;
+        };
+        let mut open = true;
+        let mut confirmed = false;
+        let mut cancelled = false;
+        egui::Window::new("Create Poll")
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.label("Question");
+                ui.add(egui::TextEdit::singleline(&mut draft.question).desired_width(300.0));
+                ui.add_space(6.0);
+                ui.label("Answers");
+                let mut remove = None;
+                for (i, answer) in draft.answers.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::TextEdit::singleline(answer)
+                                .desired_width(270.0)
+                                .hint_text(format!("Answer {}", i + 1)),
+                        );
+                        if ui
+                            .small_button("✕")
+                            .on_hover_text("Remove answer")
+                            .clicked()
+                        {
+                            remove = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = remove.filter(|_| draft.answers.len() > 2) {
+                    draft.answers.remove(i);
+                }
+                if draft.answers.len() < polls::MAX_ANSWERS
+                    && ui.small_button("+ Add answer").clicked()
+                {
+                    draft.answers.push(String::new());
+                }
+                ui.add_space(6.0);
+                let most = draft.answers().len().max(1) as u32;
+                draft.max_selections = draft.max_selections.clamp(1, most);
+                ui.horizontal(|ui| {
+                    ui.label("Choices per voter");
+                    ui.add(egui::DragValue::new(&mut draft.max_selections).range(1..=most));
+                });
+                ui.checkbox(&mut draft.disclosed, "Show results before the poll ends");
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(draft.ready(), egui::Button::new("Create"))
+                        .clicked()
+                    {
+                        confirmed = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        cancelled = true;
+                    }
+                });
+            });
+
+        if confirmed {
+            let _ = self.cmd_tx.send(AppCommand::StartPoll {
+                room_id: draft.room_id.clone(),
+                question: draft.question.trim().to_owned(),
+                answers: draft.answers(),
+                max_selections: draft.max_selections,
+                kind: draft.kind(),
+            });
+        }
+        if confirmed || cancelled || !open {
+            self.poll_draft = None;
+            self.ui.close(Dialog::CreatePoll);
+        }
+    }
+
+    /// Someone is ringing us: answer (and join their call) or decline.
+    fn show_incoming_call_dialog(&mut self, ctx: &egui::Context) {
+        let Some(call) = &self.incoming_call else {
+            self.ui.close(Dialog::IncomingCall);
+            return;
+        };
+        // Unanswered rings stop on their own, as they do for the caller.
+        if ctx.input(|i| i.time) - call.since > ring::RING_TIMEOUT.as_secs_f64() {
+            let now = ctx.input(|i| i.time);
+            self.call_toasts.push((
+                format!("Missed call from {}", self.display_name(&call.caller)),
+                now,
+            ));
+            self.incoming_call = None;
+            self.ui.close(Dialog::IncomingCall);
+            return;
+        }
+        ctx.request_repaint_after_secs(1.0);
+
+        let mut answer: Option<bool> = None;
+        egui::Window::new("Incoming call")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_TOP, [0.0, 40.0])
+            .show(ctx, |ui| {
+                let profile = self.profiles.get(call.caller.as_str());
+                ui.horizontal(|ui| {
+                    avatar_ui(ui, &call.caller, profile);
+                    ui.strong(format!("📞 {} is calling", self.display_name(&call.caller)));
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("Answer").clicked() {
+                        answer = Some(true);
+                    }
+                    if ui.button("Decline").clicked() {
+                        answer = Some(false);
+                    }
+                });
+            });
+
+        let Some(accept) = answer else { return };
+        let Some(call) = self.incoming_call.take() else {
+            return;
+        };
+        self.ui.close(Dialog::IncomingCall);
+        let _ = self.cmd_tx.send(AppCommand::AnswerRing {
+            room_id: call.room_id.clone(),
+            call_id: call.call_id,
+            caller: call.caller,
+            accept,
+        });
+        if accept {
+            if let Some(i) = self.rooms.iter().position(|r| r.id == call.room_id) {
+                self.selected_room = Some(i);
+            }
+            let _ = self.cmd_tx.send(AppCommand::JoinVoice {
+                room_id: call.room_id,
+                ice: self.settings.ice.clone(),
+                tuning: self.voice_tuning(),
+                devices: self.device_choice(),
+            });
+        }
+    }
+
+    fn show_call_summary_dialog(&mut self, ctx: &egui::Context) {
+        let mut open = true;
+        egui::Window::new("Call summary")
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let Some((room_id, summary)) = &self.call_summary else {
+                    return;
+                };
+                let room_name = self
+                    .rooms
+                    .iter()
+                    .find(|r| &r.id == room_id)
+                    .map_or(room_id.as_str(), |r| &r.name);
+                ui.strong(room_name);
+                ui.label(summary.describe());
+                ui.add_space(6.0);
+                for user_id in &summary.participants {
+                    let profile = self.profiles.get(user_id.as_str());
+                    let name = profile
+                        .and_then(|p| p.display_name.as_deref())
+                        .unwrap_or(user_id.as_str());
+                    ui.horizontal(|ui| {
+                        avatar_ui(ui, user_id.as_str(), profile);
+                        ui.label(name);
+                    });
+                }
+
+                ui.add_space(12.0);
+                ui.small("Export the room's chat from the call:");
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.call_export_file).desired_width(220.0),
+                    );
+                    if ui.button("Export").clicked() {
+                        let path = std::path::PathBuf::from(self.call_export_file.trim());
+                        let messages = self
+                            .messages
+                            .get(room_id)
+                            .map(Vec::as_slice)
+                            .unwrap_or_default();
+                        let transcript = call_transcript(summary, messages);
+                        self.call_export_status = Some(
+                            std::fs::write(&path, transcript)
+                                .map(|()| format!("Exported to {}", path.display()))
+                                .map_err(|e| format!("{}: {e}", path.display())),
+                        );
+                    }
+                });
+                match &self.call_export_status {
+                    Some(Ok(msg)) => {
+                        ui.weak(msg);
+                    }
+                    Some(Err(e)) => {
+                        ui.colored_label(egui::Color32::RED, e);
+                    }
+                    None => {}
+                }
+            });
+        if !open {
+            self.ui.close(Dialog::CallSummary);
+            self.call_summary = None;
+        }
+    }
+
+    fn show_room_settings_dialog(&mut self, ctx: &egui::Context) {
+        let mut open = true;
+        let mut publish: Option<bool> = None;
+        let mut save_voice: Option<VoiceConfigEventContent> = None;
+        let mut enable_encryption = false;
+        egui::Window::new("Room settings")
+            .collapsible(false)
+            .default_width(320.0)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.selectable_value(
+                        &mut self.room_settings_tab,
+                        RoomSettingsTab::General,
+                        "General",
+                    );
+                    ui.selectable_value(
+                        &mut self.room_settings_tab,
+                        RoomSettingsTab::Voice,
+                        "Voice",
+                    );
+                });
+                ui.separator();
+                if self.room_settings_tab == RoomSettingsTab::Voice {
+                    match &mut self.voice_config {
+                        Some(voice) => save_voice = voice_config_ui(ui, voice),
+                        None => {
+                            ui.spinner();
+                        }
+                    }
+                    return;
+                }
+                let Some((_, listing)) = &self.room_settings else {
+                    return;
+                };
+                let Some(listing) = listing else {
+                    ui.spinner();
+                    return;
+                };
+                let mut published = listing.published;
+                let resp = ui.add_enabled(
+                    listing.can_change,
+                    egui::Checkbox::new(&mut published, "Publish in the room directory"),
+                );
+                if resp.changed() {
+                    publish = Some(published);
+                }
+                if !listing.can_change {
+                    ui.weak("Only members who can change the room's address may publish it.");
+                }
+
+                ui.add_space(8.0);
+                match self.room_encryption {
+                    None => {
+                        ui.spinner();
+                    }
+                    Some(RoomEncryption { enabled: true, .. }) => {
+                        ui.label("🔒 Messages are end-to-end encrypted.");
+                    }
+                    Some(RoomEncryption {
+                        enabled: false,
+                        can_enable,
+                    }) => {
+                        ui.label("Messages are not encrypted.");
+                        if self.confirm_encryption {
+                            ui.label(
+                                "Once on, encryption can't be turned off. Bridges and bots \
+                                 without encryption support will stop seeing messages.",
+                            );
+                            ui.horizontal(|ui| {
+                                if ui.button("Turn on encryption").clicked() {
+                                    enable_encryption = true;
+                                }
+                                if ui.button("Cancel").clicked() {
+                                    self.confirm_encryption = false;
+                                }
+                            });
+                        } else if ui
+                            .add_enabled(can_enable, egui::Button::new("Turn on encryption…"))
+                            .clicked()
+                        {
+                            self.confirm_encryption = true;
+                        }
+                        if !can_enable {
+                            ui.weak("Your power level doesn't allow turning on encryption here.");
+                        }
+                    }
+                }
+            });
+
+        if let (Some(published), Some((room_id, listing))) = (publish, &mut self.room_settings) {
+            let _ = self.cmd_tx.send(AppCommand::SetPublished {
+                room_id: room_id.clone(),
+                published,
+            });
+            *listing = None;
+        }
+        if let (true, Some((room_id, _))) = (enable_encryption, &self.room_settings) {
+            let _ = self.cmd_tx.send(AppCommand::EnableEncryption {
+                room_id: room_id.clone(),
+            });
+            self.room_encryption = None;
+            self.confirm_encryption = false;
+        }
+        if let (Some(config), Some(voice)) = (save_voice, &self.voice_config) {
+            let _ = self.cmd_tx.send(AppCommand::SetVoiceConfig {
+                room_id: voice.room_id.clone(),
+                base: voice.current.clone(),
+                config,
+            });
+        }
+        if !open {
+            self.ui.close(Dialog::RoomSettings);
+            self.room_settings = None;
+            self.voice_config = None;
+        }
+    }
+
+    /// Developer mode: every state event in the room as JSON, and a form to
+    /// send any event. Picking a state event fills the form to edit it.
+    fn show_room_state_dialog(&mut self, ctx: &egui::Context) {
+        let Some((room_id, loaded)) = &self.room_state else {
+            self.ui.close(Dialog::RoomState);
+            return;
+        };
+        let room_id = room_id.clone();
+        let mut open = true;
+        let mut refresh = false;
+        let mut send: Option<AppCommand> = None;
+        egui::Window::new("Room state")
+            .collapsible(false)
+            .default_width(520.0)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.monospace(&room_id);
+                    if ui.small_button("⟳").on_hover_text("Reload").clicked() {
+                        refresh = true;
+                    }
+                });
+                let draft = &mut self.custom_event;
+                egui::ScrollArea::vertical()
+                    .max_height(320.0)
+                    .show(ui, |ui| {
+                        let Some(state) = loaded else {
+                            ui.spinner();
+                            return;
+                        };
+                        for entry in state {
+                            let title = if entry.state_key.is_empty() {
+                                entry.event_type.clone()
+                            } else {
+                                format!("{} · {}", entry.event_type, entry.state_key)
+                            };
+                            egui::CollapsingHeader::new(egui::RichText::new(title).monospace())
+                                .id_salt((&entry.event_type, &entry.state_key))
+                                .show(ui, |ui| {
+                                    let json = serde_json::to_string_pretty(&entry.content)
+                                        .unwrap_or_default();
+                                    ui.weak(format!("Sent by {}", entry.sender));
+                                    ui.monospace(&json);
+                                    if ui.small_button("Edit").clicked() {
+                                        *draft = CustomEventDraft {
+                                            state: true,
+                                            event_type: entry.event_type.clone(),
+                                            state_key: entry.state_key.clone(),
+                                            content: json,
+                                            error: None,
+                                        };
+                                    }
+                                });
+                        }
+                    });
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut draft.state, true, "State event");
+                    ui.radio_value(&mut draft.state, false, "Message event");
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Type");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut draft.event_type)
+                            .hint_text("org.example.thing"),
+                    );
+                });
+                if draft.state {
+                    ui.horizontal(|ui| {
+                        ui.label("State key");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut draft.state_key)
+                                .hint_text("empty for none"),
+                        );
+                    });
+                }
+                ui.add(
+                    egui::TextEdit::multiline(&mut draft.content)
+                        .code_editor()
+                        .hint_text("{ }")
+                        .desired_rows(6)
+                        .desired_width(f32::INFINITY),
+                );
+                if let Some(e) = &draft.error {
+                    ui.colored_label(ui.visuals().error_fg_color, e);
+                }
+                let can_send = !draft.event_type.trim().is_empty();
+                if ui
+                    .add_enabled(can_send, egui::Button::new("Send"))
+                    .clicked()
+                {
+                    match serde_json::from_str::<serde_json::Value>(&draft.content) {
+                        Ok(content) if content.is_object() => {
+                            draft.error = None;
+                            send = Some(AppCommand::SendCustomEvent {
+                                room_id: room_id.clone(),
+                                event_type: draft.event_type.trim().to_owned(),
+                                state_key: draft.state.then(|| draft.state_key.clone()),
+                                content,
+                            });
+                        }
+                        Ok(_) => draft.error = Some("Content must be a JSON object".into()),
+                        Err(e) => draft.error = Some(format!("Invalid JSON: {e}")),
+                    }
+                }
+            });
+
+        if let Some(cmd) = send {
+            let _ = self.cmd_tx.send(cmd);
+        }
+        if refresh {
+            self.room_state = Some((room_id.clone(), None));
+            let _ = self.cmd_tx.send(AppCommand::FetchRoomState { room_id });
+        }
+        if !open {
+            self.ui.close(Dialog::RoomState);
+            self.room_state = None;
+        }
+    }
+
+    fn show_server_acl_dialog(&mut self, ctx: &egui::Context) {
+        let Some((room_id, loaded)) = &self.server_acl else {
+            self.ui.close(Dialog::ServerAcl);
+            return;
+        };
+        let room_id = room_id.clone();
+        let mut open = true;
+        let mut commands: Vec<AppCommand> = Vec::new();
+        let mut ban: Option<String> = None;
+        egui::Window::new("Federation")
+            .collapsible(false)
+            .default_width(420.0)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let Some((acl, lists)) = loaded else {
+                    ui.spinner();
+                    return;
+                };
+                let draft = &mut self.acl_draft;
+                if !acl.can_edit {
+                    ui.weak("You can't change this room's server ACL.");
+                }
+
+                ui.label(format!("Allowed servers: {}", acl.allow.join(", ")));
+                ui.label("Denied servers:");
+                if acl.deny.is_empty() {
+                    ui.weak("None");
+                }
+                for server in &acl.deny {
+                    ui.horizontal(|ui| {
+                        ui.monospace(server);
+                        if acl.can_edit
+                            && ui.small_button("✕").on_hover_text("Allow again").clicked()
+                        {
+                            commands.push(AppCommand::ChangeServerAcl {
+                                room_id: room_id.clone(),
+                                change: AclChange::Undeny(server.clone()),
+                            });
+                        }
+                    });
+                }
+                ui.add_enabled_ui(acl.can_edit, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::TextEdit::singleline(&mut draft.deny)
+                                .hint_text("evil.example or *.evil.example"),
+                        );
+                        let server = draft.deny.trim();
+                        if ui
+                            .add_enabled(!server.is_empty(), egui::Button::new("Deny"))
+                            .clicked()
+                        {
+                            commands.push(AppCommand::ChangeServerAcl {
+                                room_id: room_id.clone(),
+                                change: AclChange::Deny(server.to_owned()),
+                            });
+                            draft.deny.clear();
+                        }
+                    });
+                    let mut ip_literals = acl.allow_ip_literals;
+                    if ui
+                        .checkbox(&mut ip_literals, "Allow servers addressed by IP")
+                        .changed()
+                    {
+                        commands.push(AppCommand::ChangeServerAcl {
+                            room_id: room_id.clone(),
+                            change: AclChange::AllowIpLiterals(ip_literals),
+                        });
+                    }
+                });
+
+                ui.separator();
+                ui.label("Policy lists");
+                if lists.is_empty() {
+                    ui.weak("This room doesn't follow any policy lists.");
+                }
+                for list in lists {
+                    let title = match (&list.name, list.joined) {
+                        (_, false) => format!("{} (not joined)", list.room_id),
+                        (Some(name), true) => format!("{name} · {} rules", list.rules.len()),
+                        (None, true) => format!("{} · {} rules", list.room_id, list.rules.len()),
+                    };
+                    egui::CollapsingHeader::new(title)
+                        .id_salt(&list.room_id)
+                        .show(ui, |ui| {
+                            ui.horizontal(|ui| {
+                                if !list.joined && ui.small_button("Join to see rules").clicked() {
+                                    commands.push(AppCommand::JoinRoom {
+                                        room_id: list.room_id.clone(),
+                                    });
+                                }
+                                if acl.can_edit && ui.small_button("Unfollow").clicked() {
+                                    commands.push(AppCommand::FollowPolicyList {
+                                        room_id: room_id.clone(),
+                                        list_id: list.room_id.clone(),
+                                        follow: false,
+                                    });
+                                }
+                            });
+                            egui::Grid::new(("policy_rules", &list.room_id))
+                                .num_columns(4)
+                                .show(ui, |ui| {
+                                    for rule in &list.rules {
+                                        ui.label(rule.kind.label());
+                                        ui.monospace(&rule.entity);
+                                        ui.weak(&rule.reason);
+                                        match rule.kind {
+                                            PolicyKind::Server
+                                                if acl.can_edit
+                                                    && !acl.deny.contains(&rule.entity) =>
+                                            {
+                                                if ui.small_button("Deny").clicked() {
+                                                    commands.push(AppCommand::ChangeServerAcl {
+                                                        room_id: room_id.clone(),
+                                                        change: AclChange::Deny(
+                                                            rule.entity.clone(),
+                                                        ),
+                                                    });
+                                                }
+                                            }
+                                            // Globs can't be banned one by one.
+                                            PolicyKind::User
+                                                if !rule.entity.contains(['*', '?']) =>
+                                            {
+                                                if ui.small_button("Ban here…").clicked() {
+                                                    ban = Some(rule.entity.clone());
+                                                }
+                                            }
+                                            _ => {
+                                                ui.label("");
+                                            }
+                                        }
+                                        ui.end_row();
+                                    }
+                                });
+                        });
+                }
+                if acl.can_edit {
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::TextEdit::singleline(&mut draft.follow).hint_text("!list:server"),
+                        );
+                        let list_id = draft.follow.trim();
+                        if ui
+                            .add_enabled(list_id.starts_with('!'), egui::Button::new("Follow"))
+                            .clicked()
+                        {
+                            commands.push(AppCommand::FollowPolicyList {
+                                room_id: room_id.clone(),
+                                list_id: list_id.to_owned(),
+                                follow: true,
+                            });
+                            draft.follow.clear();
+                        }
+                    });
+                }
+
+                let editable: Vec<&PolicyList> = lists.iter().filter(|l| l.can_edit).collect();
+                if !editable.is_empty() {
+                    ui.separator();
+                    ui.label("Add a ban rule");
+                    if !editable.iter().any(|l| l.room_id == draft.rule_list) {
+                        draft.rule_list = editable[0].room_id.clone();
+                    }
+                    ui.horizontal(|ui| {
+                        let selected = editable.iter().find(|l| l.room_id == draft.rule_list);
+                        egui::ComboBox::from_id_salt("policy_rule_list")
+                            .selected_text(
+                                selected
+                                    .and_then(|l| l.name.clone())
+                                    .unwrap_or(draft.rule_list.clone()),
+                            )
+                            .show_ui(ui, |ui| {
+                                for list in &editable {
+                                    let name = list.name.clone().unwrap_or(list.room_id.clone());
+                                    ui.selectable_value(
+                                        &mut draft.rule_list,
+                                        list.room_id.clone(),
+                                        name,
+                                    );
+                                }
+                            });
+                        egui::ComboBox::from_id_salt("policy_rule_kind")
+                            .selected_text(draft.rule_kind.label())
+                            .show_ui(ui, |ui| {
+                                for kind in PolicyKind::ALL {
+                                    ui.selectable_value(&mut draft.rule_kind, kind, kind.label());
+                                }
+                            });
+                    });
+                    let hint = match draft.rule_kind {
+                        PolicyKind::User => "@spammer:server",
+                        PolicyKind::Server => "evil.example",
+                        PolicyKind::Room => "!room:server",
+                    };
+                    ui.add(egui::TextEdit::singleline(&mut draft.rule_entity).hint_text(hint));
+                    ui.add(egui::TextEdit::singleline(&mut draft.rule_reason).hint_text("Reason"));
+                    let entity = draft.rule_entity.trim();
+                    if ui
+                        .add_enabled(!entity.is_empty(), egui::Button::new("Add rule"))
+                        .clicked()
+                    {
+                        commands.push(AppCommand::AddPolicyRule {
+                            room_id: room_id.clone(),
+                            list_id: draft.rule_list.clone(),
+                            kind: draft.rule_kind,
+                            entity: entity.to_owned(),
+                            reason: draft.rule_reason.trim().to_owned(),
+                        });
+                        draft.rule_entity.clear();
+                        draft.rule_reason.clear();
+                    }
+                }
+            });
+
+        for cmd in commands {
+            let _ = self.cmd_tx.send(cmd);
+        }
+        if let Some(user_id) = ban {
+            self.moderation = Some(ModerationDraft {
+                room_id: room_id.clone(),
+                user_id,
+                action: ModerationAction::Ban,
+                reason: String::new(),
+            });
+            self.ui.open(Dialog::Moderation);
+        }
+        if !open {
+            self.ui.close(Dialog::ServerAcl);
+            self.server_acl = None;
+        }
+    }
+
+    /// Create Room: a template (or, from a space, a voice channel to add to
+    /// it), then the name and whichever extras the template allows.
+    fn show_create_room_dialog(&mut self, ctx: &egui::Context) {
+        let in_space = self.create_room_space.as_ref().map(|id| {
+            self.rooms
+                .iter()
+                .find(|r| &r.id == id)
+                .map_or(id.as_str(), |r| r.name.as_str())
+                .to_owned()
+        });
+        let title = if in_space.is_some() {
+            "New Voice Channel"
+        } else {
+            "Create Room"
+        };
+        let mut open = true;
+        let mut done = false;
+        egui::Window::new(title)
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                match &in_space {
+                    Some(space) => {
+                        ui.weak(format!("In {space}"));
+                    }
+                    None => {
+                        ui.horizontal(|ui| {
+                            ui.label("Kind");
+                            egui::ComboBox::from_id_salt("create_room_template")
+                                .selected_text(self.create_room_template.label())
+                                .show_ui(ui, |ui| {
+                                    for template in RoomTemplate::ALL {
+                                        let picked = ui.selectable_value(
+                                            &mut self.create_room_template,
+                                            template,
+                                            template.label(),
+                                        );
+                                        if picked.clicked() {
+                                            self.create_room_encrypted =
+                                                template.encrypted_by_default();
+                                        }
+                                    }
+                                });
+                        });
+                    }
+                }
+                ui.label("Name");
+                let resp = ui.add(
+                    egui::TextEdit::singleline(&mut self.create_room_name).desired_width(240.0),
+                );
+                resp.request_focus();
+                if in_space.is_none() {
+                    ui.label("Topic");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.create_room_topic)
+                            .desired_width(240.0),
+                    );
+                    ui.label("Address");
+                    ui.horizontal(|ui| {
+                        ui.label("#");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.create_room_alias)
+                                .hint_text("optional")
+                                .desired_width(140.0),
+                        );
+                        ui.weak(format!(
+                            ":{}",
+                            self.own_user_id
+                                .split_once(':')
+                                .map_or("", |(_, server)| server)
+                        ));
+                    });
+                    ui.label("Invite");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.create_room_invite)
+                            .hint_text("@alice:example.org, @bob:example.org")
+                            .desired_width(240.0),
+                    );
+                    if self.create_room_template.can_encrypt() {
+                        ui.checkbox(&mut self.create_room_encrypted, "End-to-end encrypted")
+                            .on_hover_text("Can't be turned off later");
+                    }
+                    if let Some(caps) = self
+                        .capabilities
+                        .as_ref()
+                        .filter(|c| !c.stable_room_versions.is_empty())
+                    {
+                        let default = caps
+                            .default_room_version
+                            .as_ref()
+                            .map_or("server default".to_owned(), |v| {
+                                format!("{v} (server default)")
+                            });
+                        ui.horizontal(|ui| {
+                            ui.label("Room version");
+                            egui::ComboBox::from_id_salt("create_room_version")
+                                .selected_text(
+                                    self.create_room_version.clone().unwrap_or(default.clone()),
+                                )
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(
+                                        &mut self.create_room_version,
+                                        None,
+                                        default,
+                                    );
+                                    for version in &caps.stable_room_versions {
+                                        ui.selectable_value(
+                                            &mut self.create_room_version,
+                                            Some(version.to_string()),
+                                            version.as_str(),
+                                        );
+                                    }
+                                });
+                        });
+                    }
+                }
+                ui.horizontal(|ui| {
+                    let can_create = !self.create_room_name.trim().is_empty();
+                    let enter = resp.has_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    if ui
+                        .add_enabled(can_create, egui::Button::new("Create"))
+                        .clicked()
+                        || (can_create && enter)
+                    {
+                        let name = std::mem::take(&mut self.create_room_name);
+                        let cmd = match self.create_room_space.take() {
+                            Some(space_id) => AppCommand::CreateVoiceChannel {
+                                name,
+                                space_id: Some(space_id),
+                            },
+                            None => {
+                                let text = |s: &mut String| {
+                                    Some(std::mem::take(s)).filter(|s| !s.trim().is_empty())
+                                };
+                                AppCommand::CreateRoom {
+                                    name,
+                                    template: self.create_room_template,
+                                    encrypted: self.create_room_encrypted,
+                                    topic: text(&mut self.create_room_topic),
+                                    alias: text(&mut self.create_room_alias),
+                                    invite: self
+                                        .create_room_invite
+                                        .split([',', ' '])
+                                        .filter(|s| !s.is_empty())
+                                        .map(str::to_owned)
+                                        .collect(),
+                                    room_version: self.create_room_version.take(),
+                                }
+                            }
+                        };
+                        let _ = self.cmd_tx.send(cmd);
+                        done = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        done = true;
+                    }
+                });
+            });
+        if done || !open {
+            self.ui.close(Dialog::CreateRoom);
+            self.reset_create_room(RoomTemplate::PrivateChat);
+        }
+    }
+
+    /// Clear the Create Room dialog, starting over with `template`.
+    fn reset_create_room(&mut self, template: RoomTemplate) {
+        self.create_room_name.clear();
+        self.create_room_template = template;
+        self.create_room_encrypted = template.encrypted_by_default();
+        self.create_room_topic.clear();
+        self.create_room_alias.clear();
+        self.create_room_invite.clear();
+        self.create_room_version = None;
+        self.create_room_space = None;
+    }
+
+    /// A room seen from outside, in the Join dialog.
+    fn room_peek_ui(&self, ui: &mut egui::Ui, peek: &RoomPeek) {
+        ui.separator();
+        let name = peek
+            .name
+            .as_deref()
+            .or(peek.canonical_alias.as_deref())
+            .unwrap_or(peek.room_id.as_str());
+        ui.strong(name);
+        if let Some(topic) = peek.topic.as_deref().filter(|t| !t.is_empty()) {
+            ui.label(topic);
+        }
+        ui.weak(if peek.member_count == 1 {
+            "1 member".to_owned()
+        } else {
+            format!("{} members", peek.member_count)
+        });
+        if peek.recent.is_empty() {
+            ui.weak("Recent messages aren't visible until you join.");
+        } else {
+            egui::ScrollArea::vertical()
+                .max_height(160.0)
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for m in &peek.recent {
+                        ui.label(quote_line(&m.sender, &m.body));
+                    }
+                });
+        }
+        ui.separator();
+    }
+
+    fn show_afk_dialog(&mut self, ctx: &egui::Context) {
+        let Some(draft) = self.afk.as_mut() else {
+            self.ui.close(Dialog::AfkChannel);
+            return;
+        };
+        let mut channels = Vec::new();
+        if let Some(root) = self.spaces.get(&draft.space_id) {
+            collect_voice_channels(root, &mut channels);
+        }
+        let mut open = true;
+        let mut save = false;
+        egui::Window::new("AFK Channel")
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                if !draft.loaded {
+                    ui.spinner();
+                    return;
+                }
+                ui.label(
+                    "Members who stay silent in one of this space's voice channels are moved here.",
+                );
+                ui.add_enabled_ui(draft.can_edit, |ui| {
+                    let selected = match &draft.afk_room {
+                        Some(id) => channels
+                            .iter()
+                            .find(|(c, _)| c == id)
+                            .map_or(id.clone(), |(_, n)| n.clone()),
+                        None => "Off".to_owned(),
+                    };
+                    egui::ComboBox::from_label("Channel")
+                        .selected_text(selected)
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut draft.afk_room, None, "Off");
+                            for (id, name) in &channels {
+                                ui.selectable_value(
+                                    &mut draft.afk_room,
+                                    Some(id.clone()),
+                                    format!("🔊 {name}"),
+                                );
+                            }
+                        });
+                    ui.add(
+                        egui::DragValue::new(&mut draft.timeout_mins)
+                            .range(1..=240)
+                            .suffix(" min of silence"),
+                    );
+                    if ui.button("Save").clicked() {
+                        save = true;
+                    }
+                });
+                if !draft.can_edit {
+                    ui.weak("You can't change this space's AFK channel.");
+                }
+            });
+
+        if save {
+            let _ = self.cmd_tx.send(AppCommand::SetAfkPolicy {
+                space_id: draft.space_id.clone(),
+                afk_room: draft.afk_room.clone(),
+                timeout_mins: draft.timeout_mins,
+            });
+        }
+        if !open || save {
+            self.afk = None;
+            self.ui.close(Dialog::AfkChannel);
+        }
+    }
+
+    fn show_power_levels_dialog(&mut self, ctx: &egui::Context) {
+        let mut open = true;
+        let mut change: Option<PowerLevelChange> = None;
+        egui::Window::new("Roles & permissions")
+            .collapsible(false)
+            .default_width(360.0)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let Some((_, levels)) = &self.power_levels else {
+                    ui.spinner();
+                    return;
+                };
+                ui.label(format!("Your power level: {}", levels.own));
+                if !levels.can_edit {
+                    ui.weak("You can't change power levels in this room.");
+                }
+                ui.add_space(6.0);
+
+                egui::Grid::new("power_users")
+                    .num_columns(3)
+                    .spacing([12.0, 4.0])
+                    .show(ui, |ui| {
+                        for (user_id, level) in &levels.users {
+                            ui.label(user_id);
+                            ui.monospace(level.to_string());
+                            ui.horizontal(|ui| {
+                                // Peers at our level are refused by the core, except ourselves.
+                                let editable = levels.can_edit && *level <= levels.own;
+                                ui.add_enabled_ui(editable, |ui| {
+                                    for (label, target) in [
+                                        ("Admin", ADMIN_LEVEL),
+                                        ("Mod", MODERATOR_LEVEL),
+                                        ("Default", levels.users_default),
+                                    ] {
+                                        if target != *level
+                                            && target <= levels.own
+                                            && ui.small_button(label).clicked()
+                                        {
+                                            change = Some(PowerLevelChange::User {
+                                                user_id: user_id.clone(),
+                                                level: target,
+                                            });
+                                        }
+                                    }
+                                });
+                            });
+                            ui.end_row();
+                        }
+                    });
+
+                ui.add_enabled_ui(levels.can_edit, |ui| {
+                    ui.add_space(6.0);
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.power_level_draft.0)
+                                .hint_text("@user:server"),
+                        );
+                        let user_id = self.power_level_draft.0.trim();
+                        if ui
+                            .add_enabled(!user_id.is_empty(), egui::Button::new("Make moderator"))
+                            .clicked()
+                        {
+                            change = Some(PowerLevelChange::User {
+                                user_id: user_id.to_owned(),
+                                level: MODERATOR_LEVEL,
+                            });
+                            self.power_level_draft.0.clear();
+                        }
+                    });
+
+                    ui.add_space(6.0);
+                    egui::Grid::new("power_events")
+                        .num_columns(3)
+                        .spacing([12.0, 4.0])
+                        .show(ui, |ui| {
+                            ui.label("Send messages");
+                            ui.add(
+                                egui::DragValue::new(&mut self.power_level_draft.1)
+                                    .range(0..=levels.own),
+                            );
+                            if self.power_level_draft.1 != levels.events_default
+                                && ui.small_button("Apply").clicked()
+                            {
+                                change =
+                                    Some(PowerLevelChange::EventsDefault(self.power_level_draft.1));
+                            }
+                            ui.end_row();
+
+                            ui.label("Join voice");
+                            ui.add(
+                                egui::DragValue::new(&mut self.power_level_draft.2)
+                                    .range(0..=levels.own),
+                            );
+                            if self.power_level_draft.2 != levels.voice
+                                && ui.small_button("Apply").clicked()
+                            {
+                                change = Some(PowerLevelChange::Voice(self.power_level_draft.2));
+                            }
+                            ui.end_row();
+                        });
+
+                    if let Some((room_id, current, draft)) = &mut self.voice_permissions {
+                        ui.add_space(6.0);
+                        ui.label("In voice, levels needed to:");
+                        egui::Grid::new("voice_permissions")
+                            .num_columns(2)
+                            .spacing([12.0, 4.0])
+                            .show(ui, |ui| {
+                                for (label, level) in [
+                                    ("Speak", &mut draft.speak),
+                                    ("Share screen", &mut draft.screen_share),
+                                    ("Be priority speaker", &mut draft.priority_speaker),
+                                ] {
+                                    ui.label(label);
+                                    ui.add(egui::DragValue::new(level).range(0..=levels.own));
+                                    ui.end_row();
+                                }
+                            });
+                        if draft != current && ui.small_button("Apply").clicked() {
+                            let _ = self.cmd_tx.send(AppCommand::SetVoicePermissions {
+                                room_id: room_id.clone(),
+                                permissions: *draft,
+                            });
+                        }
+                    }
+                });
+                ui.small(format!(
+                    "Kick {} · Ban {} · Redact {} · Invite {} · Settings {}",
+                    levels.kick, levels.ban, levels.redact, levels.invite, levels.state_default
+                ));
+            });
+
+        if let (Some(change), Some((room_id, _))) = (change, &self.power_levels) {
+            let _ = self.cmd_tx.send(AppCommand::SetPowerLevel {
+                room_id: room_id.clone(),
+                change,
+            });
+        }
+        if !open {
+            self.ui.close(Dialog::PowerLevels);
+            self.power_levels = None;
+            self.voice_permissions = None;
+        }
+    }
+
+    /// Contents of a right-hand side panel.
+    fn side_panel_ui(&mut self, ui: &mut egui::Ui, room_id: &str, panel: Panel) {
+        ui.add_space(8.0);
+        ui.horizontal(|ui| {
+            ui.strong(format!("{} {}", panel.icon(), panel.title()));
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.small_button("✕").clicked() {
+                    self.ui.toggle_panel(room_id, panel);
+                }
+            });
+        });
+        ui.separator();
+        match panel {
+            Panel::Members => self.members_panel_ui(ui, room_id),
+            Panel::Threads => self.threads_panel_ui(ui, room_id),
+            Panel::Pinned => self.pins_panel_ui(ui, room_id),
+        }
+    }
+
+    /// Pinned messages, newest pin first, each with a way back to it in the
+    /// timeline (if it's loaded) and, for those allowed, to unpin it.
+    fn pins_panel_ui(&mut self, ui: &mut egui::Ui, room_id: &str) {
+        self.request_pins(room_id);
+        let Some(pins) = self.pins.get(room_id) else {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.weak("Loading pinned messages…");
+            });
+            return;
+        };
+        if pins.messages.is_empty() {
+            ui.weak("No pinned messages.");
+            return;
+        }
+        let mut jump = None;
+        let mut unpin = None;
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for pin in &pins.messages {
+                let name = pin.sender.as_deref().map_or("", |s| self.display_name(s));
+                ui.label(quote_line(name, &pin.body));
+                ui.horizontal(|ui| {
+                    let loaded = self.find_message(&pin.event_id).is_some();
+                    let go = ui.add_enabled(loaded, egui::Button::new("Jump").small());
+                    if go
+                        .on_disabled_hover_text("Not loaded; scroll up to find it")
+                        .clicked()
+                    {
+                        jump = Some(pin.event_id.clone());
+                    }
+                    if pins.can_pin && ui.small_button("Unpin").clicked() {
+                        unpin = Some(pin.event_id.clone());
+                    }
+                });
+                ui.separator();
+            }
+        });
+        if let Some(event_id) = jump {
+            self.focused_message = Some(event_id.clone());
+            self.jump_to = Some(event_id);
+        }
+        if let Some(event_id) = unpin {
+            let _ = self.cmd_tx.send(AppCommand::SetPinned {
+                room_id: room_id.to_owned(),
+                event_id,
+                pinned: false,
+            });
+        }
+    }
+
+    /// Ask for `room_id`'s pins unless it has been already.
+    fn request_pins(&mut self, room_id: &str) {
+        if self.pins_requested.insert(room_id.to_owned()) {
+            let _ = self.cmd_tx.send(AppCommand::FetchPins {
+                room_id: room_id.to_owned(),
+            });
+        }
+    }
+
+    /// Thread roots in this room with their reply counts, or one open
+    /// thread's replies.
+    fn threads_panel_ui(&mut self, ui: &mut egui::Ui, room_id: &str) {
+        let log = self.messages.get(room_id).map(Vec::as_slice).unwrap_or(&[]);
+        let mut open: Option<Option<String>> = None;
+        egui::ScrollArea::vertical().show(ui, |ui| match self.thread_view.as_deref() {
+            Some(root) => {
+                if ui.small_button("← All threads").clicked() {
+                    open = Some(None);
+                }
+                for m in log.iter().filter(|m| {
+                    m.event_id.as_deref() == Some(root) || m.thread_root.as_deref() == Some(root)
+                }) {
+                    ui.label(quote_line(self.display_name(&m.sender), &m.body));
+                }
+            }
+            None => {
+                let mut any = false;
+                for m in log.iter().filter(|m| m.thread_root.is_none()) {
+                    let Some(id) = m.event_id.as_deref() else {
+                        continue;
+                    };
+                    let replies = log
+                        .iter()
+                        .filter(|r| r.thread_root.as_deref() == Some(id))
+                        .count();
+                    if replies == 0 {
+                        continue;
+                    }
+                    any = true;
+                    let line = format!(
+                        "{} · {replies} 🧵",
+                        quote_line(self.display_name(&m.sender), &m.body)
+                    );
+                    if ui.selectable_label(false, line).clicked() {
+                        open = Some(Some(id.to_owned()));
+                    }
+                }
+                if !any {
+                    ui.weak("No threads yet.");
+                }
+            }
+        });
+        match open {
+            Some(Some(root)) => {
+                self.thread_view = Some(root.clone());
+                self.composer.relation = Some(MessageRelation::Thread { root });
+            }
+            Some(None) => {
+                self.thread_view = None;
+                if let Some(MessageRelation::Thread { .. }) = self.composer.relation {
+                    self.composer.relation = None;
+                }
+            }
+            None => {}
+        }
+    }
+
+    /// Ask for `room_id`'s member list unless it has been already.
+    fn request_members(&mut self, room_id: &str) {
+        if self.members_requested.insert(room_id.to_owned()) {
+            let _ = self.cmd_tx.send(AppCommand::FetchMembers {
+                room_id: room_id.to_owned(),
+            });
+        }
+    }
+
+    /// Member list, loaded on first open; the members we already know show
+    /// while the rest load. Profiles (for avatars) are only resolved for the
+    /// rows actually scrolled into view.
+    fn members_panel_ui(&mut self, ui: &mut egui::Ui, room_id: &str) {
+        self.request_members(room_id);
+        let Some(members) = self.members.get(room_id) else {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.weak("Loading members…");
+            });
+            return;
+        };
+
+        let mut refresh = false;
+        ui.horizontal(|ui| {
+            let joined = members
+                .iter()
+                .filter(|m| m.membership == MembershipState::Join)
+                .count();
+            if self.members_loading.contains(room_id) {
+                ui.spinner();
+                ui.small(format!("{joined} joined so far"));
+            } else {
+                ui.small(format!("{joined} joined"));
+                if ui.small_button("⟳").on_hover_text("Reload").clicked() {
+                    refresh = true;
+                }
+            }
+        });
+
+        let mut unresolved: Vec<String> = Vec::new();
+        egui::ScrollArea::vertical().show_rows(
+            ui,
+            AVATAR_POINTS + 4.0,
+            members.len(),
+            |ui, rows| {
+                for m in &members[rows] {
+                    let profile = self.profiles.get(&m.user_id);
+                    if profile.is_none() && !self.profiles_requested.contains(&m.user_id) {
+                        unresolved.push(m.user_id.clone());
                     }
+                    ui.horizontal(|ui| {
+                        presence_dot(ui, m.presence.as_ref());
+                        avatar_ui(ui, &m.user_id, profile);
+                        let name = egui::RichText::new(m.name());
+                        let name = if m.membership == MembershipState::Invite {
+                            name.italics().weak()
+                        } else {
+                            name.color(sender_color(ui.visuals(), &m.user_id))
+                        };
+                        ui.label(name).on_hover_text(&m.user_id);
+                        let role = if m.power_level >= ADMIN_LEVEL {
+                            Some("Admin")
+                        } else if m.power_level >= MODERATOR_LEVEL {
+                            Some("Mod")
+                        } else {
+                            None
+                        };
+                        if let Some(role) = role {
+                            ui.small(egui::RichText::new(role).weak());
+                        }
+                    });
+                }
+            },
+        );
+
+        if refresh {
+            let _ = self.cmd_tx.send(AppCommand::FetchMembers {
+                room_id: room_id.to_owned(),
+            });
+        }
+        unresolved.retain(|u| self.profiles_requested.insert(u.clone()));
+        if !unresolved.is_empty() {
+            let _ = self.cmd_tx.send(AppCommand::ResolveProfiles {
+                room_id: room_id.to_owned(),
+                user_ids: unresolved,
+            });
+        }
+    }
+
+    /// Float in-call emoji reactions up over the message pane.
+    fn paint_reactions(&mut self, ctx: &egui::Context, rect: egui::Rect) {
+        let now = ctx.input(|i| i.time);
+        self.floating_reactions
+            .retain(|r| now - r.started < REACTION_LIFETIME);
+        if self.floating_reactions.is_empty() {
+            return;
+        }
+
+        let painter = ctx.layer_painter(egui::LayerId::new(
+            egui::Order::Foreground,
+            egui::Id::new("call_reactions"),
+        ));
+        for r in &self.floating_reactions {
+            let t = ((now - r.started) / REACTION_LIFETIME) as f32;
+            let pos = egui::pos2(
+                rect.left() + rect.width() * r.x,
+                rect.bottom() - 40.0 - t * rect.height() * 0.6,
+            );
+            let alpha = ((1.0 - t) * 255.0) as u8;
+            painter.text(
+                pos,
+                egui::Align2::CENTER_CENTER,
+                &r.emoji,
+                egui::FontId::proportional(32.0),
+                egui::Color32::from_white_alpha(alpha),
+            );
+            painter.text(
+                pos + egui::vec2(0.0, 22.0),
+                egui::Align2::CENTER_TOP,
+                &r.sender,
+                egui::FontId::proportional(10.0),
+                egui::Color32::from_gray(180).gamma_multiply(1.0 - t),
+            );
+        }
+        ctx.request_repaint();
+    }
+
+    /// Stack of fading join/leave notices in the top-right of the call view.
+    fn paint_call_toasts(&mut self, ctx: &egui::Context, rect: egui::Rect) {
+        let now = ctx.input(|i| i.time);
+        self.call_toasts
+            .retain(|(_, shown)| now - shown < CALL_TOAST_LIFETIME);
+        if self.call_toasts.is_empty() {
+            return;
+        }
+
+        let painter = ctx.layer_painter(egui::LayerId::new(
+            egui::Order::Foreground,
+            egui::Id::new("call_toasts"),
+        ));
+        let mut pos = rect.right_top() + egui::vec2(-12.0, 44.0);
+        for (text, shown) in &self.call_toasts {
+            // Fade out over the last second.
+            let fade = (CALL_TOAST_LIFETIME - (now - shown)).min(1.0) as f32;
+            let galley = painter.layout_no_wrap(
+                text.clone(),
+                egui::FontId::proportional(13.0),
+                egui::Color32::from_white_alpha((fade * 255.0) as u8),
+            );
+            let bg = egui::Rect::from_min_size(
+                pos - egui::vec2(galley.size().x + 16.0, 0.0),
+                galley.size() + egui::vec2(16.0, 8.0),
+            );
+            painter.rect_filled(
+                bg,
+                4.0,
+                egui::Color32::from_black_alpha((fade * 180.0) as u8),
+            );
+            painter.galley(bg.min + egui::vec2(8.0, 4.0), galley, egui::Color32::WHITE);
+            pos.y += bg.height() + 6.0;
+        }
+        ctx.request_repaint();
+    }
+
+    fn show_explore_dialog(&mut self, ctx: &egui::Context) {
+        let mut open = true;
+        egui::Window::new("Explore Rooms")
+            .collapsible(false)
+            .default_width(420.0)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let mut search = false;
+                ui.horizontal(|ui| {
+                    let resp = ui.add(
+                        egui::TextEdit::singleline(&mut self.explore_query)
+                            .hint_text("Search public rooms")
+                            .desired_width(220.0),
+                    );
+                    let enter = resp.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.explore_server)
+                            .hint_text("server (optional)")
+                            .desired_width(120.0),
+                    );
+                    search = ui.button("Search").clicked() || enter;
                 });
+
+                // First open: list the directory without a search term.
+                if search || self.explore_results_query.is_none() {
+                    self.request_directory_page(None);
+                }
+
+                ui.separator();
+                egui::ScrollArea::vertical()
+                    .max_height(360.0)
+                    .show(ui, |ui| {
+                        let mut join: Option<String> = None;
+                        for room in &self.explore_results {
+                            ui.horizontal(|ui| {
+                                ui.vertical(|ui| {
+                                    ui.strong(room.display_name());
+                                    if let Some(topic) = &room.topic {
+                                        ui.small(topic);
+                                    }
+                                    ui.small(format!("{} members", room.num_joined_members));
+                                });
+                                ui.with_layout(
+                                    egui::Layout::right_to_left(egui::Align::Center),
+                                    |ui| {
+                                        let joined = self
+                                            .rooms
+                                            .iter()
+                                            .any(|r| r.id == room.room_id.as_str());
+                                        let banned = self.left_rooms.iter().any(|r| {
+                                            r.banned && r.room_id == room.room_id.as_str()
+                                        });
+                                        if joined {
+                                            ui.small("Joined");
+                                        } else if banned {
+                                            ui.small("Banned");
+                                        } else if ui.button("Join").clicked() {
+                                            join = Some(room.room_id.to_string());
+                                        }
+                                    },
+                                );
+                            });
+                            ui.separator();
+                        }
+                        if let Some(alias) = join {
+                            let _ = self.cmd_tx.send(AppCommand::JoinRoomByAlias { alias });
+                        }
+
+                        if self.explore_loading {
+                            ui.label("Loading…");
+                        } else if self.explore_results.is_empty() {
+                            ui.label("No rooms found.");
+                        } else if let Some(next) = self.explore_next_batch.clone() {
+                            if ui.button("Load more").clicked() {
+                                self.request_directory_page(Some(next));
+                            }
+                        }
+                    });
+            });
+        if !open {
+            self.ui.close(Dialog::Explore);
+            self.explore_results_query = None;
+            self.explore_results.clear();
+            self.explore_next_batch = None;
+        }
+    }
+
+    /// Ask the bridge for a directory page for the current query and server.
+    fn request_directory_page(&mut self, since: Option<String>) {
+        let query = self.explore_query.trim().to_owned();
+        let server = Some(self.explore_server.trim().to_owned()).filter(|s| !s.is_empty());
+        self.explore_results_query = Some(query.clone());
+        self.explore_loading = true;
+        let _ = self.cmd_tx.send(AppCommand::SearchDirectory {
+            query,
+            server,
+            since,
         });
     }
-}
 
-impl SpokeApp {
+    /// The account an SSO login may resume, if the username field names one.
+    fn sso_username(&self) -> Option<String> {
+        Some(self.login_username.trim().to_owned()).filter(|u| !u.is_empty())
+    }
+
+    /// Run the login preflight for the homeserver field if it changed since
+    /// the last check.
+    fn probe_homeserver(&mut self, ctx: &egui::Context) {
+        let homeserver = self.login_homeserver.trim();
+        if homeserver.is_empty()
+            || self.server_probe.as_deref() == Some(homeserver)
+            || self
+                .server_info
+                .as_ref()
+                .is_some_and(|(hs, _)| hs == homeserver)
+        {
+            return;
+        }
+        let Some((event_tx, _)) = &self.pending_spawn else {
+            return;
+        };
+        spawn_server_probe(event_tx.clone(), ctx.clone(), homeserver.to_owned());
+        self.server_probe = Some(homeserver.to_owned());
+    }
+
     fn show_login_panel(&mut self, ctx: &egui::Context) {
         egui::CentralPanel::default().show(ctx, |ui| {
             let available_height = ui.available_height();
@@ -464,23 +7242,46 @@ impl SpokeApp {
                 ui.heading("Spoke");
                 ui.add_space(16.0);
 
+                // What the server supports, once we know. Until then, offer
+                // password login as before.
+                let info = self
+                    .server_info
+                    .as_ref()
+                    .filter(|(hs, _)| *hs == self.login_homeserver.trim())
+                    .and_then(|(_, result)| result.as_ref().ok())
+                    .cloned();
+                let password_login = info.as_ref().is_none_or(|i| i.password_login);
+
                 egui::Grid::new("login_fields")
                     .num_columns(2)
                     .spacing([12.0, 8.0])
                     .show(ui, |ui| {
                         ui.label("Homeserver");
-                        ui.add(
+                        let homeserver = ui.add(
                             egui::TextEdit::singleline(&mut self.login_homeserver)
                                 .desired_width(240.0),
                         );
+                        if !homeserver.has_focus() {
+                            self.probe_homeserver(ctx);
+                        }
                         ui.end_row();
 
+                        // With SSO the username only picks which saved
+                        // session to resume.
                         ui.label("Username");
                         ui.add(
                             egui::TextEdit::singleline(&mut self.login_username)
+                                .hint_text(if password_login {
+                                    ""
+                                } else {
+                                    "to resume a session"
+                                })
                                 .desired_width(240.0),
                         );
                         ui.end_row();
+                        if !password_login {
+                            return;
+                        }
 
                         ui.label("Password");
                         ui.add(
@@ -491,35 +7292,122 @@ impl SpokeApp {
                         ui.end_row();
                     });
 
+                let probe_error = self
+                    .server_info
+                    .as_ref()
+                    .filter(|(hs, _)| *hs == self.login_homeserver.trim())
+                    .and_then(|(_, result)| result.as_ref().err());
+                let mut blocked = false;
+                ui.add_space(6.0);
+                match (&info, probe_error) {
+                    _ if self.server_probe.is_some() => {
+                        ui.weak("Checking server…");
+                    }
+                    (_, Some(e)) => {
+                        ui.colored_label(
+                            ui.visuals().warn_fg_color,
+                            format!("Can't reach server: {e}"),
+                        );
+                    }
+                    (Some(info), _) => match info.problem() {
+                        Some(problem) => {
+                            blocked = true;
+                            ui.colored_label(egui::Color32::RED, problem);
+                        }
+                        None if !info.password_login => {
+                            ui.label("This server requires SSO");
+                        }
+                        None => {
+                            let registration = match info.registration {
+                                Registration::Open => " · new usernames are registered",
+                                Registration::Closed => " · registration closed",
+                                Registration::Unknown => "",
+                            };
+                            let newest = info.versions.last().map(String::as_str).unwrap_or("?");
+                            ui.weak(format!("Matrix {newest}{registration}"));
+                        }
+                    },
+                    (None, None) => {}
+                }
+
                 ui.add_space(12.0);
 
-                let can_submit = !self.login_connecting
-                    && !self.login_homeserver.is_empty()
-                    && !self.login_username.is_empty()
-                    && !self.login_password.is_empty();
+                let mut login: Option<Login> = None;
+                if password_login {
+                    let can_submit = !self.login_connecting
+                        && !blocked
+                        && !self.login_homeserver.is_empty()
+                        && !self.login_username.is_empty()
+                        && !self.login_password.is_empty();
+                    let enter_pressed = ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    let login_clicked = ui
+                        .add_enabled(can_submit, egui::Button::new("Log in"))
+                        .clicked();
+                    if login_clicked || (enter_pressed && can_submit) {
+                        login = Some(Login::Password {
+                            username: self.login_username.clone(),
+                            password: self.login_password.clone(),
+                            // Don't try to register where the server says it's closed.
+                            register: info
+                                .as_ref()
+                                .is_none_or(|i| i.registration != Registration::Closed),
+                        });
+                    }
+                }
 
-                let enter_pressed = ui.input(|i| i.key_pressed(egui::Key::Enter));
-                let login_clicked =
-                    ui.add_enabled(can_submit, egui::Button::new("Log in")).clicked();
+                // SSO, with one button per identity provider the server lists.
+                if let Some(info) = info.as_ref().filter(|i| i.sso_login && !blocked) {
+                    ui.add_space(6.0);
+                    ui.add_enabled_ui(!self.login_connecting, |ui| {
+                        if info.sso_providers.is_empty() {
+                            if ui.button("Log in with SSO").clicked() {
+                                login = Some(Login::Sso {
+                                    idp_id: None,
+                                    username: self.sso_username(),
+                                });
+                            }
+                        }
+                        for provider in &info.sso_providers {
+                            if ui
+                                .button(format!("Continue with {}", provider.name))
+                                .clicked()
+                            {
+                                login = Some(Login::Sso {
+                                    idp_id: Some(provider.id.clone()),
+                                    username: self.sso_username(),
+                                });
+                            }
+                        }
+                    });
+                }
 
-                if login_clicked || (enter_pressed && can_submit) {
+                if let Some(login) = login {
                     if let Some((event_tx, cmd_rx)) = self.pending_spawn.take() {
+                        let sync = self.settings.sync.clone();
+                        let invisible = self.settings.privacy.invisible.clone();
                         spawn_matrix_task(
                             event_tx,
                             cmd_rx,
                             ctx.clone(),
                             self.login_homeserver.clone(),
-                            self.login_username.clone(),
-                            self.login_password.clone(),
+                            login,
+                            sync,
+                            invisible,
+                            client_options(&self.settings),
                         );
                         self.login_connecting = true;
                         self.login_error = None;
+                        self.sso_url = None;
                     }
                 }
 
                 if self.login_connecting {
                     ui.add_space(8.0);
                     ui.label("Connecting…");
+                    if let Some(url) = &self.sso_url {
+                        ui.small("Finish signing in in your browser. If it didn't open:");
+                        ui.hyperlink_to("Open the sign-in page", url);
+                    }
                 }
 
                 if let Some(err) = &self.login_error {
@@ -530,3 +7418,765 @@ impl SpokeApp {
         });
     }
 }
+
+// ── Room list ─────────────────────────────────────────────────────────────────
+
+/// Longest preview line under a room name, in characters.
+const PREVIEW_CHARS: usize = 48;
+
+enum RoomEntryAction {
+    Select,
+    TogglePin,
+    Report,
+}
+
+/// Notifying messages in a room since it was last read.
+#[derive(Debug, Clone, Copy, Default)]
+struct Unread {
+    notifications: u32,
+    /// At least one of them mentioned us or matched a keyword.
+    highlight: bool,
+    /// How many of them name us directly.
+    mentions: u32,
+}
+
+/// A sidebar room: its name and how long since it was last active, over a
+/// one-line preview of the latest message, with a context menu to pin it to
+/// the top.
+fn room_entry_ui(
+    ui: &mut egui::Ui,
+    room: &RoomInfo,
+    selected: bool,
+    pinned: bool,
+    unread: Option<Unread>,
+    now: u64,
+) -> Option<RoomEntryAction> {
+    let mut job = egui::text::LayoutJob::default();
+    let body = egui::TextStyle::Body.resolve(ui.style());
+    let small = egui::TextStyle::Small.resolve(ui.style());
+    let name = if pinned {
+        format!("📌 {}", room.name)
+    } else {
+        room.name.clone()
+    };
+    job.append(
+        &name,
+        0.0,
+        egui::TextFormat::simple(body.clone(), ui.visuals().text_color()),
+    );
+    if let Some(unread) = unread {
+        let (badge, color) = if unread.mentions > 0 {
+            (
+                format!("@{} · {}", unread.mentions, unread.notifications),
+                egui::Color32::from_rgb(220, 60, 60),
+            )
+        } else if unread.highlight {
+            (
+                format!("!{}", unread.notifications),
+                egui::Color32::from_rgb(220, 60, 60),
+            )
+        } else {
+            (
+                unread.notifications.to_string(),
+                ui.visuals().strong_text_color(),
+            )
+        };
+        job.append(
+            &format!(" ({badge})"),
+            0.0,
+            egui::TextFormat::simple(body, color),
+        );
+    }
+    let ts = room.activity_ts();
+    if ts > 0 {
+        let age = egui::TextFormat::simple(small.clone(), ui.visuals().weak_text_color());
+        job.append(&format!("  {}", short_age(ts, now)), 0.0, age);
+    }
+    if let Some(preview) = &room.last_message {
+        let sender = preview
+            .sender
+            .trim_start_matches('@')
+            .split(':')
+            .next()
+            .unwrap_or(&preview.sender);
+        let line = preview.body.lines().next().unwrap_or_default();
+        let mut text = format!("{sender}: {line}");
+        if let Some((cut, _)) = text.char_indices().nth(PREVIEW_CHARS) {
+            text.truncate(cut);
+            text.push('…');
+        }
+        job.append(
+            &format!("\n{text}"),
+            0.0,
+            egui::TextFormat::simple(small, ui.visuals().weak_text_color()),
+        );
+    }
+
+    let members = format!("{} members", room.num_joined_members);
+    let hover = match &room.topic {
+        Some(topic) => format!("{topic}\n{members}"),
+        None => members,
+    };
+    let resp = ui.selectable_label(selected, job).on_hover_text(hover);
+    let mut action = resp.clicked().then_some(RoomEntryAction::Select);
+    resp.context_menu(|ui| {
+        if ui
+            .button(if pinned { "Unpin" } else { "Pin to top" })
+            .clicked()
+        {
+            action = Some(RoomEntryAction::TogglePin);
+            ui.close_menu();
+        }
+        if ui.button("Report room…").clicked() {
+            action = Some(RoomEntryAction::Report);
+            ui.close_menu();
+        }
+    });
+    action
+}
+
+/// How close to the top of the timeline, in points, older history is fetched.
+const SCROLLBACK_TRIGGER_POINTS: f32 = 200.0;
+
+/// Timeline avatar edge in points.
+const AVATAR_POINTS: f32 = 20.0;
+
+/// Small coloured dot for a member's presence; blank space when unknown.
+fn presence_dot(ui: &mut egui::Ui, presence: Option<&PresenceState>) {
+    let (rect, resp) = ui.allocate_exact_size(egui::vec2(8.0, 8.0), egui::Sense::hover());
+    let (color, label) = match presence {
+        Some(PresenceState::Online) => (egui::Color32::from_rgb(60, 180, 90), "Online"),
+        Some(PresenceState::Unavailable) => (egui::Color32::from_rgb(220, 170, 40), "Away"),
+        Some(PresenceState::Offline) => (egui::Color32::GRAY, "Offline"),
+        _ => return,
+    };
+    ui.painter().circle_filled(rect.center(), 4.0, color);
+    resp.on_hover_text(label);
+}
+
+/// A user's avatar thumbnail, or their initial on their name colour while
+/// there's none.
+fn avatar_ui(ui: &mut egui::Ui, user_id: &str, profile: Option<&SenderProfile>) {
+    let size = egui::vec2(AVATAR_POINTS, AVATAR_POINTS);
+    if let Some((mxc, bytes)) = profile.and_then(|p| p.avatar_url.as_ref().zip(p.avatar.as_ref())) {
+        ui.add(
+            egui::Image::from_bytes(
+                format!("bytes://{mxc}"),
+                egui::load::Bytes::Shared(bytes.clone()),
+            )
+            .fit_to_exact_size(size)
+            .corner_radius(AVATAR_POINTS / 2.0),
+        );
+        return;
+    }
+    let name = profile
+        .and_then(|p| p.display_name.as_deref())
+        .unwrap_or(user_id);
+    let initial = name
+        .trim_start_matches('@')
+        .chars()
+        .next()
+        .unwrap_or('?')
+        .to_uppercase()
+        .to_string();
+    let fill = SENDER_PALETTE[user_color_index(user_id)].1;
+    let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+    ui.painter()
+        .circle_filled(rect.center(), AVATAR_POINTS / 2.0, fill);
+    ui.painter().text(
+        rect.center(),
+        egui::Align2::CENTER_CENTER,
+        initial,
+        egui::FontId::proportional(AVATAR_POINTS * 0.6),
+        egui::Color32::WHITE,
+    );
+}
+
+/// Combo box choosing an audio device from `names`, or the system default.
+/// A chosen device that's gone stays selected, marked as missing. Returns
+/// whether the choice changed.
+fn device_picker(
+    ui: &mut egui::Ui,
+    id: &str,
+    choice: &mut Option<String>,
+    names: &[String],
+    default: Option<&str>,
+) -> bool {
+    let default_label = match default {
+        Some(name) => format!("System default ({name})"),
+        None => "System default".to_owned(),
+    };
+    let selected = match choice.as_deref() {
+        None => default_label.clone(),
+        Some(name) if names.iter().any(|n| n == name) => name.to_owned(),
+        Some(name) => format!("{name} (not connected)"),
+    };
+    let mut changed = false;
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(selected)
+        .show_ui(ui, |ui| {
+            changed |= ui.selectable_value(choice, None, default_label).changed();
+            for name in names {
+                changed |= ui
+                    .selectable_value(choice, Some(name.clone()), name)
+                    .changed();
+            }
+        });
+    changed
+}
+
+/// Green ring drawn around the avatar at `rect` while its owner talks.
+fn speaking_ring(ui: &egui::Ui, rect: egui::Rect) {
+    let stroke = egui::Stroke::new(2.0, egui::Color32::from_rgb(60, 180, 90));
+    ui.painter()
+        .circle_stroke(rect.center(), AVATAR_POINTS / 2.0 + 1.5, stroke);
+}
+
+/// Name colours, as (on dark, on light) pairs: the same hues lightened or
+/// darkened so each stays readable on its theme's background.
+const SENDER_PALETTE: [(egui::Color32, egui::Color32); 8] = [
+    (
+        egui::Color32::from_rgb(0x6c, 0xb4, 0xff),
+        egui::Color32::from_rgb(0x1f, 0x5f, 0xb8),
+    ),
+    (
+        egui::Color32::from_rgb(0xff, 0x8a, 0x80),
+        egui::Color32::from_rgb(0xb3, 0x2d, 0x26),
+    ),
+    (
+        egui::Color32::from_rgb(0x7d, 0xd8, 0x8a),
+        egui::Color32::from_rgb(0x1e, 0x7a, 0x34),
+    ),
+    (
+        egui::Color32::from_rgb(0xe0, 0x9b, 0xf5),
+        egui::Color32::from_rgb(0x8a, 0x2f, 0xa8),
+    ),
+    (
+        egui::Color32::from_rgb(0xff, 0xc2, 0x5c),
+        egui::Color32::from_rgb(0x9a, 0x5b, 0x00),
+    ),
+    (
+        egui::Color32::from_rgb(0x5f, 0xd8, 0xd0),
+        egui::Color32::from_rgb(0x0b, 0x6e, 0x69),
+    ),
+    (
+        egui::Color32::from_rgb(0xff, 0x9c, 0xc8),
+        egui::Color32::from_rgb(0xb0, 0x24, 0x6a),
+    ),
+    (
+        egui::Color32::from_rgb(0xb0, 0xb8, 0xff),
+        egui::Color32::from_rgb(0x45, 0x4f, 0xc2),
+    ),
+];
+
+/// Which palette entry `user_id` gets: an FNV-1a hash of the MXID, so a
+/// user has the same colour everywhere and on every client run.
+fn user_color_index(user_id: &str) -> usize {
+    let hash = user_id.bytes().fold(0x811c_9dc5u32, |h, b| {
+        (h ^ u32::from(b)).wrapping_mul(0x0100_0193)
+    });
+    hash as usize % SENDER_PALETTE.len()
+}
+
+/// The colour to draw `user_id`'s name in on the current theme.
+fn sender_color(visuals: &egui::Visuals, user_id: &str) -> egui::Color32 {
+    let (dark, light) = SENDER_PALETTE[user_color_index(user_id)];
+    if visuals.dark_mode { dark } else { light }
+}
+
+/// Compact age for the sidebar: "now", "5m", "3h", "2d".
+fn short_age(ts: u64, now: u64) -> String {
+    let mins = now.saturating_sub(ts) / 60_000;
+    match mins {
+        0 => "now".into(),
+        1..60 => format!("{mins}m"),
+        60..1440 => format!("{}h", mins / 60),
+        _ => format!("{}d", mins / 1440),
+    }
+}
+
+// ── Sessions ──────────────────────────────────────────────────────────────────
+
+/// "5 min ago"-style age of a session's last activity.
+fn last_seen_label(ts: Option<u64>, now: u64) -> String {
+    let Some(ts) = ts else {
+        return "never seen".into();
+    };
+    let mins = now.saturating_sub(ts) / 60_000;
+    match mins {
+        0 => "active now".into(),
+        1..60 => format!("{mins} min ago"),
+        60..1440 => format!("{} h ago", mins / 60),
+        _ => format!("{} days ago", mins / 1440),
+    }
+}
+
+// ── Message actions ───────────────────────────────────────────────────────────
+
+/// Longest room name suggested for a spin-off, in characters.
+const SPIN_OFF_NAME_CHARS: usize = 40;
+
+fn composer_id() -> egui::Id {
+    egui::Id::new("composer")
+}
+
+/// `name: first line of body`, cut short for a one-line quote.
+fn quote_line(name: &str, body: &str) -> String {
+    let mut line = format!("{name}: {}", body.lines().next().unwrap_or_default());
+    if let Some((cut, _)) = line.char_indices().nth(PREVIEW_CHARS) {
+        line.truncate(cut);
+        line.push('…');
+    }
+    line
+}
+
+/// One message in a copied transcript: `name: body`, with any further
+/// lines of the body indented under it.
+fn transcript_entry(name: &str, body: &str) -> String {
+    format!("{name}: {}", body.replace('\n', "\n    "))
+}
+
+/// Reactions on one event grouped by key, in first-seen order: the key, who
+/// reacted, and our own reaction's event ID if we're among them.
+fn group_reactions<'a>(
+    reactions: &'a [Reaction],
+    own_user_id: &str,
+) -> Vec<(&'a str, Vec<&'a str>, Option<&'a str>)> {
+    let mut groups: Vec<(&str, Vec<&str>, Option<&str>)> = Vec::new();
+    for r in reactions {
+        let i = match groups.iter().position(|(key, ..)| *key == r.key) {
+            Some(i) => i,
+            None => {
+                groups.push((r.key.as_str(), Vec::new(), None));
+                groups.len() - 1
+            }
+        };
+        let (_, senders, mine) = &mut groups[i];
+        if !senders.contains(&r.sender.as_str()) {
+            senders.push(&r.sender);
+        }
+        if r.sender == own_user_id {
+            *mine = Some(r.event_id.as_str());
+        }
+    }
+    groups
+}
+
+// ── Link previews ─────────────────────────────────────────────────────────────
+
+/// Width of a link preview card.
+const LINK_PREVIEW_WIDTH: f32 = 360.0;
+
+/// Seconds before a link preview that failed to load is asked for again.
+const URL_PREVIEW_RETRY: f64 = 30.0;
+
+/// The first web link in a message, from its formatted body's links when it
+/// has one, else from the plain text. Mention pills don't count.
+fn first_link(m: &TimelineItem) -> Option<String> {
+    let is_web = |url: &str| {
+        (url.starts_with("https://") || url.starts_with("http://"))
+            && !url.starts_with("https://matrix.to/")
+    };
+    if let Some(rich) = &m.rich {
+        let spans = rich.blocks.iter().flat_map(|block| match block {
+            Block::Paragraph(spans) | Block::Heading(_, spans) | Block::Quote(spans) => {
+                spans.iter().collect::<Vec<_>>()
+            }
+            Block::List { items, .. } => items.iter().flatten().collect(),
+            Block::Code { .. } | Block::Rule => Vec::new(),
+        });
+        return spans
+            .filter_map(|span| span.link.as_deref())
+            .find(|url| is_web(url))
+            .map(str::to_owned);
+    }
+    m.body
+        .split_whitespace()
+        .map(|word| {
+            word.trim_matches(|c: char| {
+                matches!(
+                    c,
+                    '<' | '>' | '(' | ')' | ',' | '.' | '!' | '?' | '"' | '\''
+                )
+            })
+        })
+        .find(|word| is_web(word))
+        .map(str::to_owned)
+}
+
+/// A card with the page's site, title, description and image.
+fn link_preview_ui(ui: &mut egui::Ui, link: &LinkPreview) {
+    let preview = &link.preview;
+    egui::Frame::group(ui.style()).show(ui, |ui| {
+        ui.set_max_width(LINK_PREVIEW_WIDTH);
+        ui.vertical(|ui| {
+            if let Some(site) = &preview.site_name {
+                ui.weak(site);
+            }
+            let title = preview.title.as_deref().unwrap_or(&preview.url);
+            ui.hyperlink_to(egui::RichText::new(title).strong(), &preview.url);
+            if let Some(description) = &preview.description {
+                ui.add(egui::Label::new(description).wrap());
+            }
+            if let Some((mxc, bytes)) = preview.image.as_ref().zip(link.image.as_ref()) {
+                ui.add(
+                    egui::Image::from_bytes(
+                        format!("bytes://{mxc}"),
+                        egui::load::Bytes::Shared(bytes.clone()),
+                    )
+                    .max_width(LINK_PREVIEW_WIDTH)
+                    .corner_radius(4.0),
+                );
+            }
+        });
+    });
+}
+
+// ── Stickers ──────────────────────────────────────────────────────────────────
+
+/// Largest side of a sticker in the timeline.
+const STICKER_POINTS: f32 = 128.0;
+
+/// Side of an image in the sticker picker.
+const PICKER_STICKER_POINTS: f32 = 48.0;
+
+/// A sticker's image, or its description until the image has loaded.
+fn sticker_ui(
+    ui: &mut egui::Ui,
+    mxc: &str,
+    body: &str,
+    images: &markup::Images,
+    wanted: &mut HashSet<String>,
+) {
+    match images.get(mxc) {
+        Some(Some(bytes)) => {
+            ui.add(
+                egui::Image::from_bytes(
+                    format!("bytes://{mxc}"),
+                    egui::load::Bytes::Shared(bytes.clone()),
+                )
+                .max_size(egui::vec2(STICKER_POINTS, STICKER_POINTS)),
+            )
+            .on_hover_text(body);
+        }
+        Some(None) => {
+            ui.weak(format!("[sticker] {body}"));
+        }
+        None => {
+            wanted.insert(mxc.to_owned());
+            ui.weak(format!("[sticker] {body}"));
+        }
+    }
+}
+
+// ── Voice messages ────────────────────────────────────────────────────────────
+
+/// Size of a voice message's waveform.
+const WAVEFORM_SIZE: egui::Vec2 = egui::vec2(160.0, 24.0);
+
+/// Play/stop button, waveform and duration. Returns whether the button was
+/// clicked.
+fn voice_message_ui(ui: &mut egui::Ui, voice: &VoiceMessage, playing: bool) -> bool {
+    let clicked = ui
+        .small_button(if playing { "⏹" } else { "▶" })
+        .on_hover_text(if playing {
+            "Stop"
+        } else {
+            "Play voice message"
+        })
+        .clicked();
+    let (rect, _) = ui.allocate_exact_size(WAVEFORM_SIZE, egui::Sense::hover());
+    let color = if playing {
+        ui.visuals().selection.stroke.color
+    } else {
+        ui.visuals().text_color()
+    };
+    if voice.waveform.is_empty() {
+        ui.painter().hline(
+            rect.x_range(),
+            rect.center().y,
+            egui::Stroke::new(1.0, color),
+        );
+    } else {
+        let step = rect.width() / voice.waveform.len() as f32;
+        for (i, &value) in voice.waveform.iter().enumerate() {
+            let height =
+                (value as f32 / recording::WAVEFORM_MAX as f32).clamp(0.05, 1.0) * rect.height();
+            let x = rect.left() + (i as f32 + 0.5) * step;
+            let half = egui::vec2(0.0, height / 2.0);
+            let center = egui::pos2(x, rect.center().y);
+            let stroke = egui::Stroke::new((step * 0.6).max(1.0), color);
+            ui.painter()
+                .line_segment([center - half, center + half], stroke);
+        }
+    }
+    let secs = voice.duration_ms / 1000;
+    ui.weak(format!("{}:{:02}", secs / 60, secs % 60));
+    clicked
+}
+
+// ── Watch together ────────────────────────────────────────────────────────────
+
+/// How far the shared player's seek buttons jump.
+const SEEK_STEP_MS: u64 = 10_000;
+
+/// `h:mm:ss`, or `m:ss` under an hour.
+fn format_position(ms: u64) -> String {
+    let secs = ms / 1000;
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if h > 0 {
+        format!("{h}:{m:02}:{s:02}")
+    } else {
+        format!("{m}:{s:02}")
+    }
+}
+
+// ── Call summary ──────────────────────────────────────────────────────────────
+
+/// Suggested file for a call's chat export, e.g. `~/Documents/spoke-call-1700000000000.txt`.
+fn call_export_path(started_ts: u64) -> std::path::PathBuf {
+    dirs::document_dir()
+        .or_else(dirs::home_dir)
+        .unwrap_or_default()
+        .join(format!("spoke-call-{started_ts}.txt"))
+}
+
+/// The summary followed by every message sent during the call, each stamped
+/// with its offset from the start.
+fn call_transcript(summary: &VoiceSummaryEventContent, messages: &[TimelineItem]) -> String {
+    let end = summary.started_ts + summary.duration_secs * 1000;
+    let mut out = format!("{}\n", summary.describe());
+    for user_id in &summary.participants {
+        out.push_str(&format!("  {user_id}\n"));
+    }
+    out.push('\n');
+    for m in messages
+        .iter()
+        .filter(|m| (summary.started_ts..=end).contains(&m.ts))
+    {
+        let secs = (m.ts - summary.started_ts) / 1000;
+        out.push_str(&format!(
+            "[+{:02}:{:02}] {}: {}\n",
+            secs / 60,
+            secs % 60,
+            m.sender,
+            m.body
+        ));
+    }
+    out
+}
+
+// ── Voice settings ────────────────────────────────────────────────────────────
+
+/// Participant cap offered when a limit is first switched on.
+const DEFAULT_PARTICIPANT_CAP: u32 = 25;
+
+/// The room settings dialog's voice tab. Returns the config to send when
+/// Save is pressed.
+fn voice_config_ui(
+    ui: &mut egui::Ui,
+    voice: &mut VoiceConfigDraft,
+) -> Option<VoiceConfigEventContent> {
+    let mut save = None;
+    if !voice.can_edit {
+        ui.weak("You can't change this room's voice settings.");
+    }
+    ui.add_enabled_ui(voice.can_edit, |ui| {
+        let draft = &mut voice.draft;
+        ui.checkbox(&mut draft.stage, "Stage mode")
+            .on_hover_text("Only speakers put on stage can talk; everyone else listens.");
+
+        egui::ComboBox::from_label("Audio quality")
+            .selected_text(draft.bitrate.label())
+            .show_ui(ui, |ui| {
+                for tier in BitrateTier::ALL {
+                    ui.selectable_value(&mut draft.bitrate, tier, tier.label());
+                }
+            });
+
+        ui.horizontal(|ui| {
+            let mut capped = draft.max_participants.is_some();
+            if ui.checkbox(&mut capped, "Limit participants").changed() {
+                draft.max_participants = capped.then_some(DEFAULT_PARTICIPANT_CAP);
+            }
+            if let Some(cap) = &mut draft.max_participants {
+                ui.add(egui::DragValue::new(cap).range(1..=MAX_PARTICIPANT_CAP));
+            }
+        });
+
+        ui.add_space(6.0);
+        ui.label("Banned from voice");
+        if draft.banned.is_empty() {
+            ui.weak("Nobody");
+        }
+        let mut unban = None;
+        for (i, user_id) in draft.banned.iter().enumerate() {
+            ui.horizontal(|ui| {
+                ui.monospace(user_id.as_str());
+                if ui.small_button("✕").on_hover_text("Lift the ban").clicked() {
+                    unban = Some(i);
+                }
+            });
+        }
+        if let Some(i) = unban {
+            draft.banned.remove(i);
+        }
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut voice.ban).hint_text("@user:server"));
+            let parsed = UserId::parse(voice.ban.trim());
+            if ui
+                .add_enabled(parsed.is_ok(), egui::Button::new("Ban"))
+                .clicked()
+            {
+                if let Ok(user_id) = parsed {
+                    draft.banned.push(user_id);
+                    voice.ban.clear();
+                }
+            }
+        });
+        ui.weak("Banned members can still read and chat, but can't join the call.");
+
+        ui.add_space(6.0);
+        let valid = match draft.validate() {
+            Ok(()) => true,
+            Err(e) => {
+                ui.colored_label(ui.visuals().error_fg_color, e);
+                false
+            }
+        };
+        ui.horizontal(|ui| {
+            let changed = *draft != voice.current;
+            if ui
+                .add_enabled(changed && valid, egui::Button::new("Save"))
+                .clicked()
+            {
+                save = Some(draft.clone());
+            }
+            if ui
+                .add_enabled(changed, egui::Button::new("Revert"))
+                .clicked()
+            {
+                *draft = voice.current.clone();
+            }
+        });
+    });
+    save
+}
+
+// ── Space tree ────────────────────────────────────────────────────────────────
+
+/// Deferred sidebar action from the space tree (applied after rendering so the
+/// tree can borrow `self.spaces` immutably).
+enum SpaceAction {
+    Select(String),
+    Join(String),
+    Refresh(String),
+    /// Open the space's AFK channel dialog.
+    Afk(String),
+    /// Start creating a voice channel in the space.
+    NewVoiceChannel(String),
+}
+
+/// One connectivity probe result: latency, error, or not applicable.
+fn probe_ui(ui: &mut egui::Ui, probe: &Probe) {
+    match probe {
+        Probe::Passed { rtt_ms } => {
+            ui.colored_label(egui::Color32::GREEN, format!("✔ {rtt_ms} ms"));
+        }
+        Probe::Failed(e) => {
+            ui.colored_label(egui::Color32::RED, format!("✖ {e}"));
+        }
+        Probe::Skipped => {
+            ui.weak("not probed");
+        }
+    }
+}
+
+/// What the Matrix client is built with, from `settings`.
+fn client_options(settings: &Settings) -> ClientOptions {
+    ClientOptions {
+        verified_devices_only: settings.encryption.verified_devices_only,
+    }
+}
+
+/// Where a measured round trip's time went.
+fn latency_ui(ui: &mut egui::Ui, report: &LatencyReport) {
+    let ms = |d: std::time::Duration| format!("{} ms", d.as_millis());
+    egui::Grid::new("latency")
+        .num_columns(2)
+        .spacing([12.0, 4.0])
+        .show(ui, |ui| {
+            for (name, value) in [
+                ("Mouth to ear (est.)", report.mouth_to_ear()),
+                ("Capture", report.capture),
+                ("Network and jitter buffer", report.network),
+                ("Playback", report.playback),
+            ] {
+                ui.label(name);
+                ui.label(ms(value));
+                ui.end_row();
+            }
+        });
+    ui.weak(format!(
+        "Round trip via {}: {}",
+        report.peer,
+        ms(report.round_trip)
+    ));
+}
+
+/// Voice channels anywhere below `node`, as `(room ID, name)`.
+fn collect_voice_channels(node: &SpaceNode, out: &mut Vec<(String, String)>) {
+    for child in &node.children {
+        if child.is_voice {
+            out.push((child.room_id.to_string(), child.name.clone()));
+        }
+        collect_voice_channels(child, out);
+    }
+}
+
+fn collect_space_ids(node: &SpaceNode, out: &mut HashSet<String>) {
+    for child in &node.children {
+        out.insert(child.room_id.to_string());
+        collect_space_ids(child, out);
+    }
+}
+
+fn space_tree_ui(
+    ui: &mut egui::Ui,
+    node: &SpaceNode,
+    selected_id: Option<&str>,
+    action: &mut Option<SpaceAction>,
+) {
+    let id = node.room_id.as_str();
+    if node.is_space {
+        ui.collapsing(&node.name, |ui| {
+            for child in &node.children {
+                space_tree_ui(ui, child, selected_id, action);
+            }
+        });
+        return;
+    }
+
+    let label = if node.is_voice {
+        format!("🔊 {}", node.name)
+    } else {
+        format!("# {}", node.name)
+    };
+    if node.joined {
+        let resp = ui.selectable_label(selected_id == Some(id), label);
+        let resp = match &node.topic {
+            Some(topic) => resp.on_hover_text(topic),
+            None => resp,
+        };
+        if resp.clicked() {
+            *action = Some(SpaceAction::Select(id.to_owned()));
+        }
+    } else {
+        ui.horizontal(|ui| {
+            ui.label(egui::RichText::new(label).weak());
+            if ui.small_button("Join").clicked() {
+                *action = Some(SpaceAction::Join(id.to_owned()));
+            }
+        });
+    }
+}
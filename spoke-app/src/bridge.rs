@@ -1,39 +1,270 @@
 /// Async/sync bridge between the Matrix background task and the egui UI.
-use std::{path::PathBuf, sync::mpsc};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
+use base64::Engine;
+use futures::StreamExt;
 use matrix_sdk::{
     AuthSession, Client, Room, RoomState,
     config::SyncSettings,
+    event_handler::RawEvent,
     room::MessagesOptions,
     ruma::{
-        OwnedRoomOrAliasId, RoomId, UserId, uint,
-        api::client::room::create_room::v3::Request as CreateRoomRequest,
+        EventId, OwnedDeviceId, OwnedEventId, OwnedRoomId, OwnedRoomOrAliasId, OwnedUserId, RoomId,
+        RoomVersionId, UserId,
+        api::client::receipt::create_receipt::v3::ReceiptType as SendReceiptType,
         events::{
-            AnySyncMessageLikeEvent, AnySyncTimelineEvent,
+            AnySyncMessageLikeEvent, AnySyncStateEvent, AnySyncTimelineEvent,
+            GlobalAccountDataEvent, OriginalSyncMessageLikeEvent, OriginalSyncStateEvent,
+            StateEventType, SyncStateEvent, ToDeviceEvent,
+            reaction::OriginalSyncReactionEvent,
+            receipt::{ReceiptThread, ReceiptType, SyncReceiptEvent},
             room::{
-                member::{MembershipState, StrippedRoomMemberEvent},
-                message::{MessageType, OriginalSyncRoomMessageEvent, RoomMessageEventContent},
+                encrypted::OriginalSyncRoomEncryptedEvent,
+                member::{
+                    MembershipChange, MembershipState, OriginalSyncRoomMemberEvent,
+                    StrippedRoomMemberEvent,
+                },
+                message::{
+                    MessageFormat, MessageType, OriginalSyncRoomMessageEvent, Relation,
+                    TextMessageEventContent,
+                },
+                pinned_events::RoomPinnedEventsEventContent,
+                power_levels::RoomPowerLevelsEventContent,
+                redaction::OriginalSyncRoomRedactionEvent,
             },
+            typing::SyncTypingEvent,
         },
+        presence::PresenceState,
+        serde::Raw,
     },
 };
 use tokio::sync::mpsc as tokio_mpsc;
 use tracing::warn;
 
 use spoke_core::{
-    matrix::SpokeClient,
+    matrix::{
+        AccountSettingsEventContent, AclChange, CachedMessage, ClientOptions, DeliveryState,
+        DeliveryUpdate, DeviceInfo, DirectoryListing, DirectoryPage, ImagePack, Knock, LeftRoom,
+        MatrixError, MediaSyncEventContent, Member, MessageRelation, MessageText, ModerationAction,
+        NewRoom, NotificationMode, PackImage, Pins, PolicyKind, PolicyList, Poll,
+        PollEndEventContent, PollKind, PollResponseEventContent, PollStartEventContent,
+        PowerLevelChange, PowerLevels, Profile, RichText, RoomEncryption, RoomPeek, RoomTemplate,
+        SendQueue, ServerAcl, ServerCapabilities, ServerInfo, SessionEnded, SharedMedia,
+        SharedMediaUpdate, SpaceNode, SpinOff, SpinOffStep, SpokeClient, StateEntry,
+        StickerEventContent, SyncFilterOptions, Undecrypted, UrlPreview, VoiceMessage,
+        mentions_user, migrate,
+    },
     voice::{
-        VoiceEvent, VoiceSession,
-        events::{VoiceJoinEventContent, VoiceLeaveEventContent, VoiceMuteEventContent},
+        ConnectOptions, VoiceEvent, VoiceSession, afk,
+        audio::{self, InputMeter},
+        data::DataMessage,
+        devices::{self, AudioDevices, DeviceChoice, DeviceKind},
+        events::{
+            RingAction, VoiceConfigEventContent, VoiceHandEventContent, VoiceMemberEventContent,
+            VoiceMuteEventContent, VoicePermissions, VoiceRingEventContent, VoiceStageEventContent,
+            VoiceSummaryEventContent,
+        },
+        ice::{self, IceSettings},
+        latency::LatencyReport,
+        levels::{Level, MicTest},
+        membership,
+        preflight::{self, PreflightReport, Probe, TurnServer},
+        priority,
+        recording::{self, Recorder},
+        ring, rtc, stage,
     },
 };
 
+/// How often to check whether we've gone quiet long enough to be moved to
+/// the space's AFK channel.
+const AFK_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Events per history request, the first and each older chunk scrolled back
+/// to; the server's default of 10 rarely fills the timeline.
+const HISTORY_CHUNK: u32 = 50;
+
+/// Head start given to the selected room's history before preloading others.
+const PRELOAD_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// How long to gather room info changes before re-sending the room list, so
+/// a sync touching many rooms produces one update rather than dozens.
+const ROOM_INFO_COALESCE: Duration = Duration::from_millis(100);
+
 // ── Shared types ──────────────────────────────────────────────────────────────
 
 #[derive(Debug, Clone)]
 pub struct RoomInfo {
     pub id: String,
     pub name: String,
+    /// Canonical `#alias:server`, if the room has one.
+    pub alias: Option<String>,
+    pub is_space: bool,
+    /// Listed in our `m.direct` account data.
+    pub is_direct: bool,
+    /// `mxc://` URI of the room avatar, if any.
+    pub avatar_url: Option<String>,
+    pub num_joined_members: u64,
+    /// Messages are end-to-end encrypted.
+    pub encrypted: bool,
+    pub topic: Option<String>,
+    /// The newest text message we've seen, for the preview line.
+    pub last_message: Option<RoomPreview>,
+    /// When the newest event of any kind we've seen was sent (ms since the
+    /// Unix epoch): joins, reactions and the like count too.
+    pub last_activity_ts: Option<u64>,
+}
+
+impl RoomInfo {
+    /// When the room last saw activity, for ordering the sidebar; 0 if never.
+    pub fn activity_ts(&self) -> u64 {
+        self.last_activity_ts
+            .into_iter()
+            .chain(self.last_message.as_ref().map(|m| m.ts))
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Snippet of a room's latest message.
+#[derive(Debug, Clone)]
+pub struct RoomPreview {
+    pub sender: String,
+    pub body: String,
+    /// Milliseconds since the Unix epoch.
+    pub ts: u64,
+}
+
+impl From<&TimelineItem> for RoomPreview {
+    fn from(item: &TimelineItem) -> Self {
+        Self {
+            sender: item.sender.clone(),
+            body: item.body.clone(),
+            ts: item.ts,
+        }
+    }
+}
+
+/// One text message in a room timeline — either a server event or a local
+/// echo of something we sent.
+#[derive(Debug, Clone)]
+pub struct TimelineItem {
+    /// `None` for a local echo the server hasn't acknowledged yet.
+    pub event_id: Option<String>,
+    /// Set on our own messages; matches the remote echo to its local echo.
+    pub txn_id: Option<String>,
+    pub sender: String,
+    pub body: String,
+    /// Server timestamp in milliseconds; the send time for local echoes.
+    pub ts: u64,
+    /// `None` once the message is on the server.
+    pub delivery: Option<DeliveryState>,
+    /// The message mentions us; drawn highlighted.
+    pub mentions_me: bool,
+    /// The formatted body, parsed; `None` for plain-text messages.
+    pub rich: Option<RichText>,
+    pub reply_to: Option<String>,
+    pub thread_root: Option<String>,
+    pub edited: bool,
+    /// The question and answers when the event starts a poll.
+    pub poll: Option<Poll>,
+    /// `mxc://` URI when the event is a sticker.
+    pub sticker: Option<String>,
+    pub voice: Option<VoiceMessage>,
+    /// Shown as a placeholder until its key arrives, when a `Decrypted`
+    /// event replaces it.
+    pub undecrypted: bool,
+    /// Someone joined, left, was kicked or changed their name; `body` says
+    /// who and what.
+    pub membership: bool,
+}
+
+impl From<CachedMessage> for TimelineItem {
+    fn from(m: CachedMessage) -> Self {
+        Self {
+            event_id: Some(m.event_id),
+            txn_id: None,
+            sender: m.sender,
+            body: m.body,
+            ts: m.ts,
+            delivery: None,
+            mentions_me: m.mentions_me,
+            rich: m.html.as_deref().map(RichText::from_html),
+            reply_to: m.reply_to,
+            thread_root: m.thread_root,
+            edited: m.edited,
+            poll: m.poll,
+            sticker: m.sticker,
+            voice: m.voice,
+            undecrypted: m.undecrypted,
+            membership: m.membership,
+        }
+    }
+}
+
+/// An `m.reaction` on a timeline event.
+#[derive(Debug, Clone)]
+pub struct Reaction {
+    /// The reaction event itself; redacting it takes the reaction back.
+    pub event_id: String,
+    /// The event reacted to.
+    pub target: String,
+    pub key: String,
+    pub sender: String,
+}
+
+/// A vote in or the end of a poll, by the poll's start event ID.
+#[derive(Debug, Clone)]
+pub enum PollUpdate {
+    /// Empty `answers` withdraws the sender's vote.
+    Response {
+        poll_id: String,
+        sender: String,
+        ts: u64,
+        answers: Vec<String>,
+    },
+    End {
+        poll_id: String,
+        sender: String,
+        ts: u64,
+    },
+}
+
+/// Why the bridge couldn't carry out a command.
+#[derive(Debug, Clone)]
+pub enum CommandError {
+    /// A room, user or event ID in the command didn't parse.
+    InvalidId { value: String },
+    /// The room isn't known to the client; `detail` says what the refresh
+    /// found, if one was tried.
+    UnknownRoom { room_id: String, detail: String },
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandError::InvalidId { value } => write!(f, "invalid ID {value}"),
+            CommandError::UnknownRoom { room_id, detail } => {
+                write!(f, "unknown room {room_id} ({detail})")
+            }
+        }
+    }
+}
+
+/// A link preview and its image thumbnail, ready to render.
+#[derive(Debug, Clone)]
+pub struct LinkPreview {
+    pub preview: UrlPreview,
+    /// Encoded thumbnail bytes, if the page had an image we could fetch.
+    pub image: Option<Arc<[u8]>>,
 }
 
 #[derive(Debug, Clone)]
@@ -43,352 +274,3535 @@ pub struct InviteInfo {
     pub inviter: String,
 }
 
+/// A sender's display name and avatar, ready to render.
+#[derive(Debug, Clone, Default)]
+pub struct SenderProfile {
+    pub display_name: Option<String>,
+    /// `mxc://` URI of the avatar; also the image cache key.
+    pub avatar_url: Option<String>,
+    /// Encoded thumbnail bytes, if the avatar could be fetched.
+    pub avatar: Option<Arc<[u8]>>,
+}
+
+/// Identifies one account's bridge. The app picks a fresh one for each login,
+/// so several accounts can be connected at once and share one event channel.
+/// Commands need no tag: each account has its own command channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AccountId(pub u32);
+
+/// An `AppEvent` and the account it came from.
+#[derive(Debug)]
+pub struct AccountEvent {
+    pub account: AccountId,
+    pub event: AppEvent,
+}
+
+/// One account's end of the app's event channel; tags everything it sends.
+#[derive(Clone)]
+pub struct EventSender {
+    account: AccountId,
+    tx: mpsc::Sender<AccountEvent>,
+}
+
+impl EventSender {
+    pub fn new(account: AccountId, tx: mpsc::Sender<AccountEvent>) -> Self {
+        Self { account, tx }
+    }
+
+    pub fn account(&self) -> AccountId {
+        self.account
+    }
+
+    fn send(&self, event: AppEvent) {
+        let _ = self.tx.send(AccountEvent {
+            account: self.account,
+            event,
+        });
+    }
+}
+
 #[derive(Debug)]
 pub enum AppEvent {
-    Connected { username: String },
+    /// The SSO page was opened in the browser; `url` is shown in case it
+    /// didn't open.
+    SsoStarted {
+        url: String,
+    },
+    /// Login preflight for `homeserver`.
+    ServerProbed {
+        homeserver: String,
+        result: Result<ServerInfo, String>,
+    },
+    /// `user_id` is our full MXID.
+    Connected {
+        username: String,
+        user_id: String,
+    },
+    /// What the homeserver supports; sent once after login.
+    Capabilities(ServerCapabilities),
+    /// Our roaming settings from account data: once after login (`None` if
+    /// no device has stored any), then whenever a device changes them.
+    AccountSettings(Option<AccountSettingsEventContent>),
     RoomsUpdated(Vec<RoomInfo>),
     InvitesUpdated(Vec<InviteInfo>),
-    Message { room_id: String, sender: String, body: String },
-    Joined { room_id: String },
+    /// Rooms we left or were removed from and haven't forgotten.
+    LeftRoomsUpdated(Vec<LeftRoom>),
+    /// A message arrived from sync. Our own messages carry the `txn_id` of
+    /// their local echo.
+    Message {
+        room_id: String,
+        item: TimelineItem,
+    },
+    /// Local echo of an outgoing message that entered the send queue (also
+    /// replayed at startup for messages left over from a previous run).
+    MessageQueued {
+        room_id: String,
+        item: TimelineItem,
+    },
+    MessageDelivery {
+        room_id: String,
+        txn_id: String,
+        state: DeliveryState,
+    },
+    /// A message that couldn't be decrypted before now can; replaces its
+    /// placeholder, if shown.
+    Decrypted {
+        room_id: String,
+        item: TimelineItem,
+    },
+    /// `sender` replaced the content of `event_id`.
+    MessageEdited {
+        room_id: String,
+        event_id: String,
+        sender: String,
+        body: String,
+        rich: Option<RichText>,
+    },
+    /// Reactions from sync or history.
+    Reactions {
+        room_id: String,
+        reactions: Vec<Reaction>,
+    },
+    /// Poll votes and ends from sync or history.
+    PollUpdates {
+        room_id: String,
+        updates: Vec<PollUpdate>,
+    },
+    /// `event_id` was redacted — a message or a reaction.
+    Redacted {
+        room_id: String,
+        event_id: String,
+    },
+    Joined {
+        room_id: String,
+    },
+    /// Users currently typing in `room_id`, excluding ourselves.
+    TypingUpdated {
+        room_id: String,
+        user_ids: Vec<String>,
+    },
+    /// Public read receipts that moved: `(user_id, event_id)` pairs.
+    ReceiptsUpdated {
+        room_id: String,
+        receipts: Vec<(String, String)>,
+    },
+    /// `user_id` was kicked, banned or unbanned from `room_id` by `by`.
+    MembershipChanged {
+        room_id: String,
+        user_id: String,
+        action: ModerationAction,
+        by: String,
+        reason: Option<String>,
+    },
+    PowerLevelsLoaded {
+        room_id: String,
+        levels: PowerLevels,
+    },
+    /// What a room is watching together, found in recent history when its
+    /// timeline loads.
+    SharedMediaLoaded {
+        room_id: String,
+        media: Option<SharedMedia>,
+    },
+    /// A live play, pause, seek, share or stop.
+    SharedMediaUpdated {
+        room_id: String,
+        update: SharedMediaUpdate,
+    },
+    /// A room's server ACL and the policy lists it follows.
+    ServerAclLoaded {
+        room_id: String,
+        acl: ServerAcl,
+        lists: Vec<PolicyList>,
+    },
+    /// Pending knocks on a room we moderate (empty once all are answered).
+    KnocksUpdated {
+        room_id: String,
+        knocks: Vec<Knock>,
+    },
+    /// Our knock on `room_id` was sent.
+    Knocked {
+        room_id: String,
+    },
+    /// Answer to `PeekRoom`, for the address it was asked about.
+    RoomPeeked {
+        room: String,
+        peek: Result<RoomPeek, String>,
+    },
+    /// The homeserver is rate limiting us: requests are held back until
+    /// `until` (ms since the epoch). `None` once they flow again.
+    RateLimited(Option<(String, u64)>),
+    /// A spin-off is under way; `step` says what it's doing.
+    SpinOffProgress {
+        step: String,
+    },
+    /// A spin-off room was created; `not_invited` couldn't be invited.
+    SpunOff {
+        room_id: String,
+        not_invited: Vec<String>,
+    },
+    /// A report went through; `event_id` is `None` for a room report.
+    Reported {
+        room_id: String,
+        event_id: Option<String>,
+    },
+    /// Someone else joined voice in `room_id`.
+    VoicePing {
+        room_id: String,
+        sender: String,
+    },
+    /// Who's in `room_id`'s call, with when each membership lapses (ms since
+    /// the Unix epoch) unless renewed.
+    VoiceMembers {
+        room_id: String,
+        members: Vec<(String, u64)>,
+    },
+    /// `caller` is ringing us to join the call in `room_id`.
+    IncomingCall {
+        room_id: String,
+        call_id: String,
+        caller: String,
+    },
+    /// `user` ended the ringing for `call_id`: the caller cancelled, or the
+    /// callee (maybe us, on another device) answered or declined.
+    RingEnded {
+        call_id: String,
+        user: String,
+        action: RingAction,
+    },
+    /// The push rules say this message should notify.
+    /// `mentions_me` is set when it names us directly, as opposed to a
+    /// keyword or room-wide highlight.
+    Notification {
+        room_id: String,
+        event_id: String,
+        sender: String,
+        body: String,
+        highlight: bool,
+        mentions_me: bool,
+    },
+    /// `None` means the room follows the account default.
+    NotificationModeLoaded {
+        room_id: String,
+        mode: Option<NotificationMode>,
+    },
+    /// Joined and invited members, highest power level first. `more` while
+    /// this is only the stored part and the full list is still coming.
+    MembersLoaded {
+        room_id: String,
+        members: Vec<Member>,
+        more: bool,
+    },
+    /// A user's profile, resolved on request or changed by a member event.
+    ProfileResolved {
+        user_id: String,
+        profile: SenderProfile,
+    },
+    /// Answer to `FetchUrlPreview`; `Ok(None)` when there's nothing to show,
+    /// `Err` when it couldn't be fetched and may be worth asking again.
+    UrlPreviewLoaded {
+        url: String,
+        preview: Result<Option<LinkPreview>, String>,
+    },
+    /// Answer to `FetchImagePacks`: our own pack first, then the room's.
+    ImagePacksLoaded {
+        room_id: String,
+        packs: Vec<ImagePack>,
+    },
+    /// Answer to `FetchPackImage`; `None` if it couldn't be fetched.
+    PackImageLoaded {
+        mxc: String,
+        image: Option<Arc<[u8]>>,
+    },
+    /// Answer to `FetchPins`.
+    PinsLoaded {
+        room_id: String,
+        pins: Pins,
+    },
+    /// Someone pinned or unpinned a message in `room_id`.
+    PinsChanged {
+        room_id: String,
+    },
+    /// Answer to `FetchRoomState`, for developer mode.
+    RoomStateLoaded {
+        room_id: String,
+        state: Vec<StateEntry>,
+    },
+    /// `Logout` finished; the bridge has stopped and the UI should return to
+    /// the login panel.
+    LoggedOut,
+    /// Our account's devices, the current one first.
+    DevicesLoaded(Vec<DeviceInfo>),
     Error(String),
+    /// A command was dropped; `command` names it for the user.
+    CommandFailed {
+        command: &'static str,
+        error: CommandError,
+    },
     // Voice events
-    VoiceJoined { room_id: String },
+    /// `can_publish` is false for stage listeners; `can_moderate` means the
+    /// local user may toggle stage mode and promote listeners.
+    /// `can_screen_share` and `priority_speaker` come from the room's voice
+    /// permissions, as granted by the sidecar. `participants` is who was
+    /// already in the call.
+    VoiceJoined {
+        room_id: String,
+        participants: Vec<String>,
+        stage: bool,
+        can_publish: bool,
+        can_screen_share: bool,
+        priority_speaker: bool,
+        can_moderate: bool,
+    },
+    /// Power level thresholds for voice capabilities in `room_id`.
+    VoicePermissionsLoaded {
+        room_id: String,
+        permissions: VoicePermissions,
+    },
+    /// `room_id`'s whole voice config, when asked for and whenever it changes.
+    VoiceConfigLoaded {
+        room_id: String,
+        config: VoiceConfigEventContent,
+        can_edit: bool,
+    },
     VoiceLeft,
+    /// A voice message recording began; `meter` follows the mic level.
+    RecordingStarted {
+        meter: Arc<InputMeter>,
+    },
+    /// The recording ended, whether sent, discarded or failed.
+    RecordingStopped,
+    /// A voice message started or finished playing.
+    VoicePlayback {
+        url: String,
+        playing: bool,
+    },
     VoiceParticipantsUpdated(Vec<String>),
+    VoiceParticipantJoined {
+        identity: String,
+    },
+    VoiceParticipantLeft {
+        identity: String,
+    },
+    /// Identities of the priority speakers currently in the call.
+    VoicePrioritySpeakers(Vec<String>),
+    /// Remote audio tracks being played, for the diagnostics dialog.
+    VoicePipelines(usize),
+    /// Someone in the call (us included) started or stopped talking.
+    VoiceSpeaking {
+        participant: String,
+        speaking: bool,
+    },
+    /// Our mic level after gain; `None` when we don't publish.
+    VoiceInputMeter(Option<Arc<InputMeter>>),
+    /// How loud everyone sending audio is, us included; anyone missing is
+    /// silent. About 20 a second while anyone makes a sound.
+    VoiceLevels(Vec<(String, Level)>),
+    /// The mic's level during `TestMic`, about 20 a second.
+    MicTestLevel(Level),
+    /// Connectivity probes run before joining; shown in the diagnostics dialog.
+    VoicePreflight {
+        room_id: String,
+        report: PreflightReport,
+    },
+    /// Result of `MeasureVoiceLatency`; shown in the diagnostics dialog.
+    VoiceLatency(Result<LatencyReport, String>),
+    /// Our mic's device went away; `devices` are the inputs to offer instead.
+    VoiceMicLost {
+        devices: Vec<String>,
+    },
+    /// A mic is sending again after `VoiceMicLost`.
+    VoiceMicRestored {
+        device: String,
+    },
+    /// Our speakers' device went away; the call can't be heard until
+    /// `VoiceOutputRestored`.
+    VoiceOutputLost,
+    VoiceOutputRestored {
+        device: String,
+    },
+    /// The OS default device changed and the call moved onto it.
+    VoiceDeviceSwitched {
+        kind: DeviceKind,
+        device: String,
+    },
+    /// Answer to `ListAudioDevices`.
+    AudioDevices(AudioDevices),
+    /// TURN servers handed out with the latest voice grant.
+    TurnServers(Vec<TurnServer>),
+    /// Per-server results of `TestIceServers`.
+    IceTestResults(Vec<(String, Probe)>),
+    /// Ephemeral in-call signal from another participant (LiveKit data).
+    VoiceData {
+        sender: String,
+        message: DataMessage,
+    },
+    // Stage mode
+    StageUpdated {
+        room_id: String,
+        stage: bool,
+        speakers: Vec<String>,
+    },
+    HandRaised {
+        room_id: String,
+        user_id: String,
+        raised: bool,
+    },
+    AfkPolicyLoaded {
+        space_id: String,
+        afk_room: Option<String>,
+        timeout_mins: u32,
+        can_edit: bool,
+    },
+    /// We were silent too long in `from` and are being moved to the AFK
+    /// channel `to`.
+    MovedToAfk {
+        from: String,
+        to: String,
+    },
     // History
-    HistoryLoaded { room_id: String, messages: Vec<(String, String)> },
+    /// `prev_batch` continues backwards from the oldest message; `None` at
+    /// the start of the room.
+    HistoryLoaded {
+        room_id: String,
+        messages: Vec<TimelineItem>,
+        prev_batch: Option<String>,
+    },
+    /// An older chunk fetched with `FetchMoreHistory`, to go before the log.
+    MoreHistoryLoaded {
+        room_id: String,
+        messages: Vec<TimelineItem>,
+        prev_batch: Option<String>,
+    },
+    /// `FetchMoreHistory` failed; the token it was given is still good.
+    MoreHistoryFailed {
+        room_id: String,
+        error: String,
+    },
+    /// Messages from the local timeline cache, sent before the first sync.
+    /// Superseded by the room's next `HistoryLoaded`.
+    CachedHistoryLoaded {
+        room_id: String,
+        messages: Vec<TimelineItem>,
+    },
+    // Spaces
+    SpaceHierarchyLoaded {
+        space_id: String,
+        root: SpaceNode,
+    },
+    // Room directory
+    /// `append` is true when `page` continues an earlier result set.
+    DirectoryResults {
+        query: String,
+        page: DirectoryPage,
+        append: bool,
+    },
+    /// Whether `room_id` is published in our homeserver's directory.
+    DirectoryListingLoaded {
+        room_id: String,
+        listing: DirectoryListing,
+    },
+    RoomEncryptionLoaded {
+        room_id: String,
+        encryption: RoomEncryption,
+    },
 }
 
 #[derive(Debug)]
 pub enum AppCommand {
-    SendMessage { room_id: String, body: String },
-    InviteUser { room_id: String, mxid: String },
-    JoinRoom { room_id: String },
-    CreateRoom { name: String },
-    JoinRoomByAlias { alias: String },
-    LeaveRoom { room_id: String },
+    SendMessage {
+        room_id: String,
+        text: MessageText,
+    },
+    /// Resend a queued message now, including one that failed permanently.
+    RetryMessage {
+        txn_id: String,
+    },
+    DiscardMessage {
+        txn_id: String,
+    },
+    SendReaction {
+        room_id: String,
+        event_id: String,
+        key: String,
+    },
+    /// Take back our reaction `reaction_id`.
+    RemoveReaction {
+        room_id: String,
+        reaction_id: String,
+    },
+    StartPoll {
+        room_id: String,
+        question: String,
+        answers: Vec<String>,
+        max_selections: u32,
+        kind: PollKind,
+    },
+    /// Empty `answers` withdraws our vote.
+    VotePoll {
+        room_id: String,
+        poll_id: String,
+        answers: Vec<String>,
+    },
+    EndPoll {
+        room_id: String,
+        poll_id: String,
+    },
+    InviteUser {
+        room_id: String,
+        mxid: String,
+    },
+    JoinRoom {
+        room_id: String,
+    },
+    /// Open the DM room with `mxid`, creating it if needed.
+    StartDirectMessage {
+        mxid: String,
+    },
+    /// Request to join a `knock` room by ID or alias.
+    KnockRoom {
+        room: String,
+        reason: Option<String>,
+    },
+    AcceptKnock {
+        room_id: String,
+        user_id: String,
+    },
+    RejectKnock {
+        room_id: String,
+        user_id: String,
+        reason: Option<String>,
+    },
+    /// `alias` is the local part only; `invite` holds user IDs.
+    /// `room_version` of `None` leaves the choice to the server.
+    CreateRoom {
+        name: String,
+        template: RoomTemplate,
+        encrypted: bool,
+        topic: Option<String>,
+        alias: Option<String>,
+        invite: Vec<String>,
+        room_version: Option<String>,
+    },
+    /// Create a voice channel with the usual defaults, listed in `space_id`
+    /// if given.
+    CreateVoiceChannel {
+        name: String,
+        space_id: Option<String>,
+    },
+    /// Move the discussion around `event_id` (a message or thread root) into
+    /// a new room named `name`, quoting `quote` and inviting the thread's
+    /// participants. Reports each step with `SpinOffProgress`.
+    SpinOff {
+        room_id: String,
+        event_id: String,
+        sender: String,
+        quote: String,
+        name: String,
+    },
+    JoinRoomByAlias {
+        alias: String,
+    },
+    /// Preview a room by ID or alias without joining; answered with
+    /// `RoomPeeked`.
+    PeekRoom {
+        room: String,
+    },
+    LeaveRoom {
+        room_id: String,
+    },
+    /// Drop a left room from the account and local store.
+    ForgetRoom {
+        room_id: String,
+    },
+    // Receipts and typing
+    /// `private` sends `m.read.private`: unread counts stay in sync across
+    /// our devices without telling the room.
+    SendReadReceipt {
+        room_id: String,
+        event_id: String,
+        private: bool,
+    },
+    SetTyping {
+        room_id: String,
+        typing: bool,
+    },
+    /// Appear offline (or stop): no presence is sent while on.
+    SetInvisible(bool),
+    // Notifications
+    /// Answered with `NotificationModeLoaded`.
+    FetchNotificationMode {
+        room_id: String,
+    },
+    /// `None` goes back to the account default.
+    SetNotificationMode {
+        room_id: String,
+        mode: Option<NotificationMode>,
+    },
+    // Members
+    /// Load the member list for the members panel or mention autocomplete;
+    /// answered with one or two `MembersLoaded`.
+    FetchMembers {
+        room_id: String,
+    },
+    // Profile
+    /// Look up senders' profiles as seen in `room_id`; answered with one
+    /// `ProfileResolved` per user.
+    ResolveProfiles {
+        room_id: String,
+        user_ids: Vec<String>,
+    },
+    /// Ask the homeserver for a preview of `url`; answered with
+    /// `UrlPreviewLoaded`.
+    FetchUrlPreview {
+        url: String,
+    },
+    // Stickers and emotes
+    /// Load the image packs usable in `room_id`.
+    FetchImagePacks {
+        room_id: String,
+    },
+    /// Thumbnail a sticker or emote; answered with `PackImageLoaded`.
+    FetchPackImage {
+        mxc: String,
+    },
+    SendSticker {
+        room_id: String,
+        image: PackImage,
+    },
+    // Pinned messages
+    /// Load `room_id`'s pinned messages; answered with `PinsLoaded`.
+    FetchPins {
+        room_id: String,
+    },
+    SetPinned {
+        room_id: String,
+        event_id: String,
+        pinned: bool,
+    },
+    /// Load every state event in `room_id`; answered with `RoomStateLoaded`.
+    FetchRoomState {
+        room_id: String,
+    },
+    /// Send an event of any type: a state event under `state_key` if given,
+    /// otherwise a message-like one. Reloads the state after a state event.
+    SendCustomEvent {
+        room_id: String,
+        event_type: String,
+        state_key: Option<String>,
+        content: serde_json::Value,
+    },
+    /// Empty clears the display name.
+    SetDisplayName {
+        name: String,
+    },
+    /// Upload the image at `path` as our avatar; `None` removes it.
+    SetAvatar {
+        path: Option<PathBuf>,
+    },
+    /// Takes effect from the next sync request.
+    SetSyncFilter(SyncFilterOptions),
+    /// Store our roaming settings in account data.
+    SaveAccountSettings(AccountSettingsEventContent),
+    /// Log out and stop the bridge. `keep_crypto` sets the local stores aside
+    /// instead of deleting them.
+    Logout {
+        keep_crypto: bool,
+    },
+    // Sessions
+    /// Answered with `DevicesLoaded`.
+    FetchDevices,
+    RenameDevice {
+        name: String,
+    },
+    /// Sign out other devices; `password` confirms it's really us.
+    DeleteDevices {
+        device_ids: Vec<String>,
+        password: String,
+    },
+    // Moderation
+    KickUser {
+        room_id: String,
+        user_id: String,
+        reason: Option<String>,
+    },
+    BanUser {
+        room_id: String,
+        user_id: String,
+        reason: Option<String>,
+    },
+    UnbanUser {
+        room_id: String,
+        user_id: String,
+        reason: Option<String>,
+    },
+    /// Remove several messages at once; needs the power to redact others'.
+    RedactMessages {
+        room_id: String,
+        event_ids: Vec<String>,
+        reason: Option<String>,
+    },
+    /// Report a message to the homeserver's admins; `score` runs from -100
+    /// (most offensive) to 0.
+    ReportEvent {
+        room_id: String,
+        event_id: String,
+        score: Option<i32>,
+        reason: Option<String>,
+    },
+    /// Report a whole room to the homeserver's admins.
+    ReportRoom {
+        room_id: String,
+        reason: String,
+    },
+    FetchPowerLevels {
+        room_id: String,
+    },
+    /// Answered with a fresh `PowerLevelsLoaded` once applied.
+    SetPowerLevel {
+        room_id: String,
+        change: PowerLevelChange,
+    },
+    /// Share `url` for the room to watch together, replacing any other.
+    ShareMedia {
+        room_id: String,
+        url: String,
+    },
+    /// Play, pause or seek the room's shared player.
+    SyncMedia {
+        room_id: String,
+        media: SharedMedia,
+        playing: bool,
+        position_ms: u64,
+    },
+    StopMedia {
+        room_id: String,
+        media: SharedMedia,
+    },
+    FetchServerAcl {
+        room_id: String,
+    },
+    /// Each of these is answered with a fresh `ServerAclLoaded`.
+    ChangeServerAcl {
+        room_id: String,
+        change: AclChange,
+    },
+    FollowPolicyList {
+        room_id: String,
+        list_id: String,
+        follow: bool,
+    },
+    /// Add a ban rule to `list_id`, one of the lists `room_id` follows.
+    AddPolicyRule {
+        room_id: String,
+        list_id: String,
+        kind: PolicyKind,
+        entity: String,
+        reason: String,
+    },
     // Voice commands
-    JoinVoice { room_id: String },
+    JoinVoice {
+        room_id: String,
+        ice: IceSettings,
+        tuning: VoiceTuning,
+        devices: DeviceChoice,
+    },
+    /// Probe each configured STUN server and each TURN server.
+    TestIceServers {
+        ice: IceSettings,
+        turn_servers: Vec<TurnServer>,
+    },
     LeaveVoice,
-    MuteVoice { muted: bool },
+    MuteVoice {
+        muted: bool,
+    },
+    /// Stop playing remote audio. Doesn't touch the mic; the UI mutes too.
+    DeafenVoice {
+        deafened: bool,
+    },
+    /// Change audio levels for this and later sessions.
+    SetVoiceTuning(VoiceTuning),
+    /// Play a short tone on the chosen output device.
+    PlayTestTone,
+    /// List the OS's audio devices; answered with `AudioDevices`.
+    ListAudioDevices,
+    /// Open the chosen mic outside a call and report its level with
+    /// `MicTestLevel`, or stop. Joining voice stops it.
+    TestMic {
+        on: bool,
+    },
+    /// Use these devices for this and later sessions, and for voice
+    /// messages. A device that can't be opened mid-call is reported and the
+    /// old one kept.
+    SetAudioDevices(DeviceChoice),
+    /// Broadcast an ephemeral in-call signal to the active voice session.
+    SendVoiceData {
+        message: DataMessage,
+    },
+    /// Bounce a marker tone off someone in the call to measure latency;
+    /// answered with `VoiceLatency`.
+    MeasureVoiceLatency,
+    /// After `VoiceMicLost`, try reopening the mic now, on `device` or the
+    /// default input.
+    RetryMic {
+        device: Option<String>,
+    },
+    /// Ring the other members of a DM to join its call.
+    Ring {
+        room_id: String,
+    },
+    /// Answer or decline `caller`'s ring; answering doesn't join by itself.
+    AnswerRing {
+        room_id: String,
+        call_id: String,
+        caller: String,
+        accept: bool,
+    },
+    // Voice messages
+    /// Start recording a voice message; answered with `RecordingStarted`.
+    StartRecording,
+    /// Stop recording and send the clip to `send_to`, or discard it if `None`.
+    StopRecording {
+        send_to: Option<String>,
+    },
+    /// Download and play a voice message, stopping any already playing.
+    PlayVoiceMessage {
+        url: String,
+    },
+    StopVoiceMessage,
+    /// Re-request the LiveKit grant and reconnect if publish rights changed.
+    /// Sent internally when stage state changes in the active voice room.
+    RefreshVoiceGrant {
+        room_id: String,
+    },
+    // Stage mode
+    /// Post an `org.spoke.voice.summary` for a call that just ended.
+    PostCallSummary {
+        room_id: String,
+        summary: VoiceSummaryEventContent,
+    },
+    RaiseHand {
+        raised: bool,
+    },
+    SetStageMode {
+        room_id: String,
+        enabled: bool,
+    },
+    SetStageSpeaker {
+        room_id: String,
+        user_id: String,
+        speaker: bool,
+    },
+    /// Answered with a fresh `VoicePermissionsLoaded` once applied.
+    SetVoicePermissions {
+        room_id: String,
+        permissions: VoicePermissions,
+    },
+    FetchVoiceConfig {
+        room_id: String,
+    },
+    /// Apply the changes from `base` (the config the form was opened with)
+    /// to `config` over the room's current voice config. Answered with a
+    /// fresh `VoiceConfigLoaded`, which every client also gets through sync.
+    SetVoiceConfig {
+        room_id: String,
+        base: VoiceConfigEventContent,
+        config: VoiceConfigEventContent,
+    },
+    FetchAfkPolicy {
+        space_id: String,
+    },
+    /// `afk_room: None` turns the space's AFK channel off. Answered with a
+    /// fresh `AfkPolicyLoaded`.
+    SetAfkPolicy {
+        space_id: String,
+        afk_room: Option<String>,
+        timeout_mins: u32,
+    },
+    /// Sent periodically by the bridge itself: move to the AFK channel if
+    /// we've been silent too long.
+    CheckAfk,
     // History
-    FetchHistory { room_id: String },
+    FetchHistory {
+        room_id: String,
+    },
+    /// Fetch the chunk before `from` (a `prev_batch` token).
+    FetchMoreHistory {
+        room_id: String,
+        from: String,
+    },
+    /// Low-priority history fetch for rooms the user is likely to open next.
+    /// Replaces any preload still in progress.
+    PreloadHistory {
+        room_ids: Vec<String>,
+    },
+    // Spaces
+    FetchSpaceHierarchy {
+        space_id: String,
+    },
+    // Room directory
+    /// Search a room directory. `server` picks a remote homeserver's
+    /// directory; `since` continues from a previous page's `next_batch`.
+    SearchDirectory {
+        query: String,
+        server: Option<String>,
+        since: Option<String>,
+    },
+    /// Answered with `DirectoryListingLoaded`.
+    FetchDirectoryListing {
+        room_id: String,
+    },
+    /// Answered with `RoomEncryptionLoaded`.
+    FetchRoomEncryption {
+        room_id: String,
+    },
+    /// Turn on end-to-end encryption; it can't be turned off again.
+    EnableEncryption {
+        room_id: String,
+    },
+    /// Publish or unpublish a room in our homeserver's directory.
+    SetPublished {
+        room_id: String,
+        published: bool,
+    },
 }
 
 // ── Entry point ───────────────────────────────────────────────────────────────
 
+/// How to log in.
+#[derive(Debug, Clone)]
+pub enum Login {
+    /// `register` first tries to create the account (ignored if it exists);
+    /// pass false for servers known to have registration closed.
+    Password {
+        username: String,
+        password: String,
+        register: bool,
+    },
+    /// Through the homeserver's SSO page in the browser, optionally with a
+    /// specific identity provider. A saved session is resumed only for
+    /// `username`, the account named in the login form.
+    Sso {
+        idp_id: Option<String>,
+        username: Option<String>,
+    },
+}
+
 pub fn spawn_matrix_task(
-    event_tx: mpsc::Sender<AppEvent>,
+    event_tx: EventSender,
     cmd_rx: tokio_mpsc::UnboundedReceiver<AppCommand>,
     ctx: egui::Context,
     homeserver: String,
-    username: String,
-    password: String,
+    login: Login,
+    sync_filter: SyncFilterOptions,
+    invisible: HashSet<String>,
+    options: ClientOptions,
 ) {
     std::thread::spawn(move || {
         tokio::runtime::Runtime::new()
             .expect("tokio runtime")
-            .block_on(matrix_task(event_tx, cmd_rx, ctx, homeserver, username, password));
+            .block_on(matrix_task(
+                event_tx,
+                cmd_rx,
+                ctx,
+                homeserver,
+                login,
+                sync_filter,
+                invisible,
+                options,
+            ));
+    });
+}
+
+/// The store directory for `name` on `homeserver` in the platform data
+/// directory, moving a legacy `/tmp` store there first. If that fails the
+/// legacy store is used as is, so its keys aren't lost.
+async fn store_path(homeserver: &str, name: &str) -> PathBuf {
+    let legacy = PathBuf::from(format!("/tmp/spoke-app-{name}.db"));
+    let Some(data_dir) = dirs::data_dir().map(|d| d.join("spoke")) else {
+        return legacy;
+    };
+    let scoped = migrate::account_store_name(homeserver, name);
+    let path = migrate::store_path(&data_dir, &scoped);
+    if let Some(store) = migrate::legacy_store(name).filter(|_| !path.exists()) {
+        if let Err(e) = migrate::migrate_store(&store, &data_dir).await {
+            warn!("store migration for {name} failed, using {legacy:?}: {e}");
+            return legacy;
+        }
+    }
+    migrate::scope_store(&data_dir, name, &scoped).unwrap_or_else(|e| {
+        warn!("scoping store {name} failed: {e}");
+        migrate::store_path(&data_dir, name)
+    })
+}
+
+/// Log in as `username`, or restore their saved session, registering the
+/// account first if `register`.
+async fn password_login(
+    homeserver: &str,
+    username: &str,
+    password: &str,
+    register: bool,
+    options: ClientOptions,
+) -> Result<SpokeClient, MatrixError> {
+    let client =
+        SpokeClient::with_options(homeserver, &store_path(homeserver, username).await, options)
+            .await?;
+    if register {
+        if let Err(e) = client.register(username, password).await {
+            warn!("register: {e}");
+        }
+    }
+    client.login(username, password).await?;
+    Ok(client)
+}
+
+/// Store name an SSO login runs in until it's known whose account it is.
+const SSO_PENDING_STORE: &str = "sso-pending";
+
+/// Restore `username`'s SSO session, or open the SSO page in the browser and
+/// wait for it to come back. SSO stores are keyed by the account's localpart
+/// like password ones; a new login happens in a scratch store that's renamed
+/// once the server says who logged in.
+async fn sso_login(
+    homeserver: &str,
+    username: Option<&str>,
+    idp_id: Option<&str>,
+    options: ClientOptions,
+    tx: &EventSender,
+    ctx: &egui::Context,
+) -> Result<SpokeClient, MatrixError> {
+    if let Some(localpart) =
+        username.map(|u| u.trim_start_matches('@').split(':').next().unwrap_or(u))
+    {
+        let client = SpokeClient::with_options(
+            homeserver,
+            &store_path(homeserver, localpart).await,
+            options,
+        )
+        .await?;
+        if client.restore_session().await {
+            if client
+                .inner
+                .user_id()
+                .is_some_and(|u| u.localpart() == localpart)
+            {
+                return Ok(client);
+            }
+            warn!("stored SSO session isn't {localpart}'s, logging in afresh");
+        }
+    }
+
+    let pending = store_path(homeserver, SSO_PENDING_STORE).await;
+    // Left over from a login that never finished.
+    if pending.exists() {
+        std::fs::remove_dir_all(&pending)?;
+    }
+    let _ = std::fs::remove_file(migrate::session_path(&pending));
+    let client = SpokeClient::with_options(homeserver, &pending, options).await?;
+    let sso = client.start_sso_login(idp_id).await?;
+    ctx.open_url(egui::OpenUrl::new_tab(&sso.url));
+    send(
+        tx,
+        ctx,
+        AppEvent::SsoStarted {
+            url: sso.url.clone(),
+        },
+    );
+    client.finish_sso_login(sso).await?;
+    let localpart = client
+        .inner
+        .user_id()
+        .map(|u| u.localpart().to_owned())
+        .ok_or_else(|| MatrixError::Sso("logged in without a user ID".into()))?;
+
+    // The store is only renamed once nothing has it open.
+    drop(client);
+    let db_path = store_path(homeserver, &localpart).await;
+    move_store(&pending, &db_path)?;
+    let client = SpokeClient::with_options(homeserver, &db_path, options).await?;
+    if !client.restore_session().await {
+        return Err(MatrixError::Sso(
+            "the new session couldn't be resumed".into(),
+        ));
+    }
+    Ok(client)
+}
+
+/// Move the store at `from` and its session file to `to`. A store already
+/// there belongs to another of the account's devices; it's set aside as
+/// `<store>.<unix time>` rather than deleted, so its keys can be exported.
+fn move_store(from: &Path, to: &Path) -> std::io::Result<()> {
+    if to.exists() {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut aside = to.as_os_str().to_owned();
+        aside.push(format!(".{secs}"));
+        warn!("setting aside the previous store at {aside:?}");
+        std::fs::rename(to, &aside)?;
+        let _ = std::fs::remove_file(migrate::session_path(to));
+    }
+    std::fs::rename(from, to)?;
+    std::fs::rename(migrate::session_path(from), migrate::session_path(to))
+}
+
+/// Check what `homeserver` supports before logging in; answered with
+/// `ServerProbed`.
+pub fn spawn_server_probe(event_tx: EventSender, ctx: egui::Context, homeserver: String) {
+    std::thread::spawn(move || {
+        let result = tokio::runtime::Runtime::new()
+            .expect("tokio runtime")
+            .block_on(SpokeClient::probe_server(&homeserver))
+            .map_err(|e| e.to_string());
+        send(
+            &event_tx,
+            &ctx,
+            AppEvent::ServerProbed { homeserver, result },
+        );
     });
 }
 
 // ── Matrix task ───────────────────────────────────────────────────────────────
 
 async fn matrix_task(
-    event_tx: mpsc::Sender<AppEvent>,
+    event_tx: EventSender,
     mut cmd_rx: tokio_mpsc::UnboundedReceiver<AppCommand>,
     ctx: egui::Context,
     homeserver: String,
-    username: String,
-    password: String,
+    login: Login,
+    sync_filter: SyncFilterOptions,
+    invisible: HashSet<String>,
+    options: ClientOptions,
 ) {
-    let db_path = PathBuf::from(format!("/tmp/spoke-app-{username}.db"));
-
-    let client = match SpokeClient::new(&homeserver, &db_path).await {
-        Ok(c) => c,
-        Err(e) => { send(&event_tx, &ctx, AppEvent::Error(e.to_string())); return; }
+    let result = match login {
+        Login::Password {
+            username,
+            password,
+            register,
+        } => password_login(&homeserver, &username, &password, register, options).await,
+        Login::Sso { idp_id, username } => {
+            sso_login(
+                &homeserver,
+                username.as_deref(),
+                idp_id.as_deref(),
+                options,
+                &event_tx,
+                &ctx,
+            )
+            .await
+        }
     };
+    let client = match result {
+        Ok(client) => client,
+        Err(e) => {
+            send(&event_tx, &ctx, AppEvent::Error(e.to_string()));
+            return;
+        }
+    };
+    let username = client
+        .inner
+        .user_id()
+        .map(|u| u.localpart().to_owned())
+        .unwrap_or_default();
 
-    if let Err(e) = client.register(&username, &password).await {
-        warn!("register: {e}");
-    }
-    if let Err(e) = client.login(&username, &password).await {
-        send(&event_tx, &ctx, AppEvent::Error(e.to_string())); return;
+    send(
+        &event_tx,
+        &ctx,
+        AppEvent::Connected {
+            username: username.clone(),
+            user_id: own_user_id(&client.inner),
+        },
+    );
+    match client.fetch_capabilities().await {
+        Ok(capabilities) => send(&event_tx, &ctx, AppEvent::Capabilities(capabilities)),
+        Err(e) => warn!("server capabilities: {e}"),
     }
 
-    send(&event_tx, &ctx, AppEvent::Connected { username: username.clone() });
+    // "Appear offline", per account; every sync carries it, since syncing
+    // without a presence marks us online again.
+    let appear_offline = Arc::new(AtomicBool::new(
+        invisible.contains(&own_user_id(&client.inner)),
+    ));
 
-    // ── Event handlers ────────────────────────────────────────────────────────
-
-    // Incoming text messages.
+    // Renew the access token before it expires; only a rejected session
+    // needs the user to log in again.
+    let session_task;
     {
         let tx = event_tx.clone();
         let ctx = ctx.clone();
-        client.inner.add_event_handler(
-            move |event: OriginalSyncRoomMessageEvent, room: Room| {
-                let tx = tx.clone(); let ctx = ctx.clone();
-                async move {
-                    if room.state() != RoomState::Joined { return; }
-                    if let MessageType::Text(text) = event.content.msgtype {
-                        send(&tx, &ctx, AppEvent::Message {
-                            room_id: room.room_id().to_string(),
-                            sender: event.sender.to_string(),
-                            body: text.body,
-                        });
+        let spoke = client.clone();
+        session_task = tokio::spawn(async move {
+            let SessionEnded::UnknownToken { soft_logout } = spoke.maintain_session().await;
+            warn!("session ended (soft_logout: {soft_logout})");
+            send(
+                &tx,
+                &ctx,
+                AppEvent::Error("Session expired — log in again".into()),
+            );
+        });
+    }
+
+    // Rate-limit holds, for the status line.
+    {
+        let tx = event_tx.clone();
+        let ctx = ctx.clone();
+        let mut status = client.rate_limit_status();
+        tokio::spawn(async move {
+            while status.changed().await.is_ok() {
+                let limited = status.borrow_and_update().clone().map(|l| {
+                    let until = l
+                        .until
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |d| d.as_millis() as u64);
+                    (l.what, until)
+                });
+                send(&tx, &ctx, AppEvent::RateLimited(limited));
+            }
+        });
+    }
+
+    // Commands the bridge issues to itself (e.g. from event handlers), merged
+    // into the same command loop as commands from the UI.
+    let (internal_tx, mut internal_rx) = tokio_mpsc::unbounded_channel::<AppCommand>();
+
+    // Latest message per room, shared by the message handler and every
+    // RoomsUpdated so the sidebar can order by recency.
+    let activity = RoomActivity::default();
+
+    // ── Event handlers ────────────────────────────────────────────────────────
+
+    // Incoming text and voice messages.
+    {
+        let tx = event_tx.clone();
+        let ctx = ctx.clone();
+        let spoke = client.clone();
+        let activity = activity.clone();
+        client.inner.add_event_handler(
+            move |event: OriginalSyncRoomMessageEvent, room: Room, raw: RawEvent| {
+                let tx = tx.clone();
+                let ctx = ctx.clone();
+                let spoke = spoke.clone();
+                let activity = activity.clone();
+                async move {
+                    if room.state() != RoomState::Joined {
+                        return;
+                    }
+                    if let Some(Relation::Replacement(replacement)) = &event.content.relates_to {
+                        if let MessageType::Text(text) = &replacement.new_content.msgtype {
+                            send(
+                                &tx,
+                                &ctx,
+                                AppEvent::MessageEdited {
+                                    room_id: room.room_id().to_string(),
+                                    event_id: replacement.event_id.to_string(),
+                                    sender: event.sender.to_string(),
+                                    body: text.body.clone(),
+                                    rich: formatted_html(text).as_deref().map(RichText::from_html),
+                                },
+                            );
+                        }
+                        return;
+                    }
+                    let (reply_to, thread_root) =
+                        reply_and_thread(event.content.relates_to.as_ref());
+                    let mentions_me = spoke
+                        .inner
+                        .user_id()
+                        .is_some_and(|me| mentions_user(&event.content, me));
+                    let raw = Raw::<AnySyncTimelineEvent>::from_json(raw.0);
+                    let (body, html, voice) = match event.content.msgtype {
+                        MessageType::Text(text) => {
+                            let html = formatted_html(&text);
+                            (text.body, html, None)
+                        }
+                        MessageType::Audio(audio) => match VoiceMessage::from_event(&raw) {
+                            Some(voice) => (audio.body, None, Some(voice)),
+                            None => return,
+                        },
+                        _ => return,
+                    };
+                    if spoke.inner.user_id() != Some(&event.sender) {
+                        match spoke.push_verdict(&room, &raw).await {
+                            Ok(verdict) if verdict.notify => send(
+                                &tx,
+                                &ctx,
+                                AppEvent::Notification {
+                                    room_id: room.room_id().to_string(),
+                                    event_id: event.event_id.to_string(),
+                                    sender: event.sender.to_string(),
+                                    body: body.clone(),
+                                    highlight: verdict.highlight,
+                                    mentions_me,
+                                },
+                            ),
+                            Ok(_) => {}
+                            Err(e) => warn!("push rules: {e}"),
+                        }
+                    }
+                    let ts = u64::from(event.origin_server_ts.0);
+                    let cached = CachedMessage {
+                        event_id: event.event_id.to_string(),
+                        sender: event.sender.to_string(),
+                        body: body.clone(),
+                        ts,
+                        mentions_me,
+                        html: html.clone(),
+                        reply_to: reply_to.clone(),
+                        thread_root: thread_root.clone(),
+                        edited: false,
+                        poll: None,
+                        sticker: None,
+                        voice: voice.clone(),
+                        undecrypted: false,
+                        membership: false,
+                    };
+                    if let Err(e) = spoke.append_cached_message(room.room_id(), cached).await {
+                        warn!("timeline cache: {e}");
                     }
+                    let item = TimelineItem {
+                        event_id: Some(event.event_id.to_string()),
+                        txn_id: event.unsigned.transaction_id.map(|t| t.to_string()),
+                        sender: event.sender.to_string(),
+                        rich: html.as_deref().map(RichText::from_html),
+                        body,
+                        ts,
+                        delivery: None,
+                        mentions_me,
+                        reply_to,
+                        thread_root,
+                        edited: false,
+                        poll: None,
+                        sticker: None,
+                        voice,
+                        undecrypted: false,
+                        membership: false,
+                    };
+                    activity.record(room.room_id().as_str(), RoomPreview::from(&item));
+                    send(
+                        &tx,
+                        &ctx,
+                        AppEvent::Message {
+                            room_id: room.room_id().to_string(),
+                            item,
+                        },
+                    );
                 }
             },
         );
     }
 
-    // Incoming invites — StrippedRoomMemberEvent fires for invited rooms.
+    // Messages the SDK couldn't decrypt: shown as placeholders until their
+    // keys arrive.
     {
         let tx = event_tx.clone();
         let ctx = ctx.clone();
+        let spoke = client.clone();
         client.inner.add_event_handler(
-            move |event: StrippedRoomMemberEvent, _room: Room, client: Client| {
-                let tx = tx.clone(); let ctx = ctx.clone();
+            move |_: OriginalSyncRoomEncryptedEvent, room: Room, raw: RawEvent| {
+                let tx = tx.clone();
+                let ctx = ctx.clone();
+                let spoke = spoke.clone();
                 async move {
-                    if event.content.membership != MembershipState::Invite { return; }
-                    let Some(user_id) = client.user_id() else { return };
-                    if event.state_key != user_id { return; }
-                    send(&tx, &ctx, AppEvent::InvitesUpdated(
-                        collect_invites_from_client(&client)
-                    ));
+                    if room.state() != RoomState::Joined {
+                        return;
+                    }
+                    let raw = Raw::<AnySyncTimelineEvent>::from_json(raw.0);
+                    let Some(utd) = spoke.track_undecrypted(room.room_id(), &raw) else {
+                        return;
+                    };
+                    let cached = undecrypted_message(&utd);
+                    if let Err(e) = spoke
+                        .append_cached_message(room.room_id(), cached.clone())
+                        .await
+                    {
+                        warn!("timeline cache: {e}");
+                    }
+                    let item = TimelineItem::from(cached);
+                    send(
+                        &tx,
+                        &ctx,
+                        AppEvent::Message {
+                            room_id: room.room_id().to_string(),
+                            item,
+                        },
+                    );
                 }
             },
         );
     }
-
-    // ── Initial sync ──────────────────────────────────────────────────────────
-
-    if let Err(e) = client.inner.sync_once(Default::default()).await {
-        send(&event_tx, &ctx, AppEvent::Error(e.to_string())); return;
+    // Retry them as keys arrive, from our other devices or key backup.
+    {
+        let tx = event_tx.clone();
+        let ctx = ctx.clone();
+        let spoke = client.clone();
+        tokio::spawn(async move {
+            let Some(keys) = spoke.room_keys_received().await else {
+                return;
+            };
+            futures::pin_mut!(keys);
+            while let Some(sessions) = keys.next().await {
+                for (room_id, raw) in spoke.retry_decryption(&sessions).await {
+                    let Ok(AnySyncTimelineEvent::MessageLike(
+                        AnySyncMessageLikeEvent::RoomMessage(ev),
+                    )) = raw.deserialize()
+                    else {
+                        continue;
+                    };
+                    let Some(original) = ev.as_original() else {
+                        continue;
+                    };
+                    let Some(message) = room_message(original, &raw, spoke.inner.user_id()) else {
+                        continue;
+                    };
+                    if let Err(e) = spoke.update_cached_message(&room_id, message.clone()).await {
+                        warn!("timeline cache: {e}");
+                    }
+                    send(
+                        &tx,
+                        &ctx,
+                        AppEvent::Decrypted {
+                            room_id: room_id.to_string(),
+                            item: message.into(),
+                        },
+                    );
+                }
+            }
+        });
     }
 
-    send(&event_tx, &ctx, AppEvent::RoomsUpdated(collect_rooms(&client)));
-    send(&event_tx, &ctx, AppEvent::InvitesUpdated(collect_invites(&client)));
-
-    // ── Command handler ───────────────────────────────────────────────────────
-
-    let inner = client.inner.clone();
-    let tx = event_tx.clone();
-    let ctx_cmd = ctx.clone();
-
-    tokio::spawn(async move {
-        let mut voice: Option<VoiceSession> = None;
-        let mut voice_room_id: Option<String> = None;
-        let sidecar_url = std::env::var("SPOKE_SIDECAR")
-            .unwrap_or_else(|_| "http://localhost:8090".into());
-        let http = reqwest::Client::new();
-
-        while let Some(cmd) = cmd_rx.recv().await {
-            match cmd {
-                AppCommand::SendMessage { room_id, body } => {
-                    let Ok(rid) = RoomId::parse(&room_id) else { continue };
-                    if let Some(room) = inner.get_room(&rid) {
-                        if let Err(e) = room.send(RoomMessageEventContent::text_plain(body)).await {
-                            warn!("send: {e}");
-                        }
+    // Call summaries, shown in the timeline as a one-line card.
+    {
+        let tx = event_tx.clone();
+        let ctx = ctx.clone();
+        let spoke = client.clone();
+        client.inner.add_event_handler(
+            move |event: OriginalSyncMessageLikeEvent<VoiceSummaryEventContent>, room: Room| {
+                let tx = tx.clone();
+                let ctx = ctx.clone();
+                let spoke = spoke.clone();
+                async move {
+                    if room.state() != RoomState::Joined {
+                        return;
                     }
+                    let cached = summary_message(&event);
+                    if let Err(e) = spoke
+                        .append_cached_message(room.room_id(), cached.clone())
+                        .await
+                    {
+                        warn!("timeline cache: {e}");
+                    }
+                    let mut item = TimelineItem::from(cached);
+                    item.txn_id = event.unsigned.transaction_id.map(|t| t.to_string());
+                    send(
+                        &tx,
+                        &ctx,
+                        AppEvent::Message {
+                            room_id: room.room_id().to_string(),
+                            item,
+                        },
+                    );
                 }
+            },
+        );
+    }
 
-                AppCommand::InviteUser { room_id, mxid } => {
-                    let Ok(rid) = RoomId::parse(&room_id) else { continue };
-                    let Ok(uid) = UserId::parse(&mxid) else {
-                        warn!("invalid mxid: {mxid}"); continue;
-                    };
-                    if let Some(room) = inner.get_room(&rid) {
-                        if let Err(e) = room.invite_user_by_id(&uid).await {
-                            warn!("invite: {e}");
-                            send(&tx, &ctx_cmd, AppEvent::Error(e.to_string()));
-                        }
+    // Watch together
+    {
+        let tx = event_tx.clone();
+        let ctx = ctx.clone();
+        client.inner.add_event_handler(
+            move |event: OriginalSyncMessageLikeEvent<MediaSyncEventContent>, room: Room| {
+                let tx = tx.clone();
+                let ctx = ctx.clone();
+                async move {
+                    if room.state() != RoomState::Joined {
+                        return;
                     }
+                    let update = SharedMediaUpdate::from_event(&event);
+                    send(
+                        &tx,
+                        &ctx,
+                        AppEvent::SharedMediaUpdated {
+                            room_id: room.room_id().to_string(),
+                            update,
+                        },
+                    );
                 }
+            },
+        );
+    }
 
-                AppCommand::JoinRoom { room_id } => {
-                    let Ok(rid) = RoomId::parse(&room_id) else { continue };
-                    match inner.join_room_by_id(&rid).await {
-                        Ok(_) => {
-                            send(&tx, &ctx_cmd, AppEvent::Joined { room_id });
-                            send(&tx, &ctx_cmd, AppEvent::RoomsUpdated(collect_rooms_from_client(&inner)));
-                            send(&tx, &ctx_cmd, AppEvent::InvitesUpdated(collect_invites_from_client(&inner)));
-                        }
-                        Err(e) => {
-                            warn!("join: {e}");
-                            send(&tx, &ctx_cmd, AppEvent::Error(e.to_string()));
-                        }
+    // Stickers
+    {
+        let tx = event_tx.clone();
+        let ctx = ctx.clone();
+        let spoke = client.clone();
+        client.inner.add_event_handler(
+            move |event: OriginalSyncMessageLikeEvent<StickerEventContent>, room: Room| {
+                let tx = tx.clone();
+                let ctx = ctx.clone();
+                let spoke = spoke.clone();
+                async move {
+                    if room.state() != RoomState::Joined {
+                        return;
+                    }
+                    let cached = sticker_message(&event);
+                    if let Err(e) = spoke
+                        .append_cached_message(room.room_id(), cached.clone())
+                        .await
+                    {
+                        warn!("timeline cache: {e}");
                     }
+                    let mut item = TimelineItem::from(cached);
+                    item.txn_id = event.unsigned.transaction_id.map(|t| t.to_string());
+                    send(
+                        &tx,
+                        &ctx,
+                        AppEvent::Message {
+                            room_id: room.room_id().to_string(),
+                            item,
+                        },
+                    );
                 }
+            },
+        );
+    }
 
-                AppCommand::CreateRoom { name } => {
-                    let mut req = CreateRoomRequest::new();
-                    req.name = Some(name);
-                    match inner.create_room(req).await {
-                        Ok(resp) => {
-                            let room_id = resp.room_id().to_string();
-                            send(&tx, &ctx_cmd, AppEvent::Joined { room_id: room_id.clone() });
-                            send(&tx, &ctx_cmd, AppEvent::RoomsUpdated(collect_rooms_from_client(&inner)));
-                        }
-                        Err(e) => {
-                            warn!("create_room: {e}");
-                            send(&tx, &ctx_cmd, AppEvent::Error(e.to_string()));
-                        }
+    // Polls: the start is a timeline item; votes and ends update it.
+    {
+        let tx = event_tx.clone();
+        let ctx = ctx.clone();
+        let spoke = client.clone();
+        client.inner.add_event_handler(
+            move |event: OriginalSyncMessageLikeEvent<PollStartEventContent>, room: Room| {
+                let tx = tx.clone();
+                let ctx = ctx.clone();
+                let spoke = spoke.clone();
+                async move {
+                    if room.state() != RoomState::Joined {
+                        return;
+                    }
+                    let cached = poll_message(&event);
+                    if let Err(e) = spoke
+                        .append_cached_message(room.room_id(), cached.clone())
+                        .await
+                    {
+                        warn!("timeline cache: {e}");
                     }
+                    let mut item = TimelineItem::from(cached);
+                    item.txn_id = event.unsigned.transaction_id.map(|t| t.to_string());
+                    send(
+                        &tx,
+                        &ctx,
+                        AppEvent::Message {
+                            room_id: room.room_id().to_string(),
+                            item,
+                        },
+                    );
+                }
+            },
+        );
+    }
+    {
+        let tx = event_tx.clone();
+        let ctx = ctx.clone();
+        client.inner.add_event_handler(
+            move |event: OriginalSyncMessageLikeEvent<PollResponseEventContent>, room: Room| {
+                let tx = tx.clone();
+                let ctx = ctx.clone();
+                async move {
+                    let updates = vec![poll_response(&event)];
+                    send(
+                        &tx,
+                        &ctx,
+                        AppEvent::PollUpdates {
+                            room_id: room.room_id().to_string(),
+                            updates,
+                        },
+                    );
+                }
+            },
+        );
+    }
+    {
+        let tx = event_tx.clone();
+        let ctx = ctx.clone();
+        client.inner.add_event_handler(
+            move |event: OriginalSyncMessageLikeEvent<PollEndEventContent>, room: Room| {
+                let tx = tx.clone();
+                let ctx = ctx.clone();
+                async move {
+                    let updates = vec![poll_end(&event)];
+                    send(
+                        &tx,
+                        &ctx,
+                        AppEvent::PollUpdates {
+                            room_id: room.room_id().to_string(),
+                            updates,
+                        },
+                    );
                 }
+            },
+        );
+    }
 
-                AppCommand::JoinRoomByAlias { alias } => {
-                    let id: OwnedRoomOrAliasId = match alias.try_into() {
-                        Ok(id) => id,
-                        Err(e) => { warn!("invalid alias: {e}"); continue; }
+    // Reactions, and redactions (which may take one back).
+    {
+        let tx = event_tx.clone();
+        let ctx = ctx.clone();
+        client
+            .inner
+            .add_event_handler(move |event: OriginalSyncReactionEvent, room: Room| {
+                let tx = tx.clone();
+                let ctx = ctx.clone();
+                async move {
+                    let reaction = Reaction {
+                        event_id: event.event_id.to_string(),
+                        target: event.content.relates_to.event_id.to_string(),
+                        key: event.content.relates_to.key,
+                        sender: event.sender.to_string(),
                     };
-                    match inner.join_room_by_id_or_alias(&id, &[]).await {
-                        Ok(room) => {
-                            let room_id = room.room_id().to_string();
-                            send(&tx, &ctx_cmd, AppEvent::Joined { room_id });
-                            send(&tx, &ctx_cmd, AppEvent::RoomsUpdated(collect_rooms_from_client(&inner)));
-                        }
-                        Err(e) => {
-                            warn!("join: {e}");
-                            send(&tx, &ctx_cmd, AppEvent::Error(e.to_string()));
-                        }
-                    }
+                    send(
+                        &tx,
+                        &ctx,
+                        AppEvent::Reactions {
+                            room_id: room.room_id().to_string(),
+                            reactions: vec![reaction],
+                        },
+                    );
                 }
+            });
+    }
+    {
+        let tx = event_tx.clone();
+        let ctx = ctx.clone();
+        client
+            .inner
+            .add_event_handler(move |event: OriginalSyncRoomRedactionEvent, room: Room| {
+                let tx = tx.clone();
+                let ctx = ctx.clone();
+                async move {
+                    let Some(redacts) = event.redacts.or(event.content.redacts) else {
+                        return;
+                    };
+                    send(
+                        &tx,
+                        &ctx,
+                        AppEvent::Redacted {
+                            room_id: room.room_id().to_string(),
+                            event_id: redacts.to_string(),
+                        },
+                    );
+                }
+            });
+    }
 
-                AppCommand::LeaveRoom { room_id } => {
-                    let Ok(rid) = RoomId::parse(&room_id) else { continue };
-                    if let Some(room) = inner.get_room(&rid) {
-                        match room.leave().await {
-                            Ok(_) => send(&tx, &ctx_cmd, AppEvent::RoomsUpdated(collect_rooms_from_client(&inner))),
-                            Err(e) => {
-                                warn!("leave: {e}");
-                                send(&tx, &ctx_cmd, AppEvent::Error(e.to_string()));
-                            }
+    // Typing notifications.
+    {
+        let tx = event_tx.clone();
+        let ctx = ctx.clone();
+        client.inner.add_event_handler(
+            move |event: SyncTypingEvent, room: Room, client: Client| {
+                let tx = tx.clone();
+                let ctx = ctx.clone();
+                async move {
+                    let own = client.user_id();
+                    let user_ids = event
+                        .content
+                        .user_ids
+                        .iter()
+                        .filter(|u| Some(&***u) != own)
+                        .map(|u| u.to_string())
+                        .collect();
+                    send(
+                        &tx,
+                        &ctx,
+                        AppEvent::TypingUpdated {
+                            room_id: room.room_id().to_string(),
+                            user_ids,
+                        },
+                    );
+                }
+            },
+        );
+    }
+
+    // Public read receipts.
+    {
+        let tx = event_tx.clone();
+        let ctx = ctx.clone();
+        client.inner.add_event_handler(
+            move |event: SyncReceiptEvent, room: Room, client: Client| {
+                let tx = tx.clone();
+                let ctx = ctx.clone();
+                async move {
+                    let own = client.user_id();
+                    let mut receipts = Vec::new();
+                    for (event_id, by_type) in &event.content.0 {
+                        let Some(users) = by_type.get(&ReceiptType::Read) else {
+                            continue;
+                        };
+                        for user_id in users.keys().filter(|u| Some(&***u) != own) {
+                            receipts.push((user_id.to_string(), event_id.to_string()));
                         }
                     }
+                    if !receipts.is_empty() {
+                        send(
+                            &tx,
+                            &ctx,
+                            AppEvent::ReceiptsUpdated {
+                                room_id: room.room_id().to_string(),
+                                receipts,
+                            },
+                        );
+                    }
                 }
+            },
+        );
+    }
 
-                // ── Voice commands ─────────────────────────────────────────────
-
-                AppCommand::JoinVoice { room_id } => {
-                    // Tear down any existing session first.
-                    if let Some(old) = voice.take() {
-                        old.disconnect().await;
+    // Kicks, bans and unbans; knocks arriving or being answered.
+    {
+        let tx = event_tx.clone();
+        let ctx = ctx.clone();
+        let spoke = client.clone();
+        client
+            .inner
+            .add_event_handler(move |event: OriginalSyncRoomMemberEvent, room: Room| {
+                let tx = tx.clone();
+                let ctx = ctx.clone();
+                let spoke = spoke.clone();
+                async move {
+                    let change = event.membership_change();
+                    if matches!(
+                        change,
+                        MembershipChange::Knocked
+                            | MembershipChange::KnockAccepted
+                            | MembershipChange::KnockDenied
+                            | MembershipChange::KnockRetracted
+                    ) {
+                        send_knocks(&spoke, room.room_id(), &tx, &ctx).await;
                     }
-
-                    // Get the Matrix access token.
-                    let access_token = match inner.session() {
-                        Some(AuthSession::Matrix(s)) => s.tokens.access_token.clone(),
-                        _ => {
-                            warn!("JoinVoice: no matrix session");
-                            send(&tx, &ctx_cmd, AppEvent::Error("not logged in".into()));
-                            continue;
+                    if let MembershipChange::ProfileChanged { .. } = change {
+                        let profile = Profile {
+                            display_name: event.content.displayname.clone(),
+                            avatar_url: event.content.avatar_url.as_ref().map(|u| u.to_string()),
+                        };
+                        send(
+                            &tx,
+                            &ctx,
+                            AppEvent::ProfileResolved {
+                                user_id: event.state_key.to_string(),
+                                profile: sender_profile(&spoke, profile).await,
+                            },
+                        );
+                        return;
+                    }
+                    let action = match change {
+                        MembershipChange::Kicked => ModerationAction::Kick,
+                        MembershipChange::Banned | MembershipChange::KickedAndBanned => {
+                            ModerationAction::Ban
                         }
+                        MembershipChange::Unbanned => ModerationAction::Unban,
+                        _ => return,
                     };
+                    send(
+                        &tx,
+                        &ctx,
+                        AppEvent::MembershipChanged {
+                            room_id: room.room_id().to_string(),
+                            user_id: event.state_key.to_string(),
+                            action,
+                            by: event.sender.to_string(),
+                            reason: event.content.reason.clone(),
+                        },
+                    );
+                }
+            });
+    }
 
-                    // Send org.spoke.voice.join to the room.
-                    let session_id = uuid::Uuid::new_v4().to_string();
-                    if let Ok(rid) = RoomId::parse(&room_id) {
-                        if let Some(room) = inner.get_room(&rid) {
-                            let content = VoiceJoinEventContent { session_id };
-                            if let Err(e) = room.send(content).await {
-                                warn!("voice join event: {e}");
-                            }
-                        }
+    // Joins, leaves, kicks, bans and name changes as timeline lines. Only
+    // timeline events: a room's state section would replay everyone's join.
+    {
+        let tx = event_tx.clone();
+        let ctx = ctx.clone();
+        let spoke = client.clone();
+        client
+            .inner
+            .add_event_handler(move |ev: AnySyncTimelineEvent, room: Room| {
+                let tx = tx.clone();
+                let ctx = ctx.clone();
+                let spoke = spoke.clone();
+                async move {
+                    if room.state() != RoomState::Joined {
+                        return;
                     }
-
-                    // Ask the sidecar for a LiveKit token.
-                    let resp = http
-                        .post(format!("{sidecar_url}/_spoke/v1/voice/token"))
-                        .bearer_auth(&access_token)
-                        .json(&serde_json::json!({"room_id": &room_id}))
-                        .send()
-                        .await;
-
-                    let resp = match resp {
-                        Ok(r) if r.status().is_success() => r,
-                        Ok(r) => {
-                            warn!("sidecar returned {}", r.status());
-                            send(&tx, &ctx_cmd, AppEvent::Error(
-                                format!("sidecar error: {}", r.status()),
-                            ));
-                            continue;
-                        }
-                        Err(e) => {
-                            warn!("sidecar request: {e}");
-                            send(&tx, &ctx_cmd, AppEvent::Error(format!("sidecar: {e}")));
-                            continue;
-                        }
+                    let AnySyncTimelineEvent::State(AnySyncStateEvent::RoomMember(
+                        SyncStateEvent::Original(event),
+                    )) = ev
+                    else {
+                        return;
                     };
-
-                    let body: serde_json::Value =
-                        match resp.json().await {
-                            Ok(v) => v,
-                            Err(e) => {
-                                warn!("sidecar response parse: {e}");
-                                send(&tx, &ctx_cmd, AppEvent::Error(format!("sidecar parse: {e}")));
-                                continue;
-                            }
-                        };
-
-                    let lk_url = body["livekit_url"]
-                        .as_str()
-                        .unwrap_or("ws://localhost:7880")
-                        .to_owned();
-                    let lk_token = body["livekit_token"]
-                        .as_str()
-                        .unwrap_or("")
-                        .to_owned();
-
-                    // Connect to LiveKit.
-                    let (voice_event_tx, mut voice_event_rx) =
-                        tokio_mpsc::unbounded_channel::<VoiceEvent>();
-
-                    match VoiceSession::connect(&lk_url, &lk_token, voice_event_tx).await {
-                        Ok(session) => {
-                            voice = Some(session);
-                            voice_room_id = Some(room_id.clone());
-                            send(&tx, &ctx_cmd, AppEvent::VoiceJoined { room_id });
-
-                            // Forward VoiceEvents → AppEvents.
-                            let tx2 = tx.clone();
-                            let ctx2 = ctx_cmd.clone();
-                            tokio::spawn(async move {
-                                while let Some(ve) = voice_event_rx.recv().await {
-                                    match ve {
-                                        VoiceEvent::ParticipantsUpdated(ps) => {
-                                            send(&tx2, &ctx2, AppEvent::VoiceParticipantsUpdated(ps));
-                                        }
-                                        VoiceEvent::Error(e) => {
-                                            send(&tx2, &ctx2, AppEvent::Error(format!("voice: {e}")));
-                                        }
-                                    }
-                                }
-                            });
-                        }
-                        Err(e) => {
-                            warn!("voice connect: {e}");
-                            send(&tx, &ctx_cmd, AppEvent::Error(format!("voice: {e}")));
-                        }
+                    let Some(cached) = membership_message(&event) else {
+                        return;
+                    };
+                    if let Err(e) = spoke
+                        .append_cached_message(room.room_id(), cached.clone())
+                        .await
+                    {
+                        warn!("timeline cache: {e}");
                     }
+                    send(
+                        &tx,
+                        &ctx,
+                        AppEvent::Message {
+                            room_id: room.room_id().to_string(),
+                            item: TimelineItem::from(cached),
+                        },
+                    );
                 }
+            });
+    }
 
-                AppCommand::LeaveVoice => {
-                    if let Some(session) = voice.take() {
-                        session.disconnect().await;
+    // Incoming invites — StrippedRoomMemberEvent fires for invited rooms.
+    {
+        let tx = event_tx.clone();
+        let ctx = ctx.clone();
+        client.inner.add_event_handler(
+            move |event: StrippedRoomMemberEvent, _room: Room, client: Client| {
+                let tx = tx.clone();
+                let ctx = ctx.clone();
+                async move {
+                    if event.content.membership != MembershipState::Invite {
+                        return;
                     }
-                    // Send org.spoke.voice.leave.
-                    if let Some(rid_str) = voice_room_id.take() {
-                        if let Ok(rid) = RoomId::parse(&rid_str) {
-                            if let Some(room) = inner.get_room(&rid) {
-                                let _ = room.send(VoiceLeaveEventContent {}).await;
-                            }
-                        }
+                    let Some(user_id) = client.user_id() else {
+                        return;
+                    };
+                    if event.state_key != user_id {
+                        return;
                     }
-                    send(&tx, &ctx_cmd, AppEvent::VoiceLeft);
+                    send(
+                        &tx,
+                        &ctx,
+                        AppEvent::InvitesUpdated(collect_invites_from_client(&client)),
+                    );
                 }
-
-                AppCommand::MuteVoice { muted } => {
+            },
+        );
+    }
+
+    // Stage state — speaker list or stage toggle changed.
+    {
+        let tx = event_tx.clone();
+        let ctx = ctx.clone();
+        let internal = internal_tx.clone();
+        client.inner.add_event_handler(
+            move |_: OriginalSyncStateEvent<VoiceStageEventContent>, room: Room| {
+                let tx = tx.clone();
+                let ctx = ctx.clone();
+                let internal = internal.clone();
+                async move {
+                    on_stage_changed(&room, &tx, &ctx, &internal).await;
+                }
+            },
+        );
+    }
+    {
+        let tx = event_tx.clone();
+        let ctx = ctx.clone();
+        let internal = internal_tx.clone();
+        client.inner.add_event_handler(
+            move |_: OriginalSyncStateEvent<VoiceConfigEventContent>, room: Room| {
+                let tx = tx.clone();
+                let ctx = ctx.clone();
+                let internal = internal.clone();
+                async move {
+                    send_voice_config(&room, &tx, &ctx).await;
+                    on_stage_changed(&room, &tx, &ctx, &internal).await;
+                }
+            },
+        );
+    }
+    // Power levels decide voice rights too (priority speakers, thresholds).
+    {
+        let internal = internal_tx.clone();
+        client.inner.add_event_handler(
+            move |_: OriginalSyncStateEvent<RoomPowerLevelsEventContent>, room: Room| {
+                let internal = internal.clone();
+                async move {
+                    let _ = internal.send(AppCommand::RefreshVoiceGrant {
+                        room_id: room.room_id().to_string(),
+                    });
+                }
+            },
+        );
+    }
+
+    // Any timeline event counts as activity, for ordering the sidebar; the
+    // next room list carries it.
+    {
+        let activity = activity.clone();
+        client
+            .inner
+            .add_event_handler(move |ev: AnySyncTimelineEvent, room: Room| {
+                activity.touch(room.room_id().as_str(), u64::from(ev.origin_server_ts().0));
+                async {}
+            });
+    }
+
+    // Pins changed; the UI reloads them if it's showing them.
+    {
+        let tx = event_tx.clone();
+        let ctx = ctx.clone();
+        client.inner.add_event_handler(
+            move |_: OriginalSyncStateEvent<RoomPinnedEventsEventContent>, room: Room| {
+                let tx = tx.clone();
+                let ctx = ctx.clone();
+                async move {
+                    send(
+                        &tx,
+                        &ctx,
+                        AppEvent::PinsChanged {
+                            room_id: room.room_id().to_string(),
+                        },
+                    );
+                }
+            },
+        );
+    }
+
+    // Voice memberships: who's in each call, and others joining, for call
+    // notifications. Heartbeats only move the expiry, so they don't ping.
+    {
+        let tx = event_tx.clone();
+        let ctx = ctx.clone();
+        client.inner.add_event_handler(
+            move |event: OriginalSyncStateEvent<VoiceMemberEventContent>,
+                  room: Room,
+                  client: Client| {
+                let tx = tx.clone();
+                let ctx = ctx.clone();
+                async move {
+                    send_voice_members(&room, &tx, &ctx).await;
+                    if client.user_id() == Some(&event.sender) || !event.content.just_joined() {
+                        return;
+                    }
+                    send(
+                        &tx,
+                        &ctx,
+                        AppEvent::VoicePing {
+                            room_id: room.room_id().to_string(),
+                            sender: event.sender.to_string(),
+                        },
+                    );
+                }
+            },
+        );
+    }
+
+    // Roaming settings changed on another device (or echoed back from ours).
+    {
+        let tx = event_tx.clone();
+        let ctx = ctx.clone();
+        client.inner.add_event_handler(
+            move |event: GlobalAccountDataEvent<AccountSettingsEventContent>| {
+                let tx = tx.clone();
+                let ctx = ctx.clone();
+                async move {
+                    send(&tx, &ctx, AppEvent::AccountSettings(Some(event.content)));
+                }
+            },
+        );
+    }
+
+    // Rings for DM calls, sent straight to our devices.
+    {
+        let tx = event_tx.clone();
+        let ctx = ctx.clone();
+        client.inner.add_event_handler(
+            move |event: ToDeviceEvent<VoiceRingEventContent>, client: Client| {
+                let tx = tx.clone();
+                let ctx = ctx.clone();
+                async move {
+                    let content = event.content;
+                    if content.action != RingAction::Ring {
+                        send(
+                            &tx,
+                            &ctx,
+                            AppEvent::RingEnded {
+                                call_id: content.call_id,
+                                user: event.sender.to_string(),
+                                action: content.action,
+                            },
+                        );
+                        return;
+                    }
+                    if client.user_id() == Some(&event.sender) || !content.is_current() {
+                        return;
+                    }
+                    if ring::ring_room(&client, &event.sender, &content)
+                        .await
+                        .is_none()
+                    {
+                        warn!(
+                            "ignoring ring from {} for {}",
+                            event.sender, content.room_id
+                        );
+                        return;
+                    }
+                    send(
+                        &tx,
+                        &ctx,
+                        AppEvent::IncomingCall {
+                            room_id: content.room_id.to_string(),
+                            call_id: content.call_id,
+                            caller: event.sender.to_string(),
+                        },
+                    );
+                }
+            },
+        );
+    }
+
+    // Raised hands.
+    {
+        let tx = event_tx.clone();
+        let ctx = ctx.clone();
+        client.inner.add_event_handler(
+            move |event: OriginalSyncMessageLikeEvent<VoiceHandEventContent>, room: Room| {
+                let tx = tx.clone();
+                let ctx = ctx.clone();
+                async move {
+                    send(
+                        &tx,
+                        &ctx,
+                        AppEvent::HandRaised {
+                            room_id: room.room_id().to_string(),
+                            user_id: event.sender.to_string(),
+                            raised: event.content.raised,
+                        },
+                    );
+                }
+            },
+        );
+    }
+
+    // ── Cached timeline ───────────────────────────────────────────────────────
+
+    // Rooms restored from the store plus their cached messages, so the UI has
+    // something to show while the initial sync is in flight.
+    let mut cached_rooms = Vec::new();
+    for room in client.inner.joined_rooms() {
+        match client.cached_timeline(room.room_id()).await {
+            Ok(cached) if !cached.is_empty() => {
+                let messages: Vec<TimelineItem> =
+                    cached.into_iter().map(TimelineItem::from).collect();
+                if let Some(last) = messages.iter().rev().find(|m| !m.membership) {
+                    activity.record(room.room_id().as_str(), RoomPreview::from(last));
+                }
+                cached_rooms.push((room.room_id().to_string(), messages));
+            }
+            Ok(_) => {}
+            Err(e) => warn!("timeline cache {}: {e}", room.room_id()),
+        }
+    }
+    send(
+        &event_tx,
+        &ctx,
+        AppEvent::RoomsUpdated(collect_rooms(&client, &activity)),
+    );
+    for (room_id, messages) in cached_rooms {
+        send(
+            &event_tx,
+            &ctx,
+            AppEvent::CachedHistoryLoaded { room_id, messages },
+        );
+    }
+
+    // ── Send queue ────────────────────────────────────────────────────────────
+
+    let (delivery_tx, mut delivery_rx) = tokio_mpsc::unbounded_channel::<DeliveryUpdate>();
+    let send_queue = match SendQueue::start(client.clone(), delivery_tx).await {
+        Ok((queue, pending)) => {
+            let own_id = own_user_id(&client.inner);
+            for m in pending {
+                let delivery = match m.failed {
+                    Some(error) => DeliveryState::Failed { error },
+                    None => DeliveryState::Sending,
+                };
+                send(
+                    &event_tx,
+                    &ctx,
+                    AppEvent::MessageQueued {
+                        room_id: m.room_id,
+                        item: local_echo(&own_id, m.txn_id, m.text, delivery),
+                    },
+                );
+            }
+            queue
+        }
+        Err(e) => {
+            send(&event_tx, &ctx, AppEvent::Error(e.to_string()));
+            return;
+        }
+    };
+    {
+        let tx = event_tx.clone();
+        let ctx = ctx.clone();
+        tokio::spawn(async move {
+            while let Some(update) = delivery_rx.recv().await {
+                send(
+                    &tx,
+                    &ctx,
+                    AppEvent::MessageDelivery {
+                        room_id: update.room_id,
+                        txn_id: update.txn_id,
+                        state: update.state,
+                    },
+                );
+            }
+        });
+    }
+
+    // ── Initial sync ──────────────────────────────────────────────────────────
+
+    // Not fatal: the sync loop below keeps retrying, and queued messages go
+    // out once the homeserver is reachable again.
+    let mut settings = filtered_sync_settings(&client, &sync_filter).await;
+    let (filter_tx, mut filter_rx) = tokio::sync::watch::channel(sync_filter);
+    let sync_with_presence = |settings: &SyncSettings, offline: &AtomicBool| {
+        settings.clone().set_presence(sync_presence(offline))
+    };
+    if let Err(e) = client
+        .inner
+        .sync_once(sync_with_presence(&settings, &appear_offline))
+        .await
+    {
+        warn!("initial sync: {e}");
+        send(&event_tx, &ctx, AppEvent::Error(e.to_string()));
+    }
+
+    send(
+        &event_tx,
+        &ctx,
+        AppEvent::RoomsUpdated(collect_rooms(&client, &activity)),
+    );
+    send(
+        &event_tx,
+        &ctx,
+        AppEvent::InvitesUpdated(collect_invites(&client)),
+    );
+    send_left_rooms(&client, &event_tx, &ctx).await;
+    // Offline, we don't know the account's settings; the local ones stand
+    // until a later sign-in can compare.
+    match client.account_settings().await {
+        Ok(settings) => send(&event_tx, &ctx, AppEvent::AccountSettings(settings)),
+        Err(e) => warn!("roaming settings: {e}"),
+    }
+    for room in client.inner.joined_rooms() {
+        send_knocks(&client, room.room_id(), &event_tx, &ctx).await;
+        send_voice_members(&room, &event_tx, &ctx).await;
+    }
+
+    // Space hierarchies — fetched in the background so the flat room list
+    // shows up without waiting on one /hierarchy walk per space.
+    {
+        let spoke = client.clone();
+        let tx = event_tx.clone();
+        let ctx = ctx.clone();
+        tokio::spawn(async move {
+            for space in spoke
+                .inner
+                .joined_rooms()
+                .into_iter()
+                .filter(|r| r.is_space())
+            {
+                send_space_hierarchy(&spoke, space.room_id(), &tx, &ctx).await;
+            }
+        });
+    }
+
+    // AFK checks, fed into the command loop like any other command.
+    {
+        let internal = internal_tx.clone();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(AFK_CHECK_INTERVAL);
+            loop {
+                ticks.tick().await;
+                if internal.send(AppCommand::CheckAfk).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    // ── Command handler ───────────────────────────────────────────────────────
+
+    let spoke = client.clone();
+    let inner = client.inner.clone();
+    let tx = event_tx.clone();
+    let ctx_cmd = ctx.clone();
+    let activity_cmd = activity.clone();
+    // Dropped when the command loop ends, which stops the sync loop.
+    let (running, mut stopped) = tokio::sync::oneshot::channel::<()>();
+    let internal_cmd = internal_tx.clone();
+    let appear_offline_cmd = appear_offline.clone();
+
+    tokio::spawn(async move {
+        let _running = running;
+        let mut voice: Option<VoiceSession> = None;
+        let mut voice_room_id: Option<String> = None;
+        // Our ringing call, to cancel if we leave before anyone answers:
+        // (room, call ID, who was rung).
+        let mut outgoing_ring: Option<(String, String, Vec<OwnedUserId>)> = None;
+        // Renews our voice membership while we're in a call.
+        let mut heartbeat: Option<tokio::task::JoinHandle<()>> = None;
+        let mut preload: Option<tokio::task::JoinHandle<()>> = None;
+        let mut recorder: Option<Recorder> = None;
+        let mut mic_test: Option<MicTest> = None;
+        // Set to stop the voice message playing.
+        let mut playback: Option<Arc<AtomicBool>> = None;
+        let sidecar_url =
+            std::env::var("SPOKE_SIDECAR").unwrap_or_else(|_| "http://localhost:8090".into());
+        let http = spoke_core::proxy::http_client().unwrap_or_else(|e| {
+            warn!("proxy: {e}; reaching the sidecar directly");
+            reqwest::Client::new()
+        });
+        let mut grants = GrantCache::default();
+        // From the last JoinVoice; reused when a grant refresh reconnects.
+        let mut ice_settings = IceSettings::default();
+        let mut tuning = VoiceTuning::default();
+        let mut audio_devices = DeviceChoice::default();
+        // Carried over when a grant refresh reconnects in the same room.
+        let mut voice_muted = false;
+        let mut voice_deafened = false;
+
+        loop {
+            let cmd = tokio::select! {
+                cmd = cmd_rx.recv() => match cmd {
+                    Some(cmd) => cmd,
+                    None => break,
+                },
+                Some(cmd) = internal_rx.recv() => cmd,
+            };
+            match cmd {
+                AppCommand::SendMessage { room_id, text } => {
+                    let txn_id = send_queue.enqueue(&room_id, text.clone());
+                    let item =
+                        local_echo(&own_user_id(&inner), txn_id, text, DeliveryState::Sending);
+                    send(&tx, &ctx_cmd, AppEvent::MessageQueued { room_id, item });
+                }
+
+                AppCommand::RetryMessage { txn_id } => send_queue.retry(&txn_id),
+
+                AppCommand::SetSyncFilter(options) => {
+                    let _ = filter_tx.send(options);
+                }
+
+                AppCommand::DiscardMessage { txn_id } => send_queue.discard(&txn_id),
+
+                AppCommand::SendReaction {
+                    room_id,
+                    event_id,
+                    key,
+                } => {
+                    let (Ok(rid), Ok(eid)) = (RoomId::parse(&room_id), EventId::parse(&event_id))
+                    else {
+                        continue;
+                    };
+                    // The reaction comes back through sync like anyone else's.
+                    // Scheduled off the command loop so a rate limit doesn't
+                    // hold up everything else.
+                    let (spoke, tx, ctx) = (spoke.clone(), tx.clone(), ctx_cmd.clone());
+                    tokio::spawn(async move {
+                        if let Err(e) = spoke
+                            .scheduled("react", || spoke.react(&rid, &eid, &key))
+                            .await
+                        {
+                            warn!("react: {e}");
+                            send(&tx, &ctx, AppEvent::Error(format!("Couldn't react: {e}")));
+                        }
+                    });
+                }
+
+                AppCommand::RemoveReaction {
+                    room_id,
+                    reaction_id,
+                } => {
+                    let (Ok(rid), Ok(eid)) =
+                        (RoomId::parse(&room_id), EventId::parse(&reaction_id))
+                    else {
+                        continue;
+                    };
+                    let (spoke, tx, ctx) = (spoke.clone(), tx.clone(), ctx_cmd.clone());
+                    tokio::spawn(async move {
+                        if let Err(e) = spoke
+                            .scheduled("remove reaction", || spoke.unreact(&rid, &eid))
+                            .await
+                        {
+                            warn!("remove reaction: {e}");
+                            send(
+                                &tx,
+                                &ctx,
+                                AppEvent::Error(format!("Couldn't remove reaction: {e}")),
+                            );
+                        }
+                    });
+                }
+
+                AppCommand::StartPoll {
+                    room_id,
+                    question,
+                    answers,
+                    max_selections,
+                    kind,
+                } => {
+                    let Ok(rid) = RoomId::parse(&room_id) else {
+                        continue;
+                    };
+                    if let Err(e) = spoke
+                        .start_poll(&rid, &question, &answers, max_selections, kind)
+                        .await
+                    {
+                        warn!("start poll: {e}");
+                        send(
+                            &tx,
+                            &ctx_cmd,
+                            AppEvent::Error(format!("Couldn't start poll: {e}")),
+                        );
+                    }
+                }
+
+                AppCommand::VotePoll {
+                    room_id,
+                    poll_id,
+                    answers,
+                } => {
+                    let (Ok(rid), Ok(eid)) = (RoomId::parse(&room_id), EventId::parse(&poll_id))
+                    else {
+                        continue;
+                    };
+                    let (spoke, tx, ctx) = (spoke.clone(), tx.clone(), ctx_cmd.clone());
+                    tokio::spawn(async move {
+                        if let Err(e) = spoke
+                            .scheduled("vote", || spoke.vote_poll(&rid, &eid, answers.clone()))
+                            .await
+                        {
+                            warn!("vote: {e}");
+                            send(&tx, &ctx, AppEvent::Error(format!("Couldn't vote: {e}")));
+                        }
+                    });
+                }
+
+                AppCommand::EndPoll { room_id, poll_id } => {
+                    let (Ok(rid), Ok(eid)) = (RoomId::parse(&room_id), EventId::parse(&poll_id))
+                    else {
+                        continue;
+                    };
+                    if let Err(e) = spoke.end_poll(&rid, &eid).await {
+                        warn!("end poll: {e}");
+                        send(
+                            &tx,
+                            &ctx_cmd,
+                            AppEvent::Error(format!("Couldn't end poll: {e}")),
+                        );
+                    }
+                }
+
+                AppCommand::InviteUser { room_id, mxid } => {
+                    let Ok(uid) = UserId::parse(&mxid) else {
+                        let error = CommandError::InvalidId { value: mxid };
+                        send(
+                            &tx,
+                            &ctx_cmd,
+                            AppEvent::CommandFailed {
+                                command: "invite",
+                                error,
+                            },
+                        );
+                        continue;
+                    };
+                    let Some(room) =
+                        command_room(&spoke, &room_id, "invite", true, &tx, &ctx_cmd).await
+                    else {
+                        continue;
+                    };
+                    if let Err(e) = room.invite_user_by_id(&uid).await {
+                        warn!("invite: {e}");
+                        send(&tx, &ctx_cmd, AppEvent::Error(e.to_string()));
+                    }
+                }
+
+                AppCommand::SendReadReceipt {
+                    room_id,
+                    event_id,
+                    private,
+                } => {
+                    let Ok(eid) = EventId::parse(&event_id) else {
+                        continue;
+                    };
+                    // Not worth a refresh: the next read sends another.
+                    let Some(room) =
+                        command_room(&spoke, &room_id, "send read receipt", false, &tx, &ctx_cmd)
+                            .await
+                    else {
+                        continue;
+                    };
+                    let receipt_type = if private {
+                        SendReceiptType::ReadPrivate
+                    } else {
+                        SendReceiptType::Read
+                    };
+                    let spoke = spoke.clone();
+                    tokio::spawn(async move {
+                        let receipt = || async {
+                            room.send_single_receipt(
+                                receipt_type.clone(),
+                                ReceiptThread::Unthreaded,
+                                eid.clone(),
+                            )
+                            .await?;
+                            Ok::<_, MatrixError>(())
+                        };
+                        if let Err(e) = spoke.scheduled("send read receipt", receipt).await {
+                            warn!("read receipt {room_id}: {e}");
+                        }
+                    });
+                }
+
+                AppCommand::SetInvisible(invisible) => {
+                    appear_offline_cmd.store(invisible, Ordering::Relaxed);
+                    if let Err(e) = spoke.set_presence(sync_presence(&appear_offline_cmd)).await {
+                        warn!("set presence: {e}");
+                        send(
+                            &tx,
+                            &ctx_cmd,
+                            AppEvent::Error(format!("Couldn't change your status: {e}")),
+                        );
+                    }
+                }
+
+                AppCommand::SetTyping { room_id, typing } => {
+                    let Some(room) =
+                        command_room(&spoke, &room_id, "send typing notice", false, &tx, &ctx_cmd)
+                            .await
+                    else {
+                        continue;
+                    };
+                    if let Err(e) = room.typing_notice(typing).await {
+                        warn!("typing notice {room_id}: {e}");
+                    }
+                }
+
+                AppCommand::KickUser {
+                    room_id,
+                    user_id,
+                    reason,
+                } => {
+                    moderate(
+                        &spoke,
+                        &room_id,
+                        &user_id,
+                        ModerationAction::Kick,
+                        reason,
+                        &tx,
+                        &ctx_cmd,
+                    )
+                    .await;
+                }
+                AppCommand::BanUser {
+                    room_id,
+                    user_id,
+                    reason,
+                } => {
+                    moderate(
+                        &spoke,
+                        &room_id,
+                        &user_id,
+                        ModerationAction::Ban,
+                        reason,
+                        &tx,
+                        &ctx_cmd,
+                    )
+                    .await;
+                }
+                AppCommand::UnbanUser {
+                    room_id,
+                    user_id,
+                    reason,
+                } => {
+                    moderate(
+                        &spoke,
+                        &room_id,
+                        &user_id,
+                        ModerationAction::Unban,
+                        reason,
+                        &tx,
+                        &ctx_cmd,
+                    )
+                    .await;
+                }
+                AppCommand::RedactMessages {
+                    room_id,
+                    event_ids,
+                    reason,
+                } => {
+                    let Ok(rid) = RoomId::parse(&room_id) else {
+                        continue;
+                    };
+                    let ids: Vec<OwnedEventId> = event_ids
+                        .iter()
+                        .filter_map(|id| EventId::parse(id).ok())
+                        .collect();
+                    // Bulk removal is what trips rate limits most.
+                    let (spoke, tx, ctx) = (spoke.clone(), tx.clone(), ctx_cmd.clone());
+                    tokio::spawn(async move {
+                        let redact = || spoke.redact_messages(&rid, &ids, reason.as_deref());
+                        if let Err(e) = spoke.scheduled("remove messages", redact).await {
+                            warn!("remove messages in {room_id}: {e}");
+                            send(&tx, &ctx, AppEvent::Error(format!("remove messages: {e}")));
+                        }
+                    });
+                }
+
+                AppCommand::ReportEvent {
+                    room_id,
+                    event_id,
+                    score,
+                    reason,
+                } => {
+                    let (Ok(rid), Ok(eid)) = (RoomId::parse(&room_id), EventId::parse(&event_id))
+                    else {
+                        continue;
+                    };
+                    match spoke
+                        .report_event(&rid, &eid, score, reason.as_deref())
+                        .await
+                    {
+                        Ok(()) => send(
+                            &tx,
+                            &ctx_cmd,
+                            AppEvent::Reported {
+                                room_id,
+                                event_id: Some(event_id),
+                            },
+                        ),
+                        Err(e) => {
+                            warn!("report {event_id} in {room_id}: {e}");
+                            send(
+                                &tx,
+                                &ctx_cmd,
+                                AppEvent::Error(format!("Couldn't report message: {e}")),
+                            );
+                        }
+                    }
+                }
+                AppCommand::ReportRoom { room_id, reason } => {
+                    let Ok(rid) = RoomId::parse(&room_id) else {
+                        continue;
+                    };
+                    match spoke.report_room(&rid, &reason).await {
+                        Ok(()) => send(
+                            &tx,
+                            &ctx_cmd,
+                            AppEvent::Reported {
+                                room_id,
+                                event_id: None,
+                            },
+                        ),
+                        Err(e) => {
+                            warn!("report room {room_id}: {e}");
+                            send(
+                                &tx,
+                                &ctx_cmd,
+                                AppEvent::Error(format!("Couldn't report room: {e}")),
+                            );
+                        }
+                    }
+                }
+                AppCommand::FetchPowerLevels { room_id } => {
+                    send_power_levels(&spoke, &room_id, &tx, &ctx_cmd).await;
+                    send_voice_permissions(&inner, &room_id, &tx, &ctx_cmd).await;
+                }
+                AppCommand::SetPowerLevel { room_id, change } => {
+                    let Ok(rid) = RoomId::parse(&room_id) else {
+                        continue;
+                    };
+                    if let Err(e) = spoke.set_power_levels(&rid, change).await {
+                        warn!("power levels {room_id}: {e}");
+                        send(&tx, &ctx_cmd, AppEvent::Error(format!("Power levels: {e}")));
+                    }
+                    send_power_levels(&spoke, &room_id, &tx, &ctx_cmd).await;
+                }
+                AppCommand::ShareMedia { room_id, url } => {
+                    let Ok(rid) = RoomId::parse(&room_id) else {
+                        continue;
+                    };
+                    if let Err(e) = spoke.share_media(&rid, &url).await {
+                        warn!("share media in {room_id}: {e}");
+                        send(
+                            &tx,
+                            &ctx_cmd,
+                            AppEvent::Error(format!("Couldn't share the link: {e}")),
+                        );
+                    }
+                }
+                AppCommand::SyncMedia {
+                    room_id,
+                    media,
+                    playing,
+                    position_ms,
+                } => {
+                    let Ok(rid) = RoomId::parse(&room_id) else {
+                        continue;
+                    };
+                    if let Err(e) = spoke.sync_media(&rid, &media, playing, position_ms).await {
+                        warn!("sync media in {room_id}: {e}");
+                        send(
+                            &tx,
+                            &ctx_cmd,
+                            AppEvent::Error(format!("Couldn't update the shared player: {e}")),
+                        );
+                    }
+                }
+                AppCommand::StopMedia { room_id, media } => {
+                    let Ok(rid) = RoomId::parse(&room_id) else {
+                        continue;
+                    };
+                    if let Err(e) = spoke.stop_media(&rid, &media).await {
+                        warn!("stop media in {room_id}: {e}");
+                        send(
+                            &tx,
+                            &ctx_cmd,
+                            AppEvent::Error(format!("Couldn't stop the shared player: {e}")),
+                        );
+                    }
+                }
+                AppCommand::FetchServerAcl { room_id } => {
+                    send_server_acl(&spoke, &room_id, &tx, &ctx_cmd).await;
+                }
+                AppCommand::ChangeServerAcl { room_id, change } => {
+                    let Ok(rid) = RoomId::parse(&room_id) else {
+                        continue;
+                    };
+                    if let Err(e) = spoke.change_server_acl(&rid, change).await {
+                        warn!("server ACL {room_id}: {e}");
+                        send(&tx, &ctx_cmd, AppEvent::Error(format!("Server ACL: {e}")));
+                    }
+                    send_server_acl(&spoke, &room_id, &tx, &ctx_cmd).await;
+                }
+                AppCommand::FollowPolicyList {
+                    room_id,
+                    list_id,
+                    follow,
+                } => {
+                    let (Ok(rid), Ok(lid)) = (RoomId::parse(&room_id), RoomId::parse(&list_id))
+                    else {
+                        continue;
+                    };
+                    if let Err(e) = spoke.follow_policy_list(&rid, &lid, follow).await {
+                        warn!("policy lists {room_id}: {e}");
+                        send(&tx, &ctx_cmd, AppEvent::Error(format!("Policy lists: {e}")));
+                    }
+                    send_server_acl(&spoke, &room_id, &tx, &ctx_cmd).await;
+                }
+                AppCommand::AddPolicyRule {
+                    room_id,
+                    list_id,
+                    kind,
+                    entity,
+                    reason,
+                } => {
+                    let Ok(lid) = RoomId::parse(&list_id) else {
+                        continue;
+                    };
+                    if let Err(e) = spoke.add_policy_rule(&lid, kind, &entity, &reason).await {
+                        warn!("policy rule in {list_id}: {e}");
+                        send(
+                            &tx,
+                            &ctx_cmd,
+                            AppEvent::Error(format!("Couldn't add the rule: {e}")),
+                        );
+                    }
+                    send_server_acl(&spoke, &room_id, &tx, &ctx_cmd).await;
+                }
+
+                AppCommand::JoinRoom { room_id } => {
+                    let Ok(rid) = RoomId::parse(&room_id) else {
+                        continue;
+                    };
+                    match inner.join_room_by_id(&rid).await {
+                        Ok(_) => {
+                            send(&tx, &ctx_cmd, AppEvent::Joined { room_id });
+                            send(
+                                &tx,
+                                &ctx_cmd,
+                                AppEvent::RoomsUpdated(collect_rooms_from_client(
+                                    &inner,
+                                    &activity_cmd,
+                                )),
+                            );
+                            send(
+                                &tx,
+                                &ctx_cmd,
+                                AppEvent::InvitesUpdated(collect_invites_from_client(&inner)),
+                            );
+                        }
+                        Err(e) => {
+                            warn!("join: {e}");
+                            send(&tx, &ctx_cmd, AppEvent::Error(e.to_string()));
+                        }
+                    }
+                }
+
+                AppCommand::StartDirectMessage { mxid } => {
+                    let Ok(uid) = UserId::parse(mxid.trim()) else {
+                        send(
+                            &tx,
+                            &ctx_cmd,
+                            AppEvent::Error(format!("invalid user ID {mxid}")),
+                        );
+                        continue;
+                    };
+                    match spoke.start_direct_message(&uid).await {
+                        Ok(room_id) => {
+                            send(
+                                &tx,
+                                &ctx_cmd,
+                                AppEvent::RoomsUpdated(collect_rooms_from_client(
+                                    &inner,
+                                    &activity_cmd,
+                                )),
+                            );
+                            send(
+                                &tx,
+                                &ctx_cmd,
+                                AppEvent::Joined {
+                                    room_id: room_id.to_string(),
+                                },
+                            );
+                        }
+                        Err(e) => {
+                            warn!("direct message {mxid}: {e}");
+                            send(
+                                &tx,
+                                &ctx_cmd,
+                                AppEvent::Error(format!("Direct message: {e}")),
+                            );
+                        }
+                    }
+                }
+
+                AppCommand::FetchNotificationMode { room_id } => {
+                    let Ok(rid) = RoomId::parse(&room_id) else {
+                        continue;
+                    };
+                    let mode = spoke.room_notification_mode(&rid).await;
+                    send(
+                        &tx,
+                        &ctx_cmd,
+                        AppEvent::NotificationModeLoaded { room_id, mode },
+                    );
+                }
+
+                AppCommand::SetNotificationMode { room_id, mode } => {
+                    let Ok(rid) = RoomId::parse(&room_id) else {
+                        continue;
+                    };
+                    if let Err(e) = spoke.set_room_notification_mode(&rid, mode).await {
+                        warn!("notification mode {room_id}: {e}");
+                        send(
+                            &tx,
+                            &ctx_cmd,
+                            AppEvent::Error(format!("Notifications: {e}")),
+                        );
+                    }
+                    let mode = spoke.room_notification_mode(&rid).await;
+                    send(
+                        &tx,
+                        &ctx_cmd,
+                        AppEvent::NotificationModeLoaded { room_id, mode },
+                    );
+                }
+
+                AppCommand::FetchMembers { room_id } => {
+                    let Ok(rid) = RoomId::parse(&room_id) else {
+                        continue;
+                    };
+                    // The first /members call for a big room can take a while,
+                    // so show whoever lazy loading has already told us about.
+                    let spoke = spoke.clone();
+                    let tx = tx.clone();
+                    let ctx = ctx_cmd.clone();
+                    tokio::spawn(async move {
+                        let stored = match spoke.stored_members(&rid).await {
+                            Ok((members, true)) => {
+                                send(
+                                    &tx,
+                                    &ctx,
+                                    AppEvent::MembersLoaded {
+                                        room_id,
+                                        members,
+                                        more: false,
+                                    },
+                                );
+                                return;
+                            }
+                            Ok((members, false)) => {
+                                let partial = AppEvent::MembersLoaded {
+                                    room_id: room_id.clone(),
+                                    members: members.clone(),
+                                    more: true,
+                                };
+                                send(&tx, &ctx, partial);
+                                members
+                            }
+                            Err(e) => {
+                                warn!("stored members {room_id}: {e}");
+                                Vec::new()
+                            }
+                        };
+                        match spoke.members(&rid).await {
+                            Ok(members) => send(
+                                &tx,
+                                &ctx,
+                                AppEvent::MembersLoaded {
+                                    room_id,
+                                    members,
+                                    more: false,
+                                },
+                            ),
+                            Err(e) => {
+                                warn!("members {room_id}: {e}");
+                                send(&tx, &ctx, AppEvent::Error(format!("Members: {e}")));
+                                send(
+                                    &tx,
+                                    &ctx,
+                                    AppEvent::MembersLoaded {
+                                        room_id,
+                                        members: stored,
+                                        more: false,
+                                    },
+                                );
+                            }
+                        }
+                    });
+                }
+
+                AppCommand::ResolveProfiles { room_id, user_ids } => {
+                    let spoke = spoke.clone();
+                    let tx = tx.clone();
+                    let ctx = ctx_cmd.clone();
+                    tokio::spawn(async move {
+                        let rid = RoomId::parse(&room_id).ok();
+                        for user_id in user_ids {
+                            let Ok(uid) = UserId::parse(&user_id) else {
+                                continue;
+                            };
+                            match spoke.user_profile(&uid, rid.as_deref()).await {
+                                Ok(profile) => send(
+                                    &tx,
+                                    &ctx,
+                                    AppEvent::ProfileResolved {
+                                        user_id,
+                                        profile: sender_profile(&spoke, profile).await,
+                                    },
+                                ),
+                                Err(e) => warn!("profile {user_id}: {e}"),
+                            }
+                        }
+                    });
+                }
+
+                AppCommand::FetchUrlPreview { url } => {
+                    let spoke = spoke.clone();
+                    let tx = tx.clone();
+                    let ctx = ctx_cmd.clone();
+                    tokio::spawn(async move {
+                        let preview = match spoke.url_preview(&url).await {
+                            Ok(preview) => preview,
+                            Err(e) => {
+                                warn!("url preview {url}: {e}");
+                                send(
+                                    &tx,
+                                    &ctx,
+                                    AppEvent::UrlPreviewLoaded {
+                                        url,
+                                        preview: Err(e.to_string()),
+                                    },
+                                );
+                                return;
+                            }
+                        };
+                        let preview = match preview {
+                            Some(preview) => {
+                                let image = match &preview.image {
+                                    Some(mxc) => match spoke.preview_image(mxc).await {
+                                        Ok(bytes) => Some(Arc::from(bytes)),
+                                        Err(e) => {
+                                            warn!("url preview image {mxc}: {e}");
+                                            None
+                                        }
+                                    },
+                                    None => None,
+                                };
+                                Some(LinkPreview { preview, image })
+                            }
+                            None => None,
+                        };
+                        send(
+                            &tx,
+                            &ctx,
+                            AppEvent::UrlPreviewLoaded {
+                                url,
+                                preview: Ok(preview),
+                            },
+                        );
+                    });
+                }
+
+                AppCommand::FetchImagePacks { room_id } => {
+                    let Ok(rid) = RoomId::parse(&room_id) else {
+                        continue;
+                    };
+                    match spoke.image_packs(&rid).await {
+                        Ok(packs) => {
+                            send(&tx, &ctx_cmd, AppEvent::ImagePacksLoaded { room_id, packs })
+                        }
+                        Err(e) => {
+                            warn!("image packs {room_id}: {e}");
+                            send(
+                                &tx,
+                                &ctx_cmd,
+                                AppEvent::Error(format!("Couldn't load stickers: {e}")),
+                            );
+                        }
+                    }
+                }
+
+                AppCommand::FetchPins { room_id } => {
+                    send_pins(&spoke, &room_id, &tx, &ctx_cmd).await
+                }
+
+                AppCommand::SetPinned {
+                    room_id,
+                    event_id,
+                    pinned,
+                } => {
+                    let (Ok(rid), Ok(eid)) = (RoomId::parse(&room_id), EventId::parse(&event_id))
+                    else {
+                        continue;
+                    };
+                    // The state event comes back through sync and refreshes the panel.
+                    if let Err(e) = spoke.set_pinned(&rid, &eid, pinned).await {
+                        warn!("pin {event_id}: {e}");
+                        let what = if pinned { "Pin" } else { "Unpin" };
+                        send(
+                            &tx,
+                            &ctx_cmd,
+                            AppEvent::Error(format!("{what} message: {e}")),
+                        );
+                    }
+                }
+
+                AppCommand::FetchRoomState { room_id } => {
+                    send_room_state(&spoke, &room_id, &tx, &ctx_cmd).await
+                }
+
+                AppCommand::SendCustomEvent {
+                    room_id,
+                    event_type,
+                    state_key,
+                    content,
+                } => {
+                    let Ok(rid) = RoomId::parse(&room_id) else {
+                        continue;
+                    };
+                    let result = match &state_key {
+                        Some(key) => {
+                            spoke
+                                .send_custom_state(&rid, &event_type, key, content)
+                                .await
+                        }
+                        None => spoke.send_custom_event(&rid, &event_type, content).await,
+                    };
+                    match result {
+                        Ok(()) if state_key.is_some() => {
+                            send_room_state(&spoke, &room_id, &tx, &ctx_cmd).await
+                        }
+                        Ok(()) => {}
+                        Err(e) => {
+                            warn!("send {event_type} in {room_id}: {e}");
+                            send(
+                                &tx,
+                                &ctx_cmd,
+                                AppEvent::Error(format!("Send {event_type}: {e}")),
+                            );
+                        }
+                    }
+                }
+
+                AppCommand::FetchPackImage { mxc } => {
+                    let spoke = spoke.clone();
+                    let tx = tx.clone();
+                    let ctx = ctx_cmd.clone();
+                    tokio::spawn(async move {
+                        let image = match spoke.pack_image(&mxc).await {
+                            Ok(bytes) => Some(Arc::from(bytes)),
+                            Err(e) => {
+                                warn!("pack image {mxc}: {e}");
+                                None
+                            }
+                        };
+                        send(&tx, &ctx, AppEvent::PackImageLoaded { mxc, image });
+                    });
+                }
+
+                AppCommand::SendSticker { room_id, image } => {
+                    let Ok(rid) = RoomId::parse(&room_id) else {
+                        continue;
+                    };
+                    let (spoke, tx, ctx) = (spoke.clone(), tx.clone(), ctx_cmd.clone());
+                    tokio::spawn(async move {
+                        if let Err(e) = spoke
+                            .scheduled("send sticker", || spoke.send_sticker(&rid, &image))
+                            .await
+                        {
+                            warn!("sticker in {room_id}: {e}");
+                            send(
+                                &tx,
+                                &ctx,
+                                AppEvent::Error(format!("Couldn't send sticker: {e}")),
+                            );
+                        }
+                    });
+                }
+
+                AppCommand::SaveAccountSettings(settings) => {
+                    // The local copy stays; the next change or sign-in tries again.
+                    if let Err(e) = spoke.set_account_settings(settings).await {
+                        warn!("save settings to account: {e}");
+                    }
+                }
+
+                AppCommand::SetDisplayName { name } => {
+                    let name = name.trim();
+                    let result = spoke
+                        .set_display_name((!name.is_empty()).then_some(name))
+                        .await;
+                    match result {
+                        Ok(()) => send_own_profile(&spoke, &tx, &ctx_cmd).await,
+                        Err(e) => {
+                            warn!("set display name: {e}");
+                            send(&tx, &ctx_cmd, AppEvent::Error(format!("Display name: {e}")));
+                        }
+                    }
+                }
+
+                AppCommand::SetAvatar { path } => {
+                    let result = match &path {
+                        Some(path) => match (std::fs::read(path), image_mime(path)) {
+                            (Ok(data), Some(mime)) => {
+                                spoke.set_avatar(mime, data).await.map(|_| ())
+                            }
+                            (Err(e), _) => {
+                                send(
+                                    &tx,
+                                    &ctx_cmd,
+                                    AppEvent::Error(format!("Avatar: {}: {e}", path.display())),
+                                );
+                                continue;
+                            }
+                            (_, None) => {
+                                send(
+                                    &tx,
+                                    &ctx_cmd,
+                                    AppEvent::Error(
+                                        "Avatar: use a PNG, JPEG, GIF or WebP image".into(),
+                                    ),
+                                );
+                                continue;
+                            }
+                        },
+                        None => spoke.remove_avatar().await,
+                    };
+                    match result {
+                        Ok(()) => send_own_profile(&spoke, &tx, &ctx_cmd).await,
+                        Err(e) => {
+                            warn!("set avatar: {e}");
+                            send(&tx, &ctx_cmd, AppEvent::Error(format!("Avatar: {e}")));
+                        }
+                    }
+                }
+
+                AppCommand::Logout { keep_crypto } => {
+                    if let Some(session) = voice.take() {
+                        session.disconnect().await;
+                    }
+                    if let Some(preload) = preload.take() {
+                        preload.abort();
+                    }
+                    session_task.abort();
+                    if let Err(e) = spoke.logout(keep_crypto).await {
+                        warn!("logout: {e}");
+                        send(&tx, &ctx_cmd, AppEvent::Error(format!("Log out: {e}")));
+                    }
+                    send(&tx, &ctx_cmd, AppEvent::LoggedOut);
+                    break;
+                }
+
+                AppCommand::FetchDevices => send_devices(&spoke, &tx, &ctx_cmd).await,
+
+                AppCommand::RenameDevice { name } => {
+                    if let Err(e) = spoke.rename_own_device(name.trim()).await {
+                        warn!("rename device: {e}");
+                        send(
+                            &tx,
+                            &ctx_cmd,
+                            AppEvent::Error(format!("Rename session: {e}")),
+                        );
+                    }
+                    send_devices(&spoke, &tx, &ctx_cmd).await;
+                }
+
+                AppCommand::DeleteDevices {
+                    device_ids,
+                    password,
+                } => {
+                    let ids: Vec<OwnedDeviceId> = device_ids.into_iter().map(Into::into).collect();
+                    if let Err(e) = spoke.delete_devices(&ids, &password).await {
+                        warn!("delete devices: {e}");
+                        send(
+                            &tx,
+                            &ctx_cmd,
+                            AppEvent::Error(format!("Sign out sessions: {e}")),
+                        );
+                    }
+                    send_devices(&spoke, &tx, &ctx_cmd).await;
+                }
+
+                AppCommand::KnockRoom { room, reason } => {
+                    if spoke.capabilities().is_some_and(|c| !c.knocking()) {
+                        send(
+                            &tx,
+                            &ctx_cmd,
+                            AppEvent::Error("Knock: this server doesn't support knocking".into()),
+                        );
+                        continue;
+                    }
+                    match spoke.knock(&room, reason.as_deref()).await {
+                        Ok(room_id) => send(
+                            &tx,
+                            &ctx_cmd,
+                            AppEvent::Knocked {
+                                room_id: room_id.to_string(),
+                            },
+                        ),
+                        Err(e) => {
+                            warn!("knock {room}: {e}");
+                            send(&tx, &ctx_cmd, AppEvent::Error(format!("Knock: {e}")));
+                        }
+                    }
+                }
+
+                AppCommand::AcceptKnock { room_id, user_id } => {
+                    answer_knock(&spoke, &room_id, &user_id, true, None, &tx, &ctx_cmd).await;
+                }
+                AppCommand::RejectKnock {
+                    room_id,
+                    user_id,
+                    reason,
+                } => {
+                    answer_knock(&spoke, &room_id, &user_id, false, reason, &tx, &ctx_cmd).await;
+                }
+
+                AppCommand::CreateRoom {
+                    name,
+                    template,
+                    encrypted,
+                    topic,
+                    alias,
+                    invite,
+                    room_version,
+                } => {
+                    let mut new = NewRoom::new(name, template);
+                    new.encrypted = encrypted;
+                    new.topic = topic;
+                    new.alias = alias;
+                    let mut invalid = None;
+                    for user_id in invite {
+                        match UserId::parse(user_id.trim()) {
+                            Ok(user_id) => new.invite.push(user_id),
+                            Err(_) => invalid = Some(user_id),
+                        }
+                    }
+                    if let Some(user_id) = invalid {
+                        send(
+                            &tx,
+                            &ctx_cmd,
+                            AppEvent::Error(format!("Create room: {user_id} isn't a user ID")),
+                        );
+                        continue;
+                    }
+                    if let Some(version) = room_version {
+                        let Ok(version) = RoomVersionId::try_from(version.as_str()) else {
+                            continue;
+                        };
+                        new.room_version = Some(version);
+                    }
+                    create_room(&spoke, &inner, &activity_cmd, &new, &tx, &ctx_cmd).await;
+                }
+                AppCommand::CreateVoiceChannel { name, space_id } => {
+                    let mut new = NewRoom::new(name, RoomTemplate::VoiceChannel);
+                    new.space = space_id.and_then(|id| RoomId::parse(id).ok());
+                    create_room(&spoke, &inner, &activity_cmd, &new, &tx, &ctx_cmd).await;
+                }
+
+                AppCommand::SpinOff {
+                    room_id,
+                    event_id,
+                    sender,
+                    quote,
+                    name,
+                } => {
+                    let (Ok(origin_room), Ok(origin_event), Ok(origin_sender)) = (
+                        RoomId::parse(&room_id),
+                        EventId::parse(&event_id),
+                        UserId::parse(&sender),
+                    ) else {
+                        continue;
+                    };
+                    let spin_off = SpinOff {
+                        name,
+                        origin_room,
+                        origin_event,
+                        origin_sender,
+                        quote,
+                    };
+                    let progress = |step: SpinOffStep| {
+                        send(
+                            &tx,
+                            &ctx_cmd,
+                            AppEvent::SpinOffProgress { step: step.label() },
+                        );
+                    };
+                    match spoke.spin_off(&spin_off, progress).await {
+                        Ok(done) => {
+                            send(
+                                &tx,
+                                &ctx_cmd,
+                                AppEvent::RoomsUpdated(collect_rooms_from_client(
+                                    &inner,
+                                    &activity_cmd,
+                                )),
+                            );
+                            send(
+                                &tx,
+                                &ctx_cmd,
+                                AppEvent::SpunOff {
+                                    room_id: done.room_id.to_string(),
+                                    not_invited: done
+                                        .not_invited
+                                        .iter()
+                                        .map(ToString::to_string)
+                                        .collect(),
+                                },
+                            );
+                        }
+                        Err(e) => {
+                            warn!("spin off {event_id} in {room_id}: {e}");
+                            send(
+                                &tx,
+                                &ctx_cmd,
+                                AppEvent::Error(format!("Couldn't spin off the discussion: {e}")),
+                            );
+                        }
+                    }
+                }
+
+                AppCommand::PeekRoom { room } => {
+                    let spoke = spoke.clone();
+                    let tx = tx.clone();
+                    let ctx = ctx_cmd.clone();
+                    tokio::spawn(async move {
+                        let peek = spoke.peek_room(&room).await.map_err(|e| {
+                            warn!("peek {room}: {e}");
+                            e.to_string()
+                        });
+                        send(&tx, &ctx, AppEvent::RoomPeeked { room, peek });
+                    });
+                }
+
+                AppCommand::JoinRoomByAlias { alias } => {
+                    let id: OwnedRoomOrAliasId = match alias.try_into() {
+                        Ok(id) => id,
+                        Err(e) => {
+                            warn!("invalid alias: {e}");
+                            continue;
+                        }
+                    };
+                    match inner.join_room_by_id_or_alias(&id, &[]).await {
+                        Ok(room) => {
+                            let room_id = room.room_id().to_string();
+                            send(&tx, &ctx_cmd, AppEvent::Joined { room_id });
+                            send(
+                                &tx,
+                                &ctx_cmd,
+                                AppEvent::RoomsUpdated(collect_rooms_from_client(
+                                    &inner,
+                                    &activity_cmd,
+                                )),
+                            );
+                        }
+                        Err(e) => {
+                            warn!("join: {e}");
+                            send(&tx, &ctx_cmd, AppEvent::Error(e.to_string()));
+                        }
+                    }
+                }
+
+                AppCommand::LeaveRoom { room_id } => {
+                    let Some(room) =
+                        command_room(&spoke, &room_id, "leave room", true, &tx, &ctx_cmd).await
+                    else {
+                        continue;
+                    };
+                    match room.leave().await {
+                        Ok(_) => {
+                            send(
+                                &tx,
+                                &ctx_cmd,
+                                AppEvent::RoomsUpdated(collect_rooms_from_client(
+                                    &inner,
+                                    &activity_cmd,
+                                )),
+                            );
+                            send_left_rooms(&spoke, &tx, &ctx_cmd).await;
+                        }
+                        Err(e) => {
+                            warn!("leave: {e}");
+                            send(&tx, &ctx_cmd, AppEvent::Error(e.to_string()));
+                        }
+                    }
+                }
+
+                AppCommand::ForgetRoom { room_id } => {
+                    let Ok(rid) = RoomId::parse(&room_id) else {
+                        continue;
+                    };
+                    match spoke.forget_room(&rid).await {
+                        Ok(()) => send_left_rooms(&spoke, &tx, &ctx_cmd).await,
+                        Err(e) => {
+                            warn!("forget {room_id}: {e}");
+                            send(&tx, &ctx_cmd, AppEvent::Error(format!("Forget room: {e}")));
+                        }
+                    }
+                }
+
+                // ── Voice commands ─────────────────────────────────────────────
+                AppCommand::JoinVoice {
+                    room_id,
+                    ice,
+                    tuning: t,
+                    devices,
+                } => {
+                    ice_settings = ice;
+                    tuning = t;
+                    audio_devices = devices;
+                    mic_test = None;
+                    if voice_room_id.as_deref() != Some(room_id.as_str()) {
+                        voice_muted = false;
+                        voice_deafened = false;
+                    }
+                    // Tear down any existing session first.
+                    if let Some(old) = voice.take() {
+                        old.disconnect().await;
+                    }
+                    if let Some(old_room) = voice_room_id.take() {
+                        announce_voice_left(&inner, &old_room, &mut heartbeat).await;
+                    }
+
+                    let Some(room) =
+                        command_room(&spoke, &room_id, "join voice", true, &tx, &ctx_cmd).await
+                    else {
+                        continue;
+                    };
+                    if let Some(session) = start_voice(
+                        &inner,
+                        &http,
+                        &sidecar_url,
+                        &mut grants,
+                        &room_id,
+                        &ice_settings,
+                        &audio_devices,
+                        &tx,
+                        &ctx_cmd,
+                    )
+                    .await
+                    {
+                        tuning.apply(&session);
+                        session.set_muted(voice_muted);
+                        session.set_deafened(voice_deafened);
+                        voice = Some(session);
+                        voice_room_id = Some(room_id);
+                        // Set org.spoke.voice.member, and keep it alive.
+                        let session_id = uuid::Uuid::new_v4().to_string();
+                        if let Some(user_id) = inner.user_id() {
+                            match membership::join(&room, user_id, session_id).await {
+                                Ok(member) => {
+                                    heartbeat = Some(tokio::spawn(membership::heartbeat(
+                                        room.clone(),
+                                        user_id.to_owned(),
+                                        member,
+                                    )));
+                                }
+                                Err(e) => warn!("voice membership: {e}"),
+                            }
+                        }
+                        if let Err(e) = rtc::join(&inner, &room, &sidecar_url).await {
+                            warn!("call membership: {e}");
+                        }
+                    }
+                }
+
+                AppCommand::RefreshVoiceGrant { room_id } => {
+                    if voice_room_id.as_deref() != Some(room_id.as_str()) {
+                        continue;
+                    }
+                    let Some(session) = voice.as_ref() else {
+                        continue;
+                    };
+                    if let Some(room) = RoomId::parse(&room_id)
+                        .ok()
+                        .and_then(|rid| inner.get_room(&rid))
+                    {
+                        match stage::priority_rule(&room).await {
+                            Ok(rule) => session.set_priority_rule(rule),
+                            Err(e) => warn!("priority speakers: {e}"),
+                        }
+                    }
+                    let config = room_voice_config(&inner, &room_id).await;
+                    if is_voice_banned(&inner, &config) {
+                        grants.invalidate(&room_id);
+                        send(
+                            &tx,
+                            &ctx_cmd,
+                            AppEvent::Error("You were banned from this voice channel.".into()),
+                        );
+                        let _ = internal_cmd.send(AppCommand::LeaveVoice);
+                        continue;
+                    }
+                    // Permissions changed, so never reuse a cached grant here.
+                    let previous = grants.peek(&room_id);
+                    grants.invalidate(&room_id);
+                    let grant =
+                        match request_voice_grant(&inner, &http, &sidecar_url, &room_id).await {
+                            Ok(g) => g,
+                            Err(e) => {
+                                warn!("voice grant refresh: {e}");
+                                continue;
+                            }
+                        };
+                    grants.insert(&room_id, &grant);
+                    let unchanged = grant.can_publish == session.is_publishing()
+                        && config.bitrate == session.bitrate()
+                        && previous.is_none_or(|p| {
+                            p.can_screen_share == grant.can_screen_share
+                                && p.priority_speaker == grant.priority_speaker
+                        });
+                    if unchanged {
+                        continue;
+                    }
+
+                    // Rights or bitrate changed (promoted/demoted, or new voice
+                    // config) — reconnect with the new grant. LiveKit tokens can't be
+                    // upgraded in place. The new session keeps the user's mute
+                    // and deafen state.
+                    if let Some(old) = voice.take() {
+                        old.disconnect().await;
+                    }
+                    voice = preflight_and_connect(
+                        &inner,
+                        grant,
+                        &room_id,
+                        &ice_settings,
+                        &audio_devices,
+                        &tx,
+                        &ctx_cmd,
+                    )
+                    .await;
+                    if let Some(session) = &voice {
+                        tuning.apply(session);
+                        session.set_muted(voice_muted);
+                        session.set_deafened(voice_deafened);
+                    }
+                    if voice.is_none() {
+                        if let Some(room_id) = voice_room_id.take() {
+                            announce_voice_left(&inner, &room_id, &mut heartbeat).await;
+                        }
+                        send(&tx, &ctx_cmd, AppEvent::VoiceLeft);
+                    }
+                }
+
+                AppCommand::TestIceServers { ice, turn_servers } => {
+                    let tx = tx.clone();
+                    let ctx = ctx_cmd.clone();
+                    tokio::spawn(async move {
+                        let results = ice::test_servers(&ice, &turn_servers).await;
+                        send(&tx, &ctx, AppEvent::IceTestResults(results));
+                    });
+                }
+
+                AppCommand::LeaveVoice => {
+                    if let Some(session) = voice.take() {
+                        session.disconnect().await;
+                    }
+                    // Nobody answered (or it doesn't matter now): stop ringing.
+                    if let Some((room_id, call_id, callees)) = outgoing_ring.take() {
+                        if let Some(room) = RoomId::parse(&room_id)
+                            .ok()
+                            .and_then(|rid| inner.get_room(&rid))
+                        {
+                            let content =
+                                VoiceRingEventContent::new(&room, &call_id, RingAction::Cancel);
+                            let callees: Vec<&UserId> =
+                                callees.iter().map(|u| u.as_ref()).collect();
+                            if let Err(e) = ring::send_ring(&inner, &callees, &content).await {
+                                warn!("cancel ring: {e}");
+                            }
+                        }
+                    }
+                    if let Some(room_id) = voice_room_id.take() {
+                        announce_voice_left(&inner, &room_id, &mut heartbeat).await;
+                    }
+                    send(&tx, &ctx_cmd, AppEvent::VoiceLeft);
+                }
+
+                AppCommand::MuteVoice { muted } => {
+                    voice_muted = muted;
                     if let Some(ref session) = voice {
                         session.set_muted(muted);
                         // Send org.spoke.voice.mute.
@@ -402,92 +3816,2259 @@ async fn matrix_task(
                     }
                 }
 
+                AppCommand::DeafenVoice { deafened } => {
+                    voice_deafened = deafened;
+                    if let Some(ref session) = voice {
+                        session.set_deafened(deafened);
+                    }
+                }
+
+                AppCommand::SetVoiceTuning(t) => {
+                    tuning = t;
+                    if let Some(ref session) = voice {
+                        tuning.apply(session);
+                    }
+                    if let Some(test) = &mic_test {
+                        tuning.apply_to_test(test);
+                    }
+                }
+
+                AppCommand::TestMic { on } => {
+                    // Close any test first, so the device is free to reopen.
+                    drop(mic_test.take());
+                    if on {
+                        mic_test =
+                            start_mic_test(audio_devices.input.as_deref(), &tuning, &tx, &ctx_cmd);
+                    }
+                }
+
+                AppCommand::PlayTestTone => {
+                    let tx = tx.clone();
+                    let ctx = ctx_cmd.clone();
+                    let device = audio_devices.output.clone();
+                    tokio::task::spawn_blocking(move || {
+                        if let Err(e) = audio::play_test_tone(device.as_deref()) {
+                            warn!("test tone: {e}");
+                            send(&tx, &ctx, AppEvent::Error(format!("Test tone: {e}")));
+                        }
+                    });
+                }
+
+                AppCommand::StartRecording => {
+                    if recorder.is_some() {
+                        continue;
+                    }
+                    match Recorder::start(audio_devices.input.as_deref()) {
+                        Ok(r) => {
+                            send(
+                                &tx,
+                                &ctx_cmd,
+                                AppEvent::RecordingStarted {
+                                    meter: r.meter.clone(),
+                                },
+                            );
+                            recorder = Some(r);
+                        }
+                        Err(e) => {
+                            warn!("voice message recording: {e}");
+                            send(
+                                &tx,
+                                &ctx_cmd,
+                                AppEvent::Error(format!("Couldn't record: {e}")),
+                            );
+                        }
+                    }
+                }
+
+                AppCommand::StopRecording { send_to } => {
+                    let Some(r) = recorder.take() else { continue };
+                    send(&tx, &ctx_cmd, AppEvent::RecordingStopped);
+                    // Dropping the recorder discards it.
+                    let Some(room_id) = send_to else { continue };
+                    let Ok(rid) = RoomId::parse(&room_id) else {
+                        continue;
+                    };
+                    let spoke = spoke.clone();
+                    let tx = tx.clone();
+                    let ctx = ctx_cmd.clone();
+                    tokio::spawn(async move {
+                        let recording = match tokio::task::spawn_blocking(move || r.finish()).await
+                        {
+                            Ok(Ok(recording)) => recording,
+                            Ok(Err(e)) => {
+                                warn!("voice message encoding: {e}");
+                                send(&tx, &ctx, AppEvent::Error(format!("Couldn't record: {e}")));
+                                return;
+                            }
+                            Err(e) => {
+                                warn!("voice message encoding task: {e}");
+                                return;
+                            }
+                        };
+                        if let Err(e) = spoke.send_voice_message(&rid, &recording).await {
+                            warn!("voice message in {room_id}: {e}");
+                            send(
+                                &tx,
+                                &ctx,
+                                AppEvent::Error(format!("Couldn't send voice message: {e}")),
+                            );
+                        }
+                    });
+                }
+
+                AppCommand::PlayVoiceMessage { url } => {
+                    if let Some(stop) = playback.take() {
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                    let stop = Arc::new(AtomicBool::new(false));
+                    playback = Some(stop.clone());
+                    let spoke = spoke.clone();
+                    let tx = tx.clone();
+                    let ctx = ctx_cmd.clone();
+                    let device = audio_devices.output.clone();
+                    tokio::spawn(async move {
+                        send(
+                            &tx,
+                            &ctx,
+                            AppEvent::VoicePlayback {
+                                url: url.clone(),
+                                playing: true,
+                            },
+                        );
+                        let result = match spoke.voice_message_audio(&url).await {
+                            Ok(ogg) => tokio::task::spawn_blocking(move || {
+                                recording::decode(&ogg)
+                                    .and_then(|pcm| recording::play(&pcm, &stop, device.as_deref()))
+                                    .map_err(|e| e.to_string())
+                            })
+                            .await
+                            .unwrap_or_else(|e| Err(e.to_string())),
+                            Err(e) => Err(e.to_string()),
+                        };
+                        if let Err(e) = result {
+                            warn!("voice message {url}: {e}");
+                            send(
+                                &tx,
+                                &ctx,
+                                AppEvent::Error(format!("Couldn't play voice message: {e}")),
+                            );
+                        }
+                        send(
+                            &tx,
+                            &ctx,
+                            AppEvent::VoicePlayback {
+                                url,
+                                playing: false,
+                            },
+                        );
+                    });
+                }
+
+                AppCommand::StopVoiceMessage => {
+                    if let Some(stop) = playback.take() {
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                }
+
+                AppCommand::SendVoiceData { message } => {
+                    let Some(session) = &voice else { continue };
+                    match session.send_data(&message).await {
+                        // LiveKit doesn't loop data back to the sender; echo
+                        // reactions locally so our own float up too.
+                        Ok(()) if matches!(message, DataMessage::Reaction { .. }) => {
+                            let sender = inner.user_id().map(|u| u.to_string()).unwrap_or_default();
+                            send(&tx, &ctx_cmd, AppEvent::VoiceData { sender, message });
+                        }
+                        Ok(()) => {}
+                        Err(e) => warn!("voice data: {e}"),
+                    }
+                }
+
+                AppCommand::MeasureVoiceLatency => {
+                    let Some(session) = &voice else { continue };
+                    if let Err(e) = session.measure_latency(None).await {
+                        send(&tx, &ctx_cmd, AppEvent::VoiceLatency(Err(e.to_string())));
+                    }
+                }
+
+                AppCommand::ListAudioDevices => {
+                    let tx = tx.clone();
+                    let ctx = ctx_cmd.clone();
+                    tokio::spawn(async move {
+                        let devices = tokio::task::spawn_blocking(devices::list)
+                            .await
+                            .unwrap_or_default();
+                        send(&tx, &ctx, AppEvent::AudioDevices(devices));
+                    });
+                }
+
+                AppCommand::SetAudioDevices(devices) => {
+                    let previous = std::mem::replace(&mut audio_devices, devices);
+                    if mic_test.is_some() && audio_devices.input != previous.input {
+                        drop(mic_test.take());
+                        mic_test =
+                            start_mic_test(audio_devices.input.as_deref(), &tuning, &tx, &ctx_cmd);
+                    }
+                    let Some(session) = &voice else { continue };
+                    if audio_devices.input != previous.input {
+                        if let Err(e) = session.set_input_device(audio_devices.input.clone()) {
+                            warn!("switching mic: {e}");
+                            send(&tx, &ctx_cmd, AppEvent::Error(format!("Microphone: {e}")));
+                        }
+                    }
+                    if audio_devices.output != previous.output {
+                        if let Err(e) = session.set_output_device(audio_devices.output.clone()) {
+                            warn!("switching speakers: {e}");
+                            send(&tx, &ctx_cmd, AppEvent::Error(format!("Speakers: {e}")));
+                        }
+                    }
+                }
+
+                AppCommand::RetryMic { device } => {
+                    if let Some(session) = &voice {
+                        session.retry_mic(device);
+                    }
+                }
+
+                AppCommand::Ring { room_id } => {
+                    let Some(room) =
+                        command_room(&spoke, &room_id, "ring", false, &tx, &ctx_cmd).await
+                    else {
+                        continue;
+                    };
+                    let own = inner.user_id();
+                    let callees: Vec<OwnedUserId> = room
+                        .direct_targets()
+                        .into_iter()
+                        .filter(|u| Some(u.as_ref()) != own)
+                        .collect();
+                    if callees.is_empty() {
+                        send(
+                            &tx,
+                            &ctx_cmd,
+                            AppEvent::Error("Only direct chats can ring".into()),
+                        );
+                        continue;
+                    }
+                    let call_id = ring::new_call_id();
+                    let content = VoiceRingEventContent::new(&room, &call_id, RingAction::Ring);
+                    let targets: Vec<&UserId> = callees.iter().map(|u| u.as_ref()).collect();
+                    match ring::send_ring(&inner, &targets, &content).await {
+                        Ok(()) => outgoing_ring = Some((room_id, call_id, callees)),
+                        Err(e) => {
+                            warn!("ring: {e}");
+                            send(&tx, &ctx_cmd, AppEvent::Error(format!("Ring: {e}")));
+                        }
+                    }
+                }
+
+                AppCommand::AnswerRing {
+                    room_id,
+                    call_id,
+                    caller,
+                    accept,
+                } => {
+                    let Some(room) =
+                        command_room(&spoke, &room_id, "answer ring", false, &tx, &ctx_cmd).await
+                    else {
+                        continue;
+                    };
+                    let Ok(caller) = UserId::parse(&caller) else {
+                        continue;
+                    };
+                    let action = if accept {
+                        RingAction::Answer
+                    } else {
+                        RingAction::Decline
+                    };
+                    let content = VoiceRingEventContent::new(&room, &call_id, action);
+                    // Our other devices stop ringing too.
+                    let mut users: Vec<&UserId> = vec![&caller];
+                    users.extend(inner.user_id());
+                    if let Err(e) = ring::send_ring(&inner, &users, &content).await {
+                        warn!("answer ring: {e}");
+                    }
+                }
+
+                AppCommand::RaiseHand { raised } => {
+                    let Some(rid_str) = &voice_room_id else {
+                        continue;
+                    };
+                    let Some(room) =
+                        command_room(&spoke, rid_str, "raise hand", true, &tx, &ctx_cmd).await
+                    else {
+                        continue;
+                    };
+                    if let Err(e) = room.send(VoiceHandEventContent { raised }).await {
+                        warn!("raise hand: {e}");
+                        send(&tx, &ctx_cmd, AppEvent::Error(e.to_string()));
+                    }
+                }
+
+                AppCommand::PostCallSummary { room_id, summary } => {
+                    let Some(room) =
+                        command_room(&spoke, &room_id, "post call summary", true, &tx, &ctx_cmd)
+                            .await
+                    else {
+                        continue;
+                    };
+                    if let Err(e) = room.send(summary).await {
+                        warn!("call summary: {e}");
+                        send(&tx, &ctx_cmd, AppEvent::Error(format!("Call summary: {e}")));
+                    }
+                }
+
+                AppCommand::SetStageMode { room_id, enabled } => {
+                    let Some(room) =
+                        command_room(&spoke, &room_id, "set stage mode", true, &tx, &ctx_cmd).await
+                    else {
+                        continue;
+                    };
+                    if let Err(e) = stage::set_stage_mode(&room, enabled).await {
+                        warn!("set stage mode: {e}");
+                        send(&tx, &ctx_cmd, AppEvent::Error(format!("stage: {e}")));
+                    }
+                }
+
+                AppCommand::FetchAfkPolicy { space_id } => {
+                    send_afk_policy(&spoke, &space_id, &tx, &ctx_cmd).await;
+                }
+
+                AppCommand::SetAfkPolicy {
+                    space_id,
+                    afk_room,
+                    timeout_mins,
+                } => {
+                    let Some(space) =
+                        command_room(&spoke, &space_id, "set AFK channel", true, &tx, &ctx_cmd)
+                            .await
+                    else {
+                        continue;
+                    };
+                    let afk_room = afk_room.and_then(|r| RoomId::parse(r).ok());
+                    if let Err(e) = afk::set_afk_config(&space, afk_room, timeout_mins).await {
+                        warn!("set AFK channel: {e}");
+                        send(&tx, &ctx_cmd, AppEvent::Error(format!("AFK channel: {e}")));
+                    }
+                    send_afk_policy(&spoke, &space_id, &tx, &ctx_cmd).await;
+                }
+
+                AppCommand::CheckAfk => {
+                    let (Some(session), Some(room_id)) = (voice.as_ref(), voice_room_id.clone())
+                    else {
+                        continue;
+                    };
+                    // Listeners have no mic to judge by.
+                    let Some(speech) = session.speech_stats() else {
+                        continue;
+                    };
+                    let Ok(rid) = RoomId::parse(&room_id) else {
+                        continue;
+                    };
+                    let policy = match afk::afk_policy(&inner, &rid).await {
+                        Ok(Some(policy)) => policy,
+                        Ok(None) => continue,
+                        Err(e) => {
+                            warn!("AFK policy for {room_id}: {e}");
+                            continue;
+                        }
+                    };
+                    if !policy.should_move(&rid, speech.idle_for()) {
+                        continue;
+                    }
+
+                    // Leave as LeaveVoice would, then join the AFK channel.
+                    if let Some(session) = voice.take() {
+                        session.disconnect().await;
+                    }
+                    voice_room_id = None;
+                    announce_voice_left(&inner, &room_id, &mut heartbeat).await;
+                    send(&tx, &ctx_cmd, AppEvent::VoiceLeft);
+                    let to = policy.afk_room.to_string();
+                    send(
+                        &tx,
+                        &ctx_cmd,
+                        AppEvent::MovedToAfk {
+                            from: room_id,
+                            to: to.clone(),
+                        },
+                    );
+                    let _ = internal_cmd.send(AppCommand::JoinVoice {
+                        room_id: to,
+                        ice: ice_settings.clone(),
+                        tuning,
+                        devices: audio_devices.clone(),
+                    });
+                }
+
+                AppCommand::SetVoicePermissions {
+                    room_id,
+                    permissions,
+                } => {
+                    let Some(room) = command_room(
+                        &spoke,
+                        &room_id,
+                        "set voice permissions",
+                        true,
+                        &tx,
+                        &ctx_cmd,
+                    )
+                    .await
+                    else {
+                        continue;
+                    };
+                    if let Err(e) = stage::set_voice_permissions(&room, permissions).await {
+                        warn!("set voice permissions: {e}");
+                        send(
+                            &tx,
+                            &ctx_cmd,
+                            AppEvent::Error(format!("Voice permissions: {e}")),
+                        );
+                    }
+                    send_voice_permissions(&inner, &room_id, &tx, &ctx_cmd).await;
+                }
+
+                AppCommand::FetchVoiceConfig { room_id } => {
+                    let Some(room) =
+                        command_room(&spoke, &room_id, "fetch voice config", false, &tx, &ctx_cmd)
+                            .await
+                    else {
+                        continue;
+                    };
+                    send_voice_config(&room, &tx, &ctx_cmd).await;
+                }
+
+                AppCommand::SetVoiceConfig {
+                    room_id,
+                    base,
+                    config,
+                } => {
+                    let Some(room) =
+                        command_room(&spoke, &room_id, "set voice config", true, &tx, &ctx_cmd)
+                            .await
+                    else {
+                        continue;
+                    };
+                    if let Err(e) = stage::set_voice_config(&room, &base, &config).await {
+                        warn!("set voice config: {e}");
+                        send(
+                            &tx,
+                            &ctx_cmd,
+                            AppEvent::Error(format!("Voice settings: {e}")),
+                        );
+                    }
+                    send_voice_config(&room, &tx, &ctx_cmd).await;
+                }
+
+                AppCommand::SetStageSpeaker {
+                    room_id,
+                    user_id,
+                    speaker,
+                } => {
+                    let Ok(uid) = UserId::parse(&user_id) else {
+                        let error = CommandError::InvalidId { value: user_id };
+                        send(
+                            &tx,
+                            &ctx_cmd,
+                            AppEvent::CommandFailed {
+                                command: "set stage speaker",
+                                error,
+                            },
+                        );
+                        continue;
+                    };
+                    let Some(room) =
+                        command_room(&spoke, &room_id, "set stage speaker", true, &tx, &ctx_cmd)
+                            .await
+                    else {
+                        continue;
+                    };
+                    if let Err(e) = stage::set_stage_speaker(&room, &uid, speaker).await {
+                        warn!("set stage speaker: {e}");
+                        send(&tx, &ctx_cmd, AppEvent::Error(format!("stage: {e}")));
+                    }
+                }
+
                 AppCommand::FetchHistory { room_id } => {
-                    let Ok(rid) = RoomId::parse(&room_id) else { continue };
-                    let Some(room) = inner.get_room(&rid) else { continue };
-
-                    // Fetch up to 50 events; the default (10) is too few.
-                    let mut options = MessagesOptions::backward();
-                    options.limit = uint!(50);
-
-                    match room.messages(options).await {
-                        Ok(response) => {
-                            let mut msgs: Vec<(String, String)> = Vec::new();
-                            for event in response.chunk {
-                                if let Ok(AnySyncTimelineEvent::MessageLike(
-                                    AnySyncMessageLikeEvent::RoomMessage(ev),
-                                )) = event.raw().deserialize()
-                                {
-                                    if let Some(original) = ev.as_original() {
-                                        if let MessageType::Text(text) =
-                                            &original.content.msgtype
-                                        {
-                                            msgs.push((
-                                                original.sender.to_string(),
-                                                text.body.clone(),
-                                            ));
-                                        }
-                                    }
-                                }
+                    let Ok(rid) = RoomId::parse(&room_id) else {
+                        continue;
+                    };
+                    send_history(&spoke, &rid, &tx, &ctx_cmd).await;
+                }
+
+                AppCommand::FetchMoreHistory { room_id, from } => {
+                    let Ok(rid) = RoomId::parse(&room_id) else {
+                        continue;
+                    };
+                    match fetch_history(&spoke, &rid, Some(from)).await {
+                        Ok((messages, reactions, poll_updates, prev_batch)) => {
+                            send(
+                                &tx,
+                                &ctx_cmd,
+                                AppEvent::MoreHistoryLoaded {
+                                    room_id: room_id.clone(),
+                                    messages: messages
+                                        .into_iter()
+                                        .map(TimelineItem::from)
+                                        .collect(),
+                                    prev_batch,
+                                },
+                            );
+                            if !reactions.is_empty() {
+                                send(
+                                    &tx,
+                                    &ctx_cmd,
+                                    AppEvent::Reactions {
+                                        room_id: room_id.clone(),
+                                        reactions,
+                                    },
+                                );
+                            }
+                            if !poll_updates.is_empty() {
+                                send(
+                                    &tx,
+                                    &ctx_cmd,
+                                    AppEvent::PollUpdates {
+                                        room_id,
+                                        updates: poll_updates,
+                                    },
+                                );
                             }
-                            // messages() returns newest-first; reverse to chronological.
-                            msgs.reverse();
+                        }
+                        Err(e) => {
+                            warn!("fetch more history {room_id}: {e}");
                             send(
                                 &tx,
                                 &ctx_cmd,
-                                AppEvent::HistoryLoaded { room_id, messages: msgs },
+                                AppEvent::MoreHistoryFailed {
+                                    room_id,
+                                    error: e.to_string(),
+                                },
                             );
                         }
-                        Err(e) => warn!("fetch history {room_id}: {e}"),
                     }
                 }
+
+                AppCommand::PreloadHistory { room_ids } => {
+                    // Only the latest selection's neighbours matter.
+                    if let Some(task) = preload.take() {
+                        task.abort();
+                    }
+                    let spoke = spoke.clone();
+                    let tx = tx.clone();
+                    let ctx = ctx_cmd.clone();
+                    preload = Some(tokio::spawn(async move {
+                        // Let the selected room's own fetch go first.
+                        tokio::time::sleep(PRELOAD_DELAY).await;
+                        for room_id in room_ids {
+                            let Ok(rid) = RoomId::parse(&room_id) else {
+                                continue;
+                            };
+                            send_history(&spoke, &rid, &tx, &ctx).await;
+                        }
+                    }));
+                }
+
+                AppCommand::FetchSpaceHierarchy { space_id } => {
+                    let Ok(rid) = RoomId::parse(&space_id) else {
+                        continue;
+                    };
+                    send_space_hierarchy(&spoke, &rid, &tx, &ctx_cmd).await;
+                }
+
+                AppCommand::SearchDirectory {
+                    query,
+                    server,
+                    since,
+                } => {
+                    let append = since.is_some();
+                    match spoke
+                        .public_rooms(Some(&query), server.as_deref(), since.as_deref(), 30)
+                        .await
+                    {
+                        Ok(page) => send(
+                            &tx,
+                            &ctx_cmd,
+                            AppEvent::DirectoryResults {
+                                query,
+                                page,
+                                append,
+                            },
+                        ),
+                        Err(e) => {
+                            warn!("directory: {e}");
+                            send(&tx, &ctx_cmd, AppEvent::Error(format!("directory: {e}")));
+                        }
+                    }
+                }
+
+                AppCommand::FetchDirectoryListing { room_id } => {
+                    let Ok(rid) = RoomId::parse(&room_id) else {
+                        continue;
+                    };
+                    send_directory_listing(&spoke, &rid, &tx, &ctx_cmd).await;
+                }
+
+                AppCommand::FetchRoomEncryption { room_id } => {
+                    let Ok(rid) = RoomId::parse(&room_id) else {
+                        continue;
+                    };
+                    send_room_encryption(&spoke, &rid, &tx, &ctx_cmd).await;
+                }
+
+                AppCommand::EnableEncryption { room_id } => {
+                    let Ok(rid) = RoomId::parse(&room_id) else {
+                        continue;
+                    };
+                    if let Err(e) = spoke.enable_encryption(&rid).await {
+                        warn!("enable encryption {room_id}: {e}");
+                        send(&tx, &ctx_cmd, AppEvent::Error(format!("Encryption: {e}")));
+                    }
+                    send_room_encryption(&spoke, &rid, &tx, &ctx_cmd).await;
+                }
+
+                AppCommand::SetPublished { room_id, published } => {
+                    let Ok(rid) = RoomId::parse(&room_id) else {
+                        continue;
+                    };
+                    if let Err(e) = spoke.set_published(&rid, published).await {
+                        warn!("set published {room_id}: {e}");
+                        send(&tx, &ctx_cmd, AppEvent::Error(format!("Directory: {e}")));
+                    }
+                    send_directory_listing(&spoke, &rid, &tx, &ctx_cmd).await;
+                }
+            }
+        }
+    });
+
+    // Room info changes (name, avatar, member counts, leaving) as the store
+    // applies them, rather than re-collecting every room after each sync.
+    let (info_tx, mut info_rx) = tokio_mpsc::unbounded_channel::<()>();
+    {
+        let spoke = client.clone();
+        let activity = activity.clone();
+        let tx = event_tx.clone();
+        let ctx = ctx.clone();
+        tokio::spawn(async move {
+            while info_rx.recv().await.is_some() {
+                tokio::time::sleep(ROOM_INFO_COALESCE).await;
+                while info_rx.try_recv().is_ok() {}
+                send(
+                    &tx,
+                    &ctx,
+                    AppEvent::RoomsUpdated(collect_rooms(&spoke, &activity)),
+                );
+                // A joined room changing may mean we just left or were removed.
+                send_left_rooms(&spoke, &tx, &ctx).await;
+            }
+        });
+    }
+    let mut watched = RoomInfoWatch::new(info_tx);
+    watched.watch_new(&client.inner);
+
+    // Sync loop — manual so we can poll invite/room state after every cycle.
+    loop {
+        if filter_rx.has_changed().unwrap_or(false) {
+            // The SDK resumes from its stored token when none is given.
+            let options = filter_rx.borrow_and_update().clone();
+            settings = filtered_sync_settings(&client, &options).await;
+        }
+        let sync = sync_with_presence(&settings, &appear_offline);
+        let result = tokio::select! {
+            result = client.inner.sync_once(sync) => result,
+            _ = &mut stopped => break,
+        };
+        match result {
+            Ok(response) => {
+                settings = settings.token(response.next_batch);
+                // Known rooms report their own changes; only new ones need a push.
+                if watched.watch_new(&client.inner) {
+                    send(
+                        &event_tx,
+                        &ctx,
+                        AppEvent::RoomsUpdated(collect_rooms(&client, &activity)),
+                    );
+                }
+                send(
+                    &event_tx,
+                    &ctx,
+                    AppEvent::InvitesUpdated(collect_invites(&client)),
+                );
+            }
+            Err(e) => {
+                warn!("sync error: {e}");
+                // A rate-limited sync waits as long as asked, and holds
+                // back scheduled requests while it does.
+                let delay = MatrixError::Sdk(e).retry_after();
+                if let Some(delay) = delay {
+                    client.hold_requests("sync", delay);
+                }
+                tokio::time::sleep(delay.unwrap_or(std::time::Duration::from_secs(5))).await;
+            }
+        }
+    }
+}
+
+/// The presence each sync request should set.
+fn sync_presence(appear_offline: &AtomicBool) -> PresenceState {
+    if appear_offline.load(Ordering::Relaxed) {
+        PresenceState::Offline
+    } else {
+        PresenceState::Online
+    }
+}
+
+/// Sync settings using the server-side filter for `options`; unfiltered if
+/// the filter can't be uploaded.
+async fn filtered_sync_settings(client: &SpokeClient, options: &SyncFilterOptions) -> SyncSettings {
+    match client.sync_filter(options).await {
+        Ok(filter) => SyncSettings::default().filter(filter),
+        Err(e) => {
+            warn!("sync filter: {e}");
+            SyncSettings::default()
+        }
+    }
+}
+
+// ── Voice ─────────────────────────────────────────────────────────────────────
+
+/// Audio levels applied to every voice session; kept across reconnects.
+#[derive(Debug, Clone, Copy)]
+pub struct VoiceTuning {
+    /// How much others are turned down while a priority speaker talks (dB, ≤ 0).
+    pub duck_db: f32,
+    /// Mic gain before publishing (dB).
+    pub input_gain_db: f32,
+    /// Steer the mic toward a common level before `input_gain_db`.
+    pub auto_gain: bool,
+    /// Steer each remote participant toward a common loudness.
+    pub normalize: bool,
+    /// Cancel speaker echo out of the mic.
+    pub echo_cancellation: bool,
+}
+
+impl Default for VoiceTuning {
+    fn default() -> Self {
+        Self {
+            duck_db: priority::DEFAULT_DUCK_DB,
+            input_gain_db: 0.0,
+            auto_gain: true,
+            normalize: true,
+            echo_cancellation: true,
+        }
+    }
+}
+
+impl VoiceTuning {
+    fn apply(&self, session: &VoiceSession) {
+        session.set_priority_duck_db(self.duck_db);
+        session.set_input_gain_db(self.input_gain_db);
+        session.set_auto_gain(self.auto_gain);
+        session.set_normalize(self.normalize);
+        session.set_echo_cancellation(self.echo_cancellation);
+    }
+
+    fn apply_to_test(&self, test: &MicTest) {
+        test.set_input_gain_db(self.input_gain_db);
+        test.set_auto_gain(self.auto_gain);
+        test.set_echo_cancellation(self.echo_cancellation);
+    }
+}
+
+/// Start a mic test on `device` (or the default), forwarding its level to
+/// the UI. Errors are reported to the UI.
+fn start_mic_test(
+    device: Option<&str>,
+    tuning: &VoiceTuning,
+    tx: &EventSender,
+    ctx: &egui::Context,
+) -> Option<MicTest> {
+    let (level_tx, mut level_rx) = tokio::sync::mpsc::unbounded_channel();
+    let test = match MicTest::start(device, level_tx) {
+        Ok(test) => test,
+        Err(e) => {
+            warn!("mic test: {e}");
+            send(tx, ctx, AppEvent::Error(format!("Microphone test: {e}")));
+            return None;
+        }
+    };
+    tuning.apply_to_test(&test);
+    let tx = tx.clone();
+    let ctx = ctx.clone();
+    // Ends when the test is dropped.
+    tokio::spawn(async move {
+        while let Some(level) = level_rx.recv().await {
+            send(&tx, &ctx, AppEvent::MicTestLevel(level));
+        }
+    });
+    Some(test)
+}
+
+/// A LiveKit grant issued by the sidecar.
+#[derive(Clone)]
+struct VoiceGrant {
+    url: String,
+    token: String,
+    can_publish: bool,
+    can_screen_share: bool,
+    priority_speaker: bool,
+    turn_servers: Vec<TurnServer>,
+}
+
+/// Grants are reused until this close to their expiry.
+const GRANT_EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+/// LiveKit grants per room, so toggling voice or reconnecting doesn't cost a
+/// sidecar round trip each time.
+#[derive(Default)]
+struct GrantCache(HashMap<String, (VoiceGrant, SystemTime)>);
+
+impl GrantCache {
+    /// A cached grant for `room_id` that is still valid for a while.
+    fn get(&self, room_id: &str) -> Option<VoiceGrant> {
+        let (grant, expires) = self.0.get(room_id)?;
+        (SystemTime::now() + GRANT_EXPIRY_MARGIN < *expires).then(|| grant.clone())
+    }
+
+    /// Cache `grant`, unless its expiry can't be read from the token.
+    fn insert(&mut self, room_id: &str, grant: &VoiceGrant) {
+        match token_expiry(&grant.token) {
+            Some(expires) => {
+                self.0.insert(room_id.to_owned(), (grant.clone(), expires));
+            }
+            None => {
+                self.0.remove(room_id);
+            }
+        }
+    }
+
+    /// The cached grant for `room_id`, even if it's about to expire.
+    fn peek(&self, room_id: &str) -> Option<VoiceGrant> {
+        self.0.get(room_id).map(|(grant, _)| grant.clone())
+    }
+
+    fn invalidate(&mut self, room_id: &str) {
+        self.0.remove(room_id);
+    }
+}
+
+/// The `exp` claim of a JWT. Only read to decide on reuse — the token's
+/// signature is LiveKit's business.
+fn token_expiry(token: &str) -> Option<SystemTime> {
+    let payload = token.split('.').nth(1)?;
+    let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(payload)
+        .ok()?;
+    let claims: serde_json::Value = serde_json::from_slice(&bytes).ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(claims["exp"].as_u64()?))
+}
+
+/// Ask the sidecar for a LiveKit token for `room_id`.
+async fn request_voice_grant(
+    client: &Client,
+    http: &reqwest::Client,
+    sidecar_url: &str,
+    room_id: &str,
+) -> Result<VoiceGrant, String> {
+    let access_token = match client.session() {
+        Some(AuthSession::Matrix(s)) => s.tokens.access_token.clone(),
+        _ => return Err("not logged in".into()),
+    };
+
+    // A sidecar serving several homeservers picks its tenant by ours.
+    let server_name = client.user_id().map(|u| u.server_name().to_string());
+    let resp = http
+        .post(format!("{sidecar_url}/_spoke/v1/voice/token"))
+        .bearer_auth(&access_token)
+        .json(&serde_json::json!({"room_id": room_id, "server_name": server_name}))
+        .send()
+        .await
+        .map_err(|e| format!("sidecar: {e}"))?;
+
+    if !resp.status().is_success() {
+        let status = resp.status();
+        // Refusals (a voice ban, a full call, a busy sidecar) explain themselves.
+        let body: serde_json::Value = resp.json().await.unwrap_or_default();
+        let explained = matches!(
+            status,
+            reqwest::StatusCode::FORBIDDEN | reqwest::StatusCode::TOO_MANY_REQUESTS
+        );
+        return Err(match body["error"].as_str() {
+            Some(error) if explained => format!("voice: {error}"),
+            _ => format!("sidecar error: {status}"),
+        });
+    }
+
+    let body: serde_json::Value = resp
+        .json()
+        .await
+        .map_err(|e| format!("sidecar parse: {e}"))?;
+
+    Ok(VoiceGrant {
+        url: body["livekit_url"]
+            .as_str()
+            .unwrap_or("ws://localhost:7880")
+            .to_owned(),
+        token: body["livekit_token"].as_str().unwrap_or("").to_owned(),
+        // Older sidecars don't send this and always grant publish.
+        can_publish: body["can_publish"].as_bool().unwrap_or(true),
+        can_screen_share: body["can_screen_share"].as_bool().unwrap_or(false),
+        priority_speaker: body["priority_speaker"].as_bool().unwrap_or(false),
+        turn_servers: serde_json::from_value(body["turn_servers"].clone()).unwrap_or_default(),
+    })
+}
+
+/// Connect with a cached grant, or request a fresh one. Errors are reported
+/// to the UI; a failed connection drops the grant in case it was revoked.
+async fn start_voice(
+    client: &Client,
+    http: &reqwest::Client,
+    sidecar_url: &str,
+    grants: &mut GrantCache,
+    room_id: &str,
+    ice: &IceSettings,
+    devices: &DeviceChoice,
+    tx: &EventSender,
+    ctx: &egui::Context,
+) -> Option<VoiceSession> {
+    // The sidecar refuses fresh grants too; this catches a cached one.
+    if is_voice_banned(client, &room_voice_config(client, room_id).await) {
+        grants.invalidate(room_id);
+        send(
+            tx,
+            ctx,
+            AppEvent::Error("You're banned from this voice channel.".into()),
+        );
+        return None;
+    }
+    let grant = match grants.get(room_id) {
+        Some(grant) => grant,
+        None => match request_voice_grant(client, http, sidecar_url, room_id).await {
+            Ok(grant) => {
+                grants.insert(room_id, &grant);
+                grant
+            }
+            Err(e) => {
+                warn!("voice grant: {e}");
+                send(tx, ctx, AppEvent::Error(e));
+                return None;
+            }
+        },
+    };
+    let session = preflight_and_connect(client, grant, room_id, ice, devices, tx, ctx).await;
+    if session.is_none() {
+        grants.invalidate(room_id);
+    }
+    session
+}
+
+/// Probe connectivity, then connect if the signal server is reachable.
+/// Falls back to relay-only ICE when UDP is blocked but TURN/TCP works,
+/// unless the user's relay policy says otherwise.
+async fn preflight_and_connect(
+    client: &Client,
+    grant: VoiceGrant,
+    room_id: &str,
+    ice: &IceSettings,
+    devices: &DeviceChoice,
+    tx: &EventSender,
+    ctx: &egui::Context,
+) -> Option<VoiceSession> {
+    let report = preflight::run(
+        &grant.url,
+        &grant.token,
+        &grant.turn_servers,
+        &ice.stun_servers,
+    )
+    .await;
+    for line in report.guidance() {
+        warn!("voice preflight: {line}");
+    }
+    let can_connect = report.can_connect();
+    let force_relay = ice.force_relay(report.force_relay());
+    send(
+        tx,
+        ctx,
+        AppEvent::VoicePreflight {
+            room_id: room_id.to_owned(),
+            report,
+        },
+    );
+    send(tx, ctx, AppEvent::TurnServers(grant.turn_servers.clone()));
+    if !can_connect {
+        send(
+            tx,
+            ctx,
+            AppEvent::Error("voice: server unreachable (see diagnostics)".into()),
+        );
+        return None;
+    }
+
+    let options = ConnectOptions {
+        publish: grant.can_publish,
+        rtc_config: ice.rtc_config(&grant.turn_servers, force_relay),
+        bitrate: room_voice_config(client, room_id).await.bitrate,
+        devices: devices.clone(),
+    };
+    connect_voice(client, grant, options, room_id, tx, ctx).await
+}
+
+/// Connect to LiveKit with `grant` and forward voice events to the UI.
+async fn connect_voice(
+    client: &Client,
+    grant: VoiceGrant,
+    options: ConnectOptions,
+    room_id: &str,
+    tx: &EventSender,
+    ctx: &egui::Context,
+) -> Option<VoiceSession> {
+    let (voice_event_tx, mut voice_event_rx) = tokio_mpsc::unbounded_channel::<VoiceEvent>();
+
+    let session =
+        match VoiceSession::connect(&grant.url, &grant.token, options, voice_event_tx).await {
+            Ok(session) => session,
+            Err(e) => {
+                warn!("voice connect: {e}");
+                send(tx, ctx, AppEvent::Error(format!("voice: {e}")));
+                return None;
+            }
+        };
+
+    let room = RoomId::parse(room_id)
+        .ok()
+        .and_then(|rid| client.get_room(&rid));
+    let stage = match &room {
+        Some(room) => stage::voice_config(room)
+            .await
+            .map(|c| c.stage)
+            .unwrap_or(false),
+        None => false,
+    };
+    let can_moderate = match (&room, client.user_id()) {
+        (Some(room), Some(uid)) => stage::can_moderate_stage(room, uid).await.unwrap_or(false),
+        _ => false,
+    };
+    if let Some(room) = &room {
+        match stage::priority_rule(room).await {
+            Ok(rule) => session.set_priority_rule(rule),
+            Err(e) => warn!("priority speakers: {e}"),
+        }
+    }
+    send(tx, ctx, AppEvent::VoiceInputMeter(session.input_meter()));
+    send(
+        tx,
+        ctx,
+        AppEvent::VoiceJoined {
+            room_id: room_id.to_owned(),
+            participants: session.participants(),
+            stage,
+            can_publish: grant.can_publish,
+            can_screen_share: grant.can_screen_share,
+            priority_speaker: grant.priority_speaker,
+            can_moderate,
+        },
+    );
+
+    // Forward VoiceEvents → AppEvents.
+    let tx2 = tx.clone();
+    let ctx2 = ctx.clone();
+    tokio::spawn(async move {
+        while let Some(ve) = voice_event_rx.recv().await {
+            match ve {
+                VoiceEvent::ParticipantsUpdated(ps) => {
+                    send(&tx2, &ctx2, AppEvent::VoiceParticipantsUpdated(ps));
+                }
+                VoiceEvent::ParticipantJoined { identity } => {
+                    send(&tx2, &ctx2, AppEvent::VoiceParticipantJoined { identity });
+                }
+                VoiceEvent::ParticipantLeft { identity } => {
+                    send(&tx2, &ctx2, AppEvent::VoiceParticipantLeft { identity });
+                }
+                VoiceEvent::PrioritySpeakers(identities) => {
+                    send(&tx2, &ctx2, AppEvent::VoicePrioritySpeakers(identities));
+                }
+                VoiceEvent::Pipelines(count) => {
+                    send(&tx2, &ctx2, AppEvent::VoicePipelines(count));
+                }
+                VoiceEvent::SpeakingChanged {
+                    participant,
+                    speaking,
+                } => {
+                    send(
+                        &tx2,
+                        &ctx2,
+                        AppEvent::VoiceSpeaking {
+                            participant,
+                            speaking,
+                        },
+                    );
+                }
+                VoiceEvent::Levels(levels) => {
+                    send(&tx2, &ctx2, AppEvent::VoiceLevels(levels));
+                }
+                VoiceEvent::Data { sender, message } => {
+                    send(&tx2, &ctx2, AppEvent::VoiceData { sender, message });
+                }
+                VoiceEvent::Latency(result) => {
+                    send(&tx2, &ctx2, AppEvent::VoiceLatency(result));
+                }
+                VoiceEvent::MicLost => {
+                    let devices = tokio::task::spawn_blocking(devices::input_devices)
+                        .await
+                        .unwrap_or_default();
+                    send(&tx2, &ctx2, AppEvent::VoiceMicLost { devices });
+                }
+                VoiceEvent::MicRestored { device } => {
+                    send(&tx2, &ctx2, AppEvent::VoiceMicRestored { device });
+                }
+                VoiceEvent::OutputLost => {
+                    send(&tx2, &ctx2, AppEvent::VoiceOutputLost);
+                }
+                VoiceEvent::OutputRestored { device } => {
+                    send(&tx2, &ctx2, AppEvent::VoiceOutputRestored { device });
+                }
+                VoiceEvent::DeviceSwitched { kind, device } => {
+                    send(&tx2, &ctx2, AppEvent::VoiceDeviceSwitched { kind, device });
+                }
+                VoiceEvent::Error(e) => {
+                    send(&tx2, &ctx2, AppEvent::Error(format!("voice: {e}")));
+                }
             }
         }
     });
 
-    // Sync loop — manual so we can poll invite/room state after every cycle.
-    let mut settings = SyncSettings::default();
-    loop {
-        match client.inner.sync_once(settings.clone()).await {
-            Ok(response) => {
-                settings = settings.token(response.next_batch);
-                send(&event_tx, &ctx, AppEvent::RoomsUpdated(collect_rooms(&client)));
-                send(&event_tx, &ctx, AppEvent::InvitesUpdated(collect_invites(&client)));
+    Some(session)
+}
+
+/// Report new stage state to the UI and ask the command loop to re-check our
+/// grant (it ignores the request unless we're in voice in this room).
+async fn on_stage_changed(
+    room: &Room,
+    tx: &EventSender,
+    ctx: &egui::Context,
+    internal: &tokio_mpsc::UnboundedSender<AppCommand>,
+) {
+    let stage = stage::voice_config(room)
+        .await
+        .map(|c| c.stage)
+        .unwrap_or(false);
+    let speakers = stage::stage_speakers(room).await.unwrap_or_default();
+    send(
+        tx,
+        ctx,
+        AppEvent::StageUpdated {
+            room_id: room.room_id().to_string(),
+            stage,
+            speakers: speakers.iter().map(|u| u.to_string()).collect(),
+        },
+    );
+    let _ = internal.send(AppCommand::RefreshVoiceGrant {
+        room_id: room.room_id().to_string(),
+    });
+}
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn send(tx: &EventSender, ctx: &egui::Context, event: AppEvent) {
+    tx.send(event);
+    ctx.request_repaint();
+}
+
+/// Fetch recent messages for `room_id`, refresh its timeline cache, and send
+/// them to the UI.
+async fn send_history(
+    client: &SpokeClient,
+    room_id: &RoomId,
+    tx: &EventSender,
+    ctx: &egui::Context,
+) {
+    let (messages, reactions, poll_updates, prev_batch) =
+        match fetch_history(client, room_id, None).await {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                warn!("fetch history {room_id}: {e}");
+                return;
+            }
+        };
+    if let Err(e) = client.cache_timeline(room_id, &messages).await {
+        warn!("timeline cache {room_id}: {e}");
+    }
+    send(
+        tx,
+        ctx,
+        AppEvent::HistoryLoaded {
+            room_id: room_id.to_string(),
+            messages: messages.into_iter().map(TimelineItem::from).collect(),
+            prev_batch,
+        },
+    );
+    if !reactions.is_empty() {
+        send(
+            tx,
+            ctx,
+            AppEvent::Reactions {
+                room_id: room_id.to_string(),
+                reactions,
+            },
+        );
+    }
+    if !poll_updates.is_empty() {
+        send(
+            tx,
+            ctx,
+            AppEvent::PollUpdates {
+                room_id: room_id.to_string(),
+                updates: poll_updates,
+            },
+        );
+    }
+    match client.shared_media(room_id).await {
+        Ok(media) => send(
+            tx,
+            ctx,
+            AppEvent::SharedMediaLoaded {
+                room_id: room_id.to_string(),
+                media,
+            },
+        ),
+        Err(e) => warn!("shared media {room_id}: {e}"),
+    }
+}
+
+/// One chunk of text messages, oldest first, ending just before `from` (or
+/// at the live end), with its reactions and poll updates, plus the token for
+/// the chunk before it.
+async fn fetch_history(
+    client: &SpokeClient,
+    room_id: &RoomId,
+    from: Option<String>,
+) -> Result<
+    (
+        Vec<CachedMessage>,
+        Vec<Reaction>,
+        Vec<PollUpdate>,
+        Option<String>,
+    ),
+    matrix_sdk::Error,
+> {
+    let Some(room) = client.inner.get_room(room_id) else {
+        return Ok((Vec::new(), Vec::new(), Vec::new(), None));
+    };
+
+    let mut options = MessagesOptions::backward();
+    options.limit = HISTORY_CHUNK.into();
+    options.from = from;
+
+    let response = room.messages(options).await?;
+    let own_user = client.inner.user_id();
+    let mut messages: Vec<CachedMessage> = Vec::new();
+    let mut reactions: Vec<Reaction> = Vec::new();
+    let mut poll_updates: Vec<PollUpdate> = Vec::new();
+    // (target, sender, body, html), newest first.
+    let mut edits: Vec<(String, String, String, Option<String>)> = Vec::new();
+    for event in response.chunk {
+        let raw = event.raw();
+        match raw.get_field::<String>("type").ok().flatten().as_deref() {
+            Some("org.spoke.voice.summary") => {
+                if let Ok(summary) =
+                    raw.deserialize_as::<OriginalSyncMessageLikeEvent<VoiceSummaryEventContent>>()
+                {
+                    messages.push(summary_message(&summary));
+                }
+                continue;
+            }
+            Some("m.sticker") => {
+                if let Ok(sticker) =
+                    raw.deserialize_as::<OriginalSyncMessageLikeEvent<StickerEventContent>>()
+                {
+                    messages.push(sticker_message(&sticker));
+                }
+                continue;
+            }
+            Some("org.matrix.msc3381.poll.start") => {
+                if let Ok(start) =
+                    raw.deserialize_as::<OriginalSyncMessageLikeEvent<PollStartEventContent>>()
+                {
+                    messages.push(poll_message(&start));
+                }
+                continue;
+            }
+            Some("org.matrix.msc3381.poll.response") => {
+                if let Ok(response) =
+                    raw.deserialize_as::<OriginalSyncMessageLikeEvent<PollResponseEventContent>>()
+                {
+                    poll_updates.push(poll_response(&response));
+                }
+                continue;
+            }
+            Some("org.matrix.msc3381.poll.end") => {
+                if let Ok(end) =
+                    raw.deserialize_as::<OriginalSyncMessageLikeEvent<PollEndEventContent>>()
+                {
+                    poll_updates.push(poll_end(&end));
+                }
+                continue;
             }
+            _ => {}
+        }
+        match raw.deserialize() {
+            Ok(AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomMessage(ev))) => {
+                let Some(original) = ev.as_original() else {
+                    continue;
+                };
+                if let Some(Relation::Replacement(replacement)) = &original.content.relates_to {
+                    if let MessageType::Text(text) = &replacement.new_content.msgtype {
+                        edits.push((
+                            replacement.event_id.to_string(),
+                            original.sender.to_string(),
+                            text.body.clone(),
+                            formatted_html(text),
+                        ));
+                    }
+                    continue;
+                }
+                if let Some(message) = room_message(original, raw, own_user) {
+                    messages.push(message);
+                }
+            }
+            Ok(AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomEncrypted(_))) => {
+                if let Some(utd) = client.track_undecrypted(room_id, raw) {
+                    messages.push(undecrypted_message(&utd));
+                }
+            }
+            Ok(AnySyncTimelineEvent::State(AnySyncStateEvent::RoomMember(
+                SyncStateEvent::Original(ev),
+            ))) => {
+                messages.extend(membership_message(&ev));
+            }
+            Ok(AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::Reaction(ev))) => {
+                if let Some(original) = ev.as_original() {
+                    reactions.push(Reaction {
+                        event_id: original.event_id.to_string(),
+                        target: original.content.relates_to.event_id.to_string(),
+                        key: original.content.relates_to.key.clone(),
+                        sender: original.sender.to_string(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    // The newest edit from the original sender wins.
+    for (target, sender, body, html) in edits.into_iter().rev() {
+        if let Some(m) = messages
+            .iter_mut()
+            .find(|m| m.event_id == target && m.sender == sender)
+        {
+            m.body = body;
+            m.html = html;
+            m.edited = true;
+        }
+    }
+    // messages() returns newest-first; reverse to chronological.
+    messages.reverse();
+    reactions.reverse();
+    poll_updates.reverse();
+    Ok((messages, reactions, poll_updates, response.end))
+}
+
+/// A text or voice message from history, or from a late decryption. `None`
+/// for other message types.
+fn room_message(
+    event: &OriginalSyncRoomMessageEvent,
+    raw: &Raw<AnySyncTimelineEvent>,
+    own_user: Option<&UserId>,
+) -> Option<CachedMessage> {
+    let (body, html, voice) = match &event.content.msgtype {
+        MessageType::Text(text) => (text.body.clone(), formatted_html(text), None),
+        MessageType::Audio(audio) => (
+            audio.body.clone(),
+            None,
+            Some(VoiceMessage::from_event(raw)?),
+        ),
+        _ => return None,
+    };
+    let (reply_to, thread_root) = reply_and_thread(event.content.relates_to.as_ref());
+    Some(CachedMessage {
+        event_id: event.event_id.to_string(),
+        sender: event.sender.to_string(),
+        body,
+        ts: u64::from(event.origin_server_ts.0),
+        mentions_me: own_user.is_some_and(|me| mentions_user(&event.content, me)),
+        html,
+        reply_to,
+        thread_root,
+        edited: false,
+        poll: None,
+        sticker: None,
+        voice,
+        undecrypted: false,
+        membership: false,
+    })
+}
+
+/// Placeholder for a message we can't decrypt yet.
+const UNDECRYPTED_BODY: &str = "🔒 Waiting for the key to decrypt this message";
+
+fn undecrypted_message(utd: &Undecrypted) -> CachedMessage {
+    CachedMessage {
+        event_id: utd.event_id.to_string(),
+        sender: utd.sender.to_string(),
+        body: UNDECRYPTED_BODY.to_owned(),
+        ts: utd.ts,
+        mentions_me: false,
+        html: None,
+        reply_to: None,
+        thread_root: None,
+        edited: false,
+        poll: None,
+        sticker: None,
+        voice: None,
+        undecrypted: true,
+        membership: false,
+    }
+}
+
+/// A membership change as a timeline line; `None` for ones not worth a line
+/// (a repeated leave, an avatar-less profile edit and so on).
+fn membership_message(event: &OriginalSyncRoomMemberEvent) -> Option<CachedMessage> {
+    let target = event.state_key.as_str();
+    let previous = event.unsigned.prev_content.as_ref();
+    let name = event
+        .content
+        .displayname
+        .as_deref()
+        .or_else(|| previous.and_then(|p| p.displayname.as_deref()))
+        .unwrap_or(target);
+    let by = event.sender.as_str();
+    let reason = event
+        .content
+        .reason
+        .as_deref()
+        .map(|r| format!(" ({r})"))
+        .unwrap_or_default();
+    let body = match event.membership_change() {
+        MembershipChange::Joined | MembershipChange::InvitationAccepted => format!("{name} joined"),
+        MembershipChange::Left => format!("{name} left{reason}"),
+        MembershipChange::Invited | MembershipChange::KnockAccepted => {
+            format!("{by} invited {name}")
+        }
+        MembershipChange::InvitationRejected => format!("{name} declined the invite"),
+        MembershipChange::InvitationRevoked => format!("{by} withdrew {name}'s invite"),
+        MembershipChange::Kicked => format!("{by} removed {name}{reason}"),
+        MembershipChange::Banned | MembershipChange::KickedAndBanned => {
+            format!("{by} banned {name}{reason}")
+        }
+        MembershipChange::Unbanned => format!("{by} unbanned {name}"),
+        MembershipChange::Knocked => format!("{name} asked to join{reason}"),
+        MembershipChange::KnockRetracted => format!("{name} withdrew their request to join"),
+        MembershipChange::KnockDenied => format!("{by} turned down {name}'s request to join"),
+        MembershipChange::ProfileChanged {
+            displayname_change: Some(change),
+            ..
+        } => match (change.old, change.new) {
+            (Some(old), Some(new)) => format!("{old} changed their name to {new}"),
+            (None, Some(new)) => format!("{target} set their name to {new}"),
+            (Some(old), None) => format!("{old} removed their name"),
+            (None, None) => return None,
+        },
+        MembershipChange::ProfileChanged {
+            avatar_url_change: Some(_),
+            ..
+        } => format!("{name} changed their avatar"),
+        _ => return None,
+    };
+    Some(CachedMessage {
+        event_id: event.event_id.to_string(),
+        sender: event.sender.to_string(),
+        body,
+        ts: u64::from(event.origin_server_ts.0),
+        mentions_me: false,
+        html: None,
+        reply_to: None,
+        thread_root: None,
+        edited: false,
+        poll: None,
+        sticker: None,
+        voice: None,
+        undecrypted: false,
+        membership: true,
+    })
+}
+
+/// A call summary as a timeline line.
+fn summary_message(
+    event: &OriginalSyncMessageLikeEvent<VoiceSummaryEventContent>,
+) -> CachedMessage {
+    CachedMessage {
+        event_id: event.event_id.to_string(),
+        sender: event.sender.to_string(),
+        body: format!("📞 {}", event.content.describe()),
+        ts: u64::from(event.origin_server_ts.0),
+        mentions_me: false,
+        html: None,
+        reply_to: None,
+        thread_root: None,
+        edited: false,
+        poll: None,
+        sticker: None,
+        voice: None,
+        undecrypted: false,
+        membership: false,
+    }
+}
+
+/// A poll start as a timeline item; the body is its text fallback.
+fn poll_message(event: &OriginalSyncMessageLikeEvent<PollStartEventContent>) -> CachedMessage {
+    CachedMessage {
+        event_id: event.event_id.to_string(),
+        sender: event.sender.to_string(),
+        body: format!("📊 {}", event.content.poll.describe()),
+        ts: u64::from(event.origin_server_ts.0),
+        mentions_me: false,
+        html: None,
+        reply_to: None,
+        thread_root: None,
+        edited: false,
+        poll: Some(event.content.poll.clone()),
+        sticker: None,
+        voice: None,
+        undecrypted: false,
+        membership: false,
+    }
+}
+
+/// A sticker as a timeline item; the body is its description.
+fn sticker_message(event: &OriginalSyncMessageLikeEvent<StickerEventContent>) -> CachedMessage {
+    CachedMessage {
+        event_id: event.event_id.to_string(),
+        sender: event.sender.to_string(),
+        body: event.content.body.clone(),
+        ts: u64::from(event.origin_server_ts.0),
+        mentions_me: false,
+        html: None,
+        reply_to: None,
+        thread_root: None,
+        edited: false,
+        poll: None,
+        sticker: Some(event.content.url.clone()),
+        voice: None,
+        undecrypted: false,
+        membership: false,
+    }
+}
+
+fn poll_response(event: &OriginalSyncMessageLikeEvent<PollResponseEventContent>) -> PollUpdate {
+    PollUpdate::Response {
+        poll_id: event.content.relates_to.event_id.to_string(),
+        sender: event.sender.to_string(),
+        ts: u64::from(event.origin_server_ts.0),
+        answers: event.content.response.answers.clone(),
+    }
+}
+
+fn poll_end(event: &OriginalSyncMessageLikeEvent<PollEndEventContent>) -> PollUpdate {
+    PollUpdate::End {
+        poll_id: event.content.relates_to.event_id.to_string(),
+        sender: event.sender.to_string(),
+        ts: u64::from(event.origin_server_ts.0),
+    }
+}
+
+/// The room a command targets. When the store doesn't know it and `refresh`
+/// is set, ask the server and wait for sync once before giving up. Failures
+/// go out as `CommandFailed` rather than being dropped.
+async fn command_room(
+    spoke: &SpokeClient,
+    room_id: &str,
+    command: &'static str,
+    refresh: bool,
+    tx: &EventSender,
+    ctx: &egui::Context,
+) -> Option<Room> {
+    let error = match RoomId::parse(room_id) {
+        Err(_) => CommandError::InvalidId {
+            value: room_id.to_owned(),
+        },
+        Ok(rid) if !refresh => match spoke.inner.get_room(&rid) {
+            Some(room) => return Some(room),
+            None => CommandError::UnknownRoom {
+                room_id: room_id.to_owned(),
+                detail: "not in the local store".into(),
+            },
+        },
+        Ok(rid) => match spoke.room_or_refresh(&rid).await {
+            Ok(room) => return Some(room),
+            Err(e) => CommandError::UnknownRoom {
+                room_id: room_id.to_owned(),
+                detail: e.to_string(),
+            },
+        },
+    };
+    warn!("{command}: {error}");
+    send(tx, ctx, AppEvent::CommandFailed { command, error });
+    None
+}
+
+/// Run a moderation action, reporting refusals and failures to the UI.
+/// The resulting membership change arrives through sync.
+async fn moderate(
+    client: &SpokeClient,
+    room_id: &str,
+    user_id: &str,
+    action: ModerationAction,
+    reason: Option<String>,
+    tx: &EventSender,
+    ctx: &egui::Context,
+) {
+    let (Ok(rid), Ok(uid)) = (RoomId::parse(room_id), UserId::parse(user_id)) else {
+        send(
+            tx,
+            ctx,
+            AppEvent::Error(format!("{}: invalid user ID {user_id}", action.verb())),
+        );
+        return;
+    };
+    if let Err(e) = client.moderate(&rid, &uid, action, reason.as_deref()).await {
+        warn!("{} {user_id}: {e}", action.verb());
+        send(tx, ctx, AppEvent::Error(format!("{}: {e}", action.verb())));
+    }
+}
+
+/// Stop renewing our voice membership in `room_id` and clear it, along with
+/// our MatrixRTC membership.
+async fn announce_voice_left(
+    client: &Client,
+    room_id: &str,
+    heartbeat: &mut Option<tokio::task::JoinHandle<()>>,
+) {
+    if let Some(task) = heartbeat.take() {
+        task.abort();
+    }
+    let Some(room) = RoomId::parse(room_id)
+        .ok()
+        .and_then(|rid| client.get_room(&rid))
+    else {
+        return;
+    };
+    if let Some(user_id) = client.user_id() {
+        if let Err(e) = membership::leave(&room, user_id).await {
+            warn!("voice membership: {e}");
+        }
+    }
+    if let Err(e) = rtc::leave(client, &room).await {
+        warn!("call membership: {e}");
+    }
+}
+
+async fn send_voice_members(room: &Room, tx: &EventSender, ctx: &egui::Context) {
+    match membership::members(room).await {
+        Ok(members) => send(
+            tx,
+            ctx,
+            AppEvent::VoiceMembers {
+                room_id: room.room_id().to_string(),
+                members: members
+                    .into_iter()
+                    .map(|(user_id, expires_ts)| (user_id.to_string(), expires_ts))
+                    .collect(),
+            },
+        ),
+        Err(e) => warn!("voice members in {}: {e}", room.room_id()),
+    }
+}
+
+async fn send_knocks(
+    client: &SpokeClient,
+    room_id: &RoomId,
+    tx: &EventSender,
+    ctx: &egui::Context,
+) {
+    match client.pending_knocks(room_id).await {
+        Ok(knocks) => send(
+            tx,
+            ctx,
+            AppEvent::KnocksUpdated {
+                room_id: room_id.to_string(),
+                knocks,
+            },
+        ),
+        Err(e) => warn!("knocks {room_id}: {e}"),
+    }
+}
+
+/// Create a room (or space). It goes into the room list straight away
+/// rather than after the next sync.
+async fn create_room(
+    client: &SpokeClient,
+    inner: &Client,
+    activity: &RoomActivity,
+    new: &NewRoom,
+    tx: &EventSender,
+    ctx: &egui::Context,
+) {
+    match client.create_room(new).await {
+        Ok(room_id) => {
+            send(
+                tx,
+                ctx,
+                AppEvent::Joined {
+                    room_id: room_id.to_string(),
+                },
+            );
+            send(
+                tx,
+                ctx,
+                AppEvent::RoomsUpdated(collect_rooms_from_client(inner, activity)),
+            );
+        }
+        Err(e) => {
+            warn!("create room {}: {e}", new.name);
+            send(tx, ctx, AppEvent::Error(format!("Create room: {e}")));
+        }
+    }
+}
+
+/// Accept or reject a knock. The membership change arrives through sync and
+/// refreshes the room's pending knocks.
+async fn answer_knock(
+    client: &SpokeClient,
+    room_id: &str,
+    user_id: &str,
+    accept: bool,
+    reason: Option<String>,
+    tx: &EventSender,
+    ctx: &egui::Context,
+) {
+    let (Ok(rid), Ok(uid)) = (RoomId::parse(room_id), UserId::parse(user_id)) else {
+        return;
+    };
+    if let Err(e) = client
+        .answer_knock(&rid, &uid, accept, reason.as_deref())
+        .await
+    {
+        warn!("answer knock {user_id} in {room_id}: {e}");
+        send(tx, ctx, AppEvent::Error(format!("Knock: {e}")));
+    }
+}
+
+async fn send_power_levels(
+    client: &SpokeClient,
+    room_id: &str,
+    tx: &EventSender,
+    ctx: &egui::Context,
+) {
+    let Ok(rid) = RoomId::parse(room_id) else {
+        return;
+    };
+    match client.power_levels(&rid).await {
+        Ok(levels) => send(
+            tx,
+            ctx,
+            AppEvent::PowerLevelsLoaded {
+                room_id: room_id.to_owned(),
+                levels,
+            },
+        ),
+        Err(e) => {
+            warn!("power levels {room_id}: {e}");
+            send(tx, ctx, AppEvent::Error(format!("Power levels: {e}")));
+        }
+    }
+}
+
+async fn send_server_acl(
+    client: &SpokeClient,
+    room_id: &str,
+    tx: &EventSender,
+    ctx: &egui::Context,
+) {
+    let Ok(rid) = RoomId::parse(room_id) else {
+        return;
+    };
+    let loaded = async {
+        Ok::<_, MatrixError>((
+            client.server_acl(&rid).await?,
+            client.policy_lists(&rid).await?,
+        ))
+    };
+    match loaded.await {
+        Ok((acl, lists)) => send(
+            tx,
+            ctx,
+            AppEvent::ServerAclLoaded {
+                room_id: room_id.to_owned(),
+                acl,
+                lists,
+            },
+        ),
+        Err(e) => {
+            warn!("server ACL {room_id}: {e}");
+            send(tx, ctx, AppEvent::Error(format!("Server ACL: {e}")));
+        }
+    }
+}
+
+async fn send_afk_policy(
+    client: &SpokeClient,
+    space_id: &str,
+    tx: &EventSender,
+    ctx: &egui::Context,
+) {
+    let Some(space) = RoomId::parse(space_id)
+        .ok()
+        .and_then(|sid| client.inner.get_room(&sid))
+    else {
+        return;
+    };
+    let config = match afk::afk_config(&space).await {
+        Ok(config) => config,
+        Err(e) => {
+            warn!("AFK channel of {space_id}: {e}");
+            send(tx, ctx, AppEvent::Error(format!("AFK channel: {e}")));
+            return;
+        }
+    };
+    let can_edit = match client.inner.user_id() {
+        Some(own) => space
+            .can_user_send_state(own, StateEventType::from("org.spoke.voice.afk"))
+            .await
+            .unwrap_or(false),
+        None => false,
+    };
+    send(
+        tx,
+        ctx,
+        AppEvent::AfkPolicyLoaded {
+            space_id: space_id.to_owned(),
+            afk_room: config.room_id.map(|r| r.to_string()),
+            timeout_mins: config.timeout_mins,
+            can_edit,
+        },
+    );
+}
+
+async fn send_pins(client: &SpokeClient, room_id: &str, tx: &EventSender, ctx: &egui::Context) {
+    let Ok(rid) = RoomId::parse(room_id) else {
+        return;
+    };
+    match client.pinned_messages(&rid).await {
+        Ok(pins) => send(
+            tx,
+            ctx,
+            AppEvent::PinsLoaded {
+                room_id: room_id.to_owned(),
+                pins,
+            },
+        ),
+        Err(e) => warn!("pinned messages {room_id}: {e}"),
+    }
+}
+
+async fn send_room_state(
+    client: &SpokeClient,
+    room_id: &str,
+    tx: &EventSender,
+    ctx: &egui::Context,
+) {
+    let Ok(rid) = RoomId::parse(room_id) else {
+        return;
+    };
+    match client.room_state(&rid).await {
+        Ok(state) => send(
+            tx,
+            ctx,
+            AppEvent::RoomStateLoaded {
+                room_id: room_id.to_owned(),
+                state,
+            },
+        ),
+        Err(e) => {
+            warn!("room state {room_id}: {e}");
+            send(tx, ctx, AppEvent::Error(format!("Room state: {e}")));
+        }
+    }
+}
+
+async fn send_voice_permissions(
+    client: &Client,
+    room_id: &str,
+    tx: &EventSender,
+    ctx: &egui::Context,
+) {
+    let Some(room) = RoomId::parse(room_id)
+        .ok()
+        .and_then(|rid| client.get_room(&rid))
+    else {
+        return;
+    };
+    match stage::voice_config(&room).await {
+        Ok(config) => send(
+            tx,
+            ctx,
+            AppEvent::VoicePermissionsLoaded {
+                room_id: room_id.to_owned(),
+                permissions: config.permissions,
+            },
+        ),
+        Err(e) => warn!("voice config {room_id}: {e}"),
+    }
+}
+
+async fn send_voice_config(room: &Room, tx: &EventSender, ctx: &egui::Context) {
+    let config = match stage::voice_config(room).await {
+        Ok(config) => config,
+        Err(e) => {
+            warn!("voice config {}: {e}", room.room_id());
+            return;
+        }
+    };
+    let can_edit = match room.client().user_id() {
+        Some(own) => stage::can_edit_voice_config(room, own)
+            .await
+            .unwrap_or(false),
+        None => false,
+    };
+    send(
+        tx,
+        ctx,
+        AppEvent::VoiceConfigLoaded {
+            room_id: room.room_id().to_string(),
+            config,
+            can_edit,
+        },
+    );
+}
+
+/// `room_id`'s voice config, or the default if it can't be read.
+async fn room_voice_config(client: &Client, room_id: &str) -> VoiceConfigEventContent {
+    let Some(room) = RoomId::parse(room_id)
+        .ok()
+        .and_then(|rid| client.get_room(&rid))
+    else {
+        return VoiceConfigEventContent::default();
+    };
+    stage::voice_config(&room).await.unwrap_or_default()
+}
+
+/// Whether `config` bans us from its room's voice channel.
+fn is_voice_banned(client: &Client, config: &VoiceConfigEventContent) -> bool {
+    client
+        .user_id()
+        .is_some_and(|own| config.banned.iter().any(|u| u == own))
+}
+
+/// Avatar thumbnail edge in pixels, as requested from the media repository.
+const AVATAR_SIZE: u32 = 48;
+
+/// `profile` with its avatar thumbnail fetched. A missing thumbnail isn't an
+/// error; the UI falls back to initials.
+async fn sender_profile(client: &SpokeClient, profile: Profile) -> SenderProfile {
+    let avatar = match &profile.avatar_url {
+        Some(mxc) => match client.avatar_thumbnail(mxc, AVATAR_SIZE).await {
+            Ok(bytes) => Some(Arc::from(bytes)),
             Err(e) => {
-                warn!("sync error: {e}");
-                tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                warn!("avatar {mxc}: {e}");
+                None
             }
+        },
+        None => None,
+    };
+    SenderProfile {
+        display_name: profile.display_name,
+        avatar_url: profile.avatar_url,
+        avatar,
+    }
+}
+
+/// Our global profile, after we changed it.
+async fn send_own_profile(client: &SpokeClient, tx: &EventSender, ctx: &egui::Context) {
+    let Some(own) = client.inner.user_id().map(ToOwned::to_owned) else {
+        return;
+    };
+    match client.user_profile(&own, None).await {
+        Ok(profile) => send(
+            tx,
+            ctx,
+            AppEvent::ProfileResolved {
+                user_id: own.to_string(),
+                profile: sender_profile(client, profile).await,
+            },
+        ),
+        Err(e) => warn!("own profile: {e}"),
+    }
+}
+
+async fn send_directory_listing(
+    client: &SpokeClient,
+    room_id: &RoomId,
+    tx: &EventSender,
+    ctx: &egui::Context,
+) {
+    match client.directory_listing(room_id).await {
+        Ok(listing) => send(
+            tx,
+            ctx,
+            AppEvent::DirectoryListingLoaded {
+                room_id: room_id.to_string(),
+                listing,
+            },
+        ),
+        Err(e) => warn!("directory listing {room_id}: {e}"),
+    }
+}
+
+async fn send_room_encryption(
+    client: &SpokeClient,
+    room_id: &RoomId,
+    tx: &EventSender,
+    ctx: &egui::Context,
+) {
+    match client.room_encryption(room_id).await {
+        Ok(encryption) => send(
+            tx,
+            ctx,
+            AppEvent::RoomEncryptionLoaded {
+                room_id: room_id.to_string(),
+                encryption,
+            },
+        ),
+        Err(e) => warn!("room encryption {room_id}: {e}"),
+    }
+}
+
+async fn send_devices(client: &SpokeClient, tx: &EventSender, ctx: &egui::Context) {
+    match client.devices().await {
+        Ok(devices) => send(tx, ctx, AppEvent::DevicesLoaded(devices)),
+        Err(e) => {
+            warn!("devices: {e}");
+            send(tx, ctx, AppEvent::Error(format!("Sessions: {e}")));
         }
     }
 }
 
-// ── Helpers ───────────────────────────────────────────────────────────────────
+/// Content type for an avatar upload, from the file extension.
+fn image_mime(path: &std::path::Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    Some(match ext.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        _ => return None,
+    })
+}
 
-fn send(tx: &mpsc::Sender<AppEvent>, ctx: &egui::Context, event: AppEvent) {
-    let _ = tx.send(event);
-    ctx.request_repaint();
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+fn own_user_id(client: &Client) -> String {
+    client.user_id().map(|u| u.to_string()).unwrap_or_default()
+}
+
+/// Timeline item shown for an outgoing message before the server has it.
+fn local_echo(
+    sender: &str,
+    txn_id: String,
+    text: MessageText,
+    delivery: DeliveryState,
+) -> TimelineItem {
+    let (reply_to, thread_root) = match text.relation {
+        Some(MessageRelation::Reply { event_id }) => (Some(event_id), None),
+        Some(MessageRelation::Thread { root }) => (None, Some(root)),
+        Some(MessageRelation::Edit { .. }) | None => (None, None),
+    };
+    TimelineItem {
+        event_id: None,
+        txn_id: Some(txn_id),
+        sender: sender.to_owned(),
+        rich: text.html.as_deref().map(RichText::from_html),
+        body: text.body,
+        ts: now_millis(),
+        delivery: Some(delivery),
+        mentions_me: false,
+        reply_to,
+        thread_root,
+        edited: false,
+        poll: None,
+        sticker: None,
+        voice: None,
+        undecrypted: false,
+        membership: false,
+    }
+}
+
+/// The event a message replies to and the thread it's in, from its
+/// `m.relates_to`. A thread's fallback reply isn't a real reply.
+fn reply_and_thread<C>(relation: Option<&Relation<C>>) -> (Option<String>, Option<String>) {
+    match relation {
+        Some(Relation::Reply { in_reply_to }) => (Some(in_reply_to.event_id.to_string()), None),
+        Some(Relation::Thread(thread)) => (
+            thread
+                .in_reply_to
+                .as_ref()
+                .filter(|_| !thread.is_falling_back)
+                .map(|r| r.event_id.to_string()),
+            Some(thread.event_id.to_string()),
+        ),
+        _ => (None, None),
+    }
+}
+
+/// A text message's HTML formatted body, if it has one.
+fn formatted_html(text: &TextMessageEventContent) -> Option<String> {
+    text.formatted
+        .as_ref()
+        .filter(|f| f.format == MessageFormat::Html)
+        .map(|f| f.body.clone())
+}
+
+async fn send_space_hierarchy(
+    client: &SpokeClient,
+    space_id: &RoomId,
+    tx: &EventSender,
+    ctx: &egui::Context,
+) {
+    match client.space_hierarchy(space_id).await {
+        Ok(root) => send(
+            tx,
+            ctx,
+            AppEvent::SpaceHierarchyLoaded {
+                space_id: space_id.to_string(),
+                root,
+            },
+        ),
+        Err(e) => warn!("space hierarchy {space_id}: {e}"),
+    }
+}
+
+/// Newest known message and event time per room ID.
+#[derive(Clone, Default)]
+struct RoomActivity(Arc<Mutex<HashMap<String, Activity>>>);
+
+#[derive(Default)]
+struct Activity {
+    preview: Option<RoomPreview>,
+    /// Newest event of any kind, ms since the Unix epoch.
+    ts: u64,
+}
+
+impl RoomActivity {
+    /// Remember `preview` unless we already have something newer.
+    fn record(&self, room_id: &str, preview: RoomPreview) {
+        let mut map = self.0.lock().unwrap();
+        let activity = map.entry(room_id.to_owned()).or_default();
+        activity.ts = activity.ts.max(preview.ts);
+        if activity.preview.as_ref().is_none_or(|p| p.ts <= preview.ts) {
+            activity.preview = Some(preview);
+        }
+    }
+
+    /// Note an event sent at `ts` that doesn't make a preview.
+    fn touch(&self, room_id: &str, ts: u64) {
+        let mut map = self.0.lock().unwrap();
+        let activity = map.entry(room_id.to_owned()).or_default();
+        activity.ts = activity.ts.max(ts);
+    }
+
+    fn get(&self, room_id: &str) -> Option<RoomPreview> {
+        self.0
+            .lock()
+            .unwrap()
+            .get(room_id)
+            .and_then(|a| a.preview.clone())
+    }
+
+    fn last_ts(&self, room_id: &str) -> Option<u64> {
+        self.0
+            .lock()
+            .unwrap()
+            .get(room_id)
+            .map(|a| a.ts)
+            .filter(|&ts| ts > 0)
+    }
+}
+
+/// Joined rooms whose `RoomInfo` we're subscribed to. Each subscription
+/// pings `changed` whenever the store updates that room.
+struct RoomInfoWatch {
+    watched: HashSet<OwnedRoomId>,
+    changed: tokio_mpsc::UnboundedSender<()>,
+}
+
+impl RoomInfoWatch {
+    fn new(changed: tokio_mpsc::UnboundedSender<()>) -> Self {
+        Self {
+            watched: HashSet::new(),
+            changed,
+        }
+    }
+
+    /// Subscribe to joined rooms we aren't watching yet. `true` if any were
+    /// added, since the room list then needs resending.
+    fn watch_new(&mut self, client: &Client) -> bool {
+        let mut added = false;
+        for room in client.joined_rooms() {
+            if !self.watched.insert(room.room_id().to_owned()) {
+                continue;
+            }
+            added = true;
+            let mut info = room.subscribe_info();
+            let changed = self.changed.clone();
+            tokio::spawn(async move {
+                while info.next().await.is_some() {
+                    if changed.send(()).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        added
+    }
 }
 
-fn collect_rooms(client: &SpokeClient) -> Vec<RoomInfo> {
-    collect_rooms_from_client(&client.inner)
+fn collect_rooms(client: &SpokeClient, activity: &RoomActivity) -> Vec<RoomInfo> {
+    collect_rooms_from_client(&client.inner, activity)
 }
 
-fn collect_rooms_from_client(client: &Client) -> Vec<RoomInfo> {
-    client.joined_rooms().into_iter()
+fn collect_rooms_from_client(client: &Client, activity: &RoomActivity) -> Vec<RoomInfo> {
+    client
+        .joined_rooms()
+        .into_iter()
         .map(|r| RoomInfo {
             id: r.room_id().to_string(),
             name: r.name().unwrap_or_else(|| r.room_id().to_string()),
+            alias: r.canonical_alias().map(|a| a.to_string()),
+            is_space: r.is_space(),
+            is_direct: !r.direct_targets().is_empty(),
+            avatar_url: r.avatar_url().map(|u| u.to_string()),
+            num_joined_members: r.joined_members_count(),
+            encrypted: r.encryption_settings().is_some(),
+            topic: r.topic().filter(|t| !t.is_empty()),
+            last_message: activity.get(r.room_id().as_str()),
+            last_activity_ts: activity.last_ts(r.room_id().as_str()),
         })
         .collect()
 }
 
+async fn send_left_rooms(client: &SpokeClient, tx: &EventSender, ctx: &egui::Context) {
+    match client.left_rooms().await {
+        Ok(rooms) => send(tx, ctx, AppEvent::LeftRoomsUpdated(rooms)),
+        Err(e) => warn!("left rooms: {e}"),
+    }
+}
+
 fn collect_invites(client: &SpokeClient) -> Vec<InviteInfo> {
     collect_invites_from_client(&client.inner)
 }
 
 fn collect_invites_from_client(client: &Client) -> Vec<InviteInfo> {
-    client.invited_rooms().into_iter()
+    client
+        .invited_rooms()
+        .into_iter()
         .map(|r| InviteInfo {
             room_id: r.room_id().to_string(),
             room_name: r.name().unwrap_or_else(|| r.room_id().to_string()),
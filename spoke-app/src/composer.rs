@@ -0,0 +1,536 @@
+/// Message composer model — the text being typed plus the user and room
+/// pills inside it.
+///
+/// Pills live in the text as their display label (`@alice`, `#general:hs`);
+/// their byte ranges are tracked alongside and shifted as the text is edited.
+/// Editing inside a pill demotes it to plain text, except deletions, which
+/// remove the whole pill so backspace treats it as one unit.
+use eframe::egui;
+use spoke_core::matrix::{MessageRelation, MessageText, markdown_to_html};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PillKind {
+    User,
+    Room,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Pill {
+    kind: PillKind,
+    /// Full user ID, room alias, or room ID.
+    target: String,
+    /// Byte range of the label in `Composer::text`.
+    start: usize,
+    end: usize,
+}
+
+/// Markdown formatting applied from the toolbar or a shortcut.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Bold,
+    Italic,
+    Code,
+    Strike,
+    Quote,
+}
+
+impl Format {
+    pub const ALL: [Format; 5] = [
+        Format::Bold,
+        Format::Italic,
+        Format::Code,
+        Format::Strike,
+        Format::Quote,
+    ];
+
+    pub fn icon(self) -> &'static str {
+        match self {
+            Format::Bold => "B",
+            Format::Italic => "I",
+            Format::Code => "</>",
+            Format::Strike => "S",
+            Format::Quote => "❝",
+        }
+    }
+
+    pub fn hint(self) -> &'static str {
+        match self {
+            Format::Bold => "Bold (Ctrl+B)",
+            Format::Italic => "Italic (Ctrl+I)",
+            Format::Code => "Code (Ctrl+E)",
+            Format::Strike => "Strikethrough",
+            Format::Quote => "Quote",
+        }
+    }
+
+    /// Ctrl+key that applies this format, if any.
+    pub fn shortcut(self) -> Option<egui::Key> {
+        match self {
+            Format::Bold => Some(egui::Key::B),
+            Format::Italic => Some(egui::Key::I),
+            Format::Code => Some(egui::Key::E),
+            Format::Strike | Format::Quote => None,
+        }
+    }
+
+    /// Wrapping marker; for quotes, the line prefix.
+    fn marker(self) -> &'static str {
+        match self {
+            Format::Bold => "**",
+            Format::Italic => "_",
+            Format::Code => "`",
+            Format::Strike => "~~",
+            Format::Quote => "> ",
+        }
+    }
+}
+
+/// A completion offered for the `@`/`#` token under the cursor.
+#[derive(Clone, Debug)]
+pub struct Suggestion {
+    pub kind: PillKind,
+    pub target: String,
+    pub label: String,
+}
+
+#[derive(Default)]
+pub struct Composer {
+    /// Bound to the text field.
+    pub text: String,
+    /// `text` as of the last `sync`, for diffing.
+    synced: String,
+    pills: Vec<Pill>,
+    /// Reply, thread, or edit target for the next message.
+    pub relation: Option<MessageRelation>,
+}
+
+impl Composer {
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Reconcile pill ranges with whatever the text field did to `text`.
+    /// Returns a new cursor position (char index) if a whole pill was removed.
+    pub fn sync(&mut self) -> Option<usize> {
+        if self.text == self.synced {
+            return None;
+        }
+        let old = std::mem::take(&mut self.synced);
+        let new = &self.text;
+
+        // The edit replaced old[start..old_end] with new[start..new_end].
+        let start = common_prefix(&old, new);
+        let suffix = common_suffix(&old[start..], &new[start..]);
+        let old_end = old.len() - suffix;
+        let new_end = new.len() - suffix;
+        let delta = new_end as isize - old_end as isize;
+        let pure_delete = new_end == start;
+
+        // New-text range left over from pills clipped by a deletion.
+        let mut cut: Option<(usize, usize)> = None;
+        self.pills.retain_mut(|p| {
+            if p.end <= start {
+                return true;
+            }
+            if p.start >= old_end {
+                p.start = p.start.saturating_add_signed(delta);
+                p.end = p.end.saturating_add_signed(delta);
+                return true;
+            }
+            if pure_delete {
+                let (lo, hi) = (
+                    p.start.min(start),
+                    p.end.saturating_add_signed(delta).max(start),
+                );
+                cut = Some(match cut {
+                    Some((a, b)) => (a.min(lo), b.max(hi)),
+                    None => (lo, hi),
+                });
+            }
+            // Typing inside a pill turns it back into plain text.
+            false
+        });
+
+        let mut cursor = None;
+        if let Some((cut_start, cut_end)) = cut.filter(|(a, b)| a < b && *b <= self.text.len()) {
+            self.text.replace_range(cut_start..cut_end, "");
+            let shift = cut_end - cut_start;
+            for p in &mut self.pills {
+                if p.start >= cut_end {
+                    p.start -= shift;
+                    p.end -= shift;
+                }
+            }
+            cursor = Some(self.text[..cut_start].chars().count());
+        }
+        self.synced = self.text.clone();
+        cursor
+    }
+
+    /// The `@`/`#` token ending at `cursor` (char index), if any:
+    /// `(kind, byte start, query)`.
+    pub fn trigger(&self, cursor: usize) -> Option<(PillKind, usize, String)> {
+        let end = self
+            .text
+            .char_indices()
+            .nth(cursor)
+            .map_or(self.text.len(), |(i, _)| i);
+        if self.pills.iter().any(|p| p.start < end && end <= p.end) {
+            return None;
+        }
+        let start = self.text[..end]
+            .rfind(char::is_whitespace)
+            .map_or(0, |i| i + 1);
+        let token = &self.text[start..end];
+        let kind = match token.chars().next()? {
+            '@' => PillKind::User,
+            '#' => PillKind::Room,
+            _ => return None,
+        };
+        Some((kind, start, token[1..].to_lowercase()))
+    }
+
+    /// Replace `text[start..cursor]` with a pill and a trailing space.
+    /// Returns the new cursor position (char index).
+    pub fn insert_pill(&mut self, start: usize, cursor: usize, suggestion: &Suggestion) -> usize {
+        let end = self
+            .text
+            .char_indices()
+            .nth(cursor)
+            .map_or(self.text.len(), |(i, _)| i);
+        let label = &suggestion.label;
+        self.text.replace_range(start..end, &format!("{label} "));
+        let delta = (label.len() + 1) as isize - (end - start) as isize;
+        for p in &mut self.pills {
+            if p.start >= end {
+                p.start = p.start.saturating_add_signed(delta);
+                p.end = p.end.saturating_add_signed(delta);
+            }
+        }
+        self.pills.push(Pill {
+            kind: suggestion.kind,
+            target: suggestion.target.clone(),
+            start,
+            end: start + label.len(),
+        });
+        self.pills.sort_by_key(|p| p.start);
+        self.synced = self.text.clone();
+        self.text[..start + label.len() + 1].chars().count()
+    }
+
+    /// Apply `format` to the selection `chars` (an empty range formats at
+    /// the cursor). Inline formats wrap the selection in markers; quotes
+    /// prefix every line it touches. Returns the new selection.
+    pub fn apply_format(
+        &mut self,
+        format: Format,
+        chars: std::ops::Range<usize>,
+    ) -> std::ops::Range<usize> {
+        let byte = |c: usize| {
+            self.text
+                .char_indices()
+                .nth(c)
+                .map_or(self.text.len(), |(i, _)| i)
+        };
+        let (start, end) = (byte(chars.start), byte(chars.end));
+        let marker = format.marker();
+        let width = marker.chars().count();
+        if format == Format::Quote {
+            let mut lines = vec![self.text[..start].rfind('\n').map_or(0, |i| i + 1)];
+            lines.extend(
+                self.text[start..end]
+                    .match_indices('\n')
+                    .map(|(i, _)| start + i + 1),
+            );
+            let count = lines.len();
+            for at in lines.into_iter().rev() {
+                self.insert(at, marker);
+            }
+            return chars.start + width..chars.end + width * count;
+        }
+        self.insert(end, marker);
+        self.insert(start, marker);
+        chars.start + width..chars.end + width
+    }
+
+    /// Insert `s` at byte `at`, keeping pills in place; a pill split by the
+    /// insertion goes back to plain text.
+    fn insert(&mut self, at: usize, s: &str) {
+        self.pills.retain(|p| !(p.start < at && at < p.end));
+        for p in &mut self.pills {
+            if p.start >= at {
+                p.start += s.len();
+                p.end += s.len();
+            }
+        }
+        self.text.insert_str(at, s);
+        self.synced = self.text.clone();
+    }
+
+    /// Turn `@Display Name` typed out in full (rather than completed) into a
+    /// user pill. `people` is `(user ID, display name)`; longer names win
+    /// where one is a prefix of another.
+    pub fn pill_typed_mentions(&mut self, people: &[(String, String)]) {
+        let mut people: Vec<_> = people.iter().filter(|(_, name)| !name.is_empty()).collect();
+        people.sort_by_key(|(_, name)| std::cmp::Reverse(name.len()));
+        for (user_id, name) in people {
+            let label = format!("@{name}");
+            let mut from = 0;
+            while let Some(found) = self.text[from..].find(&label) {
+                let start = from + found;
+                let end = start + label.len();
+                from = end;
+                let bounded = self.text[..start]
+                    .chars()
+                    .next_back()
+                    .is_none_or(char::is_whitespace)
+                    && self.text[end..]
+                        .chars()
+                        .next()
+                        .is_none_or(|c| !c.is_alphanumeric());
+                let free = !self.pills.iter().any(|p| p.start < end && start < p.end);
+                if bounded && free {
+                    self.pills.push(Pill {
+                        kind: PillKind::User,
+                        target: user_id.clone(),
+                        start,
+                        end,
+                    });
+                }
+            }
+        }
+        self.pills.sort_by_key(|p| p.start);
+    }
+
+    /// Serialize to the typed text as the plain body, the Markdown rendered
+    /// as an HTML body with pills as `matrix.to` links, and the mentioned
+    /// user IDs. Clears the composer.
+    pub fn take_message(&mut self) -> MessageText {
+        let text = std::mem::take(&mut self.text);
+        let pills = std::mem::take(&mut self.pills);
+        self.synced.clear();
+
+        let mut source = String::new();
+        let mut mentions: Vec<String> = Vec::new();
+        let mut pos = 0;
+        for p in &pills {
+            source.push_str(&text[pos..p.start]);
+            source.push_str(&format!(
+                "[{}](<https://matrix.to/#/{}>)",
+                escape_markdown(&text[p.start..p.end]),
+                p.target,
+            ));
+            if p.kind == PillKind::User && !mentions.contains(&p.target) {
+                mentions.push(p.target.clone());
+            }
+            pos = p.end;
+        }
+        source.push_str(&text[pos..]);
+        let html = markdown_to_html(&source);
+        MessageText {
+            body: text,
+            html,
+            mentions,
+            relation: self.relation.take(),
+        }
+    }
+
+    /// Byte ranges of the pills, for highlighting in the text field.
+    pub fn pill_ranges(&self) -> impl Iterator<Item = (PillKind, std::ops::Range<usize>)> + '_ {
+        self.pills.iter().map(|p| (p.kind, p.start..p.end))
+    }
+}
+
+/// Lay out composer text with pills drawn as highlighted chips and a light
+/// preview of the Markdown around them.
+pub fn layout(
+    ui: &egui::Ui,
+    composer: &Composer,
+    text: &str,
+    wrap_width: f32,
+) -> egui::text::LayoutJob {
+    let font = egui::TextStyle::Body.resolve(ui.style());
+    let plain = egui::TextFormat::simple(font.clone(), ui.visuals().text_color());
+    let mut job = egui::text::LayoutJob::default();
+    job.wrap.max_width = wrap_width;
+
+    // The field may hand us text that `sync` hasn't seen yet; only trust pill
+    // ranges while they still fit.
+    let mut pos = 0;
+    for (kind, range) in composer.pill_ranges() {
+        if range.end > text.len()
+            || range.start < pos
+            || !text.is_char_boundary(range.start)
+            || !text.is_char_boundary(range.end)
+        {
+            continue;
+        }
+        append_markdown(ui, &mut job, &text[pos..range.start], &plain);
+        let color = match kind {
+            PillKind::User => ui.visuals().hyperlink_color,
+            PillKind::Room => ui.visuals().warn_fg_color,
+        };
+        let pill = egui::TextFormat {
+            color,
+            background: ui.visuals().faint_bg_color,
+            ..egui::TextFormat::simple(font.clone(), color)
+        };
+        job.append(&text[range.clone()], 0.0, pill);
+        pos = range.end;
+    }
+    append_markdown(ui, &mut job, &text[pos..], &plain);
+    job
+}
+
+/// Inline markers the preview recognises, longest first so `**` wins over `*`.
+const PREVIEW_MARKERS: [(&str, Format); 5] = [
+    ("**", Format::Bold),
+    ("~~", Format::Strike),
+    ("`", Format::Code),
+    ("_", Format::Italic),
+    ("*", Format::Italic),
+];
+
+/// Append `text` styled as its Markdown will render: markers dimmed, their
+/// contents bold/italic/struck/monospace, quoted lines italic. Only pairs on
+/// one line count.
+fn append_markdown(
+    ui: &egui::Ui,
+    job: &mut egui::text::LayoutJob,
+    text: &str,
+    plain: &egui::TextFormat,
+) {
+    let weak = egui::TextFormat {
+        color: ui.visuals().weak_text_color(),
+        ..plain.clone()
+    };
+    for line in text.split_inclusive('\n') {
+        if line.starts_with("> ") {
+            job.append("> ", 0.0, weak.clone());
+            let quoted = egui::TextFormat {
+                italics: true,
+                color: ui.visuals().weak_text_color(),
+                ..plain.clone()
+            };
+            job.append(&line[2..], 0.0, quoted);
+            continue;
+        }
+        let mut pos = 0;
+        let mut i = 0;
+        'scan: while i < line.len() {
+            let rest = &line[i..];
+            for (marker, format) in PREVIEW_MARKERS {
+                if !rest.starts_with(marker) {
+                    continue;
+                }
+                // `_` only opens at a word start, so snake_case stays plain.
+                if marker == "_"
+                    && line[..i]
+                        .chars()
+                        .next_back()
+                        .is_some_and(char::is_alphanumeric)
+                {
+                    continue;
+                }
+                let inner = &rest[marker.len()..];
+                let Some(close) = inner
+                    .find(marker)
+                    .filter(|&c| c > 0 && !inner[..c].contains('\n'))
+                else {
+                    continue;
+                };
+                job.append(&line[pos..i], 0.0, plain.clone());
+                let styled = match format {
+                    Format::Bold => egui::TextFormat {
+                        color: ui.visuals().strong_text_color(),
+                        ..plain.clone()
+                    },
+                    Format::Italic => egui::TextFormat {
+                        italics: true,
+                        ..plain.clone()
+                    },
+                    Format::Strike => egui::TextFormat {
+                        strikethrough: egui::Stroke::new(1.0, plain.color),
+                        ..plain.clone()
+                    },
+                    Format::Code | Format::Quote => egui::TextFormat {
+                        font_id: egui::TextStyle::Monospace.resolve(ui.style()),
+                        background: ui.visuals().code_bg_color,
+                        ..plain.clone()
+                    },
+                };
+                job.append(marker, 0.0, weak.clone());
+                job.append(&inner[..close], 0.0, styled);
+                job.append(marker, 0.0, weak.clone());
+                i += marker.len() * 2 + close;
+                pos = i;
+                continue 'scan;
+            }
+            i += rest.chars().next().map_or(1, char::len_utf8);
+        }
+        job.append(&line[pos..], 0.0, plain.clone());
+    }
+}
+
+/// Move the text field's cursor to `index` (chars).
+pub fn set_cursor(ctx: &egui::Context, id: egui::Id, index: usize) {
+    if let Some(mut state) = egui::TextEdit::load_state(ctx, id) {
+        let cursor = egui::text::CCursorRange::one(egui::text::CCursor::new(index));
+        state.cursor.set_char_range(Some(cursor));
+        state.store(ctx, id);
+    }
+}
+
+/// The text field's selection (chars, start ≤ end); empty at the cursor.
+pub fn selection(ctx: &egui::Context, id: egui::Id) -> Option<std::ops::Range<usize>> {
+    let range = egui::TextEdit::load_state(ctx, id)?.cursor.char_range()?;
+    let (a, b) = (range.primary.index, range.secondary.index);
+    Some(a.min(b)..a.max(b))
+}
+
+/// Select `range` (chars) in the text field.
+pub fn set_selection(ctx: &egui::Context, id: egui::Id, range: std::ops::Range<usize>) {
+    if let Some(mut state) = egui::TextEdit::load_state(ctx, id) {
+        let cursor = egui::text::CCursorRange::two(
+            egui::text::CCursor::new(range.start),
+            egui::text::CCursor::new(range.end),
+        );
+        state.cursor.set_char_range(Some(cursor));
+        state.store(ctx, id);
+    }
+}
+
+/// The text field's cursor position (chars), if it has one.
+pub fn cursor(ctx: &egui::Context, id: egui::Id) -> Option<usize> {
+    egui::TextEdit::load_state(ctx, id)
+        .and_then(|s| s.cursor.char_range())
+        .map(|r| r.primary.index)
+}
+
+fn common_prefix(a: &str, b: &str) -> usize {
+    a.char_indices()
+        .zip(b.chars())
+        .find(|((_, ca), cb)| ca != cb)
+        .map_or(a.len().min(b.len()), |((i, _), _)| i)
+}
+
+fn common_suffix(a: &str, b: &str) -> usize {
+    a.chars()
+        .rev()
+        .zip(b.chars().rev())
+        .take_while(|(ca, cb)| ca == cb)
+        .map(|(c, _)| c.len_utf8())
+        .sum()
+}
+
+/// Backslash-escape Markdown punctuation so a pill label stays literal.
+fn escape_markdown(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '\\' | '[' | ']' | '*' | '_' | '`' | '~' | '<' | '>') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
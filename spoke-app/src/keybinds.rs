@@ -0,0 +1,523 @@
+/// Voice keybinds — push-to-talk, mute, and deafen bound to keyboard keys,
+/// mouse side buttons, or gamepad buttons.
+///
+/// Keyboard and mouse input comes from egui while the Spoke window is
+/// focused. With global keybinds on, rdev watches the whole desktop on a
+/// background thread and takes over while it isn't. Gamepads are polled by
+/// gilrs on a background thread and work regardless of focus.
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Arc, Mutex, mpsc},
+    time::Duration,
+};
+
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+// ── Model ─────────────────────────────────────────────────────────────────────
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum VoiceAction {
+    PushToTalk,
+    ToggleMute,
+    ToggleDeafen,
+}
+
+impl VoiceAction {
+    pub const ALL: [VoiceAction; 3] = [
+        VoiceAction::PushToTalk,
+        VoiceAction::ToggleMute,
+        VoiceAction::ToggleDeafen,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            VoiceAction::PushToTalk => "Push to talk",
+            VoiceAction::ToggleMute => "Toggle mute",
+            VoiceAction::ToggleDeafen => "Toggle deafen",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "device", rename_all = "snake_case")]
+pub enum Binding {
+    /// An egui key name (`egui::Key::name`) plus required modifiers.
+    Key {
+        key: String,
+        #[serde(default)]
+        ctrl: bool,
+        #[serde(default)]
+        shift: bool,
+        #[serde(default)]
+        alt: bool,
+    },
+    /// `"Middle"`, `"Extra1"` (back) or `"Extra2"` (forward).
+    Mouse { button: String },
+    /// A gilrs button name, e.g. `"South"` or `"LeftTrigger"`.
+    Gamepad { button: String },
+}
+
+impl Binding {
+    pub fn label(&self) -> String {
+        match self {
+            Binding::Key {
+                key,
+                ctrl,
+                shift,
+                alt,
+            } => {
+                let mut s = String::new();
+                if *ctrl {
+                    s.push_str("Ctrl+");
+                }
+                if *shift {
+                    s.push_str("Shift+");
+                }
+                if *alt {
+                    s.push_str("Alt+");
+                }
+                s.push_str(key);
+                s
+            }
+            Binding::Mouse { button } => format!("Mouse {button}"),
+            Binding::Gamepad { button } => format!("Gamepad {button}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Keybinds {
+    #[serde(default)]
+    pub bindings: BTreeMap<VoiceAction, Binding>,
+}
+
+impl Keybinds {
+    pub fn get(&self, action: VoiceAction) -> Option<&Binding> {
+        self.bindings.get(&action)
+    }
+
+    /// The other action already using `binding`, if any.
+    pub fn conflict(&self, action: VoiceAction, binding: &Binding) -> Option<VoiceAction> {
+        self.bindings
+            .iter()
+            .find(|(a, b)| **a != action && *b == binding)
+            .map(|(a, _)| *a)
+    }
+
+    /// Bind `action`, unbinding any other action that used the same input.
+    pub fn set(&mut self, action: VoiceAction, binding: Binding) {
+        self.bindings.retain(|_, b| *b != binding);
+        self.bindings.insert(action, binding);
+    }
+
+    pub fn clear(&mut self, action: VoiceAction) {
+        self.bindings.remove(&action);
+    }
+}
+
+// ── Runtime input ─────────────────────────────────────────────────────────────
+
+struct GamepadEvent {
+    button: String,
+    pressed: bool,
+}
+
+/// Keys and mouse buttons held anywhere on the desktop, as seen by rdev.
+#[derive(Default)]
+struct GlobalInput {
+    /// Whether to use these (and wake the UI for them) at all.
+    enabled: bool,
+    /// `egui::Key` names.
+    keys: HashSet<String>,
+    /// Names as in `Binding::Mouse`.
+    buttons: HashSet<String>,
+    ctrl: bool,
+    shift: bool,
+    alt: bool,
+    /// Key and button names bound to an action; only these wake the UI.
+    bound: HashSet<String>,
+}
+
+/// Tracks held inputs across frames and turns them into action transitions.
+pub struct KeybindInput {
+    gamepad_rx: mpsc::Receiver<GamepadEvent>,
+    gamepad_held: HashSet<String>,
+    /// Gamepad buttons pressed since the last `poll`.
+    gamepad_pressed: Vec<String>,
+    /// Desktop-wide input; started the first time global keybinds are on.
+    global: Option<Arc<Mutex<GlobalInput>>>,
+    was_down: HashMap<VoiceAction, bool>,
+}
+
+impl KeybindInput {
+    pub fn new(ctx: &egui::Context) -> Self {
+        Self {
+            gamepad_rx: spawn_gamepad_thread(ctx.clone()),
+            gamepad_held: HashSet::new(),
+            gamepad_pressed: Vec::new(),
+            global: None,
+            was_down: HashMap::new(),
+        }
+    }
+
+    /// Turn keyboard and mouse bindings outside the window on or off. The
+    /// listener can't be stopped once started, so off just ignores it.
+    pub fn set_global(&mut self, ctx: &egui::Context, enabled: bool) {
+        if enabled && self.global.is_none() {
+            self.global = Some(spawn_global_thread(ctx.clone()));
+        }
+        if let Some(global) = &self.global {
+            global.lock().unwrap().enabled = enabled;
+        }
+    }
+
+    /// Drain gamepad events. Call once per frame before anything else.
+    pub fn poll(&mut self) {
+        self.gamepad_pressed.clear();
+        while let Ok(ev) = self.gamepad_rx.try_recv() {
+            if ev.pressed {
+                self.gamepad_held.insert(ev.button.clone());
+                self.gamepad_pressed.push(ev.button);
+            } else {
+                self.gamepad_held.remove(&ev.button);
+            }
+        }
+    }
+
+    /// Whether `binding` is currently held.
+    pub fn is_down(&self, ctx: &egui::Context, binding: &Binding) -> bool {
+        // Outside the window egui sees nothing, so ask rdev instead.
+        if !ctx.input(|i| i.focused) {
+            let global = self
+                .global
+                .as_ref()
+                .map(|g| g.lock().unwrap())
+                .filter(|g| g.enabled);
+            if let Some(global) = global {
+                return match binding {
+                    Binding::Key {
+                        key,
+                        ctrl,
+                        shift,
+                        alt,
+                    } => {
+                        global.keys.contains(key)
+                            && global.ctrl == *ctrl
+                            && global.shift == *shift
+                            && global.alt == *alt
+                    }
+                    Binding::Mouse { button } => global.buttons.contains(button),
+                    Binding::Gamepad { button } => self.gamepad_held.contains(button),
+                };
+            }
+        }
+        match binding {
+            Binding::Key {
+                key,
+                ctrl,
+                shift,
+                alt,
+            } => {
+                // Don't trigger on letters typed into the composer.
+                if ctx.wants_keyboard_input() {
+                    return false;
+                }
+                let Some(key) = egui::Key::from_name(key) else {
+                    return false;
+                };
+                ctx.input(|i| {
+                    i.key_down(key)
+                        && i.modifiers.ctrl == *ctrl
+                        && i.modifiers.shift == *shift
+                        && i.modifiers.alt == *alt
+                })
+            }
+            Binding::Mouse { button } => match mouse_button_from_name(button) {
+                Some(b) => ctx.input(|i| i.pointer.button_down(b)),
+                None => false,
+            },
+            Binding::Gamepad { button } => self.gamepad_held.contains(button),
+        }
+    }
+
+    /// Actions whose held state changed since the last call: `(action, down)`.
+    pub fn transitions(
+        &mut self,
+        ctx: &egui::Context,
+        keybinds: &Keybinds,
+    ) -> Vec<(VoiceAction, bool)> {
+        if let Some(global) = &self.global {
+            global.lock().unwrap().bound = keybinds
+                .bindings
+                .values()
+                .filter_map(|b| match b {
+                    Binding::Key { key, .. } => Some(key.clone()),
+                    Binding::Mouse { button } => Some(button.clone()),
+                    Binding::Gamepad { .. } => None,
+                })
+                .collect();
+        }
+        let mut out = Vec::new();
+        for action in VoiceAction::ALL {
+            let down = keybinds.get(action).is_some_and(|b| self.is_down(ctx, b));
+            let was = self.was_down.insert(action, down).unwrap_or(false);
+            if down != was {
+                out.push((action, down));
+            }
+        }
+        out
+    }
+
+    /// The first input pressed this frame, for "press a key to bind" capture.
+    pub fn capture(&self, ctx: &egui::Context) -> Option<Binding> {
+        if let Some(button) = self.gamepad_pressed.first() {
+            return Some(Binding::Gamepad {
+                button: button.clone(),
+            });
+        }
+        ctx.input(|i| {
+            i.events.iter().find_map(|e| match e {
+                egui::Event::Key {
+                    key,
+                    pressed: true,
+                    repeat: false,
+                    modifiers,
+                    ..
+                } if *key != egui::Key::Escape => Some(Binding::Key {
+                    key: key.name().to_owned(),
+                    ctrl: modifiers.ctrl,
+                    shift: modifiers.shift,
+                    alt: modifiers.alt,
+                }),
+                egui::Event::PointerButton {
+                    button,
+                    pressed: true,
+                    ..
+                } => mouse_button_name(*button).map(|name| Binding::Mouse {
+                    button: name.to_owned(),
+                }),
+                _ => None,
+            })
+        })
+    }
+}
+
+/// Only buttons that don't interfere with normal UI clicks are bindable.
+fn mouse_button_name(button: egui::PointerButton) -> Option<&'static str> {
+    match button {
+        egui::PointerButton::Middle => Some("Middle"),
+        egui::PointerButton::Extra1 => Some("Extra1"),
+        egui::PointerButton::Extra2 => Some("Extra2"),
+        _ => None,
+    }
+}
+
+fn mouse_button_from_name(name: &str) -> Option<egui::PointerButton> {
+    match name {
+        "Middle" => Some(egui::PointerButton::Middle),
+        "Extra1" => Some(egui::PointerButton::Extra1),
+        "Extra2" => Some(egui::PointerButton::Extra2),
+        _ => None,
+    }
+}
+
+/// Listen to the whole desktop's keyboard and mouse on a dedicated thread.
+/// rdev needs X11 on Linux (not Wayland) and Accessibility permission on
+/// macOS; without them it fails once and the bindings stay window-only.
+fn spawn_global_thread(ctx: egui::Context) -> Arc<Mutex<GlobalInput>> {
+    let input = Arc::new(Mutex::new(GlobalInput::default()));
+    let state = input.clone();
+    let _ = std::thread::Builder::new()
+        .name("global-keys".into())
+        .spawn(move || {
+            let result = rdev::listen(move |event| {
+                let mut input = state.lock().unwrap();
+                let name = match event.event_type {
+                    rdev::EventType::KeyPress(key) | rdev::EventType::KeyRelease(key) => {
+                        let pressed = matches!(event.event_type, rdev::EventType::KeyPress(_));
+                        match key {
+                            rdev::Key::ControlLeft | rdev::Key::ControlRight => {
+                                input.ctrl = pressed
+                            }
+                            rdev::Key::ShiftLeft | rdev::Key::ShiftRight => input.shift = pressed,
+                            rdev::Key::Alt | rdev::Key::AltGr => input.alt = pressed,
+                            _ => {}
+                        }
+                        let Some(name) = egui_key(key).map(egui::Key::name) else {
+                            // A modifier may complete or break a held binding.
+                            if input.enabled {
+                                ctx.request_repaint();
+                            }
+                            return;
+                        };
+                        if pressed {
+                            input.keys.insert(name.to_owned());
+                        } else {
+                            input.keys.remove(name);
+                        }
+                        name
+                    }
+                    rdev::EventType::ButtonPress(button)
+                    | rdev::EventType::ButtonRelease(button) => {
+                        let pressed = matches!(event.event_type, rdev::EventType::ButtonPress(_));
+                        let Some(name) = global_button_name(button) else {
+                            return;
+                        };
+                        if pressed {
+                            input.buttons.insert(name.to_owned());
+                        } else {
+                            input.buttons.remove(name);
+                        }
+                        name
+                    }
+                    _ => return,
+                };
+                if input.enabled && input.bound.contains(name) {
+                    ctx.request_repaint();
+                }
+            });
+            if let Err(e) = result {
+                warn!("global keybinds unavailable: {e:?}");
+            }
+        });
+    input
+}
+
+/// The egui key for an rdev key, where there is one.
+fn egui_key(key: rdev::Key) -> Option<egui::Key> {
+    use egui::Key as E;
+    use rdev::Key as R;
+    Some(match key {
+        R::KeyA => E::A,
+        R::KeyB => E::B,
+        R::KeyC => E::C,
+        R::KeyD => E::D,
+        R::KeyE => E::E,
+        R::KeyF => E::F,
+        R::KeyG => E::G,
+        R::KeyH => E::H,
+        R::KeyI => E::I,
+        R::KeyJ => E::J,
+        R::KeyK => E::K,
+        R::KeyL => E::L,
+        R::KeyM => E::M,
+        R::KeyN => E::N,
+        R::KeyO => E::O,
+        R::KeyP => E::P,
+        R::KeyQ => E::Q,
+        R::KeyR => E::R,
+        R::KeyS => E::S,
+        R::KeyT => E::T,
+        R::KeyU => E::U,
+        R::KeyV => E::V,
+        R::KeyW => E::W,
+        R::KeyX => E::X,
+        R::KeyY => E::Y,
+        R::KeyZ => E::Z,
+        R::Num0 | R::Kp0 => E::Num0,
+        R::Num1 | R::Kp1 => E::Num1,
+        R::Num2 | R::Kp2 => E::Num2,
+        R::Num3 | R::Kp3 => E::Num3,
+        R::Num4 | R::Kp4 => E::Num4,
+        R::Num5 | R::Kp5 => E::Num5,
+        R::Num6 | R::Kp6 => E::Num6,
+        R::Num7 | R::Kp7 => E::Num7,
+        R::Num8 | R::Kp8 => E::Num8,
+        R::Num9 | R::Kp9 => E::Num9,
+        R::F1 => E::F1,
+        R::F2 => E::F2,
+        R::F3 => E::F3,
+        R::F4 => E::F4,
+        R::F5 => E::F5,
+        R::F6 => E::F6,
+        R::F7 => E::F7,
+        R::F8 => E::F8,
+        R::F9 => E::F9,
+        R::F10 => E::F10,
+        R::F11 => E::F11,
+        R::F12 => E::F12,
+        R::UpArrow => E::ArrowUp,
+        R::DownArrow => E::ArrowDown,
+        R::LeftArrow => E::ArrowLeft,
+        R::RightArrow => E::ArrowRight,
+        R::Space => E::Space,
+        R::Tab => E::Tab,
+        R::Backspace => E::Backspace,
+        R::Return | R::KpReturn => E::Enter,
+        R::Escape => E::Escape,
+        R::Insert => E::Insert,
+        R::Delete => E::Delete,
+        R::Home => E::Home,
+        R::End => E::End,
+        R::PageUp => E::PageUp,
+        R::PageDown => E::PageDown,
+        R::BackQuote => E::Backtick,
+        R::Minus | R::KpMinus => E::Minus,
+        R::Equal => E::Equals,
+        R::KpPlus => E::Plus,
+        R::LeftBracket => E::OpenBracket,
+        R::RightBracket => E::CloseBracket,
+        R::SemiColon => E::Semicolon,
+        R::Quote => E::Quote,
+        R::BackSlash => E::Backslash,
+        R::Comma => E::Comma,
+        R::Dot => E::Period,
+        R::Slash | R::KpDivide => E::Slash,
+        _ => return None,
+    })
+}
+
+/// `Binding::Mouse` name for an rdev button. The side buttons' codes differ
+/// by platform: X11 numbers them 8 and 9, Windows reports XBUTTON 1 and 2.
+fn global_button_name(button: rdev::Button) -> Option<&'static str> {
+    const BACK: u8 = if cfg!(target_os = "linux") { 8 } else { 1 };
+    const FORWARD: u8 = if cfg!(target_os = "linux") { 9 } else { 2 };
+    match button {
+        rdev::Button::Middle => Some("Middle"),
+        rdev::Button::Unknown(BACK) => Some("Extra1"),
+        rdev::Button::Unknown(FORWARD) => Some("Extra2"),
+        _ => None,
+    }
+}
+
+/// Poll gilrs on a dedicated thread; the receiver sees button edges.
+fn spawn_gamepad_thread(ctx: egui::Context) -> mpsc::Receiver<GamepadEvent> {
+    let (tx, rx) = mpsc::channel();
+    let _ = std::thread::Builder::new()
+        .name("gamepad".into())
+        .spawn(move || {
+            let mut gilrs = match gilrs::Gilrs::new() {
+                Ok(g) => g,
+                Err(e) => {
+                    warn!("gamepad support unavailable: {e}");
+                    return;
+                }
+            };
+            loop {
+                let Some(ev) = gilrs.next_event_blocking(Some(Duration::from_millis(250))) else {
+                    continue;
+                };
+                let (button, pressed) = match ev.event {
+                    gilrs::EventType::ButtonPressed(b, _) => (b, true),
+                    gilrs::EventType::ButtonReleased(b, _) => (b, false),
+                    _ => continue,
+                };
+                if button == gilrs::Button::Unknown {
+                    continue;
+                }
+                if tx
+                    .send(GamepadEvent {
+                        button: format!("{button:?}"),
+                        pressed,
+                    })
+                    .is_err()
+                {
+                    return; // app closed
+                }
+                ctx.request_repaint();
+            }
+        });
+    rx
+}
@@ -0,0 +1,59 @@
+/// Tracing setup with a filter that can be changed while the app runs, so a
+/// user can turn up logging to capture a bug without restarting.
+use std::sync::{Arc, Mutex};
+
+use tracing_subscriber::{EnvFilter, Registry, prelude::*, reload};
+
+/// Used when `RUST_LOG` isn't set.
+const DEFAULT_FILTER: &str = "spoke=debug,spoke_core=debug,matrix_sdk=warn";
+
+/// Handle to the installed log filter. Cheap to clone.
+#[derive(Clone)]
+pub struct LogFilter {
+    handle: reload::Handle<EnvFilter, Registry>,
+    /// The filter at startup, restored by `reset`.
+    initial: String,
+    current: Arc<Mutex<String>>,
+}
+
+/// Install the global subscriber. The filter starts from `RUST_LOG`.
+pub fn init() -> LogFilter {
+    let initial = std::env::var("RUST_LOG").unwrap_or_else(|_| DEFAULT_FILTER.into());
+    let filter = EnvFilter::try_new(&initial).unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+    let (filter, handle) = reload::Layer::new(filter);
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+    LogFilter {
+        handle,
+        current: Arc::new(Mutex::new(initial.clone())),
+        initial,
+    }
+}
+
+impl LogFilter {
+    /// The directives in effect, e.g. `spoke=debug,matrix_sdk=warn`.
+    pub fn current(&self) -> String {
+        self.current.lock().unwrap().clone()
+    }
+
+    pub fn initial(&self) -> &str {
+        &self.initial
+    }
+
+    /// Replace the filter with `directives`. Invalid directives leave the
+    /// current filter in place.
+    pub fn set(&self, directives: &str) -> Result<(), String> {
+        let filter = EnvFilter::try_new(directives).map_err(|e| e.to_string())?;
+        self.handle.reload(filter).map_err(|e| e.to_string())?;
+        *self.current.lock().unwrap() = directives.to_owned();
+        tracing::info!("log filter set to {directives}");
+        Ok(())
+    }
+
+    /// Go back to the filter the app started with.
+    pub fn reset(&self) -> Result<(), String> {
+        self.set(&self.initial.clone())
+    }
+}
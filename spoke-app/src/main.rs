@@ -3,19 +3,21 @@
 
 mod app;
 mod bridge;
+mod composer;
+mod keybinds;
+mod logging;
+mod markup;
+mod message_actions;
+mod notifier;
+mod polls;
+mod search;
+mod settings;
+mod ui_state;
 
 use app::SpokeApp;
 
 fn main() -> eframe::Result<()> {
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            std::env::var("RUST_LOG")
-                .unwrap_or_else(|_| "spoke=debug,spoke_core=debug,matrix_sdk=warn".into()),
-        )
-        .init();
-
-    let (event_tx, event_rx) = std::sync::mpsc::channel();
-    let (cmd_tx, cmd_rx) = tokio::sync::mpsc::unbounded_channel();
+    let log_filter = logging::init();
 
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
@@ -28,8 +30,6 @@ fn main() -> eframe::Result<()> {
     eframe::run_native(
         "Spoke",
         options,
-        Box::new(|cc| {
-            Ok(Box::new(SpokeApp::new(cc, event_rx, cmd_tx, Some((event_tx, cmd_rx)))))
-        }),
+        Box::new(|cc| Ok(Box::new(SpokeApp::new(cc, log_filter)))),
     )
 }
@@ -0,0 +1,165 @@
+/// Drawing parsed formatted bodies (`RichText`) in the timeline. Spoilers
+/// draw as solid bars until clicked; custom emotes as text-height images.
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use eframe::egui;
+use spoke_core::matrix::{Block, RichText, Span};
+
+/// Emote thumbnails by `mxc://` URI; `None` while loading or if the fetch
+/// failed.
+pub type Images = HashMap<String, Option<Arc<[u8]>>>;
+
+/// Draw `rich` in the space left on the current row. `highlight` puts a
+/// background behind the text, as for plain messages that mention us.
+/// Emotes not in `images` yet show as their shortcode and are added to
+/// `wanted`.
+pub fn show(
+    ui: &mut egui::Ui,
+    rich: &RichText,
+    highlight: bool,
+    images: &Images,
+    wanted: &mut HashSet<String>,
+) {
+    let background = highlight.then(|| ui.visuals().selection.bg_fill.gamma_multiply(0.5));
+    let mut emotes = Emotes { images, wanted };
+    let emotes = &mut emotes;
+    ui.vertical(|ui| {
+        for block in &rich.blocks {
+            match block {
+                Block::Paragraph(spans) => spans_ui(ui, spans, emotes, background, 1.0),
+                Block::Heading(level, spans) => {
+                    let scale = match level {
+                        1 => 1.4,
+                        2 => 1.25,
+                        _ => 1.1,
+                    };
+                    spans_ui(ui, spans, emotes, background, scale);
+                }
+                Block::Quote(spans) => {
+                    ui.horizontal(|ui| {
+                        ui.add(egui::Separator::default().vertical());
+                        spans_ui(ui, spans, emotes, background, 1.0);
+                    });
+                }
+                Block::Code { language, code } => {
+                    let frame = egui::Frame::group(ui.style()).fill(ui.visuals().extreme_bg_color);
+                    let resp = frame.show(ui, |ui| {
+                        ui.add(egui::Label::new(egui::RichText::new(code).monospace()))
+                    });
+                    if let Some(language) = language {
+                        resp.response.on_hover_text(language);
+                    }
+                }
+                Block::Rule => {
+                    ui.separator();
+                }
+                Block::List { ordered, items } => {
+                    for (i, item) in items.iter().enumerate() {
+                        ui.horizontal_wrapped(|ui| {
+                            ui.spacing_mut().item_spacing.x = 0.0;
+                            let bullet = if *ordered {
+                                format!("{}. ", i + 1)
+                            } else {
+                                "• ".to_owned()
+                            };
+                            ui.label(bullet);
+                            spans_row(ui, item, emotes, background, 1.0);
+                        });
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Where emote images come from, and which are still missing.
+struct Emotes<'a> {
+    images: &'a Images,
+    wanted: &'a mut HashSet<String>,
+}
+
+fn spans_ui(
+    ui: &mut egui::Ui,
+    spans: &[Span],
+    emotes: &mut Emotes,
+    background: Option<egui::Color32>,
+    scale: f32,
+) {
+    ui.horizontal_wrapped(|ui| {
+        ui.spacing_mut().item_spacing.x = 0.0;
+        spans_row(ui, spans, emotes, background, scale);
+    });
+}
+
+/// One widget per span, flowing in the enclosing wrapped row.
+fn spans_row(
+    ui: &mut egui::Ui,
+    spans: &[Span],
+    emotes: &mut Emotes,
+    background: Option<egui::Color32>,
+    scale: f32,
+) {
+    let size = egui::TextStyle::Body.resolve(ui.style()).size * scale;
+    for span in spans {
+        if let Some(mxc) = &span.emote {
+            match emotes.images.get(mxc) {
+                Some(Some(bytes)) => {
+                    let image = egui::Image::from_bytes(
+                        format!("bytes://{mxc}"),
+                        egui::load::Bytes::Shared(bytes.clone()),
+                    )
+                    .fit_to_exact_size(egui::vec2(size * 1.4, size * 1.4));
+                    ui.add(image).on_hover_text(&span.text);
+                    continue;
+                }
+                Some(None) => {}
+                None => {
+                    emotes.wanted.insert(mxc.clone());
+                }
+            }
+        }
+        let mut text = egui::RichText::new(&span.text).size(size);
+        if span.style.bold || scale > 1.0 {
+            text = text.strong();
+        }
+        if span.style.italic {
+            text = text.italics();
+        }
+        if span.style.underline {
+            text = text.underline();
+        }
+        if span.style.strike {
+            text = text.strikethrough();
+        }
+        if span.style.code {
+            text = text.code();
+        }
+        if let Some(bg) = background {
+            text = text.background_color(bg);
+        }
+        if span.style.spoiler {
+            let id = ui.next_auto_id();
+            let revealed = ui.data(|d| d.get_temp::<bool>(id)).unwrap_or(false);
+            if !revealed {
+                let hidden = ui.visuals().strong_text_color();
+                let bar = text.color(hidden).background_color(hidden);
+                let resp = ui.add(egui::Label::new(bar).sense(egui::Sense::click()));
+                if resp.on_hover_text("Spoiler — click to reveal").clicked() {
+                    ui.data_mut(|d| d.insert_temp(id, true));
+                }
+                continue;
+            }
+        }
+        match &span.link {
+            Some(url) => {
+                ui.hyperlink_to(text, url);
+            }
+            None => {
+                ui.label(text);
+            }
+        }
+    }
+}
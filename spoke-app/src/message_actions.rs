@@ -0,0 +1,193 @@
+/// Per-message quick actions: the toolbar shown over a hovered or
+/// keyboard-focused timeline row, their shortcuts, and selecting several
+/// messages to act on at once.
+use std::collections::HashSet;
+
+use eframe::egui;
+
+/// Reactions offered straight from the toolbar.
+pub const QUICK_REACTIONS: [&str; 6] = ["👍", "❤", "😂", "🎉", "😮", "👀"];
+
+/// How long a press on a message must be held to select it.
+const LONG_PRESS_SECS: f64 = 1.0;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MessageAction {
+    React(String),
+    Reply,
+    Thread,
+    Edit,
+    CopyText,
+    CopyLink,
+    MessageSender,
+    /// Start selecting messages, with this one.
+    Select,
+    /// Report the message to the homeserver's admins.
+    Report,
+    /// Move the discussion around the message into a new room.
+    SpinOff,
+    /// Pin the message to the room, or unpin it.
+    TogglePin,
+}
+
+impl MessageAction {
+    /// Shortcut for the focused message (with Alt), if it has one.
+    pub fn key(&self) -> Option<egui::Key> {
+        match self {
+            MessageAction::Reply => Some(egui::Key::R),
+            MessageAction::Thread => Some(egui::Key::T),
+            MessageAction::Edit => Some(egui::Key::E),
+            _ => None,
+        }
+    }
+}
+
+/// What the toolbar can offer for one message.
+pub struct Offer {
+    /// Only our own sent messages can be edited.
+    pub editable: bool,
+    /// Replies can't start a thread of their own.
+    pub threadable: bool,
+    /// Whether the message is pinned; `None` if we can't pin in this room.
+    pub pinned: Option<bool>,
+}
+
+/// Draw the toolbar floating over the top-right corner of `row`. Returns
+/// the chosen action, and whether the toolbar should stay up next frame
+/// even if the row isn't hovered (the pointer is on it or a menu is open).
+pub fn toolbar(
+    ctx: &egui::Context,
+    id: egui::Id,
+    row: egui::Rect,
+    offer: Offer,
+) -> (Option<MessageAction>, bool) {
+    let mut action = None;
+    let mut menu_open = false;
+    let area = egui::Area::new(id)
+        .order(egui::Order::Foreground)
+        .pivot(egui::Align2::RIGHT_BOTTOM)
+        .fixed_pos(row.right_top() + egui::vec2(-4.0, 8.0))
+        .show(ctx, |ui| {
+            egui::Frame::popup(ui.style())
+                .inner_margin(2.0)
+                .show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.spacing_mut().item_spacing.x = 2.0;
+                        let react = ui.menu_button("😀", |ui| {
+                            ui.horizontal(|ui| {
+                                for emoji in QUICK_REACTIONS {
+                                    if ui.button(emoji).clicked() {
+                                        action = Some(MessageAction::React(emoji.to_owned()));
+                                        ui.close_menu();
+                                    }
+                                }
+                            });
+                        });
+                        menu_open |= react.inner.is_some();
+                        react.response.on_hover_text("React");
+                        if ui
+                            .small_button("↩")
+                            .on_hover_text("Reply (Alt+R)")
+                            .clicked()
+                        {
+                            action = Some(MessageAction::Reply);
+                        }
+                        if offer.threadable
+                            && ui
+                                .small_button("🧵")
+                                .on_hover_text("Reply in thread (Alt+T)")
+                                .clicked()
+                        {
+                            action = Some(MessageAction::Thread);
+                        }
+                        if offer.editable
+                            && ui.small_button("✏").on_hover_text("Edit (Alt+E)").clicked()
+                        {
+                            action = Some(MessageAction::Edit);
+                        }
+                        let more = ui.menu_button("⋯", |ui| {
+                            for (label, a) in [
+                                ("Copy text", MessageAction::CopyText),
+                                ("Copy link", MessageAction::CopyLink),
+                                ("Message sender", MessageAction::MessageSender),
+                                ("Select", MessageAction::Select),
+                                ("Spin off into a room…", MessageAction::SpinOff),
+                                ("Report…", MessageAction::Report),
+                            ] {
+                                if ui.button(label).clicked() {
+                                    action = Some(a);
+                                    ui.close_menu();
+                                }
+                            }
+                            if let Some(pinned) = offer.pinned {
+                                if ui.button(if pinned { "Unpin" } else { "Pin" }).clicked() {
+                                    action = Some(MessageAction::TogglePin);
+                                    ui.close_menu();
+                                }
+                            }
+                        });
+                        menu_open |= more.inner.is_some();
+                        more.response.on_hover_text("More…");
+                    });
+                });
+        });
+    (action, menu_open || area.response.contains_pointer())
+}
+
+/// Messages picked for a bulk action, all in one room.
+pub struct Selection {
+    pub room_id: String,
+    /// Event IDs; local echoes can't be selected.
+    pub events: HashSet<String>,
+}
+
+impl Selection {
+    pub fn new(room_id: String, event_id: String) -> Self {
+        Self {
+            room_id,
+            events: HashSet::from([event_id]),
+        }
+    }
+
+    pub fn toggle(&mut self, event_id: &str) {
+        if !self.events.remove(event_id) {
+            self.events.insert(event_id.to_owned());
+        }
+    }
+}
+
+/// How the pointer picked a message for selection.
+pub enum Pick {
+    Toggle,
+    Add,
+}
+
+/// Ctrl+click on `row` (any click once selecting) toggles its message; a
+/// long press adds it.
+pub fn pick(ui: &egui::Ui, row: egui::Rect, selecting: bool) -> Option<Pick> {
+    let row = row.intersect(ui.clip_rect());
+    let (clicked, held) = ui.input(|i| {
+        let on_row = |p: Option<egui::Pos2>| p.is_some_and(|p| row.contains(p));
+        let clicked = i.pointer.primary_clicked()
+            && on_row(i.pointer.interact_pos())
+            && (selecting || i.modifiers.command);
+        let pressed = i.pointer.primary_down()
+            && !i.pointer.is_decidedly_dragging()
+            && on_row(i.pointer.press_origin());
+        (
+            clicked,
+            pressed.then(|| i.pointer.press_start_time().map_or(0.0, |t| i.time - t)),
+        )
+    });
+    if clicked {
+        return Some(Pick::Toggle);
+    }
+    let held = held?;
+    if held >= LONG_PRESS_SECS {
+        return Some(Pick::Add);
+    }
+    // Nothing moves while the button is held; wake up when it's been long enough.
+    ui.ctx()
+        .request_repaint_after_secs((LONG_PRESS_SECS - held) as f32);
+    None
+}
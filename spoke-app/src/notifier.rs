@@ -0,0 +1,60 @@
+/// Desktop notifications via the OS notification service (libnotify/D-Bus,
+/// Windows toasts, macOS Notification Center).
+///
+/// Clicking a notification reports its room back so the app can focus it.
+/// Only the D-Bus backend tells us about clicks; elsewhere a click just
+/// raises the window through the OS.
+use std::sync::mpsc;
+
+use eframe::egui;
+use notify_rust::Notification;
+use tracing::warn;
+
+pub struct Notifier {
+    clicks_tx: mpsc::Sender<String>,
+    clicks_rx: mpsc::Receiver<String>,
+}
+
+impl Notifier {
+    pub fn new() -> Self {
+        let (clicks_tx, clicks_rx) = mpsc::channel();
+        Self {
+            clicks_tx,
+            clicks_rx,
+        }
+    }
+
+    /// Show a notification for `room_id`. Returns immediately; the OS call and
+    /// waiting for a click happen on a helper thread.
+    pub fn show(&self, ctx: &egui::Context, summary: String, body: String, room_id: String) {
+        let clicks = self.clicks_tx.clone();
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            let mut notification = Notification::new();
+            notification.appname("Spoke").summary(&summary).body(&body);
+            #[cfg(all(unix, not(target_os = "macos")))]
+            notification.action("default", "Open");
+            let handle = match notification.show() {
+                Ok(handle) => handle,
+                Err(e) => {
+                    warn!("desktop notification: {e}");
+                    return;
+                }
+            };
+            #[cfg(all(unix, not(target_os = "macos")))]
+            handle.wait_for_action(|action| {
+                if action == "default" {
+                    let _ = clicks.send(room_id);
+                    ctx.request_repaint();
+                }
+            });
+            #[cfg(not(all(unix, not(target_os = "macos"))))]
+            let _ = (handle, clicks, ctx, room_id);
+        });
+    }
+
+    /// The room of a notification the user clicked since the last call.
+    pub fn take_click(&self) -> Option<String> {
+        self.clicks_rx.try_recv().ok()
+    }
+}
@@ -0,0 +1,137 @@
+/// Polls in the timeline: the voting card drawn under a poll's question,
+/// and the draft behind the "Create poll" dialog.
+use eframe::egui;
+use spoke_core::matrix::{Poll, PollKind, PollResults};
+
+/// Most answers a poll can offer; MSC3381 allows up to 20.
+pub const MAX_ANSWERS: usize = 20;
+
+/// What the user did on a poll card.
+pub enum PollAction {
+    /// The new selection; empty withdraws the vote.
+    Vote(Vec<String>),
+    End,
+}
+
+/// Draw the answers with our choice and the counts, which undisclosed polls
+/// keep hidden until the end. `can_end` offers the creator an "End poll"
+/// button.
+pub fn show(
+    ui: &mut egui::Ui,
+    poll: &Poll,
+    results: &PollResults,
+    can_end: bool,
+) -> Option<PollAction> {
+    let mut action = None;
+    let reveal = results.ended || poll.kind == PollKind::Disclosed;
+    let multiple = poll.max_selections > 1;
+    egui::Frame::group(ui.style()).show(ui, |ui| {
+        ui.set_max_width(360.0);
+        ui.strong(&poll.question.text);
+        if multiple {
+            ui.weak(format!("Choose up to {}", poll.max_selections));
+        }
+        for (answer, count) in poll.answers.iter().zip(&results.counts) {
+            let chosen = results.own.contains(&answer.id);
+            ui.horizontal(|ui| {
+                let clicked = if results.ended {
+                    ui.add_enabled(false, egui::RadioButton::new(chosen, &answer.text))
+                        .clicked()
+                } else if multiple {
+                    let mut checked = chosen;
+                    ui.checkbox(&mut checked, &answer.text).changed()
+                } else {
+                    ui.radio(chosen, &answer.text).clicked()
+                };
+                if clicked {
+                    let mut own = results.own.clone();
+                    match (chosen, multiple) {
+                        (true, _) => own.retain(|id| id != &answer.id),
+                        (false, true) if own.len() < poll.max_selections as usize => {
+                            own.push(answer.id.clone())
+                        }
+                        (false, true) => {}
+                        (false, false) => own = vec![answer.id.clone()],
+                    }
+                    if own != results.own {
+                        action = Some(PollAction::Vote(own));
+                    }
+                }
+                if reveal {
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        ui.weak(count.to_string())
+                    });
+                }
+            });
+            if reveal {
+                let share = if results.voters == 0 {
+                    0.0
+                } else {
+                    *count as f32 / results.voters as f32
+                };
+                ui.add(egui::ProgressBar::new(share).desired_height(4.0));
+            }
+        }
+        ui.horizontal(|ui| {
+            let voters = match results.voters {
+                1 => "1 vote".to_owned(),
+                n => format!("{n} votes"),
+            };
+            let status = match (results.ended, reveal) {
+                (true, _) => format!("Ended · {voters}"),
+                (false, true) => voters,
+                (false, false) => format!("{voters} · results when the poll ends"),
+            };
+            ui.weak(status);
+            if can_end && !results.ended && ui.small_button("End poll").clicked() {
+                action = Some(PollAction::End);
+            }
+        });
+    });
+    action
+}
+
+/// A poll being written in the "Create poll" dialog.
+pub struct PollDraft {
+    pub room_id: String,
+    pub question: String,
+    pub answers: Vec<String>,
+    pub max_selections: u32,
+    /// Show results while the poll is open.
+    pub disclosed: bool,
+}
+
+impl PollDraft {
+    pub fn new(room_id: String) -> Self {
+        Self {
+            room_id,
+            question: String::new(),
+            answers: vec![String::new(), String::new()],
+            max_selections: 1,
+            disclosed: true,
+        }
+    }
+
+    /// The non-blank answers, trimmed.
+    pub fn answers(&self) -> Vec<String> {
+        self.answers
+            .iter()
+            .map(|a| a.trim())
+            .filter(|a| !a.is_empty())
+            .map(str::to_owned)
+            .collect()
+    }
+
+    /// A question and at least two answers.
+    pub fn ready(&self) -> bool {
+        !self.question.trim().is_empty() && self.answers().len() >= 2
+    }
+
+    pub fn kind(&self) -> PollKind {
+        if self.disclosed {
+            PollKind::Disclosed
+        } else {
+            PollKind::Undisclosed
+        }
+    }
+}
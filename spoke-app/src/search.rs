@@ -0,0 +1,150 @@
+/// In-room search (Ctrl+F) over the loaded timeline, with optional
+/// progressive backfill through older history.
+use eframe::egui;
+
+use crate::bridge::TimelineItem;
+
+/// Older chunks fetched per "search older messages" before giving up.
+pub const BACKFILL_PAGES: u32 = 10;
+
+pub struct RoomSearch {
+    pub room_id: String,
+    pub query: String,
+    /// Show only matching messages instead of highlighting them in place.
+    pub filter: bool,
+    /// Current match, counted from the newest so prepended history doesn't
+    /// move it.
+    current_from_end: usize,
+    /// Chunks left to fetch while looking for an older match.
+    pub backfill_remaining: u32,
+    /// Match count when the current backfill started.
+    backfill_baseline: usize,
+    /// Scroll the current match into view on the next frame.
+    pub scroll_pending: bool,
+    /// Focus the search field on the next frame.
+    pub focus_pending: bool,
+}
+
+impl RoomSearch {
+    pub fn new(room_id: String) -> Self {
+        Self {
+            room_id,
+            query: String::new(),
+            filter: false,
+            current_from_end: 0,
+            backfill_remaining: 0,
+            backfill_baseline: 0,
+            scroll_pending: false,
+            focus_pending: true,
+        }
+    }
+
+    pub fn is_match(&self, item: &TimelineItem) -> bool {
+        !self.query.is_empty()
+            && item
+                .body
+                .to_lowercase()
+                .contains(&self.query.to_lowercase())
+    }
+
+    /// Indices of matching messages in `log`, oldest first.
+    pub fn matches(&self, log: &[TimelineItem]) -> Vec<usize> {
+        log.iter()
+            .enumerate()
+            .filter(|(_, m)| self.is_match(m))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Index into `log` of the current match.
+    pub fn current(&self, matches: &[usize]) -> Option<usize> {
+        let n = matches.len();
+        (self.current_from_end < n).then(|| matches[n - 1 - self.current_from_end])
+    }
+
+    /// 1-based position of the current match for the "k / n" counter.
+    pub fn position(&self, matches: &[usize]) -> usize {
+        matches.len().saturating_sub(self.current_from_end)
+    }
+
+    pub fn reset(&mut self) {
+        self.current_from_end = 0;
+        self.backfill_remaining = 0;
+        self.scroll_pending = true;
+    }
+
+    /// Step to the next older match.
+    pub fn older(&mut self, matches: &[usize]) {
+        if self.current_from_end + 1 < matches.len() {
+            self.current_from_end += 1;
+            self.scroll_pending = true;
+        }
+    }
+
+    /// Step to the next newer match.
+    pub fn newer(&mut self) {
+        if self.current_from_end > 0 {
+            self.current_from_end -= 1;
+            self.scroll_pending = true;
+        }
+    }
+
+    pub fn start_backfill(&mut self, matches: &[usize]) {
+        self.backfill_remaining = BACKFILL_PAGES;
+        self.backfill_baseline = matches.len();
+    }
+
+    /// After an older chunk arrived: `true` to fetch another one. Jumps to
+    /// the newest of any matches the backfill turned up.
+    pub fn continue_backfill(&mut self, matches: &[usize], has_more: bool) -> bool {
+        if self.backfill_remaining == 0 {
+            return false;
+        }
+        if matches.len() > self.backfill_baseline {
+            self.backfill_remaining = 0;
+            self.current_from_end = self.backfill_baseline;
+            self.scroll_pending = true;
+            return false;
+        }
+        self.backfill_remaining -= 1;
+        has_more && self.backfill_remaining > 0
+    }
+}
+
+/// `text` with every case-insensitive occurrence of `query` highlighted.
+pub fn highlight(ui: &egui::Ui, text: &str, query: &str, current: bool) -> egui::text::LayoutJob {
+    let font = egui::TextStyle::Body.resolve(ui.style());
+    let color = ui.visuals().text_color();
+    let plain = egui::TextFormat::simple(font.clone(), color);
+    let hit = egui::TextFormat {
+        background: if current {
+            egui::Color32::from_rgb(230, 150, 40)
+        } else {
+            ui.visuals().selection.bg_fill
+        },
+        ..egui::TextFormat::simple(font, color)
+    };
+
+    let mut job = egui::text::LayoutJob::default();
+    let lower = text.to_lowercase();
+    let needle = query.to_lowercase();
+    // Lowercasing can change byte lengths; fall back to no highlight then.
+    if needle.is_empty() || lower.len() != text.len() {
+        job.append(text, 0.0, plain);
+        return job;
+    }
+    let mut pos = 0;
+    for (start, _) in lower.match_indices(&needle) {
+        if start < pos
+            || !text.is_char_boundary(start)
+            || !text.is_char_boundary(start + needle.len())
+        {
+            continue;
+        }
+        job.append(&text[pos..start], 0.0, plain.clone());
+        job.append(&text[start..start + needle.len()], 0.0, hit.clone());
+        pos = start + needle.len();
+    }
+    job.append(&text[pos..], 0.0, plain);
+    job
+}
@@ -0,0 +1,442 @@
+/// User settings persisted as JSON in the platform config directory, and
+/// roamed through `org.spoke.settings` account data (see `to_account`).
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use spoke_core::{
+    matrix::{AccountSettingsEventContent, SyncFilterOptions},
+    proxy::ProxySettings,
+    voice::{ice::IceSettings, priority},
+};
+use tracing::warn;
+
+use crate::keybinds::Keybinds;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub keybinds: Keybinds,
+    pub privacy: Privacy,
+    pub ice: IceSettings,
+    pub voice: VoiceSettings,
+    pub notifications: Notifications,
+    /// What sync fetches; less is faster on large accounts.
+    pub sync: SyncFilterOptions,
+    pub proxy: ProxySettings,
+    pub encryption: Encryption,
+    pub appearance: Appearance,
+    pub developer: Developer,
+    /// Identifies this installation's section of the roaming settings.
+    pub machine_id: String,
+    /// When the settings last changed, in milliseconds since the Unix epoch;
+    /// decides whether our copy or the account's wins.
+    pub updated_ts: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Appearance {
+    pub theme: Theme,
+    /// Fold runs of joins, leaves and name changes into one line.
+    pub collapse_membership: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Theme {
+    /// Follow the OS.
+    #[default]
+    System,
+    Dark,
+    Light,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 3] = [Theme::System, Theme::Dark, Theme::Light];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Theme::System => "Follow system",
+            Theme::Dark => "Dark",
+            Theme::Light => "Light",
+        }
+    }
+
+    pub fn preference(self) -> egui::ThemePreference {
+        match self {
+            Theme::System => egui::ThemePreference::System,
+            Theme::Dark => egui::ThemePreference::Dark,
+            Theme::Light => egui::ThemePreference::Light,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Developer {
+    /// Offer the room state viewer and the custom event sender.
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Encryption {
+    /// Share room keys only with verified devices. Takes effect at the next
+    /// sign-in, when the client is built.
+    pub verified_devices_only: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Notifications {
+    /// Show OS notifications for mentions, DMs and calls while unfocused.
+    pub desktop: bool,
+    /// Suppress desktop notifications without turning them off.
+    pub do_not_disturb: bool,
+}
+
+impl Default for Notifications {
+    fn default() -> Self {
+        Self {
+            desktop: true,
+            do_not_disturb: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VoiceSettings {
+    /// How much others are turned down while a priority speaker talks (dB).
+    pub priority_duck_db: f32,
+    /// Post an `org.spoke.voice.summary` when we're the last to leave a call.
+    pub post_call_summary: bool,
+    /// Mic gain applied before publishing (dB).
+    pub input_gain_db: f32,
+    /// Turn quiet mics up and hot ones down automatically.
+    pub auto_gain: bool,
+    /// Even out loudness between remote participants.
+    pub normalize_levels: bool,
+    /// Cancel speaker echo out of the mic; not needed with headphones.
+    pub echo_cancellation: bool,
+    /// Input and output devices by name; `None` follows the OS default.
+    pub input_device: Option<String>,
+    pub output_device: Option<String>,
+    /// When the mic is open on this machine.
+    pub input_mode: InputMode,
+    /// Keyboard and mouse keybinds work while Spoke is in the background.
+    pub global_keybinds: bool,
+}
+
+/// How the mic opens during a call.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputMode {
+    /// Open whenever we're unmuted.
+    VoiceActivity,
+    /// Open only while the push-to-talk keybind is held. Acts like
+    /// `VoiceActivity` until one is bound.
+    #[default]
+    PushToTalk,
+}
+
+impl InputMode {
+    pub const ALL: [InputMode; 2] = [InputMode::VoiceActivity, InputMode::PushToTalk];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            InputMode::VoiceActivity => "Voice activity",
+            InputMode::PushToTalk => "Push to talk",
+        }
+    }
+}
+
+impl Default for VoiceSettings {
+    fn default() -> Self {
+        Self {
+            priority_duck_db: priority::DEFAULT_DUCK_DB,
+            post_call_summary: false,
+            input_gain_db: 0.0,
+            auto_gain: true,
+            normalize_levels: true,
+            echo_cancellation: true,
+            input_device: None,
+            output_device: None,
+            input_mode: InputMode::default(),
+            global_keybinds: false,
+        }
+    }
+}
+
+/// What we disclose to other room members. Turning a signal off also hides
+/// everyone else's — the exchange is reciprocal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Privacy {
+    pub read_receipts: bool,
+    pub typing: bool,
+    /// Show link previews in unencrypted rooms. Fetched through the
+    /// homeserver, which then sees every link posted in the room, so
+    /// encrypted rooms only show them when turned on per room.
+    pub url_previews: bool,
+    /// Per-room overrides keyed by room ID; `None` follows the global value.
+    pub rooms: HashMap<String, RoomPrivacy>,
+    /// Accounts, by user ID, that appear offline: no presence, typing or
+    /// public read receipts, while everyone else's still show.
+    pub invisible: HashSet<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RoomPrivacy {
+    pub read_receipts: Option<bool>,
+    pub typing: Option<bool>,
+    pub url_previews: Option<bool>,
+}
+
+impl Default for Privacy {
+    fn default() -> Self {
+        Self {
+            read_receipts: true,
+            typing: true,
+            url_previews: true,
+            rooms: HashMap::new(),
+            invisible: HashSet::new(),
+        }
+    }
+}
+
+impl Privacy {
+    pub fn read_receipts(&self, room_id: &str) -> bool {
+        self.rooms
+            .get(room_id)
+            .and_then(|r| r.read_receipts)
+            .unwrap_or(self.read_receipts)
+    }
+
+    pub fn typing(&self, room_id: &str) -> bool {
+        self.rooms
+            .get(room_id)
+            .and_then(|r| r.typing)
+            .unwrap_or(self.typing)
+    }
+
+    pub fn url_previews(&self, room_id: &str, encrypted: bool) -> bool {
+        self.rooms
+            .get(room_id)
+            .and_then(|r| r.url_previews)
+            .unwrap_or(self.url_previews && !encrypted)
+    }
+
+    pub fn invisible(&self, user_id: &str) -> bool {
+        self.invisible.contains(user_id)
+    }
+
+    pub fn set_invisible(&mut self, user_id: &str, invisible: bool) {
+        if invisible {
+            self.invisible.insert(user_id.to_owned());
+        } else {
+            self.invisible.remove(user_id);
+        }
+    }
+
+    pub fn room_mut(&mut self, room_id: &str) -> &mut RoomPrivacy {
+        self.rooms.entry(room_id.to_owned()).or_default()
+    }
+
+    /// Drop overrides that no longer override anything.
+    pub fn prune(&mut self) {
+        self.rooms.retain(|_, r| {
+            r.read_receipts.is_some() || r.typing.is_some() || r.url_previews.is_some()
+        });
+    }
+}
+
+/// The settings every device signed in to the account shares. Network,
+/// sync and encryption settings stay with the machine they were made on.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct SharedSettings {
+    keybinds: Keybinds,
+    privacy: Privacy,
+    notifications: Notifications,
+    appearance: Appearance,
+    /// Sidebar layout.
+    pinned_rooms: Vec<String>,
+}
+
+/// The settings kept per machine in the account: audio depends on the
+/// hardware at hand.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct MachineSettings {
+    voice: VoiceSettings,
+}
+
+/// Marker identifying a settings export file.
+const EXPORT_FORMAT: &str = "spoke.settings";
+/// Bumped when an export can no longer be read by older versions.
+const EXPORT_VERSION: u32 = 1;
+
+/// On-disk shape of an exported settings file.
+#[derive(Serialize, Deserialize)]
+struct SettingsExport {
+    format: String,
+    version: u32,
+    settings: Settings,
+}
+
+impl Settings {
+    /// `{config_dir}/spoke/settings.json`, e.g. `~/.config/spoke/settings.json`.
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|d| d.join("spoke").join("settings.json"))
+    }
+
+    /// Load settings, falling back to defaults if the file is missing or bad.
+    pub fn load() -> Self {
+        let mut settings = Self::read();
+        if settings.machine_id.is_empty() {
+            settings.machine_id = uuid::Uuid::new_v4().to_string();
+            settings.write();
+        }
+        settings
+    }
+
+    fn read() -> Self {
+        let Some(path) = Self::path() else {
+            return Self::default();
+        };
+        let Ok(json) = std::fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        serde_json::from_str(&json).unwrap_or_else(|e| {
+            warn!("ignoring unreadable settings {path:?}: {e}");
+            Self::default()
+        })
+    }
+
+    /// What to store in account data: the shared settings (with the sidebar's
+    /// `pinned_rooms`) and this machine's section, keeping `existing`'s
+    /// sections for other machines.
+    pub fn to_account(
+        &self,
+        pinned_rooms: &[String],
+        existing: Option<&AccountSettingsEventContent>,
+    ) -> AccountSettingsEventContent {
+        let shared = SharedSettings {
+            keybinds: self.keybinds.clone(),
+            privacy: self.privacy.clone(),
+            notifications: self.notifications.clone(),
+            appearance: self.appearance.clone(),
+            pinned_rooms: pinned_rooms.to_vec(),
+        };
+        let machine = MachineSettings {
+            voice: self.voice.clone(),
+        };
+        let mut machines = existing.map(|e| e.machines.clone()).unwrap_or_default();
+        machines.insert(
+            self.machine_id.clone(),
+            serde_json::to_value(machine).unwrap_or_default(),
+        );
+        AccountSettingsEventContent {
+            shared: serde_json::to_value(shared).unwrap_or_default(),
+            machines,
+            updated_ts: self.updated_ts,
+        }
+    }
+
+    /// Take on the account's settings and save them locally. Returns the
+    /// sidebar's pinned rooms. Sections that can't be read keep ours.
+    pub fn apply_account(&mut self, account: &AccountSettingsEventContent) -> Option<Vec<String>> {
+        let shared = match serde_json::from_value::<SharedSettings>(account.shared.clone()) {
+            Ok(shared) => {
+                self.keybinds = shared.keybinds;
+                self.privacy = shared.privacy;
+                self.notifications = shared.notifications;
+                self.appearance = shared.appearance;
+                Some(shared.pinned_rooms)
+            }
+            Err(e) => {
+                warn!("ignoring unreadable roaming settings: {e}");
+                None
+            }
+        };
+        if let Some(machine) = account.machines.get(&self.machine_id) {
+            match serde_json::from_value::<MachineSettings>(machine.clone()) {
+                Ok(machine) => self.voice = machine.voice,
+                Err(e) => warn!("ignoring unreadable roaming settings for this machine: {e}"),
+            }
+        }
+        self.updated_ts = account.updated_ts;
+        self.write();
+        shared
+    }
+
+    /// Suggested location for an export, e.g. `~/Documents/spoke-settings.json`.
+    pub fn default_export_path() -> PathBuf {
+        dirs::document_dir()
+            .or_else(dirs::home_dir)
+            .unwrap_or_default()
+            .join("spoke-settings.json")
+    }
+
+    /// Write every setting, including per-room overrides, to `path` so it can
+    /// be imported on another machine.
+    pub fn export(&self, path: &Path) -> Result<(), String> {
+        let export = SettingsExport {
+            format: EXPORT_FORMAT.to_owned(),
+            version: EXPORT_VERSION,
+            settings: self.clone(),
+        };
+        let json = serde_json::to_string_pretty(&export).map_err(|e| e.to_string())?;
+        std::fs::write(path, json).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// Read settings exported by [`Settings::export`]. Sections missing from
+    /// the file (e.g. from an older version) keep their defaults.
+    pub fn import(path: &Path) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let export: SettingsExport =
+            serde_json::from_str(&json).map_err(|e| format!("not a Spoke settings file: {e}"))?;
+        if export.format != EXPORT_FORMAT {
+            return Err("not a Spoke settings file".into());
+        }
+        if export.version > EXPORT_VERSION {
+            return Err(format!(
+                "exported by a newer Spoke (format version {}); please update",
+                export.version
+            ));
+        }
+        let mut settings = export.settings;
+        settings.privacy.prune();
+        Ok(settings)
+    }
+
+    /// Save a change the user made, marking it newer than the account's.
+    pub fn save(&mut self) {
+        self.updated_ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        self.write();
+    }
+
+    fn write(&self) {
+        let Some(path) = Self::path() else { return };
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        match serde_json::to_string_pretty(self) {
+            Ok(json) => {
+                if let Err(e) = std::fs::write(&path, json) {
+                    warn!("failed to write settings {path:?}: {e}");
+                }
+            }
+            Err(e) => warn!("failed to serialise settings: {e}"),
+        }
+    }
+}
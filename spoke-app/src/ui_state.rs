@@ -0,0 +1,239 @@
+/// Central UI-state model: which dialogs are open (transient) and the per-room
+/// side panel layout (persisted), replacing scattered `show_*` booleans.
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Minimum time between layout writes while the user drags a panel edge.
+const SAVE_DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// Modal-ish windows. Never persisted — they always start closed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Dialog {
+    Invite,
+    CreateRoom,
+    JoinRoom,
+    Explore,
+    Settings,
+    VoiceDiagnostics,
+    Moderation,
+    PowerLevels,
+    ServerAcl,
+    RoomSettings,
+    DirectMessage,
+    CallSummary,
+    IncomingCall,
+    CreatePoll,
+    Report,
+    AfkChannel,
+    SpinOff,
+    RoomState,
+}
+
+/// Right-hand side panels, laid out per room.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Panel {
+    Members,
+    Threads,
+    Pinned,
+}
+
+impl Panel {
+    pub const ALL: [Panel; 3] = [Panel::Members, Panel::Threads, Panel::Pinned];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Panel::Members => "Members",
+            Panel::Threads => "Threads",
+            Panel::Pinned => "Pinned",
+        }
+    }
+
+    pub fn icon(self) -> &'static str {
+        match self {
+            Panel::Members => "👥",
+            Panel::Threads => "🧵",
+            Panel::Pinned => "📌",
+        }
+    }
+
+    pub fn default_width(self) -> f32 {
+        match self {
+            Panel::Members => 220.0,
+            Panel::Threads => 320.0,
+            Panel::Pinned => 280.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PanelState {
+    pub open: bool,
+    pub width: f32,
+}
+
+/// Panel layout for one room.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RoomLayout {
+    #[serde(default)]
+    pub panels: BTreeMap<Panel, PanelState>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UiState {
+    /// Width of the room list; `None` until the user resizes it.
+    #[serde(default)]
+    pub sidebar_width: Option<f32>,
+    /// Per-room panel layout, keyed by room ID.
+    #[serde(default)]
+    pub rooms: HashMap<String, RoomLayout>,
+    /// Rooms pinned to the top of the sidebar, in pin order.
+    #[serde(default)]
+    pub pinned_rooms: Vec<String>,
+
+    #[serde(skip)]
+    dialogs: HashSet<Dialog>,
+    #[serde(skip)]
+    dirty: bool,
+    #[serde(skip)]
+    last_save: Option<Instant>,
+}
+
+impl UiState {
+    /// `{config_dir}/spoke/ui_state.json`.
+    fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|d| d.join("spoke").join("ui_state.json"))
+    }
+
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Self::default();
+        };
+        let Ok(json) = std::fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        serde_json::from_str(&json).unwrap_or_else(|e| {
+            warn!("ignoring unreadable UI state {path:?}: {e}");
+            Self::default()
+        })
+    }
+
+    // ── Dialogs ───────────────────────────────────────────────────────────────
+
+    pub fn is_open(&self, dialog: Dialog) -> bool {
+        self.dialogs.contains(&dialog)
+    }
+
+    pub fn open(&mut self, dialog: Dialog) {
+        self.dialogs.insert(dialog);
+    }
+
+    pub fn close(&mut self, dialog: Dialog) {
+        self.dialogs.remove(&dialog);
+    }
+
+    // ── Panels ────────────────────────────────────────────────────────────────
+
+    pub fn panel_open(&self, room_id: &str, panel: Panel) -> bool {
+        self.rooms
+            .get(room_id)
+            .and_then(|l| l.panels.get(&panel))
+            .is_some_and(|p| p.open)
+    }
+
+    pub fn panel_width(&self, room_id: &str, panel: Panel) -> f32 {
+        self.rooms
+            .get(room_id)
+            .and_then(|l| l.panels.get(&panel))
+            .map(|p| p.width)
+            .unwrap_or_else(|| panel.default_width())
+    }
+
+    pub fn toggle_panel(&mut self, room_id: &str, panel: Panel) {
+        let state = self.panel_entry(room_id, panel);
+        state.open = !state.open;
+        self.dirty = true;
+    }
+
+    /// Record the panel's rendered width (only marks dirty on real changes).
+    pub fn set_panel_width(&mut self, room_id: &str, panel: Panel, width: f32) {
+        let state = self.panel_entry(room_id, panel);
+        if (state.width - width).abs() >= 1.0 {
+            state.width = width;
+            self.dirty = true;
+        }
+    }
+
+    pub fn set_sidebar_width(&mut self, width: f32) {
+        if self.sidebar_width.is_none_or(|w| (w - width).abs() >= 1.0) {
+            self.sidebar_width = Some(width);
+            self.dirty = true;
+        }
+    }
+
+    fn panel_entry(&mut self, room_id: &str, panel: Panel) -> &mut PanelState {
+        self.rooms
+            .entry(room_id.to_owned())
+            .or_default()
+            .panels
+            .entry(panel)
+            .or_insert_with(|| PanelState {
+                open: false,
+                width: panel.default_width(),
+            })
+    }
+
+    // ── Room list ─────────────────────────────────────────────────────────────
+
+    pub fn is_pinned(&self, room_id: &str) -> bool {
+        self.pinned_rooms.iter().any(|id| id == room_id)
+    }
+
+    pub fn toggle_pinned(&mut self, room_id: &str) {
+        if self.is_pinned(room_id) {
+            self.pinned_rooms.retain(|id| id != room_id);
+        } else {
+            self.pinned_rooms.push(room_id.to_owned());
+        }
+        self.dirty = true;
+    }
+
+    // ── Persistence ───────────────────────────────────────────────────────────
+
+    /// Write the layout if it changed, at most once per `SAVE_DEBOUNCE`.
+    /// Returns the delay after which a deferred save should be retried.
+    pub fn save_if_dirty(&mut self) -> Option<Duration> {
+        if !self.dirty {
+            return None;
+        }
+        if let Some(elapsed) = self.last_save.map(|t| t.elapsed()) {
+            if elapsed < SAVE_DEBOUNCE {
+                return Some(SAVE_DEBOUNCE - elapsed);
+            }
+        }
+        self.save();
+        None
+    }
+
+    pub fn save(&mut self) {
+        self.dirty = false;
+        self.last_save = Some(Instant::now());
+        let Some(path) = Self::path() else { return };
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        match serde_json::to_string_pretty(self) {
+            Ok(json) => {
+                if let Err(e) = std::fs::write(&path, json) {
+                    warn!("failed to write UI state {path:?}: {e}");
+                }
+            }
+            Err(e) => warn!("failed to serialise UI state: {e}"),
+        }
+    }
+}
@@ -0,0 +1,88 @@
+// spoke-cli: maintenance commands for a local Spoke install.
+//
+// Commands:
+//   migrate-store [--dry-run] [--data-dir DIR]
+//       Move legacy /tmp/spoke-app-*.db stores and their session files into
+//       the data directory (default: the platform data dir + /spoke).
+
+use std::{path::PathBuf, process::ExitCode};
+
+use spoke_core::matrix::migrate;
+
+const USAGE: &str = "usage: spoke-cli migrate-store [--dry-run] [--data-dir DIR]";
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    tracing_subscriber::fmt()
+        .with_env_filter(std::env::var("RUST_LOG").unwrap_or_else(|_| "spoke_core=info".into()))
+        .init();
+
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        Some("migrate-store") => migrate_store(args.collect()).await,
+        _ => {
+            eprintln!("{USAGE}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn migrate_store(args: Vec<String>) -> ExitCode {
+    let mut dry_run = false;
+    let mut data_dir = dirs::data_dir().map(|d| d.join("spoke"));
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dry-run" => dry_run = true,
+            "--data-dir" => data_dir = args.next().map(PathBuf::from),
+            _ => {
+                eprintln!("{USAGE}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let Some(data_dir) = data_dir else {
+        eprintln!("no platform data directory; pass --data-dir");
+        return ExitCode::FAILURE;
+    };
+
+    let stores = migrate::legacy_stores();
+    if stores.is_empty() {
+        println!("no legacy stores found");
+        return ExitCode::SUCCESS;
+    }
+
+    let mut failed = 0;
+    for store in &stores {
+        let target = migrate::store_path(&data_dir, &store.name);
+        let session = if store.session_path.is_some() {
+            "with session"
+        } else {
+            "no session"
+        };
+        if dry_run {
+            println!(
+                "would move {} ({session}) -> {}",
+                store.db_path.display(),
+                target.display()
+            );
+            continue;
+        }
+        match migrate::migrate_store(store, &data_dir).await {
+            Ok(path) => println!(
+                "moved {} ({session}) -> {}",
+                store.db_path.display(),
+                path.display()
+            ),
+            Err(e) => {
+                failed += 1;
+                eprintln!("{}: {e}", store.db_path.display());
+            }
+        }
+    }
+    if failed > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
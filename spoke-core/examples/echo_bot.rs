@@ -0,0 +1,99 @@
+//! Echo bot: replies to every text message in joined rooms with the same
+//! text, and joins rooms it's invited to.
+//!
+//! With `SPOKE_RUN_SECS` set the bot stops after that long, so a CI job can
+//! start it, talk to it, and check it answered.
+//!
+//! Prerequisites:
+//!   docker compose -f infra/docker-compose.dev.yml up -d
+//!
+//! Run from the workspace root:
+//!   cargo run -p spoke-core --example echo_bot
+//!
+//! Env vars (all optional, shown with defaults):
+//!   SPOKE_HS         http://localhost:8448
+//!   SPOKE_USER       echo
+//!   SPOKE_PASS       echopass
+//!   SPOKE_RUN_SECS   unset (run until Ctrl-C)
+//!   RUST_LOG         spoke_core=debug,matrix_sdk=warn
+
+use std::{env, path::PathBuf, time::Duration};
+
+use matrix_sdk::{
+    Room, RoomState,
+    ruma::events::room::{
+        member::StrippedRoomMemberEvent,
+        message::{MessageType, OriginalSyncRoomMessageEvent, RoomMessageEventContent},
+    },
+};
+use spoke_core::matrix::SpokeClient;
+use tracing::{info, warn};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            env::var("RUST_LOG").unwrap_or_else(|_| "spoke_core=debug,matrix_sdk=warn".into()),
+        )
+        .init();
+
+    let homeserver = env::var("SPOKE_HS").unwrap_or_else(|_| "http://localhost:8448".into());
+    let username = env::var("SPOKE_USER").unwrap_or_else(|_| "echo".into());
+    let password = env::var("SPOKE_PASS").unwrap_or_else(|_| "echopass".into());
+    let run_for = env::var("SPOKE_RUN_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .map(Duration::from_secs);
+
+    let db_path = PathBuf::from(format!("/tmp/spoke-dev-{username}.db"));
+    let client = SpokeClient::new(&homeserver, &db_path).await?;
+    client.register(&username, &password).await?;
+    client.login(&username, &password).await?;
+
+    // Skip the backlog: only messages arriving after this sync get echoed.
+    client.inner.sync_once(Default::default()).await?;
+    let own_user_id = client.inner.user_id().ok_or("not logged in")?.to_owned();
+
+    client
+        .inner
+        .add_event_handler(move |event: OriginalSyncRoomMessageEvent, room: Room| {
+            let own_user_id = own_user_id.clone();
+            async move {
+                if room.state() != RoomState::Joined || event.sender == own_user_id {
+                    return;
+                }
+                let MessageType::Text(text) = event.content.msgtype else {
+                    return;
+                };
+                let reply = RoomMessageEventContent::text_plain(text.body);
+                if let Err(e) = room.send(reply).await {
+                    warn!("echo in {}: {e}", room.room_id());
+                }
+            }
+        });
+
+    client
+        .inner
+        .add_event_handler(|event: StrippedRoomMemberEvent, room: Room| async move {
+            if room.state() != RoomState::Invited
+                || Some(&*event.state_key) != room.client().user_id()
+            {
+                return;
+            }
+            info!("invited to {} — joining", room.room_id());
+            if let Err(e) = room.join().await {
+                warn!("join {}: {e}", room.room_id());
+            }
+        });
+
+    info!("echoing — Ctrl-C to stop");
+    match run_for {
+        Some(limit) => {
+            if let Ok(result) = tokio::time::timeout(limit, client.sync()).await {
+                result?;
+            }
+        }
+        None => client.sync().await?,
+    }
+    Ok(())
+}
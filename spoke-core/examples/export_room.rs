@@ -0,0 +1,118 @@
+//! Room export: page back through a room's history and write it out as
+//! JSON lines, oldest first, one message per line.
+//!
+//! Prerequisites:
+//!   docker compose -f infra/docker-compose.dev.yml up -d
+//!
+//! Run from the workspace root:
+//!   cargo run -p spoke-core --example export_room > room.jsonl
+//!
+//! Env vars (all optional, shown with defaults):
+//!   SPOKE_HS      http://localhost:8448
+//!   SPOKE_USER    alice
+//!   SPOKE_PASS    alicepass
+//!   SPOKE_ROOM    first joined room
+//!   SPOKE_LIMIT   1000 (events to read at most)
+//!   RUST_LOG      spoke_core=debug,matrix_sdk=warn
+
+use std::{env, path::PathBuf};
+
+use matrix_sdk::{
+    room::MessagesOptions,
+    ruma::{
+        OwnedRoomId, UInt,
+        events::{AnySyncMessageLikeEvent, AnySyncTimelineEvent, room::message::MessageType},
+    },
+};
+use spoke_core::matrix::SpokeClient;
+use tracing::{info, warn};
+
+/// Events asked for per `/messages` request.
+const PAGE: u32 = 100;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            env::var("RUST_LOG").unwrap_or_else(|_| "spoke_core=debug,matrix_sdk=warn".into()),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+
+    let homeserver = env::var("SPOKE_HS").unwrap_or_else(|_| "http://localhost:8448".into());
+    let username = env::var("SPOKE_USER").unwrap_or_else(|_| "alice".into());
+    let password = env::var("SPOKE_PASS").unwrap_or_else(|_| "alicepass".into());
+    let limit: usize = env::var("SPOKE_LIMIT")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(1000);
+
+    let db_path = PathBuf::from(format!("/tmp/spoke-dev-{username}.db"));
+    let client = SpokeClient::new(&homeserver, &db_path).await?;
+    client.register(&username, &password).await?;
+    client.login(&username, &password).await?;
+    client.inner.sync_once(Default::default()).await?;
+
+    let room = match env::var("SPOKE_ROOM") {
+        Ok(room) => {
+            let room_id: OwnedRoomId = room.parse()?;
+            client
+                .inner
+                .get_room(&room_id)
+                .ok_or("not a member of that room")?
+        }
+        Err(_) => client
+            .inner
+            .joined_rooms()
+            .into_iter()
+            .next()
+            .ok_or("no joined rooms")?,
+    };
+    info!("exporting {}", room.room_id());
+
+    // /messages pages newest first; collect, then print in order.
+    let mut lines = Vec::new();
+    let mut from: Option<String> = None;
+    let mut read = 0;
+    while read < limit {
+        let mut options = MessagesOptions::backward();
+        options.from = from.clone();
+        options.limit = UInt::from(PAGE.min((limit - read) as u32));
+        let page = room.messages(options).await?;
+        read += page.chunk.len();
+        for event in &page.chunk {
+            match event.raw().deserialize() {
+                Ok(AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomMessage(ev))) => {
+                    let Some(original) = ev.as_original() else {
+                        continue;
+                    };
+                    let (kind, body) = match &original.content.msgtype {
+                        MessageType::Text(text) => ("text", text.body.clone()),
+                        MessageType::Notice(notice) => ("notice", notice.body.clone()),
+                        MessageType::Emote(emote) => ("emote", emote.body.clone()),
+                        other => (other.msgtype(), other.body().to_owned()),
+                    };
+                    lines.push(serde_json::json!({
+                        "event_id": original.event_id,
+                        "sender": original.sender,
+                        "ts": original.origin_server_ts,
+                        "kind": kind,
+                        "body": body,
+                    }));
+                }
+                Ok(_) => {}
+                Err(e) => warn!("skipping unreadable event: {e}"),
+            }
+        }
+        match page.end {
+            Some(end) if !page.chunk.is_empty() => from = Some(end),
+            _ => break,
+        }
+    }
+
+    for line in lines.iter().rev() {
+        println!("{line}");
+    }
+    info!("exported {} messages from {read} events", lines.len());
+    Ok(())
+}
@@ -0,0 +1,176 @@
+//! E2EE device verification with emoji SAS: either request verification of
+//! another of our devices, or wait for one of them to ask, then compare the
+//! emojis and confirm.
+//!
+//! Log in with the same account elsewhere (another Spoke or Element) to have
+//! a device to verify against. `SPOKE_AUTO_CONFIRM` skips the prompt for
+//! scripted runs where both sides are known to match.
+//!
+//! Prerequisites:
+//!   docker compose -f infra/docker-compose.dev.yml up -d
+//!
+//! Run from the workspace root:
+//!   cargo run -p spoke-core --example verification
+//!
+//! Env vars (all optional, shown with defaults):
+//!   SPOKE_HS             http://localhost:8448
+//!   SPOKE_USER           alice
+//!   SPOKE_PASS           alicepass
+//!   SPOKE_VERIFY_DEVICE  unset (wait for a request instead)
+//!   SPOKE_AUTO_CONFIRM   unset (ask on stdin)
+//!   RUST_LOG             spoke_core=debug,matrix_sdk=warn
+
+use std::{env, path::PathBuf};
+
+use futures::StreamExt;
+use matrix_sdk::{
+    Client,
+    encryption::verification::{
+        SasState, SasVerification, Verification, VerificationRequest, VerificationRequestState,
+    },
+    ruma::{
+        OwnedDeviceId, events::key::verification::request::ToDeviceKeyVerificationRequestEvent,
+    },
+};
+use spoke_core::matrix::SpokeClient;
+use tokio::sync::mpsc;
+use tracing::info;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            env::var("RUST_LOG").unwrap_or_else(|_| "spoke_core=debug,matrix_sdk=warn".into()),
+        )
+        .init();
+
+    let homeserver = env::var("SPOKE_HS").unwrap_or_else(|_| "http://localhost:8448".into());
+    let username = env::var("SPOKE_USER").unwrap_or_else(|_| "alice".into());
+    let password = env::var("SPOKE_PASS").unwrap_or_else(|_| "alicepass".into());
+
+    let db_path = PathBuf::from(format!("/tmp/spoke-dev-{username}.db"));
+    let client = SpokeClient::new(&homeserver, &db_path).await?;
+    client.register(&username, &password).await?;
+    client.login(&username, &password).await?;
+
+    // Incoming requests are handed to main over a channel.
+    let (request_tx, mut request_rx) = mpsc::unbounded_channel();
+    client.inner.add_event_handler(
+        move |event: ToDeviceKeyVerificationRequestEvent, client: Client| {
+            let request_tx = request_tx.clone();
+            async move {
+                let request = client
+                    .encryption()
+                    .get_verification_request(&event.sender, &event.content.transaction_id)
+                    .await;
+                if let Some(request) = request {
+                    let _ = request_tx.send(request);
+                }
+            }
+        },
+    );
+
+    let sync_client = client.clone();
+    let sync = tokio::spawn(async move { sync_client.sync().await });
+    // Let the first sync land so our devices are known.
+    client.inner.sync_once(Default::default()).await?;
+
+    let own_user_id = client.inner.user_id().ok_or("not logged in")?.to_owned();
+    let result = match env::var("SPOKE_VERIFY_DEVICE") {
+        Ok(device_id) => {
+            let device_id: OwnedDeviceId = device_id.into();
+            let device = client
+                .inner
+                .encryption()
+                .get_device(&own_user_id, &device_id)
+                .await?
+                .ok_or("no such device on this account")?;
+            info!("requesting verification of {device_id}");
+            let request = device.request_verification().await?;
+            drive_request(request, true).await
+        }
+        Err(_) => {
+            println!("waiting for a verification request from another of our devices…");
+            let request = request_rx.recv().await.ok_or("event handler stopped")?;
+            request.accept().await?;
+            drive_request(request, false).await
+        }
+    };
+    sync.abort();
+    result
+}
+
+/// Follow a request until SAS starts, starting it ourselves if we asked.
+async fn drive_request(
+    request: VerificationRequest,
+    we_asked: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut changes = request.changes();
+    while let Some(state) = changes.next().await {
+        match state {
+            VerificationRequestState::Ready { .. } if we_asked => {
+                let sas = request
+                    .start_sas()
+                    .await?
+                    .ok_or("the other device can't do emoji SAS")?;
+                return drive_sas(sas).await;
+            }
+            VerificationRequestState::Transitioned {
+                verification: Verification::SasV1(sas),
+            } if !we_asked => {
+                sas.accept().await?;
+                return drive_sas(sas).await;
+            }
+            VerificationRequestState::Done => return Ok(()),
+            VerificationRequestState::Cancelled(info) => return Err(info.reason().into()),
+            _ => {}
+        }
+    }
+    Err("verification request ended early".into())
+}
+
+/// Show the emojis, confirm or reject them, and wait for the outcome.
+async fn drive_sas(sas: SasVerification) -> Result<(), Box<dyn std::error::Error>> {
+    let mut changes = sas.changes();
+    while let Some(state) = changes.next().await {
+        match state {
+            SasState::KeysExchanged { emojis, decimals } => {
+                match emojis {
+                    Some(emojis) => {
+                        for emoji in emojis.emojis {
+                            print!("{} {}   ", emoji.symbol, emoji.description);
+                        }
+                        println!();
+                    }
+                    None => println!("{} {} {}", decimals.0, decimals.1, decimals.2),
+                }
+                if confirmed().await? {
+                    sas.confirm().await?;
+                } else {
+                    sas.mismatch().await?;
+                }
+            }
+            SasState::Done { .. } => {
+                println!("verified {}", sas.other_device().device_id());
+                return Ok(());
+            }
+            SasState::Cancelled(info) => return Err(info.reason().into()),
+            _ => {}
+        }
+    }
+    Err("verification ended early".into())
+}
+
+/// Whether the emojis match, from `SPOKE_AUTO_CONFIRM` or a stdin prompt.
+async fn confirmed() -> Result<bool, Box<dyn std::error::Error>> {
+    if env::var_os("SPOKE_AUTO_CONFIRM").is_some() {
+        return Ok(true);
+    }
+    println!("Do these match the other device? [y/N]");
+    let answer = tokio::task::spawn_blocking(|| {
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line).map(|_| line)
+    })
+    .await??;
+    Ok(answer.trim().eq_ignore_ascii_case("y"))
+}
@@ -0,0 +1,122 @@
+//! Voice-only join: log in, ask the sidecar for a LiveKit grant for a room,
+//! connect, and print voice events until the time is up.
+//!
+//! Nothing is sent to the room's timeline, so this exercises the voice path
+//! on its own. Exits non-zero if the grant or the connection fails.
+//!
+//! Prerequisites:
+//!   docker compose -f infra/docker-compose.dev.yml up -d
+//!   cargo run -p spoke-sidecar
+//!
+//! Run from the workspace root:
+//!   cargo run -p spoke-core --example voice
+//!
+//! Env vars (all optional, shown with defaults):
+//!   SPOKE_HS         http://localhost:8448
+//!   SPOKE_USER       alice
+//!   SPOKE_PASS       alicepass
+//!   SPOKE_SIDECAR    http://localhost:8090
+//!   SPOKE_ROOM       first joined room
+//!   SPOKE_LISTEN     unset; set to join without opening the mic
+//!   SPOKE_RUN_SECS   10
+//!   RUST_LOG         spoke_core=debug,matrix_sdk=warn
+
+use std::{env, path::PathBuf, time::Duration};
+
+use matrix_sdk::ruma::{OwnedRoomId, api::client::room::create_room};
+use spoke_core::{
+    matrix::SpokeClient,
+    voice::{ConnectOptions, VoiceEvent, VoiceSession},
+};
+use tokio::sync::mpsc;
+use tracing::info;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            env::var("RUST_LOG").unwrap_or_else(|_| "spoke_core=debug,matrix_sdk=warn".into()),
+        )
+        .init();
+
+    let homeserver = env::var("SPOKE_HS").unwrap_or_else(|_| "http://localhost:8448".into());
+    let username = env::var("SPOKE_USER").unwrap_or_else(|_| "alice".into());
+    let password = env::var("SPOKE_PASS").unwrap_or_else(|_| "alicepass".into());
+    let sidecar = env::var("SPOKE_SIDECAR").unwrap_or_else(|_| "http://localhost:8090".into());
+    let run_for = Duration::from_secs(
+        env::var("SPOKE_RUN_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(10),
+    );
+    let publish = env::var_os("SPOKE_LISTEN").is_none();
+
+    let db_path = PathBuf::from(format!("/tmp/spoke-dev-{username}.db"));
+    let client = SpokeClient::new(&homeserver, &db_path).await?;
+    client.register(&username, &password).await?;
+    client.login(&username, &password).await?;
+    client.inner.sync_once(Default::default()).await?;
+
+    let room_id: OwnedRoomId = match env::var("SPOKE_ROOM") {
+        Ok(room) => room.parse()?,
+        Err(_) => match client.inner.joined_rooms().into_iter().next() {
+            Some(room) => room.room_id().to_owned(),
+            None => {
+                info!("no rooms — creating one to talk in");
+                let room = client
+                    .inner
+                    .create_room(create_room::v3::Request::new())
+                    .await?;
+                room.room_id().to_owned()
+            }
+        },
+    };
+
+    // The sidecar checks our Matrix token and membership, then signs a
+    // LiveKit token for the room.
+    let access_token = client.inner.access_token().ok_or("not logged in")?;
+    info!("requesting a voice grant for {room_id}");
+    let grant: serde_json::Value = reqwest::Client::new()
+        .post(format!("{sidecar}/_spoke/v1/voice/token"))
+        .bearer_auth(access_token)
+        .json(&serde_json::json!({ "room_id": room_id }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let url = grant["livekit_url"]
+        .as_str()
+        .ok_or("grant without livekit_url")?;
+    let token = grant["livekit_token"]
+        .as_str()
+        .ok_or("grant without livekit_token")?;
+
+    let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+    let options = ConnectOptions {
+        publish,
+        ..Default::default()
+    };
+    let session = VoiceSession::connect(url, token, options, event_tx).await?;
+    info!(
+        "connected to {url}; publishing: {}",
+        session.is_publishing()
+    );
+
+    let deadline = tokio::time::sleep(run_for);
+    tokio::pin!(deadline);
+    loop {
+        tokio::select! {
+            () = &mut deadline => break,
+            event = event_rx.recv() => match event {
+                Some(VoiceEvent::Error(e)) => eprintln!("voice error: {e}"),
+                Some(event) => println!("{event:?}"),
+                None => break,
+            },
+        }
+    }
+
+    println!("participants at exit: {:?}", session.participants());
+    session.disconnect().await;
+    Ok(())
+}
@@ -1,3 +1,4 @@
 pub mod matrix;
-pub mod voice;
+pub mod proxy;
 pub mod state;
+pub mod voice;
@@ -0,0 +1,68 @@
+// Roaming settings — the app's preferences kept in `org.spoke.settings`
+// account data, so they follow the user to every device they sign in on.
+//
+// The content is opaque here: `shared` holds whatever the app wants every
+// device to agree on, and `machines` holds per-machine sections (audio
+// devices differ from one computer to the next), keyed by an ID each
+// installation makes up for itself. `updated_ts` lets a device tell whether
+// its own copy or the account's is newer.
+
+use std::collections::BTreeMap;
+
+use matrix_sdk::ruma::events::macros::EventContent;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, EventContent)]
+#[ruma_event(type = "org.spoke.settings", kind = GlobalAccountData)]
+pub struct AccountSettingsEventContent {
+    /// Settings every device shares.
+    #[serde(default)]
+    pub shared: serde_json::Value,
+    /// Per-machine settings, by machine ID.
+    #[serde(default)]
+    pub machines: BTreeMap<String, serde_json::Value>,
+    /// When these were last changed, in milliseconds since the Unix epoch.
+    #[serde(default)]
+    pub updated_ts: u64,
+}
+
+impl SpokeClient {
+    /// Our roaming settings, if any device has stored readable ones.
+    pub async fn account_settings(
+        &self,
+    ) -> Result<Option<AccountSettingsEventContent>, MatrixError> {
+        let Some(raw) = self
+            .inner
+            .account()
+            .account_data::<AccountSettingsEventContent>()
+            .await?
+        else {
+            return Ok(None);
+        };
+        match raw.deserialize() {
+            Ok(content) => Ok(Some(content)),
+            Err(e) => {
+                warn!("unreadable roaming settings: {e}");
+                Ok(None)
+            }
+        }
+    }
+
+    /// Replace our roaming settings.
+    pub async fn set_account_settings(
+        &self,
+        content: AccountSettingsEventContent,
+    ) -> Result<(), MatrixError> {
+        self.scheduled("save settings", || async {
+            self.inner
+                .account()
+                .set_account_data(content.clone())
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+}
@@ -0,0 +1,122 @@
+// Server capabilities — spec versions and `/capabilities`, fetched once after
+// login so features the server lacks can be hidden or refused with a clear
+// message instead of failing with an opaque server error.
+
+use std::collections::BTreeMap;
+
+use matrix_sdk::ruma::{
+    RoomVersionId,
+    api::client::discovery::{
+        get_capabilities::v3::{self as get_capabilities, RoomVersionStability},
+        get_supported_versions,
+    },
+};
+use tracing::warn;
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+/// What the logged-in homeserver supports.
+#[derive(Debug, Clone, Default)]
+pub struct ServerCapabilities {
+    /// Spec versions from `/versions`, as advertised.
+    pub versions: Vec<String>,
+    pub unstable_features: BTreeMap<String, bool>,
+    /// Room version used for new rooms unless the creator asks otherwise.
+    pub default_room_version: Option<RoomVersionId>,
+    /// Room versions the server marks stable, oldest first.
+    pub stable_room_versions: Vec<RoomVersionId>,
+    pub change_password: bool,
+    pub set_displayname: bool,
+    pub set_avatar_url: bool,
+}
+
+impl ServerCapabilities {
+    /// Whether any advertised spec version is `v1.minor` or newer.
+    pub fn supports_spec(&self, minor: u32) -> bool {
+        self.versions.iter().any(|v| {
+            v.strip_prefix("v1.")
+                .and_then(|m| m.parse::<u32>().ok())
+                .is_some_and(|m| m >= minor)
+        })
+    }
+
+    /// Threads (`m.thread` relations and `/threads`): spec v1.4, or the
+    /// stable flag of MSC3440 on older servers.
+    pub fn threads(&self) -> bool {
+        self.supports_spec(4)
+            || self.unstable_features.get("org.matrix.msc3440.stable") == Some(&true)
+    }
+
+    /// Knocking needs the `/knock` endpoint (v1.1) and a room version that
+    /// knows the `knock` join rule (7 or later) to create knockable rooms in.
+    pub fn knocking(&self) -> bool {
+        self.supports_spec(1) && self.stable_room_versions.iter().any(supports_knock)
+    }
+
+    /// Whether new rooms may be created with `version`.
+    pub fn can_create_room_version(&self, version: &RoomVersionId) -> bool {
+        // Without `/capabilities` we can't tell; let the server decide.
+        self.stable_room_versions.is_empty() || self.stable_room_versions.contains(version)
+    }
+
+    /// Newest stable room version supporting knocks, for rooms that need them.
+    pub fn knock_room_version(&self) -> Option<&RoomVersionId> {
+        self.stable_room_versions
+            .iter()
+            .rev()
+            .find(|v| supports_knock(v))
+    }
+}
+
+impl SpokeClient {
+    /// Ask the server what it supports and remember the answer. A server
+    /// without `/capabilities` gets the spec's defaults for it.
+    pub async fn fetch_capabilities(&self) -> Result<ServerCapabilities, MatrixError> {
+        let versions = self
+            .inner
+            .send(get_supported_versions::Request::new(), None)
+            .await?;
+        let mut capabilities = ServerCapabilities {
+            versions: versions.versions,
+            unstable_features: versions.unstable_features,
+            change_password: true,
+            set_displayname: true,
+            set_avatar_url: true,
+            ..Default::default()
+        };
+        match self
+            .inner
+            .send(get_capabilities::Request::new(), None)
+            .await
+        {
+            Ok(response) => {
+                let caps = response.capabilities;
+                capabilities.default_room_version = Some(caps.room_versions.default);
+                let mut stable: Vec<RoomVersionId> = caps
+                    .room_versions
+                    .available
+                    .into_iter()
+                    .filter(|(_, stability)| *stability == RoomVersionStability::Stable)
+                    .map(|(version, _)| version)
+                    .collect();
+                stable.sort_by_key(|v| v.as_str().parse::<u32>().unwrap_or(u32::MAX));
+                capabilities.stable_room_versions = stable;
+                capabilities.change_password = caps.change_password.enabled;
+                capabilities.set_displayname = caps.set_displayname.enabled;
+                capabilities.set_avatar_url = caps.set_avatar_url.enabled;
+            }
+            Err(e) => warn!("/capabilities unavailable, assuming defaults: {e}"),
+        }
+        *self.capabilities.lock().unwrap() = Some(capabilities.clone());
+        Ok(capabilities)
+    }
+
+    /// The capabilities from the last `fetch_capabilities`, if any.
+    pub fn capabilities(&self) -> Option<ServerCapabilities> {
+        self.capabilities.lock().unwrap().clone()
+    }
+}
+
+fn supports_knock(version: &RoomVersionId) -> bool {
+    version.as_str().parse::<u32>().is_ok_and(|v| v >= 7)
+}
@@ -1,22 +1,63 @@
-use std::path::{Path, PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use matrix_sdk::{
     AuthSession, Client,
     config::SyncSettings,
+    crypto::CollectStrategy,
+    encryption::{BackupDownloadStrategy, EncryptionSettings},
     matrix_auth::MatrixSession,
     ruma::{
         UserId,
         api::client::{account::register::v3 as register, uiaa::AuthData},
     },
 };
+use serde::{Deserialize, Serialize};
 use tracing::{info, warn};
 
-use crate::matrix::error::MatrixError;
+use crate::{
+    matrix::{
+        SyncFilterOptions, capabilities::ServerCapabilities, error::MatrixError,
+        rate_limit::RateLimiter, utd::UtdTracker,
+    },
+    proxy,
+};
 
 /// Spoke's handle to a Matrix session.
+///
+/// Cheap to clone — clones share the same underlying `Client`.
+#[derive(Clone)]
 pub struct SpokeClient {
     pub inner: Client,
     db_path: PathBuf,
+    /// When the current access token expires, if the server said.
+    token_expires: Arc<Mutex<Option<SystemTime>>>,
+    /// Filled in by `fetch_capabilities` after login.
+    pub(crate) capabilities: Arc<Mutex<Option<ServerCapabilities>>>,
+    pub(crate) rate_limit: RateLimiter,
+    /// Events waiting for their room keys.
+    pub(crate) utd: UtdTracker,
+    pub(crate) options: ClientOptions,
+}
+
+/// Choices fixed when the client is built.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClientOptions {
+    /// Share room keys only with devices their owner has verified, so
+    /// unverified sessions can't read what we send.
+    pub verified_devices_only: bool,
+}
+
+/// The session file: the SDK's session plus when its access token expires.
+#[derive(Serialize, Deserialize)]
+struct SavedSession {
+    #[serde(flatten)]
+    session: MatrixSession,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    access_token_expires_ms: Option<u64>,
 }
 
 impl SpokeClient {
@@ -24,23 +65,52 @@ impl SpokeClient {
     /// `db_path` (a directory — matrix-sdk creates SQLite files inside it).
     ///
     /// If the crypto store directory exists but no session file is present
-    /// the state is inconsistent (e.g. after a code update that added session
-    /// persistence). The stale store is wiped so the next login is clean.
+    /// the store belongs to a logged-out session (or predates session
+    /// persistence). It's set aside if `logout` was asked to keep it, and
+    /// wiped otherwise, so the next login is clean.
     pub async fn new(homeserver_url: &str, db_path: &Path) -> Result<Self, MatrixError> {
+        Self::with_options(homeserver_url, db_path, ClientOptions::default()).await
+    }
+
+    /// `new`, with `options`.
+    pub async fn with_options(
+        homeserver_url: &str,
+        db_path: &Path,
+        options: ClientOptions,
+    ) -> Result<Self, MatrixError> {
         let session_path = Self::session_path_for(db_path);
 
         if db_path.exists() && !session_path.exists() {
-            warn!("crypto store present but no session file — wiping stale store");
-            let _ = std::fs::remove_dir_all(db_path);
+            Self::retire_store(db_path);
         }
 
+        // With refresh tokens, requests failing with M_UNKNOWN_TOKEN are
+        // retried after a refresh instead of ending the session.
         let client = Client::builder()
             .homeserver_url(homeserver_url)
+            .http_client(proxy::http_client()?)
             .sqlite_store(db_path, None)
+            .handle_refresh_tokens()
+            // Missing room keys are fetched from key backup when needed.
+            .with_encryption_settings(EncryptionSettings {
+                backup_download_strategy: BackupDownloadStrategy::AfterDecryptionFailure,
+                ..Default::default()
+            })
+            .with_room_key_recipient_strategy(CollectStrategy::new_device_based(
+                options.verified_devices_only,
+            ))
             .build()
             .await?;
 
-        Ok(Self { inner: client, db_path: db_path.to_owned() })
+        Ok(Self {
+            inner: client,
+            db_path: db_path.to_owned(),
+            token_expires: Arc::default(),
+            capabilities: Arc::default(),
+            rate_limit: RateLimiter::default(),
+            utd: UtdTracker::default(),
+            options,
+        })
     }
 
     /// Restore a previous session or perform a fresh password login.
@@ -54,47 +124,53 @@ impl SpokeClient {
             return Ok(());
         }
 
-        let session_path = Self::session_path_for(&self.db_path);
-
         // Try to restore a saved session first.
-        if let Some(session) = Self::load_session(&session_path) {
-            match self.inner.restore_session(session).await {
-                Ok(()) => {
-                    info!("session restored from {session_path:?}");
-                    return Ok(());
-                }
-                Err(e) => {
-                    // Stale session (token expired, server wiped, etc).
-                    // Delete it and fall through to fresh login.
-                    warn!("session restore failed ({e}), doing fresh login");
-                    let _ = std::fs::remove_file(&session_path);
-                }
-            }
+        if self.restore_session().await {
+            return Ok(());
         }
 
         // Fresh password login.
         let mxid = self.full_mxid(username);
-        let user_id = UserId::parse(&mxid)
-            .map_err(|e| MatrixError::InvalidUserId(e.to_string()))?;
+        let user_id =
+            UserId::parse(&mxid).map_err(|e| MatrixError::InvalidUserId(e.to_string()))?;
 
-        self.inner
+        let response = self
+            .inner
             .matrix_auth()
             .login_username(user_id, password)
             .initial_device_display_name("Spoke")
+            .request_refresh_token()
             .send()
             .await?;
 
         info!("logged in as {mxid}");
+        self.set_token_lifetime(response.expires_in);
+        self.save_session();
+        Ok(())
+    }
 
-        // Persist the session so the next startup can restore it.
-        if let Some(AuthSession::Matrix(session)) = self.inner.session() {
-            match serde_json::to_string(&session) {
-                Ok(json) => { let _ = std::fs::write(&session_path, json); }
-                Err(e) => warn!("failed to serialise session: {e}"),
+    /// Restore the session saved by a previous login, if there is one and
+    /// the server still accepts it. A stale session file is deleted.
+    pub async fn restore_session(&self) -> bool {
+        let session_path = Self::session_path_for(&self.db_path);
+        let Some(saved) = Self::load_session(&session_path) else {
+            return false;
+        };
+        match self.inner.restore_session(saved.session).await {
+            Ok(()) => {
+                info!("session restored from {session_path:?}");
+                *self.token_expires.lock().unwrap() = saved
+                    .access_token_expires_ms
+                    .map(|ms| UNIX_EPOCH + Duration::from_millis(ms));
+                true
+            }
+            Err(e) => {
+                // Stale session (token expired, server wiped, etc).
+                warn!("session restore failed ({e}), doing fresh login");
+                let _ = std::fs::remove_file(&session_path);
+                false
             }
         }
-
-        Ok(())
     }
 
     /// Register a new account. Returns Ok(()) if the user already exists.
@@ -105,10 +181,11 @@ impl SpokeClient {
         req.auth = Some(AuthData::Dummy(Default::default()));
 
         match self.inner.matrix_auth().register(req).await {
-            Ok(_) => { info!("registered {username}"); Ok(()) }
-            Err(matrix_sdk::Error::Http(ref e))
-                if e.to_string().contains("M_USER_IN_USE") =>
-            {
+            Ok(_) => {
+                info!("registered {username}");
+                Ok(())
+            }
+            Err(matrix_sdk::Error::Http(ref e)) if e.to_string().contains("M_USER_IN_USE") => {
                 info!("{username} already registered");
                 Ok(())
             }
@@ -116,10 +193,40 @@ impl SpokeClient {
         }
     }
 
-    /// Run the Matrix sync loop. Blocks until the client stops.
-    /// Run on a dedicated tokio task.
+    /// Log out on the server and drop the local session file.
+    ///
+    /// The stores are tied to this device, which no longer exists afterwards.
+    /// With `keep_crypto` they're set aside as `<store>.<device id>` so the
+    /// device's keys can still be exported; otherwise they're deleted. This
+    /// client keeps the store open until it's dropped, so neither happens
+    /// here: the next client built on the same path does it before opening
+    /// the store. Either way the next login starts from a fresh store.
+    pub async fn logout(&self, keep_crypto: bool) -> Result<(), MatrixError> {
+        let device_id = self.inner.device_id().map(|d| d.to_string());
+        // An expired or revoked token still needs the local cleanup.
+        if let Err(e) = self.inner.matrix_auth().logout().await {
+            warn!("server logout failed: {e}");
+        }
+        *self.token_expires.lock().unwrap() = None;
+
+        if let (Some(device_id), true) = (&device_id, keep_crypto) {
+            std::fs::write(Self::keep_path_for(&self.db_path), device_id)?;
+        }
+        let session_path = Self::session_path_for(&self.db_path);
+        if session_path.exists() {
+            std::fs::remove_file(&session_path)?;
+        }
+        info!("logged out");
+        Ok(())
+    }
+
+    /// Run the Matrix sync loop with the default filter. Blocks until the
+    /// client stops. Run on a dedicated tokio task.
     pub async fn sync(&self) -> Result<(), MatrixError> {
-        self.inner.sync(SyncSettings::default()).await?;
+        let filter = self.sync_filter(&SyncFilterOptions::default()).await?;
+        self.inner
+            .sync(SyncSettings::default().filter(filter))
+            .await?;
         Ok(())
     }
 
@@ -129,17 +236,92 @@ impl SpokeClient {
         db_path.with_extension("session.json")
     }
 
-    fn load_session(path: &Path) -> Option<MatrixSession> {
+    /// Left by `logout` with `keep_crypto`: the device ID to set the store
+    /// aside under.
+    fn keep_path_for(db_path: &Path) -> PathBuf {
+        db_path.with_extension("keep")
+    }
+
+    /// Set aside or wipe the store of a logged-out session. Only called
+    /// before a client opens it.
+    fn retire_store(db_path: &Path) {
+        let keep_path = Self::keep_path_for(db_path);
+        let device_id = std::fs::read_to_string(&keep_path).ok();
+        let _ = std::fs::remove_file(&keep_path);
+        let result = match device_id.as_deref().map(str::trim) {
+            Some(device_id) => {
+                let mut aside = db_path.as_os_str().to_owned();
+                aside.push(format!(".{}", Self::aside_suffix(device_id)));
+                info!("keeping logged-out store at {aside:?}");
+                std::fs::rename(db_path, aside)
+            }
+            None => {
+                warn!("crypto store present but no session file — wiping stale store");
+                std::fs::remove_dir_all(db_path)
+            }
+        };
+        if let Err(e) = result {
+            warn!("retiring store at {db_path:?}: {e}");
+        }
+    }
+
+    /// What to name a store set aside for `device_id`: the device ID itself
+    /// if it's safe in a file name, else the current time, so a keep file
+    /// can't send the store anywhere but next to itself.
+    fn aside_suffix(device_id: &str) -> String {
+        let safe = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+        if !device_id.is_empty() && device_id.chars().all(safe) {
+            return device_id.to_owned();
+        }
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        secs.to_string()
+    }
+
+    /// Persist the session (including any refresh token) so the next
+    /// startup can restore it.
+    pub(crate) fn save_session(&self) {
+        let session_path = Self::session_path_for(&self.db_path);
+        if let Some(AuthSession::Matrix(session)) = self.inner.session() {
+            let saved = SavedSession {
+                session,
+                access_token_expires_ms: self
+                    .token_expires()
+                    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                    .map(|d| d.as_millis() as u64),
+            };
+            match serde_json::to_string(&saved) {
+                Ok(json) => {
+                    let _ = std::fs::write(&session_path, json);
+                }
+                Err(e) => warn!("failed to serialise session: {e}"),
+            }
+        }
+    }
+
+    fn load_session(path: &Path) -> Option<SavedSession> {
         let json = std::fs::read_to_string(path).ok()?;
         serde_json::from_str(&json).ok()
     }
 
+    pub(crate) fn token_expires(&self) -> Option<SystemTime> {
+        *self.token_expires.lock().unwrap()
+    }
+
+    /// Record a new access token's lifetime, as given by the server.
+    pub(crate) fn set_token_lifetime(&self, lifetime: Option<Duration>) {
+        *self.token_expires.lock().unwrap() = lifetime.map(|l| SystemTime::now() + l);
+    }
+
     /// Build a full MXID from a bare username using the homeserver's host.
     fn full_mxid(&self, username: &str) -> String {
         if username.starts_with('@') {
             return username.to_owned();
         }
-        let server = self.inner.homeserver()
+        let server = self
+            .inner
+            .homeserver()
             .host_str()
             .unwrap_or("localhost")
             .to_owned();
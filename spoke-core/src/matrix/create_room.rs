@@ -0,0 +1,217 @@
+// Room creation from templates — the handful of room shapes Spoke offers
+// (private chat, public community, voice channel, space), each mapped onto
+// the spec's creation presets plus whatever state Spoke reads back later.
+//
+// Voice channels carry `VOICE_CHANNEL_ROOM_TYPE` in their create event, which
+// is how the space tree and the voice UI tell them apart; it can't be added
+// afterwards. Every room that can hold a call also lowers the call
+// membership events to the default user level, since as state events they'd
+// otherwise need moderator rights and invitees couldn't join.
+//
+// Creating the room is the only step that has to succeed; settling its power
+// levels and listing it in a space are best-effort, so a room that already
+// exists isn't reported as a failure.
+
+use matrix_sdk::ruma::{
+    OwnedRoomId, OwnedUserId, RoomVersionId,
+    api::client::room::{
+        Visibility,
+        create_room::v3::{CreationContent, Request as CreateRoomRequest, RoomPreset},
+    },
+    events::{
+        InitialStateEvent,
+        room::encryption::RoomEncryptionEventContent,
+        space::{child::SpaceChildEventContent, parent::SpaceParentEventContent},
+    },
+    room::RoomType,
+    serde::Raw,
+};
+use tracing::warn;
+
+use crate::{
+    matrix::{PowerLevelChange, SpokeClient, error::MatrixError},
+    voice::events::VOICE_CHANNEL_ROOM_TYPE,
+};
+
+/// The kinds of room the Create Room dialog offers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoomTemplate {
+    /// Invite-only, encrypted by default.
+    PrivateChat,
+    /// Anyone can join, and it's listed in the server's directory.
+    PublicCommunity,
+    /// An invite-only room marked as a Spoke voice channel.
+    VoiceChannel,
+    /// An invite-only space to group rooms under.
+    Space,
+}
+
+impl RoomTemplate {
+    pub const ALL: [RoomTemplate; 4] = [
+        RoomTemplate::PrivateChat,
+        RoomTemplate::PublicCommunity,
+        RoomTemplate::VoiceChannel,
+        RoomTemplate::Space,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            RoomTemplate::PrivateChat => "Private chat",
+            RoomTemplate::PublicCommunity => "Public community room",
+            RoomTemplate::VoiceChannel => "Voice channel",
+            RoomTemplate::Space => "Space",
+        }
+    }
+
+    /// Whether new rooms of this kind start out encrypted. Public rooms
+    /// don't: anyone can join and read them anyway, and history stays
+    /// readable to newcomers.
+    pub fn encrypted_by_default(self) -> bool {
+        matches!(self, RoomTemplate::PrivateChat | RoomTemplate::VoiceChannel)
+    }
+
+    /// Whether rooms of this kind can be encrypted at all; a space only
+    /// holds links to other rooms.
+    pub fn can_encrypt(self) -> bool {
+        self != RoomTemplate::Space
+    }
+}
+
+/// A room to create.
+#[derive(Debug, Clone)]
+pub struct NewRoom {
+    pub name: String,
+    pub template: RoomTemplate,
+    /// Ignored for spaces.
+    pub encrypted: bool,
+    pub topic: Option<String>,
+    /// Local part of the room's alias, e.g. `general` for `#general:server`.
+    pub alias: Option<String>,
+    pub invite: Vec<OwnedUserId>,
+    /// `None` leaves the choice to the server.
+    pub room_version: Option<RoomVersionId>,
+    /// A space to list the new room in.
+    pub space: Option<OwnedRoomId>,
+}
+
+impl NewRoom {
+    /// A room named `name` with `template`'s defaults.
+    pub fn new(name: String, template: RoomTemplate) -> Self {
+        Self {
+            name,
+            template,
+            encrypted: template.encrypted_by_default(),
+            topic: None,
+            alias: None,
+            invite: Vec::new(),
+            room_version: None,
+            space: None,
+        }
+    }
+}
+
+impl SpokeClient {
+    /// Create `new`, returning its ID.
+    pub async fn create_room(&self, new: &NewRoom) -> Result<OwnedRoomId, MatrixError> {
+        let mut request = CreateRoomRequest::new();
+        request.name = Some(new.name.trim().to_owned());
+        request.topic = new
+            .topic
+            .as_deref()
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(str::to_owned);
+        request.room_alias_name = new
+            .alias
+            .as_deref()
+            .map(|a| a.trim().trim_start_matches('#'))
+            .map(|a| a.split(':').next().unwrap_or(a).to_owned())
+            .filter(|a| !a.is_empty());
+        request.invite = new.invite.clone();
+
+        if let Some(version) = &new.room_version {
+            if self
+                .capabilities()
+                .is_some_and(|c| !c.can_create_room_version(version))
+            {
+                return Err(MatrixError::Forbidden(format!(
+                    "this server doesn't support room version {version}"
+                )));
+            }
+            request.room_version = Some(version.clone());
+        }
+
+        let public = new.template == RoomTemplate::PublicCommunity;
+        request.preset = Some(if public {
+            RoomPreset::PublicChat
+        } else {
+            RoomPreset::PrivateChat
+        });
+        if public {
+            request.visibility = Visibility::Public;
+        }
+
+        let room_type = match new.template {
+            RoomTemplate::VoiceChannel => Some(RoomType::from(VOICE_CHANNEL_ROOM_TYPE)),
+            RoomTemplate::Space => Some(RoomType::Space),
+            RoomTemplate::PrivateChat | RoomTemplate::PublicCommunity => None,
+        };
+        if room_type.is_some() {
+            let mut creation = CreationContent::new();
+            creation.room_type = room_type;
+            request.creation_content = Some(Raw::new(&creation).map_err(matrix_sdk::Error::from)?);
+        }
+
+        if new.encrypted && new.template.can_encrypt() {
+            request.initial_state.push(
+                InitialStateEvent::new(RoomEncryptionEventContent::with_recommended_defaults())
+                    .to_raw_any(),
+            );
+        }
+        if let Some(space) = &new.space {
+            let via = self.own_user_id()?.server_name().to_owned();
+            let mut parent = SpaceParentEventContent::new(vec![via]);
+            parent.canonical = true;
+            let event = InitialStateEvent {
+                content: parent,
+                state_key: space.clone(),
+            };
+            request.initial_state.push(event.to_raw_any());
+        }
+
+        let room = self.inner.create_room(request).await?;
+        let room_id = room.room_id().to_owned();
+
+        if new.template != RoomTemplate::Space {
+            if let Err(e) = self
+                .set_power_levels(&room_id, PowerLevelChange::Voice(0))
+                .await
+            {
+                warn!("new room {room_id}: couldn't open voice to everyone: {e}");
+            }
+        }
+        if let Some(space_id) = &new.space {
+            if let Err(e) = self.add_to_space(space_id, &room_id).await {
+                warn!("new room {room_id}: couldn't list it in {space_id}: {e}");
+            }
+        }
+        Ok(room_id)
+    }
+
+    /// List `room_id` as a child of `space_id`, reachable through our server.
+    async fn add_to_space(
+        &self,
+        space_id: &OwnedRoomId,
+        room_id: &OwnedRoomId,
+    ) -> Result<(), MatrixError> {
+        let space = self
+            .inner
+            .get_room(space_id)
+            .ok_or_else(|| MatrixError::NotFound(space_id.to_string()))?;
+        let via = self.own_user_id()?.server_name().to_owned();
+        space
+            .send_state_event_for_key(room_id, SpaceChildEventContent::new(vec![via]))
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,94 @@
+// Devices (sessions) on our account — list them with where and when they were
+// last seen, rename our own, and sign out others. Deleting devices needs
+// user-interactive auth; we answer the password stage with the password the
+// user confirms in the UI.
+
+use matrix_sdk::ruma::{
+    DeviceId, OwnedDeviceId,
+    api::client::uiaa::{AuthData, Password, UserIdentifier},
+};
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+/// One of our account's devices.
+#[derive(Debug, Clone)]
+pub struct DeviceInfo {
+    pub device_id: String,
+    pub display_name: Option<String>,
+    pub last_seen_ip: Option<String>,
+    /// Milliseconds since the epoch.
+    pub last_seen_ts: Option<u64>,
+    /// This is the device we're running as.
+    pub current: bool,
+}
+
+impl SpokeClient {
+    /// All devices on our account, ours first, then most recently seen.
+    pub async fn devices(&self) -> Result<Vec<DeviceInfo>, MatrixError> {
+        let own = self.inner.device_id();
+        let mut devices: Vec<DeviceInfo> = self
+            .inner
+            .devices()
+            .await?
+            .devices
+            .into_iter()
+            .map(|d| DeviceInfo {
+                current: Some(&*d.device_id) == own,
+                device_id: d.device_id.to_string(),
+                display_name: d.display_name,
+                last_seen_ip: d.last_seen_ip,
+                last_seen_ts: d.last_seen_ts.map(|ts| u64::from(ts.0)),
+            })
+            .collect();
+        devices.sort_by(|a, b| {
+            b.current
+                .cmp(&a.current)
+                .then(b.last_seen_ts.cmp(&a.last_seen_ts))
+        });
+        Ok(devices)
+    }
+
+    /// Rename the device we're running as.
+    pub async fn rename_own_device(&self, name: &str) -> Result<(), MatrixError> {
+        let own = self
+            .inner
+            .device_id()
+            .ok_or_else(|| MatrixError::NotFound("own device".into()))?;
+        self.inner.rename_device(own, name).await?;
+        Ok(())
+    }
+
+    /// Sign out `device_ids`, confirming with our account `password`. Our own
+    /// device is refused — logging out is how that one goes away.
+    pub async fn delete_devices(
+        &self,
+        device_ids: &[OwnedDeviceId],
+        password: &str,
+    ) -> Result<(), MatrixError> {
+        let own: Option<&DeviceId> = self.inner.device_id();
+        if device_ids.iter().any(|id| Some(&**id) == own) {
+            return Err(MatrixError::Forbidden(
+                "cannot delete the current device".into(),
+            ));
+        }
+        let user_id = self.own_user_id()?;
+
+        // The first request, without auth, tells us the UIAA session to use.
+        let info = match self.inner.delete_devices(device_ids, None).await {
+            Ok(_) => return Ok(()),
+            Err(e) => match e.as_uiaa_response() {
+                Some(info) => info.clone(),
+                None => return Err(e.into()),
+            },
+        };
+        let mut auth = Password::new(
+            UserIdentifier::UserIdOrLocalpart(user_id.to_string()),
+            password.to_owned(),
+        );
+        auth.session = info.session;
+        self.inner
+            .delete_devices(device_ids, Some(AuthData::Password(auth)))
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,25 @@
+// Direct messages — one-to-one rooms tracked in `m.direct` account data.
+
+use matrix_sdk::ruma::{OwnedRoomId, UserId};
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+impl SpokeClient {
+    /// The DM room with `user_id`, creating it if there is none yet.
+    ///
+    /// New rooms are created with `is_direct` and an invite for `user_id`;
+    /// the SDK records them in our `m.direct` account data so other clients
+    /// list them as DMs too.
+    pub async fn start_direct_message(&self, user_id: &UserId) -> Result<OwnedRoomId, MatrixError> {
+        if self.inner.user_id() == Some(user_id) {
+            return Err(MatrixError::Forbidden(
+                "can't start a direct message with yourself".into(),
+            ));
+        }
+        if let Some(room) = self.inner.get_dm_room(user_id) {
+            return Ok(room.room_id().to_owned());
+        }
+        let room = self.inner.create_dm(user_id).await?;
+        Ok(room.room_id().to_owned())
+    }
+}
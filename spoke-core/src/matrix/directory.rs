@@ -0,0 +1,160 @@
+// Public room directory — `/publicRooms` search with pagination, and
+// publishing our own rooms there (`/directory/list/room/{roomId}`).
+//
+// Servers differ on who may publish; Synapse asks for the power to change the
+// room's canonical alias, so that's what we check before offering it.
+
+use matrix_sdk::ruma::{
+    OwnedRoomId, RoomId, ServerName, UInt,
+    api::client::{
+        directory::{
+            get_public_rooms_filtered::v3 as get_public_rooms_filtered, get_room_visibility,
+            set_room_visibility,
+        },
+        room::Visibility,
+    },
+    directory::Filter,
+    events::StateEventType,
+};
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+/// One room listed in a homeserver's public directory.
+#[derive(Debug, Clone)]
+pub struct PublicRoom {
+    pub room_id: OwnedRoomId,
+    pub name: Option<String>,
+    pub topic: Option<String>,
+    pub canonical_alias: Option<String>,
+    /// `mxc://` URI of the room avatar, if any.
+    pub avatar_url: Option<String>,
+    pub num_joined_members: u64,
+    pub world_readable: bool,
+}
+
+impl PublicRoom {
+    /// Best human-readable label: name, then alias, then room ID.
+    pub fn display_name(&self) -> String {
+        self.name
+            .clone()
+            .or_else(|| self.canonical_alias.clone())
+            .unwrap_or_else(|| self.room_id.to_string())
+    }
+}
+
+/// A page of directory results.
+#[derive(Debug, Clone, Default)]
+pub struct DirectoryPage {
+    pub rooms: Vec<PublicRoom>,
+    /// Pass as `since` to fetch the next page; `None` on the last page.
+    pub next_batch: Option<String>,
+    pub total_estimate: Option<u64>,
+}
+
+/// A room's entry in our homeserver's public directory.
+#[derive(Debug, Clone, Copy)]
+pub struct DirectoryListing {
+    pub published: bool,
+    /// Whether we're likely allowed to publish or unpublish it.
+    pub can_change: bool,
+}
+
+impl SpokeClient {
+    /// Query a room directory.
+    ///
+    /// `server` selects a third-party homeserver's directory (our own if
+    /// `None`); `since` is a `next_batch` token from a previous page.
+    pub async fn public_rooms(
+        &self,
+        search: Option<&str>,
+        server: Option<&str>,
+        since: Option<&str>,
+        limit: u32,
+    ) -> Result<DirectoryPage, MatrixError> {
+        let mut req = get_public_rooms_filtered::Request::new();
+        req.limit = Some(UInt::from(limit));
+        req.since = since.map(str::to_owned);
+        if let Some(server) = server {
+            req.server = Some(
+                ServerName::parse(server)
+                    .map_err(|e| MatrixError::InvalidServerName(e.to_string()))?,
+            );
+        }
+        let mut filter = Filter::new();
+        filter.generic_search_term = search.filter(|s| !s.is_empty()).map(str::to_owned);
+        req.filter = filter;
+
+        let resp = self.inner.public_rooms_filtered(req).await?;
+
+        Ok(DirectoryPage {
+            rooms: resp
+                .chunk
+                .into_iter()
+                .map(|r| PublicRoom {
+                    room_id: r.room_id,
+                    name: r.name,
+                    topic: r.topic,
+                    canonical_alias: r.canonical_alias.map(|a| a.to_string()),
+                    avatar_url: r.avatar_url.map(|u| u.to_string()),
+                    num_joined_members: r.num_joined_members.into(),
+                    world_readable: r.world_readable,
+                })
+                .collect(),
+            next_batch: resp.next_batch,
+            total_estimate: resp.total_room_count_estimate.map(Into::into),
+        })
+    }
+
+    /// Whether `room_id` is published in our homeserver's directory.
+    pub async fn directory_listing(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<DirectoryListing, MatrixError> {
+        let resp = self
+            .inner
+            .send(
+                get_room_visibility::v3::Request::new(room_id.to_owned()),
+                None,
+            )
+            .await?;
+        Ok(DirectoryListing {
+            published: resp.visibility == Visibility::Public,
+            can_change: self.can_publish(room_id).await?,
+        })
+    }
+
+    /// Publish `room_id` in our homeserver's directory, or take it out.
+    pub async fn set_published(
+        &self,
+        room_id: &RoomId,
+        published: bool,
+    ) -> Result<(), MatrixError> {
+        if !self.can_publish(room_id).await? {
+            return Err(MatrixError::Forbidden(format!(
+                "publishing {room_id} needs permission to change its address"
+            )));
+        }
+        let visibility = if published {
+            Visibility::Public
+        } else {
+            Visibility::Private
+        };
+        self.inner
+            .send(
+                set_room_visibility::v3::Request::new(room_id.to_owned(), visibility),
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn can_publish(&self, room_id: &RoomId) -> Result<bool, MatrixError> {
+        let room = self
+            .inner
+            .get_room(room_id)
+            .ok_or_else(|| MatrixError::NotFound(room_id.to_string()))?;
+        Ok(room
+            .can_user_send_state(self.own_user_id()?, StateEventType::RoomCanonicalAlias)
+            .await?)
+    }
+}
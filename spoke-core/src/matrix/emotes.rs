@@ -0,0 +1,214 @@
+// Stickers and custom emotes — MSC2545 image packs from our account data
+// (`im.ponies.user_emotes`) and the room's state (`im.ponies.room_emotes`,
+// one pack per state key), and sending `m.sticker` events from them.
+
+use std::collections::BTreeMap;
+
+use matrix_sdk::{
+    deserialized_responses::SyncOrStrippedState,
+    media::{MediaFormat, MediaRequest, MediaThumbnailSettings},
+    ruma::{
+        OwnedEventId, OwnedMxcUri, RoomId,
+        api::client::media::get_content_thumbnail::v3::Method,
+        events::{SyncStateEvent, macros::EventContent, room::MediaSource},
+    },
+};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+/// Thumbnail bounds for stickers and emotes.
+const PACK_IMAGE_SIZE: u32 = 256;
+
+/// What a pack image may be used as. Absent or empty means both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PackUsage {
+    Emoticon,
+    Sticker,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PackInfo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub usage: Vec<PackUsage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackImageContent {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub usage: Vec<PackUsage>,
+}
+
+/// An image pack in a room's state, keyed by its state key.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, EventContent)]
+#[ruma_event(type = "im.ponies.room_emotes", kind = State, state_key_type = String)]
+pub struct RoomEmotesEventContent {
+    #[serde(default)]
+    pub images: BTreeMap<String, PackImageContent>,
+    #[serde(default)]
+    pub pack: PackInfo,
+}
+
+/// Our personal image pack, available in every room.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, EventContent)]
+#[ruma_event(type = "im.ponies.user_emotes", kind = GlobalAccountData)]
+pub struct UserEmotesEventContent {
+    #[serde(default)]
+    pub images: BTreeMap<String, PackImageContent>,
+    #[serde(default)]
+    pub pack: PackInfo,
+}
+
+/// `m.sticker`, kept to the fields we send and show.
+#[derive(Clone, Debug, Serialize, Deserialize, EventContent)]
+#[ruma_event(type = "m.sticker", kind = MessageLike)]
+pub struct StickerEventContent {
+    /// Description of the sticker; the shortcode for pack stickers.
+    pub body: String,
+    pub url: String,
+    #[serde(default)]
+    pub info: StickerInfo,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct StickerInfo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub w: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub h: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mimetype: Option<String>,
+}
+
+/// One pack, flattened for display.
+#[derive(Debug, Clone)]
+pub struct ImagePack {
+    pub name: String,
+    pub images: Vec<PackImage>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PackImage {
+    pub shortcode: String,
+    /// `mxc://` URI.
+    pub url: String,
+    pub body: Option<String>,
+    pub sticker: bool,
+    pub emoticon: bool,
+}
+
+fn flatten_pack(
+    name: String,
+    pack: &PackInfo,
+    images: &BTreeMap<String, PackImageContent>,
+) -> ImagePack {
+    let images = images
+        .iter()
+        .filter(|(_, image)| image.url.starts_with("mxc://"))
+        .map(|(shortcode, image)| {
+            let usage = if image.usage.is_empty() {
+                &pack.usage
+            } else {
+                &image.usage
+            };
+            let any = usage.is_empty();
+            PackImage {
+                shortcode: shortcode.clone(),
+                url: image.url.clone(),
+                body: image.body.clone(),
+                sticker: any || usage.contains(&PackUsage::Sticker),
+                emoticon: any || usage.contains(&PackUsage::Emoticon),
+            }
+        })
+        .collect();
+    ImagePack {
+        name: pack.display_name.clone().unwrap_or(name),
+        images,
+    }
+}
+
+impl SpokeClient {
+    /// Our own pack, then the packs in `room_id`'s state. Empty packs are left out.
+    pub async fn image_packs(&self, room_id: &RoomId) -> Result<Vec<ImagePack>, MatrixError> {
+        let mut packs = Vec::new();
+        if let Some(raw) = self
+            .inner
+            .account()
+            .account_data::<UserEmotesEventContent>()
+            .await?
+        {
+            match raw.deserialize() {
+                Ok(user) => packs.push(flatten_pack(
+                    "Personal".to_owned(),
+                    &user.pack,
+                    &user.images,
+                )),
+                Err(e) => warn!("unreadable user emotes: {e}"),
+            }
+        }
+
+        let room = self
+            .inner
+            .get_room(room_id)
+            .ok_or_else(|| MatrixError::NotFound(room_id.to_string()))?;
+        for raw in room
+            .get_state_events_static::<RoomEmotesEventContent>()
+            .await?
+        {
+            match raw.deserialize() {
+                Ok(SyncOrStrippedState::Sync(SyncStateEvent::Original(ev))) => {
+                    let name = match ev.state_key.as_str() {
+                        "" => room.name().unwrap_or_else(|| "Room".to_owned()),
+                        key => key.to_owned(),
+                    };
+                    packs.push(flatten_pack(name, &ev.content.pack, &ev.content.images));
+                }
+                Ok(_) => {}
+                Err(e) => warn!("unreadable emote pack in {room_id}: {e}"),
+            }
+        }
+        packs.retain(|p| !p.images.is_empty());
+        Ok(packs)
+    }
+
+    /// Send `image` as a sticker.
+    pub async fn send_sticker(
+        &self,
+        room_id: &RoomId,
+        image: &PackImage,
+    ) -> Result<OwnedEventId, MatrixError> {
+        let room = self
+            .inner
+            .get_room(room_id)
+            .ok_or_else(|| MatrixError::NotFound(room_id.to_string()))?;
+        let content = StickerEventContent {
+            body: image
+                .body
+                .clone()
+                .unwrap_or_else(|| image.shortcode.clone()),
+            url: image.url.clone(),
+            info: StickerInfo::default(),
+        };
+        Ok(room.send(content).await?.event_id)
+    }
+
+    /// A thumbnail of a sticker or emote, cached in the media store.
+    pub async fn pack_image(&self, mxc: &str) -> Result<Vec<u8>, MatrixError> {
+        let request = MediaRequest {
+            source: MediaSource::Plain(OwnedMxcUri::from(mxc)),
+            format: MediaFormat::Thumbnail(MediaThumbnailSettings::new(
+                Method::Scale,
+                PACK_IMAGE_SIZE.into(),
+                PACK_IMAGE_SIZE.into(),
+            )),
+        };
+        Ok(self.inner.media().get_media_content(&request, true).await?)
+    }
+}
@@ -0,0 +1,71 @@
+// Room encryption — turning end-to-end encryption on for a room, and which of
+// the members' devices get its keys.
+//
+// Turning it on sends `m.room.encryption` with the recommended Megolm
+// settings. There's no turning it off again: the spec ignores any later
+// attempt, so the UI should say so before asking.
+//
+// Who receives room keys is chosen when the client is built
+// (`ClientOptions::verified_devices_only`): by default every device of every
+// member gets them; with the option set, devices their owner hasn't
+// cross-signed are left out, so an unverified session can't read what we send.
+
+use matrix_sdk::ruma::{RoomId, events::StateEventType};
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+/// A room's encryption, for its settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoomEncryption {
+    pub enabled: bool,
+    /// Whether our power level lets us turn it on.
+    pub can_enable: bool,
+}
+
+impl SpokeClient {
+    /// Whether `room_id` is encrypted, and whether we could make it so.
+    pub async fn room_encryption(&self, room_id: &RoomId) -> Result<RoomEncryption, MatrixError> {
+        let room = self
+            .inner
+            .get_room(room_id)
+            .ok_or_else(|| MatrixError::NotFound(room_id.to_string()))?;
+        let enabled = room.is_encrypted().await?;
+        let can_enable = !enabled
+            && room
+                .can_user_send_state(self.own_user_id()?, StateEventType::RoomEncryption)
+                .await?;
+        Ok(RoomEncryption {
+            enabled,
+            can_enable,
+        })
+    }
+
+    /// Turn on end-to-end encryption in `room_id`, for good. Does nothing if
+    /// it's already on.
+    pub async fn enable_encryption(&self, room_id: &RoomId) -> Result<(), MatrixError> {
+        let room = self
+            .inner
+            .get_room(room_id)
+            .ok_or_else(|| MatrixError::NotFound(room_id.to_string()))?;
+        if room.is_encrypted().await? {
+            return Ok(());
+        }
+        if !room
+            .can_user_send_state(self.own_user_id()?, StateEventType::RoomEncryption)
+            .await?
+        {
+            return Err(MatrixError::Forbidden(
+                "your power level doesn't allow you to turn on encryption in this room".into(),
+            ));
+        }
+        self.scheduled("enable encryption", || async {
+            Ok(room.enable_encryption().await?)
+        })
+        .await
+    }
+
+    /// Whether room keys only go to verified devices; see `ClientOptions`.
+    pub fn verified_devices_only(&self) -> bool {
+        self.options.verified_devices_only
+    }
+}
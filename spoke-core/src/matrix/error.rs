@@ -5,9 +5,39 @@ pub enum MatrixError {
     #[error("matrix sdk error: {0}")]
     Sdk(#[from] matrix_sdk::Error),
 
+    #[error("matrix http error: {0}")]
+    Http(#[from] matrix_sdk::HttpError),
+
+    #[error("matrix store error: {0}")]
+    Store(#[from] matrix_sdk::StoreError),
+
     #[error("matrix client build error: {0}")]
     Build(#[from] matrix_sdk::ClientBuildError),
 
+    #[error("notification settings error: {0}")]
+    Notifications(#[from] matrix_sdk::notification_settings::NotificationSettingsError),
+
     #[error("invalid user id: {0}")]
     InvalidUserId(String),
+
+    #[error("invalid server name: {0}")]
+    InvalidServerName(String),
+
+    #[error("invalid media type: {0}")]
+    InvalidMediaType(String),
+
+    #[error("request failed: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("local storage error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("SSO login failed: {0}")]
+    Sso(String),
+
+    #[error("not found: {0}")]
+    NotFound(String),
+
+    #[error("not allowed: {0}")]
+    Forbidden(String),
 }
@@ -0,0 +1,97 @@
+// Knocking — request to join rooms with the `knock` join rule, and let room
+// moderators see and answer pending knocks. Accepting a knock is an invite;
+// rejecting one is a kick of the knocking member.
+
+use matrix_sdk::{
+    RoomMemberships,
+    ruma::{
+        OwnedRoomId, OwnedRoomOrAliasId, RoomId, UserId,
+        api::client::knock::knock_room::v3 as knock_room,
+    },
+};
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+/// A pending request to join a room.
+#[derive(Debug, Clone)]
+pub struct Knock {
+    pub user_id: String,
+    pub display_name: Option<String>,
+    pub reason: Option<String>,
+}
+
+impl SpokeClient {
+    /// Ask to join `room` (a room ID or alias). The room shows up as joined
+    /// once a moderator accepts and we accept the resulting invite.
+    pub async fn knock(
+        &self,
+        room: &str,
+        reason: Option<&str>,
+    ) -> Result<OwnedRoomId, MatrixError> {
+        let target = OwnedRoomOrAliasId::try_from(room)
+            .map_err(|_| MatrixError::NotFound(format!("invalid room address {room}")))?;
+        let mut request = knock_room::Request::new(target);
+        request.reason = reason.map(str::to_owned);
+        let response = self.inner.send(request, None).await?;
+        Ok(response.room_id)
+    }
+
+    /// Users currently knocking on `room_id`. Empty unless we may invite,
+    /// since only moderators can act on knocks.
+    pub async fn pending_knocks(&self, room_id: &RoomId) -> Result<Vec<Knock>, MatrixError> {
+        let room = self
+            .inner
+            .get_room(room_id)
+            .ok_or_else(|| MatrixError::NotFound(room_id.to_string()))?;
+        let Some(own) = self.inner.user_id() else {
+            return Ok(Vec::new());
+        };
+        if !room.can_user_invite(own).await? {
+            return Ok(Vec::new());
+        }
+        let members = room.members_no_sync(RoomMemberships::KNOCK).await?;
+        Ok(members
+            .into_iter()
+            .map(|m| Knock {
+                user_id: m.user_id().to_string(),
+                display_name: m.display_name().map(str::to_owned),
+                reason: m.event().original_content().and_then(|c| c.reason.clone()),
+            })
+            .collect())
+    }
+
+    /// Accept (invite) or reject (kick) a knock on `room_id` by `user_id`.
+    pub async fn answer_knock(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+        accept: bool,
+        reason: Option<&str>,
+    ) -> Result<(), MatrixError> {
+        let room = self
+            .inner
+            .get_room(room_id)
+            .ok_or_else(|| MatrixError::NotFound(room_id.to_string()))?;
+        let own = self
+            .inner
+            .user_id()
+            .ok_or_else(|| MatrixError::NotFound("own user id".into()))?;
+
+        if accept {
+            if !room.can_user_invite(own).await? {
+                return Err(MatrixError::Forbidden(
+                    "your power level doesn't allow inviting".into(),
+                ));
+            }
+            room.invite_user_by_id(user_id).await?;
+        } else {
+            if !room.can_user_kick(own).await? {
+                return Err(MatrixError::Forbidden(
+                    "your power level doesn't allow rejecting knocks".into(),
+                ));
+            }
+            room.kick_user(user_id, reason).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+// Rooms we've left or been removed from — listed with how we left so bans
+// can be shown with their reason, and forgettable so they disappear from the
+// account and the local store for good.
+
+use matrix_sdk::ruma::{RoomId, events::room::member::MembershipState};
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+/// A room in the left state.
+#[derive(Debug, Clone)]
+pub struct LeftRoom {
+    pub room_id: String,
+    pub name: String,
+    /// We were banned rather than leaving or being kicked. Rejoining is
+    /// pointless until someone unbans us.
+    pub banned: bool,
+    /// Who removed us, if it wasn't our own leave.
+    pub by: Option<String>,
+    pub reason: Option<String>,
+}
+
+impl SpokeClient {
+    /// Rooms we left, were kicked from or banned from, and haven't forgotten.
+    pub async fn left_rooms(&self) -> Result<Vec<LeftRoom>, MatrixError> {
+        let Some(own) = self.inner.user_id() else {
+            return Ok(Vec::new());
+        };
+        let mut rooms = Vec::new();
+        for room in self.inner.left_rooms() {
+            let member = room.get_member_no_sync(own).await?;
+            let event = member.as_ref().map(|m| m.event());
+            let sender = event.map(|e| e.sender().to_owned());
+            rooms.push(LeftRoom {
+                room_id: room.room_id().to_string(),
+                name: room.name().unwrap_or_else(|| room.room_id().to_string()),
+                banned: member
+                    .as_ref()
+                    .is_some_and(|m| *m.membership() == MembershipState::Ban),
+                by: sender.filter(|s| s != own).map(|s| s.to_string()),
+                reason: event
+                    .and_then(|e| e.original_content())
+                    .and_then(|c| c.reason.clone()),
+            });
+        }
+        Ok(rooms)
+    }
+
+    /// Forget a room we've left: the server drops it from our account and the
+    /// SDK removes it from the local store.
+    pub async fn forget_room(&self, room_id: &RoomId) -> Result<(), MatrixError> {
+        let room = self
+            .inner
+            .get_room(room_id)
+            .ok_or_else(|| MatrixError::NotFound(room_id.to_string()))?;
+        room.forget().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,203 @@
+// Watch together — a room-wide shared player. Whoever shares a link or
+// presses play, pause or seek sends an `org.spoke.media.sync` event carrying
+// the whole player state, so a missed update never leaves anyone out of step
+// and the latest event is all a late joiner needs. They're message events
+// rather than room state so that any member may send them, not just those
+// allowed to change the room's settings.
+//
+// Positions are anchored to the event's `origin_server_ts`; while playing,
+// everyone extrapolates from there with their own clock, which keeps members
+// within their clock skew of each other. Sharing a new link replaces the old
+// one; stopping clears the URL.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use matrix_sdk::{
+    room::MessagesOptions,
+    ruma::{
+        MilliSecondsSinceUnixEpoch, OwnedUserId, RoomId, UInt,
+        events::{OriginalSyncMessageLikeEvent, macros::EventContent},
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+/// The shared player's state. An event without a URL means nothing is shared.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, EventContent)]
+#[ruma_event(type = "org.spoke.media.sync", kind = MessageLike)]
+pub struct MediaSyncEventContent {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// The share's time in milliseconds since the Unix epoch, as a string;
+    /// kept by every update to it, so a stale seek or stop can't touch a
+    /// newer share.
+    #[serde(default)]
+    pub session_id: String,
+    #[serde(default)]
+    pub playing: bool,
+    /// Playback position when the event was sent.
+    #[serde(default)]
+    pub position_ms: u64,
+}
+
+/// How far back `shared_media` looks for the latest sync event.
+const LOOKBACK_EVENTS: u32 = 200;
+
+/// What's playing in a room, for display.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedMedia {
+    pub url: String,
+    pub session_id: String,
+    /// Who last changed the player.
+    pub updated_by: OwnedUserId,
+    pub playing: bool,
+    pub position_ms: u64,
+    /// When `position_ms` was true, in milliseconds since the Unix epoch.
+    pub anchor_ms: u64,
+}
+
+/// One sync event, to be applied to what a room is showing.
+#[derive(Debug, Clone)]
+pub struct SharedMediaUpdate {
+    session_id: String,
+    ts: u64,
+    /// `None` when the share was stopped.
+    media: Option<SharedMedia>,
+}
+
+impl SharedMediaUpdate {
+    pub fn from_event(event: &OriginalSyncMessageLikeEvent<MediaSyncEventContent>) -> Self {
+        let ts = u64::from(event.origin_server_ts.0);
+        let media = event.content.url.clone().map(|url| SharedMedia {
+            url,
+            session_id: event.content.session_id.clone(),
+            updated_by: event.sender.clone(),
+            playing: event.content.playing,
+            position_ms: event.content.position_ms,
+            anchor_ms: ts,
+        });
+        Self {
+            session_id: event.content.session_id.clone(),
+            ts,
+            media,
+        }
+    }
+
+    /// Apply this update to `current`, unless it's older than what's there
+    /// or belongs to an earlier share.
+    pub fn apply(self, current: &mut Option<SharedMedia>) {
+        if let Some(shown) = current {
+            let session = |id: &str| id.parse::<u64>().unwrap_or(0);
+            if self.ts < shown.anchor_ms || session(&self.session_id) < session(&shown.session_id) {
+                return;
+            }
+        }
+        *current = self.media;
+    }
+}
+
+impl SharedMedia {
+    /// Where playback is at `now_ms`.
+    pub fn position_at(&self, now_ms: u64) -> u64 {
+        if self.playing {
+            self.position_ms + now_ms.saturating_sub(self.anchor_ms)
+        } else {
+            self.position_ms
+        }
+    }
+
+    /// Where playback is now.
+    pub fn position_now(&self) -> u64 {
+        self.position_at(now_ms())
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+impl SpokeClient {
+    /// What's shared in `room_id`, from the latest sync event in recent
+    /// history. Anything older than that is assumed to be over.
+    pub async fn shared_media(&self, room_id: &RoomId) -> Result<Option<SharedMedia>, MatrixError> {
+        let room = self
+            .inner
+            .get_room(room_id)
+            .ok_or_else(|| MatrixError::NotFound(room_id.to_string()))?;
+        let mut options = MessagesOptions::backward();
+        options.limit = UInt::from(LOOKBACK_EVENTS);
+        let response = room.messages(options).await?;
+        for event in &response.chunk {
+            let raw = event.raw();
+            if raw.get_field::<String>("type").ok().flatten().as_deref()
+                != Some("org.spoke.media.sync")
+            {
+                continue;
+            }
+            if let Ok(sync) =
+                raw.deserialize_as::<OriginalSyncMessageLikeEvent<MediaSyncEventContent>>()
+            {
+                return Ok(SharedMediaUpdate::from_event(&sync).media);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Share `url` with the room, paused at the start.
+    pub async fn share_media(&self, room_id: &RoomId, url: &str) -> Result<(), MatrixError> {
+        let session_id = format!("{}", MilliSecondsSinceUnixEpoch::now().0);
+        let content = MediaSyncEventContent {
+            url: Some(url.trim().to_owned()),
+            session_id,
+            playing: false,
+            position_ms: 0,
+        };
+        self.send_media_sync(room_id, content).await
+    }
+
+    /// Play, pause or seek the shared player `media` to `position_ms`.
+    pub async fn sync_media(
+        &self,
+        room_id: &RoomId,
+        media: &SharedMedia,
+        playing: bool,
+        position_ms: u64,
+    ) -> Result<(), MatrixError> {
+        let content = MediaSyncEventContent {
+            url: Some(media.url.clone()),
+            session_id: media.session_id.clone(),
+            playing,
+            position_ms,
+        };
+        self.send_media_sync(room_id, content).await
+    }
+
+    /// Stop sharing `media` in `room_id`.
+    pub async fn stop_media(
+        &self,
+        room_id: &RoomId,
+        media: &SharedMedia,
+    ) -> Result<(), MatrixError> {
+        let content = MediaSyncEventContent {
+            session_id: media.session_id.clone(),
+            ..Default::default()
+        };
+        self.send_media_sync(room_id, content).await
+    }
+
+    async fn send_media_sync(
+        &self,
+        room_id: &RoomId,
+        content: MediaSyncEventContent,
+    ) -> Result<(), MatrixError> {
+        let room = self
+            .inner
+            .get_room(room_id)
+            .ok_or_else(|| MatrixError::NotFound(room_id.to_string()))?;
+        room.send(content).await?;
+        Ok(())
+    }
+}
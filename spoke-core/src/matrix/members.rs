@@ -0,0 +1,94 @@
+// Room member lists — loaded with `/members` on first use, since sync is
+// lazy-loading members and only sends the ones the timeline needs. The
+// members already stored can be shown while that request runs. Presence
+// comes from whatever presence events sync has stored.
+
+use matrix_sdk::{
+    RoomMemberships, StateStore,
+    room::RoomMember,
+    ruma::{
+        RoomId,
+        events::{presence::PresenceEvent, room::member::MembershipState},
+        presence::PresenceState,
+    },
+};
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+/// One joined or invited member of a room.
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub user_id: String,
+    pub display_name: Option<String>,
+    /// `mxc://` URI of the member's avatar in this room, if any.
+    pub avatar_url: Option<String>,
+    pub membership: MembershipState,
+    pub power_level: i64,
+    /// `None` if the server hasn't told us (presence may be disabled).
+    pub presence: Option<PresenceState>,
+}
+
+impl Member {
+    /// Display name, or the MXID if there is none.
+    pub fn name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.user_id)
+    }
+}
+
+impl SpokeClient {
+    /// Joined and invited members of `room_id`, highest power level first,
+    /// then by name. Fetches the full list from the server the first time.
+    pub async fn members(&self, room_id: &RoomId) -> Result<Vec<Member>, MatrixError> {
+        let room = self
+            .inner
+            .get_room(room_id)
+            .ok_or_else(|| MatrixError::NotFound(room_id.to_string()))?;
+        let members = room
+            .members(RoomMemberships::JOIN | RoomMemberships::INVITE)
+            .await?;
+        self.to_members(members).await
+    }
+
+    /// The members already in the store, without asking the server, and
+    /// whether that is everyone. Until the full list has been fetched this is
+    /// only the people lazy loading has told us about.
+    pub async fn stored_members(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<(Vec<Member>, bool), MatrixError> {
+        let room = self
+            .inner
+            .get_room(room_id)
+            .ok_or_else(|| MatrixError::NotFound(room_id.to_string()))?;
+        let members = room
+            .members_no_sync(RoomMemberships::JOIN | RoomMemberships::INVITE)
+            .await?;
+        Ok((self.to_members(members).await?, room.are_members_synced()))
+    }
+
+    async fn to_members(&self, room_members: Vec<RoomMember>) -> Result<Vec<Member>, MatrixError> {
+        let store = self.inner.store();
+        let mut members = Vec::with_capacity(room_members.len());
+        for m in room_members {
+            let presence = store
+                .get_presence_event(m.user_id())
+                .await?
+                .and_then(|raw| raw.deserialize().ok())
+                .map(|event: PresenceEvent| event.content.presence);
+            members.push(Member {
+                user_id: m.user_id().to_string(),
+                display_name: m.display_name().map(str::to_owned),
+                avatar_url: m.avatar_url().map(|u| u.to_string()),
+                membership: m.membership().clone(),
+                power_level: m.power_level(),
+                presence,
+            });
+        }
+        members.sort_by(|a, b| {
+            b.power_level
+                .cmp(&a.power_level)
+                .then_with(|| a.name().to_lowercase().cmp(&b.name().to_lowercase()))
+        });
+        Ok(members)
+    }
+}
@@ -0,0 +1,27 @@
+// Mentions — whether an incoming message mentions a given user. `m.mentions`
+// is authoritative when present; messages from clients that predate it are
+// checked for a matrix.to pill pointing at the user instead.
+
+use matrix_sdk::ruma::{
+    UserId,
+    events::room::message::{MessageType, RoomMessageEventContent},
+};
+
+/// Whether `content` mentions `user_id`.
+pub fn mentions_user(content: &RoomMessageEventContent, user_id: &UserId) -> bool {
+    if let Some(mentions) = &content.mentions {
+        return mentions.user_ids.contains(user_id);
+    }
+    let MessageType::Text(text) = &content.msgtype else {
+        return false;
+    };
+    let Some(formatted) = &text.formatted else {
+        return false;
+    };
+    let plain = format!("matrix.to/#/{user_id}");
+    let encoded = format!(
+        "matrix.to/#/{}",
+        user_id.as_str().replace('@', "%40").replace(':', "%3A")
+    );
+    formatted.body.contains(&plain) || formatted.body.contains(&encoded)
+}
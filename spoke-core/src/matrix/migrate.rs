@@ -0,0 +1,204 @@
+// Store migration — early builds kept each account's SQLite stores and
+// session file under `/tmp/spoke-app-<name>.db`, which the OS may wipe. This
+// moves them into the platform data directory once.
+//
+// Stores in the data directory were later scoped by homeserver too, so the
+// same username on two servers (or two accounts at once) never share one;
+// unscoped stores are renamed on first use.
+//
+// Originals are only deleted after the copied session has been restored
+// against the copied stores, so a failed copy never costs the user their
+// encryption keys.
+
+use std::path::{Path, PathBuf};
+
+use matrix_sdk::{Client, matrix_auth::MatrixSession};
+use thiserror::Error;
+use tracing::info;
+
+/// Where legacy stores were created.
+const LEGACY_DIR: &str = "/tmp";
+const LEGACY_PREFIX: &str = "spoke-app-";
+
+#[derive(Debug, Error)]
+pub enum MigrationError {
+    #[error("i/o error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("{0} already exists")]
+    Exists(PathBuf),
+
+    #[error("migrated store doesn't open: {0}")]
+    Verify(String),
+}
+
+/// A store left in the legacy location.
+#[derive(Debug, Clone)]
+pub struct LegacyStore {
+    /// The `<name>` part: a username, or `sso-<host>`.
+    pub name: String,
+    pub db_path: PathBuf,
+    /// Absent if the app never finished logging in with this store.
+    pub session_path: Option<PathBuf>,
+}
+
+/// Store name for the account known locally as `name` (a username, or
+/// `sso-<host>`) on `homeserver`.
+pub fn account_store_name(homeserver: &str, name: &str) -> String {
+    let host = reqwest::Url::parse(homeserver)
+        .ok()
+        .and_then(|u| {
+            u.host_str().map(|h| match u.port() {
+                Some(port) => format!("{h}_{port}"),
+                None => h.to_owned(),
+            })
+        })
+        .unwrap_or_default();
+    format!("{name}@{host}")
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "@._-=".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Rename the unscoped store `name` (and its session file) to `scoped`,
+/// unless a store already exists there. Returns the store path to use.
+pub fn scope_store(data_dir: &Path, name: &str, scoped: &str) -> Result<PathBuf, MigrationError> {
+    let target = store_path(data_dir, scoped);
+    let unscoped = store_path(data_dir, name);
+    if target.exists() || !unscoped.is_dir() {
+        return Ok(target);
+    }
+    std::fs::rename(&unscoped, &target)?;
+    if session_path(&unscoped).exists() {
+        std::fs::rename(session_path(&unscoped), session_path(&target))?;
+    }
+    info!("scoped store {name} as {scoped}");
+    Ok(target)
+}
+
+/// Store directory for `name` under `data_dir`.
+pub fn store_path(data_dir: &Path, name: &str) -> PathBuf {
+    data_dir.join("stores").join(format!("{name}.db"))
+}
+
+/// The session file that goes with the store at `db_path`.
+pub fn session_path(db_path: &Path) -> PathBuf {
+    db_path.with_extension("session.json")
+}
+
+/// Legacy stores still in `/tmp`, by name.
+pub fn legacy_stores() -> Vec<LegacyStore> {
+    let Ok(entries) = std::fs::read_dir(LEGACY_DIR) else {
+        return Vec::new();
+    };
+    let mut stores: Vec<LegacyStore> = entries
+        .filter_map(Result::ok)
+        .filter(|e| e.path().is_dir())
+        .filter_map(|e| {
+            let file_name = e.file_name().into_string().ok()?;
+            let name = file_name
+                .strip_prefix(LEGACY_PREFIX)?
+                .strip_suffix(".db")?
+                .to_owned();
+            Some(legacy_store_at(e.path(), name))
+        })
+        .collect();
+    stores.sort_by(|a, b| a.name.cmp(&b.name));
+    stores
+}
+
+/// The legacy store for `name`, if there is one.
+pub fn legacy_store(name: &str) -> Option<LegacyStore> {
+    let db_path = Path::new(LEGACY_DIR).join(format!("{LEGACY_PREFIX}{name}.db"));
+    db_path
+        .is_dir()
+        .then(|| legacy_store_at(db_path, name.to_owned()))
+}
+
+fn legacy_store_at(db_path: PathBuf, name: String) -> LegacyStore {
+    let session = session_path(&db_path);
+    LegacyStore {
+        name,
+        session_path: session.exists().then_some(session),
+        db_path,
+    }
+}
+
+/// Copy `legacy` into `data_dir`, check that it opens, then delete the
+/// original. Returns the new store path.
+pub async fn migrate_store(
+    legacy: &LegacyStore,
+    data_dir: &Path,
+) -> Result<PathBuf, MigrationError> {
+    let target = store_path(data_dir, &legacy.name);
+    if target.exists() {
+        return Err(MigrationError::Exists(target));
+    }
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    copy_dir(&legacy.db_path, &target)?;
+    if let Some(session) = &legacy.session_path {
+        std::fs::copy(session, session_path(&target))?;
+    }
+
+    if let Err(e) = verify(&target).await {
+        // Leave the original alone and don't half-migrate.
+        let _ = std::fs::remove_dir_all(&target);
+        let _ = std::fs::remove_file(session_path(&target));
+        return Err(e);
+    }
+
+    std::fs::remove_dir_all(&legacy.db_path)?;
+    if let Some(session) = &legacy.session_path {
+        std::fs::remove_file(session)?;
+    }
+    info!("migrated store {} to {target:?}", legacy.name);
+    Ok(target)
+}
+
+/// Open the stores at `db_path` and restore its session into them, which
+/// loads the crypto store for that user and device. Nothing goes over the
+/// network.
+async fn verify(db_path: &Path) -> Result<(), MigrationError> {
+    let client = Client::builder()
+        // Never contacted; building needs some homeserver.
+        .homeserver_url("http://localhost")
+        .sqlite_store(db_path, None)
+        .build()
+        .await
+        .map_err(|e| MigrationError::Verify(e.to_string()))?;
+
+    let session_file = session_path(db_path);
+    if !session_file.exists() {
+        return Ok(());
+    }
+    let json = std::fs::read_to_string(&session_file)?;
+    let session: MatrixSession = serde_json::from_str(&json)
+        .map_err(|e| MigrationError::Verify(format!("session file: {e}")))?;
+    client
+        .restore_session(session)
+        .await
+        .map_err(|e| MigrationError::Verify(e.to_string()))
+}
+
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
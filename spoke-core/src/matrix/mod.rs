@@ -1,8 +1,89 @@
 // Matrix protocol layer — wraps matrix-rust-sdk
 // Handles sync, auth, rooms, messages, and E2E encryption.
 
+mod account_settings;
+mod capabilities;
 mod client;
+mod create_room;
+mod devices;
+mod direct;
+mod directory;
+mod emotes;
+mod encryption;
 mod error;
+mod knock;
+mod left;
+mod media_sync;
+mod members;
+mod mentions;
+pub mod migrate;
+mod moderation;
+mod notifications;
+mod peek;
+mod pins;
+mod polls;
+mod power_levels;
+mod presence;
+mod profile;
+mod rate_limit;
+mod reactions;
+mod reports;
+mod rich_text;
+mod room_lookup;
+mod room_state;
+mod send_queue;
+mod server_acl;
+mod server_info;
+mod session;
+mod spaces;
+mod spin_off;
+mod sso;
+mod sync_filter;
+mod timeline_cache;
+mod url_preview;
+mod utd;
+mod voice_messages;
 
-pub use client::SpokeClient;
+pub use account_settings::AccountSettingsEventContent;
+pub use capabilities::ServerCapabilities;
+pub use client::{ClientOptions, SpokeClient};
+pub use create_room::{NewRoom, RoomTemplate};
+pub use devices::DeviceInfo;
+pub use directory::{DirectoryListing, DirectoryPage, PublicRoom};
+pub use emotes::{ImagePack, PackImage, StickerEventContent};
+pub use encryption::RoomEncryption;
 pub use error::MatrixError;
+pub use knock::Knock;
+pub use left::LeftRoom;
+pub use media_sync::{MediaSyncEventContent, SharedMedia, SharedMediaUpdate};
+pub use members::Member;
+pub use mentions::mentions_user;
+pub use moderation::ModerationAction;
+pub use notifications::{NotificationMode, PushVerdict};
+pub use peek::{PeekedMessage, RoomPeek};
+pub use pins::{PinnedMessage, Pins};
+pub use polls::{
+    Poll, PollAnswer, PollEndEventContent, PollKind, PollResponseEventContent, PollResults,
+    PollStartEventContent, PollVotes,
+};
+pub use power_levels::{ADMIN_LEVEL, MODERATOR_LEVEL, PowerLevelChange, PowerLevels};
+pub use profile::Profile;
+pub use rate_limit::RateLimited;
+pub use rich_text::{Block, RichText, Span, SpanStyle, markdown_to_html};
+pub use room_state::StateEntry;
+pub use send_queue::{
+    DeliveryState, DeliveryUpdate, MessageRelation, MessageText, PendingMessage, SendQueue,
+};
+pub use server_acl::{
+    AclChange, PolicyKind, PolicyList, PolicyListsEventContent, PolicyRule, ServerAcl,
+};
+pub use server_info::{Registration, ServerInfo, SsoProvider};
+pub use session::SessionEnded;
+pub use spaces::SpaceNode;
+pub use spin_off::{SpinOff, SpinOffStep, SpunOff};
+pub use sso::SsoLogin;
+pub use sync_filter::SyncFilterOptions;
+pub use timeline_cache::CachedMessage;
+pub use url_preview::UrlPreview;
+pub use utd::Undecrypted;
+pub use voice_messages::VoiceMessage;
@@ -0,0 +1,136 @@
+// Notifications — which incoming events should notify or highlight, decided
+// locally from the account's push rules, and per-room notification modes,
+// stored as room-specific push rules in the account.
+
+use matrix_sdk::{
+    Room,
+    notification_settings::RoomNotificationMode,
+    ruma::{
+        RoomId,
+        api::client::push::get_pushrules_all::v3 as get_pushrules_all,
+        events::push_rules::PushRulesEventContent,
+        push::{Action, Ruleset},
+        serde::Raw,
+    },
+};
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+/// How much a room notifies, overriding the account-wide rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationMode {
+    AllMessages,
+    MentionsAndKeywords,
+    Mute,
+}
+
+impl NotificationMode {
+    pub const ALL: [NotificationMode; 3] = [
+        NotificationMode::AllMessages,
+        NotificationMode::MentionsAndKeywords,
+        NotificationMode::Mute,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            NotificationMode::AllMessages => "All messages",
+            NotificationMode::MentionsAndKeywords => "Mentions & keywords",
+            NotificationMode::Mute => "Mute",
+        }
+    }
+}
+
+impl From<RoomNotificationMode> for NotificationMode {
+    fn from(mode: RoomNotificationMode) -> Self {
+        match mode {
+            RoomNotificationMode::AllMessages => NotificationMode::AllMessages,
+            RoomNotificationMode::MentionsAndKeywordsOnly => NotificationMode::MentionsAndKeywords,
+            RoomNotificationMode::Mute => NotificationMode::Mute,
+        }
+    }
+}
+
+impl From<NotificationMode> for RoomNotificationMode {
+    fn from(mode: NotificationMode) -> Self {
+        match mode {
+            NotificationMode::AllMessages => RoomNotificationMode::AllMessages,
+            NotificationMode::MentionsAndKeywords => RoomNotificationMode::MentionsAndKeywordsOnly,
+            NotificationMode::Mute => RoomNotificationMode::Mute,
+        }
+    }
+}
+
+/// What the push rules say to do with an event.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PushVerdict {
+    pub notify: bool,
+    /// Mentions and keywords; shown more prominently than plain notifications.
+    pub highlight: bool,
+}
+
+impl SpokeClient {
+    /// The account's push rules, as last synced, or from the server if sync
+    /// hasn't delivered them yet.
+    pub async fn push_rules(&self) -> Result<Ruleset, MatrixError> {
+        if let Some(raw) = self
+            .inner
+            .account()
+            .account_data::<PushRulesEventContent>()
+            .await?
+        {
+            if let Ok(content) = raw.deserialize() {
+                return Ok(content.global);
+            }
+        }
+        let response = self
+            .inner
+            .send(get_pushrules_all::Request::new(), None)
+            .await?;
+        Ok(response.global)
+    }
+
+    /// Evaluate `event` in `room` against the account's push rules.
+    pub async fn push_verdict<T>(
+        &self,
+        room: &Room,
+        event: &Raw<T>,
+    ) -> Result<PushVerdict, MatrixError> {
+        let Some(context) = room.push_context().await? else {
+            return Ok(PushVerdict::default());
+        };
+        let rules = self.push_rules().await?;
+        let actions = rules.get_actions(event, &context);
+        Ok(PushVerdict {
+            notify: actions.iter().any(Action::should_notify),
+            highlight: actions.iter().any(Action::is_highlight),
+        })
+    }
+
+    /// The mode set for `room_id`, or `None` if it follows the account default.
+    pub async fn room_notification_mode(&self, room_id: &RoomId) -> Option<NotificationMode> {
+        let settings = self.inner.notification_settings().await;
+        settings
+            .get_user_defined_room_notification_mode(room_id)
+            .await
+            .map(NotificationMode::from)
+    }
+
+    /// Set `room_id`'s mode; `None` removes its rules so it follows the
+    /// account default again.
+    pub async fn set_room_notification_mode(
+        &self,
+        room_id: &RoomId,
+        mode: Option<NotificationMode>,
+    ) -> Result<(), MatrixError> {
+        let settings = self.inner.notification_settings().await;
+        match mode {
+            Some(mode) => {
+                settings
+                    .set_room_notification_mode(room_id, mode.into())
+                    .await?
+            }
+            None => settings.delete_user_defined_room_rules(room_id).await?,
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+// Room previews — a look at a room before joining it: its name, topic and
+// member count, and the last few messages when its history is public.
+//
+// Servers only hand out `/state` and `/messages` of a room we're not in when
+// its history is world-readable. For any other room the public directory's
+// entry, where there is one, still gives the basics.
+
+use matrix_sdk::ruma::{
+    OwnedRoomId, OwnedRoomOrAliasId, RoomId, UInt,
+    api::client::{message::get_message_events, state::get_state_events},
+    events::{AnyMessageLikeEvent, AnyStateEvent, AnyTimelineEvent, room::member::MembershipState},
+};
+use tracing::warn;
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+/// Most recent messages shown in a preview.
+const PEEK_MESSAGES: u32 = 20;
+/// Directory entries searched for the room when its state is private.
+const PEEK_DIRECTORY_ROOMS: u32 = 50;
+
+/// What can be seen of a room from outside.
+#[derive(Debug, Clone)]
+pub struct RoomPeek {
+    pub room_id: OwnedRoomId,
+    pub name: Option<String>,
+    pub topic: Option<String>,
+    pub canonical_alias: Option<String>,
+    /// `mxc://` URI of the room avatar, if any.
+    pub avatar_url: Option<String>,
+    pub member_count: u64,
+    /// Latest messages, oldest first; empty unless the history is public.
+    pub recent: Vec<PeekedMessage>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PeekedMessage {
+    pub sender: String,
+    pub body: String,
+    /// Milliseconds since the Unix epoch.
+    pub ts: u64,
+}
+
+impl SpokeClient {
+    /// Preview `room` (a room ID or alias) without joining it. Fails with
+    /// `MatrixError::Forbidden` when the room is neither world-readable nor
+    /// in its server's directory.
+    pub async fn peek_room(&self, room: &str) -> Result<RoomPeek, MatrixError> {
+        let target = OwnedRoomOrAliasId::try_from(room.trim())
+            .map_err(|_| MatrixError::NotFound(format!("invalid room address {room}")))?;
+        // Directory searches match aliases but not room IDs.
+        let (room_id, server, search) = match OwnedRoomId::try_from(target) {
+            Ok(room_id) => {
+                let server = room_id.server_name().map(|s| s.to_string());
+                (room_id, server, None)
+            }
+            Err(alias) => {
+                let room_id = self.inner.resolve_room_alias(&alias).await?.room_id;
+                (
+                    room_id,
+                    Some(alias.server_name().to_string()),
+                    Some(alias.to_string()),
+                )
+            }
+        };
+
+        match self.peek_state(&room_id).await {
+            Ok(mut peek) => {
+                peek.recent = self.peek_messages(&room_id).await.unwrap_or_else(|e| {
+                    warn!("peek {room_id}: no messages: {e}");
+                    Vec::new()
+                });
+                Ok(peek)
+            }
+            Err(e) => {
+                warn!("peek {room_id}: no state: {e}");
+                self.peek_directory(&room_id, server.as_deref(), search.as_deref())
+                    .await?
+                    .ok_or_else(|| {
+                        MatrixError::Forbidden(format!("{room} can't be previewed before joining"))
+                    })
+            }
+        }
+    }
+
+    /// The room's basics from its full state.
+    async fn peek_state(&self, room_id: &RoomId) -> Result<RoomPeek, MatrixError> {
+        let request = get_state_events::v3::Request::new(room_id.to_owned());
+        let state = self.inner.send(request, None).await?.room_state;
+        let mut peek = RoomPeek {
+            room_id: room_id.to_owned(),
+            name: None,
+            topic: None,
+            canonical_alias: None,
+            avatar_url: None,
+            member_count: 0,
+            recent: Vec::new(),
+        };
+        for event in state.iter().filter_map(|raw| raw.deserialize().ok()) {
+            match event {
+                AnyStateEvent::RoomName(ev) => {
+                    peek.name = ev.as_original().map(|ev| ev.content.name.clone())
+                }
+                AnyStateEvent::RoomTopic(ev) => {
+                    peek.topic = ev.as_original().map(|ev| ev.content.topic.clone())
+                }
+                AnyStateEvent::RoomCanonicalAlias(ev) => {
+                    peek.canonical_alias = ev
+                        .as_original()
+                        .and_then(|ev| ev.content.alias.as_ref())
+                        .map(ToString::to_string);
+                }
+                AnyStateEvent::RoomAvatar(ev) => {
+                    peek.avatar_url = ev
+                        .as_original()
+                        .and_then(|ev| ev.content.url.as_ref())
+                        .map(ToString::to_string);
+                }
+                AnyStateEvent::RoomMember(ev) if ev.membership() == &MembershipState::Join => {
+                    peek.member_count += 1
+                }
+                _ => {}
+            }
+        }
+        Ok(peek)
+    }
+
+    /// The latest messages, oldest first.
+    async fn peek_messages(&self, room_id: &RoomId) -> Result<Vec<PeekedMessage>, MatrixError> {
+        let mut request = get_message_events::v3::Request::backward(room_id.to_owned());
+        request.limit = UInt::from(PEEK_MESSAGES);
+        let chunk = self.inner.send(request, None).await?.chunk;
+        let mut messages: Vec<PeekedMessage> = chunk
+            .iter()
+            .filter_map(|raw| raw.deserialize().ok())
+            .filter_map(|event| {
+                let body = match &event {
+                    AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomMessage(ev)) => {
+                        ev.as_original()?.content.body().to_owned()
+                    }
+                    AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomEncrypted(_)) => {
+                        "Encrypted message".to_owned()
+                    }
+                    _ => return None,
+                };
+                Some(PeekedMessage {
+                    sender: event.sender().to_string(),
+                    body,
+                    ts: u64::from(event.origin_server_ts().0),
+                })
+            })
+            .collect();
+        messages.reverse();
+        Ok(messages)
+    }
+
+    /// The room's entry in its server's public directory, if listed there.
+    async fn peek_directory(
+        &self,
+        room_id: &RoomId,
+        server: Option<&str>,
+        search: Option<&str>,
+    ) -> Result<Option<RoomPeek>, MatrixError> {
+        let page = self
+            .public_rooms(search, server, None, PEEK_DIRECTORY_ROOMS)
+            .await?;
+        Ok(page
+            .rooms
+            .into_iter()
+            .find(|r| r.room_id == room_id)
+            .map(|r| RoomPeek {
+                room_id: r.room_id,
+                name: r.name,
+                topic: r.topic,
+                canonical_alias: r.canonical_alias,
+                avatar_url: r.avatar_url,
+                member_count: r.num_joined_members,
+                recent: Vec::new(),
+            }))
+    }
+}
@@ -0,0 +1,157 @@
+// Pinned messages — `m.room.pinned_events`, the room's short list of
+// messages worth keeping in view, resolved into something a pins panel can
+// show.
+//
+// The state event only lists event IDs, oldest pin first. Each is fetched
+// (and decrypted, where we have the keys) on its own; one that can't be
+// read still shows, as a placeholder, so it can be unpinned.
+
+use matrix_sdk::{
+    Room,
+    deserialized_responses::SyncOrStrippedState,
+    ruma::{
+        EventId, OwnedEventId, RoomId,
+        events::{
+            AnyMessageLikeEvent, AnyTimelineEvent, StateEventType, SyncStateEvent,
+            room::pinned_events::RoomPinnedEventsEventContent,
+        },
+    },
+};
+use tracing::warn;
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+/// A room's pins, for its pins panel.
+#[derive(Debug, Clone, Default)]
+pub struct Pins {
+    /// Most recently pinned first.
+    pub messages: Vec<PinnedMessage>,
+    /// Whether our power level lets us pin and unpin.
+    pub can_pin: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct PinnedMessage {
+    pub event_id: String,
+    /// `None` if the event couldn't be fetched.
+    pub sender: Option<String>,
+    /// Text to show: the message body, or a note saying why there isn't one.
+    pub body: String,
+    /// Milliseconds since the Unix epoch; `None` if the event couldn't be
+    /// fetched.
+    pub ts: Option<u64>,
+}
+
+impl SpokeClient {
+    /// The messages pinned in `room_id`, with their content.
+    pub async fn pinned_messages(&self, room_id: &RoomId) -> Result<Pins, MatrixError> {
+        let room = self
+            .inner
+            .get_room(room_id)
+            .ok_or_else(|| MatrixError::NotFound(room_id.to_string()))?;
+        let can_pin = room
+            .can_user_send_state(self.own_user_id()?, StateEventType::RoomPinnedEvents)
+            .await?;
+        let mut messages = Vec::new();
+        for event_id in pinned_event_ids(&room).await?.iter().rev() {
+            messages.push(pinned_message(&room, event_id).await);
+        }
+        Ok(Pins { messages, can_pin })
+    }
+
+    /// Pin or unpin `event_id` in `room_id`. Pinning puts it at the end of
+    /// the list, as the newest pin.
+    pub async fn set_pinned(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        pinned: bool,
+    ) -> Result<(), MatrixError> {
+        let room = self
+            .inner
+            .get_room(room_id)
+            .ok_or_else(|| MatrixError::NotFound(room_id.to_string()))?;
+        if !room
+            .can_user_send_state(self.own_user_id()?, StateEventType::RoomPinnedEvents)
+            .await?
+        {
+            return Err(MatrixError::Forbidden(
+                "your power level doesn't allow you to pin messages here".into(),
+            ));
+        }
+        let mut pinned_ids = pinned_event_ids(&room).await?;
+        if pinned_ids.iter().any(|id| id == event_id) == pinned {
+            return Ok(());
+        }
+        if pinned {
+            pinned_ids.push(event_id.to_owned());
+        } else {
+            pinned_ids.retain(|id| id != event_id);
+        }
+        let content = RoomPinnedEventsEventContent::new(pinned_ids);
+        self.scheduled("pin message", || async {
+            room.send_state_event(content.clone()).await?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// The pinned event IDs, oldest pin first.
+async fn pinned_event_ids(room: &Room) -> Result<Vec<OwnedEventId>, MatrixError> {
+    let Some(raw) = room
+        .get_state_event_static::<RoomPinnedEventsEventContent>()
+        .await?
+    else {
+        return Ok(Vec::new());
+    };
+    Ok(match raw.deserialize() {
+        Ok(SyncOrStrippedState::Sync(SyncStateEvent::Original(ev))) => ev.content.pinned,
+        Ok(_) => Vec::new(),
+        Err(e) => {
+            warn!("unreadable pinned events in {}: {e}", room.room_id());
+            Vec::new()
+        }
+    })
+}
+
+/// `event_id` resolved for display, or a placeholder saying why it can't be.
+async fn pinned_message(room: &Room, event_id: &EventId) -> PinnedMessage {
+    let unavailable = |body: &str| PinnedMessage {
+        event_id: event_id.to_string(),
+        sender: None,
+        body: body.to_owned(),
+        ts: None,
+    };
+    let event = match room.event(event_id).await {
+        Ok(event) => event,
+        Err(e) => {
+            warn!("pinned event {event_id}: {e}");
+            return unavailable("Message not found");
+        }
+    };
+    let Ok(event) = event.event.deserialize() else {
+        return unavailable("Unreadable message");
+    };
+    let body = match &event {
+        AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomMessage(ev)) => {
+            match ev.as_original() {
+                Some(original) => original.content.body().to_owned(),
+                None => "Message deleted".to_owned(),
+            }
+        }
+        AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomEncrypted(_)) => {
+            "Unable to decrypt this message".to_owned()
+        }
+        AnyTimelineEvent::MessageLike(ev) if ev.original_content().is_none() => {
+            "Message deleted".to_owned()
+        }
+        _ => format!("({})", event.event_type()),
+    };
+    PinnedMessage {
+        event_id: event_id.to_string(),
+        sender: Some(event.sender().to_string()),
+        body,
+        ts: Some(u64::from(event.origin_server_ts().0)),
+    }
+}
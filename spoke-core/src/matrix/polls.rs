@@ -0,0 +1,276 @@
+// Polls (MSC3381) — start, response and end events under the unstable
+// prefixes current clients send, and the MSC's tallying rules: each voter's
+// latest response before the end counts, answers past `max_selections` or
+// not in the poll are ignored, an empty response withdraws the vote, and only
+// the poll's creator can end it.
+
+use std::collections::HashMap;
+
+use matrix_sdk::ruma::{EventId, OwnedEventId, RoomId, events::macros::EventContent};
+use serde::{Deserialize, Serialize};
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+/// A poll's question and options, as carried by its start event.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Poll {
+    pub question: PollText,
+    #[serde(default)]
+    pub kind: PollKind,
+    #[serde(default = "one")]
+    pub max_selections: u32,
+    pub answers: Vec<PollAnswer>,
+}
+
+fn one() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PollText {
+    #[serde(rename = "org.matrix.msc1767.text")]
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PollAnswer {
+    pub id: String,
+    #[serde(rename = "org.matrix.msc1767.text")]
+    pub text: String,
+}
+
+/// Whether results are visible while the poll is open.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PollKind {
+    #[serde(rename = "org.matrix.msc3381.poll.disclosed")]
+    Disclosed,
+    /// Results only once the poll ends. Unknown kinds are treated as this.
+    #[default]
+    #[serde(rename = "org.matrix.msc3381.poll.undisclosed", other)]
+    Undisclosed,
+}
+
+/// `m.reference` to the poll's start event.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PollReference {
+    rel_type: String,
+    pub event_id: OwnedEventId,
+}
+
+impl PollReference {
+    pub fn new(event_id: OwnedEventId) -> Self {
+        Self {
+            rel_type: "m.reference".to_owned(),
+            event_id,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, EventContent)]
+#[ruma_event(type = "org.matrix.msc3381.poll.start", kind = MessageLike)]
+pub struct PollStartEventContent {
+    #[serde(rename = "org.matrix.msc3381.poll.start")]
+    pub poll: Poll,
+    /// Fallback for clients without poll support.
+    #[serde(
+        rename = "org.matrix.msc1767.text",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub fallback: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, EventContent)]
+#[ruma_event(type = "org.matrix.msc3381.poll.response", kind = MessageLike)]
+pub struct PollResponseEventContent {
+    #[serde(rename = "m.relates_to")]
+    pub relates_to: PollReference,
+    #[serde(rename = "org.matrix.msc3381.poll.response")]
+    pub response: PollSelection,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PollSelection {
+    #[serde(default)]
+    pub answers: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, EventContent)]
+#[ruma_event(type = "org.matrix.msc3381.poll.end", kind = MessageLike)]
+pub struct PollEndEventContent {
+    #[serde(rename = "m.relates_to")]
+    pub relates_to: PollReference,
+    #[serde(rename = "org.matrix.msc3381.poll.end", default)]
+    end: PollEndMarker,
+    #[serde(
+        rename = "org.matrix.msc1767.text",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub fallback: Option<String>,
+}
+
+/// The empty `org.matrix.msc3381.poll.end` object.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct PollEndMarker {}
+
+/// Responses to one poll and claimed ends, in whatever order they arrive
+/// (history can deliver them before the start event).
+#[derive(Debug, Clone, Default)]
+pub struct PollVotes {
+    /// Every response per voter as `(ts, answer IDs)`.
+    responses: HashMap<String, Vec<(u64, Vec<String>)>>,
+    /// `(sender, ts)` of each end event; only the creator's counts.
+    ends: Vec<(String, u64)>,
+}
+
+impl PollVotes {
+    pub fn add_response(&mut self, sender: &str, ts: u64, answers: Vec<String>) {
+        self.responses
+            .entry(sender.to_owned())
+            .or_default()
+            .push((ts, answers));
+    }
+
+    pub fn add_end(&mut self, sender: &str, ts: u64) {
+        self.ends.push((sender.to_owned(), ts));
+    }
+}
+
+/// A poll's standing, for display.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PollResults {
+    /// Votes per answer, in the poll's answer order.
+    pub counts: Vec<u32>,
+    /// People whose vote counts.
+    pub voters: u32,
+    /// Our own current selection.
+    pub own: Vec<String>,
+    pub ended: bool,
+}
+
+impl Poll {
+    /// Tally `votes` for this poll, created by `creator`.
+    pub fn tally(&self, creator: &str, votes: &PollVotes, own_user_id: &str) -> PollResults {
+        let end = votes
+            .ends
+            .iter()
+            .filter(|(sender, _)| sender == creator)
+            .map(|(_, ts)| *ts)
+            .min();
+        let mut counts = vec![0; self.answers.len()];
+        let mut voters = 0;
+        let mut own = Vec::new();
+        for (voter, responses) in &votes.responses {
+            let latest = responses
+                .iter()
+                .filter(|(ts, _)| end.is_none_or(|end| *ts <= end))
+                .max_by_key(|(ts, _)| *ts);
+            let Some((_, answers)) = latest else { continue };
+            let chosen: Vec<usize> = answers
+                .iter()
+                .take(self.max_selections.max(1) as usize)
+                .filter_map(|id| self.answers.iter().position(|a| &a.id == id))
+                .collect();
+            if chosen.is_empty() {
+                continue;
+            }
+            voters += 1;
+            for &i in &chosen {
+                counts[i] += 1;
+            }
+            if voter == own_user_id {
+                own = chosen.iter().map(|&i| self.answers[i].id.clone()).collect();
+            }
+        }
+        PollResults {
+            counts,
+            voters,
+            own,
+            ended: end.is_some(),
+        }
+    }
+
+    /// The question and numbered answers as plain text, for the fallback
+    /// body and the timeline cache.
+    pub fn describe(&self) -> String {
+        let mut text = self.question.text.clone();
+        for (i, answer) in self.answers.iter().enumerate() {
+            text.push_str(&format!("\n{}. {}", i + 1, answer.text));
+        }
+        text
+    }
+}
+
+impl SpokeClient {
+    /// Post a poll. Answers get IDs by position. Returns the start event's
+    /// ID, which responses and the end refer to.
+    pub async fn start_poll(
+        &self,
+        room_id: &RoomId,
+        question: &str,
+        answers: &[String],
+        max_selections: u32,
+        kind: PollKind,
+    ) -> Result<OwnedEventId, MatrixError> {
+        let room = self
+            .inner
+            .get_room(room_id)
+            .ok_or_else(|| MatrixError::NotFound(room_id.to_string()))?;
+        let poll = Poll {
+            question: PollText {
+                text: question.to_owned(),
+            },
+            kind,
+            max_selections: max_selections.clamp(1, answers.len().max(1) as u32),
+            answers: answers
+                .iter()
+                .enumerate()
+                .map(|(i, text)| PollAnswer {
+                    id: format!("answer-{i}"),
+                    text: text.clone(),
+                })
+                .collect(),
+        };
+        let fallback = Some(poll.describe());
+        Ok(room
+            .send(PollStartEventContent { poll, fallback })
+            .await?
+            .event_id)
+    }
+
+    /// Vote in the poll started by `poll_id`. An empty `answers` withdraws
+    /// our vote.
+    pub async fn vote_poll(
+        &self,
+        room_id: &RoomId,
+        poll_id: &EventId,
+        answers: Vec<String>,
+    ) -> Result<(), MatrixError> {
+        let room = self
+            .inner
+            .get_room(room_id)
+            .ok_or_else(|| MatrixError::NotFound(room_id.to_string()))?;
+        let content = PollResponseEventContent {
+            relates_to: PollReference::new(poll_id.to_owned()),
+            response: PollSelection { answers },
+        };
+        room.send(content).await?;
+        Ok(())
+    }
+
+    /// Close a poll we started. Other clients ignore ends from anyone else.
+    pub async fn end_poll(&self, room_id: &RoomId, poll_id: &EventId) -> Result<(), MatrixError> {
+        let room = self
+            .inner
+            .get_room(room_id)
+            .ok_or_else(|| MatrixError::NotFound(room_id.to_string()))?;
+        let content = PollEndEventContent {
+            relates_to: PollReference::new(poll_id.to_owned()),
+            end: PollEndMarker::default(),
+            fallback: Some("The poll has ended.".to_owned()),
+        };
+        room.send(content).await?;
+        Ok(())
+    }
+}
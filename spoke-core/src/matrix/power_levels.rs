@@ -0,0 +1,219 @@
+// Room power levels — read the `m.room.power_levels` state event and apply
+// single changes to it (promote a user, events_default, who may use voice).
+//
+// Changes are read-modify-write against a fresh copy fetched from the server,
+// not the sync cache, so a concurrent edit by another moderator is never
+// clobbered by a stale local view. The spec's rules for who may change what
+// are checked up front so the UI gets a clear refusal instead of a bare 403.
+
+use matrix_sdk::ruma::{
+    Int, OwnedUserId, RoomId, UserId,
+    api::client::state::get_state_events_for_key,
+    events::{StateEventType, TimelineEventType, room::power_levels::RoomPowerLevelsEventContent},
+};
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+/// Conventional moderator level.
+pub const MODERATOR_LEVEL: i64 = 50;
+/// Conventional admin level.
+pub const ADMIN_LEVEL: i64 = 100;
+
+/// Message-like Spoke voice events, kept at the voice level.
+const VOICE_EVENTS: [&str; 2] = ["org.spoke.voice.mute", "org.spoke.voice.hand"];
+
+/// Call membership state events: ours, whose level decides who can join a
+/// call, and MatrixRTC's, so whoever may join a Spoke call may also say so
+/// to other clients.
+const VOICE_STATE_EVENTS: [&str; 2] = ["org.spoke.voice.member", "m.call.member"];
+
+/// A room's power levels, flattened for display.
+#[derive(Debug, Clone)]
+pub struct PowerLevels {
+    /// Users with an explicit level, highest first.
+    pub users: Vec<(String, i64)>,
+    pub users_default: i64,
+    pub events_default: i64,
+    pub state_default: i64,
+    pub kick: i64,
+    pub ban: i64,
+    pub redact: i64,
+    pub invite: i64,
+    /// Level needed to send `org.spoke.voice.*` call events.
+    pub voice: i64,
+    /// Our own level in the room.
+    pub own: i64,
+    /// Whether we may edit the power levels at all.
+    pub can_edit: bool,
+}
+
+/// One edit to a room's power levels.
+#[derive(Debug, Clone)]
+pub enum PowerLevelChange {
+    /// Set a user's level; `users_default` removes the explicit entry.
+    User { user_id: String, level: i64 },
+    /// Level needed to send ordinary messages.
+    EventsDefault(i64),
+    /// Level needed to send `org.spoke.voice.*` call events.
+    Voice(i64),
+}
+
+impl SpokeClient {
+    /// The current power levels of `room_id`, fetched from the server.
+    pub async fn power_levels(&self, room_id: &RoomId) -> Result<PowerLevels, MatrixError> {
+        let own = self.own_user_id()?;
+        let content = self.fetch_power_levels(room_id).await?;
+        let level = |v: Int| i64::from(v);
+
+        let mut users: Vec<(String, i64)> = content
+            .users
+            .iter()
+            .map(|(u, l)| (u.to_string(), level(*l)))
+            .collect();
+        users.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let own_level = user_level(&content, own);
+        let can_edit = own_level >= event_level(&content, "m.room.power_levels", true);
+        Ok(PowerLevels {
+            users,
+            users_default: level(content.users_default),
+            events_default: level(content.events_default),
+            state_default: level(content.state_default),
+            kick: level(content.kick),
+            ban: level(content.ban),
+            redact: level(content.redact),
+            invite: level(content.invite),
+            voice: event_level(&content, VOICE_STATE_EVENTS[0], true),
+            own: own_level,
+            can_edit,
+        })
+    }
+
+    /// Apply `change` to the power levels of `room_id`.
+    ///
+    /// Fails with `MatrixError::Forbidden` if the change would touch a level
+    /// at or above our own, or if we can't edit power levels at all.
+    pub async fn set_power_levels(
+        &self,
+        room_id: &RoomId,
+        change: PowerLevelChange,
+    ) -> Result<(), MatrixError> {
+        let room = self
+            .inner
+            .get_room(room_id)
+            .ok_or_else(|| MatrixError::NotFound(room_id.to_string()))?;
+        let own = self.own_user_id()?;
+        let mut content = self.fetch_power_levels(room_id).await?;
+        let own_level = user_level(&content, own);
+
+        let edit_level = event_level(&content, "m.room.power_levels", true);
+        if own_level < edit_level {
+            return Err(MatrixError::Forbidden(format!(
+                "changing power levels needs level {edit_level}, yours is {own_level}"
+            )));
+        }
+
+        match change {
+            PowerLevelChange::User { user_id, level } => {
+                let target: OwnedUserId = user_id
+                    .parse()
+                    .map_err(|_| MatrixError::InvalidUserId(user_id.clone()))?;
+                let current = user_level(&content, &target);
+                // You may lower yourself, but not anyone at or above you.
+                if target != own && current >= own_level {
+                    return Err(MatrixError::Forbidden(format!(
+                        "{target} has power level {current}, not below yours ({own_level})"
+                    )));
+                }
+                check_raise(level, own_level)?;
+                if level == i64::from(content.users_default) {
+                    content.users.remove(&target);
+                } else {
+                    content.users.insert(target, Int::new_saturating(level));
+                }
+            }
+            PowerLevelChange::EventsDefault(level) => {
+                check_raise(i64::from(content.events_default).max(level), own_level)?;
+                content.events_default = Int::new_saturating(level);
+            }
+            PowerLevelChange::Voice(level) => {
+                for event_type in VOICE_EVENTS {
+                    check_raise(
+                        event_level(&content, event_type, false).max(level),
+                        own_level,
+                    )?;
+                }
+                for event_type in VOICE_STATE_EVENTS {
+                    check_raise(
+                        event_level(&content, event_type, true).max(level),
+                        own_level,
+                    )?;
+                }
+                for event_type in VOICE_EVENTS.into_iter().chain(VOICE_STATE_EVENTS) {
+                    content.events.insert(
+                        TimelineEventType::from(event_type),
+                        Int::new_saturating(level),
+                    );
+                }
+            }
+        }
+
+        room.send_state_event(content).await?;
+        Ok(())
+    }
+
+    async fn fetch_power_levels(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<RoomPowerLevelsEventContent, MatrixError> {
+        let request = get_state_events_for_key::v3::Request::new(
+            room_id.to_owned(),
+            StateEventType::RoomPowerLevels,
+            String::new(),
+        );
+        let response = self.inner.send(request, None).await?;
+        response
+            .content
+            .deserialize_as::<RoomPowerLevelsEventContent>()
+            .map_err(|e| MatrixError::Sdk(e.into()))
+    }
+
+    pub(crate) fn own_user_id(&self) -> Result<&UserId, MatrixError> {
+        self.inner
+            .user_id()
+            .ok_or_else(|| MatrixError::NotFound("own user id".into()))
+    }
+}
+
+fn user_level(content: &RoomPowerLevelsEventContent, user_id: &UserId) -> i64 {
+    content
+        .users
+        .get(user_id)
+        .copied()
+        .unwrap_or(content.users_default)
+        .into()
+}
+
+fn event_level(content: &RoomPowerLevelsEventContent, event_type: &str, state: bool) -> i64 {
+    let default = if state {
+        content.state_default
+    } else {
+        content.events_default
+    };
+    content
+        .events
+        .get(&TimelineEventType::from(event_type))
+        .copied()
+        .unwrap_or(default)
+        .into()
+}
+
+/// Nobody may hand out (or take back) a level above their own.
+fn check_raise(level: i64, own_level: i64) -> Result<(), MatrixError> {
+    if level > own_level {
+        return Err(MatrixError::Forbidden(format!(
+            "can't set a level of {level}, above your own ({own_level})"
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,18 @@
+// Presence — our own online status as other users see it. Receiving other
+// people's presence is up to the sync filter; this only sets ours.
+
+use matrix_sdk::ruma::{api::client::presence::set_presence, presence::PresenceState};
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+impl SpokeClient {
+    /// Set how we appear to others. `Offline` while connected is how
+    /// "appear offline" works; the sync loop must then also sync with
+    /// `set_presence(PresenceState::Offline)` or the server flips us back
+    /// online on the next request.
+    pub async fn set_presence(&self, presence: PresenceState) -> Result<(), MatrixError> {
+        let request = set_presence::v3::Request::new(self.own_user_id()?.to_owned(), presence);
+        self.inner.send(request, None).await?;
+        Ok(())
+    }
+}
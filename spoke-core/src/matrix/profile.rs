@@ -0,0 +1,88 @@
+// User profiles — set our own display name and avatar, and resolve other
+// users' names and avatars for rendering senders. A room's member state is
+// checked first since it's local and carries per-room names; the profile API
+// is the fallback for users we share no loaded state with.
+
+use matrix_sdk::{
+    media::{MediaFormat, MediaRequest, MediaThumbnailSettings},
+    ruma::{
+        OwnedMxcUri, RoomId, UserId,
+        api::client::{media::get_content_thumbnail::v3::Method, profile::get_profile},
+        events::room::MediaSource,
+    },
+};
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+/// A user's display name and avatar.
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    pub display_name: Option<String>,
+    /// `mxc://` URI of the avatar, if any.
+    pub avatar_url: Option<String>,
+}
+
+impl SpokeClient {
+    /// Set (or with `None`, clear) our global display name.
+    pub async fn set_display_name(&self, name: Option<&str>) -> Result<(), MatrixError> {
+        self.inner.account().set_display_name(name).await?;
+        Ok(())
+    }
+
+    /// Upload `data` as our avatar. Returns the new `mxc://` URI.
+    pub async fn set_avatar(
+        &self,
+        content_type: &str,
+        data: Vec<u8>,
+    ) -> Result<String, MatrixError> {
+        let mime: mime::Mime = content_type
+            .parse()
+            .map_err(|_| MatrixError::InvalidMediaType(content_type.to_owned()))?;
+        let url = self.inner.account().upload_avatar(&mime, data).await?;
+        Ok(url.to_string())
+    }
+
+    pub async fn remove_avatar(&self) -> Result<(), MatrixError> {
+        self.inner.account().set_avatar_url(None).await?;
+        Ok(())
+    }
+
+    /// `user_id`'s profile as seen in `room_id`, falling back to their global
+    /// profile if they aren't a known member there.
+    pub async fn user_profile(
+        &self,
+        user_id: &UserId,
+        room_id: Option<&RoomId>,
+    ) -> Result<Profile, MatrixError> {
+        if let Some(room) = room_id.and_then(|id| self.inner.get_room(id)) {
+            if let Some(member) = room.get_member_no_sync(user_id).await? {
+                return Ok(Profile {
+                    display_name: member.display_name().map(str::to_owned),
+                    avatar_url: member.avatar_url().map(|u| u.to_string()),
+                });
+            }
+        }
+        let response = self
+            .inner
+            .send(get_profile::v3::Request::new(user_id.to_owned()), None)
+            .await?;
+        Ok(Profile {
+            display_name: response.displayname,
+            avatar_url: response.avatar_url.map(|u| u.to_string()),
+        })
+    }
+
+    /// A `size`×`size` cropped thumbnail of the avatar at `mxc`, cached in
+    /// the media store.
+    pub async fn avatar_thumbnail(&self, mxc: &str, size: u32) -> Result<Vec<u8>, MatrixError> {
+        let request = MediaRequest {
+            source: MediaSource::Plain(OwnedMxcUri::from(mxc)),
+            format: MediaFormat::Thumbnail(MediaThumbnailSettings::new(
+                Method::Crop,
+                size.into(),
+                size.into(),
+            )),
+        };
+        Ok(self.inner.media().get_media_content(&request, true).await?)
+    }
+}
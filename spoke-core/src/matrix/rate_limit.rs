@@ -0,0 +1,157 @@
+// Rate limiting — a homeserver that's had enough answers 429
+// `M_LIMIT_EXCEEDED`, usually saying how long to wait. Requests made through
+// `SpokeClient::scheduled` wait out that delay and try again instead of
+// failing, and while one is waiting every other scheduled request holds back
+// too, since the limit is per account rather than per endpoint.
+//
+// Whoever wants to tell the user watches `rate_limit_status`: it reads
+// `Some` while requests are held back and `None` once they're flowing again.
+
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use matrix_sdk::ruma::api::client::error::{ErrorKind, RetryAfter};
+use tokio::sync::watch;
+use tracing::warn;
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+/// Wait when a 429 doesn't say how long.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(5);
+/// Longest single wait honoured; a server asking for more gets asked again.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(120);
+/// Tries per request before its rate-limit error is handed back.
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Requests are being held back by the homeserver's rate limit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimited {
+    /// The request that was refused, e.g. "send reaction".
+    pub what: String,
+    /// When requests go out again.
+    pub until: SystemTime,
+}
+
+/// The shared hold on requests; one per client.
+#[derive(Clone)]
+pub(crate) struct RateLimiter {
+    until: Arc<Mutex<Option<Instant>>>,
+    status: Arc<watch::Sender<Option<RateLimited>>>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self {
+            until: Arc::default(),
+            status: Arc::new(watch::channel(None).0),
+        }
+    }
+}
+
+impl RateLimiter {
+    /// Wait until any current hold is over.
+    pub(crate) async fn ready(&self) {
+        loop {
+            let until = *self.until.lock().unwrap();
+            match until {
+                Some(until) if until > Instant::now() => {
+                    tokio::time::sleep_until(until.into()).await
+                }
+                _ => return,
+            }
+        }
+    }
+
+    /// Hold every scheduled request back for `delay`.
+    pub(crate) fn hold(&self, what: &str, delay: Duration) {
+        let delay = delay.min(MAX_RETRY_AFTER);
+        let until = Instant::now() + delay;
+        {
+            let mut current = self.until.lock().unwrap();
+            if current.is_some_and(|c| c >= until) {
+                return;
+            }
+            *current = Some(until);
+        }
+        warn!("rate limited ({what}), holding requests for {delay:?}");
+        self.status.send_replace(Some(RateLimited {
+            what: what.to_owned(),
+            until: SystemTime::now() + delay,
+        }));
+    }
+
+    /// A request went through; clear the status if the hold is over.
+    pub(crate) fn clear(&self) {
+        let mut current = self.until.lock().unwrap();
+        if current.is_some_and(|c| c <= Instant::now()) {
+            *current = None;
+            self.status.send_if_modified(|s| s.take().is_some());
+        }
+    }
+}
+
+impl MatrixError {
+    /// How long the server asked us to wait, if this is a rate-limit error.
+    pub fn retry_after(&self) -> Option<Duration> {
+        let kind = match self {
+            MatrixError::Sdk(e) => e.client_api_error_kind(),
+            MatrixError::Http(e) => e.client_api_error_kind(),
+            _ => None,
+        }?;
+        let ErrorKind::LimitExceeded { retry_after } = kind else {
+            return None;
+        };
+        Some(match retry_after {
+            Some(RetryAfter::Delay(delay)) => *delay,
+            Some(RetryAfter::DateTime(at)) => {
+                at.duration_since(SystemTime::now()).unwrap_or_default()
+            }
+            None => DEFAULT_RETRY_AFTER,
+        })
+    }
+}
+
+impl SpokeClient {
+    /// Run `request`, waiting out and retrying rate-limit errors. `what`
+    /// names it in the status and logs. Other errors come straight back.
+    pub async fn scheduled<T, F, Fut>(&self, what: &str, mut request: F) -> Result<T, MatrixError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, MatrixError>>,
+    {
+        let mut attempt = 1;
+        loop {
+            self.rate_limit.ready().await;
+            match request().await {
+                Err(e) if attempt < MAX_ATTEMPTS => {
+                    let Some(delay) = e.retry_after() else {
+                        return Err(e);
+                    };
+                    self.rate_limit.hold(what, delay);
+                    attempt += 1;
+                }
+                result => {
+                    if result.is_ok() {
+                        self.rate_limit.clear();
+                    }
+                    return result;
+                }
+            }
+        }
+    }
+
+    /// Hold scheduled requests back for `delay`, for a rate limit hit by a
+    /// request made some other way (e.g. the sync loop).
+    pub fn hold_requests(&self, what: &str, delay: Duration) {
+        self.rate_limit.hold(what, delay);
+    }
+
+    /// Whether requests are being held back, and until when. Changes as
+    /// holds start and end.
+    pub fn rate_limit_status(&self) -> watch::Receiver<Option<RateLimited>> {
+        self.rate_limit.status.subscribe()
+    }
+}
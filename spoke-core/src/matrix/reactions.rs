@@ -0,0 +1,42 @@
+// Reactions — `m.reaction` annotations on timeline events. Taking a reaction
+// back is a redaction of the reaction event itself.
+
+use matrix_sdk::ruma::{
+    EventId, OwnedEventId, RoomId,
+    events::{reaction::ReactionEventContent, relation::Annotation},
+};
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+impl SpokeClient {
+    /// React to `event_id` with `key` (usually an emoji). Returns the
+    /// reaction's event ID, needed to take it back.
+    pub async fn react(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        key: &str,
+    ) -> Result<OwnedEventId, MatrixError> {
+        let room = self
+            .inner
+            .get_room(room_id)
+            .ok_or_else(|| MatrixError::NotFound(room_id.to_string()))?;
+        let content =
+            ReactionEventContent::new(Annotation::new(event_id.to_owned(), key.to_owned()));
+        Ok(room.send(content).await?.event_id)
+    }
+
+    /// Take back one of our reactions.
+    pub async fn unreact(
+        &self,
+        room_id: &RoomId,
+        reaction_id: &EventId,
+    ) -> Result<(), MatrixError> {
+        let room = self
+            .inner
+            .get_room(room_id)
+            .ok_or_else(|| MatrixError::NotFound(room_id.to_string()))?;
+        room.redact(reaction_id, None, None).await?;
+        Ok(())
+    }
+}
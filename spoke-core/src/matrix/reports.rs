@@ -0,0 +1,76 @@
+// Reporting abuse to the homeserver's admins — single events through the
+// content report endpoint, and whole rooms through the room report endpoint
+// (Matrix 1.13). Our ruma predates the latter, so it's sent by hand with the
+// session's access token.
+
+use matrix_sdk::ruma::{EventId, Int, RoomId, api::client::room::report_content};
+use serde_json::json;
+
+use crate::{
+    matrix::{SpokeClient, error::MatrixError},
+    proxy,
+};
+
+/// Report scores run from -100 (most offensive) to 0.
+const MOST_OFFENSIVE: i32 = -100;
+
+impl SpokeClient {
+    /// Report `event_id` in `room_id`. `score` is clamped to -100..=0.
+    pub async fn report_event(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        score: Option<i32>,
+        reason: Option<&str>,
+    ) -> Result<(), MatrixError> {
+        let request = report_content::v3::Request::new(
+            room_id.to_owned(),
+            event_id.to_owned(),
+            score.map(|s| Int::from(s.clamp(MOST_OFFENSIVE, 0))),
+            reason.map(str::to_owned),
+        );
+        self.inner.send(request, None).await?;
+        Ok(())
+    }
+
+    /// Report `room_id` as a whole. Fails with `MatrixError::NotFound` when
+    /// the homeserver doesn't offer room reports.
+    pub async fn report_room(&self, room_id: &RoomId, reason: &str) -> Result<(), MatrixError> {
+        let token = self
+            .inner
+            .access_token()
+            .ok_or_else(|| MatrixError::Forbidden("not logged in".to_owned()))?;
+        let mut url = self.inner.homeserver();
+        url.path_segments_mut()
+            .expect("homeserver URL can have a path")
+            .pop_if_empty()
+            .extend([
+                "_matrix",
+                "client",
+                "v3",
+                "rooms",
+                room_id.as_str(),
+                "report",
+            ]);
+        let response = proxy::http_client()?
+            .post(url)
+            .bearer_auth(token)
+            .json(&json!({ "reason": reason }))
+            .send()
+            .await?;
+        match response.status() {
+            reqwest::StatusCode::NOT_FOUND | reqwest::StatusCode::METHOD_NOT_ALLOWED => {
+                Err(MatrixError::NotFound(
+                    "room reports aren't supported by this homeserver".to_owned(),
+                ))
+            }
+            reqwest::StatusCode::FORBIDDEN => Err(MatrixError::Forbidden(
+                response.text().await.unwrap_or_default(),
+            )),
+            _ => {
+                response.error_for_status()?;
+                Ok(())
+            }
+        }
+    }
+}
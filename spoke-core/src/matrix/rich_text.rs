@@ -0,0 +1,498 @@
+// Rich text — Markdown in, `org.matrix.custom.html` out, and the reverse:
+// incoming formatted bodies parsed into a small block/span model the UI can
+// draw. Only the subset other clients actually send is understood (emphasis,
+// code, links, lists, quotes, headings, spoilers); anything else degrades to
+// its text.
+//
+// Parsing doubles as sanitization: nothing from the HTML reaches the UI
+// except text, the style flags below, and links whose scheme is on the
+// spec's allowlist. Content of `<script>`, `<style>` and the like is dropped.
+
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd, html};
+
+/// Render a Markdown message to HTML. `None` if it has no formatting, in
+/// which case it should go out as plain text only.
+pub fn markdown_to_html(source: &str) -> Option<String> {
+    let events: Vec<Event> = Parser::new_ext(source, Options::ENABLE_STRIKETHROUGH)
+        .map(|e| match e {
+            // Matrix clients keep single newlines; CommonMark folds them.
+            Event::SoftBreak => Event::HardBreak,
+            // Typed HTML is shown as typed, never passed through.
+            Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+            e => e,
+        })
+        .collect();
+    let mut paragraphs = 0;
+    let formatted = events.iter().any(|e| match e {
+        Event::Start(Tag::Paragraph) => {
+            paragraphs += 1;
+            paragraphs > 1
+        }
+        Event::End(TagEnd::Paragraph) | Event::Text(_) | Event::HardBreak => false,
+        _ => true,
+    });
+    if !formatted {
+        return None;
+    }
+    let mut out = String::new();
+    html::push_html(&mut out, events.into_iter());
+    Some(out.trim_end().to_owned())
+}
+
+/// Inline formatting of a run of text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SpanStyle {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strike: bool,
+    pub code: bool,
+    /// `data-mx-spoiler`: hidden until the reader asks.
+    pub spoiler: bool,
+}
+
+/// A run of text with one style, optionally a link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub text: String,
+    pub style: SpanStyle,
+    pub link: Option<String>,
+    /// `mxc://` URI of a custom emote (`<img data-mx-emoticon>`); `text` is
+    /// its shortcode, shown until the image loads.
+    pub emote: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Block {
+    Paragraph(Vec<Span>),
+    /// Level 1–6.
+    Heading(u8, Vec<Span>),
+    Quote(Vec<Span>),
+    Code {
+        language: Option<String>,
+        code: String,
+    },
+    /// Nested lists are flattened into their parent.
+    List {
+        ordered: bool,
+        items: Vec<Vec<Span>>,
+    },
+    /// `<hr>`.
+    Rule,
+}
+
+/// A formatted message body as blocks of styled text.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RichText {
+    pub blocks: Vec<Block>,
+}
+
+impl RichText {
+    /// Parse a formatted body. Never fails: unknown tags are dropped and
+    /// their text kept; the `mx-reply` fallback is skipped entirely.
+    pub fn from_html(html: &str) -> Self {
+        let mut p = HtmlParser::default();
+        let mut rest = html;
+        while !rest.is_empty() {
+            match rest.find('<') {
+                Some(0) => {
+                    let end = rest.find('>').map_or(rest.len(), |i| i + 1);
+                    p.tag(&rest[1..end.saturating_sub(1).max(1)]);
+                    rest = &rest[end..];
+                }
+                Some(i) => {
+                    p.text(&decode_entities(&rest[..i]));
+                    rest = &rest[i..];
+                }
+                None => {
+                    p.text(&decode_entities(rest));
+                    rest = "";
+                }
+            }
+        }
+        p.finish()
+    }
+
+    /// The text with formatting dropped, one line per block.
+    pub fn plain(&self) -> String {
+        let spans = |s: &[Span]| s.iter().map(|s| s.text.as_str()).collect::<String>();
+        self.blocks
+            .iter()
+            .map(|b| match b {
+                Block::Paragraph(s) | Block::Heading(_, s) | Block::Quote(s) => spans(s),
+                Block::Code { code, .. } => code.clone(),
+                Block::List { items, .. } => items
+                    .iter()
+                    .map(|i| spans(i))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                Block::Rule => String::new(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Container {
+    Paragraph,
+    Heading(u8),
+}
+
+#[derive(Default)]
+struct HtmlParser {
+    blocks: Vec<Block>,
+    spans: Vec<Span>,
+    container: Option<Container>,
+    bold: u32,
+    italic: u32,
+    underline: u32,
+    strike: u32,
+    code: u32,
+    spoiler: u32,
+    links: Vec<Option<String>>,
+    /// Open `<span>`/`<font>` tags, innermost last: whether each is a spoiler.
+    inline: Vec<bool>,
+    /// Inside `<pre>`: the language and the code so far.
+    pre: Option<(Option<String>, String)>,
+    /// Open lists, innermost last; items collect into the outermost.
+    lists: Vec<bool>,
+    items: Vec<Vec<Span>>,
+    in_item: bool,
+    /// Depth inside `<blockquote>`.
+    quote: u32,
+    /// Depth inside tags whose content is dropped (`mx-reply`, `script`…).
+    skip: u32,
+}
+
+/// Tags whose content never reaches the reader: the reply fallback (shown
+/// from the replied-to event instead) and anything that isn't text.
+const SKIPPED_TAGS: [&str; 8] = [
+    "mx-reply", "script", "style", "head", "title", "iframe", "object", "svg",
+];
+
+/// Link schemes the spec allows in formatted bodies.
+const LINK_SCHEMES: [&str; 5] = ["https", "http", "ftp", "mailto", "magnet"];
+
+impl HtmlParser {
+    fn tag(&mut self, tag: &str) {
+        let closing = tag.starts_with('/');
+        let tag = tag.trim_start_matches('/').trim_end_matches('/');
+        let (name, attrs) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+        let name = name.to_ascii_lowercase();
+
+        if SKIPPED_TAGS.contains(&name.as_str()) {
+            bump(&mut self.skip, closing);
+            return;
+        }
+        if self.skip > 0 {
+            return;
+        }
+        if self.pre.is_some() {
+            match (name.as_str(), closing) {
+                ("pre", true) => {
+                    let (language, mut code) = self.pre.take().unwrap_or_default();
+                    if code.ends_with('\n') {
+                        code.pop();
+                    }
+                    self.blocks.push(Block::Code { language, code });
+                }
+                ("code", false) => {
+                    let language = attr(attrs, "class").and_then(|c| {
+                        c.split_whitespace()
+                            .find_map(|c| c.strip_prefix("language-"))
+                            .map(str::to_owned)
+                    });
+                    if let Some(pre) = &mut self.pre {
+                        pre.0 = language;
+                    }
+                }
+                ("br", _) => self.text("\n"),
+                _ => {}
+            }
+            return;
+        }
+
+        match (name.as_str(), closing) {
+            ("b" | "strong", _) => bump(&mut self.bold, closing),
+            ("i" | "em", _) => bump(&mut self.italic, closing),
+            ("u", _) => bump(&mut self.underline, closing),
+            ("del" | "s" | "strike", _) => bump(&mut self.strike, closing),
+            ("code", _) => bump(&mut self.code, closing),
+            ("a", false) => self
+                .links
+                .push(attr(attrs, "href").filter(|href| safe_link(href))),
+            ("a", true) => {
+                self.links.pop();
+            }
+            ("span" | "font", false) => {
+                let spoiler = has_attr(attrs, "data-mx-spoiler");
+                if spoiler {
+                    self.spoiler += 1;
+                }
+                self.inline.push(spoiler);
+            }
+            ("span" | "font", true) => {
+                if self.inline.pop() == Some(true) {
+                    self.spoiler = self.spoiler.saturating_sub(1);
+                }
+            }
+            ("img", false) => {
+                // Custom emotes draw as images; any other inline image shows
+                // as its alt text.
+                let alt = attr(attrs, "alt").or_else(|| attr(attrs, "title"));
+                let label = alt.as_deref().filter(|a| !a.is_empty()).unwrap_or("🖼");
+                let emote = attr(attrs, "src")
+                    .filter(|src| src.starts_with("mxc://") && has_attr(attrs, "data-mx-emoticon"));
+                match emote {
+                    Some(src) => self.emote(label, src),
+                    None => self.text(label),
+                }
+            }
+            ("hr", _) => {
+                self.flush();
+                self.blocks.push(Block::Rule);
+            }
+            ("br", _) => self.text("\n"),
+            ("p" | "div", false) => self.open(Container::Paragraph),
+            ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", false) => {
+                self.open(Container::Heading(name.as_bytes()[1] - b'0'));
+            }
+            ("blockquote", _) => {
+                self.flush();
+                bump(&mut self.quote, closing);
+            }
+            ("p" | "div" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6", true) => self.flush(),
+            ("pre", false) => {
+                self.flush();
+                self.pre = Some((None, String::new()));
+            }
+            ("ul" | "ol", false) => {
+                self.flush();
+                self.end_item();
+                self.lists.push(name == "ol");
+            }
+            ("ul" | "ol", true) => {
+                self.end_item();
+                if let Some(ordered) = self.lists.pop() {
+                    if self.lists.is_empty() && !self.items.is_empty() {
+                        let items = std::mem::take(&mut self.items);
+                        self.blocks.push(Block::List { ordered, items });
+                    }
+                }
+            }
+            ("li", false) => {
+                self.end_item();
+                self.in_item = true;
+            }
+            ("li", true) => self.end_item(),
+            _ => {}
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.skip > 0 {
+            return;
+        }
+        if let Some((_, code)) = &mut self.pre {
+            code.push_str(text);
+            return;
+        }
+        // Whitespace between block tags isn't content.
+        if self.spans.is_empty() && text.trim().is_empty() {
+            return;
+        }
+        let style = self.style();
+        let link = self.links.last().cloned().flatten();
+        match self.spans.last_mut() {
+            Some(last) if last.style == style && last.link == link && last.emote.is_none() => {
+                last.text.push_str(text)
+            }
+            _ => self.spans.push(Span {
+                text: text.to_owned(),
+                style,
+                link,
+                emote: None,
+            }),
+        }
+    }
+
+    /// A custom emote, always a span of its own.
+    fn emote(&mut self, shortcode: &str, mxc: String) {
+        let span = Span {
+            text: shortcode.to_owned(),
+            style: self.style(),
+            link: self.links.last().cloned().flatten(),
+            emote: Some(mxc),
+        };
+        self.spans.push(span);
+    }
+
+    fn style(&self) -> SpanStyle {
+        SpanStyle {
+            bold: self.bold > 0 || matches!(self.container, Some(Container::Heading(_))),
+            italic: self.italic > 0,
+            underline: self.underline > 0,
+            strike: self.strike > 0,
+            code: self.code > 0,
+            spoiler: self.spoiler > 0,
+        }
+    }
+
+    fn open(&mut self, container: Container) {
+        self.flush();
+        self.container = Some(container);
+    }
+
+    /// Close the current paragraph-like block, if it has anything in it.
+    fn flush(&mut self) {
+        let container = self.container.take();
+        if self.in_item {
+            return;
+        }
+        let mut spans = std::mem::take(&mut self.spans);
+        trim_spans(&mut spans);
+        if spans.is_empty() {
+            return;
+        }
+        self.blocks.push(match container {
+            _ if self.quote > 0 => Block::Quote(spans),
+            Some(Container::Heading(level)) => Block::Heading(level, spans),
+            _ => Block::Paragraph(spans),
+        });
+    }
+
+    fn end_item(&mut self) {
+        if !self.in_item {
+            return;
+        }
+        self.in_item = false;
+        let mut spans = std::mem::take(&mut self.spans);
+        trim_spans(&mut spans);
+        if !spans.is_empty() {
+            self.items.push(spans);
+        }
+    }
+
+    fn finish(mut self) -> RichText {
+        if let Some((language, code)) = self.pre.take() {
+            self.blocks.push(Block::Code { language, code });
+        }
+        self.end_item();
+        if !self.items.is_empty() {
+            let ordered = self.lists.first().copied().unwrap_or(false);
+            self.blocks.push(Block::List {
+                ordered,
+                items: std::mem::take(&mut self.items),
+            });
+        }
+        self.flush();
+        RichText {
+            blocks: self.blocks,
+        }
+    }
+}
+
+fn bump(depth: &mut u32, closing: bool) {
+    *depth = if closing {
+        depth.saturating_sub(1)
+    } else {
+        *depth + 1
+    };
+}
+
+/// Drop leading and trailing line breaks from a block's spans.
+fn trim_spans(spans: &mut Vec<Span>) {
+    if let Some(first) = spans.first_mut() {
+        first.text = first.text.trim_start_matches('\n').to_owned();
+    }
+    if let Some(last) = spans.last_mut() {
+        last.text = last.text.trim_end_matches('\n').to_owned();
+    }
+    spans.retain(|s| !s.text.is_empty());
+}
+
+/// Whether `href` uses an allowed scheme. Relative links have no meaning in
+/// a message and are refused too.
+fn safe_link(href: &str) -> bool {
+    href.split_once(':').is_some_and(|(scheme, _)| {
+        LINK_SCHEMES
+            .iter()
+            .any(|s| s.eq_ignore_ascii_case(scheme.trim()))
+    })
+}
+
+/// Whether attribute `name` is present, with or without a value.
+fn has_attr(attrs: &str, name: &str) -> bool {
+    attrs
+        .split(|c: char| c.is_whitespace() || c == '=')
+        .any(|word| word.eq_ignore_ascii_case(name))
+}
+
+/// The value of attribute `name` in a tag's attribute string.
+fn attr(attrs: &str, name: &str) -> Option<String> {
+    let mut rest = attrs;
+    while let Some(i) = rest.find(name) {
+        let after = rest[i + name.len()..].trim_start();
+        let boundary = i == 0 || rest[..i].ends_with(char::is_whitespace);
+        if let (true, Some(value)) = (boundary, after.strip_prefix('=')) {
+            let value = value.trim_start();
+            let (quote, value) = match value.chars().next() {
+                Some(q @ ('"' | '\'')) => (Some(q), &value[1..]),
+                _ => (None, value),
+            };
+            let end = match quote {
+                Some(q) => value.find(q),
+                None => value.find(char::is_whitespace),
+            }
+            .unwrap_or(value.len());
+            return Some(decode_entities(&value[..end]));
+        }
+        rest = &rest[i + name.len()..];
+    }
+    None
+}
+
+fn decode_entities(s: &str) -> String {
+    if !s.contains('&') {
+        return s.to_owned();
+    }
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('&') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let Some(end) = rest.find(';').filter(|&e| e <= 10) else {
+            out.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,41 @@
+// Room lookup with one refresh. A command can name a room the local store
+// doesn't have yet (joined from another device, sync lagging behind): before
+// giving up, ask the server whether we're joined and, if so, wait briefly for
+// sync to deliver the room.
+
+use std::time::Duration;
+
+use matrix_sdk::{
+    Room,
+    ruma::{RoomId, api::client::membership::joined_rooms},
+};
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+/// How long to wait for sync to catch up with a room the server says we're in.
+const ROOM_REFRESH_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl SpokeClient {
+    /// `room_id` from the store, or after one refresh. Fails with
+    /// `MatrixError::NotFound` when the server doesn't list us as joined
+    /// either, or sync doesn't deliver the room in time.
+    pub async fn room_or_refresh(&self, room_id: &RoomId) -> Result<Room, MatrixError> {
+        if let Some(room) = self.inner.get_room(room_id) {
+            return Ok(room);
+        }
+        let joined = self
+            .inner
+            .send(joined_rooms::v3::Request::new(), None)
+            .await?
+            .joined_rooms;
+        if !joined.iter().any(|r| r == room_id) {
+            return Err(MatrixError::NotFound(format!("{room_id}: not joined")));
+        }
+        tokio::time::timeout(
+            ROOM_REFRESH_TIMEOUT,
+            self.inner.await_room_remote_echo(room_id),
+        )
+        .await
+        .map_err(|_| MatrixError::NotFound(format!("{room_id}: joined, but not synced yet")))
+    }
+}
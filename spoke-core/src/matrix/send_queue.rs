@@ -0,0 +1,385 @@
+// Outgoing message queue — text messages are persisted in the state store and
+// sent one at a time by a background worker, so nothing typed while the
+// homeserver is unreachable is lost.
+//
+// Every message gets a transaction ID up front and keeps it across retries and
+// restarts, which makes resends idempotent on the server side.
+//
+// Transient failures (network errors, 5xx, 429) retry with exponential
+// backoff indefinitely; a 429 waits as long as the server asked instead, and
+// holds back the client's other scheduled requests meanwhile. Permanent
+// failures (other 4xx, unknown room) park the message as failed until the
+// user retries or discards it.
+
+use std::{collections::VecDeque, time::Duration};
+
+use matrix_sdk::{
+    StateStore,
+    ruma::{
+        OwnedEventId, OwnedTransactionId, OwnedUserId, RoomId, TransactionId,
+        events::{
+            Mentions,
+            relation::{InReplyTo, Thread},
+            room::message::{Relation, ReplacementMetadata, RoomMessageEventContent},
+        },
+    },
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+const QUEUE_KEY: &[u8] = b"spoke.send_queue";
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Content of an outgoing text message.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MessageText {
+    /// Plain-text body; pills appear as their display text.
+    pub body: String,
+    /// `org.matrix.custom.html` formatted body, if any.
+    #[serde(default)]
+    pub html: Option<String>,
+    /// User IDs for `m.mentions`.
+    #[serde(default)]
+    pub mentions: Vec<String>,
+    /// What the message replies to, continues, or replaces.
+    #[serde(default)]
+    pub relation: Option<MessageRelation>,
+}
+
+/// How an outgoing message relates to an earlier event.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MessageRelation {
+    Reply {
+        event_id: String,
+    },
+    /// A reply in the thread rooted at `root`.
+    Thread {
+        root: String,
+    },
+    /// New content for one of our own messages.
+    Edit {
+        event_id: String,
+    },
+}
+
+impl MessageText {
+    pub fn plain(body: impl Into<String>) -> Self {
+        Self {
+            body: body.into(),
+            ..Self::default()
+        }
+    }
+
+    fn to_content(&self) -> RoomMessageEventContent {
+        let content = match &self.html {
+            Some(html) => RoomMessageEventContent::text_html(&self.body, html),
+            None => RoomMessageEventContent::text_plain(&self.body),
+        };
+        let user_ids: Vec<OwnedUserId> = self
+            .mentions
+            .iter()
+            .filter_map(|u| u.parse().ok())
+            .collect();
+        let mut content = if user_ids.is_empty() {
+            content
+        } else {
+            content.add_mentions(Mentions::with_user_ids(user_ids))
+        };
+        match &self.relation {
+            None => content,
+            Some(MessageRelation::Reply { event_id }) => {
+                match OwnedEventId::try_from(event_id.as_str()) {
+                    Ok(event_id) => {
+                        content.relates_to = Some(Relation::Reply {
+                            in_reply_to: InReplyTo::new(event_id),
+                        });
+                        content
+                    }
+                    Err(_) => content,
+                }
+            }
+            Some(MessageRelation::Thread { root }) => match OwnedEventId::try_from(root.as_str()) {
+                Ok(root) => {
+                    content.relates_to = Some(Relation::Thread(Thread::plain(root.clone(), root)));
+                    content
+                }
+                Err(_) => content,
+            },
+            Some(MessageRelation::Edit { event_id }) => {
+                match OwnedEventId::try_from(event_id.as_str()) {
+                    Ok(event_id) => {
+                        content.make_replacement(ReplacementMetadata::new(event_id, None), None)
+                    }
+                    Err(_) => content,
+                }
+            }
+        }
+    }
+}
+
+/// A queued outgoing message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingMessage {
+    pub txn_id: String,
+    pub room_id: String,
+    #[serde(flatten)]
+    pub text: MessageText,
+    /// Set when the last attempt failed permanently; the worker skips the
+    /// message until it is retried.
+    #[serde(default)]
+    pub failed: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeliveryState {
+    Sending,
+    Sent { event_id: String },
+    Failed { error: String },
+}
+
+/// Delivery progress for one queued message.
+#[derive(Debug, Clone)]
+pub struct DeliveryUpdate {
+    pub txn_id: String,
+    pub room_id: String,
+    pub state: DeliveryState,
+}
+
+enum QueueCommand {
+    Enqueue(PendingMessage),
+    Retry(String),
+    Discard(String),
+}
+
+/// Handle to the send-queue worker. Dropping every handle stops the worker.
+#[derive(Clone)]
+pub struct SendQueue {
+    cmd_tx: mpsc::UnboundedSender<QueueCommand>,
+}
+
+impl SendQueue {
+    /// Load the persisted queue and start the worker. Returns the messages
+    /// still pending from a previous run so the UI can show them.
+    pub async fn start(
+        client: SpokeClient,
+        update_tx: mpsc::UnboundedSender<DeliveryUpdate>,
+    ) -> Result<(Self, Vec<PendingMessage>), MatrixError> {
+        let pending = load_queue(&client).await?;
+        let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
+        let worker = Worker {
+            client,
+            queue: pending.iter().cloned().collect(),
+            update_tx,
+            cmd_rx,
+            backoff: INITIAL_BACKOFF,
+        };
+        tokio::spawn(worker.run());
+        Ok((Self { cmd_tx }, pending))
+    }
+
+    /// Queue `text` for `room_id`. Returns the transaction ID that later
+    /// `DeliveryUpdate`s refer to.
+    pub fn enqueue(&self, room_id: &str, text: MessageText) -> String {
+        let txn_id = TransactionId::new().to_string();
+        let _ = self.cmd_tx.send(QueueCommand::Enqueue(PendingMessage {
+            txn_id: txn_id.clone(),
+            room_id: room_id.to_owned(),
+            text,
+            failed: None,
+        }));
+        txn_id
+    }
+
+    /// Retry a failed message (or a backing-off one) immediately.
+    pub fn retry(&self, txn_id: &str) {
+        let _ = self.cmd_tx.send(QueueCommand::Retry(txn_id.to_owned()));
+    }
+
+    /// Drop a message from the queue without sending it.
+    pub fn discard(&self, txn_id: &str) {
+        let _ = self.cmd_tx.send(QueueCommand::Discard(txn_id.to_owned()));
+    }
+}
+
+// ── Worker ────────────────────────────────────────────────────────────────────
+
+struct Worker {
+    client: SpokeClient,
+    queue: VecDeque<PendingMessage>,
+    update_tx: mpsc::UnboundedSender<DeliveryUpdate>,
+    cmd_rx: mpsc::UnboundedReceiver<QueueCommand>,
+    backoff: Duration,
+}
+
+enum SendError {
+    /// With the wait the server asked for, when rate limited.
+    Transient(String, Option<Duration>),
+    Permanent(String),
+}
+
+impl Worker {
+    async fn run(mut self) {
+        loop {
+            let Some(next) = self.queue.iter().find(|m| m.failed.is_none()).cloned() else {
+                // Nothing sendable — wait for new work.
+                let Some(cmd) = self.cmd_rx.recv().await else {
+                    return;
+                };
+                self.handle(cmd).await;
+                continue;
+            };
+
+            match self.send(&next).await {
+                Ok(event_id) => {
+                    self.backoff = INITIAL_BACKOFF;
+                    self.queue.retain(|m| m.txn_id != next.txn_id);
+                    self.persist().await;
+                    self.update(&next, DeliveryState::Sent { event_id });
+                }
+                Err(SendError::Permanent(error)) => {
+                    warn!("send {} failed: {error}", next.txn_id);
+                    if let Some(m) = self.queue.iter_mut().find(|m| m.txn_id == next.txn_id) {
+                        m.failed = Some(error.clone());
+                    }
+                    self.persist().await;
+                    self.update(&next, DeliveryState::Failed { error });
+                }
+                Err(SendError::Transient(error, retry_after)) => {
+                    let delay = match retry_after {
+                        Some(delay) => {
+                            self.client.rate_limit.hold("send message", delay);
+                            delay
+                        }
+                        None => {
+                            let delay = self.backoff;
+                            self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+                            delay
+                        }
+                    };
+                    warn!(
+                        "send {} deferred ({error}), retrying in {delay:?}",
+                        next.txn_id
+                    );
+                    // Keep accepting commands while waiting.
+                    let sleep = tokio::time::sleep(delay);
+                    tokio::pin!(sleep);
+                    loop {
+                        tokio::select! {
+                            _ = &mut sleep => break,
+                            cmd = self.cmd_rx.recv() => {
+                                let Some(cmd) = cmd else { return };
+                                if self.handle(cmd).await {
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Apply a command. Returns true if a pending backoff should be cut short.
+    async fn handle(&mut self, cmd: QueueCommand) -> bool {
+        match cmd {
+            QueueCommand::Enqueue(message) => {
+                self.update(&message, DeliveryState::Sending);
+                self.queue.push_back(message);
+                self.persist().await;
+                false
+            }
+            QueueCommand::Retry(txn_id) => {
+                let Some(m) = self.queue.iter_mut().find(|m| m.txn_id == txn_id) else {
+                    return false;
+                };
+                m.failed = None;
+                let m = m.clone();
+                self.backoff = INITIAL_BACKOFF;
+                self.persist().await;
+                self.update(&m, DeliveryState::Sending);
+                true
+            }
+            QueueCommand::Discard(txn_id) => {
+                self.queue.retain(|m| m.txn_id != txn_id);
+                self.persist().await;
+                false
+            }
+        }
+    }
+
+    async fn send(&self, message: &PendingMessage) -> Result<String, SendError> {
+        let room = RoomId::parse(&message.room_id)
+            .ok()
+            .and_then(|rid| self.client.inner.get_room(&rid))
+            .ok_or_else(|| SendError::Permanent(format!("unknown room {}", message.room_id)))?;
+        let txn_id: OwnedTransactionId = message.txn_id.clone().into();
+
+        self.client.rate_limit.ready().await;
+        match room
+            .send(message.text.to_content())
+            .with_transaction_id(&txn_id)
+            .await
+        {
+            Ok(response) => {
+                self.client.rate_limit.clear();
+                Ok(response.event_id.to_string())
+            }
+            Err(e) => Err(classify(e)),
+        }
+    }
+
+    async fn persist(&self) {
+        let queue: Vec<_> = self.queue.iter().cloned().collect();
+        if let Err(e) = save_queue(&self.client, &queue).await {
+            warn!("failed to persist send queue: {e}");
+        }
+    }
+
+    fn update(&self, message: &PendingMessage, state: DeliveryState) {
+        let _ = self.update_tx.send(DeliveryUpdate {
+            txn_id: message.txn_id.clone(),
+            room_id: message.room_id.clone(),
+            state,
+        });
+    }
+}
+
+/// Client errors other than rate limiting won't succeed on retry.
+fn classify(e: matrix_sdk::Error) -> SendError {
+    match e.as_client_api_error().map(|api| api.status_code) {
+        Some(status) if status.is_client_error() && status.as_u16() != 429 => {
+            SendError::Permanent(e.to_string())
+        }
+        _ => {
+            let error = e.to_string();
+            SendError::Transient(error, MatrixError::Sdk(e).retry_after())
+        }
+    }
+}
+
+// ── Persistence ───────────────────────────────────────────────────────────────
+
+async fn load_queue(client: &SpokeClient) -> Result<Vec<PendingMessage>, MatrixError> {
+    let Some(bytes) = client.inner.store().get_custom_value(QUEUE_KEY).await? else {
+        return Ok(Vec::new());
+    };
+    Ok(serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+        warn!("discarding unreadable send queue: {e}");
+        Vec::new()
+    }))
+}
+
+async fn save_queue(client: &SpokeClient, queue: &[PendingMessage]) -> Result<(), MatrixError> {
+    let bytes = serde_json::to_vec(queue).expect("pending messages serialize");
+    client
+        .inner
+        .store()
+        .set_custom_value(QUEUE_KEY, bytes)
+        .await?;
+    Ok(())
+}
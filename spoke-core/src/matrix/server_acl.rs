@@ -0,0 +1,384 @@
+// Federation abuse tools — the room's `m.room.server_acl` and the MSC2313
+// policy lists it follows.
+//
+// Matrix has no standard way for a room to say which policy lists it
+// follows, so Spoke keeps them in an `org.spoke.policy_lists` state event in
+// the room. A list is any room carrying `m.policy.rule.*` state; its rules
+// are only readable once we've joined it.
+//
+// ACL changes are read-modify-write against a fresh copy from the server,
+// as with power levels, and refuse anything that would shut our own server
+// out of the room.
+
+use matrix_sdk::{
+    Room,
+    deserialized_responses::{RawAnySyncOrStrippedState, SyncOrStrippedState},
+    ruma::{
+        OwnedRoomId, RoomId, ServerName,
+        api::client::{error::ErrorKind, state::get_state_events_for_key},
+        events::{
+            EmptyStateKey, StateEventType, SyncStateEvent, macros::EventContent,
+            room::server_acl::RoomServerAclEventContent,
+        },
+    },
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::json;
+use tracing::warn;
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+/// The recommendation every current moderation bot understands.
+const BAN_RECOMMENDATION: &str = "m.ban";
+
+/// A room's server ACL, for display.
+#[derive(Debug, Clone)]
+pub struct ServerAcl {
+    /// Server globs allowed to take part; `*` when the room sets no ACL.
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+    pub allow_ip_literals: bool,
+    /// Whether our power level lets us change it.
+    pub can_edit: bool,
+}
+
+/// One edit to a room's server ACL.
+#[derive(Debug, Clone)]
+pub enum AclChange {
+    /// Add a server glob (`evil.example`, `*.evil.example`) to the deny list.
+    Deny(String),
+    /// Take a glob off the deny list.
+    Undeny(String),
+    AllowIpLiterals(bool),
+}
+
+/// The policy lists a room follows, by room ID.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, EventContent)]
+#[ruma_event(type = "org.spoke.policy_lists", kind = State, state_key_type = EmptyStateKey)]
+pub struct PolicyListsEventContent {
+    #[serde(default)]
+    pub rooms: Vec<OwnedRoomId>,
+}
+
+/// What a policy rule is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyKind {
+    User,
+    Server,
+    Room,
+}
+
+impl PolicyKind {
+    pub const ALL: [PolicyKind; 3] = [PolicyKind::User, PolicyKind::Server, PolicyKind::Room];
+
+    pub fn event_type(self) -> &'static str {
+        match self {
+            PolicyKind::User => "m.policy.rule.user",
+            PolicyKind::Server => "m.policy.rule.server",
+            PolicyKind::Room => "m.policy.rule.room",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PolicyKind::User => "User",
+            PolicyKind::Server => "Server",
+            PolicyKind::Room => "Room",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PolicyRule {
+    pub kind: PolicyKind,
+    /// The user, server or room ID the rule matches; may contain globs.
+    pub entity: String,
+    pub recommendation: String,
+    pub reason: String,
+}
+
+/// A policy list a room follows.
+#[derive(Debug, Clone)]
+pub struct PolicyList {
+    pub room_id: String,
+    pub name: Option<String>,
+    /// Rules are only known for lists we've joined.
+    pub joined: bool,
+    pub rules: Vec<PolicyRule>,
+    /// Whether we may add rules to it.
+    pub can_edit: bool,
+}
+
+/// Rule content as it is in the wild: removed rules are sent as `{}`.
+#[derive(Deserialize)]
+struct RuleContent {
+    entity: Option<String>,
+    recommendation: Option<String>,
+    #[serde(default)]
+    reason: String,
+}
+
+impl SpokeClient {
+    /// The current server ACL of `room_id`, fetched from the server.
+    pub async fn server_acl(&self, room_id: &RoomId) -> Result<ServerAcl, MatrixError> {
+        let room = self
+            .inner
+            .get_room(room_id)
+            .ok_or_else(|| MatrixError::NotFound(room_id.to_string()))?;
+        let content = self.fetch_server_acl(room_id).await?;
+        let can_edit = room
+            .can_user_send_state(self.own_user_id()?, StateEventType::RoomServerAcl)
+            .await?;
+        Ok(ServerAcl {
+            allow: content.allow,
+            deny: content.deny,
+            allow_ip_literals: content.allow_ip_literals,
+            can_edit,
+        })
+    }
+
+    /// Apply `change` to the server ACL of `room_id`.
+    ///
+    /// Fails with `MatrixError::Forbidden` if we can't send the ACL or the
+    /// result would deny our own homeserver, which would cut us off from the
+    /// room for good.
+    pub async fn change_server_acl(
+        &self,
+        room_id: &RoomId,
+        change: AclChange,
+    ) -> Result<(), MatrixError> {
+        let room = self
+            .inner
+            .get_room(room_id)
+            .ok_or_else(|| MatrixError::NotFound(room_id.to_string()))?;
+        let own = self.own_user_id()?;
+        if !room
+            .can_user_send_state(own, StateEventType::RoomServerAcl)
+            .await?
+        {
+            return Err(MatrixError::Forbidden(
+                "your power level doesn't allow you to change this room's server ACL".into(),
+            ));
+        }
+
+        let mut content = self.fetch_server_acl(room_id).await?;
+        match change {
+            AclChange::Deny(server) => {
+                let server = server.trim().to_lowercase();
+                if !is_server_glob(&server) {
+                    return Err(MatrixError::InvalidServerName(server));
+                }
+                if !content.deny.contains(&server) {
+                    content.deny.push(server);
+                }
+            }
+            AclChange::Undeny(server) => content.deny.retain(|s| *s != server),
+            AclChange::AllowIpLiterals(allow) => content.allow_ip_literals = allow,
+        }
+        if !content.is_allowed(own.server_name()) {
+            return Err(MatrixError::Forbidden(format!(
+                "that would deny your own server ({})",
+                own.server_name()
+            )));
+        }
+
+        room.send_state_event(content).await?;
+        Ok(())
+    }
+
+    /// The policy lists `room_id` follows, with their rules where we can
+    /// read them.
+    pub async fn policy_lists(&self, room_id: &RoomId) -> Result<Vec<PolicyList>, MatrixError> {
+        let room = self
+            .inner
+            .get_room(room_id)
+            .ok_or_else(|| MatrixError::NotFound(room_id.to_string()))?;
+        let own = self.own_user_id()?;
+        let followed = match room
+            .get_state_event_static::<PolicyListsEventContent>()
+            .await?
+        {
+            Some(raw) => match raw.deserialize() {
+                Ok(SyncOrStrippedState::Sync(SyncStateEvent::Original(ev))) => ev.content.rooms,
+                Ok(_) => Vec::new(),
+                Err(e) => {
+                    warn!("unreadable policy lists in {room_id}: {e}");
+                    Vec::new()
+                }
+            },
+            None => Vec::new(),
+        };
+
+        let mut lists = Vec::with_capacity(followed.len());
+        for list_id in followed {
+            let list = match self
+                .inner
+                .get_room(&list_id)
+                .filter(|r| r.state() == matrix_sdk::RoomState::Joined)
+            {
+                Some(list_room) => PolicyList {
+                    room_id: list_id.to_string(),
+                    name: list_room.name(),
+                    joined: true,
+                    rules: policy_rules(&list_room).await?,
+                    can_edit: list_room
+                        .can_user_send_state(own, StateEventType::PolicyRuleUser)
+                        .await?,
+                },
+                None => PolicyList {
+                    room_id: list_id.to_string(),
+                    name: None,
+                    joined: false,
+                    rules: Vec::new(),
+                    can_edit: false,
+                },
+            };
+            lists.push(list);
+        }
+        Ok(lists)
+    }
+
+    /// Start or stop following the policy list `list_id` in `room_id`.
+    pub async fn follow_policy_list(
+        &self,
+        room_id: &RoomId,
+        list_id: &RoomId,
+        follow: bool,
+    ) -> Result<(), MatrixError> {
+        let room = self
+            .inner
+            .get_room(room_id)
+            .ok_or_else(|| MatrixError::NotFound(room_id.to_string()))?;
+        let event_type = StateEventType::from("org.spoke.policy_lists");
+        if !room
+            .can_user_send_state(self.own_user_id()?, event_type.clone())
+            .await?
+        {
+            return Err(MatrixError::Forbidden(
+                "your power level doesn't allow you to change this room's policy lists".into(),
+            ));
+        }
+        let mut content: PolicyListsEventContent = self
+            .fetch_state(room_id, event_type)
+            .await?
+            .unwrap_or_default();
+        content.rooms.retain(|r| r != list_id);
+        if follow {
+            content.rooms.push(list_id.to_owned());
+        }
+        room.send_state_event(content).await?;
+        Ok(())
+    }
+
+    /// Add a ban rule for `entity` to the policy list `list_id`, replacing
+    /// any rule the list already has for it.
+    pub async fn add_policy_rule(
+        &self,
+        list_id: &RoomId,
+        kind: PolicyKind,
+        entity: &str,
+        reason: &str,
+    ) -> Result<(), MatrixError> {
+        let list = self
+            .inner
+            .get_room(list_id)
+            .ok_or_else(|| MatrixError::NotFound(list_id.to_string()))?;
+        let event_type = StateEventType::from(kind.event_type());
+        if !list
+            .can_user_send_state(self.own_user_id()?, event_type)
+            .await?
+        {
+            return Err(MatrixError::Forbidden(
+                "your power level doesn't allow you to add rules to this list".into(),
+            ));
+        }
+        let entity = entity.trim();
+        let content = json!({
+            "entity": entity,
+            "recommendation": BAN_RECOMMENDATION,
+            "reason": reason,
+        });
+        // One rule per entity, so sending it again updates the reason.
+        list.send_state_event_raw(kind.event_type(), &format!("rule:{entity}"), content)
+            .await?;
+        Ok(())
+    }
+
+    async fn fetch_server_acl(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<RoomServerAclEventContent, MatrixError> {
+        // No ACL is the same as allowing everyone except IP literals.
+        let content = self
+            .fetch_state(room_id, StateEventType::RoomServerAcl)
+            .await?;
+        Ok(content.unwrap_or_else(|| {
+            RoomServerAclEventContent::new(true, vec!["*".to_owned()], Vec::new())
+        }))
+    }
+
+    /// The state event `event_type` with an empty state key, fresh from the
+    /// server; `None` if the room has none.
+    async fn fetch_state<C: DeserializeOwned>(
+        &self,
+        room_id: &RoomId,
+        event_type: StateEventType,
+    ) -> Result<Option<C>, MatrixError> {
+        let request = get_state_events_for_key::v3::Request::new(
+            room_id.to_owned(),
+            event_type,
+            String::new(),
+        );
+        match self.inner.send(request, None).await {
+            Ok(response) => response
+                .content
+                .deserialize_as::<C>()
+                .map(Some)
+                .map_err(|e| MatrixError::Sdk(e.into())),
+            Err(e) if matches!(e.client_api_error_kind(), Some(ErrorKind::NotFound)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Every live rule in a joined policy list.
+async fn policy_rules(list: &Room) -> Result<Vec<PolicyRule>, MatrixError> {
+    let mut rules = Vec::new();
+    for kind in PolicyKind::ALL {
+        for raw in list
+            .get_state_events(StateEventType::from(kind.event_type()))
+            .await?
+        {
+            let RawAnySyncOrStrippedState::Sync(raw) = raw else {
+                continue;
+            };
+            let Ok(Some(content)) = raw.get_field::<RuleContent>("content") else {
+                continue;
+            };
+            let (Some(entity), Some(recommendation)) = (content.entity, content.recommendation)
+            else {
+                continue;
+            };
+            rules.push(PolicyRule {
+                kind,
+                entity,
+                recommendation,
+                reason: content.reason,
+            });
+        }
+    }
+    rules.sort_by(|a, b| a.entity.cmp(&b.entity));
+    Ok(rules)
+}
+
+/// Whether `glob` could name servers: a server name, optionally with `*`
+/// and `?` standing in for parts of it.
+fn is_server_glob(glob: &str) -> bool {
+    if glob.contains('*') || glob.contains('?') {
+        return !glob.is_empty()
+            && glob
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-.:*?[]".contains(c));
+    }
+    <&ServerName>::try_from(glob).is_ok()
+}
@@ -0,0 +1,129 @@
+// Homeserver preflight — which spec versions, login flows and registration
+// a server offers, asked anonymously before logging in so the login screen
+// only presents options that can work there.
+//
+// `/capabilities` needs an access token, so it isn't part of this check.
+
+use matrix_sdk::{
+    Client,
+    ruma::api::client::{
+        account::register::v3 as register, discovery::get_supported_versions,
+        session::get_login_types::v3::LoginType,
+    },
+};
+
+use crate::{
+    matrix::{SpokeClient, error::MatrixError},
+    proxy,
+};
+
+/// Oldest spec version Spoke's endpoints work with (`v1.N`).
+const MIN_SPEC_MINOR: u32 = 1;
+
+/// Whether the server lets anyone create an account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Registration {
+    Open,
+    Closed,
+    /// The server's answer didn't say either way.
+    Unknown,
+}
+
+/// An SSO identity provider the server offers.
+#[derive(Debug, Clone)]
+pub struct SsoProvider {
+    pub id: String,
+    pub name: String,
+}
+
+/// What a homeserver supports, as far as logging in is concerned.
+#[derive(Debug, Clone)]
+pub struct ServerInfo {
+    /// Spec versions from `/versions`, as advertised.
+    pub versions: Vec<String>,
+    /// At least one advertised version is new enough for Spoke.
+    pub supported: bool,
+    pub password_login: bool,
+    pub sso_login: bool,
+    /// SSO identity providers, if the server lists them.
+    pub sso_providers: Vec<SsoProvider>,
+    pub registration: Registration,
+}
+
+impl ServerInfo {
+    /// Why Spoke can't log in here at all, if it can't.
+    pub fn problem(&self) -> Option<String> {
+        if !self.supported {
+            return Some(format!(
+                "This server only supports Matrix {}; Spoke needs v1.{MIN_SPEC_MINOR} or newer",
+                self.versions.join(", ")
+            ));
+        }
+        if !self.password_login && !self.sso_login {
+            return Some("This server offers no login method Spoke supports".into());
+        }
+        None
+    }
+}
+
+impl SpokeClient {
+    /// Ask `homeserver_url` what it supports, without logging in or touching
+    /// any local store.
+    pub async fn probe_server(homeserver_url: &str) -> Result<ServerInfo, MatrixError> {
+        let client = Client::builder()
+            .homeserver_url(homeserver_url)
+            .http_client(proxy::http_client()?)
+            .build()
+            .await?;
+
+        let versions = client
+            .send(get_supported_versions::Request::new(), None)
+            .await?
+            .versions;
+        let supported = versions.iter().any(|v| is_supported(v));
+
+        let flows = client.matrix_auth().get_login_types().await?.flows;
+        let password_login = flows.iter().any(|f| matches!(f, LoginType::Password(_)));
+        let sso = flows.iter().find_map(|f| match f {
+            LoginType::Sso(sso) => Some(sso),
+            _ => None,
+        });
+        let sso_providers = sso
+            .map(|s| {
+                s.identity_providers
+                    .iter()
+                    .map(|p| SsoProvider {
+                        id: p.id.clone(),
+                        name: p.name.clone(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(ServerInfo {
+            versions,
+            supported,
+            password_login,
+            sso_login: sso.is_some(),
+            sso_providers,
+            registration: registration(&client).await,
+        })
+    }
+}
+
+fn is_supported(version: &str) -> bool {
+    version
+        .strip_prefix("v1.")
+        .and_then(|minor| minor.parse::<u32>().ok())
+        .is_some_and(|minor| minor >= MIN_SPEC_MINOR)
+}
+
+/// An empty `/register` is answered with the interactive-auth flows when
+/// registration is open and `M_FORBIDDEN` when it's closed.
+async fn registration(client: &Client) -> Registration {
+    match client.send(register::Request::new(), None).await {
+        Err(e) if e.as_uiaa_response().is_some() => Registration::Open,
+        Err(e) if e.to_string().contains("M_FORBIDDEN") => Registration::Closed,
+        _ => Registration::Unknown,
+    }
+}
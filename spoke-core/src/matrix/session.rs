@@ -0,0 +1,88 @@
+// Access token upkeep — on servers that issue refresh tokens, renew the
+// access token shortly before it expires and save every new token pair, so a
+// restart restores a working session instead of needing a fresh login.
+//
+// Requests that still fail with M_UNKNOWN_TOKEN are refreshed and retried by
+// the SDK itself (`handle_refresh_tokens`); we only hear about it when that
+// doesn't help.
+
+use std::time::{Duration, SystemTime};
+
+use matrix_sdk::SessionChange;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+use crate::matrix::SpokeClient;
+
+/// Renew this long before the access token expires.
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+/// Wait before retrying a failed renewal.
+const REFRESH_RETRY: Duration = Duration::from_secs(30);
+
+/// Why a session could not be kept alive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionEnded {
+    /// The server rejected our tokens. With `soft_logout` the device and its
+    /// keys still exist, and logging in again resumes it.
+    UnknownToken { soft_logout: bool },
+}
+
+impl SpokeClient {
+    /// Keep the access token fresh until the server rejects the session.
+    /// Run on its own task after logging in.
+    pub async fn maintain_session(&self) -> SessionEnded {
+        let mut changes = self.inner.subscribe_to_session_changes();
+        // Lifetime of the last token we know about, reused to schedule the
+        // next renewal after the SDK refreshed on its own.
+        let mut lifetime = self
+            .token_expires()
+            .and_then(|t| t.duration_since(SystemTime::now()).ok());
+        // Once the SDK drops its end, only the renewal timer is left to run.
+        let mut changes_open = true;
+
+        loop {
+            let wait = self.token_expires().map(|t| {
+                t.duration_since(SystemTime::now())
+                    .unwrap_or_default()
+                    .saturating_sub(REFRESH_MARGIN)
+            });
+            let renew = async {
+                match wait {
+                    Some(wait) => tokio::time::sleep(wait).await,
+                    None => std::future::pending().await,
+                }
+            };
+
+            tokio::select! {
+                change = changes.recv(), if changes_open => match change {
+                    Ok(SessionChange::TokensRefreshed) => {
+                        let expires = self.token_expires();
+                        if expires.is_none_or(|t| t <= SystemTime::now() + REFRESH_MARGIN) {
+                            self.set_token_lifetime(lifetime);
+                        }
+                        self.save_session();
+                    }
+                    Ok(SessionChange::UnknownToken { soft_logout }) => {
+                        return SessionEnded::UnknownToken { soft_logout };
+                    }
+                    Err(RecvError::Lagged(_)) => self.save_session(),
+                    Err(RecvError::Closed) => changes_open = false,
+                },
+                () = renew => match self.inner.matrix_auth().refresh_access_token().await {
+                    Ok(Some(response)) => {
+                        info!("access token renewed");
+                        lifetime = response.expires_in_ms;
+                        self.set_token_lifetime(lifetime);
+                        self.save_session();
+                    }
+                    // No refresh token: the token expires when it expires.
+                    Ok(None) => self.set_token_lifetime(None),
+                    Err(e) => {
+                        warn!("access token renewal failed: {e}");
+                        self.set_token_lifetime(Some(REFRESH_RETRY + REFRESH_MARGIN));
+                    }
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+// Space hierarchy — walks `/hierarchy` for a space room and builds a tree of
+// child rooms (and nested sub-spaces) for the sidebar.
+
+use std::collections::{HashMap, HashSet};
+
+use matrix_sdk::ruma::{
+    OwnedRoomId, RoomId, api::client::space::get_hierarchy::v1 as get_hierarchy, room::RoomType,
+};
+use tracing::warn;
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+use crate::voice::events::VOICE_CHANNEL_ROOM_TYPE;
+
+/// A node in a space hierarchy: either a plain room or a nested space.
+#[derive(Debug, Clone)]
+pub struct SpaceNode {
+    pub room_id: OwnedRoomId,
+    pub name: String,
+    pub topic: Option<String>,
+    pub num_joined_members: u64,
+    pub is_space: bool,
+    /// Room type marks this as a Spoke voice channel.
+    pub is_voice: bool,
+    /// Whether the local user is a member of this room.
+    pub joined: bool,
+    pub children: Vec<SpaceNode>,
+}
+
+/// One flattened `/hierarchy` chunk before it is linked into the tree.
+struct HierarchyEntry {
+    node: SpaceNode,
+    child_ids: Vec<OwnedRoomId>,
+}
+
+impl SpokeClient {
+    /// Fetch the full hierarchy below `space_id` and return it as a tree.
+    ///
+    /// Follows `next_batch` until the server has returned every room.
+    /// Children the server does not return (e.g. not world-readable and not
+    /// joinable by us) are simply omitted. Cycles between spaces are broken
+    /// by only visiting each room once.
+    pub async fn space_hierarchy(&self, space_id: &RoomId) -> Result<SpaceNode, MatrixError> {
+        let mut entries: HashMap<OwnedRoomId, HierarchyEntry> = HashMap::new();
+        let mut from: Option<String> = None;
+
+        loop {
+            let mut req = get_hierarchy::Request::new(space_id.to_owned());
+            req.from = from.take();
+
+            let resp = self.inner.send(req, None).await?;
+
+            for chunk in resp.rooms {
+                let child_ids = chunk
+                    .children_state
+                    .iter()
+                    .filter_map(|raw| match raw.deserialize() {
+                        Ok(ev) => Some(ev.state_key),
+                        Err(e) => {
+                            warn!("bad m.space.child in {}: {e}", chunk.room_id);
+                            None
+                        }
+                    })
+                    .collect();
+
+                let room_type = chunk.room_type.as_ref();
+                let joined = self
+                    .inner
+                    .get_room(&chunk.room_id)
+                    .is_some_and(|r| r.state() == matrix_sdk::RoomState::Joined);
+
+                let node = SpaceNode {
+                    name: chunk
+                        .name
+                        .clone()
+                        .or_else(|| chunk.canonical_alias.as_ref().map(|a| a.to_string()))
+                        .unwrap_or_else(|| chunk.room_id.to_string()),
+                    topic: chunk.topic.clone(),
+                    num_joined_members: chunk.num_joined_members.into(),
+                    is_space: room_type == Some(&RoomType::Space),
+                    is_voice: room_type.is_some_and(|t| t.as_str() == VOICE_CHANNEL_ROOM_TYPE),
+                    joined,
+                    children: Vec::new(),
+                    room_id: chunk.room_id.clone(),
+                };
+                entries.insert(chunk.room_id, HierarchyEntry { node, child_ids });
+            }
+
+            match resp.next_batch {
+                Some(token) => from = Some(token),
+                None => break,
+            }
+        }
+
+        let mut visited = HashSet::new();
+        link_node(space_id, &mut entries, &mut visited)
+            .ok_or_else(|| MatrixError::NotFound(format!("space {space_id}")))
+    }
+}
+
+/// Remove `room_id` from `entries` and recursively attach its children.
+fn link_node(
+    room_id: &RoomId,
+    entries: &mut HashMap<OwnedRoomId, HierarchyEntry>,
+    visited: &mut HashSet<OwnedRoomId>,
+) -> Option<SpaceNode> {
+    if !visited.insert(room_id.to_owned()) {
+        return None;
+    }
+    let HierarchyEntry {
+        mut node,
+        child_ids,
+    } = entries.remove(room_id)?;
+    node.children = child_ids
+        .iter()
+        .filter_map(|id| link_node(id, entries, visited))
+        .collect();
+    // Spaces first, then rooms, each group alphabetically.
+    node.children.sort_by(|a, b| {
+        b.is_space
+            .cmp(&a.is_space)
+            .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+    });
+    Some(node)
+}
@@ -0,0 +1,192 @@
+// Spin-offs — moving a side discussion out of a room into one of its own.
+// The new room opens with a quote of the message it started from and a link
+// back; everyone who took part in that message's thread is invited, and the
+// thread gets a pointer to where the conversation went.
+//
+// It's several requests in a row, so progress is reported step by step; a
+// failed invite is skipped rather than abandoning a room that already exists.
+
+use matrix_sdk::ruma::{
+    EventId, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, UserId,
+    api::client::{
+        relations::get_relating_events_with_rel_type,
+        room::create_room::v3::Request as CreateRoomRequest,
+    },
+    events::{
+        InitialStateEvent, relation::RelationType, room::encryption::RoomEncryptionEventContent,
+    },
+};
+use serde_json::json;
+use tracing::warn;
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+/// Most thread replies read when gathering who took part.
+const PARTICIPANT_EVENTS: u32 = 200;
+
+/// What to spin off, and from where.
+#[derive(Debug, Clone)]
+pub struct SpinOff {
+    /// Name of the new room.
+    pub name: String,
+    pub origin_room: OwnedRoomId,
+    /// The message (or thread root) the discussion starts from.
+    pub origin_event: OwnedEventId,
+    pub origin_sender: OwnedUserId,
+    /// Its text, quoted in the new room. Passed in because the origin may be
+    /// encrypted.
+    pub quote: String,
+}
+
+/// How far a spin-off has got.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpinOffStep {
+    GatheringParticipants,
+    CreatingRoom,
+    PostingQuote,
+    Inviting { done: usize, total: usize },
+    LinkingBack,
+}
+
+impl SpinOffStep {
+    pub fn label(&self) -> String {
+        match self {
+            SpinOffStep::GatheringParticipants => "Finding who took part…".into(),
+            SpinOffStep::CreatingRoom => "Creating the room…".into(),
+            SpinOffStep::PostingQuote => "Quoting the original message…".into(),
+            SpinOffStep::Inviting { done, total } => {
+                format!("Inviting participants ({done}/{total})…")
+            }
+            SpinOffStep::LinkingBack => "Linking back from the thread…".into(),
+        }
+    }
+}
+
+/// A spin-off that made it: the new room, and who couldn't be invited.
+#[derive(Debug, Clone)]
+pub struct SpunOff {
+    pub room_id: OwnedRoomId,
+    pub not_invited: Vec<OwnedUserId>,
+}
+
+impl SpokeClient {
+    /// The sender of `root` and everyone who replied in its thread, without
+    /// us.
+    pub async fn thread_participants(
+        &self,
+        room_id: &RoomId,
+        root: &EventId,
+        root_sender: &UserId,
+    ) -> Result<Vec<OwnedUserId>, MatrixError> {
+        let own = self.own_user_id()?;
+        let mut request = get_relating_events_with_rel_type::v1::Request::new(
+            room_id.to_owned(),
+            root.to_owned(),
+            RelationType::Thread,
+        );
+        request.limit = Some(PARTICIPANT_EVENTS.into());
+        let response = self.inner.send(request, None).await?;
+
+        let mut participants = vec![root_sender.to_owned()];
+        for event in &response.chunk {
+            // The sender is readable even when the content is encrypted.
+            if let Ok(Some(sender)) = event.get_field::<OwnedUserId>("sender") {
+                if !participants.contains(&sender) {
+                    participants.push(sender);
+                }
+            }
+        }
+        participants.retain(|u| u != own);
+        Ok(participants)
+    }
+
+    /// Create a room for `spin_off`, calling `progress` before each step.
+    /// The new room is encrypted if the origin is.
+    pub async fn spin_off(
+        &self,
+        spin_off: &SpinOff,
+        mut progress: impl FnMut(SpinOffStep),
+    ) -> Result<SpunOff, MatrixError> {
+        let origin = self
+            .inner
+            .get_room(&spin_off.origin_room)
+            .ok_or_else(|| MatrixError::NotFound(spin_off.origin_room.to_string()))?;
+
+        progress(SpinOffStep::GatheringParticipants);
+        let participants = self
+            .thread_participants(
+                &spin_off.origin_room,
+                &spin_off.origin_event,
+                &spin_off.origin_sender,
+            )
+            .await?;
+
+        progress(SpinOffStep::CreatingRoom);
+        let mut request = CreateRoomRequest::new();
+        request.name = Some(spin_off.name.trim().to_owned());
+        let origin_name = origin
+            .name()
+            .unwrap_or_else(|| spin_off.origin_room.to_string());
+        request.topic = Some(format!("Spun off from {origin_name}"));
+        if origin.is_encrypted().await? {
+            request.initial_state = vec![
+                InitialStateEvent::new(RoomEncryptionEventContent::with_recommended_defaults())
+                    .to_raw_any(),
+            ];
+        }
+        let room = self.inner.create_room(request).await?;
+        let room_id = room.room_id().to_owned();
+
+        progress(SpinOffStep::PostingQuote);
+        let origin_link = format!(
+            "https://matrix.to/#/{}/{}",
+            spin_off.origin_room, spin_off.origin_event
+        );
+        let quoted: Vec<String> = spin_off.quote.lines().map(|l| format!("> {l}")).collect();
+        let body = format!(
+            "{}\n\n— {}, spun off from {origin_link}",
+            quoted.join("\n"),
+            spin_off.origin_sender
+        );
+        room.send_raw(
+            "m.room.message",
+            json!({ "msgtype": "m.text", "body": body }),
+        )
+        .await?;
+
+        let total = participants.len();
+        let mut not_invited = Vec::new();
+        for (done, user_id) in participants.into_iter().enumerate() {
+            progress(SpinOffStep::Inviting { done, total });
+            if let Err(e) = room.invite_user_by_id(&user_id).await {
+                warn!("spin-off: couldn't invite {user_id}: {e}");
+                not_invited.push(user_id);
+            }
+        }
+
+        progress(SpinOffStep::LinkingBack);
+        let link = format!("Discussion continued in https://matrix.to/#/{room_id}");
+        let content = json!({
+            "msgtype": "m.notice",
+            "body": link,
+            "m.relates_to": {
+                "rel_type": "m.thread",
+                "event_id": spin_off.origin_event,
+                "is_falling_back": true,
+                "m.in_reply_to": { "event_id": spin_off.origin_event },
+            },
+        });
+        // The room exists and people are in it; a missing pointer isn't worth failing over.
+        if let Err(e) = origin.send_raw("m.room.message", content).await {
+            warn!(
+                "spin-off: couldn't link back from {}: {e}",
+                spin_off.origin_room
+            );
+        }
+
+        Ok(SpunOff {
+            room_id,
+            not_invited,
+        })
+    }
+}
@@ -0,0 +1,121 @@
+// SSO login — the homeserver's SSO redirect is opened in the user's browser
+// and sends them back to a one-shot HTTP listener on the loopback interface
+// with a `loginToken`, which is then exchanged for a session.
+//
+// Split in two so the caller can open the browser between the steps: `start`
+// binds the listener and builds the URL, `finish` waits for the callback.
+
+use std::time::Duration;
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use tracing::info;
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+/// How long to wait for the browser to come back before giving up.
+const SSO_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Longest callback request we read; the token is all we need from it.
+const MAX_CALLBACK_BYTES: usize = 8 * 1024;
+
+const CALLBACK_PAGE: &str = "<!doctype html><title>Spoke</title>\
+                             <p>Signed in. You can close this tab and return to Spoke.</p>";
+
+/// An SSO login waiting for the browser to come back.
+pub struct SsoLogin {
+    listener: TcpListener,
+    /// The homeserver's SSO page; open this in a browser.
+    pub url: String,
+}
+
+impl SpokeClient {
+    /// Start an SSO login, optionally with a specific identity provider.
+    pub async fn start_sso_login(&self, idp_id: Option<&str>) -> Result<SsoLogin, MatrixError> {
+        let listener = TcpListener::bind(("127.0.0.1", 0))
+            .await
+            .map_err(|e| MatrixError::Sso(format!("can't listen for the SSO callback: {e}")))?;
+        let port = listener
+            .local_addr()
+            .map_err(|e| MatrixError::Sso(e.to_string()))?
+            .port();
+        let redirect = format!("http://127.0.0.1:{port}/");
+        let url = self
+            .inner
+            .matrix_auth()
+            .get_sso_login_url(&redirect, idp_id)
+            .await?;
+        Ok(SsoLogin { listener, url })
+    }
+
+    /// Wait for the SSO callback and log in with its token.
+    pub async fn finish_sso_login(&self, sso: SsoLogin) -> Result<(), MatrixError> {
+        let token = tokio::time::timeout(SSO_TIMEOUT, accept_callback(&sso.listener))
+            .await
+            .map_err(|_| MatrixError::Sso("timed out waiting for the browser".into()))??;
+
+        let response = self
+            .inner
+            .matrix_auth()
+            .login_token(&token)
+            .initial_device_display_name("Spoke")
+            .request_refresh_token()
+            .send()
+            .await?;
+        info!("logged in via SSO as {}", response.user_id);
+        self.set_token_lifetime(response.expires_in);
+        self.save_session();
+        Ok(())
+    }
+}
+
+/// Serve requests on `listener` until one carries a `loginToken`.
+async fn accept_callback(listener: &TcpListener) -> Result<String, MatrixError> {
+    loop {
+        let (mut stream, _) = listener
+            .accept()
+            .await
+            .map_err(|e| MatrixError::Sso(e.to_string()))?;
+
+        let mut buf = Vec::new();
+        let mut chunk = [0u8; 1024];
+        while !buf.windows(4).any(|w| w == b"\r\n\r\n") && buf.len() < MAX_CALLBACK_BYTES {
+            match stream.read(&mut chunk).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => buf.extend_from_slice(&chunk[..n]),
+            }
+        }
+
+        // Browsers also ask for /favicon.ico and the like; ignore those.
+        let Some(token) = login_token(&buf) else {
+            let _ = stream
+                .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n")
+                .await;
+            continue;
+        };
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: \
+             {}\r\nConnection: close\r\n\r\n{CALLBACK_PAGE}",
+            CALLBACK_PAGE.len()
+        );
+        let _ = stream.write_all(response.as_bytes()).await;
+        return Ok(token);
+    }
+}
+
+/// The `loginToken` query parameter of an HTTP request's `GET` line.
+fn login_token(request: &[u8]) -> Option<String> {
+    let request = std::str::from_utf8(request).ok()?;
+    let path = request
+        .lines()
+        .next()?
+        .strip_prefix("GET ")?
+        .split(' ')
+        .next()?;
+    let url = reqwest::Url::parse(&format!("http://127.0.0.1{path}")).ok()?;
+    url.query_pairs()
+        .find(|(k, _)| k == "loginToken")
+        .map(|(_, v)| v.into_owned())
+}
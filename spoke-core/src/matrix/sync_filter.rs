@@ -0,0 +1,158 @@
+// Sync filter — what the homeserver leaves out of `/sync`. Lazy-loaded
+// members and a short timeline window cut the initial sync on large accounts
+// from megabytes to kilobytes; presence, typing and receipts can be dropped
+// as well, along with timeline event types nobody reads. Each distinct filter
+// is uploaded once and its ID kept in the state store by the SDK.
+
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use matrix_sdk::ruma::{
+    UInt,
+    api::client::{
+        filter::{FilterDefinition, RoomEventFilter, RoomFilter},
+        sync::sync_events::v3::Filter,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+/// Timeline events left out by default: legacy VoIP signalling, which
+/// Spoke doesn't use and busy rooms are full of.
+pub const DEFAULT_EXCLUDED_TYPES: [&str; 4] = [
+    "m.call.candidates",
+    "m.call.negotiate",
+    "m.call.select_answer",
+    "m.call.hangup",
+];
+
+/// What sync should carry besides room state and messages.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncFilterOptions {
+    pub presence: bool,
+    pub typing: bool,
+    pub receipts: bool,
+    /// Messages per room in each sync response; older ones are paginated.
+    pub timeline_limit: u32,
+    /// Timeline event types the server should leave out; `*` wildcards work.
+    pub excluded_types: Vec<String>,
+}
+
+impl Default for SyncFilterOptions {
+    fn default() -> Self {
+        Self {
+            presence: true,
+            typing: true,
+            receipts: true,
+            timeline_limit: 20,
+            excluded_types: DEFAULT_EXCLUDED_TYPES.map(str::to_owned).to_vec(),
+        }
+    }
+}
+
+impl SyncFilterOptions {
+    fn definition(&self) -> FilterDefinition {
+        let mut room = RoomFilter::default();
+        room.state = RoomEventFilter::with_lazy_loading();
+        // Lazy loading applies per section; the timeline brings the members
+        // of its senders along.
+        room.timeline = RoomEventFilter::with_lazy_loading();
+        room.timeline.limit = Some(UInt::from(self.timeline_limit.max(1)));
+        room.timeline.not_types = self.excluded_types.clone();
+        if !self.typing {
+            room.ephemeral.not_types.push("m.typing".to_owned());
+        }
+        if !self.receipts {
+            room.ephemeral.not_types.push("m.receipt".to_owned());
+        }
+
+        let mut filter = FilterDefinition::default();
+        filter.room = room;
+        if !self.presence {
+            filter.presence.not_types = vec!["*".to_owned()];
+        }
+        filter
+    }
+
+    /// Names the filter in the store; a different definition gets a new one.
+    fn name(&self) -> String {
+        let flag = |on: bool| if on { '1' } else { '0' };
+        let mut excluded = DefaultHasher::new();
+        self.excluded_types.hash(&mut excluded);
+        format!(
+            "spoke.sync.{}{}{}.{}.{:x}",
+            flag(self.presence),
+            flag(self.typing),
+            flag(self.receipts),
+            self.timeline_limit,
+            excluded.finish()
+        )
+    }
+}
+
+impl SpokeClient {
+    /// The server-side filter for `options`, uploaded on first use.
+    pub async fn sync_filter(&self, options: &SyncFilterOptions) -> Result<Filter, MatrixError> {
+        let id = self
+            .inner
+            .get_or_upload_filter(&options.name(), options.definition())
+            .await?;
+        Ok(Filter::FilterId(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_keeps_everything_but_legacy_voip() {
+        let filter = SyncFilterOptions::default().definition();
+        assert_eq!(filter.room.timeline.limit, Some(UInt::from(20u32)));
+        assert_eq!(
+            filter.room.timeline.not_types,
+            DEFAULT_EXCLUDED_TYPES.map(str::to_owned)
+        );
+        assert!(filter.room.ephemeral.not_types.is_empty());
+        assert!(filter.presence.not_types.is_empty());
+    }
+
+    #[test]
+    fn dropped_extras_are_filtered_out() {
+        let options = SyncFilterOptions {
+            presence: false,
+            typing: false,
+            receipts: false,
+            timeline_limit: 0,
+            excluded_types: Vec::new(),
+        };
+        let filter = options.definition();
+        assert_eq!(filter.room.ephemeral.not_types, ["m.typing", "m.receipt"]);
+        assert_eq!(filter.presence.not_types, ["*"]);
+        assert!(filter.room.timeline.not_types.is_empty());
+        // A timeline of nothing would leave every room empty.
+        assert_eq!(filter.room.timeline.limit, Some(UInt::from(1u32)));
+    }
+
+    #[test]
+    fn name_follows_the_definition() {
+        let default = SyncFilterOptions::default();
+        assert_eq!(default.name(), SyncFilterOptions::default().name());
+        let no_typing = SyncFilterOptions {
+            typing: false,
+            ..SyncFilterOptions::default()
+        };
+        assert_ne!(default.name(), no_typing.name());
+        let longer = SyncFilterOptions {
+            timeline_limit: 50,
+            ..SyncFilterOptions::default()
+        };
+        assert_ne!(default.name(), longer.name());
+        let more_excluded = SyncFilterOptions {
+            excluded_types: vec!["m.room.encrypted".to_owned()],
+            ..SyncFilterOptions::default()
+        };
+        assert_ne!(default.name(), more_excluded.name());
+    }
+}
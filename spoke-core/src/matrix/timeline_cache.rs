@@ -0,0 +1,126 @@
+// Local timeline cache — the last few text messages per room, kept in the
+// SQLite state store's custom-value table so a restart can paint the last
+// conversation before the first sync returns.
+
+use matrix_sdk::{StateStore, ruma::RoomId};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::matrix::{Poll, SpokeClient, VoiceMessage, error::MatrixError};
+
+/// Messages kept per room. Older entries are dropped on write.
+const TIMELINE_CACHE_LIMIT: usize = 50;
+
+/// A text message as stored in the cache.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedMessage {
+    pub event_id: String,
+    pub sender: String,
+    pub body: String,
+    /// `origin_server_ts` in milliseconds; 0 for entries cached before it
+    /// was recorded.
+    #[serde(default)]
+    pub ts: u64,
+    /// The message mentions the local user.
+    #[serde(default)]
+    pub mentions_me: bool,
+    /// `org.matrix.custom.html` formatted body, if the message had one.
+    #[serde(default)]
+    pub html: Option<String>,
+    /// The event this message replies to.
+    #[serde(default)]
+    pub reply_to: Option<String>,
+    /// Root of the thread this message is in.
+    #[serde(default)]
+    pub thread_root: Option<String>,
+    /// The body is from an edit of the original.
+    #[serde(default)]
+    pub edited: bool,
+    /// Set when the event is a poll start; `body` is its text fallback.
+    #[serde(default)]
+    pub poll: Option<Poll>,
+    /// `mxc://` URI when the event is a sticker; `body` is its description.
+    #[serde(default)]
+    pub sticker: Option<String>,
+    /// Set when the event is a voice message.
+    #[serde(default)]
+    pub voice: Option<VoiceMessage>,
+    /// The event couldn't be decrypted yet; `body` is a placeholder.
+    #[serde(default)]
+    pub undecrypted: bool,
+    /// A join, leave or other `m.room.member` change; `body` describes it.
+    #[serde(default)]
+    pub membership: bool,
+}
+
+fn cache_key(room_id: &RoomId) -> Vec<u8> {
+    format!("spoke.timeline.{room_id}").into_bytes()
+}
+
+impl SpokeClient {
+    /// Cached messages for `room_id`, oldest first. Empty if nothing is cached
+    /// or the entry can't be decoded.
+    pub async fn cached_timeline(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<CachedMessage>, MatrixError> {
+        let Some(bytes) = self
+            .inner
+            .store()
+            .get_custom_value(&cache_key(room_id))
+            .await?
+        else {
+            return Ok(Vec::new());
+        };
+        Ok(serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+            warn!("discarding unreadable timeline cache for {room_id}: {e}");
+            Vec::new()
+        }))
+    }
+
+    /// Replace the cache for `room_id` with `messages` (oldest first), keeping
+    /// only the newest `TIMELINE_CACHE_LIMIT`.
+    pub async fn cache_timeline(
+        &self,
+        room_id: &RoomId,
+        messages: &[CachedMessage],
+    ) -> Result<(), MatrixError> {
+        let start = messages.len().saturating_sub(TIMELINE_CACHE_LIMIT);
+        let bytes = serde_json::to_vec(&messages[start..]).expect("cached messages serialize");
+        self.inner
+            .store()
+            .set_custom_value(&cache_key(room_id), bytes)
+            .await?;
+        Ok(())
+    }
+
+    /// Append a live message to the cache, ignoring events already cached
+    /// (the initial sync replays recent timeline events).
+    pub async fn append_cached_message(
+        &self,
+        room_id: &RoomId,
+        message: CachedMessage,
+    ) -> Result<(), MatrixError> {
+        let mut messages = self.cached_timeline(room_id).await?;
+        if messages.iter().any(|m| m.event_id == message.event_id) {
+            return Ok(());
+        }
+        messages.push(message);
+        self.cache_timeline(room_id, &messages).await
+    }
+
+    /// Replace a cached message, e.g. once it's been decrypted. Does
+    /// nothing if it isn't cached.
+    pub async fn update_cached_message(
+        &self,
+        room_id: &RoomId,
+        message: CachedMessage,
+    ) -> Result<(), MatrixError> {
+        let mut messages = self.cached_timeline(room_id).await?;
+        let Some(cached) = messages.iter_mut().find(|m| m.event_id == message.event_id) else {
+            return Ok(());
+        };
+        *cached = message;
+        self.cache_timeline(room_id, &messages).await
+    }
+}
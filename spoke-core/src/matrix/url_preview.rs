@@ -0,0 +1,129 @@
+// Link previews — Open Graph data for URLs in the timeline, fetched through
+// the homeserver's `/preview_url` so the client never contacts the linked
+// site itself. Results, including "the server has no preview", are kept in
+// the state store for a day.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use matrix_sdk::{
+    StateStore,
+    media::{MediaFormat, MediaRequest, MediaThumbnailSettings},
+    ruma::{
+        OwnedMxcUri,
+        api::client::media::{get_content_thumbnail::v3::Method, get_media_preview},
+        events::room::MediaSource,
+    },
+};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::matrix::{SpokeClient, error::MatrixError};
+
+/// How long a cached preview is trusted.
+const PREVIEW_TTL_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// Thumbnail bounds for a preview card's image.
+const PREVIEW_IMAGE_WIDTH: u32 = 320;
+const PREVIEW_IMAGE_HEIGHT: u32 = 180;
+
+/// What the homeserver could tell us about a link.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UrlPreview {
+    pub url: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub site_name: Option<String>,
+    /// `mxc://` URI of the page's `og:image`, rehosted by the homeserver.
+    pub image: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedPreview {
+    fetched_ms: u64,
+    preview: Option<UrlPreview>,
+}
+
+fn cache_key(url: &str) -> Vec<u8> {
+    format!("spoke.url_preview.{url}").into_bytes()
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+impl SpokeClient {
+    /// The preview for `url`, from cache when fresh. `None` when the page has
+    /// nothing worth showing or the server won't preview it.
+    pub async fn url_preview(&self, url: &str) -> Result<Option<UrlPreview>, MatrixError> {
+        let store = self.inner.store();
+        if let Some(bytes) = store.get_custom_value(&cache_key(url)).await? {
+            match serde_json::from_slice::<CachedPreview>(&bytes) {
+                Ok(cached) if now_ms().saturating_sub(cached.fetched_ms) < PREVIEW_TTL_MS => {
+                    return Ok(cached.preview);
+                }
+                Ok(_) => {}
+                Err(e) => warn!("discarding unreadable preview cache for {url}: {e}"),
+            }
+        }
+
+        let preview = match self
+            .inner
+            .send(get_media_preview::v3::Request::new(url.to_owned()), None)
+            .await
+        {
+            Ok(response) => response
+                .data
+                .and_then(|raw| serde_json::from_str::<serde_json::Value>(raw.get()).ok())
+                .and_then(|og| parse_open_graph(url, &og)),
+            // The server answered but can't preview this page: remember that.
+            Err(e) if e.as_client_api_error().is_some() => None,
+            Err(e) => return Err(e.into()),
+        };
+        let cached = CachedPreview {
+            fetched_ms: now_ms(),
+            preview: preview.clone(),
+        };
+        let bytes = serde_json::to_vec(&cached).expect("preview serializes");
+        store.set_custom_value(&cache_key(url), bytes).await?;
+        Ok(preview)
+    }
+
+    /// A thumbnail of a preview's image, cached in the media store.
+    pub async fn preview_image(&self, mxc: &str) -> Result<Vec<u8>, MatrixError> {
+        let request = MediaRequest {
+            source: MediaSource::Plain(OwnedMxcUri::from(mxc)),
+            format: MediaFormat::Thumbnail(MediaThumbnailSettings::new(
+                Method::Scale,
+                PREVIEW_IMAGE_WIDTH.into(),
+                PREVIEW_IMAGE_HEIGHT.into(),
+            )),
+        };
+        Ok(self.inner.media().get_media_content(&request, true).await?)
+    }
+}
+
+/// Pick the fields we show out of the `og:*` map. A page with neither a
+/// title nor a description isn't worth a card.
+fn parse_open_graph(url: &str, og: &serde_json::Value) -> Option<UrlPreview> {
+    let field = |key: &str| {
+        og.get(key)
+            .and_then(|v| v.as_str())
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_owned)
+    };
+    let title = field("og:title");
+    let description = field("og:description");
+    if title.is_none() && description.is_none() {
+        return None;
+    }
+    Some(UrlPreview {
+        url: url.to_owned(),
+        title,
+        description,
+        site_name: field("og:site_name"),
+        image: field("og:image").filter(|i| i.starts_with("mxc://")),
+    })
+}
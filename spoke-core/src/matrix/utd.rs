@@ -0,0 +1,175 @@
+// Unable-to-decrypt tracking — an encrypted message whose Megolm key we don't
+// have yet is shown as a placeholder and remembered here by its session.
+//
+// Asking for the key is the SDK's job: a failed decryption sends a key
+// request to our other devices and, as the client is built with
+// `BackupDownloadStrategy::AfterDecryptionFailure`, fetches the session from
+// key backup. Either way the key lands in the crypto store and shows up on
+// `room_keys_received`; every event waiting on one of those sessions is then
+// decrypted again and handed back so its placeholder can be replaced.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use futures::{Stream, StreamExt};
+use matrix_sdk::ruma::{
+    OwnedEventId, OwnedRoomId, OwnedUserId, RoomId,
+    events::{
+        AnySyncTimelineEvent,
+        room::encrypted::{EncryptedEventScheme, OriginalSyncRoomEncryptedEvent},
+    },
+    serde::Raw,
+};
+use tracing::{debug, warn};
+
+use crate::matrix::SpokeClient;
+
+/// Most events kept waiting for keys; beyond this, new failures are shown
+/// but not retried.
+const MAX_TRACKED: usize = 2000;
+
+/// A message that couldn't be decrypted, for its placeholder.
+#[derive(Debug, Clone)]
+pub struct Undecrypted {
+    pub event_id: OwnedEventId,
+    pub sender: OwnedUserId,
+    /// `origin_server_ts` in milliseconds.
+    pub ts: u64,
+    /// The Megolm session whose key is missing.
+    pub session_id: String,
+}
+
+/// An event waiting for its key.
+struct Waiting {
+    room_id: OwnedRoomId,
+    event_id: OwnedEventId,
+    raw: Raw<OriginalSyncRoomEncryptedEvent>,
+}
+
+/// Events waiting for keys, by Megolm session ID.
+#[derive(Clone, Default)]
+pub(crate) struct UtdTracker {
+    pending: Arc<Mutex<HashMap<String, Vec<Waiting>>>>,
+}
+
+fn waiting_count(pending: &HashMap<String, Vec<Waiting>>) -> usize {
+    pending.values().map(Vec::len).sum()
+}
+
+impl SpokeClient {
+    /// Remember `raw`, an `m.room.encrypted` event in `room_id` that the SDK
+    /// couldn't decrypt, so it's tried again when its key arrives. `None` if
+    /// it isn't a Megolm event, which no key will ever unlock.
+    pub fn track_undecrypted(
+        &self,
+        room_id: &RoomId,
+        raw: &Raw<AnySyncTimelineEvent>,
+    ) -> Option<Undecrypted> {
+        let raw = raw.clone().cast::<OriginalSyncRoomEncryptedEvent>();
+        let event = raw.deserialize().ok()?;
+        let EncryptedEventScheme::MegolmV1AesSha2(scheme) = &event.content.scheme else {
+            return None;
+        };
+        let utd = Undecrypted {
+            event_id: event.event_id.clone(),
+            sender: event.sender.clone(),
+            ts: u64::from(event.origin_server_ts.0),
+            session_id: scheme.session_id.clone(),
+        };
+
+        let mut pending = self.utd.pending.lock().unwrap();
+        let tracked = pending
+            .get(&utd.session_id)
+            .is_some_and(|events| events.iter().any(|w| w.event_id == utd.event_id));
+        if tracked {
+            return Some(utd);
+        }
+        if waiting_count(&pending) >= MAX_TRACKED {
+            warn!(
+                "not tracking {}: {MAX_TRACKED} events already waiting for keys",
+                utd.event_id
+            );
+            return Some(utd);
+        }
+        debug!(
+            "{} in {room_id} waits for session {}",
+            utd.event_id, utd.session_id
+        );
+        let waiting = Waiting {
+            room_id: room_id.to_owned(),
+            event_id: utd.event_id.clone(),
+            raw,
+        };
+        pending
+            .entry(utd.session_id.clone())
+            .or_default()
+            .push(waiting);
+        Some(utd)
+    }
+
+    /// How many events are waiting for keys.
+    pub fn undecrypted_count(&self) -> usize {
+        waiting_count(&self.utd.pending.lock().unwrap())
+    }
+
+    /// Megolm session IDs whose keys just arrived, in batches, whether shared
+    /// by another device or restored from backup. `None` before login.
+    pub async fn room_keys_received(&self) -> Option<impl Stream<Item = Vec<String>> + '_> {
+        let stream = self.inner.encryption().room_keys_received_stream().await?;
+        Some(stream.filter_map(|batch| async move {
+            match batch {
+                Ok(keys) => Some(keys.into_iter().map(|k| k.session_id).collect()),
+                // Lagged behind: the keys that went by unseen only help
+                // events fetched from now on.
+                Err(e) => {
+                    warn!("room keys stream: {e}");
+                    None
+                }
+            }
+        }))
+    }
+
+    /// Decrypt again every tracked event from `sessions`. Returns those that
+    /// now decrypt, by room; the rest keep waiting.
+    pub async fn retry_decryption(
+        &self,
+        sessions: &[String],
+    ) -> Vec<(OwnedRoomId, Raw<AnySyncTimelineEvent>)> {
+        let waiting: Vec<(String, Vec<Waiting>)> = {
+            let mut pending = self.utd.pending.lock().unwrap();
+            sessions
+                .iter()
+                .filter_map(|s| pending.remove(s).map(|events| (s.clone(), events)))
+                .collect()
+        };
+
+        let mut decrypted = Vec::new();
+        for (session_id, events) in waiting {
+            let mut still_waiting = Vec::new();
+            for waiting in events {
+                let Some(room) = self.inner.get_room(&waiting.room_id) else {
+                    continue;
+                };
+                match room.decrypt_event(&waiting.raw).await {
+                    Ok(event) => decrypted.push((waiting.room_id, event.raw().clone().cast())),
+                    Err(e) => {
+                        debug!("still can't decrypt {}: {e}", waiting.event_id);
+                        still_waiting.push(waiting);
+                    }
+                }
+            }
+            if !still_waiting.is_empty() {
+                self.utd
+                    .pending
+                    .lock()
+                    .unwrap()
+                    .entry(session_id)
+                    .or_default()
+                    .extend(still_waiting);
+            }
+        }
+        decrypted
+    }
+}
@@ -0,0 +1,135 @@
+// Voice messages — `m.audio` events marked with `org.matrix.msc3245.voice`,
+// carrying their duration and waveform in `org.matrix.msc1767.audio`
+// (MSC3246). Recording and decoding live in `voice::recording`; this is the
+// upload, the event, and reading those fields back out of timeline events.
+
+use matrix_sdk::{
+    media::{MediaFormat, MediaRequest},
+    ruma::{
+        OwnedEventId, OwnedMxcUri, RoomId,
+        events::{AnySyncTimelineEvent, room::MediaSource},
+        serde::Raw,
+    },
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    matrix::{SpokeClient, error::MatrixError},
+    voice::recording::Recording,
+};
+
+const VOICE_MIMETYPE: &str = "audio/ogg";
+const VOICE_BODY: &str = "Voice message";
+
+/// A voice message as shown in the timeline.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VoiceMessage {
+    /// `mxc://` URI of the Ogg/Opus file.
+    pub url: String,
+    pub duration_ms: u64,
+    /// 0..=1024 per bar; empty if the sender didn't include one.
+    #[serde(default)]
+    pub waveform: Vec<u16>,
+}
+
+/// The parts of an `m.room.message` content a voice message needs.
+#[derive(Deserialize)]
+struct VoiceContent {
+    msgtype: String,
+    url: Option<String>,
+    #[serde(default)]
+    info: Option<VoiceInfo>,
+    #[serde(rename = "org.matrix.msc1767.audio", default)]
+    audio: Option<VoiceAudio>,
+    #[serde(rename = "org.matrix.msc3245.voice", default)]
+    voice: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct VoiceInfo {
+    duration: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct VoiceAudio {
+    duration: Option<u64>,
+    #[serde(default)]
+    waveform: Vec<u16>,
+}
+
+impl VoiceMessage {
+    /// The voice message in `event`, if it is one. Encrypted files aren't
+    /// supported and read as `None`.
+    pub fn from_event(event: &Raw<AnySyncTimelineEvent>) -> Option<Self> {
+        let content: VoiceContent = event.get_field("content").ok().flatten()?;
+        if content.msgtype != "m.audio" || content.voice.is_none() {
+            return None;
+        }
+        let url = content.url.filter(|u| u.starts_with("mxc://"))?;
+        let duration_ms = content
+            .audio
+            .as_ref()
+            .and_then(|a| a.duration)
+            .or_else(|| content.info.and_then(|i| i.duration))
+            .unwrap_or(0);
+        let waveform = content.audio.map(|a| a.waveform).unwrap_or_default();
+        Some(Self {
+            url,
+            duration_ms,
+            waveform,
+        })
+    }
+}
+
+impl SpokeClient {
+    /// Upload `recording` and post it to `room_id` as a voice message.
+    pub async fn send_voice_message(
+        &self,
+        room_id: &RoomId,
+        recording: &Recording,
+    ) -> Result<OwnedEventId, MatrixError> {
+        let room = self
+            .inner
+            .get_room(room_id)
+            .ok_or_else(|| MatrixError::NotFound(room_id.to_string()))?;
+        let mime: mime::Mime = VOICE_MIMETYPE.parse().expect("valid mimetype");
+        let size = recording.ogg.len();
+        let upload = self
+            .inner
+            .media()
+            .upload(&mime, recording.ogg.clone())
+            .await?;
+        let content = json!({
+            "msgtype": "m.audio",
+            "body": VOICE_BODY,
+            "url": upload.content_uri,
+            "info": {
+                "mimetype": VOICE_MIMETYPE,
+                "size": size,
+                "duration": recording.duration_ms,
+            },
+            "org.matrix.msc1767.text": VOICE_BODY,
+            "org.matrix.msc1767.file": {
+                "url": upload.content_uri,
+                "mimetype": VOICE_MIMETYPE,
+                "size": size,
+            },
+            "org.matrix.msc1767.audio": {
+                "duration": recording.duration_ms,
+                "waveform": recording.waveform,
+            },
+            "org.matrix.msc3245.voice": {},
+        });
+        Ok(room.send_raw("m.room.message", content).await?.event_id)
+    }
+
+    /// The Ogg/Opus file behind a voice message, cached in the media store.
+    pub async fn voice_message_audio(&self, mxc: &str) -> Result<Vec<u8>, MatrixError> {
+        let request = MediaRequest {
+            source: MediaSource::Plain(OwnedMxcUri::from(mxc)),
+            format: MediaFormat::File,
+        };
+        Ok(self.inner.media().get_media_content(&request, true).await?)
+    }
+}
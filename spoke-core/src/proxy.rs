@@ -0,0 +1,126 @@
+// Proxy support — one process-wide setting that every HTTP client Spoke
+// builds goes through: the matrix-sdk client, the sidecar grant requests,
+// room reports and the voice preflight probes.
+//
+// "System" leaves detection to reqwest, which reads HTTP_PROXY, HTTPS_PROXY,
+// ALL_PROXY and NO_PROXY and, on Windows and macOS, the OS proxy settings.
+// "Manual" takes an `http://`, `https://`, `socks5://` or `socks5h://` URL.
+//
+// LiveKit's signalling WebSocket is opened by the livekit crate itself, which
+// has no proxy hook; when a proxy is set, `livekit_bypasses_proxy` tells the
+// voice layer to warn that signalling goes around it. Media goes over
+// ICE, where TURN over TCP/TLS is the way through a restrictive network.
+
+use std::sync::RwLock;
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// How to reach the network.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxyMode {
+    /// Use the proxy configured in the environment or the OS.
+    #[default]
+    System,
+    /// Connect directly, ignoring any system proxy.
+    Direct,
+    /// Use `ProxySettings::url`.
+    Manual,
+}
+
+impl ProxyMode {
+    pub const ALL: [ProxyMode; 3] = [ProxyMode::System, ProxyMode::Direct, ProxyMode::Manual];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ProxyMode::System => "System settings",
+            ProxyMode::Direct => "No proxy",
+            ProxyMode::Manual => "Manual",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProxySettings {
+    pub mode: ProxyMode,
+    /// e.g. `http://proxy.corp:3128` or `socks5h://127.0.0.1:1080`, with
+    /// optional `user:password@`.
+    pub url: String,
+    /// Comma-separated hosts, domains and CIDRs to reach directly.
+    pub no_proxy: String,
+}
+
+impl ProxySettings {
+    /// Why the manual proxy URL can't be used, if it can't.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.mode != ProxyMode::Manual {
+            return Ok(());
+        }
+        let url = self.url.trim();
+        let scheme = url.split_once("://").map(|(s, _)| s).unwrap_or_default();
+        if !matches!(scheme, "http" | "https" | "socks5" | "socks5h") {
+            return Err("use an http://, https://, socks5:// or socks5h:// URL".into());
+        }
+        reqwest::Proxy::all(url)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Apply these settings to `builder`. An unusable manual proxy falls
+    /// back to a direct connection rather than the system proxy, so traffic
+    /// never silently takes a route the user ruled out.
+    fn apply(&self, builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+        match self.mode {
+            ProxyMode::System => builder,
+            ProxyMode::Direct => builder.no_proxy(),
+            ProxyMode::Manual => match reqwest::Proxy::all(self.url.trim()) {
+                Ok(proxy) => {
+                    let proxy = proxy.no_proxy(reqwest::NoProxy::from_string(&self.no_proxy));
+                    builder.no_proxy().proxy(proxy)
+                }
+                Err(e) => {
+                    warn!("ignoring unusable proxy {:?}: {e}", self.url);
+                    builder.no_proxy()
+                }
+            },
+        }
+    }
+}
+
+static SETTINGS: RwLock<Option<ProxySettings>> = RwLock::new(None);
+
+/// Replace the process-wide proxy settings. Clients built afterwards use
+/// them; existing ones keep what they were built with.
+pub fn set(settings: ProxySettings) {
+    *SETTINGS.write().unwrap() = Some(settings);
+}
+
+/// The current process-wide proxy settings.
+pub fn current() -> ProxySettings {
+    SETTINGS.read().unwrap().clone().unwrap_or_default()
+}
+
+/// A reqwest builder with the current proxy settings applied.
+pub fn client_builder() -> reqwest::ClientBuilder {
+    current()
+        .apply(reqwest::Client::builder().user_agent(concat!("Spoke/", env!("CARGO_PKG_VERSION"))))
+}
+
+/// A reqwest client with the current proxy settings.
+pub fn http_client() -> reqwest::Result<reqwest::Client> {
+    client_builder().build()
+}
+
+/// Whether LiveKit's signalling connection would go around the configured
+/// proxy, which the livekit crate can't use.
+pub fn livekit_bypasses_proxy() -> bool {
+    match current().mode {
+        ProxyMode::Direct => false,
+        ProxyMode::Manual => true,
+        ProxyMode::System => ["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"]
+            .iter()
+            .any(|var| std::env::var_os(var).is_some_and(|v| !v.is_empty())),
+    }
+}
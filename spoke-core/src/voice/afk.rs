@@ -0,0 +1,167 @@
+// AFK channels — a space names one of its voice channels as the AFK channel
+// (`org.spoke.voice.afk`), and each client moves itself there once its user
+// has been silent for the space's timeout in any of the space's other voice
+// channels.
+//
+// Silence is judged from our own mic: every captured frame is classed as
+// speech or not by its level, with a short hangover so the gaps between words
+// don't count. Muted frames are silence, so sitting muted counts as idle.
+
+use std::{
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use matrix_sdk::{
+    Client, Room,
+    deserialized_responses::SyncOrStrippedState,
+    ruma::{
+        OwnedRoomId, RoomId,
+        events::{StateEventType, SyncStateEvent},
+    },
+};
+
+use super::events::VoiceAfkEventContent;
+
+/// RMS level (of full scale) above which a frame counts as speech, about
+/// -36 dBFS: above a quiet room, below a soft voice.
+const SPEECH_RMS: f32 = 0.016;
+/// Quiet right after speech still counts as speech.
+const HANGOVER: Duration = Duration::from_millis(300);
+
+/// Voice activity on our mic, fed by the capture pipeline.
+#[derive(Debug)]
+pub struct SpeechStats {
+    started: Instant,
+    last_speech: Mutex<Instant>,
+    frames: AtomicU64,
+    speech_frames: AtomicU64,
+}
+
+impl Default for SpeechStats {
+    fn default() -> Self {
+        let now = Instant::now();
+        Self {
+            started: now,
+            last_speech: Mutex::new(now),
+            frames: AtomicU64::new(0),
+            speech_frames: AtomicU64::new(0),
+        }
+    }
+}
+
+impl SpeechStats {
+    /// Class one frame of mono or interleaved samples; `None` for a muted frame.
+    pub(crate) fn record(&self, samples: Option<&[i16]>) {
+        self.frames.fetch_add(1, Ordering::Relaxed);
+        let Some(samples) = samples.filter(|s| !s.is_empty()) else {
+            return;
+        };
+        let energy: f32 = samples
+            .iter()
+            .map(|&s| {
+                let v = s as f32 / i16::MAX as f32;
+                v * v
+            })
+            .sum();
+        let rms = (energy / samples.len() as f32).sqrt();
+        let mut last = self.last_speech.lock().unwrap();
+        if rms >= SPEECH_RMS {
+            *last = Instant::now();
+            self.speech_frames.fetch_add(1, Ordering::Relaxed);
+        } else if last.elapsed() < HANGOVER {
+            self.speech_frames.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// How long since we last spoke (or since capture started).
+    pub fn idle_for(&self) -> Duration {
+        self.last_speech.lock().unwrap().elapsed()
+    }
+
+    /// Share of frames so far that were speech, 0..=1.
+    pub fn speech_ratio(&self) -> f32 {
+        let frames = self.frames.load(Ordering::Relaxed);
+        if frames == 0 {
+            return 0.0;
+        }
+        self.speech_frames.load(Ordering::Relaxed) as f32 / frames as f32
+    }
+
+    /// How long the mic has been captured.
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+/// Where and when an idle member of a voice channel is moved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AfkPolicy {
+    /// The space that set it.
+    pub space_id: OwnedRoomId,
+    pub afk_room: OwnedRoomId,
+    pub timeout: Duration,
+}
+
+impl AfkPolicy {
+    /// Whether someone in `room_id` who has been silent for `idle` should be
+    /// moved now.
+    pub fn should_move(&self, room_id: &RoomId, idle: Duration) -> bool {
+        room_id != self.afk_room && idle >= self.timeout
+    }
+}
+
+/// The space's AFK setting, or the default (off) if unset.
+pub async fn afk_config(space: &Room) -> Result<VoiceAfkEventContent> {
+    let Some(raw) = space
+        .get_state_event_static::<VoiceAfkEventContent>()
+        .await?
+    else {
+        return Ok(VoiceAfkEventContent::default());
+    };
+    Ok(match raw.deserialize()? {
+        SyncOrStrippedState::Sync(SyncStateEvent::Original(ev)) => ev.content,
+        _ => VoiceAfkEventContent::default(),
+    })
+}
+
+/// Set or clear (`afk_room: None`) the space's AFK channel.
+pub async fn set_afk_config(
+    space: &Room,
+    afk_room: Option<OwnedRoomId>,
+    timeout_mins: u32,
+) -> Result<()> {
+    let content = VoiceAfkEventContent {
+        room_id: afk_room,
+        timeout_mins: timeout_mins.max(1),
+    };
+    space.send_state_event(content).await?;
+    Ok(())
+}
+
+/// The AFK policy that applies to `room`: the first joined space listing it
+/// as a child that has an AFK channel set.
+pub async fn afk_policy(client: &Client, room: &RoomId) -> Result<Option<AfkPolicy>> {
+    for space in client.joined_rooms().into_iter().filter(|r| r.is_space()) {
+        if space
+            .get_state_event(StateEventType::SpaceChild, room.as_str())
+            .await?
+            .is_none()
+        {
+            continue;
+        }
+        let config = afk_config(&space).await?;
+        if let Some(afk_room) = config.room_id {
+            return Ok(Some(AfkPolicy {
+                space_id: space.room_id().to_owned(),
+                afk_room,
+                timeout: Duration::from_secs(u64::from(config.timeout_mins.max(1)) * 60),
+            }));
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,64 @@
+// Automatic gain control for the mic — a quiet mic is turned up and a hot
+// one down, so what we send lands near a common level whatever the hardware.
+//
+// Like `normalize`, the speech level is a slow moving average that pauses
+// ignore. Gain rises slowly toward the level that brings speech to the
+// target, so breaths and room noise between words aren't pumped up. It
+// falls as soon as speech gets louder, and further still for any frame that
+// would otherwise clip.
+
+/// Level speech is steered toward (RMS of full scale, about -20 dBFS).
+const TARGET_RMS: f32 = 0.1;
+/// Limits on the correction: up to +18 dB, down to -12 dB.
+const MIN_GAIN: f32 = 0.25;
+const MAX_GAIN: f32 = 8.0;
+/// Frames quieter than this are background and don't move the estimate.
+const SILENCE_RMS: f32 = 0.005;
+/// Highest peak a frame may reach after gain, of full scale.
+const PEAK_LIMIT: f32 = 0.9;
+/// Time constants (seconds) of the level estimate and of raising the gain.
+const LEVEL_TAU: f32 = 1.5;
+const RISE_TAU: f32 = 2.0;
+
+/// Per-mic gain control state.
+#[derive(Debug, Clone)]
+pub(crate) struct AutoGain {
+    mean_square: f32,
+    gain: f32,
+}
+
+impl AutoGain {
+    pub(crate) fn new() -> Self {
+        Self {
+            mean_square: TARGET_RMS * TARGET_RMS,
+            gain: 1.0,
+        }
+    }
+
+    /// Update the estimate with `frame` (mono, `sample_rate` Hz) and return
+    /// the gain to apply to it.
+    pub(crate) fn process(&mut self, frame: &[i16], sample_rate: u32) -> f32 {
+        if frame.is_empty() || sample_rate == 0 {
+            return self.gain;
+        }
+        let secs = frame.len() as f32 / sample_rate as f32;
+        let (sum_sq, peak) = frame.iter().fold((0.0f32, 0.0f32), |(sum, peak), &s| {
+            let v = s as f32 / i16::MAX as f32;
+            (sum + v * v, peak.max(v.abs()))
+        });
+        let mean_square = sum_sq / frame.len() as f32;
+        if mean_square.sqrt() > SILENCE_RMS {
+            self.mean_square += (mean_square - self.mean_square) * (secs / LEVEL_TAU).min(1.0);
+            let wanted = (TARGET_RMS / self.mean_square.sqrt()).clamp(MIN_GAIN, MAX_GAIN);
+            if wanted > self.gain {
+                self.gain += (wanted - self.gain) * (secs / RISE_TAU).min(1.0);
+            } else {
+                self.gain = wanted;
+            }
+        }
+        if peak * self.gain > PEAK_LIMIT {
+            self.gain = (PEAK_LIMIT / peak).max(MIN_GAIN);
+        }
+        self.gain
+    }
+}
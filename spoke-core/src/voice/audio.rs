@@ -1,7 +1,9 @@
 // CPAL ↔ LiveKit audio bridge.
 //
-// AudioCapture: mic → NativeAudioSource (→ LiveKit track)
-// AudioOutput:  LiveKit NativeAudioStream frames → speaker ring buffer → cpal output
+// AudioCapture: mic → 48 kHz → NativeAudioSource (→ LiveKit track)
+// AudioOutput:  LiveKit NativeAudioStream frames → per-track mixer → cpal output
+//
+// Either can switch devices mid-call; see `devices` for finding them.
 //
 // IMPORTANT: cpal::Stream deliberately opts out of Send (to support Android's AAudio).
 // We work around this by building cpal streams on dedicated OS threads that own
@@ -12,265 +14,700 @@ use std::{
     borrow::Cow,
     sync::{
         Arc, Mutex,
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU32, Ordering},
     },
+    time::{Duration, Instant},
 };
 
 use anyhow::Result;
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::traits::{DeviceTrait, StreamTrait};
 use livekit::webrtc::audio_frame::AudioFrame;
 use livekit::webrtc::audio_source::native::NativeAudioSource;
 use livekit::webrtc::audio_source::{AudioSourceOptions, RtcAudioSource};
+use tokio::sync::{Notify, watch};
 use tracing::warn;
 
+use super::afk::SpeechStats;
+use super::agc::AutoGain;
+use super::devices::{find_input, find_output};
+use super::echo::{EchoCanceller, FRAME_SAMPLES};
+use super::latency::{DeviceDelay, Marker, Playback};
+use super::levels::LevelMeter;
+use super::mixer::{Mixer, TrackInput};
+use super::resample::{LIVEKIT_RATE, Resampler};
+use super::vad::VoiceActivity;
+
 // ── Mic capture ───────────────────────────────────────────────────────────────
 
+/// Input gain range offered to users, in dB.
+pub const INPUT_GAIN_RANGE_DB: std::ops::RangeInclusive<f32> = -20.0..=20.0;
+
+/// Peak mic level after gain, for the input meter.
+#[derive(Debug, Default)]
+pub struct InputMeter {
+    /// Peak since the last `take`, 0..=1 of full scale, as `f32` bits.
+    peak: AtomicU32,
+    /// Whether gain pushed any sample past full scale since the last `take`.
+    clipped: AtomicBool,
+}
+
+impl InputMeter {
+    pub(crate) fn record(&self, peak: f32, clipped: bool) {
+        // Non-negative floats order the same as their bit patterns.
+        self.peak
+            .fetch_max(peak.min(1.0).to_bits(), Ordering::Relaxed);
+        if clipped {
+            self.clipped.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Peak level and whether anything clipped since the last call.
+    pub fn take(&self) -> (f32, bool) {
+        let peak = f32::from_bits(self.peak.swap(0, Ordering::Relaxed));
+        (peak, self.clipped.swap(false, Ordering::Relaxed))
+    }
+}
+
+/// How often a stream thread checks on its device.
+const DEVICE_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+/// A device that delivers nothing for this long is treated as gone; not
+/// every backend reports an unplugged device as an error.
+const DEVICE_STALL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// What a stream's callbacks report, so the thread owning it can tell when
+/// its device has gone.
+#[derive(Default)]
+struct Liveness {
+    /// Callbacks delivered so far.
+    callbacks: AtomicU32,
+    /// The backend said the device is gone.
+    unplugged: AtomicBool,
+}
+
+impl Liveness {
+    /// Call from every data callback.
+    fn tick(&self) {
+        self.callbacks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Call from the error callback.
+    fn error(&self, e: &cpal::StreamError) {
+        if matches!(e, cpal::StreamError::DeviceNotAvailable) {
+            self.unplugged.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Block until `kill_rx` is signalled or dropped (`false`) or the device
+    /// is gone (`true`).
+    fn wait(&self, kill_rx: &std::sync::mpsc::Receiver<()>) -> bool {
+        let mut last = 0;
+        let mut quiet = Duration::ZERO;
+        loop {
+            match kill_rx.recv_timeout(DEVICE_CHECK_INTERVAL) {
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+                _ => return false,
+            }
+            let count = self.callbacks.load(Ordering::Relaxed);
+            quiet = if count == last {
+                quiet + DEVICE_CHECK_INTERVAL
+            } else {
+                Duration::ZERO
+            };
+            last = count;
+            if self.unplugged.load(Ordering::Relaxed) || quiet >= DEVICE_STALL_TIMEOUT {
+                return true;
+            }
+        }
+    }
+}
+
 /// Captures microphone audio and feeds it into a LiveKit `NativeAudioSource`.
+///
+/// The source outlives the device: `reopen` switches to another one and the
+/// published track carries on. If the device goes away, `lost` is notified
+/// and the track goes quiet until then. Mute, gain and the meters carry over.
 pub struct AudioCapture {
-    /// The LiveKit audio source — clone this to create a `LocalAudioTrack`.
-    pub source: NativeAudioSource,
     /// Set to `true` to send silence instead of real mic audio.
     pub muted: Arc<AtomicBool>,
+    /// Linear gain applied before publishing, as `f32` bits.
+    gain: Arc<AtomicU32>,
+    /// Whether automatic gain control runs ahead of `gain`.
+    auto_gain: Arc<AtomicBool>,
+    /// Level after gain; shared with the UI.
+    pub meter: Arc<InputMeter>,
+    /// Speech detected in what we send, for AFK detection.
+    pub speech: Arc<SpeechStats>,
+    /// Latency marker to send in place of mic audio.
+    pub(crate) marker: Arc<Marker>,
+    /// Mic to callback, as the device reports it.
+    pub(crate) delay: Arc<DeviceDelay>,
+    /// Notified when the open device disappears or stops delivering audio.
+    pub(crate) lost: Arc<Notify>,
+    /// Takes speaker echo out of what we send.
+    pub(crate) echo: Arc<EchoCanceller>,
+    /// Whether we're talking, judged from what we send; muted is silent.
+    pub(crate) speaking: Arc<watch::Sender<bool>>,
+    /// What we send, metered for `VoiceEvent::Levels`; muted is silent.
+    pub(crate) level: Arc<Mutex<LevelMeter>>,
+    /// The LiveKit audio source — clone this to create a `LocalAudioTrack`.
+    source: NativeAudioSource,
+    /// The open device; replaced by `reopen`.
+    device: Mutex<CaptureDevice>,
+}
+
+/// An open input device.
+struct CaptureDevice {
+    name: String,
+    /// The name it was opened by; `None` if it's the default.
+    chosen: Option<String>,
     /// Dropping this ends the mic capture thread and stops the cpal stream.
     _kill: std::sync::mpsc::Sender<()>,
 }
 
+/// What the feeder shares with `AudioCapture`, whichever device is open.
+#[derive(Clone)]
+struct Feed {
+    source: NativeAudioSource,
+    muted: Arc<AtomicBool>,
+    gain: Arc<AtomicU32>,
+    auto_gain: Arc<AtomicBool>,
+    meter: Arc<InputMeter>,
+    speech: Arc<SpeechStats>,
+    marker: Arc<Marker>,
+    delay: Arc<DeviceDelay>,
+    lost: Arc<Notify>,
+    echo: Arc<EchoCanceller>,
+    speaking: Arc<watch::Sender<bool>>,
+    level: Arc<Mutex<LevelMeter>>,
+}
+
 impl AudioCapture {
-    pub fn start() -> Result<Self> {
-        // ── Step 1: Discover device config (no ownership of non-Send types) ──
-        let (sample_rate, channels) = {
-            let host = cpal::default_host();
-            let dev = host
-                .default_input_device()
-                .ok_or_else(|| anyhow::anyhow!("no default input device"))?;
-            let cfg = dev.default_input_config()?;
-            (cfg.sample_rate().0, cfg.channels() as u32)
+    /// Open the input device named `device`, or the default one.
+    pub fn start(device: Option<&str>) -> Result<Self> {
+        let feed = Feed {
+            // Always 48 kHz mono; the feeder downmixes and converts whatever
+            // the device delivers.
+            source: NativeAudioSource::new(
+                AudioSourceOptions::default(),
+                LIVEKIT_RATE,
+                1,
+                200, // 200 ms internal buffer
+            ),
+            muted: Arc::new(AtomicBool::new(false)),
+            gain: Arc::new(AtomicU32::new(1.0f32.to_bits())),
+            auto_gain: Arc::new(AtomicBool::new(true)),
+            meter: Arc::new(InputMeter::default()),
+            speech: Arc::new(SpeechStats::default()),
+            marker: Arc::new(Marker::default()),
+            delay: Arc::new(DeviceDelay::default()),
+            lost: Arc::new(Notify::new()),
+            echo: EchoCanceller::new(),
+            speaking: Arc::new(watch::channel(false).0),
+            level: Arc::new(Mutex::new(LevelMeter::default())),
         };
+        let device = open_input(device, feed.clone())?;
+        Ok(Self {
+            muted: feed.muted,
+            gain: feed.gain,
+            auto_gain: feed.auto_gain,
+            meter: feed.meter,
+            speech: feed.speech,
+            marker: feed.marker,
+            delay: feed.delay,
+            lost: feed.lost,
+            echo: feed.echo,
+            speaking: feed.speaking,
+            level: feed.level,
+            source: feed.source,
+            device: Mutex::new(device),
+        })
+    }
 
-        // ── Step 2: Create the LiveKit audio source ───────────────────────────
-        let source = NativeAudioSource::new(
-            AudioSourceOptions::default(),
-            sample_rate,
-            channels,
-            200, // 200 ms internal buffer
-        );
-        let source_clone = source.clone();
-        let muted = Arc::new(AtomicBool::new(false));
-        let muted_clone = muted.clone();
-
-        // ── Step 3: Channels ─────────────────────────────────────────────────
-        let (pcm_tx, pcm_rx) = std::sync::mpsc::sync_channel::<Vec<i16>>(8);
-        let (kill_tx, kill_rx) = std::sync::mpsc::channel::<()>();
-        // Signals back whether the stream started successfully.
-        let (ready_tx, ready_rx) = std::sync::mpsc::channel::<Result<(), String>>();
-
-        // ── Step 4: Build+own the cpal stream on a dedicated thread ──────────
-        // cpal::Stream is intentionally !Send; we never move it.
-        std::thread::spawn(move || {
-            let host = cpal::default_host();
-            let dev = match host.default_input_device() {
-                Some(d) => d,
-                None => {
-                    let _ = ready_tx.send(Err("no default input device".into()));
-                    return;
-                }
-            };
-            let cfg = match dev.default_input_config() {
-                Ok(c) => c,
-                Err(e) => {
-                    let _ = ready_tx.send(Err(format!("input config: {e}")));
-                    return;
-                }
-            };
-            let stream_cfg: cpal::StreamConfig = cfg.into();
-            let stream = match dev.build_input_stream(
-                &stream_cfg,
-                move |data: &[f32], _: &cpal::InputCallbackInfo| {
-                    let samples: Vec<i16> = data
-                        .iter()
-                        .map(|&s| (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
-                        .collect();
-                    let _ = pcm_tx.try_send(samples);
-                },
-                |e| warn!("cpal input error: {e}"),
-                None,
-            ) {
-                Ok(s) => s,
-                Err(e) => {
-                    let _ = ready_tx.send(Err(format!("build input stream: {e}")));
-                    return;
-                }
-            };
-            if let Err(e) = stream.play() {
-                let _ = ready_tx.send(Err(format!("play input stream: {e}")));
+    /// Replace the open device with `device`, or the default one. If it
+    /// can't be opened, the current one (if any) stays. Blocks until the new
+    /// stream starts, so async callers go through `spawn_blocking`.
+    pub fn reopen(&self, device: Option<&str>) -> Result<()> {
+        let feed = Feed {
+            source: self.source.clone(),
+            muted: self.muted.clone(),
+            gain: self.gain.clone(),
+            auto_gain: self.auto_gain.clone(),
+            meter: self.meter.clone(),
+            speech: self.speech.clone(),
+            marker: self.marker.clone(),
+            delay: self.delay.clone(),
+            lost: self.lost.clone(),
+            echo: self.echo.clone(),
+            speaking: self.speaking.clone(),
+            level: self.level.clone(),
+        };
+        let opened = open_input(device, feed)?;
+        *self.device.lock().unwrap() = opened;
+        Ok(())
+    }
+
+    /// Name of the open input device.
+    pub fn device_name(&self) -> String {
+        self.device.lock().unwrap().name.clone()
+    }
+
+    /// The name the open device was asked for by, or `None` if it's
+    /// following the OS default.
+    pub(crate) fn chosen_device(&self) -> Option<String> {
+        self.device.lock().unwrap().chosen.clone()
+    }
+
+    /// Amplify (or attenuate) the mic by `db`, clamped to `INPUT_GAIN_RANGE_DB`.
+    pub fn set_gain_db(&self, db: f32) {
+        let db = db.clamp(*INPUT_GAIN_RANGE_DB.start(), *INPUT_GAIN_RANGE_DB.end());
+        self.gain
+            .store(10f32.powf(db / 20.0).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Turn automatic gain control on or off (on by default). The manual
+    /// gain still applies on top.
+    pub fn set_auto_gain(&self, enabled: bool) {
+        self.auto_gain.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Returns the `RtcAudioSource` to pass to `LocalAudioTrack::create_audio_track`.
+    pub fn rtc_source(&self) -> RtcAudioSource {
+        RtcAudioSource::Native(self.source.clone())
+    }
+}
+
+/// Open input device `name` (or the default) and start feeding it, through
+/// `feed`, into its LiveKit source.
+fn open_input(name: Option<&str>, feed: Feed) -> Result<CaptureDevice> {
+    // ── Step 1: Discover device config (no ownership of non-Send types) ──
+    let (device_name, sample_rate, channels) = {
+        let host = cpal::default_host();
+        let dev = find_input(&host, name)?;
+        let cfg = dev.default_input_config()?;
+        (
+            dev.name().unwrap_or_default(),
+            cfg.sample_rate().0,
+            cfg.channels() as u32,
+        )
+    };
+
+    // ── Step 2: Channels ─────────────────────────────────────────────────
+    let (pcm_tx, pcm_rx) = std::sync::mpsc::sync_channel::<Vec<f32>>(8);
+    let (kill_tx, kill_rx) = std::sync::mpsc::channel::<()>();
+    // Signals back whether the stream started successfully.
+    let (ready_tx, ready_rx) = std::sync::mpsc::channel::<Result<(), String>>();
+
+    // ── Step 3: Build+own the cpal stream on a dedicated thread ──────────
+    // cpal::Stream is intentionally !Send; we never move it.
+    let thread_name = name.map(str::to_owned);
+    let delay_in = feed.delay.clone();
+    let lost = feed.lost.clone();
+    std::thread::spawn(move || {
+        let host = cpal::default_host();
+        let dev = match find_input(&host, thread_name.as_deref()) {
+            Ok(d) => d,
+            Err(e) => {
+                let _ = ready_tx.send(Err(e.to_string()));
+                return;
+            }
+        };
+        let cfg = match dev.default_input_config() {
+            Ok(c) => c,
+            Err(e) => {
+                let _ = ready_tx.send(Err(format!("input config: {e}")));
                 return;
             }
-            let _ = ready_tx.send(Ok(()));
-            // Block until AudioCapture is dropped (kill_tx dropped → recv Err).
-            let _ = kill_rx.recv();
-            // `stream` is dropped here, stopping mic capture.
-        });
-
-        ready_rx
-            .recv()
-            .map_err(|_| anyhow::anyhow!("input thread died before ready"))?
-            .map_err(|e| anyhow::anyhow!("{e}"))?;
-
-        // ── Step 5: Feeder task: PCM → LiveKit NativeAudioSource ─────────────
-        // spawn_blocking is used so the brief recv() doesn't starve the executor.
-        let rt_handle = tokio::runtime::Handle::current();
-        tokio::task::spawn_blocking(move || {
-            loop {
-                match pcm_rx.recv() {
-                    Ok(samples) => {
-                        let samples_per_channel = (samples.len() as u32) / channels.max(1);
-                        let data: Vec<i16> = if muted_clone.load(Ordering::Relaxed) {
-                            vec![0i16; samples.len()]
-                        } else {
-                            samples
-                        };
-                        let frame = AudioFrame {
-                            data: Cow::Owned(data),
-                            sample_rate,
-                            num_channels: channels,
-                            samples_per_channel,
-                        };
-                        let _ = rt_handle.block_on(source_clone.capture_frame(&frame));
+        };
+        let stream_cfg: cpal::StreamConfig = cfg.into();
+        let liveness = Arc::new(Liveness::default());
+        let liveness_in = liveness.clone();
+        let liveness_err = liveness.clone();
+        let stream = match dev.build_input_stream(
+            &stream_cfg,
+            move |data: &[f32], info: &cpal::InputCallbackInfo| {
+                liveness_in.tick();
+                let ts = info.timestamp();
+                delay_in.record(ts.callback.duration_since(&ts.capture));
+                let _ = pcm_tx.try_send(data.to_vec());
+            },
+            move |e| {
+                warn!("cpal input error: {e}");
+                liveness_err.error(&e);
+            },
+            None,
+        ) {
+            Ok(s) => s,
+            Err(e) => {
+                let _ = ready_tx.send(Err(format!("build input stream: {e}")));
+                return;
+            }
+        };
+        if let Err(e) = stream.play() {
+            let _ = ready_tx.send(Err(format!("play input stream: {e}")));
+            return;
+        }
+        let _ = ready_tx.send(Ok(()));
+        // Run until AudioCapture drops or replaces us (kill_tx dropped →
+        // recv Err), or the device goes away. Returning drops `stream`,
+        // stopping mic capture and closing the PCM channel, which ends the
+        // feeder.
+        if liveness.wait(&kill_rx) {
+            warn!("input device lost");
+            lost.notify_one();
+        }
+    });
+
+    ready_rx
+        .recv()
+        .map_err(|_| anyhow::anyhow!("input thread died before ready"))?
+        .map_err(|e| anyhow::anyhow!("{e}"))?;
+
+    // ── Step 4: Feeder task: PCM → mono 48 kHz → echo cancelled → gain → LiveKit
+    // spawn_blocking is used so the brief recv() doesn't starve the executor.
+    let rt_handle = tokio::runtime::Handle::current();
+    let mut resampler = Resampler::new(sample_rate, LIVEKIT_RATE, 1);
+    tokio::task::spawn_blocking(move || {
+        // Converted audio short of a whole 10 ms frame, which is what the
+        // echo canceller takes.
+        let mut pending = Vec::new();
+        let mut agc = AutoGain::new();
+        let mut vad = VoiceActivity::new();
+        // Ends when the stream thread exits and drops pcm_tx.
+        while let Ok(pcm) = pcm_rx.recv() {
+            resampler.process(&downmix(&pcm, channels as usize), &mut pending);
+            let whole = pending.len() / FRAME_SAMPLES * FRAME_SAMPLES;
+            for chunk in pending[..whole].chunks_exact(FRAME_SAMPLES) {
+                let mut samples: Vec<i16> = chunk
+                    .iter()
+                    .map(|&s| (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
+                    .collect();
+                let muted = feed.muted.load(Ordering::Relaxed);
+                let mut data: Vec<i16> = if muted {
+                    feed.speech.record(None);
+                    vec![0i16; samples.len()]
+                } else {
+                    feed.echo.process(&mut samples);
+                    let mut gain = f32::from_bits(feed.gain.load(Ordering::Relaxed));
+                    if feed.auto_gain.load(Ordering::Relaxed) {
+                        gain *= agc.process(&samples, LIVEKIT_RATE);
                     }
-                    Err(_) => break, // stream thread exited → pcm_tx dropped
+                    let (data, peak, clipped) = apply_gain(&samples, gain);
+                    feed.meter.record(peak, clipped);
+                    feed.speech.record(Some(&data));
+                    data
+                };
+                let change = if muted {
+                    vad.silence()
+                } else {
+                    vad.process(&data, LIVEKIT_RATE)
+                };
+                if let Some(speaking) = change {
+                    feed.speaking.send_replace(speaking);
                 }
+                feed.level.lock().unwrap().record(&data);
+                feed.marker.apply(&mut data, LIVEKIT_RATE, 1);
+                let frame = AudioFrame {
+                    data: Cow::Owned(data),
+                    sample_rate: LIVEKIT_RATE,
+                    num_channels: 1,
+                    samples_per_channel: FRAME_SAMPLES as u32,
+                };
+                let _ = rt_handle.block_on(feed.source.capture_frame(&frame));
             }
-        });
+            pending.drain(..whole);
+        }
+    });
 
-        Ok(Self {
-            source,
-            muted,
-            _kill: kill_tx,
-        })
-    }
+    Ok(CaptureDevice {
+        name: device_name,
+        chosen: name.map(str::to_owned),
+        _kill: kill_tx,
+    })
+}
 
-    /// Returns the `RtcAudioSource` to pass to `LocalAudioTrack::create_audio_track`.
-    pub fn rtc_source(&self) -> RtcAudioSource {
-        RtcAudioSource::Native(self.source.clone())
+/// Average each frame of interleaved `pcm` down to one sample. Summing
+/// instead would double the level of a mic that fills both channels.
+fn downmix(pcm: &[f32], channels: usize) -> Cow<'_, [f32]> {
+    if channels <= 1 {
+        return Cow::Borrowed(pcm);
     }
+    Cow::Owned(
+        pcm.chunks(channels)
+            .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+            .collect(),
+    )
+}
+
+/// Scale `samples` by `gain`, clamping to full scale. Returns the result,
+/// its peak (0..=1) and whether any sample had to be clamped.
+fn apply_gain(samples: &[i16], gain: f32) -> (Vec<i16>, f32, bool) {
+    let mut peak = 0.0f32;
+    let mut clipped = false;
+    let data = samples
+        .iter()
+        .map(|&s| {
+            let v = s as f32 / i16::MAX as f32 * gain;
+            clipped |= v.abs() > 1.0;
+            peak = peak.max(v.abs());
+            (v.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+        })
+        .collect();
+    (data, peak, clipped)
 }
 
 // ── Speaker output ────────────────────────────────────────────────────────────
 
-/// Receives i16 PCM frames (from remote LiveKit audio tracks) and plays them
-/// through the default output device via a shared ring buffer.
-///
-/// Multiple remote tracks write into the same ring buffer — last-writer-wins
-/// rather than proper mixing, which is acceptable for ≤ 2 participants (MVP).
+/// Plays remote tracks, cues and clips through an output device, mixed by a
+/// shared `Mixer`. The device can be swapped with `reopen` without the
+/// tracks noticing. If it goes away, `lost` is notified and nothing plays
+/// until then.
 pub struct AudioOutput {
-    /// Push decoded samples here; the cpal output callback drains them.
-    pub buf: Arc<Mutex<std::collections::VecDeque<f32>>>,
+    /// Each source gets a track here; the cpal output callback mixes them.
+    pub(crate) mixer: Arc<Mixer>,
+    /// Callback to speaker, as the device reports it.
+    delay: Arc<DeviceDelay>,
+    /// Notified when the open device disappears (unplugged, switched off).
+    pub(crate) lost: Arc<Notify>,
+    /// The open device; replaced by `reopen`.
+    device: Mutex<OutputDevice>,
+}
+
+/// An open output device.
+struct OutputDevice {
+    name: String,
+    /// The name it was opened by; `None` if it's the default.
+    chosen: Option<String>,
     /// Dropping this ends the output thread and stops the cpal stream.
     _kill: std::sync::mpsc::Sender<()>,
 }
 
 impl AudioOutput {
-    pub fn new() -> Result<Self> {
-        // ── Step 1: Discover output config ───────────────────────────────────
-        let (sample_format, _channels, buffer_size) = {
-            let host = cpal::default_host();
-            let dev = host
-                .default_output_device()
-                .ok_or_else(|| anyhow::anyhow!("no default output device"))?;
-            let cfg = dev.default_output_config()?;
-            (cfg.sample_format(), cfg.channels() as u32, cfg.config())
-        };
+    /// Open the output device named `device`, or the default one.
+    pub fn new(device: Option<&str>) -> Result<Self> {
+        let mixer = Mixer::new(LIVEKIT_RATE);
+        let delay = Arc::new(DeviceDelay::default());
+        let lost = Arc::new(Notify::new());
+        let opened = open_output(device, mixer.clone(), delay.clone(), lost.clone())?;
+        Ok(Self {
+            mixer,
+            delay,
+            lost,
+            device: Mutex::new(opened),
+        })
+    }
 
-        // ── Step 2: Shared ring buffer ────────────────────────────────────────
-        let buf: Arc<Mutex<std::collections::VecDeque<f32>>> =
-            Arc::new(Mutex::new(std::collections::VecDeque::with_capacity(192_000)));
-
-        let (kill_tx, kill_rx) = std::sync::mpsc::channel::<()>();
-        let (ready_tx, ready_rx) = std::sync::mpsc::channel::<Result<(), String>>();
-
-        // ── Step 3: Build+own the cpal output stream on a dedicated thread ────
-        let buf_out = buf.clone();
-        std::thread::spawn(move || {
-            let host = cpal::default_host();
-            let dev = match host.default_output_device() {
-                Some(d) => d,
-                None => {
-                    let _ = ready_tx.send(Err("no default output device".into()));
-                    return;
-                }
-            };
-            let stream = match build_output_stream(sample_format, &buffer_size, &dev, buf_out) {
-                Ok(s) => s,
-                Err(e) => {
-                    let _ = ready_tx.send(Err(format!("build output stream: {e}")));
-                    return;
-                }
-            };
-            if let Err(e) = stream.play() {
-                let _ = ready_tx.send(Err(format!("play output stream: {e}")));
-                return;
-            }
-            let _ = ready_tx.send(Ok(()));
-            let _ = kill_rx.recv();
-            // `stream` dropped here.
-        });
+    /// Replace the open device with `device`, or the default one. If it
+    /// can't be opened, the current one stays. Blocks like
+    /// `AudioCapture::reopen`.
+    pub fn reopen(&self, device: Option<&str>) -> Result<()> {
+        let opened = open_output(
+            device,
+            self.mixer.clone(),
+            self.delay.clone(),
+            self.lost.clone(),
+        )?;
+        *self.device.lock().unwrap() = opened;
+        Ok(())
+    }
 
-        ready_rx
-            .recv()
-            .map_err(|_| anyhow::anyhow!("output thread died before ready"))?
-            .map_err(|e| anyhow::anyhow!("{e}"))?;
+    /// Name of the open output device.
+    pub fn device_name(&self) -> String {
+        self.device.lock().unwrap().name.clone()
+    }
 
-        Ok(Self { buf, _kill: kill_tx })
+    /// The name the open device was asked for by, or `None` if it's
+    /// following the OS default.
+    pub(crate) fn chosen_device(&self) -> Option<String> {
+        self.device.lock().unwrap().chosen.clone()
     }
 
-    /// Push a batch of i16 samples into the playback ring buffer.
-    pub fn push_samples(&self, samples: &[i16]) {
-        let mut guard = self.buf.lock().unwrap();
-        for &s in samples {
-            guard.push_back(s as f32 / i16::MAX as f32);
+    /// What stands between pushed samples and the speaker, for latency reports.
+    pub(crate) fn playback(&self) -> Playback {
+        Playback {
+            delay: self.delay.clone(),
+            mixer: self.mixer.clone(),
         }
-        // Cap at ~2 seconds to prevent unbounded growth.
-        while guard.len() > 192_000 {
-            guard.pop_front();
+    }
+
+    /// A new track in the mix, for one source's audio.
+    pub(crate) fn track(&self) -> TrackInput {
+        self.mixer.track()
+    }
+}
+
+/// Open output device `name` (or the default) and start playing `mixer`
+/// through it, at the device's rate.
+fn open_output(
+    name: Option<&str>,
+    mixer: Arc<Mixer>,
+    delay: Arc<DeviceDelay>,
+    lost: Arc<Notify>,
+) -> Result<OutputDevice> {
+    // ── Step 1: Discover output config ───────────────────────────────────────
+    let (device_name, sample_format, config) = {
+        let host = cpal::default_host();
+        let dev = find_output(&host, name)?;
+        let cfg = dev.default_output_config()?;
+        (
+            dev.name().unwrap_or_default(),
+            cfg.sample_format(),
+            cfg.config(),
+        )
+    };
+
+    let (kill_tx, kill_rx) = std::sync::mpsc::channel::<()>();
+    let (ready_tx, ready_rx) = std::sync::mpsc::channel::<Result<(), String>>();
+
+    // ── Step 2: Build+own the cpal output stream on a dedicated thread ────────
+    let thread_name = name.map(str::to_owned);
+    let rate = config.sample_rate.0;
+    let mixer_out = mixer.clone();
+    std::thread::spawn(move || {
+        let host = cpal::default_host();
+        let dev = match find_output(&host, thread_name.as_deref()) {
+            Ok(d) => d,
+            Err(e) => {
+                let _ = ready_tx.send(Err(e.to_string()));
+                return;
+            }
+        };
+        let liveness = Arc::new(Liveness::default());
+        let stream = match build_output_stream(
+            sample_format,
+            &config,
+            &dev,
+            mixer_out,
+            delay,
+            liveness.clone(),
+        ) {
+            Ok(s) => s,
+            Err(e) => {
+                let _ = ready_tx.send(Err(format!("build output stream: {e}")));
+                return;
+            }
+        };
+        if let Err(e) = stream.play() {
+            let _ = ready_tx.send(Err(format!("play output stream: {e}")));
+            return;
+        }
+        let _ = ready_tx.send(Ok(()));
+        // Run until AudioOutput drops or replaces us, or the device goes
+        // away; `stream` is dropped on return.
+        if liveness.wait(&kill_rx) {
+            warn!("output device lost");
+            lost.notify_one();
         }
+    });
+
+    ready_rx
+        .recv()
+        .map_err(|_| anyhow::anyhow!("output thread died before ready"))?
+        .map_err(|e| anyhow::anyhow!("{e}"))?;
+    // The old device's stream may pull a little more at the old rate until
+    // it's dropped; the mix only loses what was queued.
+    mixer.set_rate(rate);
+
+    Ok(OutputDevice {
+        name: device_name,
+        chosen: name.map(str::to_owned),
+        _kill: kill_tx,
+    })
+}
+
+/// Short two-note chime played when someone joins (rising) or leaves
+/// (falling) the call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cue {
+    Join,
+    Leave,
+}
+
+/// Play `cue` on top of whatever is queued, so it doesn't wait behind
+/// buffered speech.
+pub(crate) fn play_cue(mixer: &Mixer, cue: Cue) {
+    const RATE: f32 = 48_000.0;
+    const NOTE: usize = 4_800; // 100 ms per note
+    let notes = match cue {
+        Cue::Join => [660.0, 880.0],
+        Cue::Leave => [880.0, 660.0],
+    };
+    let samples: Vec<f32> = notes
+        .iter()
+        .flat_map(|&freq| {
+            (0..NOTE).map(move |i| {
+                // Linear fade in/out over the note to avoid clicks.
+                let env = (i.min(NOTE - i) as f32 / 480.0).min(1.0);
+                0.15 * env * (std::f32::consts::TAU * freq * i as f32 / RATE).sin()
+            })
+        })
+        .collect();
+    mixer.play(&samples, RATE as u32);
+}
+
+/// Play a one-second 440 Hz tone on output device `device` (or the default)
+/// so users can check their speakers. Blocks until the tone has played.
+pub fn play_test_tone(device: Option<&str>) -> Result<()> {
+    const RATE: f32 = 48_000.0;
+    const LEN: usize = 48_000;
+    let output = AudioOutput::new(device)?;
+    let tone: Vec<f32> = (0..LEN)
+        .map(|i| {
+            // 20 ms fade in/out to avoid clicks.
+            let env = (i.min(LEN - i) as f32 / 960.0).min(1.0);
+            0.2 * env * (std::f32::consts::TAU * 440.0 * i as f32 / RATE).sin()
+        })
+        .collect();
+    output.mixer.play(&tone, RATE as u32);
+    // Let the tone drain before dropping the stream, but don't hang on a
+    // device that stopped pulling samples.
+    let deadline = Instant::now() + Duration::from_secs(3);
+    while output.mixer.queued() > 0 && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(50));
     }
+    Ok(())
 }
 
 fn build_output_stream(
     fmt: cpal::SampleFormat,
     config: &cpal::StreamConfig,
     device: &cpal::Device,
-    buf: Arc<Mutex<std::collections::VecDeque<f32>>>,
+    mixer: Arc<Mixer>,
+    delay: Arc<DeviceDelay>,
+    liveness: Arc<Liveness>,
 ) -> Result<cpal::Stream> {
+    let channels = usize::from(config.channels);
+    let liveness_err = liveness.clone();
+    let on_error = move |e: cpal::StreamError| {
+        warn!("cpal output error: {e}");
+        liveness_err.error(&e);
+    };
     let stream = match fmt {
-        cpal::SampleFormat::F32 => {
-            let b = buf.clone();
-            device.build_output_stream::<f32, _, _>(
-                config,
-                move |data: &mut [f32], _| {
-                    let mut g = b.lock().unwrap();
-                    for s in data.iter_mut() {
-                        *s = g.pop_front().unwrap_or(0.0);
-                    }
-                },
-                |e| warn!("cpal output error: {e}"),
-                None,
-            )?
-        }
+        cpal::SampleFormat::F32 => device.build_output_stream::<f32, _, _>(
+            config,
+            move |data: &mut [f32], info: &cpal::OutputCallbackInfo| {
+                liveness.tick();
+                let ts = info.timestamp();
+                delay.record(ts.playback.duration_since(&ts.callback));
+                mixer.mix(data, channels);
+            },
+            on_error,
+            None,
+        )?,
         cpal::SampleFormat::I16 => {
-            let b = buf.clone();
+            let mut mixed = Vec::new();
             device.build_output_stream::<i16, _, _>(
                 config,
-                move |data: &mut [i16], _| {
-                    let mut g = b.lock().unwrap();
-                    for s in data.iter_mut() {
-                        *s = g
-                            .pop_front()
-                            .map(|f| (f * i16::MAX as f32) as i16)
-                            .unwrap_or(0);
+                move |data: &mut [i16], info: &cpal::OutputCallbackInfo| {
+                    liveness.tick();
+                    let ts = info.timestamp();
+                    delay.record(ts.playback.duration_since(&ts.callback));
+                    mixed.resize(data.len(), 0.0);
+                    mixer.mix(&mut mixed, channels);
+                    for (s, &f) in data.iter_mut().zip(&mixed) {
+                        *s = (f * i16::MAX as f32) as i16;
                     }
                 },
-                |e| warn!("cpal output error: {e}"),
+                on_error,
                 None,
             )?
         }
@@ -0,0 +1,198 @@
+// In-call data messages — ephemeral signals sent over LiveKit data packets
+// instead of the Matrix timeline (reactions, typing, hand bursts, …).
+//
+// Wire format: a JSON envelope `{"v": 1, "type": "...", "content": {...}}`
+// published on the `spoke` topic. Unknown types are ignored on receipt so
+// newer clients can add message kinds without breaking older ones.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// LiveKit data topic used for all Spoke envelopes.
+pub const DATA_TOPIC: &str = "spoke";
+
+/// Envelope version. Bump only for incompatible changes to the envelope itself.
+const ENVELOPE_VERSION: u32 = 1;
+
+/// Reactions allowed per participant within `REACTION_WINDOW`, applied both
+/// when sending and (per sender) when receiving.
+pub const REACTION_BURST: usize = 4;
+pub const REACTION_WINDOW: Duration = Duration::from_secs(2);
+
+/// Longest reaction shown, in grapheme clusters: room for an emoji or two,
+/// not a banner across everyone's overlay.
+pub const REACTION_MAX_GRAPHEMES: usize = 3;
+
+/// Longest reaction shown, in bytes, so a single cluster piled high with
+/// combining marks doesn't slip through.
+const REACTION_MAX_BYTES: usize = 64;
+
+/// A typed in-call signal.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "content")]
+pub enum DataMessage {
+    /// Quick hand raise, e.g. to ask to speak in a regular (non-stage) call.
+    #[serde(rename = "org.spoke.hand")]
+    Hand { raised: bool },
+    /// A short emoji burst.
+    #[serde(rename = "org.spoke.reaction")]
+    Reaction { emoji: String },
+    /// The sender is typing in the call's chat.
+    #[serde(rename = "org.spoke.typing")]
+    Typing { typing: bool },
+    /// The sender is about to send a latency marker on its mic track;
+    /// `responder` should send one back as soon as it hears it.
+    #[serde(rename = "org.spoke.latency_probe")]
+    LatencyProbe { responder: String },
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    v: u32,
+    #[serde(flatten)]
+    message: serde_json::Value,
+}
+
+impl DataMessage {
+    /// Whether the message must arrive (reliable, ordered) or may be dropped
+    /// under loss (lossy, lower latency).
+    pub fn reliable(&self) -> bool {
+        match self {
+            DataMessage::Hand { .. } | DataMessage::LatencyProbe { .. } => true,
+            DataMessage::Reaction { .. } | DataMessage::Typing { .. } => false,
+        }
+    }
+
+    /// Serialize into the wire envelope.
+    pub fn encode(&self) -> Vec<u8> {
+        let envelope = Envelope {
+            v: ENVELOPE_VERSION,
+            message: serde_json::to_value(self).unwrap_or_default(),
+        };
+        serde_json::to_vec(&envelope).unwrap_or_default()
+    }
+
+    /// Whether a received message is small enough to show. Only reactions
+    /// carry free text worth bounding.
+    pub fn within_limits(&self) -> bool {
+        match self {
+            DataMessage::Reaction { emoji } => {
+                !emoji.is_empty()
+                    && emoji.len() <= REACTION_MAX_BYTES
+                    && emoji.graphemes(true).nth(REACTION_MAX_GRAPHEMES).is_none()
+            }
+            _ => true,
+        }
+    }
+
+    /// Parse a wire envelope. Returns `None` for malformed payloads, other
+    /// envelope versions, and message types this client doesn't know.
+    pub fn decode(payload: &[u8]) -> Option<Self> {
+        let envelope: Envelope = serde_json::from_slice(payload).ok()?;
+        if envelope.v != ENVELOPE_VERSION {
+            return None;
+        }
+        serde_json::from_value(envelope.message).ok()
+    }
+}
+
+// ── Rate limiting ─────────────────────────────────────────────────────────────
+
+/// Sliding-window limiter: at most `max` events in any `window`.
+#[derive(Debug)]
+pub struct RateLimiter {
+    max: usize,
+    window: Duration,
+    recent: VecDeque<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(max: usize, window: Duration) -> Self {
+        Self {
+            max,
+            window,
+            recent: VecDeque::with_capacity(max),
+        }
+    }
+
+    /// Limiter used for emoji reactions.
+    pub fn reactions() -> Self {
+        Self::new(REACTION_BURST, REACTION_WINDOW)
+    }
+
+    /// Record an event now if it fits in the window; `false` if it doesn't.
+    pub fn allow(&mut self) -> bool {
+        self.allow_at(Instant::now())
+    }
+
+    fn allow_at(&mut self, now: Instant) -> bool {
+        while self
+            .recent
+            .front()
+            .is_some_and(|t| now.duration_since(*t) >= self.window)
+        {
+            self.recent.pop_front();
+        }
+        if self.recent.len() >= self.max {
+            return false;
+        }
+        self.recent.push_back(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reaction(emoji: &str) -> DataMessage {
+        DataMessage::Reaction {
+            emoji: emoji.to_owned(),
+        }
+    }
+
+    #[test]
+    fn reactions_are_capped_in_graphemes() {
+        assert!(reaction("👍").within_limits());
+        // One cluster each, though several code points.
+        assert!(reaction("👨‍👩‍👧‍👦🏳️‍🌈👍🏽").within_limits());
+        assert!(!reaction("👍👍👍👍").within_limits());
+        assert!(!reaction("").within_limits());
+        assert!(!reaction(&format!("a{}", "\u{301}".repeat(64))).within_limits());
+    }
+
+    #[test]
+    fn allows_a_burst_then_refuses() {
+        let mut limiter = RateLimiter::reactions();
+        let now = Instant::now();
+        assert!((0..REACTION_BURST).all(|_| limiter.allow_at(now)));
+        assert!(!limiter.allow_at(now));
+    }
+
+    #[test]
+    fn window_slides() {
+        let mut limiter = RateLimiter::new(2, Duration::from_secs(2));
+        let start = Instant::now();
+        assert!(limiter.allow_at(start));
+        assert!(limiter.allow_at(start + Duration::from_secs(1)));
+        assert!(!limiter.allow_at(start + Duration::from_millis(1999)));
+        // The first event has left the window; the second hasn't.
+        assert!(limiter.allow_at(start + Duration::from_secs(2)));
+        assert!(!limiter.allow_at(start + Duration::from_millis(2500)));
+        assert!(limiter.allow_at(start + Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn refusals_dont_count() {
+        let mut limiter = RateLimiter::new(1, Duration::from_secs(2));
+        let start = Instant::now();
+        assert!(limiter.allow_at(start));
+        assert!(!limiter.allow_at(start + Duration::from_secs(1)));
+        assert!(limiter.allow_at(start + Duration::from_secs(2)));
+    }
+}
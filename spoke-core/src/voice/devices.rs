@@ -0,0 +1,99 @@
+// Audio devices — what the OS offers for capture and playback, and which of
+// them a session should use.
+//
+// Devices are known by name only: that's all cpal gives that survives a
+// restart, so it's what settings store. A stored name that's no longer
+// present fails to open like any other error rather than silently falling
+// back, so the UI can say so.
+
+use anyhow::Result;
+use cpal::traits::{DeviceTrait, HostTrait};
+use tracing::warn;
+
+/// Which way a device faces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceKind {
+    Input,
+    Output,
+}
+
+/// The devices a session opens; `None` means the OS default.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceChoice {
+    pub input: Option<String>,
+    pub output: Option<String>,
+}
+
+/// Every capture and playback device the OS offers, by name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AudioDevices {
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    pub default_input: Option<String>,
+    pub default_output: Option<String>,
+}
+
+/// List the OS's audio devices. Blocks while the backend enumerates them.
+pub fn list() -> AudioDevices {
+    let host = cpal::default_host();
+    let (default_input, default_output) = defaults();
+    AudioDevices {
+        inputs: input_devices(),
+        outputs: match host.output_devices() {
+            Ok(devices) => devices.filter_map(|d| d.name().ok()).collect(),
+            Err(e) => {
+                warn!("listing output devices: {e}");
+                Vec::new()
+            }
+        },
+        default_input,
+        default_output,
+    }
+}
+
+/// Names of the OS's default input and output devices. Cheaper than `list`.
+pub(crate) fn defaults() -> (Option<String>, Option<String>) {
+    let host = cpal::default_host();
+    (
+        host.default_input_device().and_then(|d| d.name().ok()),
+        host.default_output_device().and_then(|d| d.name().ok()),
+    )
+}
+
+/// Names of the input devices the OS offers.
+pub fn input_devices() -> Vec<String> {
+    let host = cpal::default_host();
+    match host.input_devices() {
+        Ok(devices) => devices.filter_map(|d| d.name().ok()).collect(),
+        Err(e) => {
+            warn!("listing input devices: {e}");
+            Vec::new()
+        }
+    }
+}
+
+/// The input device named `name`, or the default one.
+pub(crate) fn find_input(host: &cpal::Host, name: Option<&str>) -> Result<cpal::Device> {
+    match name {
+        None => host
+            .default_input_device()
+            .ok_or_else(|| anyhow::anyhow!("no default input device")),
+        Some(name) => host
+            .input_devices()?
+            .find(|d| d.name().is_ok_and(|n| n == name))
+            .ok_or_else(|| anyhow::anyhow!("no input device named {name:?}")),
+    }
+}
+
+/// The output device named `name`, or the default one.
+pub(crate) fn find_output(host: &cpal::Host, name: Option<&str>) -> Result<cpal::Device> {
+    match name {
+        None => host
+            .default_output_device()
+            .ok_or_else(|| anyhow::anyhow!("no default output device")),
+        Some(name) => host
+            .output_devices()?
+            .find(|d| d.name().is_ok_and(|n| n == name))
+            .ok_or_else(|| anyhow::anyhow!("no output device named {name:?}")),
+    }
+}
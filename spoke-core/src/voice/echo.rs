@@ -0,0 +1,139 @@
+// Acoustic echo cancellation — without headphones, whatever the speakers
+// play reaches the mic and goes back out to everyone, who then hear
+// themselves a moment later.
+//
+// WebRTC's audio processing module takes away the part of the mic signal
+// that matches what was played. The mixer hands over everything it sends to
+// the speakers (the "reverse" stream); the capture feeder passes it on just
+// before each mic frame, so the module always has the reference first and
+// works out the delay between the two itself.
+//
+// The module wants 10 ms frames at 48 kHz. Capture already runs at that
+// rate; the reference is converted from the output rate here, off the
+// output callback.
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use livekit::webrtc::native::apm::AudioProcessingModule;
+use tracing::warn;
+
+use super::resample::{LIVEKIT_RATE, Resampler};
+
+/// Samples in one 10 ms frame at `LIVEKIT_RATE`.
+pub(crate) const FRAME_SAMPLES: usize = LIVEKIT_RATE as usize / 100;
+/// Most reference audio kept waiting, in seconds. Only piles up if the mic
+/// stops pulling it, e.g. while muted.
+const MAX_REFERENCE_SECS: u32 = 1;
+
+/// Cancels speaker echo out of the mic.
+pub(crate) struct EchoCanceller {
+    enabled: AtomicBool,
+    /// What the speakers played, not yet given to the module.
+    reference: Mutex<Reference>,
+    state: Mutex<State>,
+}
+
+struct Reference {
+    /// Output rate, Hz.
+    rate: u32,
+    samples: VecDeque<f32>,
+}
+
+struct State {
+    apm: AudioProcessingModule,
+    /// Reference converted to `LIVEKIT_RATE`; remade if the output rate changes.
+    resampler: Option<Resampler>,
+    /// Converted reference short of a whole frame.
+    pending: Vec<f32>,
+}
+
+impl EchoCanceller {
+    pub(crate) fn new() -> Arc<Self> {
+        Arc::new(Self {
+            enabled: AtomicBool::new(true),
+            reference: Mutex::new(Reference {
+                rate: LIVEKIT_RATE,
+                samples: VecDeque::new(),
+            }),
+            state: Mutex::new(State {
+                // Echo cancellation, plus the high-pass filter it works best behind.
+                apm: AudioProcessingModule::new(true, false, true, false),
+                resampler: None,
+                pending: Vec::new(),
+            }),
+        })
+    }
+
+    /// Turn cancellation on or off (on by default); headphone users don't
+    /// need it.
+    pub(crate) fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            self.reference.lock().unwrap().samples.clear();
+        }
+    }
+
+    /// Note that `samples` (mono, `rate` Hz) just went to the speakers.
+    pub(crate) fn played(&self, samples: &[f32], rate: u32) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut reference = self.reference.lock().unwrap();
+        if reference.rate != rate {
+            reference.rate = rate;
+            reference.samples.clear();
+        }
+        reference.samples.extend(samples);
+        let excess = reference
+            .samples
+            .len()
+            .saturating_sub((MAX_REFERENCE_SECS * rate) as usize);
+        reference.samples.drain(..excess);
+    }
+
+    /// Remove echo from one mic frame: `FRAME_SAMPLES` mono samples at
+    /// `LIVEKIT_RATE`.
+    pub(crate) fn process(&self, frame: &mut [i16]) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        let (rate, played): (u32, Vec<f32>) = {
+            let mut reference = self.reference.lock().unwrap();
+            (reference.rate, reference.samples.drain(..).collect())
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let State {
+            apm,
+            resampler,
+            pending,
+        } = &mut *state;
+        if resampler.as_ref().is_some_and(|r| r.from_rate() != rate) {
+            *resampler = None;
+        }
+        resampler
+            .get_or_insert_with(|| Resampler::new(rate, LIVEKIT_RATE, 1))
+            .process(&played, pending);
+        let whole = pending.len() / FRAME_SAMPLES * FRAME_SAMPLES;
+        for chunk in pending[..whole].chunks_exact(FRAME_SAMPLES) {
+            let mut reverse: Vec<i16> = chunk
+                .iter()
+                .map(|&s| (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
+                .collect();
+            if let Err(e) = apm.process_reverse_stream(&mut reverse, LIVEKIT_RATE as i32, 1) {
+                warn!("echo canceller reference: {e}");
+            }
+        }
+        pending.drain(..whole);
+
+        if let Err(e) = apm.process_stream(frame, LIVEKIT_RATE as i32, 1) {
+            warn!("echo canceller: {e}");
+        }
+    }
+}
@@ -1,20 +1,10 @@
 // Matrix signaling events for Spoke voice.
 // These are sent to the room when a user joins/leaves/mutes voice.
 
-use matrix_sdk::ruma::events::macros::EventContent;
+use matrix_sdk::ruma::{OwnedRoomId, OwnedUserId, events::macros::EventContent};
 
-/// Sent when a local user joins the voice channel.
-#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, EventContent)]
-#[ruma_event(type = "org.spoke.voice.join", kind = MessageLike)]
-pub struct VoiceJoinEventContent {
-    /// Opaque session identifier (UUID) so other clients can correlate events.
-    pub session_id: String,
-}
-
-/// Sent when a local user leaves the voice channel.
-#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, EventContent)]
-#[ruma_event(type = "org.spoke.voice.leave", kind = MessageLike)]
-pub struct VoiceLeaveEventContent {}
+/// Custom `m.room.create` room type marking a room as a Spoke voice channel.
+pub const VOICE_CHANNEL_ROOM_TYPE: &str = "org.spoke.voice";
 
 /// Sent when the local user toggles microphone mute state.
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize, EventContent)]
@@ -22,3 +12,306 @@ pub struct VoiceLeaveEventContent {}
 pub struct VoiceMuteEventContent {
     pub muted: bool,
 }
+
+/// Sent by a stage listener to ask moderators for speaking rights.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, EventContent)]
+#[ruma_event(type = "org.spoke.voice.hand", kind = MessageLike)]
+pub struct VoiceHandEventContent {
+    /// `true` to raise the hand, `false` to lower it.
+    pub raised: bool,
+}
+
+/// Posted by the last participant out when a call ends, so the room keeps a
+/// record of voice activity.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, EventContent)]
+#[ruma_event(type = "org.spoke.voice.summary", kind = MessageLike)]
+pub struct VoiceSummaryEventContent {
+    /// When the poster joined, in milliseconds since the Unix epoch.
+    pub started_ts: u64,
+    pub duration_secs: u64,
+    /// Everyone seen in the call, the poster included.
+    pub participants: Vec<OwnedUserId>,
+    /// Most participants in the call at once.
+    pub peak_participants: u32,
+}
+
+impl VoiceSummaryEventContent {
+    /// One-line rendering for the timeline, e.g.
+    /// "Call ended · 12m 05s · 4 participants (peak 3)".
+    pub fn describe(&self) -> String {
+        let (h, m, s) = (
+            self.duration_secs / 3600,
+            self.duration_secs / 60 % 60,
+            self.duration_secs % 60,
+        );
+        let duration = if h > 0 {
+            format!("{h}h {m:02}m")
+        } else {
+            format!("{m}m {s:02}s")
+        };
+        let n = self.participants.len();
+        let people = if n == 1 {
+            "1 participant".to_owned()
+        } else {
+            format!("{n} participants")
+        };
+        format!(
+            "Call ended · {duration} · {people} (peak {})",
+            self.peak_participants
+        )
+    }
+}
+
+// ── To-device events ──────────────────────────────────────────────────────────
+
+/// Rings a user's devices directly to invite them into a call (a DM call),
+/// and ends the ringing. See `ring`.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize, EventContent)]
+#[ruma_event(type = "org.spoke.voice.ring", kind = ToDevice)]
+pub struct VoiceRingEventContent {
+    /// The room whose call to join.
+    pub room_id: OwnedRoomId,
+    /// Ties a ring to the answer, decline or cancel that ends it.
+    pub call_id: String,
+    pub action: RingAction,
+    /// When the ring was sent, in milliseconds since the Unix epoch.
+    pub ts: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RingAction {
+    /// Start ringing.
+    Ring,
+    /// The caller gave up.
+    Cancel,
+    /// The callee won't join.
+    Decline,
+    /// The callee joined, from one of their devices.
+    Answer,
+}
+
+// ── State events ──────────────────────────────────────────────────────────────
+
+/// A user's presence in the room's call, under their user ID as state key.
+/// Joining sets it, a heartbeat pushes `expires_ts` forward, and leaving
+/// empties it; a membership past `expires_ts` counts as gone, so a crashed
+/// client doesn't haunt the call. See `membership`.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize, EventContent)]
+#[ruma_event(type = "org.spoke.voice.member", kind = State, state_key_type = OwnedUserId)]
+pub struct VoiceMemberEventContent {
+    /// Opaque session identifier (UUID) so other clients can correlate events.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// When this session joined, in milliseconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub joined_ts: Option<u64>,
+    /// When the membership lapses unless renewed, in milliseconds since the
+    /// Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_ts: Option<u64>,
+}
+
+/// Room-wide voice configuration, set by room moderators.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize, EventContent)]
+#[ruma_event(type = "org.spoke.voice.config", kind = State, state_key_type = EmptyStateKey)]
+pub struct VoiceConfigEventContent {
+    /// Stage mode: only users in `org.spoke.voice.stage` (or who can edit it)
+    /// receive publish grants; everyone else joins as a listener.
+    #[serde(default)]
+    pub stage: bool,
+    /// Power levels needed for each voice capability.
+    #[serde(default)]
+    pub permissions: VoicePermissions,
+    /// Bitrate everyone publishes their mic at.
+    #[serde(default)]
+    pub bitrate: BitrateTier,
+    /// Most people in the call at once; the sidecar refuses grants beyond it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_participants: Option<u32>,
+    /// Users refused voice grants in this room, though they may still chat.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub banned: Vec<OwnedUserId>,
+}
+
+/// Largest participant cap that can be set; LiveKit rooms get unwieldy well
+/// before this.
+pub const MAX_PARTICIPANT_CAP: u32 = 500;
+
+impl VoiceConfigEventContent {
+    /// Why this config can't be sent, if it can't.
+    pub fn validate(&self) -> Result<(), String> {
+        match self.max_participants {
+            Some(0) => return Err("the participant cap must be at least 1".into()),
+            Some(n) if n > MAX_PARTICIPANT_CAP => {
+                return Err(format!(
+                    "the participant cap can't be over {MAX_PARTICIPANT_CAP}"
+                ));
+            }
+            _ => {}
+        }
+        for (i, user_id) in self.banned.iter().enumerate() {
+            if self.banned[..i].contains(user_id) {
+                return Err(format!("{user_id} is listed twice"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Audio quality of a voice channel, as Opus bitrates.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BitrateTier {
+    /// 24 kbps, for poor connections.
+    Low,
+    /// 48 kbps.
+    #[default]
+    Standard,
+    /// 96 kbps.
+    High,
+    /// 128 kbps, for music.
+    Studio,
+}
+
+impl BitrateTier {
+    pub const ALL: [BitrateTier; 4] = [
+        BitrateTier::Low,
+        BitrateTier::Standard,
+        BitrateTier::High,
+        BitrateTier::Studio,
+    ];
+
+    pub fn kbps(self) -> u32 {
+        match self {
+            BitrateTier::Low => 24,
+            BitrateTier::Standard => 48,
+            BitrateTier::High => 96,
+            BitrateTier::Studio => 128,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            BitrateTier::Low => "Low (24 kbps)",
+            BitrateTier::Standard => "Standard (48 kbps)",
+            BitrateTier::High => "High (96 kbps)",
+            BitrateTier::Studio => "Studio (128 kbps)",
+        }
+    }
+}
+
+/// Minimum power level for each voice capability. The sidecar applies these
+/// when issuing grants; in stage mode, speaking additionally needs a place on
+/// the stage.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct VoicePermissions {
+    #[serde(default)]
+    pub speak: i64,
+    #[serde(default)]
+    pub screen_share: i64,
+    /// Priority speakers duck everyone else while they talk.
+    #[serde(default = "default_priority_speaker")]
+    pub priority_speaker: i64,
+}
+
+impl Default for VoicePermissions {
+    fn default() -> Self {
+        Self {
+            speak: 0,
+            screen_share: 0,
+            priority_speaker: default_priority_speaker(),
+        }
+    }
+}
+
+fn default_priority_speaker() -> i64 {
+    50
+}
+
+/// The current set of stage speakers. Moderators promote a listener by adding
+/// them here; clients watching this event refresh their LiveKit grant.
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize, EventContent)]
+#[ruma_event(type = "org.spoke.voice.stage", kind = State, state_key_type = EmptyStateKey)]
+pub struct VoiceStageEventContent {
+    #[serde(default)]
+    pub speakers: Vec<OwnedUserId>,
+}
+
+/// A space's AFK channel. Members who say nothing for `timeout_mins` in any
+/// of the space's voice channels are moved there by their own client.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, EventContent)]
+#[ruma_event(type = "org.spoke.voice.afk", kind = State, state_key_type = EmptyStateKey)]
+pub struct VoiceAfkEventContent {
+    /// The AFK voice channel; `None` turns the policy off.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub room_id: Option<OwnedRoomId>,
+    #[serde(default = "default_afk_timeout_mins")]
+    pub timeout_mins: u32,
+}
+
+impl Default for VoiceAfkEventContent {
+    fn default() -> Self {
+        Self {
+            room_id: None,
+            timeout_mins: default_afk_timeout_mins(),
+        }
+    }
+}
+
+fn default_afk_timeout_mins() -> u32 {
+    5
+}
+
+/// A MatrixRTC call membership (MSC3401/MSC4143), one per device, so clients
+/// like Element Call see who's in the room's call. Leaving replaces it with
+/// empty content, which is why every field is optional. See `rtc`.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize, EventContent)]
+#[ruma_event(type = "m.call.member", kind = State, state_key_type = String)]
+pub struct CallMemberEventContent {
+    /// "m.call" for a voice/video call.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub application: Option<String>,
+    /// Empty for the room's one call.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub call_id: Option<String>,
+    /// "m.room" for a call everyone in the room can join.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<String>,
+    /// When the membership was made, in milliseconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_ts: Option<u64>,
+    /// Milliseconds after `created_ts` the membership lapses unless renewed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<u64>,
+    /// How the call's SFU is chosen among the members' preferred ones.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub focus_active: Option<ActiveFocus>,
+    /// SFUs this member can be reached through, most preferred first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub foci_preferred: Vec<LivekitFocus>,
+}
+
+/// MatrixRTC `focus_active`: which SFU the call uses.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ActiveFocus {
+    /// "livekit".
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// "oldest_membership": everyone follows whoever joined first.
+    pub focus_selection: String,
+}
+
+/// A LiveKit SFU a member can be reached through.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct LivekitFocus {
+    /// "livekit".
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Where to get a LiveKit token for the call.
+    pub livekit_service_url: String,
+    /// The LiveKit room name; the Matrix room ID.
+    pub livekit_alias: String,
+}
@@ -0,0 +1,109 @@
+// Device hot-plug — the speakers going away mid-call, and the OS default
+// changing under a session that follows it (a headset plugged in, a new
+// default picked in the OS sound settings). The mic going away is `mic`'s.
+//
+// Lost speakers are reopened like a lost mic: the chosen device, or failing
+// that the default, backing off between attempts. Defaults are polled, since
+// cpal has no notification for them; a stream opened as the default is moved
+// to the new one when the name changes.
+
+use std::{sync::Arc, time::Duration};
+
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use super::{
+    VoiceEvent,
+    audio::{AudioCapture, AudioOutput},
+    devices::{self, DeviceKind},
+    mic::{RETRY_MAX, RETRY_MIN},
+};
+
+/// How often to check whether the OS default devices changed.
+const DEFAULT_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Reopen the speakers each time `output` loses its device, for as long as
+/// the session lasts.
+pub(crate) async fn watch_output(output: Arc<AudioOutput>, tx: mpsc::UnboundedSender<VoiceEvent>) {
+    loop {
+        output.lost.notified().await;
+        let _ = tx.send(VoiceEvent::OutputLost);
+
+        let chosen = output.chosen_device();
+        let mut backoff = RETRY_MIN;
+        loop {
+            tokio::time::sleep(backoff).await;
+            let (reopening, chosen) = (output.clone(), chosen.clone());
+            let reopened = tokio::task::spawn_blocking(move || {
+                reopening
+                    .reopen(chosen.as_deref())
+                    .or_else(|e| match chosen {
+                        Some(_) => reopening.reopen(None),
+                        None => Err(e),
+                    })
+            })
+            .await
+            .unwrap_or_else(|e| Err(e.into()));
+            match reopened {
+                Ok(()) => {
+                    let _ = tx.send(VoiceEvent::OutputRestored {
+                        device: output.device_name(),
+                    });
+                    break;
+                }
+                Err(e) => {
+                    warn!("reopening speakers: {e}");
+                    backoff = (backoff * 2).min(RETRY_MAX);
+                }
+            }
+        }
+    }
+}
+
+/// Move whichever of `capture` and `output` follow the OS default onto the
+/// new default whenever it changes.
+pub(crate) async fn follow_defaults(
+    capture: Option<Arc<AudioCapture>>,
+    output: Option<Arc<AudioOutput>>,
+    tx: mpsc::UnboundedSender<VoiceEvent>,
+) {
+    loop {
+        tokio::time::sleep(DEFAULT_CHECK_INTERVAL).await;
+        let Ok((input, speakers)) = tokio::task::spawn_blocking(devices::defaults).await else {
+            continue;
+        };
+
+        if let (Some(capture), Some(default)) = (&capture, input) {
+            if capture.chosen_device().is_none() && capture.device_name() != default {
+                let reopening = capture.clone();
+                let reopened = tokio::task::spawn_blocking(move || reopening.reopen(None)).await;
+                match reopened.unwrap_or_else(|e| Err(e.into())) {
+                    Ok(()) => {
+                        let device = capture.device_name();
+                        let _ = tx.send(VoiceEvent::DeviceSwitched {
+                            kind: DeviceKind::Input,
+                            device,
+                        });
+                    }
+                    Err(e) => warn!("switching mic to new default {default:?}: {e}"),
+                }
+            }
+        }
+        if let (Some(output), Some(default)) = (&output, speakers) {
+            if output.chosen_device().is_none() && output.device_name() != default {
+                let reopening = output.clone();
+                let reopened = tokio::task::spawn_blocking(move || reopening.reopen(None)).await;
+                match reopened.unwrap_or_else(|e| Err(e.into())) {
+                    Ok(()) => {
+                        let device = output.device_name();
+                        let _ = tx.send(VoiceEvent::DeviceSwitched {
+                            kind: DeviceKind::Output,
+                            device,
+                        });
+                    }
+                    Err(e) => warn!("switching speakers to new default {default:?}: {e}"),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,131 @@
+// ICE configuration — user STUN servers plus the sidecar's TURN list,
+// assembled into the RTC configuration handed to LiveKit, and a relay policy
+// that can override what preflight decided.
+//
+// The UDP port range is configured on the LiveKit server (see
+// infra/livekit.yaml); the SDK leaves our local ports to the OS.
+
+use livekit::webrtc::prelude::{IceServer, IceTransportsType, RtcConfiguration};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::preflight::{self, Probe, TurnServer};
+
+/// Whether ICE may use direct (host/STUN) candidates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RelayPolicy {
+    /// Relay only when preflight finds UDP blocked.
+    #[default]
+    Auto,
+    /// Always go through TURN, e.g. to hide our address from peers. Only
+    /// possible when the sidecar hands out TURN servers.
+    Always,
+    /// Never force relay, even if preflight suggests it.
+    Never,
+}
+
+impl RelayPolicy {
+    pub const ALL: [RelayPolicy; 3] = [RelayPolicy::Auto, RelayPolicy::Always, RelayPolicy::Never];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            RelayPolicy::Auto => "Automatic",
+            RelayPolicy::Always => "Always relay",
+            RelayPolicy::Never => "Never force relay",
+        }
+    }
+}
+
+/// User ICE preferences, persisted with the app settings.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IceSettings {
+    /// Extra STUN servers as `stun:host[:port]` URLs. Empty leaves STUN to
+    /// the LiveKit server's defaults.
+    pub stun_servers: Vec<String>,
+    pub relay: RelayPolicy,
+}
+
+impl IceSettings {
+    /// Apply the policy to preflight's relay suggestion.
+    pub fn force_relay(&self, suggested: bool) -> bool {
+        match self.relay {
+            RelayPolicy::Auto => suggested,
+            RelayPolicy::Always => true,
+            RelayPolicy::Never => false,
+        }
+    }
+
+    /// The RTC configuration for a session: our STUN servers plus the
+    /// sidecar's TURN servers. With neither, the server-provided ICE servers
+    /// are used as before. Relay isn't forced without TURN servers to relay
+    /// through, since nothing could connect.
+    pub fn rtc_config(&self, turn_servers: &[TurnServer], force_relay: bool) -> RtcConfiguration {
+        let mut config = livekit::RoomOptions::default().rtc_config;
+        config.ice_servers = self
+            .stun_servers
+            .iter()
+            .map(|url| IceServer {
+                urls: vec![url.clone()],
+                username: String::new(),
+                password: String::new(),
+            })
+            .chain(turn_servers.iter().map(|t| IceServer {
+                urls: vec![t.urls.clone()],
+                username: t.username.clone(),
+                password: t.credential.clone(),
+            }))
+            .collect();
+        if force_relay && turn_servers.is_empty() {
+            warn!("relay-only ICE asked for, but there are no TURN servers; connecting directly");
+        } else if force_relay {
+            config.ice_transport_type = IceTransportsType::Relay;
+        }
+        config
+    }
+}
+
+/// `host:port` from a `stun:host[:port]` URL.
+pub fn stun_address(url: &str) -> Option<String> {
+    let rest = url
+        .strip_prefix("stun:")
+        .or_else(|| url.strip_prefix("stuns:"))?;
+    let host_port = rest.split('?').next().filter(|h| !h.is_empty())?;
+    Some(if host_port.contains(':') {
+        host_port.to_owned()
+    } else {
+        format!("{host_port}:3478")
+    })
+}
+
+/// Test every configured server: a Binding round trip per STUN server and a
+/// UDP and a TCP allocation per TURN server. Returns `(label, result)` pairs.
+pub async fn test_servers(
+    settings: &IceSettings,
+    turn_servers: &[TurnServer],
+) -> Vec<(String, Probe)> {
+    let stun = settings.stun_servers.iter().map(|url| async move {
+        let probe = match stun_address(url) {
+            // STUN over TLS isn't something the probe speaks.
+            Some(_) if url.starts_with("stuns:") => Probe::Skipped,
+            Some(addr) => preflight::probe_udp(&addr).await,
+            None => Probe::Failed(format!("not a stun: URL: {url}")),
+        };
+        vec![(url.clone(), probe)]
+    });
+    let turn = turn_servers.iter().map(|server| async move {
+        let (udp, tcp) = tokio::join!(
+            preflight::probe_turn(server, false),
+            preflight::probe_turn(server, true)
+        );
+        vec![
+            (format!("{} (UDP)", server.urls), udp),
+            (format!("{} (TCP)", server.urls), tcp),
+        ]
+    });
+    let (stun, turn) = tokio::join!(
+        futures::future::join_all(stun),
+        futures::future::join_all(turn)
+    );
+    stun.into_iter().chain(turn).flatten().collect()
+}
@@ -0,0 +1,307 @@
+// Latency measurement — how long speech takes from one mouth to another ear,
+// and where that time goes.
+//
+// We send a short marker tone on our mic track and ask one peer, by data
+// message, to listen for it. When the peer hears the marker in our audio it
+// sends a marker straight back on its own track; the time from sending ours
+// to hearing theirs is the round trip through encoding, the network,
+// LiveKit, the far jitter buffer and decoding, twice over. Half of it is the
+// one-way network share.
+//
+// The ends of the path are local: the capture device's delay comes from the
+// timestamps cpal hands its input callback, and playback is the output
+// device's delay plus whatever is queued in our mixer. Neither shows up
+// in the round trip, since markers are injected after capture and detected
+// before playback.
+
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use super::mixer::Mixer;
+
+/// Marker frequency. At 48 kHz and 10 ms frames it sits exactly on a
+/// Goertzel bin, and it's well above where most voice energy is.
+const MARKER_HZ: f32 = 2_500.0;
+/// How long each marker plays.
+const MARKER_LEN: Duration = Duration::from_millis(80);
+/// Marker level, of full scale.
+const MARKER_LEVEL: f32 = 0.25;
+/// Share of a frame's energy that must be at `MARKER_HZ` to count as a marker.
+const MARKER_PURITY: f32 = 0.6;
+/// Quietest frame (RMS, of full scale) that can count as a marker.
+const MARKER_FLOOR: f32 = 0.02;
+/// Time between asking the peer to listen and sending the marker, so the
+/// request (a data packet) is there before the audio is.
+pub(crate) const PROBE_LEAD: Duration = Duration::from_millis(300);
+/// How long to wait for the marker to come back.
+pub(crate) const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where a measured round trip's time went, as heard from our side.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyReport {
+    /// Identity of the participant who sent the marker back.
+    pub peer: String,
+    /// From our marker leaving the mic pipeline to theirs coming back.
+    pub round_trip: Duration,
+    /// Mic to our pipeline: the capture device's buffering.
+    pub capture: Duration,
+    /// One way through encode, network, jitter buffer and decode: half the
+    /// round trip.
+    pub network: Duration,
+    /// Our pipeline to the speaker: queued audio plus the output device.
+    pub playback: Duration,
+}
+
+impl LatencyReport {
+    /// Estimated mouth-to-ear delay, assuming the peer's devices are like ours.
+    pub fn mouth_to_ear(&self) -> Duration {
+        self.capture + self.network + self.playback
+    }
+}
+
+// ── Device delay ──────────────────────────────────────────────────────────────
+
+/// The delay a cpal stream last reported between the hardware and its
+/// callback, in µs.
+#[derive(Debug, Default)]
+pub(crate) struct DeviceDelay(AtomicU32);
+
+impl DeviceDelay {
+    pub(crate) fn record(&self, delay: Option<Duration>) {
+        if let Some(delay) = delay {
+            self.0.store(
+                delay.as_micros().min(u32::MAX as u128) as u32,
+                Ordering::Relaxed,
+            );
+        }
+    }
+
+    pub(crate) fn get(&self) -> Duration {
+        Duration::from_micros(u64::from(self.0.load(Ordering::Relaxed)))
+    }
+}
+
+/// What stands between a decoded frame and the speaker.
+#[derive(Clone)]
+pub(crate) struct Playback {
+    pub(crate) delay: Arc<DeviceDelay>,
+    pub(crate) mixer: Arc<Mixer>,
+}
+
+impl Playback {
+    fn latency(&self) -> Duration {
+        let queued = self.mixer.queued() as f64 / f64::from(self.mixer.rate().max(1));
+        self.delay.get() + Duration::from_secs_f64(queued)
+    }
+}
+
+// ── Marker tone ───────────────────────────────────────────────────────────────
+
+/// A marker waiting to go out on the mic track, or going out.
+#[derive(Debug, Default)]
+pub(crate) struct Marker {
+    /// Samples per channel of the marker sent so far; `None` when idle.
+    pos: Mutex<Option<usize>>,
+    /// When the current marker's first frame went out.
+    sent_at: Mutex<Option<Instant>>,
+}
+
+impl Marker {
+    /// Send a marker, starting with the next captured frame.
+    fn arm(&self) {
+        *self.sent_at.lock().unwrap() = None;
+        *self.pos.lock().unwrap() = Some(0);
+    }
+
+    fn sent_at(&self) -> Option<Instant> {
+        *self.sent_at.lock().unwrap()
+    }
+
+    /// Overwrite captured `data` (interleaved) with the marker while one is
+    /// going out. Replaces rather than mixes, so speech can't mask it, and
+    /// goes out even while muted.
+    pub(crate) fn apply(&self, data: &mut [i16], sample_rate: u32, channels: u32) {
+        let mut pos = self.pos.lock().unwrap();
+        let Some(p) = pos.as_mut() else { return };
+        if *p == 0 {
+            *self.sent_at.lock().unwrap() = Some(Instant::now());
+        }
+        let len = (MARKER_LEN.as_secs_f32() * sample_rate as f32) as usize;
+        for frame in data.chunks_mut(channels.max(1) as usize) {
+            if *p >= len {
+                break;
+            }
+            let s = MARKER_LEVEL
+                * (std::f32::consts::TAU * MARKER_HZ * *p as f32 / sample_rate as f32).sin();
+            frame.fill((s * i16::MAX as f32) as i16);
+            *p += 1;
+        }
+        if *p >= len {
+            *pos = None;
+        }
+    }
+}
+
+/// Whether a mono frame is mostly marker tone (Goertzel at `MARKER_HZ`).
+pub(crate) fn is_marker(samples: &[i16], sample_rate: u32) -> bool {
+    let n = samples.len();
+    if n == 0 || sample_rate == 0 {
+        return false;
+    }
+    let coeff = 2.0 * (std::f32::consts::TAU * MARKER_HZ / sample_rate as f32).cos();
+    let (mut s1, mut s2, mut energy) = (0.0f32, 0.0f32, 0.0f32);
+    for &x in samples {
+        let x = x as f32 / i16::MAX as f32;
+        energy += x * x;
+        let s = x + coeff * s1 - s2;
+        s2 = s1;
+        s1 = s;
+    }
+    let rms = (energy / n as f32).sqrt();
+    if rms < MARKER_FLOOR {
+        return false;
+    }
+    // A pure tone on the bin has power n²A²/4 and energy nA²/2.
+    let power = s1 * s1 + s2 * s2 - coeff * s1 * s2;
+    power / (energy * n as f32 / 2.0) >= MARKER_PURITY
+}
+
+// ── Probe state ───────────────────────────────────────────────────────────────
+
+enum State {
+    Idle,
+    /// We sent (or are about to send) a marker and are listening for `peer`'s.
+    Probing {
+        peer: String,
+        started: Instant,
+    },
+    /// `peer` asked us to send a marker back once we hear theirs.
+    Reflecting {
+        peer: String,
+        until: Instant,
+    },
+}
+
+/// One session's latency measurement; at most one is under way at a time.
+pub(crate) struct Latency {
+    state: Mutex<State>,
+    /// Our mic's marker and capture delay; `None` for listener sessions,
+    /// which can neither probe nor answer.
+    capture: Option<(Arc<Marker>, Arc<DeviceDelay>)>,
+    playback: Option<Playback>,
+}
+
+impl Latency {
+    pub(crate) fn new(
+        capture: Option<(Arc<Marker>, Arc<DeviceDelay>)>,
+        playback: Option<Playback>,
+    ) -> Self {
+        Self {
+            state: Mutex::new(State::Idle),
+            capture,
+            playback,
+        }
+    }
+
+    pub(crate) fn can_probe(&self) -> bool {
+        self.capture.is_some()
+    }
+
+    /// Start listening for `peer`'s marker. Returns when we started, to
+    /// tell this probe apart from later ones.
+    pub(crate) fn start(&self, peer: &str) -> Instant {
+        let started = Instant::now();
+        *self.state.lock().unwrap() = State::Probing {
+            peer: peer.to_owned(),
+            started,
+        };
+        started
+    }
+
+    /// Send our marker, if probe `started` is still waiting for it.
+    pub(crate) fn send_marker(&self, started: Instant) {
+        let state = self.state.lock().unwrap();
+        if matches!(&*state, State::Probing { started: s, .. } if *s == started) {
+            if let Some((marker, _)) = &self.capture {
+                marker.arm();
+            }
+        }
+    }
+
+    /// Give up on probe `started` if it's still waiting. Returns the peer
+    /// that never answered.
+    pub(crate) fn expire(&self, started: Instant) -> Option<String> {
+        let mut state = self.state.lock().unwrap();
+        let State::Probing { peer, started: s } = &*state else {
+            return None;
+        };
+        if *s != started {
+            return None;
+        }
+        let peer = peer.clone();
+        *state = State::Idle;
+        Some(peer)
+    }
+
+    /// `peer` asked us to answer its marker with ours. Ignored while we're
+    /// probing ourselves, and by listener sessions.
+    pub(crate) fn reflect_for(&self, peer: &str) {
+        if self.capture.is_none() {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        if matches!(&*state, State::Probing { .. }) {
+            return;
+        }
+        *state = State::Reflecting {
+            peer: peer.to_owned(),
+            until: Instant::now() + PROBE_TIMEOUT,
+        };
+    }
+
+    /// Whether `identity`'s audio should be checked for a marker.
+    pub(crate) fn listening_to(&self, identity: &str) -> bool {
+        match &*self.state.lock().unwrap() {
+            State::Idle => false,
+            State::Probing { peer, .. } => peer == identity,
+            State::Reflecting { peer, until } => peer == identity && Instant::now() < *until,
+        }
+    }
+
+    /// A marker was heard in `identity`'s audio. Answers it if we're
+    /// reflecting; returns the report if it finishes our probe.
+    pub(crate) fn heard(&self, identity: &str) -> Option<LatencyReport> {
+        let (marker, capture_delay) = self.capture.as_ref()?;
+        let mut state = self.state.lock().unwrap();
+        match &*state {
+            State::Reflecting { peer, .. } if peer == identity => {
+                marker.arm();
+                *state = State::Idle;
+                None
+            }
+            State::Probing { peer, .. } if peer == identity => {
+                // Before ours went out, this can't be the answer to it.
+                let round_trip = marker.sent_at()?.elapsed();
+                let report = LatencyReport {
+                    peer: peer.clone(),
+                    round_trip,
+                    capture: capture_delay.get(),
+                    network: round_trip / 2,
+                    playback: self
+                        .playback
+                        .as_ref()
+                        .map(Playback::latency)
+                        .unwrap_or_default(),
+                };
+                *state = State::Idle;
+                Some(report)
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,182 @@
+// Level metering — how loud the mic and each remote participant are, for
+// the mic test in settings and the loudness bars in the call view.
+//
+// Every stream adds its frames to a meter as they pass; a reporter takes
+// the RMS and peak since its last look about 20 times a second and sends
+// them all in one event. A stream that sent nothing in between is left out,
+// which the UI reads as silent. Nothing is sent while everyone stays silent.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Result;
+use tokio::sync::mpsc;
+
+use super::{VoiceEvent, audio::AudioCapture};
+
+/// How often levels are reported.
+pub const LEVEL_INTERVAL: Duration = Duration::from_millis(50);
+/// Quietest level a meter shows (dBFS); anything below reads as empty.
+const METER_FLOOR_DB: f32 = -60.0;
+
+/// Loudness of one stream over one report interval, both of full scale.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Level {
+    pub rms: f32,
+    pub peak: f32,
+}
+
+impl Level {
+    /// RMS on a 0..=1 scale for drawing, linear in dB from
+    /// `METER_FLOOR_DB` up to full scale.
+    pub fn meter(&self) -> f32 {
+        if self.rms <= 0.0 {
+            return 0.0;
+        }
+        (1.0 - 20.0 * self.rms.log10() / METER_FLOOR_DB).clamp(0.0, 1.0)
+    }
+
+    fn is_silent(&self) -> bool {
+        self.meter() == 0.0
+    }
+}
+
+/// One stream's audio since the last report.
+#[derive(Debug, Default)]
+pub(crate) struct LevelMeter {
+    sum_sq: f32,
+    samples: usize,
+    peak: f32,
+}
+
+impl LevelMeter {
+    pub(crate) fn record(&mut self, frame: &[i16]) {
+        for &s in frame {
+            let v = s as f32 / i16::MAX as f32;
+            self.sum_sq += v * v;
+            self.peak = self.peak.max(v.abs());
+        }
+        self.samples += frame.len();
+    }
+
+    /// The level since the last call, or `None` if nothing was recorded.
+    pub(crate) fn take(&mut self) -> Option<Level> {
+        let meter = std::mem::take(self);
+        (meter.samples > 0).then(|| Level {
+            rms: (meter.sum_sq / meter.samples as f32).sqrt(),
+            peak: meter.peak.min(1.0),
+        })
+    }
+}
+
+/// Meters of every remote participant's audio, by identity.
+#[derive(Debug, Default)]
+pub(crate) struct Levels {
+    meters: Mutex<HashMap<String, LevelMeter>>,
+}
+
+impl Levels {
+    pub(crate) fn record(&self, identity: &str, frame: &[i16]) {
+        let mut meters = self.meters.lock().unwrap();
+        match meters.get_mut(identity) {
+            Some(meter) => meter.record(frame),
+            None => meters.entry(identity.to_owned()).or_default().record(frame),
+        }
+    }
+
+    /// Forget `identity`, e.g. when they leave.
+    pub(crate) fn remove(&self, identity: &str) {
+        self.meters.lock().unwrap().remove(identity);
+    }
+
+    fn take(&self) -> Vec<(String, Level)> {
+        let mut meters = self.meters.lock().unwrap();
+        meters
+            .iter_mut()
+            .filter_map(|(identity, meter)| Some((identity.clone(), meter.take()?)))
+            .collect()
+    }
+}
+
+/// Send `VoiceEvent::Levels` every `LEVEL_INTERVAL` for the remote streams
+/// in `levels` and, under `local`'s identity, our own mic.
+pub(crate) async fn report(
+    levels: Arc<Levels>,
+    local: Option<(String, Arc<Mutex<LevelMeter>>)>,
+    tx: mpsc::UnboundedSender<VoiceEvent>,
+) {
+    let mut tick = tokio::time::interval(LEVEL_INTERVAL);
+    tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    let mut was_silent = true;
+    loop {
+        tick.tick().await;
+        let mut all = levels.take();
+        if let Some((identity, meter)) = &local {
+            if let Some(level) = meter.lock().unwrap().take() {
+                all.push((identity.clone(), level));
+            }
+        }
+        let silent = all.iter().all(|(_, level)| level.is_silent());
+        if silent && was_silent {
+            continue;
+        }
+        was_silent = silent;
+        if tx.send(VoiceEvent::Levels(all)).is_err() {
+            return;
+        }
+    }
+}
+
+/// The mic on its own, outside any call, so its level can be checked in
+/// settings. Processed as in a call: echo cancelled, gain applied.
+pub struct MicTest {
+    capture: AudioCapture,
+    report: tokio::task::JoinHandle<()>,
+}
+
+impl MicTest {
+    /// Open input device `device` (or the default) and send its level to
+    /// `tx` every `LEVEL_INTERVAL` until dropped.
+    pub fn start(device: Option<&str>, tx: mpsc::UnboundedSender<Level>) -> Result<Self> {
+        let capture = AudioCapture::start(device)?;
+        let meter = capture.level.clone();
+        let report = tokio::spawn(async move {
+            let mut tick = tokio::time::interval(LEVEL_INTERVAL);
+            loop {
+                tick.tick().await;
+                let level = meter.lock().unwrap().take().unwrap_or_default();
+                if tx.send(level).is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(Self { capture, report })
+    }
+
+    /// Name of the device under test.
+    pub fn device_name(&self) -> String {
+        self.capture.device_name()
+    }
+
+    /// Mic gain in dB; see `audio::INPUT_GAIN_RANGE_DB`.
+    pub fn set_input_gain_db(&self, db: f32) {
+        self.capture.set_gain_db(db);
+    }
+
+    pub fn set_auto_gain(&self, enabled: bool) {
+        self.capture.set_auto_gain(enabled);
+    }
+
+    pub fn set_echo_cancellation(&self, enabled: bool) {
+        self.capture.echo.set_enabled(enabled);
+    }
+}
+
+impl Drop for MicTest {
+    fn drop(&mut self) {
+        self.report.abort();
+    }
+}
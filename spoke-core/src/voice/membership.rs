@@ -0,0 +1,123 @@
+// Voice membership — who's in a room's call, as `org.spoke.voice.member`
+// state under each user's ID.
+//
+// Message-like join/leave events left a ghost behind whenever a client
+// crashed or lost its connection before saying goodbye. A membership instead
+// carries an expiry that the joined client keeps pushing forward with a
+// heartbeat; once it passes, everyone treats the user as gone, whether or
+// not the leave ever arrived.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use matrix_sdk::{
+    Room,
+    deserialized_responses::SyncOrStrippedState,
+    ruma::{OwnedUserId, UserId, events::SyncStateEvent},
+};
+use tracing::warn;
+
+use super::events::VoiceMemberEventContent;
+
+/// How long a membership lasts without a heartbeat.
+pub const MEMBERSHIP_TTL: Duration = Duration::from_secs(5 * 60);
+/// How often the joined client renews its membership; well inside the TTL,
+/// so one lost renewal doesn't drop us.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(2 * 60);
+/// A membership this new means someone just joined, not that a heartbeat
+/// came in or an old one turned up in sync.
+const JUST_JOINED: Duration = Duration::from_secs(60);
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+impl VoiceMemberEventContent {
+    /// A fresh membership for `session_id`.
+    pub fn joined(session_id: String) -> Self {
+        let now = now_ms();
+        Self {
+            session_id: Some(session_id),
+            joined_ts: Some(now),
+            expires_ts: Some(now + MEMBERSHIP_TTL.as_millis() as u64),
+        }
+    }
+
+    /// Whether the user is still in the call: joined and not expired.
+    pub fn is_live(&self) -> bool {
+        self.session_id.is_some() && self.expires_ts.is_some_and(|ts| ts > now_ms())
+    }
+
+    /// Whether this membership started a moment ago, as opposed to being
+    /// renewed.
+    pub fn just_joined(&self) -> bool {
+        self.is_live()
+            && self
+                .joined_ts
+                .is_some_and(|ts| now_ms().saturating_sub(ts) < JUST_JOINED.as_millis() as u64)
+    }
+
+    /// The same membership, good for another `MEMBERSHIP_TTL`.
+    fn renewed(&self) -> Self {
+        Self {
+            expires_ts: Some(now_ms() + MEMBERSHIP_TTL.as_millis() as u64),
+            ..self.clone()
+        }
+    }
+}
+
+/// Everyone in `room`'s call, with when each membership lapses (ms since the
+/// Unix epoch). Expired memberships are left out.
+pub async fn members(room: &Room) -> Result<Vec<(OwnedUserId, u64)>> {
+    let mut members = Vec::new();
+    for raw in room
+        .get_state_events_static::<VoiceMemberEventContent>()
+        .await?
+    {
+        let SyncOrStrippedState::Sync(SyncStateEvent::Original(ev)) = raw.deserialize()? else {
+            continue;
+        };
+        if let (true, Some(expires_ts)) = (ev.content.is_live(), ev.content.expires_ts) {
+            members.push((ev.state_key, expires_ts));
+        }
+    }
+    Ok(members)
+}
+
+/// Join `room`'s call as `user_id`. Returns the membership for `heartbeat`.
+pub async fn join(
+    room: &Room,
+    user_id: &UserId,
+    session_id: String,
+) -> Result<VoiceMemberEventContent> {
+    let content = VoiceMemberEventContent::joined(session_id);
+    room.send_state_event_for_key(user_id, content.clone())
+        .await?;
+    Ok(content)
+}
+
+/// Keep `membership` alive until the task is aborted.
+pub async fn heartbeat(room: Room, user_id: OwnedUserId, membership: VoiceMemberEventContent) {
+    let mut ticks = tokio::time::interval(HEARTBEAT_INTERVAL);
+    // The first tick is immediate; `join` just sent it.
+    ticks.tick().await;
+    loop {
+        ticks.tick().await;
+        if let Err(e) = room
+            .send_state_event_for_key(&user_id, membership.renewed())
+            .await
+        {
+            warn!("renewing voice membership: {e}");
+        }
+    }
+}
+
+/// Leave `room`'s call as `user_id`.
+pub async fn leave(room: &Room, user_id: &UserId) -> Result<()> {
+    room.send_state_event_for_key(user_id, VoiceMemberEventContent::default())
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,128 @@
+// Mic recovery — when the capture device goes away (a USB mic unplugged, a
+// headset switched off) the published track would carry nothing for the
+// rest of the call while the user thinks they're being heard.
+//
+// `AudioCapture` notices and notifies; this watcher tells the UI, then tries
+// to open a device again — the one the user picked if it's back, else the
+// default — backing off between attempts. The published track stays as it
+// is: capture always feeds it 48 kHz mono, whatever the new device delivers.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Result;
+use livekit::{
+    Room,
+    options::{AudioEncoding, TrackPublishOptions},
+    prelude::{LocalAudioTrack, LocalTrack, TrackSource},
+    webrtc::audio_source::RtcAudioSource,
+};
+use tokio::sync::{Notify, mpsc};
+use tracing::warn;
+
+use super::{VoiceEvent, audio::AudioCapture, events::BitrateTier};
+
+/// First wait before trying to reopen a device, doubled after each failure.
+pub(crate) const RETRY_MIN: Duration = Duration::from_secs(1);
+pub(crate) const RETRY_MAX: Duration = Duration::from_secs(30);
+
+/// What to try once the mic is lost.
+#[derive(Default)]
+pub(crate) struct MicRecovery {
+    /// Input device to reopen; `None` for the default.
+    device: Mutex<Option<String>>,
+    /// Cuts the current backoff short.
+    retry_now: Notify,
+}
+
+impl MicRecovery {
+    /// Start with `device` (or the default) as the one to reopen.
+    pub(crate) fn new(device: Option<String>) -> Self {
+        Self {
+            device: Mutex::new(device),
+            retry_now: Notify::new(),
+        }
+    }
+
+    /// Try `device` (or the default) right away if the mic is lost, and on
+    /// every later loss.
+    pub(crate) fn retry(&self, device: Option<String>) {
+        self.set_device(device);
+        self.retry_now.notify_waiters();
+    }
+
+    /// Reopen `device` (or the default) on later losses.
+    pub(crate) fn set_device(&self, device: Option<String>) {
+        *self.device.lock().unwrap() = device;
+    }
+}
+
+/// Publish `source` as our microphone at `bitrate`.
+pub(crate) async fn publish(
+    room: &Room,
+    source: RtcAudioSource,
+    bitrate: BitrateTier,
+) -> Result<()> {
+    let track = LocalAudioTrack::create_audio_track("microphone", source);
+    room.local_participant()
+        .publish_track(
+            LocalTrack::Audio(track),
+            TrackPublishOptions {
+                source: TrackSource::Microphone,
+                audio_encoding: Some(AudioEncoding {
+                    max_bitrate: u64::from(bitrate.kbps()) * 1000,
+                }),
+                ..Default::default()
+            },
+        )
+        .await?;
+    Ok(())
+}
+
+/// Reopen the mic each time `capture` loses its device, for as long as the
+/// session lasts.
+pub(crate) async fn watch(
+    capture: Arc<AudioCapture>,
+    recovery: Arc<MicRecovery>,
+    tx: mpsc::UnboundedSender<VoiceEvent>,
+) {
+    loop {
+        capture.lost.notified().await;
+        let _ = tx.send(VoiceEvent::MicLost);
+
+        let mut backoff = RETRY_MIN;
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(backoff) => {}
+                _ = recovery.retry_now.notified() => {}
+            }
+            // A chosen mic that's still missing gives way to the default.
+            let device = recovery.device.lock().unwrap().clone();
+            let reopening = capture.clone();
+            let reopened = tokio::task::spawn_blocking(move || {
+                reopening
+                    .reopen(device.as_deref())
+                    .or_else(|e| match device {
+                        Some(_) => reopening.reopen(None),
+                        None => Err(e),
+                    })
+            })
+            .await
+            .unwrap_or_else(|e| Err(e.into()));
+            match reopened {
+                Ok(()) => {
+                    let _ = tx.send(VoiceEvent::MicRestored {
+                        device: capture.device_name(),
+                    });
+                    break;
+                }
+                Err(e) => {
+                    warn!("reopening mic: {e}");
+                    backoff = (backoff * 2).min(RETRY_MAX);
+                }
+            }
+        }
+    }
+}
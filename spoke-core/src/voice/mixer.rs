@@ -0,0 +1,221 @@
+// Output mixing — every remote track (and every cue or clip we play) gets
+// its own queue of mono samples at the output rate, and the output callback
+// sums whatever each queue has for the moment it's filling, then copies the
+// result to every channel the device has.
+//
+// Tracks arrive at whatever rate they were made at and are resampled on the
+// way in (see `resample`), so the callback only adds. A sum of several loud speakers can
+// go past full scale; a limiter turns the whole mix down just enough instead
+// of letting it clip, and eases back up once the peak has passed.
+//
+// Each queue is capped, so a track whose sender runs fast (or a callback
+// that stalls) loses its oldest audio rather than drifting ever further
+// behind the others.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU32, AtomicU64, Ordering},
+    },
+};
+
+use super::echo::EchoCanceller;
+use super::resample::{Resampler, resample_clip};
+
+/// Longest a track may queue, in seconds of output.
+const MAX_QUEUE_SECS: u32 = 1;
+/// Level the limiter keeps the mix under, of full scale.
+const LIMIT: f32 = 0.98;
+/// Time (seconds) for the limiter to ease back to unity gain.
+const RELEASE_SECS: f32 = 0.25;
+
+/// Mixes every queued track into the output stream.
+pub(crate) struct Mixer {
+    /// Output sample rate, Hz; changes when the output device does.
+    rate: AtomicU32,
+    queues: Mutex<Queues>,
+    next_id: AtomicU64,
+    /// Told everything that's played, so it can be cancelled out of the mic.
+    echo: Mutex<Option<Arc<EchoCanceller>>>,
+}
+
+struct Queues {
+    tracks: HashMap<u64, Queue>,
+    /// Limiter gain, ≤ 1.
+    gain: f32,
+}
+
+struct Queue {
+    samples: VecDeque<f32>,
+    /// Dropped once drained, rather than when its `TrackInput` goes.
+    one_shot: bool,
+}
+
+impl Mixer {
+    pub(crate) fn new(rate: u32) -> Arc<Self> {
+        Arc::new(Self {
+            rate: AtomicU32::new(rate),
+            queues: Mutex::new(Queues {
+                tracks: HashMap::new(),
+                gain: 1.0,
+            }),
+            next_id: AtomicU64::new(0),
+            echo: Mutex::new(None),
+        })
+    }
+
+    /// Output sample rate, Hz.
+    pub(crate) fn rate(&self) -> u32 {
+        self.rate.load(Ordering::Relaxed)
+    }
+
+    /// Switch to a new output rate. What's queued was made for the old one
+    /// and is dropped; tracks convert to the new rate from their next push.
+    pub(crate) fn set_rate(&self, rate: u32) {
+        if self.rate.swap(rate, Ordering::Relaxed) != rate {
+            self.clear();
+        }
+    }
+
+    /// A new, empty track. Its queue goes away with the returned input.
+    pub(crate) fn track(self: &Arc<Self>) -> TrackInput {
+        let id = self.insert(false);
+        TrackInput {
+            mixer: self.clone(),
+            id,
+            resampler: None,
+        }
+    }
+
+    /// Play `samples` (mono, `rate` Hz) once, on top of everything else.
+    pub(crate) fn play(&self, samples: &[f32], rate: u32) {
+        let id = self.insert(true);
+        let resampled = resample_clip(samples, rate, self.rate());
+        if let Some(queue) = self.queues.lock().unwrap().tracks.get_mut(&id) {
+            queue.samples.extend(resampled);
+        }
+    }
+
+    fn insert(&self, one_shot: bool) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let queue = Queue {
+            samples: VecDeque::new(),
+            one_shot,
+        };
+        self.queues.lock().unwrap().tracks.insert(id, queue);
+        id
+    }
+
+    /// Send what's played to `echo` as its reference from now on.
+    pub(crate) fn set_echo_reference(&self, echo: Option<Arc<EchoCanceller>>) {
+        *self.echo.lock().unwrap() = echo;
+    }
+
+    /// Throw away everything queued, e.g. when deafening.
+    pub(crate) fn clear(&self) {
+        let mut queues = self.queues.lock().unwrap();
+        queues.tracks.retain(|_, q| !q.one_shot);
+        for queue in queues.tracks.values_mut() {
+            queue.samples.clear();
+        }
+    }
+
+    /// Frames queued on the fullest track: how long until what's pushed
+    /// now is heard.
+    pub(crate) fn queued(&self) -> usize {
+        self.queues
+            .lock()
+            .unwrap()
+            .tracks
+            .values()
+            .map(|q| q.samples.len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Fill `out`, interleaved with `channels` channels, with the sum of
+    /// every track, limited to full scale. The mix is mono, so each frame
+    /// gets the same sample on every channel. Tracks with nothing queued
+    /// contribute silence.
+    pub(crate) fn mix(&self, out: &mut [f32], channels: usize) {
+        let out_rate = self.rate();
+        let release = 1.0 / (RELEASE_SECS * out_rate.max(1) as f32);
+        let echo = self.echo.lock().unwrap().clone();
+        let mut played = Vec::new();
+        let mut queues = self.queues.lock().unwrap();
+        let Queues { tracks, gain } = &mut *queues;
+        for frame in out.chunks_mut(channels.max(1)) {
+            let sum: f32 = tracks
+                .values_mut()
+                .filter_map(|q| q.samples.pop_front())
+                .sum();
+            // Clamp down at once on a peak, recover slowly.
+            if sum.abs() * *gain > LIMIT {
+                *gain = LIMIT / sum.abs();
+            } else {
+                *gain = (*gain + release).min(1.0);
+            }
+            let mixed = (sum * *gain).clamp(-1.0, 1.0);
+            frame.fill(mixed);
+            if echo.is_some() {
+                played.push(mixed);
+            }
+        }
+        tracks.retain(|_, q| !(q.one_shot && q.samples.is_empty()));
+        drop(queues);
+        if let Some(echo) = echo {
+            echo.played(&played, out_rate);
+        }
+    }
+}
+
+/// Where one remote track's audio goes into the mix.
+pub(crate) struct TrackInput {
+    mixer: Arc<Mixer>,
+    id: u64,
+    /// Made for the rates of the first push, and remade if either changes.
+    resampler: Option<Resampler>,
+}
+
+impl TrackInput {
+    /// Queue `samples` (mono, `rate` Hz).
+    pub(crate) fn push(&mut self, samples: &[f32], rate: u32) {
+        let out_rate = self.mixer.rate();
+        if self
+            .resampler
+            .as_ref()
+            .is_some_and(|r| r.from_rate() != rate || r.to_rate() != out_rate)
+        {
+            self.resampler = None;
+        }
+        let resampler = self
+            .resampler
+            .get_or_insert_with(|| Resampler::new(rate, out_rate, 1));
+        let mut resampled = Vec::with_capacity(samples.len());
+        resampler.process(samples, &mut resampled);
+        let cap = (MAX_QUEUE_SECS * out_rate) as usize;
+        if let Some(queue) = self.mixer.queues.lock().unwrap().tracks.get_mut(&self.id) {
+            queue.samples.extend(resampled);
+            let excess = queue.samples.len().saturating_sub(cap);
+            queue.samples.drain(..excess);
+        }
+    }
+
+    /// Samples still waiting to be played on this track.
+    pub(crate) fn queued(&self) -> usize {
+        self.mixer
+            .queues
+            .lock()
+            .unwrap()
+            .tracks
+            .get(&self.id)
+            .map_or(0, |q| q.samples.len())
+    }
+}
+
+impl Drop for TrackInput {
+    fn drop(&mut self) {
+        self.mixer.queues.lock().unwrap().tracks.remove(&self.id);
+    }
+}
@@ -1,23 +1,66 @@
 // Voice session layer — LiveKit Rust SDK + CPAL audio pipeline.
-// Voice join/leave is signaled via org.spoke.voice.* Matrix events.
+// Voice join/leave is signaled via org.spoke.voice.* Matrix events (see
+// `membership`), and via MatrixRTC m.call.member state for other clients
+// (see `rtc`).
 
+pub mod afk;
+mod agc;
 pub mod audio;
+pub mod data;
+pub mod devices;
+mod echo;
 pub mod events;
+mod hotplug;
+pub mod ice;
+pub mod latency;
+pub mod levels;
+pub mod membership;
+mod mic;
+mod mixer;
+mod normalize;
+mod pipeline;
+pub mod preflight;
+pub mod priority;
+pub mod recording;
+mod resample;
+pub mod ring;
+pub mod rtc;
+pub mod stage;
+mod stun;
+pub mod summary;
+mod vad;
 
-use std::sync::{Arc, atomic::Ordering};
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
 
 use anyhow::Result;
 use futures::StreamExt;
 use livekit::{
-    Room, RoomEvent, RoomOptions,
-    prelude::{LocalAudioTrack, LocalTrack, RemoteTrack, TrackSource},
-    options::TrackPublishOptions,
-    webrtc::audio_stream::native::NativeAudioStream,
+    DataPacket, Room, RoomEvent, RoomOptions,
+    prelude::RemoteTrack,
+    webrtc::{audio_stream::native::NativeAudioStream, prelude::RtcConfiguration},
 };
 use tokio::sync::mpsc;
 use tracing::warn;
 
-use audio::{AudioCapture, AudioOutput};
+use afk::SpeechStats;
+use audio::{AudioCapture, AudioOutput, Cue, InputMeter};
+use data::{DATA_TOPIC, DataMessage, RateLimiter};
+use devices::{DeviceChoice, DeviceKind};
+use events::BitrateTier;
+use latency::{Latency, LatencyReport, PROBE_LEAD, PROBE_TIMEOUT};
+use levels::{Level, Levels};
+use mic::MicRecovery;
+use normalize::Normalizer;
+use pipeline::Pipelines;
+use priority::{DEFAULT_DUCK_DB, Ducking, PriorityRule};
+use resample::LIVEKIT_RATE;
+use vad::VoiceActivity;
 
 // ── Public types ──────────────────────────────────────────────────────────────
 
@@ -26,111 +69,358 @@ use audio::{AudioCapture, AudioOutput};
 pub enum VoiceEvent {
     /// The list of remote participant display names has changed.
     ParticipantsUpdated(Vec<String>),
+    /// A remote participant joined; sent before the updated roster.
+    ParticipantJoined { identity: String },
+    /// A remote participant left; sent before the updated roster.
+    ParticipantLeft { identity: String },
+    /// Participants (including us) who are priority speakers; sent with
+    /// every roster change and whenever the rule changes.
+    PrioritySpeakers(Vec<String>),
+    /// The number of remote audio tracks being played changed.
+    Pipelines(usize),
+    /// An in-call data message arrived from a remote participant.
+    Data {
+        sender: String,
+        message: DataMessage,
+    },
+    /// A participant (us included, by our identity) started or stopped
+    /// talking.
+    SpeakingChanged { participant: String, speaking: bool },
+    /// Loudness of everyone sending audio (us included, by our identity),
+    /// about every `levels::LEVEL_INTERVAL`. Anyone left out was silent.
+    /// Not sent while everyone stays silent.
+    Levels(Vec<(String, Level)>),
+    /// A latency measurement finished, or nobody answered it.
+    Latency(Result<LatencyReport, String>),
+    /// The mic's device went away; others hear nothing until it's back.
+    /// Another device is tried with backoff, or when asked via `retry_mic`.
+    MicLost,
+    /// A mic was opened again after `MicLost`.
+    MicRestored { device: String },
+    /// The speakers' device went away; nothing plays until it's back.
+    /// The chosen device, then the default, is tried with backoff.
+    OutputLost,
+    /// Speakers were opened again after `OutputLost`.
+    OutputRestored { device: String },
+    /// The OS default device changed and a stream following it moved over.
+    DeviceSwitched { kind: DeviceKind, device: String },
     /// A non-fatal error occurred in the voice session.
     Error(String),
 }
 
+/// How to join a LiveKit room.
+#[derive(Debug, Clone)]
+pub struct ConnectOptions {
+    /// Open the mic and publish it. `false` for stage listeners.
+    pub publish: bool,
+    /// ICE servers and transport policy; see `ice::IceSettings::rtc_config`.
+    pub rtc_config: RtcConfiguration,
+    /// The room's mic bitrate, from its voice config.
+    pub bitrate: BitrateTier,
+    /// Input and output devices to open.
+    pub devices: DeviceChoice,
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        Self {
+            publish: true,
+            rtc_config: RoomOptions::default().rtc_config,
+            bitrate: BitrateTier::default(),
+            devices: DeviceChoice::default(),
+        }
+    }
+}
+
 /// An active LiveKit voice session with mic capture and speaker playback.
 pub struct VoiceSession {
     room: Arc<Room>,
-    capture: AudioCapture,
-    _output: Option<AudioOutput>,
-    /// Handles to tasks feeding remote audio into the output ring buffer.
-    _output_handles: Vec<tokio::task::JoinHandle<()>>,
+    /// `None` for subscribe-only (stage listener) sessions.
+    capture: Option<Arc<AudioCapture>>,
+    /// The published mic track, and how to get it back if its device goes.
+    mic: Arc<MicRecovery>,
+    /// Reopens the mic when its device goes away.
+    _mic_watch: Option<tokio::task::JoinHandle<()>>,
+    /// Reports when we start and stop talking.
+    _speaking_watch: Option<tokio::task::JoinHandle<()>>,
+    _output: Option<Arc<AudioOutput>>,
+    /// Reopens the speakers when their device goes away.
+    _output_watch: Option<tokio::task::JoinHandle<()>>,
+    /// Moves streams that follow the OS default onto a new one.
+    _default_watch: tokio::task::JoinHandle<()>,
+    /// Sends `VoiceEvent::Levels`.
+    _level_report: tokio::task::JoinHandle<()>,
+    /// Tasks feeding remote audio into the output mixer, by track SID.
+    pipelines: Arc<Pipelines>,
     /// Handle to the room-event dispatch task.
     _event_handle: tokio::task::JoinHandle<()>,
+    /// Caps how fast we broadcast reactions.
+    reaction_limiter: Mutex<RateLimiter>,
+    /// When set, remote audio is dropped instead of played.
+    deafened: Arc<AtomicBool>,
+    /// When set, each remote track is steered toward a common loudness.
+    normalize: Arc<AtomicBool>,
+    /// Who is a priority speaker, and whether one is talking.
+    ducking: Arc<Ducking>,
+    /// The latency measurement under way, or being answered.
+    latency: Arc<Latency>,
+    event_tx: mpsc::UnboundedSender<VoiceEvent>,
+    bitrate: BitrateTier,
 }
 
 impl VoiceSession {
     /// Connect to a LiveKit room, start mic capture, and begin receiving audio.
+    ///
+    /// With `options.publish == false` (stage listener) the mic is never
+    /// opened and no local track is published; the token only needs
+    /// subscribe rights.
     pub async fn connect(
         url: &str,
         token: &str,
+        options: ConnectOptions,
         event_tx: mpsc::UnboundedSender<VoiceEvent>,
     ) -> Result<Self> {
         // Connect to the LiveKit room.
-        let (room, mut events) =
-            Room::connect(url, token, RoomOptions::default()).await?;
+        if crate::proxy::livekit_bypasses_proxy() {
+            warn!("connecting to {url} directly: LiveKit signalling can't go through a proxy");
+        }
+        let room_options = RoomOptions {
+            rtc_config: options.rtc_config,
+            ..Default::default()
+        };
+        let (room, mut events) = Room::connect(url, token, room_options).await?;
         let room = Arc::new(room);
 
-        // Start microphone capture.
-        let capture = AudioCapture::start()?;
-
-        // Publish the local audio track.
-        let local_track = LocalAudioTrack::create_audio_track(
-            "microphone",
-            capture.rtc_source(),
-        );
-        room.local_participant()
-            .publish_track(
-                LocalTrack::Audio(local_track),
-                TrackPublishOptions {
-                    source: TrackSource::Microphone,
-                    ..Default::default()
-                },
-            )
-            .await?;
+        let mic = Arc::new(MicRecovery::new(options.devices.input.clone()));
+        let capture = if options.publish {
+            // Start microphone capture and publish the local audio track.
+            let capture = Arc::new(AudioCapture::start(options.devices.input.as_deref())?);
+            mic::publish(&room, capture.rtc_source(), options.bitrate).await?;
+            Some(capture)
+        } else {
+            None
+        };
+        let mic_watch = capture
+            .clone()
+            .map(|capture| tokio::spawn(mic::watch(capture, mic.clone(), event_tx.clone())));
 
         // Create speaker output (best-effort; log and continue if unavailable).
-        let output = match AudioOutput::new() {
-            Ok(o) => Some(o),
+        let output = match AudioOutput::new(options.devices.output.as_deref()) {
+            Ok(o) => Some(Arc::new(o)),
             Err(e) => {
                 warn!("audio output unavailable: {e}");
                 None
             }
         };
 
+        let speaking_watch = capture.as_ref().map(|capture| {
+            let mut speaking = capture.speaking.subscribe();
+            let participant = room.local_participant().identity().to_string();
+            let tx = event_tx.clone();
+            tokio::spawn(async move {
+                while speaking.changed().await.is_ok() {
+                    let speaking = *speaking.borrow_and_update();
+                    let _ = tx.send(VoiceEvent::SpeakingChanged {
+                        participant: participant.clone(),
+                        speaking,
+                    });
+                }
+            })
+        });
+
+        let output_watch = output
+            .clone()
+            .map(|output| tokio::spawn(hotplug::watch_output(output, event_tx.clone())));
+        let default_watch = tokio::spawn(hotplug::follow_defaults(
+            capture.clone(),
+            output.clone(),
+            event_tx.clone(),
+        ));
+
+        let levels = Arc::new(Levels::default());
+        let level_report = tokio::spawn(levels::report(
+            levels.clone(),
+            capture.as_ref().map(|c| {
+                (
+                    room.local_participant().identity().to_string(),
+                    c.level.clone(),
+                )
+            }),
+            event_tx.clone(),
+        ));
+
+        // Let the mic hear what the speakers are playing, to cancel it out.
+        if let (Some(capture), Some(output)) = (&capture, &output) {
+            output.mixer.set_echo_reference(Some(capture.echo.clone()));
+        }
+
         // Spawn the room-event loop.
         let room_clone = room.clone();
-        let output_buf = output.as_ref().map(|o| o.buf.clone());
-        let output_handles: Vec<tokio::task::JoinHandle<()>> = Vec::new();
+        let mixer = output.as_ref().map(|o| o.mixer.clone());
+        let deafened = Arc::new(AtomicBool::new(false));
+        let deafened_ev = deafened.clone();
+        let normalize = Arc::new(AtomicBool::new(true));
+        let normalize_ev = normalize.clone();
+        let ducking = Arc::new(Ducking::new(DEFAULT_DUCK_DB));
+        let ducking_ev = ducking.clone();
+        let pipelines = Arc::new(Pipelines::default());
+        let latency = Arc::new(Latency::new(
+            capture
+                .as_ref()
+                .map(|c| (c.marker.clone(), c.delay.clone())),
+            output.as_deref().map(AudioOutput::playback),
+        ));
+        let latency_ev = latency.clone();
 
         let event_handle = {
-            // We collect output track handles as events arrive; store them in a
-            // Vec inside the task (the outer vec is for the struct only).
+            let pipelines = pipelines.clone();
             let tx = event_tx.clone();
             let room_ev = room_clone.clone();
             tokio::spawn(async move {
+                // Per-sender reaction limiters, so one spammy client can't
+                // flood everyone's overlay.
+                let mut reaction_limits: HashMap<String, RateLimiter> = HashMap::new();
+                // Roster change: chime (unless deafened), the change itself,
+                // then the full roster.
+                let announce = |change: VoiceEvent, cue: Cue| {
+                    if let Some(mixer) = mixer
+                        .as_ref()
+                        .filter(|_| !deafened_ev.load(Ordering::Relaxed))
+                    {
+                        audio::play_cue(mixer, cue);
+                    }
+                    let _ = tx.send(change);
+                    let names: Vec<String> = room_ev
+                        .remote_participants()
+                        .values()
+                        .map(|p| p.name().to_owned())
+                        .collect();
+                    let _ = tx.send(VoiceEvent::ParticipantsUpdated(names));
+                    let _ = tx.send(VoiceEvent::PrioritySpeakers(priority_speakers(
+                        &room_ev,
+                        &ducking_ev,
+                    )));
+                };
                 while let Some(event) = events.recv().await {
                     match event {
-                        RoomEvent::TrackSubscribed { track, .. } => {
+                        RoomEvent::TrackSubscribed {
+                            track,
+                            publication,
+                            participant,
+                        } => {
                             if let RemoteTrack::Audio(audio_track) = track {
-                                let buf = output_buf.clone();
+                                // Dropped with the task, taking the track out of the mix.
+                                let mut input = mixer.as_ref().map(|m| m.track());
+                                let deafened = deafened_ev.clone();
+                                let ducking = ducking_ev.clone();
+                                let normalize = normalize_ev.clone();
+                                let probe = latency_ev.clone();
+                                let levels = levels.clone();
+                                let tx = tx.clone();
+                                let identity = participant.identity().to_string();
                                 let handle = tokio::spawn(async move {
                                     let rtc = audio_track.rtc_track();
                                     // Request 48 kHz mono from LiveKit's jitter buffer.
                                     let mut stream =
-                                        NativeAudioStream::new(rtc, 48_000, 1);
+                                        NativeAudioStream::new(rtc, LIVEKIT_RATE as i32, 1);
+                                    let mut normalizer = Normalizer::new();
+                                    let mut vad = VoiceActivity::new();
                                     while let Some(frame) = stream.next().await {
-                                        if let Some(ref b) = buf {
-                                            let mut guard = b.lock().unwrap();
-                                            for &s in frame.data.iter() {
-                                                guard.push_back(
-                                                    s as f32 / i16::MAX as f32,
-                                                );
-                                            }
-                                            // Cap buffer to ~2 seconds.
-                                            while guard.len() > 192_000 {
-                                                guard.pop_front();
+                                        if let Some(speaking) =
+                                            vad.process(&frame.data, frame.sample_rate)
+                                        {
+                                            let participant = identity.clone();
+                                            let _ = tx.send(VoiceEvent::SpeakingChanged {
+                                                participant,
+                                                speaking,
+                                            });
+                                        }
+                                        levels.record(&identity, &frame.data);
+                                        if probe.listening_to(&identity)
+                                            && latency::is_marker(&frame.data, frame.sample_rate)
+                                        {
+                                            if let Some(report) = probe.heard(&identity) {
+                                                let _ = tx.send(VoiceEvent::Latency(Ok(report)));
                                             }
                                         }
+                                        if deafened.load(Ordering::Relaxed) {
+                                            continue;
+                                        }
+                                        let mut gain = ducking.frame_gain(&identity, &frame.data);
+                                        if normalize.load(Ordering::Relaxed) {
+                                            gain *=
+                                                normalizer.process(&frame.data, frame.sample_rate);
+                                        }
+                                        if let Some(input) = input.as_mut() {
+                                            let samples: Vec<f32> = frame
+                                                .data
+                                                .iter()
+                                                .map(|&s| gain * s as f32 / i16::MAX as f32)
+                                                .collect();
+                                            input.push(&samples, frame.sample_rate);
+                                        }
                                     }
                                 });
-                                // Note: we can't mutate output_handles from inside
-                                // the spawned task, so just detach; the task ends
-                                // when the audio stream closes.
-                                drop(handle); // detach — task runs to completion
+                                let identity = participant.identity().to_string();
+                                pipelines.insert(publication.sid().to_string(), identity, handle);
+                                let _ = tx.send(VoiceEvent::Pipelines(pipelines.active()));
                             }
                         }
 
-                        RoomEvent::ParticipantConnected(_)
-                        | RoomEvent::ParticipantDisconnected(_) => {
-                            let names: Vec<String> = room_ev
-                                .remote_participants()
-                                .values()
-                                .map(|p| p.name().to_owned())
-                                .collect();
-                            let _ = tx.send(VoiceEvent::ParticipantsUpdated(names));
+                        RoomEvent::TrackUnsubscribed { publication, .. } => {
+                            if pipelines.remove_track(&publication.sid().to_string()) {
+                                let _ = tx.send(VoiceEvent::Pipelines(pipelines.active()));
+                            }
+                        }
+
+                        RoomEvent::DataReceived {
+                            payload,
+                            topic,
+                            participant,
+                            ..
+                        } => {
+                            if topic.as_deref() != Some(DATA_TOPIC) {
+                                continue;
+                            }
+                            let Some(message) =
+                                DataMessage::decode(&payload).filter(DataMessage::within_limits)
+                            else {
+                                continue;
+                            };
+                            let sender = participant
+                                .map(|p| p.identity().to_string())
+                                .unwrap_or_default();
+                            if let DataMessage::LatencyProbe { responder } = &message {
+                                if *responder == room_ev.local_participant().identity().to_string()
+                                {
+                                    latency_ev.reflect_for(&sender);
+                                }
+                                continue;
+                            }
+                            if matches!(message, DataMessage::Reaction { .. })
+                                && !reaction_limits
+                                    .entry(sender.clone())
+                                    .or_insert_with(RateLimiter::reactions)
+                                    .allow()
+                            {
+                                continue;
+                            }
+                            let _ = tx.send(VoiceEvent::Data { sender, message });
+                        }
+
+                        RoomEvent::ParticipantConnected(p) => {
+                            let identity = p.identity().to_string();
+                            announce(VoiceEvent::ParticipantJoined { identity }, Cue::Join);
+                        }
+
+                        RoomEvent::ParticipantDisconnected(p) => {
+                            let identity = p.identity().to_string();
+                            levels.remove(&identity);
+                            reaction_limits.remove(&identity);
+                            if pipelines.remove_participant(&identity) > 0 {
+                                let _ = tx.send(VoiceEvent::Pipelines(pipelines.active()));
+                            }
+                            announce(VoiceEvent::ParticipantLeft { identity }, Cue::Leave);
                         }
 
                         _ => {}
@@ -142,27 +432,265 @@ impl VoiceSession {
         Ok(Self {
             room,
             capture,
+            mic,
+            _mic_watch: mic_watch,
+            _speaking_watch: speaking_watch,
             _output: output,
-            _output_handles: output_handles,
+            _output_watch: output_watch,
+            _default_watch: default_watch,
+            _level_report: level_report,
+            pipelines,
             _event_handle: event_handle,
+            reaction_limiter: Mutex::new(RateLimiter::reactions()),
+            deafened,
+            normalize,
+            ducking,
+            latency,
+            event_tx,
+            bitrate: options.bitrate,
         })
     }
 
     /// Disconnect from the LiveKit room and release audio resources.
     pub async fn disconnect(&self) {
         self._event_handle.abort();
+        if let Some(watch) = &self._mic_watch {
+            watch.abort();
+        }
+        if let Some(watch) = &self._speaking_watch {
+            watch.abort();
+        }
+        if let Some(watch) = &self._output_watch {
+            watch.abort();
+        }
+        self._default_watch.abort();
+        self._level_report.abort();
+        self.pipelines.clear();
         if let Err(e) = self.room.close().await {
             warn!("room close: {e}");
         }
     }
 
+    /// Broadcast an in-call data message to every participant.
+    ///
+    /// Reactions beyond `data::REACTION_BURST` per window are rejected.
+    pub async fn send_data(&self, message: &DataMessage) -> Result<()> {
+        if matches!(message, DataMessage::Reaction { .. })
+            && !self.reaction_limiter.lock().unwrap().allow()
+        {
+            anyhow::bail!("reaction rate limit exceeded");
+        }
+        self.room
+            .local_participant()
+            .publish_data(DataPacket {
+                payload: message.encode(),
+                topic: Some(DATA_TOPIC.to_owned()),
+                reliable: message.reliable(),
+                ..Default::default()
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Measure latency to `peer` (an identity), or to someone whose audio
+    /// we're playing if `None`. Returns who was asked; the result arrives
+    /// later as `VoiceEvent::Latency`. Needs a microphone to send the
+    /// marker on.
+    pub async fn measure_latency(&self, peer: Option<&str>) -> Result<String> {
+        if !self.latency.can_probe() {
+            anyhow::bail!("measuring latency needs a microphone");
+        }
+        let peer = match peer {
+            Some(peer) => peer.to_owned(),
+            None => self
+                .pipelines
+                .identities()
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("nobody in the call is sending audio"))?,
+        };
+        let started = self.latency.start(&peer);
+        if let Err(e) = self
+            .send_data(&DataMessage::LatencyProbe {
+                responder: peer.clone(),
+            })
+            .await
+        {
+            self.latency.expire(started);
+            return Err(e);
+        }
+
+        let latency = self.latency.clone();
+        let tx = self.event_tx.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(PROBE_LEAD).await;
+            latency.send_marker(started);
+            tokio::time::sleep(PROBE_TIMEOUT).await;
+            if let Some(peer) = latency.expire(started) {
+                let _ = tx.send(VoiceEvent::Latency(Err(format!(
+                    "{peer} didn't send the marker back"
+                ))));
+            }
+        });
+        Ok(peer)
+    }
+
+    /// After `VoiceEvent::MicLost`, try to reopen the mic now with `device`
+    /// (an input device name), or the default device if `None`. Later
+    /// losses try the same device.
+    pub fn retry_mic(&self, device: Option<String>) {
+        self.mic.retry(device);
+    }
+
+    /// Name of the input device being captured, or `None` for listeners.
+    pub fn mic_device(&self) -> Option<String> {
+        self.capture.as_ref().map(|c| c.device_name())
+    }
+
+    /// Switch the mic to `device` (an input device name), or the default
+    /// device if `None`, without touching the published track. If it can't
+    /// be opened the current mic stays. No-op for listeners.
+    pub fn set_input_device(&self, device: Option<String>) -> Result<()> {
+        let Some(capture) = &self.capture else {
+            return Ok(());
+        };
+        capture.reopen(device.as_deref())?;
+        self.mic.set_device(device);
+        Ok(())
+    }
+
+    /// Name of the output device playing the call, or `None` if there's none.
+    pub fn output_device(&self) -> Option<String> {
+        self._output.as_deref().map(AudioOutput::device_name)
+    }
+
+    /// Switch playback to `device` (an output device name), or the default
+    /// device if `None`. If it can't be opened the current one stays.
+    pub fn set_output_device(&self, device: Option<String>) -> Result<()> {
+        match &self._output {
+            Some(output) => output.reopen(device.as_deref()),
+            None => anyhow::bail!("no audio output"),
+        }
+    }
+
     /// Mute or unmute the local microphone.
     /// When muted, silence frames are fed to LiveKit instead of real audio.
     pub fn set_muted(&self, muted: bool) {
-        self.capture.muted.store(muted, Ordering::Relaxed);
+        if let Some(capture) = &self.capture {
+            capture.muted.store(muted, Ordering::Relaxed);
+        }
+    }
+
+    /// Mic gain in dB; see `audio::INPUT_GAIN_RANGE_DB`. No-op for listeners.
+    pub fn set_input_gain_db(&self, db: f32) {
+        if let Some(capture) = &self.capture {
+            capture.set_gain_db(db);
+        }
+    }
+
+    /// Turn automatic gain control on the mic on or off (on by default).
+    /// No-op for listeners.
+    pub fn set_auto_gain(&self, enabled: bool) {
+        if let Some(capture) = &self.capture {
+            capture.set_auto_gain(enabled);
+        }
+    }
+
+    /// Mic level after gain, or `None` for listener sessions.
+    pub fn input_meter(&self) -> Option<Arc<InputMeter>> {
+        self.capture.as_ref().map(|c| c.meter.clone())
+    }
+
+    /// Voice activity on our mic, or `None` for listener sessions.
+    pub fn speech_stats(&self) -> Option<Arc<SpeechStats>> {
+        self.capture.as_ref().map(|c| c.speech.clone())
     }
 
+    /// Listener sessions have no mic and always report muted.
     pub fn is_muted(&self) -> bool {
-        self.capture.muted.load(Ordering::Relaxed)
+        self.capture
+            .as_ref()
+            .is_none_or(|c| c.muted.load(Ordering::Relaxed))
     }
+
+    /// Stop (or resume) playing remote audio. Buffered audio is discarded so
+    /// undeafening doesn't replay stale speech.
+    pub fn set_deafened(&self, deafened: bool) {
+        self.deafened.store(deafened, Ordering::Relaxed);
+        if deafened {
+            if let Some(output) = &self._output {
+                output.mixer.clear();
+            }
+        }
+    }
+
+    pub fn is_deafened(&self) -> bool {
+        self.deafened.load(Ordering::Relaxed)
+    }
+
+    /// Turn loudness normalization of remote tracks on or off (on by default).
+    pub fn set_normalize(&self, normalize: bool) {
+        self.normalize.store(normalize, Ordering::Relaxed);
+    }
+
+    /// Turn echo cancellation on the mic on or off (on by default). No-op
+    /// for listeners.
+    pub fn set_echo_cancellation(&self, enabled: bool) {
+        if let Some(capture) = &self.capture {
+            capture.echo.set_enabled(enabled);
+        }
+    }
+
+    /// Remote audio tracks currently being played.
+    pub fn active_pipelines(&self) -> usize {
+        self.pipelines.active()
+    }
+
+    /// Display names of the remote participants currently in the room.
+    pub fn participants(&self) -> Vec<String> {
+        self.room
+            .remote_participants()
+            .values()
+            .map(|p| p.name().to_owned())
+            .collect()
+    }
+
+    /// Replace the rule deciding who is a priority speaker.
+    pub fn set_priority_rule(&self, rule: PriorityRule) {
+        self.ducking.set_rule(rule);
+        let _ = self
+            .event_tx
+            .send(VoiceEvent::PrioritySpeakers(priority_speakers(
+                &self.room,
+                &self.ducking,
+            )));
+    }
+
+    /// How much to turn others down while a priority speaker talks (dB, ≤ 0).
+    pub fn set_priority_duck_db(&self, duck_db: f32) {
+        self.ducking.set_duck_db(duck_db);
+    }
+
+    /// Whether this session publishes a microphone track.
+    pub fn is_publishing(&self) -> bool {
+        self.capture.is_some()
+    }
+
+    /// The bitrate the mic is published at.
+    pub fn bitrate(&self) -> BitrateTier {
+        self.bitrate
+    }
+}
+
+/// Everyone in `room`, us included, whom `ducking` treats as a priority speaker.
+fn priority_speakers(room: &Room, ducking: &Ducking) -> Vec<String> {
+    let local = room.local_participant().identity().to_string();
+    std::iter::once(local)
+        .chain(
+            room.remote_participants()
+                .values()
+                .map(|p| p.identity().to_string()),
+        )
+        .filter(|identity| ducking.is_priority(identity))
+        .collect()
 }
@@ -0,0 +1,50 @@
+// Loudness normalization — each remote track's level is tracked as a slow
+// moving average, and its gain eased toward whatever brings that level to a
+// common target, so quiet and loud participants end up comparable.
+
+/// Level every track is steered toward (RMS of full scale, about -20 dBFS).
+const TARGET_RMS: f32 = 0.1;
+/// Limits on the correction, so near-silent tracks aren't blown up.
+const MIN_GAIN: f32 = 0.25;
+const MAX_GAIN: f32 = 4.0;
+/// Frames quieter than this are pauses and don't move the estimate.
+const SILENCE_RMS: f32 = 0.01;
+/// Time constants (seconds) of the level estimate and of the gain itself.
+const LEVEL_TAU: f32 = 3.0;
+const GAIN_TAU: f32 = 2.0;
+
+/// Per-track loudness state.
+#[derive(Debug, Clone)]
+pub(crate) struct Normalizer {
+    mean_square: f32,
+    gain: f32,
+}
+
+impl Normalizer {
+    pub(crate) fn new() -> Self {
+        Self {
+            mean_square: TARGET_RMS * TARGET_RMS,
+            gain: 1.0,
+        }
+    }
+
+    /// Update the estimate with `frame` (mono, `sample_rate` Hz) and return
+    /// the gain to apply to it.
+    pub(crate) fn process(&mut self, frame: &[i16], sample_rate: u32) -> f32 {
+        if frame.is_empty() || sample_rate == 0 {
+            return self.gain;
+        }
+        let secs = frame.len() as f32 / sample_rate as f32;
+        let mean_square = frame
+            .iter()
+            .map(|&s| (s as f32 / i16::MAX as f32).powi(2))
+            .sum::<f32>()
+            / frame.len() as f32;
+        if mean_square.sqrt() > SILENCE_RMS {
+            self.mean_square += (mean_square - self.mean_square) * (secs / LEVEL_TAU).min(1.0);
+            let wanted = (TARGET_RMS / self.mean_square.sqrt()).clamp(MIN_GAIN, MAX_GAIN);
+            self.gain += (wanted - self.gain) * (secs / GAIN_TAU).min(1.0);
+        }
+        self.gain
+    }
+}
@@ -0,0 +1,81 @@
+// Remote audio pipelines — one playback task per subscribed audio track,
+// keyed by track SID so they can be torn down when the track goes away
+// instead of lingering and feeding stale audio into the output.
+
+use std::{collections::HashMap, sync::Mutex};
+
+use tokio::task::JoinHandle;
+
+struct Pipeline {
+    identity: String,
+    task: JoinHandle<()>,
+}
+
+/// Playback tasks for the session's remote tracks.
+#[derive(Default)]
+pub(crate) struct Pipelines {
+    tracks: Mutex<HashMap<String, Pipeline>>,
+}
+
+impl Pipelines {
+    /// Start tracking `task` for track `sid`, replacing (and stopping) any
+    /// pipeline already registered for it.
+    pub(crate) fn insert(&self, sid: String, identity: String, task: JoinHandle<()>) {
+        if let Some(old) = self
+            .tracks
+            .lock()
+            .unwrap()
+            .insert(sid, Pipeline { identity, task })
+        {
+            old.task.abort();
+        }
+    }
+
+    /// Stop the pipeline for track `sid`. Returns whether there was one.
+    pub(crate) fn remove_track(&self, sid: &str) -> bool {
+        let removed = self.tracks.lock().unwrap().remove(sid);
+        removed.map(|p| p.task.abort()).is_some()
+    }
+
+    /// Stop every pipeline playing `identity`'s tracks. Returns how many.
+    pub(crate) fn remove_participant(&self, identity: &str) -> usize {
+        let mut tracks = self.tracks.lock().unwrap();
+        let before = tracks.len();
+        tracks.retain(|_, p| {
+            let keep = p.identity != identity;
+            if !keep {
+                p.task.abort();
+            }
+            keep
+        });
+        before - tracks.len()
+    }
+
+    /// Pipelines still running; ones whose stream ended are dropped.
+    pub(crate) fn active(&self) -> usize {
+        let mut tracks = self.tracks.lock().unwrap();
+        tracks.retain(|_, p| !p.task.is_finished());
+        tracks.len()
+    }
+
+    /// Participants with at least one running pipeline.
+    pub(crate) fn identities(&self) -> Vec<String> {
+        let mut identities: Vec<String> = self
+            .tracks
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|p| !p.task.is_finished())
+            .map(|p| p.identity.clone())
+            .collect();
+        identities.sort();
+        identities.dedup();
+        identities
+    }
+
+    pub(crate) fn clear(&self) {
+        for (_, p) in self.tracks.lock().unwrap().drain() {
+            p.task.abort();
+        }
+    }
+}
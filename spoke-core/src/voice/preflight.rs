@@ -0,0 +1,368 @@
+// Voice preflight — quick connectivity probes run before joining LiveKit so a
+// failed join can say *why* ("UDP blocked") instead of a generic error.
+//
+// Probes (run concurrently, each bounded by a short timeout):
+//   signal    HTTP GET {livekit}/rtc/validate — server reachable, token accepted
+//   udp       STUN Binding round trip — outbound UDP works
+//   turn_udp  TURN Allocate over UDP with the sidecar's credentials
+//   turn_tcp  TURN Allocate over TCP — the fallback when UDP is blocked
+//
+// Only servers the sidecar handed out or the user configured are contacted;
+// with neither, the UDP probe is skipped rather than asking a public STUN
+// server, which would learn the user's address. `turns:` servers speak TLS,
+// which these probes don't, so they're skipped too.
+
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpStream, UdpSocket},
+    time::timeout,
+};
+
+use super::{ice, stun};
+use crate::proxy;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A TURN server as returned by the sidecar token endpoint.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TurnServer {
+    pub urls: String,
+    pub username: String,
+    pub credential: String,
+}
+
+impl TurnServer {
+    /// `host:port` from a `turn:host:port[?transport=…]` URL. The port
+    /// defaults to 3478, or 5349 for `turns:`.
+    pub fn address(&self) -> Option<String> {
+        let (rest, port) = match self.urls.strip_prefix("turns:") {
+            Some(rest) => (rest, 5349),
+            None => (self.urls.strip_prefix("turn:")?, 3478),
+        };
+        let host_port = rest.split('?').next()?;
+        Some(if host_port.contains(':') {
+            host_port.to_owned()
+        } else {
+            format!("{host_port}:{port}")
+        })
+    }
+
+    /// A `turns:` server, reached over TLS.
+    pub fn tls(&self) -> bool {
+        self.urls.starts_with("turns:")
+    }
+}
+
+/// Outcome of a single probe.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Probe {
+    Passed {
+        rtt_ms: u64,
+    },
+    Failed(String),
+    /// Not applicable (e.g. no TURN server configured).
+    Skipped,
+}
+
+impl Probe {
+    pub fn passed(&self) -> bool {
+        matches!(self, Probe::Passed { .. })
+    }
+
+    pub fn failed(&self) -> bool {
+        matches!(self, Probe::Failed(_))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PreflightReport {
+    pub signal: Probe,
+    pub udp: Probe,
+    pub turn_udp: Probe,
+    pub turn_tcp: Probe,
+}
+
+impl PreflightReport {
+    /// Joining is pointless if the signal server can't be reached.
+    pub fn can_connect(&self) -> bool {
+        !self.signal.failed()
+    }
+
+    /// UDP is blocked but a TCP relay works — force ICE through the relay.
+    pub fn force_relay(&self) -> bool {
+        self.udp.failed() && self.turn_tcp.passed()
+    }
+
+    /// Human-readable advice for every problem found. Empty if all is well.
+    pub fn guidance(&self) -> Vec<String> {
+        let mut out = Vec::new();
+        if let Probe::Failed(e) = &self.signal {
+            out.push(format!(
+                "Can't reach the voice server ({e}). Check your connection, or ask \
+                 the server admin whether LiveKit is running."
+            ));
+        }
+        match (&self.udp, &self.turn_tcp) {
+            (Probe::Failed(_), Probe::Passed { .. }) => {
+                out.push("UDP blocked — forcing TCP relay. Expect slightly higher latency.".into());
+            }
+            (Probe::Failed(_), Probe::Failed(_)) => {
+                out.push(
+                    "UDP is blocked and the TURN relay is unreachable over TCP. Voice will \
+                     likely fail on this network (corporate firewall or VPN?)."
+                        .into(),
+                );
+            }
+            (Probe::Failed(_), Probe::Skipped) => {
+                out.push(
+                    "UDP is blocked and the server has no TURN relay configured. Voice will \
+                     likely fail on this network."
+                        .into(),
+                );
+            }
+            _ => {}
+        }
+        if let Probe::Failed(e) = &self.turn_udp {
+            if e.contains("401") {
+                out.push(
+                    "The TURN server rejected our credentials — the sidecar's TURN_SECRET \
+                     may not match the TURN server."
+                        .into(),
+                );
+            }
+        }
+        out
+    }
+}
+
+/// Run all probes concurrently. The UDP probe goes to the first plain TURN
+/// server, else the first of the user's plain `stun:` servers.
+pub async fn run(
+    livekit_url: &str,
+    token: &str,
+    turn_servers: &[TurnServer],
+    stun_servers: &[String],
+) -> PreflightReport {
+    let turn = turn_servers.iter().find(|t| !t.tls());
+    let stun_addr = turn.and_then(TurnServer::address).or_else(|| {
+        stun_servers
+            .iter()
+            .filter(|url| url.starts_with("stun:"))
+            .find_map(|url| ice::stun_address(url))
+    });
+
+    let (signal, udp, turn_udp, turn_tcp) = tokio::join!(
+        probe_signal(livekit_url, token),
+        async {
+            match &stun_addr {
+                Some(addr) => probe_udp(addr).await,
+                None => Probe::Skipped,
+            }
+        },
+        async {
+            match turn {
+                Some(t) => probe_turn(t, false).await,
+                None => Probe::Skipped,
+            }
+        },
+        async {
+            match turn {
+                Some(t) => probe_turn(t, true).await,
+                None => Probe::Skipped,
+            }
+        },
+    );
+    PreflightReport {
+        signal,
+        udp,
+        turn_udp,
+        turn_tcp,
+    }
+}
+
+// ── Probes ────────────────────────────────────────────────────────────────────
+
+async fn probe_signal(livekit_url: &str, token: &str) -> Probe {
+    let http_url = livekit_url
+        .replacen("wss://", "https://", 1)
+        .replacen("ws://", "http://", 1);
+    let url = format!("{}/rtc/validate", http_url.trim_end_matches('/'));
+
+    let start = Instant::now();
+    let client = proxy::client_builder().timeout(PROBE_TIMEOUT).build();
+    let Ok(client) = client else {
+        return Probe::Failed("http client".into());
+    };
+    match client
+        .get(&url)
+        .query(&[("access_token", token)])
+        .send()
+        .await
+    {
+        Ok(r) if r.status().is_success() => Probe::Passed {
+            rtt_ms: elapsed_ms(start),
+        },
+        Ok(r) if r.status() == reqwest::StatusCode::UNAUTHORIZED => {
+            Probe::Failed("token rejected by LiveKit — sidecar key/secret mismatch?".into())
+        }
+        Ok(r) => Probe::Failed(format!("HTTP {}", r.status())),
+        Err(e) if e.is_timeout() => Probe::Failed("timed out".into()),
+        Err(e) if e.is_connect() => Probe::Failed("connection refused or unreachable".into()),
+        Err(e) => Probe::Failed(e.to_string()),
+    }
+}
+
+/// STUN Binding round trip to `addr` (`host:port`).
+pub async fn probe_udp(addr: &str) -> Probe {
+    let start = Instant::now();
+    match timeout(PROBE_TIMEOUT, stun_binding(addr)).await {
+        Ok(Ok(())) => Probe::Passed {
+            rtt_ms: elapsed_ms(start),
+        },
+        Ok(Err(e)) => Probe::Failed(e),
+        Err(_) => Probe::Failed(format!("no STUN response from {addr}")),
+    }
+}
+
+/// TURN allocation with the server's credentials, over TCP or UDP. Skipped
+/// for `turns:` servers.
+pub async fn probe_turn(server: &TurnServer, tcp: bool) -> Probe {
+    if server.tls() {
+        return Probe::Skipped;
+    }
+    let Some(addr) = server.address() else {
+        return Probe::Failed(format!("unparseable TURN URL {}", server.urls));
+    };
+    let start = Instant::now();
+    match timeout(PROBE_TIMEOUT, turn_allocate(&addr, server, tcp)).await {
+        Ok(Ok(())) => Probe::Passed {
+            rtt_ms: elapsed_ms(start),
+        },
+        Ok(Err(e)) => Probe::Failed(e),
+        Err(_) => Probe::Failed("timed out".into()),
+    }
+}
+
+fn elapsed_ms(start: Instant) -> u64 {
+    start.elapsed().as_millis() as u64
+}
+
+// ── STUN transport ────────────────────────────────────────────────────────────
+
+/// One request/response exchange over UDP or TCP.
+enum Transport {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+}
+
+impl Transport {
+    async fn connect(addr: &str, tcp: bool) -> Result<Self, String> {
+        if tcp {
+            let stream = TcpStream::connect(addr)
+                .await
+                .map_err(|e| format!("tcp {addr}: {e}"))?;
+            Ok(Transport::Tcp(stream))
+        } else {
+            let sock = UdpSocket::bind("0.0.0.0:0")
+                .await
+                .map_err(|e| e.to_string())?;
+            sock.connect(addr)
+                .await
+                .map_err(|e| format!("udp {addr}: {e}"))?;
+            Ok(Transport::Udp(sock))
+        }
+    }
+
+    async fn exchange(&mut self, msg: &[u8], txid: [u8; 12]) -> Result<stun::Response, String> {
+        match self {
+            Transport::Udp(sock) => {
+                // UDP may drop packets: retransmit until the outer timeout.
+                let mut buf = [0u8; 1500];
+                loop {
+                    sock.send(msg).await.map_err(|e| e.to_string())?;
+                    if let Ok(Ok(n)) =
+                        timeout(Duration::from_millis(500), sock.recv(&mut buf)).await
+                    {
+                        if let Some(resp) = stun::parse(&buf[..n]).filter(|r| r.txid == txid) {
+                            return Ok(resp);
+                        }
+                    }
+                }
+            }
+            Transport::Tcp(stream) => {
+                stream.write_all(msg).await.map_err(|e| e.to_string())?;
+                let mut header = [0u8; stun::header_len()];
+                stream
+                    .read_exact(&mut header)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                let mut buf = vec![0u8; stun::message_len(&header)];
+                buf[..header.len()].copy_from_slice(&header);
+                stream
+                    .read_exact(&mut buf[header.len()..])
+                    .await
+                    .map_err(|e| e.to_string())?;
+                stun::parse(&buf)
+                    .filter(|r| r.txid == txid)
+                    .ok_or_else(|| "malformed STUN response".into())
+            }
+        }
+    }
+}
+
+async fn stun_binding(addr: &str) -> Result<(), String> {
+    let mut transport = Transport::connect(addr, false).await?;
+    let req = stun::Request::new(stun::BINDING_REQUEST);
+    let txid = req.txid();
+    let resp = transport.exchange(&req.encode(None), txid).await?;
+    if resp.msg_type == stun::BINDING_SUCCESS {
+        Ok(())
+    } else {
+        Err(format!("unexpected STUN response 0x{:04x}", resp.msg_type))
+    }
+}
+
+/// Allocate a relay with long-term credentials, then release it immediately.
+async fn turn_allocate(addr: &str, server: &TurnServer, tcp: bool) -> Result<(), String> {
+    let mut transport = Transport::connect(addr, tcp).await?;
+
+    // 1. Unauthenticated Allocate → 401 with REALM and NONCE.
+    let req = stun::Request::new(stun::ALLOCATE_REQUEST).requested_transport_udp();
+    let txid = req.txid();
+    let challenge = transport.exchange(&req.encode(None), txid).await?;
+    let (Some(realm), Some(nonce)) = (challenge.realm, challenge.nonce) else {
+        return Err("TURN server sent no auth challenge".into());
+    };
+
+    // 2. Authenticated Allocate.
+    let creds = stun::Credentials {
+        username: &server.username,
+        password: &server.credential,
+        realm: &realm,
+        nonce: &nonce,
+    };
+    let req = stun::Request::new(stun::ALLOCATE_REQUEST).requested_transport_udp();
+    let txid = req.txid();
+    let resp = transport.exchange(&req.encode(Some(&creds)), txid).await?;
+    match resp.msg_type {
+        stun::ALLOCATE_SUCCESS => {}
+        stun::ALLOCATE_ERROR => {
+            let (code, reason) = resp.error_code.unwrap_or((0, String::new()));
+            return Err(format!("allocation refused: {code} {reason}"));
+        }
+        other => return Err(format!("unexpected TURN response 0x{other:04x}")),
+    }
+
+    // 3. Release the allocation (lifetime 0). Best-effort.
+    let req = stun::Request::new(stun::REFRESH_REQUEST).lifetime(0);
+    let txid = req.txid();
+    let _ = timeout(
+        Duration::from_millis(500),
+        transport.exchange(&req.encode(Some(&creds)), txid),
+    )
+    .await;
+    Ok(())
+}
@@ -0,0 +1,113 @@
+// Priority speakers — while one of them talks, everyone else's audio is
+// ducked by a configurable amount so announcements cut through a busy call.
+//
+// Who counts as a priority speaker comes from the room: users whose power
+// level reaches the `priority_speaker` threshold in `org.spoke.voice.config`.
+// The bridge keeps the rule current; the session applies it per frame.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        Mutex,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// Default attenuation of other speakers while a priority speaker talks.
+pub const DEFAULT_DUCK_DB: f32 = -15.0;
+
+/// RMS (of full scale) above which a priority speaker's frame counts as speech.
+const SPEECH_RMS: f32 = 0.02;
+/// Keep ducking this long after the last speech frame, so pauses between
+/// words don't pump the volume.
+const DUCK_HOLD: Duration = Duration::from_millis(400);
+
+/// Power levels deciding who is a priority speaker.
+#[derive(Debug, Clone, Default)]
+pub struct PriorityRule {
+    /// Explicit levels by user ID (LiveKit identity).
+    pub users: HashMap<String, i64>,
+    pub users_default: i64,
+    /// Level needed to be a priority speaker; `None` disables the feature.
+    pub threshold: Option<i64>,
+}
+
+impl PriorityRule {
+    pub fn is_priority(&self, identity: &str) -> bool {
+        let level = self
+            .users
+            .get(identity)
+            .copied()
+            .unwrap_or(self.users_default);
+        self.threshold.is_some_and(|t| level >= t)
+    }
+}
+
+/// Shared between a session's playback tasks.
+pub(crate) struct Ducking {
+    rule: Mutex<PriorityRule>,
+    last_priority_speech: Mutex<Option<Instant>>,
+    /// Linear gain applied while ducking, as `f32` bits.
+    gain: AtomicU32,
+}
+
+impl Ducking {
+    pub(crate) fn new(duck_db: f32) -> Self {
+        Self {
+            rule: Mutex::default(),
+            last_priority_speech: Mutex::new(None),
+            gain: AtomicU32::new(db_to_gain(duck_db).to_bits()),
+        }
+    }
+
+    pub(crate) fn set_rule(&self, rule: PriorityRule) {
+        *self.rule.lock().unwrap() = rule;
+    }
+
+    pub(crate) fn set_duck_db(&self, duck_db: f32) {
+        self.gain
+            .store(db_to_gain(duck_db).to_bits(), Ordering::Relaxed);
+    }
+
+    pub(crate) fn is_priority(&self, identity: &str) -> bool {
+        self.rule.lock().unwrap().is_priority(identity)
+    }
+
+    /// Gain for a frame of `identity`'s audio. Frames from priority speakers
+    /// are never ducked, and their speech starts the ducking of everyone else.
+    pub(crate) fn frame_gain(&self, identity: &str, frame: &[i16]) -> f32 {
+        let now = Instant::now();
+        if self.is_priority(identity) {
+            if rms(frame) > SPEECH_RMS {
+                *self.last_priority_speech.lock().unwrap() = Some(now);
+            }
+            return 1.0;
+        }
+        let ducking = self
+            .last_priority_speech
+            .lock()
+            .unwrap()
+            .is_some_and(|t| now.duration_since(t) < DUCK_HOLD);
+        if ducking {
+            f32::from_bits(self.gain.load(Ordering::Relaxed))
+        } else {
+            1.0
+        }
+    }
+}
+
+fn rms(frame: &[i16]) -> f32 {
+    if frame.is_empty() {
+        return 0.0;
+    }
+    let sum: f32 = frame
+        .iter()
+        .map(|&s| (s as f32 / i16::MAX as f32).powi(2))
+        .sum();
+    (sum / frame.len() as f32).sqrt()
+}
+
+fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db.min(0.0) / 20.0)
+}
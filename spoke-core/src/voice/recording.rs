@@ -0,0 +1,284 @@
+// Voice messages — recording the mic into an Ogg/Opus clip with its
+// duration and waveform (MSC3245/MSC3246), and decoding and playing clips
+// others sent.
+//
+// The cpal input stream lives on its own thread, as in `audio.rs`. Samples
+// are kept as mono f32 at the device rate and only resampled to Opus's 48 kHz
+// once the recording is finished.
+
+use std::{
+    io::Cursor,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use cpal::traits::{DeviceTrait, StreamTrait};
+use ogg::{PacketReader, PacketWriteEndInfo, PacketWriter};
+use tracing::warn;
+
+use crate::voice::{
+    audio::{AudioOutput, InputMeter},
+    devices::find_input,
+    resample::resample_clip,
+};
+
+/// Opus always runs at 48 kHz here.
+const OPUS_RATE: u32 = 48_000;
+/// 20 ms frames.
+const FRAME_SAMPLES: usize = 960;
+/// Ogg stream serial; a clip holds a single stream.
+const STREAM_SERIAL: u32 = 0x5370_6b65;
+/// Recordings stop growing after this long.
+pub const MAX_RECORDING: Duration = Duration::from_secs(15 * 60);
+/// Number of bars in a waveform; MSC3246 suggests 30–120.
+const WAVEFORM_BARS: usize = 100;
+/// Waveform values run 0..=1024.
+pub const WAVEFORM_MAX: u16 = 1024;
+
+/// A finished recording, ready to upload.
+#[derive(Debug, Clone)]
+pub struct Recording {
+    /// Ogg/Opus file contents.
+    pub ogg: Vec<u8>,
+    pub duration_ms: u64,
+    pub waveform: Vec<u16>,
+}
+
+/// Records an input device until finished or dropped.
+pub struct Recorder {
+    samples: Arc<Mutex<Vec<f32>>>,
+    sample_rate: u32,
+    started: Instant,
+    /// Peak level while recording; shared with the UI.
+    pub meter: Arc<InputMeter>,
+    /// Dropping this ends the capture thread and stops the cpal stream.
+    _kill: std::sync::mpsc::Sender<()>,
+}
+
+impl Recorder {
+    /// Record from input device `device`, or the default one.
+    pub fn start(device: Option<&str>) -> Result<Self> {
+        let samples = Arc::new(Mutex::new(Vec::new()));
+        let meter = Arc::new(InputMeter::default());
+        let (kill_tx, kill_rx) = std::sync::mpsc::channel::<()>();
+        let (ready_tx, ready_rx) = std::sync::mpsc::channel::<Result<u32, String>>();
+
+        let samples_in = samples.clone();
+        let meter_in = meter.clone();
+        let device = device.map(str::to_owned);
+        std::thread::spawn(move || {
+            let host = cpal::default_host();
+            let dev = match find_input(&host, device.as_deref()) {
+                Ok(d) => d,
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e.to_string()));
+                    return;
+                }
+            };
+            let cfg = match dev.default_input_config() {
+                Ok(c) => c,
+                Err(e) => {
+                    let _ = ready_tx.send(Err(format!("input config: {e}")));
+                    return;
+                }
+            };
+            let sample_rate = cfg.sample_rate().0;
+            let channels = cfg.channels().max(1) as usize;
+            let limit = (MAX_RECORDING.as_secs() * sample_rate as u64) as usize;
+            let stream_cfg: cpal::StreamConfig = cfg.into();
+            let stream = match dev.build_input_stream(
+                &stream_cfg,
+                move |data: &[f32], _: &cpal::InputCallbackInfo| {
+                    let mut peak = 0.0f32;
+                    let mono = data.chunks(channels).map(|frame| {
+                        let s = frame.iter().sum::<f32>() / frame.len() as f32;
+                        peak = peak.max(s.abs());
+                        s
+                    });
+                    let mut samples = samples_in.lock().unwrap();
+                    let room = limit.saturating_sub(samples.len());
+                    samples.extend(mono.take(room));
+                    meter_in.record(peak, peak > 1.0);
+                },
+                |e| warn!("cpal input error: {e}"),
+                None,
+            ) {
+                Ok(s) => s,
+                Err(e) => {
+                    let _ = ready_tx.send(Err(format!("build input stream: {e}")));
+                    return;
+                }
+            };
+            if let Err(e) = stream.play() {
+                let _ = ready_tx.send(Err(format!("play input stream: {e}")));
+                return;
+            }
+            let _ = ready_tx.send(Ok(sample_rate));
+            let _ = kill_rx.recv();
+        });
+
+        let sample_rate = ready_rx
+            .recv()
+            .map_err(|_| anyhow::anyhow!("input thread died before ready"))?
+            .map_err(|e| anyhow::anyhow!("{e}"))?;
+        Ok(Self {
+            samples,
+            sample_rate,
+            started: Instant::now(),
+            meter,
+            _kill: kill_tx,
+        })
+    }
+
+    /// Time since recording started, capped at `MAX_RECORDING`.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed().min(MAX_RECORDING)
+    }
+
+    /// Stop recording and encode what was captured.
+    pub fn finish(self) -> Result<Recording> {
+        let Recorder {
+            samples,
+            sample_rate,
+            _kill: kill,
+            ..
+        } = self;
+        drop(kill);
+        let samples = std::mem::take(&mut *samples.lock().unwrap());
+        if samples.is_empty() {
+            anyhow::bail!("nothing was recorded");
+        }
+        let pcm = resample_clip(&samples, sample_rate, OPUS_RATE);
+        let duration_ms = pcm.len() as u64 * 1000 / OPUS_RATE as u64;
+        Ok(Recording {
+            ogg: encode(&pcm)?,
+            duration_ms,
+            waveform: waveform(&pcm),
+        })
+    }
+}
+
+/// Peak level per bar, scaled to 0..=`WAVEFORM_MAX`.
+fn waveform(pcm: &[f32]) -> Vec<u16> {
+    let bar = pcm.len().div_ceil(WAVEFORM_BARS).max(1);
+    pcm.chunks(bar)
+        .map(|chunk| {
+            let peak = chunk.iter().fold(0.0f32, |p, s| p.max(s.abs())).min(1.0);
+            (peak * WAVEFORM_MAX as f32) as u16
+        })
+        .collect()
+}
+
+/// Encode 48 kHz mono `pcm` as an Ogg/Opus file (RFC 7845).
+fn encode(pcm: &[f32]) -> Result<Vec<u8>> {
+    let mut encoder = opus::Encoder::new(OPUS_RATE, opus::Channels::Mono, opus::Application::Voip)?;
+    let pre_skip = encoder.get_lookahead()? as u16;
+
+    let mut head = Vec::with_capacity(19);
+    head.extend_from_slice(b"OpusHead");
+    head.push(1); // version
+    head.push(1); // channels
+    head.extend_from_slice(&pre_skip.to_le_bytes());
+    head.extend_from_slice(&OPUS_RATE.to_le_bytes());
+    head.extend_from_slice(&0i16.to_le_bytes()); // output gain
+    head.push(0); // mapping family
+    let vendor = b"spoke";
+    let mut tags = Vec::new();
+    tags.extend_from_slice(b"OpusTags");
+    tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    tags.extend_from_slice(vendor);
+    tags.extend_from_slice(&0u32.to_le_bytes()); // no comments
+
+    let mut writer = PacketWriter::new(Vec::new());
+    writer.write_packet(head, STREAM_SERIAL, PacketWriteEndInfo::EndPage, 0)?;
+    writer.write_packet(tags, STREAM_SERIAL, PacketWriteEndInfo::EndPage, 0)?;
+
+    let frames = pcm.len().div_ceil(FRAME_SAMPLES);
+    let mut out = vec![0u8; 4000];
+    for (i, chunk) in pcm.chunks(FRAME_SAMPLES).enumerate() {
+        let mut frame = [0.0f32; FRAME_SAMPLES];
+        frame[..chunk.len()].copy_from_slice(chunk);
+        let len = encoder.encode_float(&frame, &mut out)?;
+        let last = i + 1 == frames;
+        // The final granule position marks where the real audio ends.
+        let granule = if last {
+            pcm.len() as u64 + pre_skip as u64
+        } else {
+            ((i + 1) * FRAME_SAMPLES) as u64 + pre_skip as u64
+        };
+        let end = if last {
+            PacketWriteEndInfo::EndStream
+        } else {
+            PacketWriteEndInfo::NormalPacket
+        };
+        writer.write_packet(out[..len].to_vec(), STREAM_SERIAL, end, granule)?;
+    }
+    Ok(writer.into_inner())
+}
+
+/// Decode an Ogg/Opus file to 48 kHz mono samples.
+pub fn decode(ogg: &[u8]) -> Result<Vec<f32>> {
+    let mut reader = PacketReader::new(Cursor::new(ogg));
+    let head = reader
+        .read_packet()?
+        .ok_or_else(|| anyhow::anyhow!("empty file"))?;
+    if !head.data.starts_with(b"OpusHead") || head.data.len() < 19 {
+        anyhow::bail!("not an Opus file");
+    }
+    let channels = match head.data[9] {
+        1 => opus::Channels::Mono,
+        2 => opus::Channels::Stereo,
+        n => anyhow::bail!("unsupported channel count {n}"),
+    };
+    let pre_skip = u16::from_le_bytes([head.data[10], head.data[11]]) as usize;
+    let mut decoder = opus::Decoder::new(OPUS_RATE, channels)?;
+    let width = channels as usize;
+
+    let _tags = reader.read_packet()?;
+    let mut pcm = Vec::new();
+    // Up to 120 ms per packet.
+    let mut buf = vec![0.0f32; 5760 * width];
+    let mut last_granule = 0;
+    while let Some(packet) = reader.read_packet()? {
+        let n = decoder.decode_float(&packet.data, &mut buf, false)?;
+        pcm.extend(
+            buf[..n * width]
+                .chunks(width)
+                .map(|f| f.iter().sum::<f32>() / width as f32),
+        );
+        last_granule = packet.absgp_page();
+    }
+    // Trim the encoder delay at the start and the padding at the end.
+    let end = (last_granule as usize)
+        .saturating_sub(pre_skip)
+        .min(pcm.len().saturating_sub(pre_skip));
+    Ok(pcm.into_iter().skip(pre_skip).take(end).collect())
+}
+
+/// Play 48 kHz mono `pcm` on output device `device` (or the default).
+/// Blocks until it has played or `stop` is set.
+pub fn play(pcm: &[f32], stop: &AtomicBool, device: Option<&str>) -> Result<()> {
+    let output = AudioOutput::new(device)?;
+    let mut track = output.track();
+    for chunk in pcm.chunks(OPUS_RATE as usize / 10) {
+        // Keep about half a second queued so stopping is prompt.
+        while track.queued() > output.mixer.rate() as usize / 2 {
+            if stop.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        if stop.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        track.push(chunk, OPUS_RATE);
+    }
+    while track.queued() > 0 && !stop.load(Ordering::Relaxed) {
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    Ok(())
+}
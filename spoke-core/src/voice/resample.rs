@@ -0,0 +1,129 @@
+// Sample-rate conversion — LiveKit and Opus both work at 48 kHz, while
+// devices run at whatever the OS picked: 44.1 kHz on plenty of sound cards,
+// 16 kHz or 8 kHz on Bluetooth headsets in call mode, and odder rates now
+// and then. Audio is converted at the edges: capture on its way to LiveKit,
+// remote tracks on their way into the mixer.
+//
+// rubato's FFT resampler takes fixed-size chunks, so input is held back
+// until a full chunk (10 ms) is there. That adds a chunk plus the filter's
+// own delay, a few ms either way; when the rates match nothing is held.
+
+use rubato::{FftFixedIn, Resampler as _};
+use tracing::warn;
+
+/// Rate of every stream exchanged with LiveKit.
+pub(crate) const LIVEKIT_RATE: u32 = 48_000;
+/// Sub-chunks rubato splits each chunk into; more means a shorter delay
+/// and a little more work.
+const SUB_CHUNKS: usize = 2;
+
+/// Streaming converter for interleaved audio with `channels` channels.
+pub(crate) struct Resampler {
+    from: u32,
+    to: u32,
+    channels: usize,
+    /// `None` when `from == to`.
+    inner: Option<FftFixedIn<f32>>,
+    /// Input held back until there's a whole chunk, one `Vec` per channel.
+    pending: Vec<Vec<f32>>,
+}
+
+impl Resampler {
+    pub(crate) fn new(from: u32, to: u32, channels: usize) -> Self {
+        let channels = channels.max(1);
+        let inner = if from == to || from == 0 || to == 0 {
+            None
+        } else {
+            let chunk = (from as usize / 100).max(1);
+            match FftFixedIn::new(from as usize, to as usize, chunk, SUB_CHUNKS, channels) {
+                Ok(r) => Some(r),
+                Err(e) => {
+                    warn!("can't resample {from} Hz to {to} Hz: {e}");
+                    None
+                }
+            }
+        };
+        Self {
+            from,
+            to,
+            channels,
+            inner,
+            pending: vec![Vec::new(); channels],
+        }
+    }
+
+    /// Input rate, Hz.
+    pub(crate) fn from_rate(&self) -> u32 {
+        self.from
+    }
+
+    /// Output rate, Hz.
+    pub(crate) fn to_rate(&self) -> u32 {
+        self.to
+    }
+
+    /// Convert `input`, appending whatever is ready to `out`. Both are
+    /// interleaved.
+    pub(crate) fn process(&mut self, input: &[f32], out: &mut Vec<f32>) {
+        let Some(inner) = self.inner.as_mut() else {
+            out.extend_from_slice(input);
+            return;
+        };
+        for frame in input.chunks_exact(self.channels) {
+            for (pending, &s) in self.pending.iter_mut().zip(frame) {
+                pending.push(s);
+            }
+        }
+        loop {
+            let need = inner.input_frames_next();
+            if self.pending[0].len() < need {
+                break;
+            }
+            let chunk: Vec<&[f32]> = self.pending.iter().map(|p| &p[..need]).collect();
+            match inner.process(&chunk, None) {
+                Ok(converted) => interleave(&converted, out),
+                Err(e) => warn!("resampling {} Hz to {} Hz: {e}", self.from, self.to),
+            }
+            for pending in &mut self.pending {
+                pending.drain(..need);
+            }
+        }
+    }
+
+    /// Convert whatever is held back, plus the filter's tail, so the end of
+    /// a finite clip isn't lost.
+    pub(crate) fn flush(&mut self, out: &mut Vec<f32>) {
+        let Some(inner) = self.inner.as_mut() else {
+            return;
+        };
+        let partial = std::mem::replace(&mut self.pending, vec![Vec::new(); self.channels]);
+        for wave_in in [Some(partial), None] {
+            match inner.process_partial(wave_in.as_deref(), None) {
+                Ok(converted) => interleave(&converted, out),
+                Err(e) => warn!("resampling {} Hz to {} Hz: {e}", self.from, self.to),
+            }
+        }
+    }
+}
+
+/// Convert a whole mono clip from `from` Hz to `to` Hz, trimmed to line up
+/// with the input.
+pub(crate) fn resample_clip(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+    let mut resampler = Resampler::new(from, to, 1);
+    let Some(delay) = resampler.inner.as_ref().map(|r| r.output_delay()) else {
+        return samples.to_vec();
+    };
+    let len = (samples.len() as u64 * u64::from(to) / u64::from(from)) as usize;
+    let mut out = Vec::with_capacity(delay + len);
+    resampler.process(samples, &mut out);
+    resampler.flush(&mut out);
+    out.into_iter().skip(delay).take(len).collect()
+}
+
+fn interleave(channels: &[Vec<f32>], out: &mut Vec<f32>) {
+    let frames = channels.first().map_or(0, Vec::len);
+    out.reserve(frames * channels.len());
+    for i in 0..frames {
+        out.extend(channels.iter().map(|c| c[i]));
+    }
+}
@@ -0,0 +1,103 @@
+// Call ringing — inviting someone into a DM call by ringing their devices
+// directly with `org.spoke.voice.ring` to-device messages, rather than
+// posting into the room and hoping they look.
+//
+// A ring is answered, declined or cancelled with the same `call_id`. The
+// answer and decline also go to the callee's own devices, so every other
+// device they have stops ringing. Rings are sent unencrypted to all of a
+// user's devices; they carry only the room and call IDs, which the
+// homeserver already knows from the room itself.
+
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use matrix_sdk::{
+    Client, Room, RoomState,
+    ruma::{
+        TransactionId, UserId,
+        api::client::to_device::send_event_to_device,
+        events::{AnyToDeviceEventContent, ToDeviceEventContent, room::member::MembershipState},
+        serde::Raw,
+        to_device::DeviceIdOrAllDevices,
+    },
+};
+
+use super::events::{RingAction, VoiceRingEventContent};
+
+/// How long a ring lasts unless answered, declined or cancelled. Older rings
+/// (e.g. delivered when a device comes back online) are ignored.
+pub const RING_TIMEOUT: Duration = Duration::from_secs(45);
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+impl VoiceRingEventContent {
+    /// `action` for call `call_id` in `room`, stamped now.
+    pub fn new(room: &Room, call_id: &str, action: RingAction) -> Self {
+        Self {
+            room_id: room.room_id().to_owned(),
+            call_id: call_id.to_owned(),
+            action,
+            ts: now_ms(),
+        }
+    }
+
+    /// Whether a ring sent at `ts` is still ringing. Allows for a minute of
+    /// clock skew between the two devices.
+    pub fn is_current(&self) -> bool {
+        let age = now_ms().saturating_sub(self.ts);
+        age < RING_TIMEOUT.as_millis() as u64 && self.ts < now_ms() + 60_000
+    }
+}
+
+/// A fresh call ID.
+pub fn new_call_id() -> String {
+    format!("{:016x}", rand::random::<u64>())
+}
+
+/// Send `content` to every device of each of `users`.
+pub async fn send_ring(
+    client: &Client,
+    users: &[&UserId],
+    content: &VoiceRingEventContent,
+) -> Result<()> {
+    let raw = Raw::new(content)?.cast::<AnyToDeviceEventContent>();
+    let messages = users
+        .iter()
+        .map(|user| {
+            (
+                (*user).to_owned(),
+                BTreeMap::from([(DeviceIdOrAllDevices::AllDevices, raw.clone())]),
+            )
+        })
+        .collect();
+    let request = send_event_to_device::v3::Request::new_raw(
+        content.event_type(),
+        TransactionId::new(),
+        messages,
+    );
+    client.send(request, None).await?;
+    Ok(())
+}
+
+/// The room an incoming ring from `sender` is for, if we should act on it:
+/// we're in the room and so is the sender. Anything else is spam or stale.
+pub async fn ring_room(
+    client: &Client,
+    sender: &UserId,
+    content: &VoiceRingEventContent,
+) -> Option<Room> {
+    let room = client.get_room(&content.room_id)?;
+    if room.state() != RoomState::Joined {
+        return None;
+    }
+    let member = room.get_member_no_sync(sender).await.ok()??;
+    (*member.membership() == MembershipState::Join).then_some(room)
+}
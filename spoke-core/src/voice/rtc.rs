@@ -0,0 +1,83 @@
+// MatrixRTC membership — `m.call.member` state events (MSC3401, with
+// MSC4143's per-device memberships) alongside our own org.spoke.voice.*
+// events, so Element Call and other MatrixRTC clients show who's in a
+// Spoke call.
+//
+// Each device keeps its own membership under the state key
+// `_{user_id}_{device_id}`; leaving empties it. Memberships name the
+// sidecar as the LiveKit focus, with the room ID as the LiveKit room, which
+// is where Spoke calls actually live. They lapse after `MEMBERSHIP_EXPIRY`
+// in case we never get to clear ours.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use matrix_sdk::{Client, Room};
+
+use super::events::{ActiveFocus, CallMemberEventContent, LivekitFocus};
+
+/// How long a membership counts for without being renewed.
+pub const MEMBERSHIP_EXPIRY: Duration = Duration::from_secs(4 * 60 * 60);
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+impl CallMemberEventContent {
+    /// This device in the room's call, reachable through `service_url`.
+    pub fn joined(room: &Room, device_id: &str, service_url: &str) -> Self {
+        Self {
+            application: Some("m.call".into()),
+            call_id: Some(String::new()),
+            scope: Some("m.room".into()),
+            device_id: Some(device_id.to_owned()),
+            created_ts: Some(now_ms()),
+            expires: Some(MEMBERSHIP_EXPIRY.as_millis() as u64),
+            focus_active: Some(ActiveFocus {
+                kind: "livekit".into(),
+                focus_selection: "oldest_membership".into(),
+            }),
+            foci_preferred: vec![LivekitFocus {
+                kind: "livekit".into(),
+                livekit_service_url: service_url.to_owned(),
+                livekit_alias: room.room_id().to_string(),
+            }],
+        }
+    }
+
+    /// Whether this is a live membership, given when the event was sent (for
+    /// memberships without `created_ts`).
+    pub fn is_active(&self, origin_server_ts: u64) -> bool {
+        let Some(expires) = self.expires else {
+            return false;
+        };
+        self.application.is_some()
+            && self.created_ts.unwrap_or(origin_server_ts) + expires > now_ms()
+    }
+}
+
+/// Our device's state key in a room.
+fn own_state_key(client: &Client) -> Result<String> {
+    let user_id = client.user_id().context("not logged in")?;
+    let device_id = client.device_id().context("no device ID")?;
+    Ok(format!("_{user_id}_{device_id}"))
+}
+
+/// Announce that this device joined `room`'s call.
+pub async fn join(client: &Client, room: &Room, service_url: &str) -> Result<()> {
+    let device_id = client.device_id().context("no device ID")?;
+    let content = CallMemberEventContent::joined(room, device_id.as_str(), service_url);
+    room.send_state_event_for_key(&own_state_key(client)?, content)
+        .await?;
+    Ok(())
+}
+
+/// Announce that this device left `room`'s call.
+pub async fn leave(client: &Client, room: &Room) -> Result<()> {
+    room.send_state_event_for_key(&own_state_key(client)?, CallMemberEventContent::default())
+        .await?;
+    Ok(())
+}
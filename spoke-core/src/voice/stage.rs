@@ -0,0 +1,227 @@
+// Stage mode and voice permissions — read-modify-write helpers for the voice
+// config and speaker list state events. The sidecar enforces the result when
+// issuing grants.
+
+use anyhow::Result;
+use matrix_sdk::{
+    Room,
+    deserialized_responses::SyncOrStrippedState,
+    ruma::{
+        OwnedUserId, UserId,
+        api::client::{error::ErrorKind, state::get_state_events_for_key},
+        events::{StateEventType, SyncStateEvent},
+    },
+};
+
+use super::events::{VoiceConfigEventContent, VoicePermissions, VoiceStageEventContent};
+use super::priority::PriorityRule;
+
+/// The room's current `org.spoke.voice.config`, or the default if unset.
+pub async fn voice_config(room: &Room) -> Result<VoiceConfigEventContent> {
+    let Some(raw) = room
+        .get_state_event_static::<VoiceConfigEventContent>()
+        .await?
+    else {
+        return Ok(VoiceConfigEventContent::default());
+    };
+    Ok(match raw.deserialize()? {
+        SyncOrStrippedState::Sync(SyncStateEvent::Original(ev)) => ev.content,
+        _ => VoiceConfigEventContent::default(),
+    })
+}
+
+/// The room's current stage speakers from `org.spoke.voice.stage`.
+pub async fn stage_speakers(room: &Room) -> Result<Vec<OwnedUserId>> {
+    let Some(raw) = room
+        .get_state_event_static::<VoiceStageEventContent>()
+        .await?
+    else {
+        return Ok(Vec::new());
+    };
+    Ok(match raw.deserialize()? {
+        SyncOrStrippedState::Sync(SyncStateEvent::Original(ev)) => ev.content.speakers,
+        _ => Vec::new(),
+    })
+}
+
+/// Turn stage mode on or off, preserving the rest of the voice config.
+pub async fn set_stage_mode(room: &Room, enabled: bool) -> Result<()> {
+    let mut config = fetch_voice_config(room).await?;
+    if config.stage == enabled {
+        return Ok(());
+    }
+    config.stage = enabled;
+    room.send_state_event(config).await?;
+    Ok(())
+}
+
+/// The room's `org.spoke.voice.config` fresh from the server rather than the
+/// sync cache, or the default if unset.
+async fn fetch_voice_config(room: &Room) -> Result<VoiceConfigEventContent> {
+    let request = get_state_events_for_key::v3::Request::new(
+        room.room_id().to_owned(),
+        StateEventType::from("org.spoke.voice.config"),
+        String::new(),
+    );
+    match room.client().send(request, None).await {
+        Ok(response) => Ok(response.content.deserialize_as()?),
+        Err(e) if matches!(e.client_api_error_kind(), Some(ErrorKind::NotFound)) => {
+            Ok(VoiceConfigEventContent::default())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Apply the fields changed between `base` (the config an edit started
+/// from) and `edited` to a fresh copy fetched from the server, so a stale
+/// form doesn't undo anyone else's changes to fields it didn't touch.
+pub async fn set_voice_config(
+    room: &Room,
+    base: &VoiceConfigEventContent,
+    edited: &VoiceConfigEventContent,
+) -> Result<()> {
+    let fresh = fetch_voice_config(room).await?;
+    let config = merge_voice_config(fresh.clone(), base, edited);
+    config.validate().map_err(anyhow::Error::msg)?;
+    if config == fresh {
+        return Ok(());
+    }
+    room.send_state_event(config).await?;
+    Ok(())
+}
+
+/// `fresh` with the changes from `base` to `edited` applied. Bans are merged
+/// per user, so two moderators banning different people both stick.
+fn merge_voice_config(
+    mut fresh: VoiceConfigEventContent,
+    base: &VoiceConfigEventContent,
+    edited: &VoiceConfigEventContent,
+) -> VoiceConfigEventContent {
+    if edited.stage != base.stage {
+        fresh.stage = edited.stage;
+    }
+    if edited.permissions != base.permissions {
+        fresh.permissions = edited.permissions;
+    }
+    if edited.bitrate != base.bitrate {
+        fresh.bitrate = edited.bitrate;
+    }
+    if edited.max_participants != base.max_participants {
+        fresh.max_participants = edited.max_participants;
+    }
+    fresh
+        .banned
+        .retain(|u| edited.banned.contains(u) || !base.banned.contains(u));
+    for user_id in &edited.banned {
+        if !base.banned.contains(user_id) && !fresh.banned.contains(user_id) {
+            fresh.banned.push(user_id.clone());
+        }
+    }
+    fresh
+}
+
+/// Replace the power level thresholds for voice capabilities, preserving the
+/// rest of the voice config.
+pub async fn set_voice_permissions(room: &Room, permissions: VoicePermissions) -> Result<()> {
+    let mut config = fetch_voice_config(room).await?;
+    if config.permissions == permissions {
+        return Ok(());
+    }
+    config.permissions = permissions;
+    room.send_state_event(config).await?;
+    Ok(())
+}
+
+/// Who is a priority speaker in `room`: its power levels against the voice
+/// config's `priority_speaker` threshold.
+pub async fn priority_rule(room: &Room) -> Result<PriorityRule> {
+    let power_levels = room.power_levels().await?;
+    let config = voice_config(room).await?;
+    Ok(PriorityRule {
+        users: power_levels
+            .users
+            .iter()
+            .map(|(user_id, level)| (user_id.to_string(), i64::from(*level)))
+            .collect(),
+        users_default: i64::from(power_levels.users_default),
+        threshold: Some(config.permissions.priority_speaker),
+    })
+}
+
+/// Add `user_id` to (or remove them from) the stage speaker list.
+pub async fn set_stage_speaker(room: &Room, user_id: &UserId, speaker: bool) -> Result<()> {
+    let mut speakers = stage_speakers(room).await?;
+    let present = speakers.iter().any(|u| u == user_id);
+    match (speaker, present) {
+        (true, false) => speakers.push(user_id.to_owned()),
+        (false, true) => speakers.retain(|u| u != user_id),
+        _ => return Ok(()),
+    }
+    room.send_state_event(VoiceStageEventContent { speakers })
+        .await?;
+    Ok(())
+}
+
+/// Whether `user_id` may edit the speaker list (and so moderate the stage).
+pub async fn can_moderate_stage(room: &Room, user_id: &UserId) -> Result<bool> {
+    Ok(room
+        .can_user_send_state(user_id, StateEventType::from("org.spoke.voice.stage"))
+        .await?)
+}
+
+/// Whether `user_id` may change the room's voice config.
+pub async fn can_edit_voice_config(room: &Room, user_id: &UserId) -> Result<bool> {
+    Ok(room
+        .can_user_send_state(user_id, StateEventType::from("org.spoke.voice.config"))
+        .await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk::ruma::user_id;
+
+    use super::*;
+    use crate::voice::events::BitrateTier;
+
+    #[test]
+    fn merge_keeps_fields_the_edit_left_alone() {
+        let base = VoiceConfigEventContent::default();
+        let edited = VoiceConfigEventContent {
+            bitrate: BitrateTier::High,
+            ..base.clone()
+        };
+        let fresh = VoiceConfigEventContent {
+            stage: true,
+            max_participants: Some(10),
+            ..base.clone()
+        };
+        let merged = merge_voice_config(fresh, &base, &edited);
+        assert!(merged.stage);
+        assert_eq!(merged.max_participants, Some(10));
+        assert_eq!(merged.bitrate, BitrateTier::High);
+    }
+
+    #[test]
+    fn merge_applies_bans_per_user() {
+        let alice = user_id!("@alice:example.org").to_owned();
+        let bob = user_id!("@bob:example.org").to_owned();
+        let carol = user_id!("@carol:example.org").to_owned();
+        let base = VoiceConfigEventContent {
+            banned: vec![alice.clone()],
+            ..Default::default()
+        };
+        // We lifted Alice's ban and banned Carol; meanwhile someone banned Bob.
+        let edited = VoiceConfigEventContent {
+            banned: vec![carol.clone()],
+            ..Default::default()
+        };
+        let fresh = VoiceConfigEventContent {
+            banned: vec![alice, bob.clone()],
+            ..Default::default()
+        };
+        assert_eq!(
+            merge_voice_config(fresh, &base, &edited).banned,
+            vec![bob, carol]
+        );
+    }
+}
@@ -0,0 +1,180 @@
+// Minimal STUN/TURN message codec (RFC 5389 / RFC 5766) — just enough for
+// the voice preflight: Binding requests and authenticated Allocate/Refresh.
+
+use hmac::{Hmac, Mac};
+use md5::{Digest, Md5};
+use sha1::Sha1;
+
+const MAGIC_COOKIE: u32 = 0x2112_A442;
+const HEADER_LEN: usize = 20;
+
+// Message types.
+pub const BINDING_REQUEST: u16 = 0x0001;
+pub const BINDING_SUCCESS: u16 = 0x0101;
+pub const ALLOCATE_REQUEST: u16 = 0x0003;
+pub const ALLOCATE_SUCCESS: u16 = 0x0103;
+pub const ALLOCATE_ERROR: u16 = 0x0113;
+pub const REFRESH_REQUEST: u16 = 0x0004;
+
+// Attribute types.
+const ATTR_USERNAME: u16 = 0x0006;
+const ATTR_MESSAGE_INTEGRITY: u16 = 0x0008;
+const ATTR_ERROR_CODE: u16 = 0x0009;
+const ATTR_LIFETIME: u16 = 0x000D;
+const ATTR_REALM: u16 = 0x0014;
+const ATTR_NONCE: u16 = 0x0015;
+const ATTR_REQUESTED_TRANSPORT: u16 = 0x0019;
+
+/// IANA protocol number for UDP, used in REQUESTED-TRANSPORT.
+const TRANSPORT_UDP: u8 = 17;
+
+/// Long-term credentials for MESSAGE-INTEGRITY.
+pub struct Credentials<'a> {
+    pub username: &'a str,
+    pub password: &'a str,
+    pub realm: &'a str,
+    pub nonce: &'a str,
+}
+
+/// A STUN message being built.
+pub struct Request {
+    msg_type: u16,
+    txid: [u8; 12],
+    attrs: Vec<u8>,
+}
+
+impl Request {
+    pub fn new(msg_type: u16) -> Self {
+        Self {
+            msg_type,
+            txid: rand::random(),
+            attrs: Vec::new(),
+        }
+    }
+
+    pub fn txid(&self) -> [u8; 12] {
+        self.txid
+    }
+
+    fn attr(&mut self, ty: u16, value: &[u8]) {
+        self.attrs.extend_from_slice(&ty.to_be_bytes());
+        self.attrs
+            .extend_from_slice(&(value.len() as u16).to_be_bytes());
+        self.attrs.extend_from_slice(value);
+        // Attributes are padded to a 4-byte boundary.
+        let pad = (4 - value.len() % 4) % 4;
+        self.attrs.extend(std::iter::repeat_n(0u8, pad));
+    }
+
+    /// REQUESTED-TRANSPORT: UDP (the only transport TURN relays allocate).
+    pub fn requested_transport_udp(mut self) -> Self {
+        self.attr(ATTR_REQUESTED_TRANSPORT, &[TRANSPORT_UDP, 0, 0, 0]);
+        self
+    }
+
+    pub fn lifetime(mut self, seconds: u32) -> Self {
+        self.attr(ATTR_LIFETIME, &seconds.to_be_bytes());
+        self
+    }
+
+    /// Serialize, appending USERNAME/REALM/NONCE and MESSAGE-INTEGRITY when
+    /// credentials are given.
+    pub fn encode(mut self, creds: Option<&Credentials<'_>>) -> Vec<u8> {
+        let Some(creds) = creds else {
+            return self.finish(0);
+        };
+        self.attr(ATTR_USERNAME, creds.username.as_bytes());
+        self.attr(ATTR_REALM, creds.realm.as_bytes());
+        self.attr(ATTR_NONCE, creds.nonce.as_bytes());
+
+        // The length in the header must already count the 24-byte
+        // MESSAGE-INTEGRITY attribute when the HMAC is computed.
+        let mut msg = self.finish(24);
+        let key = Md5::digest(format!(
+            "{}:{}:{}",
+            creds.username, creds.realm, creds.password
+        ));
+        let mut mac = Hmac::<Sha1>::new_from_slice(&key).expect("hmac accepts any key length");
+        mac.update(&msg);
+        let digest = mac.finalize().into_bytes();
+
+        msg.extend_from_slice(&ATTR_MESSAGE_INTEGRITY.to_be_bytes());
+        msg.extend_from_slice(&20u16.to_be_bytes());
+        msg.extend_from_slice(&digest);
+        msg
+    }
+
+    fn finish(&self, extra_len: usize) -> Vec<u8> {
+        let mut msg = Vec::with_capacity(HEADER_LEN + self.attrs.len() + extra_len);
+        msg.extend_from_slice(&self.msg_type.to_be_bytes());
+        msg.extend_from_slice(&((self.attrs.len() + extra_len) as u16).to_be_bytes());
+        msg.extend_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        msg.extend_from_slice(&self.txid);
+        msg.extend_from_slice(&self.attrs);
+        msg
+    }
+}
+
+/// A parsed STUN response.
+#[derive(Debug)]
+pub struct Response {
+    pub msg_type: u16,
+    pub txid: [u8; 12],
+    pub error_code: Option<(u16, String)>,
+    pub realm: Option<String>,
+    pub nonce: Option<String>,
+}
+
+/// Total message length (header + body) declared by a STUN header, used to
+/// frame messages on stream transports.
+pub fn message_len(header: &[u8; HEADER_LEN]) -> usize {
+    HEADER_LEN + u16::from_be_bytes([header[2], header[3]]) as usize
+}
+
+pub const fn header_len() -> usize {
+    HEADER_LEN
+}
+
+pub fn parse(buf: &[u8]) -> Option<Response> {
+    if buf.len() < HEADER_LEN {
+        return None;
+    }
+    let msg_type = u16::from_be_bytes([buf[0], buf[1]]);
+    let len = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+    if u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]) != MAGIC_COOKIE
+        || buf.len() < HEADER_LEN + len
+    {
+        return None;
+    }
+    let mut txid = [0u8; 12];
+    txid.copy_from_slice(&buf[8..20]);
+
+    let mut resp = Response {
+        msg_type,
+        txid,
+        error_code: None,
+        realm: None,
+        nonce: None,
+    };
+    let mut rest = &buf[HEADER_LEN..HEADER_LEN + len];
+    while rest.len() >= 4 {
+        let ty = u16::from_be_bytes([rest[0], rest[1]]);
+        let alen = u16::from_be_bytes([rest[2], rest[3]]) as usize;
+        let Some(value) = rest.get(4..4 + alen) else {
+            break;
+        };
+        match ty {
+            ATTR_ERROR_CODE if value.len() >= 4 => {
+                let code = (value[2] & 0x07) as u16 * 100 + value[3] as u16;
+                let reason = String::from_utf8_lossy(&value[4..]).into_owned();
+                resp.error_code = Some((code, reason));
+            }
+            ATTR_REALM => resp.realm = Some(String::from_utf8_lossy(value).into_owned()),
+            ATTR_NONCE => resp.nonce = Some(String::from_utf8_lossy(value).into_owned()),
+            _ => {}
+        }
+        let padded = 4 + alen + (4 - alen % 4) % 4;
+        rest = rest.get(padded..).unwrap_or(&[]);
+    }
+    Some(resp)
+}
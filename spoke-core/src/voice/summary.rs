@@ -0,0 +1,71 @@
+// Call summaries — who was in a call, for how long, and how busy it got, as
+// seen from one client. The last participant out posts the result as an
+// `org.spoke.voice.summary` event.
+
+use std::{
+    collections::BTreeSet,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use matrix_sdk::ruma::UserId;
+
+use super::events::VoiceSummaryEventContent;
+
+/// Tracks one call from the moment we join until we leave.
+#[derive(Debug, Clone)]
+pub struct CallTracker {
+    started: Instant,
+    started_ts: u64,
+    participants: BTreeSet<String>,
+    peak: usize,
+    initiator: bool,
+}
+
+impl CallTracker {
+    /// Start tracking as `local` joins; `remote` is who was already there.
+    pub fn new(local: &str, remote: &[String]) -> Self {
+        let started_ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let mut tracker = Self {
+            started: Instant::now(),
+            started_ts,
+            participants: BTreeSet::from([local.to_owned()]),
+            peak: 1,
+            initiator: remote.is_empty(),
+        };
+        tracker.observe(remote);
+        tracker
+    }
+
+    /// Record the current remote roster.
+    pub fn observe(&mut self, remote: &[String]) {
+        self.participants.extend(remote.iter().cloned());
+        self.peak = self.peak.max(remote.len() + 1);
+    }
+
+    /// Whether the room was empty when we joined, i.e. we started the call.
+    pub fn initiator(&self) -> bool {
+        self.initiator
+    }
+
+    /// When we joined, in milliseconds since the Unix epoch.
+    pub fn started_ts(&self) -> u64 {
+        self.started_ts
+    }
+
+    /// The call so far. Identities that aren't user IDs are left out.
+    pub fn summary(&self) -> VoiceSummaryEventContent {
+        VoiceSummaryEventContent {
+            started_ts: self.started_ts,
+            duration_secs: self.started.elapsed().as_secs(),
+            participants: self
+                .participants
+                .iter()
+                .filter_map(|p| UserId::parse(p.as_str()).ok())
+                .collect(),
+            peak_participants: self.peak as u32,
+        }
+    }
+}
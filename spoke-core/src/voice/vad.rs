@@ -0,0 +1,85 @@
+// Voice activity detection — whether someone is talking right now, for the
+// speaking ring in the UI. Runs on our mic after processing and on every
+// remote track as it's decoded.
+//
+// Frames are judged by level against a noise floor that follows the quiet
+// parts of the stream, so a steady fan or hum doesn't read as speech. Speech
+// starts after a couple of loud frames, so a click doesn't flash the ring,
+// and ends only after a hangover, so it doesn't flicker between words.
+
+use std::time::Duration;
+
+/// Speech must be this far above the noise floor (ratio of RMS, ~+10 dB).
+const SPEECH_OVER_FLOOR: f32 = 3.0;
+/// Quietest level that counts as speech however low the floor (~-46 dBFS).
+const MIN_SPEECH_RMS: f32 = 0.005;
+/// Loud audio needed before speech starts.
+const ATTACK: Duration = Duration::from_millis(30);
+/// Quiet needed before speech ends.
+const HANGOVER: Duration = Duration::from_millis(400);
+/// Time constant (seconds) of the floor rising toward louder background;
+/// it falls to a quieter one at once.
+const FLOOR_RISE_TAU: f32 = 5.0;
+
+/// Speech state of one stream.
+#[derive(Debug, Clone)]
+pub(crate) struct VoiceActivity {
+    floor: f32,
+    speaking: bool,
+    /// Loud audio in a row while silent, or quiet in a row while speaking.
+    run: Duration,
+}
+
+impl VoiceActivity {
+    pub(crate) fn new() -> Self {
+        Self {
+            floor: MIN_SPEECH_RMS / SPEECH_OVER_FLOOR,
+            speaking: false,
+            run: Duration::ZERO,
+        }
+    }
+
+    /// Judge `frame` (mono, `sample_rate` Hz). Returns the new state when it
+    /// changes.
+    pub(crate) fn process(&mut self, frame: &[i16], sample_rate: u32) -> Option<bool> {
+        if frame.is_empty() || sample_rate == 0 {
+            return None;
+        }
+        let secs = frame.len() as f32 / sample_rate as f32;
+        let rms = (frame
+            .iter()
+            .map(|&s| (s as f32 / i16::MAX as f32).powi(2))
+            .sum::<f32>()
+            / frame.len() as f32)
+            .sqrt();
+        let loud = rms >= (self.floor * SPEECH_OVER_FLOOR).max(MIN_SPEECH_RMS);
+        if !loud {
+            self.floor = if rms < self.floor {
+                rms
+            } else {
+                self.floor + (rms - self.floor) * (secs / FLOOR_RISE_TAU).min(1.0)
+            };
+        }
+
+        // A frame that agrees with the current state resets the run.
+        if loud == self.speaking {
+            self.run = Duration::ZERO;
+            return None;
+        }
+        self.run += Duration::from_secs_f32(secs);
+        let needed = if self.speaking { HANGOVER } else { ATTACK };
+        if self.run < needed {
+            return None;
+        }
+        self.speaking = loud;
+        self.run = Duration::ZERO;
+        Some(loud)
+    }
+
+    /// Force the state to silent, e.g. on mute. Returns `Some(false)` if it
+    /// was speaking.
+    pub(crate) fn silence(&mut self) -> Option<bool> {
+        self.run = Duration::ZERO;
+        std::mem::replace(&mut self.speaking, false).then_some(false)
+    }
+}
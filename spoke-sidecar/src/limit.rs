@@ -0,0 +1,109 @@
+// Fixed-window request budgets, kept separately per key: per client IP for
+// requests that haven't authenticated yet, per user for a tenant's budget.
+// Nobody can spend anyone else's budget, and one noisy client can't lock a
+// whole community out of voice.
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Windows tracked before expired ones are swept out.
+const SWEEP_AT: usize = 1024;
+
+/// Allows `limit` requests per key in each `window`.
+pub struct RateLimiter<K> {
+    /// Requests allowed per key per window; 0 for no limit.
+    limit: u32,
+    window: Duration,
+    /// Start of each key's current window and requests counted in it.
+    windows: Mutex<HashMap<K, (Instant, u32)>>,
+}
+
+impl<K: Eq + Hash> RateLimiter<K> {
+    pub fn new(limit: u32, window: Duration) -> Self {
+        Self {
+            limit,
+            window,
+            windows: Mutex::default(),
+        }
+    }
+
+    /// Count a request from `key`. `Err` with how long until the next one
+    /// is allowed if this one isn't.
+    pub fn admit(&self, key: K) -> Result<(), Duration> {
+        self.admit_at(key, Instant::now())
+    }
+
+    pub(crate) fn admit_at(&self, key: K, now: Instant) -> Result<(), Duration> {
+        if self.limit == 0 {
+            return Ok(());
+        }
+        let mut windows = self.windows.lock().unwrap();
+        if windows.len() >= SWEEP_AT {
+            windows.retain(|_, (started, _)| now.duration_since(*started) < self.window);
+        }
+        let (started, count) = windows.entry(key).or_insert((now, 0));
+        if now.duration_since(*started) >= self.window {
+            *started = now;
+            *count = 0;
+        }
+        if *count >= self.limit {
+            return Err(self.window.saturating_sub(now.duration_since(*started)));
+        }
+        *count += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn refuses_past_the_limit_until_the_window_rolls_over() {
+        let limiter = RateLimiter::new(2, WINDOW);
+        let start = Instant::now();
+        assert_eq!(limiter.admit_at("a", start), Ok(()));
+        assert_eq!(
+            limiter.admit_at("a", start + Duration::from_secs(1)),
+            Ok(())
+        );
+        assert_eq!(
+            limiter.admit_at("a", start + Duration::from_secs(20)),
+            Err(Duration::from_secs(40))
+        );
+        assert_eq!(limiter.admit_at("a", start + WINDOW), Ok(()));
+    }
+
+    #[test]
+    fn keys_have_separate_budgets() {
+        let limiter = RateLimiter::new(1, WINDOW);
+        let now = Instant::now();
+        assert_eq!(limiter.admit_at("a", now), Ok(()));
+        assert!(limiter.admit_at("a", now).is_err());
+        assert_eq!(limiter.admit_at("b", now), Ok(()));
+    }
+
+    #[test]
+    fn zero_is_unlimited() {
+        let limiter = RateLimiter::new(0, WINDOW);
+        let now = Instant::now();
+        assert!((0..1000).all(|_| limiter.admit_at("a", now).is_ok()));
+    }
+
+    #[test]
+    fn expired_windows_are_swept() {
+        let limiter = RateLimiter::new(1, WINDOW);
+        let start = Instant::now();
+        for key in 0..SWEEP_AT {
+            limiter.admit_at(key, start).unwrap();
+        }
+        limiter.admit_at(SWEEP_AT, start + WINDOW).unwrap();
+        assert_eq!(limiter.windows.lock().unwrap().len(), 1);
+    }
+}
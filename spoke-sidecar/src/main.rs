@@ -1,48 +1,179 @@
 // spoke-sidecar: validates Matrix access tokens and issues LiveKit JWTs.
 // Routes: POST /_spoke/v1/voice/token
+//         GET  /metrics (Prometheus text format, labelled by tenant)
 //
 // Env vars:
+//   TENANTS_FILE    (optional) JSON file of per-homeserver tenants; see
+//                   tenant.rs. When set, the next six are ignored.
 //   LIVEKIT_URL     ws://localhost:7880
 //   LIVEKIT_KEY     devkey
 //   LIVEKIT_SECRET  devsecretatmostthirtytwocharslong
 //   MATRIX_SERVER   http://localhost:8448
 //   TURN_SECRET     (optional) shared TURN secret
 //   TURN_HOST       (optional) TURN hostname
+//   RATE_LIMIT      0 (token requests per user per minute; 0 for no limit)
+//   IP_RATE_LIMIT   0 (token requests per client IP per minute, counted
+//                   before authentication; 0 for no limit. Leave at 0
+//                   behind a reverse proxy, where every client shares its IP)
 //   PORT            8090 (default)
+//   SHUTDOWN_GRACE  10 (seconds to drain in-flight requests on SIGTERM/SIGINT)
 
-use std::time::{SystemTime, UNIX_EPOCH};
+mod limit;
+mod tenant;
+
+use std::{
+    future::IntoFuture,
+    net::{IpAddr, SocketAddr},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 use axum::{
     Router,
-    extract::{Json, State},
+    extract::{ConnectInfo, DefaultBodyLimit, Json, State, rejection::JsonRejection},
     http::{HeaderMap, StatusCode},
-    routing::post,
+    response::{IntoResponse, Response},
+    routing::{get, post},
 };
 use base64::Engine;
 use hmac::{Hmac, Mac};
 use livekit_api::access_token::{AccessToken, VideoGrants};
 use serde::{Deserialize, Serialize};
 use sha1::Sha1;
-use tracing::warn;
+use tokio::sync::Notify;
+use tracing::{error, info, warn};
+
+use limit::RateLimiter;
+use tenant::{RATE_WINDOW, Tenant, Tenants};
 
 // ── App state ─────────────────────────────────────────────────────────────────
 
 #[derive(Clone)]
 struct AppState {
-    livekit_url: String,
-    livekit_key: String,
-    livekit_secret: String,
-    turn_secret: Option<String>,
-    turn_host: Option<String>,
-    matrix_server: String,
-    http: reqwest::Client,
+    tenants: Arc<Tenants>,
+    /// Budget per client IP, spent before the request is authenticated.
+    by_ip: Arc<RateLimiter<IpAddr>>,
+    stats: Arc<Stats>,
 }
 
+/// Request counters across all tenants, for the shutdown summary.
+#[derive(Default)]
+struct Stats {
+    in_flight: AtomicU64,
+    issued: AtomicU64,
+    refused: AtomicU64,
+}
+
+/// Counts a token request as in flight until dropped.
+struct InFlight(Arc<Stats>);
+
+impl InFlight {
+    fn new(stats: &Arc<Stats>) -> Self {
+        stats.in_flight.fetch_add(1, Ordering::Relaxed);
+        Self(stats.clone())
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Token requests are a few dozen bytes; anything near this is abuse.
+const MAX_BODY_BYTES: usize = 4 * 1024;
+
+/// Matrix caps identifiers at 255 bytes.
+const MAX_ROOM_ID_BYTES: usize = 255;
+
 // ── Request / response types ──────────────────────────────────────────────────
 
 #[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
 struct TokenRequest {
     room_id: String,
+    /// The requesting user's homeserver, which picks the tenant. Only
+    /// needed when serving several.
+    #[serde(default)]
+    server_name: Option<String>,
+}
+
+/// Error response body, shaped like a Matrix error: `{"errcode", "error"}`.
+#[derive(Serialize)]
+struct ErrorBody {
+    errcode: &'static str,
+    error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_after_ms: Option<u64>,
+}
+
+struct ApiError {
+    status: StatusCode,
+    body: ErrorBody,
+}
+
+impl ApiError {
+    fn bad_request(errcode: &'static str, error: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            body: ErrorBody {
+                errcode,
+                error: error.into(),
+                retry_after_ms: None,
+            },
+        }
+    }
+
+    fn forbidden(error: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::FORBIDDEN,
+            body: ErrorBody {
+                errcode: "M_FORBIDDEN",
+                error: error.into(),
+                retry_after_ms: None,
+            },
+        }
+    }
+
+    fn rate_limited(retry_after: Duration) -> Self {
+        Self {
+            status: StatusCode::TOO_MANY_REQUESTS,
+            body: ErrorBody {
+                errcode: "M_LIMIT_EXCEEDED",
+                error: "too many voice token requests".into(),
+                retry_after_ms: Some(retry_after.as_millis() as u64),
+            },
+        }
+    }
+}
+
+/// Bare status codes from the auth and state checks keep their old meaning.
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        let errcode = match status {
+            StatusCode::UNAUTHORIZED => "M_UNKNOWN_TOKEN",
+            StatusCode::FORBIDDEN => "M_FORBIDDEN",
+            _ => "M_UNKNOWN",
+        };
+        let error = status.canonical_reason().unwrap_or("error").to_owned();
+        Self {
+            status,
+            body: ErrorBody {
+                errcode,
+                error,
+                retry_after_ms: None,
+            },
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self.body)).into_response()
+    }
 }
 
 #[derive(Serialize)]
@@ -56,6 +187,13 @@ struct TurnServer {
 struct TokenResponse {
     livekit_url: String,
     livekit_token: String,
+    /// `false` when the room is in stage mode and the user is a listener, or
+    /// their power level is below the room's `speak` threshold.
+    can_publish: bool,
+    can_screen_share: bool,
+    /// Also carried in the token's participant metadata, where other clients
+    /// can trust it.
+    priority_speaker: bool,
     #[serde(skip_serializing_if = "Vec::is_empty")]
     turn_servers: Vec<TurnServer>,
 }
@@ -66,44 +204,299 @@ struct TokenResponse {
 async fn main() {
     tracing_subscriber::fmt::init();
 
+    let tenants = match Tenants::from_env() {
+        Ok(tenants) => Arc::new(tenants),
+        Err(e) => {
+            error!("TENANTS_FILE: {e}");
+            std::process::exit(1);
+        }
+    };
+    if tenants.is_multi() {
+        let names: Vec<&str> = tenants.iter().map(|t| t.name.as_str()).collect();
+        info!("serving {} tenant(s): {}", names.len(), names.join(", "));
+    }
+    let ip_rate_limit = std::env::var("IP_RATE_LIMIT")
+        .ok()
+        .and_then(|r| r.parse().ok())
+        .unwrap_or(0);
     let state = AppState {
-        livekit_url: std::env::var("LIVEKIT_URL")
-            .unwrap_or_else(|_| "ws://localhost:7880".into()),
-        livekit_key: std::env::var("LIVEKIT_KEY")
-            .unwrap_or_else(|_| "devkey".into()),
-        livekit_secret: std::env::var("LIVEKIT_SECRET")
-            .unwrap_or_else(|_| "devsecretatmostthirtytwocharslong".into()),
-        turn_secret: std::env::var("TURN_SECRET").ok(),
-        turn_host: std::env::var("TURN_HOST").ok(),
-        matrix_server: std::env::var("MATRIX_SERVER")
-            .unwrap_or_else(|_| "http://localhost:8448".into()),
-        http: reqwest::Client::new(),
+        tenants: tenants.clone(),
+        by_ip: Arc::new(RateLimiter::new(ip_rate_limit, RATE_WINDOW)),
+        stats: Arc::default(),
     };
+    let stats = state.stats.clone();
 
     let port: u16 = std::env::var("PORT")
         .ok()
         .and_then(|p| p.parse().ok())
         .unwrap_or(8090);
+    let grace = Duration::from_secs(
+        std::env::var("SHUTDOWN_GRACE")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(10),
+    );
 
     let app = Router::new()
         .route("/_spoke/v1/voice/token", post(token_handler))
+        .route("/metrics", get(metrics_handler))
+        .layer(DefaultBodyLimit::max(MAX_BODY_BYTES))
         .with_state(state);
 
     let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{port}"))
         .await
         .expect("bind");
 
-    tracing::info!("spoke-sidecar listening on :{port}");
-    axum::serve(listener, app).await.expect("serve");
+    info!("spoke-sidecar listening on :{port}");
+    let started = Instant::now();
+
+    // On a signal, stop accepting and let in-flight requests finish — up to
+    // `grace`, so a rolling deploy never hangs on a stuck homeserver call.
+    let stop = Arc::new(Notify::new());
+    let mut server = tokio::spawn(
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown({
+            let stop = stop.clone();
+            async move { stop.notified().await }
+        })
+        .into_future(),
+    );
+    tokio::select! {
+        result = &mut server => {
+            result.expect("serve task").expect("serve");
+            return;
+        }
+        signal = shutdown_signal() => {
+            info!(
+                "received {signal}, draining {} in-flight request(s) (grace {grace:?})",
+                stats.in_flight.load(Ordering::Relaxed)
+            );
+        }
+    }
+    stop.notify_one();
+    let drained = tokio::time::timeout(grace, &mut server).await.is_ok();
+    if !drained {
+        server.abort();
+    }
+
+    info!(
+        "shutdown after {:?}: {} token(s) issued, {} refused, {} abandoned{}",
+        started.elapsed(),
+        stats.issued.load(Ordering::Relaxed),
+        stats.refused.load(Ordering::Relaxed),
+        stats.in_flight.load(Ordering::Relaxed),
+        if drained {
+            ""
+        } else {
+            " (grace period exceeded)"
+        },
+    );
+    if tenants.is_multi() {
+        for tenant in tenants.iter() {
+            info!(
+                "  {}: {} issued, {} refused ({} rate limited)",
+                tenant.name,
+                tenant.stats.issued.load(Ordering::Relaxed),
+                tenant.stats.refused.load(Ordering::Relaxed),
+                tenant.stats.rate_limited.load(Ordering::Relaxed),
+            );
+        }
+    }
+}
+
+/// Resolves with the signal's name on SIGINT (Ctrl+C) or, on Unix, SIGTERM.
+async fn shutdown_signal() -> &'static str {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("install Ctrl+C handler");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => "SIGINT",
+        () = terminate => "SIGTERM",
+    }
 }
 
 // ── Token handler ─────────────────────────────────────────────────────────────
 
 async fn token_handler(
     State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
-    Json(body): Json<TokenRequest>,
-) -> Result<Json<TokenResponse>, StatusCode> {
+    body: Result<Json<TokenRequest>, JsonRejection>,
+) -> Result<Json<TokenResponse>, ApiError> {
+    let _in_flight = InFlight::new(&state.stats);
+    let mut served_by = None;
+    // Unauthenticated requests are capped per client IP, so a flood without
+    // credentials can't turn into a flood of whoami calls.
+    let admitted = state
+        .by_ip
+        .admit(client.ip())
+        .map_err(ApiError::rate_limited);
+    let result = match admitted.and_then(|()| validate(body)) {
+        Ok(body) => match state.tenants.get(body.server_name.as_deref()) {
+            Some(tenant) => {
+                served_by = Some(tenant);
+                issue_token(tenant, state.tenants.is_multi(), &headers, body).await
+            }
+            None => Err(ApiError::forbidden(match &body.server_name {
+                Some(server) => format!("this sidecar doesn't serve {server}"),
+                None => "server_name is required".into(),
+            })),
+        },
+        Err(e) => Err(e),
+    };
+    let counter = if result.is_ok() {
+        &state.stats.issued
+    } else {
+        &state.stats.refused
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
+    if let Some(tenant) = served_by {
+        let counter = if result.is_ok() {
+            &tenant.stats.issued
+        } else {
+            &tenant.stats.refused
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+    result
+}
+
+/// Request counters in the Prometheus text format, per tenant.
+async fn metrics_handler(State(state): State<AppState>) -> String {
+    let mut out = String::new();
+    let mut counter = |name: &str, help: &str, value: &dyn Fn(&Tenant) -> u64| {
+        out += &format!("# HELP {name} {help}\n# TYPE {name} counter\n");
+        for tenant in state.tenants.iter() {
+            out += &format!("{name}{{tenant=\"{}\"}} {}\n", tenant.name, value(tenant));
+        }
+    };
+    counter(
+        "spoke_sidecar_tokens_issued_total",
+        "Voice tokens issued.",
+        &|t| t.stats.issued.load(Ordering::Relaxed),
+    );
+    counter(
+        "spoke_sidecar_tokens_refused_total",
+        "Token requests refused.",
+        &|t| t.stats.refused.load(Ordering::Relaxed),
+    );
+    counter(
+        "spoke_sidecar_rate_limited_total",
+        "Token requests refused by the tenant's per-user rate limit.",
+        &|t| t.stats.rate_limited.load(Ordering::Relaxed),
+    );
+    out += &format!(
+        "# HELP spoke_sidecar_requests_in_flight Token requests being handled.\n\
+         # TYPE spoke_sidecar_requests_in_flight gauge\n\
+         spoke_sidecar_requests_in_flight {}\n",
+        state.stats.in_flight.load(Ordering::Relaxed)
+    );
+    out
+}
+
+/// Reject oversized, malformed or ill-typed bodies and room IDs that aren't
+/// Matrix room IDs, before anything reaches the homeserver or LiveKit.
+fn validate(body: Result<Json<TokenRequest>, JsonRejection>) -> Result<TokenRequest, ApiError> {
+    let Json(body) = body.map_err(|rejection| match rejection {
+        JsonRejection::MissingJsonContentType(_) => {
+            ApiError::bad_request("M_NOT_JSON", "expected Content-Type: application/json")
+        }
+        JsonRejection::BytesRejection(_) if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE => {
+            ApiError {
+                status: StatusCode::PAYLOAD_TOO_LARGE,
+                body: ErrorBody {
+                    errcode: "M_TOO_LARGE",
+                    error: format!("request body exceeds {MAX_BODY_BYTES} bytes"),
+                    retry_after_ms: None,
+                },
+            }
+        }
+        JsonRejection::JsonSyntaxError(_) => {
+            ApiError::bad_request("M_NOT_JSON", rejection.body_text())
+        }
+        _ => ApiError::bad_request("M_BAD_JSON", rejection.body_text()),
+    })?;
+    if !is_room_id(&body.room_id) {
+        return Err(ApiError::bad_request(
+            "M_INVALID_PARAM",
+            "room_id must be a Matrix room ID (!opaque:server.name)",
+        ));
+    }
+    if body
+        .server_name
+        .as_deref()
+        .is_some_and(|s| !is_server_name(s))
+    {
+        return Err(ApiError::bad_request(
+            "M_INVALID_PARAM",
+            "server_name must be a Matrix server name",
+        ));
+    }
+    Ok(body)
+}
+
+/// `!localpart:server_name` per the Matrix identifier grammar.
+fn is_room_id(room_id: &str) -> bool {
+    if room_id.len() > MAX_ROOM_ID_BYTES {
+        return false;
+    }
+    let Some((localpart, server)) = room_id.strip_prefix('!').and_then(|r| r.split_once(':'))
+    else {
+        return false;
+    };
+    !localpart.is_empty()
+        && localpart.bytes().all(|b| b.is_ascii_graphic())
+        && is_server_name(server)
+}
+
+/// `host[:port]`, where host is a DNS name, IPv4 address or `[IPv6]` literal.
+fn is_server_name(server: &str) -> bool {
+    let (host, port) = match server.rfind(':') {
+        // A colon inside an IPv6 literal isn't a port separator.
+        Some(i) if !server[i..].contains(']') => (&server[..i], Some(&server[i + 1..])),
+        _ => (server, None),
+    };
+    let host_ok = match host.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+        Some(ipv6) => {
+            !ipv6.is_empty()
+                && ipv6
+                    .bytes()
+                    .all(|b| b.is_ascii_hexdigit() || b == b':' || b == b'.')
+        }
+        None => {
+            !host.is_empty()
+                && host.len() <= 255
+                && host
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'.')
+        }
+    };
+    let port_ok =
+        port.is_none_or(|p| !p.is_empty() && p.len() <= 5 && p.bytes().all(|b| b.is_ascii_digit()));
+    host_ok && port_ok
+}
+
+async fn issue_token(
+    tenant: &Tenant,
+    multi: bool,
+    headers: &HeaderMap,
+    body: TokenRequest,
+) -> Result<Json<TokenResponse>, ApiError> {
     // 1. Extract Bearer token from Authorization header.
     let bearer = headers
         .get("Authorization")
@@ -112,12 +505,12 @@ async fn token_handler(
         .ok_or(StatusCode::UNAUTHORIZED)?
         .to_owned();
 
-    // 2. Validate Matrix token via whoami.
-    let whoami_resp = state
+    // 2. Validate Matrix token via whoami, against the tenant's homeserver.
+    let whoami_resp = tenant
         .http
         .get(format!(
             "{}/_matrix/client/v3/account/whoami",
-            state.matrix_server
+            tenant.matrix_server
         ))
         .bearer_auth(&bearer)
         .send()
@@ -128,7 +521,7 @@ async fn token_handler(
         })?;
 
     if !whoami_resp.status().is_success() {
-        return Err(StatusCode::UNAUTHORIZED);
+        return Err(StatusCode::UNAUTHORIZED.into());
     }
 
     let whoami: serde_json::Value = whoami_resp
@@ -140,19 +533,68 @@ async fn token_handler(
         .as_str()
         .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?
         .to_owned();
+    if !tenant.serves(&user_id, multi) {
+        warn!("{user_id} asked for a grant from tenant {}", tenant.name);
+        return Err(ApiError::forbidden(format!(
+            "{user_id} isn't from {}",
+            tenant.name
+        )));
+    }
+    // Each user has their own budget in each tenant, spent before any
+    // further homeserver or LiveKit calls.
+    tenant.admit(&user_id).map_err(ApiError::rate_limited)?;
 
     // 3. Build a deterministic LiveKit room name from the Matrix room ID.
     let livekit_room =
         base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(body.room_id.as_bytes());
 
-    // 4. Generate LiveKit JWT.
-    let livekit_token = AccessToken::with_api_key(&state.livekit_key, &state.livekit_secret)
+    // 4. The room's voice config may keep the user out altogether. Anyone
+    // banned since they joined is removed from the call here, since every
+    // participant asks for a fresh grant when the config changes.
+    let config = fetch_state(tenant, &bearer, &body.room_id, "org.spoke.voice.config")
+        .await?
+        .unwrap_or_default();
+    let banned: Vec<&str> = config["banned"]
+        .as_array()
+        .map(|list| list.iter().filter_map(|u| u.as_str()).collect())
+        .unwrap_or_default();
+    if !banned.is_empty() {
+        remove_banned(tenant, &livekit_room, &banned).await;
+    }
+    if banned.contains(&user_id.as_str()) {
+        return Err(ApiError::forbidden(
+            "you are banned from this room's voice channel",
+        ));
+    }
+    if let Some(cap) = config["max_participants"].as_u64() {
+        if call_full(tenant, &livekit_room, &user_id, cap).await {
+            return Err(ApiError::forbidden(format!(
+                "the call is full ({cap} participants)"
+            )));
+        }
+    }
+
+    // 5. Voice permissions and stage mode decide what the user may publish.
+    let caps = voice_capabilities(tenant, &bearer, &body.room_id, &user_id, &config).await?;
+
+    // 6. Generate LiveKit JWT, limited to the sources the user may publish.
+    let mut sources = Vec::new();
+    if caps.speak {
+        sources.push("microphone".to_owned());
+    }
+    if caps.screen_share {
+        sources.extend(["screen_share".to_owned(), "screen_share_audio".to_owned()]);
+    }
+    let metadata = serde_json::json!({ "priority_speaker": caps.priority_speaker }).to_string();
+    let livekit_token = AccessToken::with_api_key(&tenant.livekit_key, &tenant.livekit_secret)
         .with_identity(&user_id)
         .with_name(&user_id)
+        .with_metadata(&metadata)
         .with_grants(VideoGrants {
             room_join: true,
             room: livekit_room,
-            can_publish: true,
+            can_publish: !sources.is_empty(),
+            can_publish_sources: sources,
             can_subscribe: true,
             ..Default::default()
         })
@@ -162,18 +604,207 @@ async fn token_handler(
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
 
-    // 5. Generate TURN credentials (only if TURN_SECRET and TURN_HOST are set).
-    let turn_servers = build_turn_servers(&state, &user_id);
+    // 7. Generate TURN credentials (only if TURN_SECRET and TURN_HOST are set).
+    let turn_servers = build_turn_servers(tenant, &user_id);
 
     Ok(Json(TokenResponse {
-        livekit_url: state.livekit_url.clone(),
+        livekit_url: tenant.livekit_url.clone(),
         livekit_token,
+        can_publish: caps.speak,
+        can_screen_share: caps.screen_share,
+        priority_speaker: caps.priority_speaker,
         turn_servers,
     }))
 }
 
-fn build_turn_servers(state: &AppState, user_id: &str) -> Vec<TurnServer> {
-    let (Some(secret), Some(host)) = (&state.turn_secret, &state.turn_host) else {
+// ── Room state ────────────────────────────────────────────────────────────────
+
+/// What a user may do in a room's voice channel.
+struct VoiceCapabilities {
+    speak: bool,
+    screen_share: bool,
+    priority_speaker: bool,
+}
+
+/// Decide what `user_id` may do in `room_id`'s voice channel.
+///
+/// Each capability needs the power level set for it in the `permissions` of
+/// `org.spoke.voice.config` (speak and screen share default to 0, priority
+/// speaker to 50). In stage mode a user may also only speak or share if they
+/// are listed in `org.spoke.voice.stage`, or if their power level lets them
+/// edit that list.
+async fn voice_capabilities(
+    tenant: &Tenant,
+    bearer: &str,
+    room_id: &str,
+    user_id: &str,
+    config: &serde_json::Value,
+) -> Result<VoiceCapabilities, ApiError> {
+    let power_levels = fetch_state(tenant, bearer, room_id, "m.room.power_levels").await?;
+    let level = power_levels
+        .as_ref()
+        .map_or(0, |pl| user_power(pl, user_id));
+    let allowed = |capability: &str, default: i64| {
+        level
+            >= config["permissions"][capability]
+                .as_i64()
+                .unwrap_or(default)
+    };
+
+    let on_stage = if config["stage"].as_bool().unwrap_or(false) {
+        stage_can_publish(tenant, bearer, room_id, user_id, power_levels.as_ref()).await?
+    } else {
+        true
+    };
+
+    Ok(VoiceCapabilities {
+        speak: on_stage && allowed("speak", 0),
+        screen_share: on_stage && allowed("screen_share", 0),
+        priority_speaker: allowed("priority_speaker", 50),
+    })
+}
+
+/// Whether the call in `livekit_room` already has `cap` participants. Someone
+/// already in it (reconnecting, or refreshing their grant) isn't turned away.
+async fn call_full(tenant: &Tenant, livekit_room: &str, user_id: &str, cap: u64) -> bool {
+    match tenant.rooms.list_participants(livekit_room).await {
+        Ok(participants) => {
+            !participants.iter().any(|p| p.identity == user_id) && participants.len() as u64 >= cap
+        }
+        // Also what LiveKit says before anyone has joined. Failing open keeps
+        // a LiveKit hiccup from locking everyone out.
+        Err(e) => {
+            info!("participants of {livekit_room}: {e}");
+            false
+        }
+    }
+}
+
+/// Disconnect anyone in `banned` from the call in `livekit_room`.
+///
+/// This only runs when someone asks for a grant, so a banned user can still
+/// rejoin with the LiveKit token they already hold until the next grant
+/// request in the room removes them again, or the token expires.
+async fn remove_banned(tenant: &Tenant, livekit_room: &str, banned: &[&str]) {
+    let participants = match tenant.rooms.list_participants(livekit_room).await {
+        Ok(participants) => participants,
+        Err(e) => {
+            info!("participants of {livekit_room}: {e}");
+            return;
+        }
+    };
+    for p in participants
+        .iter()
+        .filter(|p| banned.contains(&p.identity.as_str()))
+    {
+        info!("removing banned {} from {livekit_room}", p.identity);
+        if let Err(e) = tenant
+            .rooms
+            .remove_participant(livekit_room, &p.identity)
+            .await
+        {
+            warn!("remove {} from {livekit_room}: {e}", p.identity);
+        }
+    }
+}
+
+/// Whether `user_id` is on the stage of a room in stage mode: listed as a
+/// speaker, or able to edit the speaker list.
+async fn stage_can_publish(
+    tenant: &Tenant,
+    bearer: &str,
+    room_id: &str,
+    user_id: &str,
+    power_levels: Option<&serde_json::Value>,
+) -> Result<bool, ApiError> {
+    let speakers = fetch_state(tenant, bearer, room_id, "org.spoke.voice.stage").await?;
+    let is_speaker = speakers
+        .as_ref()
+        .and_then(|s| s["speakers"].as_array())
+        .is_some_and(|list| list.iter().any(|u| u.as_str() == Some(user_id)));
+    if is_speaker {
+        return Ok(true);
+    }
+
+    Ok(power_levels.is_some_and(|pl| {
+        user_power(pl, user_id) >= state_event_power(pl, "org.spoke.voice.stage")
+    }))
+}
+
+/// Fetch the content of a state event (empty state key) as the requesting
+/// user. Returns `Ok(None)` only if the event isn't set. A user who can't
+/// see the room's state is refused, and any other failure is a 502: guessing
+/// defaults there would skip the room's bans, caps and thresholds.
+async fn fetch_state(
+    tenant: &Tenant,
+    bearer: &str,
+    room_id: &str,
+    event_type: &str,
+) -> Result<Option<serde_json::Value>, ApiError> {
+    let mut url = reqwest::Url::parse(&tenant.matrix_server).map_err(|e| {
+        warn!("bad homeserver URL for {}: {e}", tenant.name);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    url.path_segments_mut()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .pop_if_empty()
+        // Trailing "" → trailing slash, i.e. the empty state key.
+        .extend([
+            "_matrix", "client", "v3", "rooms", room_id, "state", event_type, "",
+        ]);
+
+    let resp = tenant
+        .http
+        .get(url)
+        .bearer_auth(bearer)
+        .send()
+        .await
+        .map_err(|e| {
+            warn!("state request failed: {e}");
+            StatusCode::BAD_GATEWAY
+        })?;
+
+    match resp.status() {
+        status if status.is_success() => resp
+            .json()
+            .await
+            .map(Some)
+            .map_err(|_| StatusCode::BAD_GATEWAY.into()),
+        reqwest::StatusCode::NOT_FOUND => {
+            let body: serde_json::Value = resp.json().await.unwrap_or_default();
+            if body["errcode"] == "M_NOT_FOUND" {
+                Ok(None)
+            } else {
+                warn!("{event_type} in {room_id}: 404 without M_NOT_FOUND");
+                Err(StatusCode::BAD_GATEWAY.into())
+            }
+        }
+        reqwest::StatusCode::FORBIDDEN => {
+            Err(ApiError::forbidden("you can't see this room's state"))
+        }
+        status => {
+            warn!("{event_type} in {room_id}: homeserver answered {status}");
+            Err(StatusCode::BAD_GATEWAY.into())
+        }
+    }
+}
+
+fn user_power(power_levels: &serde_json::Value, user_id: &str) -> i64 {
+    power_levels["users"][user_id]
+        .as_i64()
+        .or_else(|| power_levels["users_default"].as_i64())
+        .unwrap_or(0)
+}
+
+fn state_event_power(power_levels: &serde_json::Value, event_type: &str) -> i64 {
+    power_levels["events"][event_type]
+        .as_i64()
+        .or_else(|| power_levels["state_default"].as_i64())
+        .unwrap_or(50)
+}
+
+fn build_turn_servers(tenant: &Tenant, user_id: &str) -> Vec<TurnServer> {
+    let (Some(secret), Some(host)) = (&tenant.turn_secret, &tenant.turn_host) else {
         return vec![];
     };
 
@@ -194,8 +825,7 @@ fn build_turn_servers(state: &AppState, user_id: &str) -> Vec<TurnServer> {
         }
     };
     mac.update(username.as_bytes());
-    let credential =
-        base64::engine::general_purpose::STANDARD.encode(mac.finalize().into_bytes());
+    let credential = base64::engine::general_purpose::STANDARD.encode(mac.finalize().into_bytes());
 
     vec![TurnServer {
         urls: format!("turn:{host}:3478"),
@@ -0,0 +1,232 @@
+// Tenants — one sidecar can back several homeservers, each with its own
+// LiveKit project, TURN setup and per-user request budget. The client names its
+// homeserver in the token request; the sidecar validates the access token
+// against that tenant's homeserver only, and checks the user really is from
+// there, so a token from one community never earns a grant in another's
+// LiveKit project.
+//
+// TENANTS_FILE points at a JSON object keyed by server name:
+//
+//   { "example.org": { "matrix_server": "https://matrix.example.org",
+//                      "livekit_url": "wss://lk.example.org",
+//                      "livekit_key": "…", "livekit_secret": "…",
+//                      "turn_secret": "…", "turn_host": "turn.example.org",
+//                      "rate_limit": 120 } }
+//
+// Without it, the LIVEKIT_*, MATRIX_SERVER, TURN_* and RATE_LIMIT env vars
+// make up a single tenant that serves every request, as before.
+
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use livekit_api::services::room::RoomClient;
+use serde::Deserialize;
+
+use crate::limit::RateLimiter;
+
+/// Rate limits count token requests per this window.
+pub const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// One homeserver's LiveKit project and TURN setup.
+pub struct Tenant {
+    /// The homeserver's server name, or "default" for the env-var tenant.
+    /// Labels this tenant's metrics.
+    pub name: String,
+    pub matrix_server: String,
+    pub livekit_url: String,
+    pub livekit_key: String,
+    pub livekit_secret: String,
+    pub turn_secret: Option<String>,
+    pub turn_host: Option<String>,
+    pub http: reqwest::Client,
+    /// LiveKit's room service, to count who's in a call.
+    pub rooms: RoomClient,
+    /// Token requests allowed per user per `RATE_WINDOW`.
+    limiter: RateLimiter<String>,
+    pub stats: TenantStats,
+}
+
+/// Per-tenant request counters, exported as metrics.
+#[derive(Default)]
+pub struct TenantStats {
+    pub issued: AtomicU64,
+    pub refused: AtomicU64,
+    pub rate_limited: AtomicU64,
+}
+
+/// A tenant as written in TENANTS_FILE.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TenantConfig {
+    matrix_server: String,
+    livekit_url: String,
+    livekit_key: String,
+    livekit_secret: String,
+    #[serde(default)]
+    turn_secret: Option<String>,
+    #[serde(default)]
+    turn_host: Option<String>,
+    #[serde(default)]
+    rate_limit: u32,
+}
+
+impl Tenant {
+    fn new(name: String, config: TenantConfig, http: reqwest::Client) -> Self {
+        // The room service speaks HTTP on the same host and port as signalling.
+        let service_url = match config.livekit_url.strip_prefix("ws") {
+            Some(rest) => format!("http{rest}"),
+            None => config.livekit_url.clone(),
+        };
+        Self {
+            rooms: RoomClient::with_api_key(
+                &service_url,
+                &config.livekit_key,
+                &config.livekit_secret,
+            ),
+            name,
+            matrix_server: config.matrix_server,
+            livekit_url: config.livekit_url,
+            livekit_key: config.livekit_key,
+            livekit_secret: config.livekit_secret,
+            turn_secret: config.turn_secret,
+            turn_host: config.turn_host,
+            http,
+            limiter: RateLimiter::new(config.rate_limit, RATE_WINDOW),
+            stats: TenantStats::default(),
+        }
+    }
+
+    /// Count a token request from `user_id`, once their homeserver has
+    /// vouched for them, against their budget. `Err` with how long until the
+    /// next one is allowed if this one isn't.
+    pub fn admit(&self, user_id: &str) -> Result<(), Duration> {
+        self.admit_at(user_id, Instant::now())
+    }
+
+    fn admit_at(&self, user_id: &str, now: Instant) -> Result<(), Duration> {
+        self.limiter
+            .admit_at(user_id.to_owned(), now)
+            .inspect_err(|_| {
+                self.stats.rate_limited.fetch_add(1, Ordering::Relaxed);
+            })
+    }
+
+    /// Whether `user_id` is from this tenant's homeserver. The env-var
+    /// tenant accepts whoever its homeserver vouches for.
+    pub fn serves(&self, user_id: &str, multi: bool) -> bool {
+        !multi
+            || user_id
+                .split_once(':')
+                .is_some_and(|(_, server)| server == self.name)
+    }
+}
+
+/// Every tenant this sidecar serves.
+pub enum Tenants {
+    /// From the env vars; serves every request.
+    Single(Tenant),
+    /// From TENANTS_FILE, by server name.
+    Multi(HashMap<String, Tenant>),
+}
+
+impl Tenants {
+    /// Load TENANTS_FILE if set, else the single env-var tenant.
+    pub fn from_env() -> Result<Self, String> {
+        let http = reqwest::Client::new();
+        let Ok(path) = std::env::var("TENANTS_FILE") else {
+            let env =
+                |var: &str, default: &str| std::env::var(var).unwrap_or_else(|_| default.into());
+            let config = TenantConfig {
+                matrix_server: env("MATRIX_SERVER", "http://localhost:8448"),
+                livekit_url: env("LIVEKIT_URL", "ws://localhost:7880"),
+                livekit_key: env("LIVEKIT_KEY", "devkey"),
+                livekit_secret: env("LIVEKIT_SECRET", "devsecretatmostthirtytwocharslong"),
+                turn_secret: std::env::var("TURN_SECRET").ok(),
+                turn_host: std::env::var("TURN_HOST").ok(),
+                rate_limit: std::env::var("RATE_LIMIT")
+                    .ok()
+                    .and_then(|r| r.parse().ok())
+                    .unwrap_or(0),
+            };
+            return Ok(Tenants::Single(Tenant::new("default".into(), config, http)));
+        };
+
+        let text = std::fs::read_to_string(&path).map_err(|e| format!("{path}: {e}"))?;
+        let configs: HashMap<String, TenantConfig> =
+            serde_json::from_str(&text).map_err(|e| format!("{path}: {e}"))?;
+        if configs.is_empty() {
+            return Err(format!("{path}: no tenants"));
+        }
+        let tenants = configs
+            .into_iter()
+            .map(|(name, config)| (name.clone(), Tenant::new(name, config, http.clone())))
+            .collect();
+        Ok(Tenants::Multi(tenants))
+    }
+
+    /// The tenant for a request naming `server_name`, if this sidecar serves it.
+    pub fn get(&self, server_name: Option<&str>) -> Option<&Tenant> {
+        match self {
+            Tenants::Single(tenant) => Some(tenant),
+            Tenants::Multi(tenants) => tenants.get(server_name?),
+        }
+    }
+
+    pub fn is_multi(&self) -> bool {
+        matches!(self, Tenants::Multi(_))
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = &Tenant> + '_> {
+        match self {
+            Tenants::Single(tenant) => Box::new(std::iter::once(tenant)),
+            Tenants::Multi(tenants) => Box::new(tenants.values()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tenant(name: &str, rate_limit: u32) -> Tenant {
+        let config = TenantConfig {
+            matrix_server: format!("https://matrix.{name}"),
+            livekit_url: format!("wss://lk.{name}"),
+            livekit_key: "key".into(),
+            livekit_secret: "secret".into(),
+            turn_secret: None,
+            turn_host: None,
+            rate_limit,
+        };
+        Tenant::new(name.into(), config, reqwest::Client::new())
+    }
+
+    #[test]
+    fn refusals_are_counted() {
+        let tenant = tenant("example.org", 1);
+        let now = Instant::now();
+        tenant.admit_at("@a:example.org", now).unwrap();
+        assert!(tenant.admit_at("@a:example.org", now).is_err());
+        assert!(tenant.admit_at("@a:example.org", now).is_err());
+        assert_eq!(tenant.stats.rate_limited.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn serves_only_its_own_users() {
+        let tenant = tenant("example.org", 0);
+        assert!(tenant.serves("@a:example.org", true));
+        assert!(!tenant.serves("@a:other.org", true));
+        assert!(!tenant.serves("@a:matrix.example.org", true));
+        assert!(!tenant.serves("not-a-user-id", true));
+    }
+
+    #[test]
+    fn single_tenant_serves_everyone() {
+        let tenant = tenant("default", 0);
+        assert!(tenant.serves("@a:example.org", false));
+        assert!(tenant.serves("@a:other.org", false));
+    }
+}